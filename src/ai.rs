@@ -0,0 +1,711 @@
+//! LLM-backed insights about a sampled process.
+//!
+//! `Provider` hides which backend answers: OpenAI's chat completions API (or
+//! anything speaking it: Azure OpenAI, vLLM, LM Studio), Anthropic's Messages
+//! API, or a local Ollama server so insights work offline and process details
+//! never leave the machine.
+
+use std::fmt;
+use std::fs;
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+
+use anyhow::{anyhow, Context, Result};
+use reqwest::header::AUTHORIZATION;
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+
+use crate::budget::{estimate_tokens, Budget, Usage};
+use crate::config::BudgetConfig;
+use crate::error::Error;
+use crate::history::ProcessHistory;
+use crate::process::ProcessInfo;
+use crate::redact::{Redactor, REDACTED};
+use crate::units::{format_duration, format_mb};
+
+const OPENAI_URL: &str = "https://api.openai.com/v1";
+const ANTHROPIC_URL: &str = "https://api.anthropic.com/v1";
+const OLLAMA_URL: &str = "http://localhost:11434";
+/// Sent as `anthropic-version`; the Messages API requires one.
+const ANTHROPIC_VERSION: &str = "2023-06-01";
+const AZURE_API_VERSION: &str = "2024-06-01";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, clap::ValueEnum)]
+#[serde(rename_all = "kebab-case")]
+pub enum ProviderKind {
+    /// OpenAI chat completions; needs OPENAI_API_KEY
+    Openai,
+    /// Any OpenAI-compatible server (vLLM, LM Studio, …) at --ai-url
+    OpenaiCompatible,
+    /// An Azure OpenAI deployment URL; needs AZURE_OPENAI_API_KEY
+    Azure,
+    /// Anthropic Messages API; needs ANTHROPIC_API_KEY
+    Anthropic,
+    /// A local or self-hosted Ollama server
+    Ollama,
+}
+
+impl ProviderKind {
+    pub fn default_model(self) -> &'static str {
+        match self {
+            ProviderKind::Openai | ProviderKind::OpenaiCompatible | ProviderKind::Azure => "gpt-4o-mini",
+            ProviderKind::Anthropic => "claude-3-5-haiku-latest",
+            ProviderKind::Ollama => "llama3",
+        }
+    }
+
+    fn default_url(self) -> Option<&'static str> {
+        match self {
+            ProviderKind::Openai => Some(OPENAI_URL),
+            ProviderKind::Anthropic => Some(ANTHROPIC_URL),
+            ProviderKind::Ollama => Some(OLLAMA_URL),
+            // There is no sensible default host for these.
+            ProviderKind::OpenaiCompatible | ProviderKind::Azure => None,
+        }
+    }
+
+    /// Environment variable the API key is read from unless configured
+    /// otherwise. Self-hosted backends get none, so a vendor key is never
+    /// sent to an arbitrary URL by accident.
+    pub fn default_key_env(self) -> Option<&'static str> {
+        match self {
+            ProviderKind::Openai => Some("OPENAI_API_KEY"),
+            ProviderKind::Azure => Some("AZURE_OPENAI_API_KEY"),
+            ProviderKind::Anthropic => Some("ANTHROPIC_API_KEY"),
+            ProviderKind::OpenaiCompatible | ProviderKind::Ollama => None,
+        }
+    }
+
+    pub fn label(self) -> &'static str {
+        match self {
+            ProviderKind::Openai => "OpenAI",
+            ProviderKind::OpenaiCompatible => "OpenAI-compatible endpoint",
+            ProviderKind::Azure => "Azure OpenAI",
+            ProviderKind::Anthropic => "Anthropic",
+            ProviderKind::Ollama => "Ollama",
+        }
+    }
+}
+
+/// Everything needed to build a `Provider`; unset fields use the backend's defaults.
+#[derive(Debug, Clone)]
+pub struct ProviderOptions {
+    pub kind: ProviderKind,
+    pub url: Option<String>,
+    pub model: Option<String>,
+    pub api_key: Option<String>,
+    /// Azure's `api-version` query parameter.
+    pub api_version: Option<String>,
+    /// Sampling temperature [default: 0.7].
+    pub temperature: Option<f64>,
+    /// Applied to every message before it is sent.
+    pub redactor: Redactor,
+    /// Build requests but return them as a `DryRun` error instead of sending.
+    pub dry_run: bool,
+    /// Request/token limits and the prompt cache.
+    pub budget: BudgetConfig,
+}
+
+impl ProviderOptions {
+    pub fn new(kind: ProviderKind) -> Self {
+        Self {
+            kind,
+            url: None,
+            model: None,
+            api_key: None,
+            api_version: None,
+            temperature: None,
+            redactor: Redactor::default(),
+            dry_run: false,
+            budget: BudgetConfig::default(),
+        }
+    }
+}
+
+/// Returned instead of a reply when the provider is in dry-run mode; holds
+/// the request exactly as it would have been sent, credentials masked.
+#[derive(Debug)]
+pub struct DryRun {
+    pub request: String,
+}
+
+impl fmt::Display for DryRun {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Dry run, nothing sent:\n{}", self.request)
+    }
+}
+
+impl std::error::Error for DryRun {}
+
+/// One chat turn, in the `{role, content}` shape every backend accepts.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Message {
+    pub role: String,
+    pub content: String,
+}
+
+impl Message {
+    pub fn system(content: impl Into<String>) -> Self {
+        Self {
+            role: "system".to_string(),
+            content: content.into(),
+        }
+    }
+
+    pub fn user(content: impl Into<String>) -> Self {
+        Self {
+            role: "user".to_string(),
+            content: content.into(),
+        }
+    }
+
+    pub fn assistant(content: impl Into<String>) -> Self {
+        Self {
+            role: "assistant".to_string(),
+            content: content.into(),
+        }
+    }
+}
+
+/// A configured LLM backend.
+#[derive(Debug, Clone)]
+pub struct Provider {
+    kind: ProviderKind,
+    base_url: String,
+    model: String,
+    api_key: Option<String>,
+    api_version: String,
+    temperature: f64,
+    redactor: Redactor,
+    dry_run: bool,
+    /// Shared by clones, so background requests count against the same limits.
+    budget: Arc<Mutex<Budget>>,
+    client: reqwest::Client,
+}
+
+impl Provider {
+    pub fn new(options: ProviderOptions) -> Result<Self> {
+        let ProviderOptions {
+            kind,
+            url,
+            model,
+            api_key,
+            api_version,
+            temperature,
+            redactor,
+            dry_run,
+            budget,
+        } = options;
+        let api_key = api_key.filter(|k| !k.trim().is_empty());
+        // A dry run never authenticates, so it works before a key is set up.
+        if let (None, Some(env), false) = (&api_key, kind.default_key_env(), dry_run) {
+            return Err(anyhow!("The {} provider needs an API key ({})", kind.label(), env));
+        }
+        let base_url = url
+            .or_else(|| kind.default_url().map(str::to_string))
+            .ok_or_else(|| anyhow!("The {} provider needs --ai-url", kind.label()))?;
+
+        Ok(Self {
+            kind,
+            base_url: base_url.trim_end_matches('/').to_string(),
+            model: model.unwrap_or_else(|| kind.default_model().to_string()),
+            api_key,
+            api_version: api_version.unwrap_or_else(|| AZURE_API_VERSION.to_string()),
+            temperature: temperature.unwrap_or(0.7),
+            redactor,
+            dry_run,
+            budget: Arc::new(Mutex::new(Budget::new(budget))),
+            client: reqwest::Client::new(),
+        })
+    }
+
+    pub fn kind(&self) -> ProviderKind {
+        self.kind
+    }
+
+    pub fn model(&self) -> &str {
+        &self.model
+    }
+
+    /// The rules applied to outgoing messages; callers building prompts from
+    /// a `ProcessInfo` should also run it through `Redactor::process`.
+    pub fn redactor(&self) -> &Redactor {
+        &self.redactor
+    }
+
+    /// Spending so far: `(this run, cache hits)`.
+    pub fn usage(&self) -> (Usage, u64) {
+        let budget = self.budget.lock().expect("budget lock");
+        (budget.run_usage(), budget.cache_hits())
+    }
+
+    /// Sends the conversation and returns the assistant's reply.
+    pub async fn complete(&self, messages: &[Message]) -> Result<String> {
+        self.complete_as(messages, false).await
+    }
+
+    /// Like `complete`, but asks the backend to reply with a JSON object
+    /// where it has a mode for that. The prompt still has to describe the shape.
+    pub async fn complete_json(&self, messages: &[Message]) -> Result<String> {
+        self.complete_as(messages, true).await
+    }
+
+    async fn complete_as(&self, messages: &[Message], json: bool) -> Result<String> {
+        let messages: Vec<Message> = messages
+            .iter()
+            .map(|m| Message {
+                role: m.role.clone(),
+                content: self.redactor.text(&m.content),
+            })
+            .collect();
+        let messages = messages.as_slice();
+        match self.kind {
+            ProviderKind::Openai | ProviderKind::OpenaiCompatible | ProviderKind::Azure => {
+                self.complete_openai(messages, json).await
+            }
+            // The Messages API has no JSON mode; the system prompt has to do.
+            ProviderKind::Anthropic => self.complete_anthropic(messages).await,
+            ProviderKind::Ollama => self.complete_ollama(messages, json).await,
+        }
+    }
+
+    async fn complete_openai(&self, messages: &[Message], json: bool) -> Result<String> {
+        let mut body = json!({
+            "model": self.model,
+            "messages": messages,
+            "temperature": self.temperature
+        });
+        if json {
+            body["response_format"] = json!({ "type": "json_object" });
+        }
+        let url = format!("{}/chat/completions", self.base_url);
+        let mut request = match self.kind {
+            // The deployment in the URL picks the model; auth is an `api-key` header.
+            ProviderKind::Azure => self
+                .client
+                .post(url)
+                .query(&[("api-version", self.api_version.as_str())]),
+            _ => self.client.post(url),
+        }
+        .json(&body);
+        if let Some(key) = &self.api_key {
+            request = match self.kind {
+                ProviderKind::Azure => request.header("api-key", key),
+                _ => request.header(AUTHORIZATION, format!("Bearer {}", key)),
+            };
+        }
+        let parsed: OpenAIResponse = self.send(request).await?;
+        parsed
+            .choices
+            .into_iter()
+            .next()
+            .map(|c| c.message.content)
+            .ok_or_else(|| anyhow!("No choices returned by {}", self.kind.label()))
+    }
+
+    async fn complete_anthropic(&self, messages: &[Message]) -> Result<String> {
+        // The Messages API takes the system prompt separately from the turns.
+        let system: Vec<&str> = messages
+            .iter()
+            .filter(|m| m.role == "system")
+            .map(|m| m.content.as_str())
+            .collect();
+        let turns: Vec<&Message> = messages.iter().filter(|m| m.role != "system").collect();
+        let body = json!({
+            "model": self.model,
+            "max_tokens": 1024,
+            "system": system.join("\n\n"),
+            "messages": turns,
+            "temperature": self.temperature
+        });
+        let mut request = self
+            .client
+            .post(format!("{}/messages", self.base_url))
+            .header("anthropic-version", ANTHROPIC_VERSION)
+            .json(&body);
+        if let Some(key) = &self.api_key {
+            request = request.header("x-api-key", key);
+        }
+        let parsed: AnthropicResponse = self.send(request).await?;
+        let text: String = parsed
+            .content
+            .into_iter()
+            .filter(|block| block.kind == "text")
+            .map(|block| block.text)
+            .collect();
+        if text.is_empty() {
+            return Err(anyhow!("No text returned by {}", self.kind.label()));
+        }
+        Ok(text)
+    }
+
+    async fn complete_ollama(&self, messages: &[Message], json: bool) -> Result<String> {
+        let mut body = json!({
+            "model": self.model,
+            "messages": messages,
+            "stream": false,
+            "options": { "temperature": self.temperature }
+        });
+        if json {
+            body["format"] = json!("json");
+        }
+        let request = self.client.post(format!("{}/api/chat", self.base_url)).json(&body);
+        let parsed: OllamaResponse = self.send(request).await?;
+        Ok(parsed.message.content)
+    }
+
+    async fn send<T: for<'de> Deserialize<'de>>(&self, request: reqwest::RequestBuilder) -> Result<T> {
+        let label = self.kind.label();
+        let request = request
+            .build()
+            .with_context(|| format!("Failed to build {} request", label))?;
+        if self.dry_run {
+            return Err(DryRun {
+                request: describe_request(&request),
+            }
+            .into());
+        }
+
+        let body = request.body().and_then(|b| b.as_bytes()).unwrap_or_default();
+        let key = format!("{} {}", request.url(), String::from_utf8_lossy(body));
+        let cached = self.budget.lock().expect("budget lock").begin(&key)?;
+        if let Some(reply) = cached {
+            return serde_json::from_str(&reply).with_context(|| format!("Failed to parse {} response", label));
+        }
+        let sent_bytes = body.len();
+
+        let res = self
+            .client
+            .execute(request)
+            .await
+            .with_context(|| format!("Failed to call {} at {}", label, self.base_url))?;
+
+        if !res.status().is_success() {
+            let status = res.status();
+            let text = res.text().await.unwrap_or_default();
+            return Err(Error::ApiError {
+                service: format!("{} API", label),
+                status: status.as_u16(),
+                message: text,
+            }
+            .into());
+        }
+
+        let reply = res
+            .text()
+            .await
+            .with_context(|| format!("Failed to read {} response", label))?;
+        let value: serde_json::Value =
+            serde_json::from_str(&reply).with_context(|| format!("Failed to parse {} response", label))?;
+        let tokens = reported_tokens(&value).unwrap_or_else(|| estimate_tokens(&reply) + sent_bytes.div_ceil(4) as u64);
+        self.budget.lock().expect("budget lock").record(key, &reply, tokens)?;
+        serde_json::from_value(value).with_context(|| format!("Failed to parse {} response", label))
+    }
+}
+
+/// Tokens a response says it used: OpenAI's `usage.total_tokens`, Anthropic's
+/// input plus output tokens, or Ollama's prompt plus eval counts.
+fn reported_tokens(reply: &serde_json::Value) -> Option<u64> {
+    let field = |path: &[&str]| path.iter().try_fold(reply, |v, key| v.get(key)).and_then(|v| v.as_u64());
+    field(&["usage", "total_tokens"])
+        .or_else(|| Some(field(&["usage", "input_tokens"])? + field(&["usage", "output_tokens"])?))
+        .or_else(|| Some(field(&["prompt_eval_count"])? + field(&["eval_count"])?))
+}
+
+/// Method, URL, headers and pretty-printed body, with credential headers masked.
+fn describe_request(request: &reqwest::Request) -> String {
+    let mut out = format!("{} {}\n", request.method(), request.url());
+    for (name, value) in request.headers() {
+        let value = match name.as_str() {
+            "authorization" | "api-key" | "x-api-key" => REDACTED.to_string(),
+            _ => value.to_str().unwrap_or("<binary>").to_string(),
+        };
+        out.push_str(&format!("{}: {}\n", name, value));
+    }
+    let body = request.body().and_then(|b| b.as_bytes()).unwrap_or_default();
+    let body = serde_json::from_slice::<serde_json::Value>(body)
+        .and_then(|v| serde_json::to_string_pretty(&v))
+        .unwrap_or_else(|_| String::from_utf8_lossy(body).into_owned());
+    out.push('\n');
+    out.push_str(&body);
+    out
+}
+
+#[derive(Deserialize)]
+struct OpenAIChoice {
+    message: Message,
+}
+
+#[derive(Deserialize)]
+struct OpenAIResponse {
+    choices: Vec<OpenAIChoice>,
+}
+
+#[derive(Deserialize)]
+struct AnthropicBlock {
+    #[serde(rename = "type")]
+    kind: String,
+    #[serde(default)]
+    text: String,
+}
+
+#[derive(Deserialize)]
+struct AnthropicResponse {
+    content: Vec<AnthropicBlock>,
+}
+
+#[derive(Deserialize)]
+struct OllamaResponse {
+    message: Message,
+}
+
+/// The user prompt sent for an insight, with `{placeholder}`s filled in from
+/// the sample. `{{` and `}}` stand for literal braces.
+///
+/// Placeholders: `name`, `pid`, `user`, `command`, `uptime`, `cpu` (percent),
+/// `mem` (formatted MB), `samples`, `avg_cpu`, `max_cpu`, `avg_mem`, `max_mem`,
+/// `cpu_trend` (signed points) and `mem_trend` (signed MB), newer half of the
+/// history against the older half.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PromptTemplate {
+    text: String,
+}
+
+pub const PLACEHOLDERS: [&str; 14] = [
+    "name", "pid", "user", "command", "uptime", "cpu", "mem", "samples", "avg_cpu", "max_cpu", "avg_mem", "max_mem",
+    "cpu_trend", "mem_trend",
+];
+
+const DEFAULT_TEMPLATE: &str = "Process Insight Request:\nName: {name}\nPID: {pid}\nCPU: {cpu}%\nMemory: {mem}\n\nPlease assess this process's resource consumption.";
+
+const WATCH_TEMPLATE: &str = "Process Watch Summary:\nName: {name}\nPID: {pid}\nUptime: {uptime}\nCPU: {cpu}% now, {avg_cpu}% average, {max_cpu}% peak, trend {cpu_trend} points\nMemory: {mem} now, {avg_mem} average, {max_mem} peak, trend {mem_trend}\nSamples: {samples}\n\nPlease assess this process's recent resource usage and point out anything notable.";
+
+impl Default for PromptTemplate {
+    fn default() -> Self {
+        Self {
+            text: DEFAULT_TEMPLATE.to_string(),
+        }
+    }
+}
+
+impl PromptTemplate {
+    /// The default prompt for watch mode, which leans on the history figures.
+    pub fn watch() -> Self {
+        Self {
+            text: WATCH_TEMPLATE.to_string(),
+        }
+    }
+
+    /// Checks every placeholder is known, so typos fail up front rather than
+    /// reaching the model verbatim.
+    pub fn parse(text: &str) -> Result<Self> {
+        for piece in pieces(text) {
+            if let Piece::Placeholder(name) = piece? {
+                if !PLACEHOLDERS.contains(&name) {
+                    return Err(anyhow!(
+                        "Unknown placeholder {{{}}} in prompt template (known: {})",
+                        name,
+                        PLACEHOLDERS.join(", ")
+                    ));
+                }
+            }
+        }
+        Ok(Self { text: text.to_string() })
+    }
+
+    pub fn load(path: &Path) -> Result<Self> {
+        let text = fs::read_to_string(path).with_context(|| format!("Failed to read prompt template {}", path.display()))?;
+        Self::parse(&text).with_context(|| format!("Invalid prompt template {}", path.display()))
+    }
+
+    pub fn render(&self, info: &ProcessInfo, history: &ProcessHistory) -> String {
+        let mut out = String::with_capacity(self.text.len());
+        for piece in pieces(&self.text).flatten() {
+            match piece {
+                Piece::Text(text) => out.push_str(text),
+                Piece::Placeholder(name) => out.push_str(&value(name, info, history)),
+            }
+        }
+        out
+    }
+}
+
+enum Piece<'a> {
+    Text(&'a str),
+    Placeholder(&'a str),
+}
+
+fn pieces(text: &str) -> impl Iterator<Item = Result<Piece<'_>>> {
+    let mut rest = text;
+    std::iter::from_fn(move || {
+        if rest.is_empty() {
+            return None;
+        }
+        let Some(pos) = rest.find(['{', '}']) else {
+            let text = rest;
+            rest = "";
+            return Some(Ok(Piece::Text(text)));
+        };
+        if pos > 0 {
+            let (text, tail) = rest.split_at(pos);
+            rest = tail;
+            return Some(Ok(Piece::Text(text)));
+        }
+        if let Some(tail) = rest.strip_prefix("{{") {
+            rest = tail;
+            return Some(Ok(Piece::Text("{")));
+        }
+        if let Some(tail) = rest.strip_prefix("}}") {
+            rest = tail;
+            return Some(Ok(Piece::Text("}")));
+        }
+        if rest.starts_with('}') {
+            rest = "";
+            return Some(Err(anyhow!("Unmatched `}}` in prompt template; use `}}}}` for a literal brace")));
+        }
+        match rest[1..].find('}') {
+            Some(end) => {
+                let name = &rest[1..end + 1];
+                rest = &rest[end + 2..];
+                Some(Ok(Piece::Placeholder(name.trim())))
+            }
+            None => {
+                rest = "";
+                Some(Err(anyhow!("Unclosed `{{` in prompt template")))
+            }
+        }
+    })
+}
+
+fn value(name: &str, info: &ProcessInfo, history: &ProcessHistory) -> String {
+    match name {
+        "name" => info.name.clone(),
+        "pid" => info.pid.to_string(),
+        "user" => info.user.clone().unwrap_or_else(|| "-".to_string()),
+        "command" => info.cmdline.join(" "),
+        "uptime" => format_duration(info.uptime_secs),
+        "cpu" => format!("{:.2}", info.cpu_percent),
+        "mem" => format_mb(info.memory_bytes),
+        "samples" => history.cpu_history.len().to_string(),
+        "avg_cpu" => format!("{:.2}", history.avg_cpu()),
+        "max_cpu" => format!("{:.2}", history.max_cpu()),
+        "avg_mem" => format_mb(history.avg_mem() as u64),
+        "max_mem" => format_mb(history.max_mem()),
+        "cpu_trend" => format!("{:+.2}", history.cpu_trend()),
+        "mem_trend" => {
+            let trend = history.mem_trend();
+            format!("{}{}", if trend < 0.0 { "-" } else { "+" }, format_mb(trend.abs() as u64))
+        }
+        _ => format!("{{{}}}", name),
+    }
+}
+
+const SYSTEM_PROMPT: &str = "You are a tech genius performance analyst who summarizes resource usage succinctly.";
+
+const VERDICT_PROMPT: &str = "You are a tech genius performance analyst. Reply with only a JSON object, no prose or code fences, of the form {\"severity\": \"ok\" | \"info\" | \"warning\" | \"critical\", \"summary\": one sentence, \"cause\": the probable cause or \"none\", \"action\": a suggested action or \"none\"}.";
+
+/// How worried a verdict says to be, least to most.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Severity {
+    Ok,
+    Info,
+    Warning,
+    Critical,
+}
+
+impl Severity {
+    pub fn label(self) -> &'static str {
+        match self {
+            Severity::Ok => "OK",
+            Severity::Info => "INFO",
+            Severity::Warning => "WARNING",
+            Severity::Critical => "CRITICAL",
+        }
+    }
+
+    // Models drift from the enum in the prompt; take the common synonyms.
+    fn parse(text: &str) -> Option<Self> {
+        match text.trim().to_ascii_lowercase().as_str() {
+            "ok" | "none" | "normal" | "healthy" => Some(Severity::Ok),
+            "info" | "low" | "notice" => Some(Severity::Info),
+            "warning" | "warn" | "medium" | "moderate" => Some(Severity::Warning),
+            "critical" | "high" | "severe" | "error" => Some(Severity::Critical),
+            _ => None,
+        }
+    }
+}
+
+/// A structured insight: what the model thinks of the process, and why.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct Verdict {
+    pub severity: Severity,
+    pub summary: String,
+    /// Probable cause; empty when the model saw nothing to explain.
+    pub cause: String,
+    /// Suggested action; empty when nothing needs doing.
+    pub action: String,
+}
+
+#[derive(Deserialize)]
+struct RawVerdict {
+    severity: String,
+    #[serde(default)]
+    summary: String,
+    #[serde(default, alias = "probable_cause")]
+    cause: String,
+    #[serde(default, alias = "suggested_action")]
+    action: String,
+}
+
+/// Parses a verdict reply, tolerating code fences or prose around the object.
+pub fn parse_verdict(reply: &str) -> Result<Verdict> {
+    let start = reply.find('{');
+    let end = reply.rfind('}');
+    let object = match (start, end) {
+        (Some(start), Some(end)) if start < end => &reply[start..=end],
+        _ => return Err(anyhow!("Reply is not a JSON object: {}", reply.trim())),
+    };
+    let raw: RawVerdict = serde_json::from_str(object).context("Reply does not match the verdict schema")?;
+    let severity = Severity::parse(&raw.severity).ok_or_else(|| anyhow!("Unknown severity {:?}", raw.severity))?;
+    let none = |s: String| if s.trim().eq_ignore_ascii_case("none") { String::new() } else { s.trim().to_string() };
+    Ok(Verdict {
+        severity,
+        summary: raw.summary.trim().to_string(),
+        cause: none(raw.cause),
+        action: none(raw.action),
+    })
+}
+
+/// The insight conversation: one system turn and the rendered template.
+pub fn insight_messages(template: &PromptTemplate, info: &ProcessInfo, history: &ProcessHistory) -> Vec<Message> {
+    vec![Message::system(SYSTEM_PROMPT), Message::user(template.render(info, history))]
+}
+
+/// A free-form insight about `info`, asked for with `template`.
+pub async fn generate_insight(
+    provider: &Provider,
+    template: &PromptTemplate,
+    info: &ProcessInfo,
+    history: &ProcessHistory,
+) -> Result<String> {
+    let info = provider.redactor().process(info);
+    provider.complete(&insight_messages(template, &info, history)).await
+}
+
+/// Like `insight_messages`, with a system prompt asking for a `Verdict`.
+pub fn verdict_messages(template: &PromptTemplate, info: &ProcessInfo, history: &ProcessHistory) -> Vec<Message> {
+    vec![Message::system(VERDICT_PROMPT), Message::user(template.render(info, history))]
+}
+
+/// A structured verdict about `info`, asked for with `template`.
+pub async fn generate_verdict(
+    provider: &Provider,
+    template: &PromptTemplate,
+    info: &ProcessInfo,
+    history: &ProcessHistory,
+) -> Result<Verdict> {
+    let info = provider.redactor().process(info);
+    let reply = provider.complete_json(&verdict_messages(template, &info, history)).await?;
+    parse_verdict(&reply)
+}
@@ -0,0 +1,51 @@
+//! Events pinned to the watch timeline: the process restarting, a threshold
+//! being crossed, an anomaly, a watchdog action, a port bound or closed, a
+//! marker dropped with 'm', or a deploy announced over the control socket. They're drawn on the
+//! history charts and handed to the exporters with the next sample, so a
+//! deploy can be lined up with what CPU and memory did afterwards.
+
+use chrono::{DateTime, Local};
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum AnnotationKind {
+    /// The followed process was replaced by another PID.
+    Restart,
+    /// Something went over (or into) a warning level.
+    Threshold,
+    /// Dropped by hand, with 'm' or `annotate` on the control socket.
+    Marker,
+    /// Announced with `deploy` on the control socket.
+    Deploy,
+    /// CPU or memory went well outside the process's usual range.
+    Anomaly,
+    /// The `[watchdog]` acted on the process.
+    Watchdog,
+    /// The process started or stopped listening on a socket.
+    Listener,
+}
+
+impl AnnotationKind {
+    pub fn label(&self) -> &'static str {
+        match self {
+            AnnotationKind::Restart => "restart",
+            AnnotationKind::Threshold => "threshold",
+            AnnotationKind::Marker => "marker",
+            AnnotationKind::Deploy => "deploy",
+            AnnotationKind::Anomaly => "anomaly",
+            AnnotationKind::Watchdog => "watchdog",
+            AnnotationKind::Listener => "listener",
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Annotation {
+    pub timestamp: DateTime<Local>,
+    pub kind: AnnotationKind,
+    pub text: String,
+    /// Samples taken before it, which places it on the charts.
+    #[serde(skip)]
+    pub sample: u64,
+}
@@ -0,0 +1,169 @@
+//! Spotting samples that are out of character for the process, with no
+//! threshold to tune.
+//!
+//! Each metric keeps an exponentially weighted mean and variance over
+//! about the last `SPAN` samples. A sample more than `sigma` standard
+//! deviations from the mean is anomalous, once `WARMUP` samples have been
+//! seen. The band follows the process, so a lasting change of level stops
+//! being flagged after a while rather than staying red forever.
+
+use crate::units::format_mb;
+
+/// `--anomaly-sigma` unless given.
+pub const DEFAULT_SIGMA: f64 = 3.0;
+/// Samples the mean and variance are weighted over.
+pub const SPAN: f64 = 30.0;
+/// Samples seen before anything is flagged.
+pub const WARMUP: u32 = 20;
+
+/// The smallest deviation that counts, so a flat line at 0.3% CPU isn't
+/// flagged the moment it reads 0.5%.
+const CPU_FLOOR: f64 = 2.0;
+/// As a share of the mean memory, and never under 1 MB.
+const MEMORY_FLOOR: f64 = 0.01;
+
+/// An exponentially weighted mean and variance.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct Band {
+    pub mean: f64,
+    pub variance: f64,
+    pub seen: u32,
+}
+
+impl Band {
+    /// How many deviations `value` is from the band (`floor` at least),
+    /// then takes it in.
+    pub fn observe(&mut self, value: f64, floor: f64) -> f64 {
+        if self.seen == 0 {
+            self.mean = value;
+            self.seen = 1;
+            return 0.0;
+        }
+        let z = (value - self.mean) / self.spread(floor);
+        let alpha = 2.0 / (SPAN + 1.0);
+        let diff = value - self.mean;
+        let step = alpha * diff;
+        self.mean += step;
+        self.variance = (1.0 - alpha) * (self.variance + diff * step);
+        self.seen = self.seen.saturating_add(1);
+        z
+    }
+
+    pub fn spread(&self, floor: f64) -> f64 {
+        self.variance.sqrt().max(floor)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Signal {
+    Cpu,
+    Memory,
+}
+
+impl Signal {
+    /// The alert rule raised for it.
+    pub fn rule(self) -> &'static str {
+        match self {
+            Signal::Cpu => "cpu_anomaly",
+            Signal::Memory => "memory_anomaly",
+        }
+    }
+}
+
+/// A metric that has just gone out of its band.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Anomaly {
+    pub signal: Signal,
+    pub value: f64,
+    /// The mean and deviation it was judged against.
+    pub mean: f64,
+    pub spread: f64,
+    /// Signed deviations from the mean.
+    pub z: f64,
+}
+
+impl Anomaly {
+    /// E.g. `CPU 94.0% is 4.2σ above its usual 12.3% ± 5.1`.
+    pub fn describe(&self) -> String {
+        let direction = if self.z > 0.0 { "above" } else { "below" };
+        match self.signal {
+            Signal::Cpu => format!(
+                "CPU {:.1}% is {:.1}σ {} its usual {:.1}% ± {:.1}",
+                self.value,
+                self.z.abs(),
+                direction,
+                self.mean,
+                self.spread
+            ),
+            Signal::Memory => format!(
+                "Memory {} is {:.1}σ {} its usual {} ± {}",
+                format_mb(self.value as u64),
+                self.z.abs(),
+                direction,
+                format_mb(self.mean as u64),
+                format_mb(self.spread as u64)
+            ),
+        }
+    }
+}
+
+/// The CPU and memory bands, and which are out of them now.
+#[derive(Debug, Clone)]
+pub struct AnomalyDetector {
+    sigma: f64,
+    cpu: Band,
+    memory: Band,
+    /// Whether each signal was out of its band on the last sample, so an
+    /// episode is reported once.
+    out: [bool; 2],
+}
+
+impl AnomalyDetector {
+    pub fn new(sigma: f64) -> Self {
+        Self {
+            sigma,
+            cpu: Band::default(),
+            memory: Band::default(),
+            out: [false; 2],
+        }
+    }
+
+    /// Takes a sample in; returns the signals that have just left their band.
+    pub fn observe(&mut self, cpu_percent: f64, memory_bytes: f64) -> Vec<Anomaly> {
+        let memory_floor = (self.memory.mean * MEMORY_FLOOR).max(1_000_000.0);
+        let readings = [(Signal::Cpu, cpu_percent, CPU_FLOOR), (Signal::Memory, memory_bytes, memory_floor)];
+        let mut fresh = Vec::new();
+        for (i, (signal, value, floor)) in readings.into_iter().enumerate() {
+            let band = match signal {
+                Signal::Cpu => &mut self.cpu,
+                Signal::Memory => &mut self.memory,
+            };
+            let (mean, spread, warm) = (band.mean, band.spread(floor), band.seen >= WARMUP);
+            let z = band.observe(value, floor);
+            let out = warm && z.abs() >= self.sigma;
+            if out && !self.out[i] {
+                fresh.push(Anomaly {
+                    signal,
+                    value,
+                    mean,
+                    spread,
+                    z,
+                });
+            }
+            self.out[i] = out;
+        }
+        fresh
+    }
+
+    /// Whether the latest CPU or memory reading was out of its band.
+    pub fn is_out(&self, signal: Signal) -> bool {
+        self.out[signal as usize]
+    }
+
+    pub fn band(&self, signal: Signal) -> &Band {
+        match signal {
+            Signal::Cpu => &self.cpu,
+            Signal::Memory => &self.memory,
+        }
+    }
+}
@@ -0,0 +1,331 @@
+//! The application's own metrics: a Prometheus `/metrics` endpoint or Go's
+//! `/debug/pprof`, found on the watched port or one of the process's sibling
+//! ports. Goroutine counts, heap stats and request rates say more about an
+//! app than its CPU and RSS do.
+
+use std::collections::BTreeMap;
+use std::time::{Duration, Instant};
+
+use anyhow::{Context, Result};
+use futures_util::future::join_all;
+
+/// Metrics shown when `--app-metric` isn't given, if the app exports them.
+/// Anything ending in `_requests_total` is shown as well.
+pub const DEFAULT_METRICS: &[&str] = &[
+    "http_server_requests_seconds_count",
+    "grpc_server_handled_total",
+    "process_open_fds",
+    "go_memstats_alloc_bytes_total",
+];
+
+/// Rows of selected metrics in the panel.
+pub const MAX_SHOWN: usize = 8;
+
+/// The pprof heap profile is a full sample dump, so it is read less often.
+const HEAP_EVERY: u64 = 10;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MetricKind {
+    Counter,
+    Gauge,
+}
+
+/// One metric from an exposition, summed over its label sets.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Metric {
+    pub kind: MetricKind,
+    pub value: f64,
+}
+
+pub type Exposition = BTreeMap<String, Metric>;
+
+/// Parses the Prometheus text format. Series are summed per name; a
+/// histogram's or summary's `_sum` and `_count` count as counters and its
+/// buckets and quantiles are dropped.
+pub fn parse_exposition(text: &str) -> Exposition {
+    let mut types: BTreeMap<&str, &str> = BTreeMap::new();
+    let mut metrics = Exposition::new();
+    for line in text.lines().map(str::trim) {
+        if let Some(rest) = line.strip_prefix("# TYPE ") {
+            if let Some((name, kind)) = rest.split_once(' ') {
+                types.insert(name, kind.trim());
+            }
+            continue;
+        }
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let name_end = line.find(['{', ' ']).unwrap_or(line.len());
+        let name = &line[..name_end];
+        // The value follows the labels; a timestamp may follow the value.
+        let after_labels = match line[name_end..].strip_prefix('{') {
+            Some(labels) => labels.rsplit_once('}').map_or("", |(_, rest)| rest),
+            None => &line[name_end..],
+        };
+        let Some(value) = after_labels.split_whitespace().next().and_then(|v| v.parse::<f64>().ok()) else {
+            continue;
+        };
+        let kind = match types.get(name) {
+            Some(&"counter") => MetricKind::Counter,
+            Some(&("histogram" | "summary")) => continue,
+            Some(_) => MetricKind::Gauge,
+            None => {
+                let base = name.strip_suffix("_sum").or_else(|| name.strip_suffix("_count"));
+                match base.and_then(|base| types.get(base)) {
+                    Some(&("histogram" | "summary")) => MetricKind::Counter,
+                    _ if name.ends_with("_bucket") => continue,
+                    _ if name.ends_with("_total") => MetricKind::Counter,
+                    _ => MetricKind::Gauge,
+                }
+            }
+        };
+        if value.is_finite() {
+            metrics.entry(name.to_string()).or_insert(Metric { kind, value: 0.0 }).value += value;
+        }
+    }
+    metrics
+}
+
+/// Profile name → count from the `/debug/pprof/` index page, e.g.
+/// `<td>42</td><td><a href='goroutine?debug=1'>goroutine</a>`.
+pub fn parse_pprof_index(html: &str) -> BTreeMap<String, u64> {
+    let mut counts = BTreeMap::new();
+    let chunks: Vec<&str> = html.split("<a ").collect();
+    for pair in chunks.windows(2) {
+        let Some(href) = pair[1].split_once("href=").map(|(_, h)| h.trim_start_matches(['\'', '"'])) else {
+            continue;
+        };
+        let Some((name, _)) = href.split_once("?debug=1") else {
+            continue;
+        };
+        // The count is the last number before the link.
+        let digits: String = pair[0]
+            .chars()
+            .rev()
+            .skip_while(|c| !c.is_ascii_digit())
+            .take_while(|c| c.is_ascii_digit())
+            .collect();
+        if let Ok(count) = digits.chars().rev().collect::<String>().parse() {
+            counts.insert(name.to_string(), count);
+        }
+    }
+    counts
+}
+
+/// The Go heap, from `go_memstats_*` metrics or pprof's `runtime.MemStats`.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct GoHeap {
+    pub alloc_bytes: u64,
+    pub inuse_bytes: u64,
+    pub sys_bytes: u64,
+    pub num_gc: u64,
+}
+
+/// The `# runtime.MemStats` trailer of `/debug/pprof/heap?debug=1`.
+pub fn parse_memstats(text: &str) -> Option<GoHeap> {
+    let mut stats = BTreeMap::new();
+    for line in text.lines() {
+        if let Some((key, value)) = line.strip_prefix("# ").and_then(|l| l.split_once(" = ")) {
+            if let Ok(value) = value.trim().parse::<u64>() {
+                stats.insert(key.trim(), value);
+            }
+        }
+    }
+    Some(GoHeap {
+        alloc_bytes: *stats.get("HeapAlloc")?,
+        inuse_bytes: stats.get("HeapInuse").copied().unwrap_or(0),
+        sys_bytes: stats.get("HeapSys").copied().unwrap_or(0),
+        num_gc: stats.get("NumGC").copied().unwrap_or(0),
+    })
+}
+
+fn heap_from_exposition(metrics: &Exposition) -> Option<GoHeap> {
+    let get = |name: &str| metrics.get(name).map(|m| m.value as u64);
+    Some(GoHeap {
+        alloc_bytes: get("go_memstats_heap_alloc_bytes")?,
+        inuse_bytes: get("go_memstats_heap_inuse_bytes").unwrap_or(0),
+        sys_bytes: get("go_memstats_heap_sys_bytes").unwrap_or(0),
+        num_gc: get("go_gc_duration_seconds_count").unwrap_or(0),
+    })
+}
+
+/// One selected metric as shown: counters become per-second rates.
+#[derive(Debug, Clone, PartialEq)]
+pub struct AppValue {
+    pub name: String,
+    pub kind: MetricKind,
+    /// The gauge's value, or the counter's rate since the last scrape
+    /// (`None` until there are two).
+    pub value: Option<f64>,
+}
+
+/// What was found on the app's endpoints, refreshed every tick.
+pub struct AppMetrics {
+    pub metrics_url: Option<String>,
+    pub pprof_url: Option<String>,
+    selected: Vec<String>,
+    previous: Option<(Instant, Exposition)>,
+    ticks: u64,
+    pub goroutines: Option<u64>,
+    pub threads: Option<u64>,
+    pub heap: Option<GoHeap>,
+    pub values: Vec<AppValue>,
+    /// Why the latest scrape failed, if it did.
+    pub error: Option<String>,
+    client: reqwest::Client,
+}
+
+impl AppMetrics {
+    /// `selected` names the metrics to show; empty means [`DEFAULT_METRICS`].
+    pub fn new(metrics_url: Option<String>, pprof_url: Option<String>, selected: Vec<String>, timeout: Duration) -> Result<Self> {
+        let client = reqwest::Client::builder()
+            .timeout(timeout)
+            .build()
+            .context("Failed to build HTTP client")?;
+        Ok(Self {
+            metrics_url,
+            pprof_url,
+            selected,
+            previous: None,
+            ticks: 0,
+            goroutines: None,
+            threads: None,
+            heap: None,
+            values: Vec::new(),
+            error: None,
+            client,
+        })
+    }
+
+    /// Tries `/metrics` and `/debug/pprof/` on each `host:port` target, the
+    /// watched port first; the first target answering each one wins.
+    pub async fn discover(targets: &[String], selected: Vec<String>, timeout: Duration) -> Option<Self> {
+        let probe = Self::new(None, None, selected, timeout).ok()?;
+        let find = |path: &'static str, marker: &'static str| {
+            let client = &probe.client;
+            join_all(targets.iter().map(move |target| async move {
+                let url = format!("http://{}{}", target, path);
+                let body = get_text(client, &url).await.ok()?;
+                body.contains(marker).then_some(url)
+            }))
+        };
+        // A Prometheus exposition always has TYPE or HELP comments; the pprof index links its profiles.
+        let (metrics, pprof) = futures_util::join!(find("/metrics", "# TYPE "), find("/debug/pprof/", "goroutine?debug=1"));
+        let metrics_url = metrics.into_iter().flatten().next();
+        let pprof_url = pprof.into_iter().flatten().next();
+        if metrics_url.is_none() && pprof_url.is_none() {
+            return None;
+        }
+        Some(Self {
+            metrics_url,
+            pprof_url,
+            ..probe
+        })
+    }
+
+    /// Where the panel says the numbers come from; a pprof index on the
+    /// same origin as `/metrics` is shortened to its path.
+    pub fn describe(&self) -> String {
+        match (&self.metrics_url, &self.pprof_url) {
+            (Some(metrics), Some(pprof)) => {
+                let origin = metrics.trim_end_matches("/metrics");
+                format!("{}, {}", metrics, pprof.strip_prefix(origin).unwrap_or(pprof))
+            }
+            (Some(url), None) | (None, Some(url)) => url.clone(),
+            (None, None) => String::new(),
+        }
+    }
+
+    /// Scrapes the endpoints once. A failure is kept in `error`; the last
+    /// values stay on screen.
+    pub async fn tick(&mut self) {
+        self.ticks += 1;
+        let mut errors = Vec::new();
+        if let Some(url) = self.metrics_url.clone() {
+            match get_text(&self.client, &url).await {
+                Ok(text) => self.apply_exposition(Instant::now(), &text),
+                Err(e) => errors.push(e),
+            }
+        }
+        if let Some(base) = self.pprof_url.clone() {
+            match get_text(&self.client, &base).await {
+                Ok(html) => self.apply_pprof_index(&html),
+                Err(e) => errors.push(e),
+            }
+            // /metrics already has the heap numbers when it's a Go app.
+            if self.metrics_url.is_none() && (self.ticks - 1).is_multiple_of(HEAP_EVERY) {
+                if let Ok(text) = get_text(&self.client, &format!("{}heap?debug=1", base)).await {
+                    self.heap = parse_memstats(&text).or(self.heap);
+                }
+            }
+        }
+        self.error = (!errors.is_empty()).then(|| errors.join("; "));
+    }
+
+    /// Takes in one `/metrics` scrape made at `at`.
+    pub fn apply_exposition(&mut self, at: Instant, text: &str) {
+        let metrics = parse_exposition(text);
+        if let Some(goroutines) = metrics.get("go_goroutines") {
+            self.goroutines = Some(goroutines.value as u64);
+        }
+        if let Some(threads) = metrics.get("go_threads") {
+            self.threads = Some(threads.value as u64);
+        }
+        self.heap = heap_from_exposition(&metrics).or(self.heap);
+
+        let names: Vec<&str> = if self.selected.is_empty() {
+            metrics
+                .keys()
+                .map(String::as_str)
+                .filter(|name| name.ends_with("_requests_total") || DEFAULT_METRICS.contains(name))
+                .collect()
+        } else {
+            self.selected.iter().map(String::as_str).filter(|name| metrics.contains_key(*name)).collect()
+        };
+        let previous = self.previous.as_ref();
+        self.values = names
+            .into_iter()
+            .take(MAX_SHOWN)
+            .map(|name| {
+                let metric = metrics[name];
+                let value = match metric.kind {
+                    MetricKind::Gauge => Some(metric.value),
+                    MetricKind::Counter => previous.and_then(|(then, before)| {
+                        let secs = at.saturating_duration_since(*then).as_secs_f64();
+                        let before = before.get(name)?.value;
+                        // A counter that went down means the app restarted.
+                        (secs > 0.0 && metric.value >= before).then(|| (metric.value - before) / secs)
+                    }),
+                };
+                AppValue {
+                    name: name.to_string(),
+                    kind: metric.kind,
+                    value,
+                }
+            })
+            .collect();
+        self.previous = Some((at, metrics));
+    }
+
+    /// Takes in the `/debug/pprof/` index. `go_goroutines` wins when `/metrics` has it.
+    pub fn apply_pprof_index(&mut self, html: &str) {
+        let from_metrics = self.previous.as_ref().is_some_and(|(_, m)| m.contains_key("go_goroutines"));
+        if !from_metrics {
+            self.goroutines = parse_pprof_index(html).get("goroutine").copied().or(self.goroutines);
+        }
+    }
+}
+
+async fn get_text(client: &reqwest::Client, url: &str) -> Result<String, String> {
+    let response = client.get(url).send().await.map_err(|e| {
+        if e.is_timeout() {
+            format!("{} timed out", url)
+        } else {
+            format!("{} unreachable", url)
+        }
+    })?;
+    if !response.status().is_success() {
+        return Err(format!("{} returned {}", url, response.status().as_u16()));
+    }
+    response.text().await.map_err(|e| e.to_string())
+}
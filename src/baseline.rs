@@ -0,0 +1,130 @@
+//! `baseline save` / `baseline check`: record how much CPU and memory a
+//! process uses over a while, then fail a later run that uses more, so a CI
+//! job can catch a performance regression.
+
+use std::fs;
+use std::path::Path;
+
+use anyhow::{Context, Result};
+use chrono::{DateTime, Local};
+use serde::{Deserialize, Serialize};
+
+use crate::process::ProcessInfo;
+
+/// Headroom on top of the tolerance so a process idling near zero doesn't
+/// fail on noise: one percentage point of CPU, and 1 MB of memory.
+const CPU_SLACK_PERCENT: f64 = 1.0;
+const MEMORY_SLACK_BYTES: f64 = 1_000_000.0;
+
+/// Average, 95th percentile and peak of one metric over a capture.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct Summary {
+    pub avg: f64,
+    pub p95: f64,
+    pub max: f64,
+}
+
+impl Summary {
+    pub fn of(values: &[f64]) -> Option<Self> {
+        if values.is_empty() {
+            return None;
+        }
+        let mut sorted = values.to_vec();
+        sorted.sort_by(|a, b| a.total_cmp(b));
+        // Nearest rank, as the latency panels use.
+        let rank = (0.95 * sorted.len() as f64).ceil() as usize;
+        Some(Self {
+            avg: values.iter().sum::<f64>() / values.len() as f64,
+            p95: sorted[rank.clamp(1, sorted.len()) - 1],
+            max: sorted[sorted.len() - 1],
+        })
+    }
+}
+
+/// What `baseline save` writes.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Baseline {
+    pub port: u16,
+    pub process: String,
+    pub captured_at: DateTime<Local>,
+    pub duration_secs: f64,
+    pub samples: usize,
+    pub cpu_percent: Summary,
+    pub memory_bytes: Summary,
+}
+
+impl Baseline {
+    pub fn from_samples(port: u16, captured_at: DateTime<Local>, duration_secs: f64, samples: &[ProcessInfo]) -> Option<Self> {
+        let cpu: Vec<f64> = samples.iter().map(|s| s.cpu_percent as f64).collect();
+        let memory: Vec<f64> = samples.iter().map(|s| s.memory_bytes as f64).collect();
+        Some(Self {
+            port,
+            process: samples.first()?.name.clone(),
+            captured_at,
+            duration_secs,
+            samples: samples.len(),
+            cpu_percent: Summary::of(&cpu)?,
+            memory_bytes: Summary::of(&memory)?,
+        })
+    }
+
+    pub fn load(path: &Path) -> Result<Self> {
+        let text = fs::read_to_string(path).with_context(|| format!("Failed to read baseline {}", path.display()))?;
+        serde_json::from_str(&text).with_context(|| format!("{} is not a crabtop baseline", path.display()))
+    }
+
+    pub fn save(&self, path: &Path) -> Result<()> {
+        fs::write(path, serde_json::to_string_pretty(self)? + "\n")
+            .with_context(|| format!("Failed to write baseline {}", path.display()))
+    }
+}
+
+/// Parses `20%` or `20` into a percentage.
+pub fn parse_tolerance(text: &str) -> Result<f64, String> {
+    let number = text.trim().trim_end_matches('%');
+    match number.parse::<f64>() {
+        Ok(value) if value >= 0.0 && value.is_finite() => Ok(value),
+        _ => Err(format!("invalid tolerance: {} (use e.g. 20%)", text)),
+    }
+}
+
+/// One comparison of the current run against the baseline.
+#[derive(Debug, Clone, PartialEq)]
+pub struct CheckRow {
+    pub metric: &'static str,
+    pub baseline: f64,
+    pub current: f64,
+    /// The most `current` may be and still pass.
+    pub limit: f64,
+}
+
+impl CheckRow {
+    pub fn passed(&self) -> bool {
+        self.current <= self.limit
+    }
+
+    pub fn is_memory(&self) -> bool {
+        self.metric.starts_with("Memory")
+    }
+}
+
+/// Average and p95 CPU and memory of `current` against `baseline`, each
+/// allowed to be `tolerance` percent higher.
+pub fn check(baseline: &Baseline, current: &Baseline, tolerance: f64) -> Vec<CheckRow> {
+    let factor = 1.0 + tolerance / 100.0;
+    let (b, c) = (baseline, current);
+    [
+        ("CPU avg", b.cpu_percent.avg, c.cpu_percent.avg, CPU_SLACK_PERCENT),
+        ("CPU p95", b.cpu_percent.p95, c.cpu_percent.p95, CPU_SLACK_PERCENT),
+        ("Memory avg", b.memory_bytes.avg, c.memory_bytes.avg, MEMORY_SLACK_BYTES),
+        ("Memory p95", b.memory_bytes.p95, c.memory_bytes.p95, MEMORY_SLACK_BYTES),
+    ]
+    .into_iter()
+    .map(|(metric, baseline, current, slack)| CheckRow {
+        metric,
+        baseline,
+        current,
+        limit: baseline * factor + slack,
+    })
+    .collect()
+}
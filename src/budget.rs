@@ -0,0 +1,213 @@
+//! Request and token budgets for AI calls, plus a cache of identical prompts,
+//! so a long watch session with `--ai-interval` can't quietly run up a bill.
+//! The cache keeps the most recently used replies, for an hour at most.
+//!
+//! Per-run figures live in memory. Per-day figures are kept in a small JSON
+//! state file so they add up across runs; it is only touched when a daily
+//! limit is configured.
+
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
+
+use anyhow::{anyhow, Context, Result};
+use chrono::{Local, NaiveDate};
+use serde::{Deserialize, Serialize};
+
+use crate::config::BudgetConfig;
+
+/// Requests and tokens spent.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Usage {
+    pub requests: u64,
+    pub tokens: u64,
+}
+
+/// Replies the cache holds; past this the least recently used goes.
+pub const CACHE_ENTRIES: usize = 32;
+
+/// How long a reply is reused: the process it was about has moved on.
+const CACHE_TTL: Duration = Duration::from_secs(3600);
+
+#[derive(Debug)]
+struct Cached {
+    reply: String,
+    stored: Instant,
+    /// `Budget::uses` when last handed out or stored.
+    used: u64,
+}
+
+/// The state file's contents: one day's usage.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct DayUsage {
+    date: NaiveDate,
+    #[serde(flatten)]
+    usage: Usage,
+}
+
+#[derive(Debug)]
+pub struct Budget {
+    config: BudgetConfig,
+    state_path: Option<PathBuf>,
+    run: Usage,
+    cache_hits: u64,
+    cache: HashMap<String, Cached>,
+    /// Counts cache lookups and stores, to order entries by last use.
+    uses: u64,
+}
+
+impl Budget {
+    pub fn new(config: BudgetConfig) -> Self {
+        let daily = config.max_requests_per_day.is_some() || config.max_tokens_per_day.is_some();
+        let state_path = match &config.state_file {
+            Some(path) => Some(path.clone()),
+            None if daily => default_state_path(),
+            None => None,
+        };
+        Self {
+            config,
+            state_path,
+            run: Usage::default(),
+            cache_hits: 0,
+            cache: HashMap::new(),
+            uses: 0,
+        }
+    }
+
+    /// A previous reply to exactly this request, if caching is on and has
+    /// one. Otherwise checks the limits and counts the request as sent, in
+    /// the same call, so two callers can't both take the last one.
+    pub fn begin(&mut self, key: &str) -> Result<Option<String>> {
+        if let Some(reply) = self.cached(key) {
+            self.cache_hits += 1;
+            return Ok(Some(reply));
+        }
+        self.check()?;
+        self.run.requests += 1;
+        if let Some(path) = &self.state_path {
+            let mut today = self.today()?.unwrap_or_default();
+            today.requests += 1;
+            save_day(path, today)?;
+        }
+        Ok(None)
+    }
+
+    fn cached(&mut self, key: &str) -> Option<String> {
+        if !self.config.cache {
+            return None;
+        }
+        self.uses += 1;
+        let entry = self.cache.get_mut(key)?;
+        if entry.stored.elapsed() > CACHE_TTL {
+            self.cache.remove(key);
+            return None;
+        }
+        entry.used = self.uses;
+        Some(entry.reply.clone())
+    }
+
+    /// Errors when another request would go over a limit.
+    pub fn check(&self) -> Result<()> {
+        let c = &self.config;
+        exceeded("requests this run", self.run.requests, c.max_requests_per_run, "max_requests_per_run")?;
+        exceeded("tokens this run", self.run.tokens, c.max_tokens_per_run, "max_tokens_per_run")?;
+        if let Some(today) = self.today()? {
+            exceeded("requests today", today.requests, c.max_requests_per_day, "max_requests_per_day")?;
+            exceeded("tokens today", today.tokens, c.max_tokens_per_day, "max_tokens_per_day")?;
+        }
+        Ok(())
+    }
+
+    /// Counts the tokens of a request `begin` let through and remembers its reply.
+    pub fn record(&mut self, key: String, reply: &str, tokens: u64) -> Result<()> {
+        self.run.tokens += tokens;
+        if self.config.cache {
+            if self.cache.len() >= CACHE_ENTRIES && !self.cache.contains_key(&key) {
+                self.cache.retain(|_, entry| entry.stored.elapsed() <= CACHE_TTL);
+                if self.cache.len() >= CACHE_ENTRIES {
+                    if let Some(oldest) = self.cache.iter().min_by_key(|(_, entry)| entry.used).map(|(key, _)| key.clone()) {
+                        self.cache.remove(&oldest);
+                    }
+                }
+            }
+            self.uses += 1;
+            let entry = Cached {
+                reply: reply.to_string(),
+                stored: Instant::now(),
+                used: self.uses,
+            };
+            self.cache.insert(key, entry);
+        }
+        if let Some(path) = &self.state_path {
+            let mut today = self.today()?.unwrap_or_default();
+            today.tokens += tokens;
+            save_day(path, today)?;
+        }
+        Ok(())
+    }
+
+    pub fn run_usage(&self) -> Usage {
+        self.run
+    }
+
+    pub fn cache_hits(&self) -> u64 {
+        self.cache_hits
+    }
+
+    /// Today's usage across runs; `None` without a state file.
+    pub fn today(&self) -> Result<Option<Usage>> {
+        let Some(path) = &self.state_path else {
+            return Ok(None);
+        };
+        let text = match fs::read_to_string(path) {
+            Ok(text) => text,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(Some(Usage::default())),
+            Err(e) => return Err(e).with_context(|| format!("Failed to read {}", path.display())),
+        };
+        let day: DayUsage =
+            serde_json::from_str(&text).with_context(|| format!("Failed to parse {}", path.display()))?;
+        Ok(Some(if day.date == Local::now().date_naive() {
+            day.usage
+        } else {
+            Usage::default()
+        }))
+    }
+}
+
+fn exceeded(what: &str, used: u64, limit: Option<u64>, key: &str) -> Result<()> {
+    match limit {
+        Some(limit) if used >= limit => Err(anyhow!(
+            "AI budget exhausted: {} of {} {} (raise [ai.budget] {})",
+            used,
+            limit,
+            what,
+            key
+        )),
+        _ => Ok(()),
+    }
+}
+
+fn save_day(path: &Path, usage: Usage) -> Result<()> {
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir).with_context(|| format!("Failed to create {}", dir.display()))?;
+    }
+    let day = DayUsage {
+        date: Local::now().date_naive(),
+        usage,
+    };
+    fs::write(path, serde_json::to_string(&day)?).with_context(|| format!("Failed to write {}", path.display()))
+}
+
+/// `$XDG_STATE_HOME/crabtop/ai-usage.json`, falling back to `~/.local/state`.
+pub fn default_state_path() -> Option<PathBuf> {
+    let base = std::env::var_os("XDG_STATE_HOME")
+        .map(PathBuf::from)
+        .or_else(|| std::env::var_os("HOME").map(|home| PathBuf::from(home).join(".local").join("state")))?;
+    Some(base.join("crabtop").join("ai-usage.json"))
+}
+
+/// Rough token count for backends that don't report usage: ~4 bytes a token.
+pub fn estimate_tokens(text: &str) -> u64 {
+    (text.len() as u64).div_ceil(4)
+}
@@ -0,0 +1,143 @@
+//! Linux cgroup v2 helpers.
+
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use serde::{Deserialize, Serialize};
+
+/// Root of the unified (v2) hierarchy, falling back to the hybrid-mode mount.
+fn cgroup_root() -> &'static Path {
+    if Path::new("/sys/fs/cgroup/cgroup.controllers").exists() {
+        Path::new("/sys/fs/cgroup")
+    } else {
+        Path::new("/sys/fs/cgroup/unified")
+    }
+}
+
+/// The cgroup v2 directory `pid` belongs to, if it can be read.
+pub fn cgroup_dir(pid: u32) -> Option<PathBuf> {
+    let contents = fs::read_to_string(format!("/proc/{}/cgroup", pid)).ok()?;
+    // cgroup v2 has a single "0::/path" line
+    let path = contents.lines().find_map(|l| l.strip_prefix("0::"))?;
+    Some(cgroup_root().join(path.trim_start_matches('/')))
+}
+
+/// Every PID in the same cgroup as `pid`, sorted. Empty if unreadable.
+pub fn cgroup_pids(pid: u32) -> Vec<u32> {
+    cgroup_dir(pid).map(|dir| procs(&dir)).unwrap_or_default()
+}
+
+/// `path` as given when it is a cgroup directory (e.g. one mounted into a
+/// container), else taken as a path under the cgroup root, as
+/// `/proc/<pid>/cgroup` writes them.
+pub fn resolve_dir(path: &Path) -> PathBuf {
+    if path.join("cgroup.procs").is_file() {
+        return path.to_path_buf();
+    }
+    let relative = path.strip_prefix("/").unwrap_or(path);
+    cgroup_root().join(relative)
+}
+
+/// The PIDs in the cgroup at `dir`, sorted. Processes outside this PID
+/// namespace are listed as 0 and left out; empty if unreadable.
+pub fn procs(dir: &Path) -> Vec<u32> {
+    let mut pids: Vec<u32> = fs::read_to_string(dir.join("cgroup.procs"))
+        .unwrap_or_default()
+        .lines()
+        .filter_map(|l| l.trim().parse().ok())
+        .filter(|&pid| pid != 0)
+        .collect();
+    pids.sort_unstable();
+    pids
+}
+
+/// Cumulative counters from `cpu.stat`.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+pub struct CpuStat {
+    pub usage_usec: u64,
+    pub nr_periods: u64,
+    pub nr_throttled: u64,
+    pub throttled_usec: u64,
+}
+
+impl CpuStat {
+    /// Share of enforcement periods that were throttled since `prev`.
+    /// `None` when no periods elapsed (no quota, or no time passed).
+    pub fn throttled_ratio_since(&self, prev: &CpuStat) -> Option<f64> {
+        let periods = self.nr_periods.checked_sub(prev.nr_periods)?;
+        let throttled = self.nr_throttled.checked_sub(prev.nr_throttled)?;
+        if periods == 0 {
+            None
+        } else {
+            Some(throttled as f64 / periods as f64)
+        }
+    }
+}
+
+/// Limits and usage of the cgroup a process runs in.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct CgroupStats {
+    pub path: PathBuf,
+    pub memory_current: Option<u64>,
+    /// `None` when `memory.max` is "max" (unlimited).
+    pub memory_max: Option<u64>,
+    /// CPU quota in cores (`cpu.max` quota / period); `None` when unlimited.
+    pub cpu_quota_cores: Option<f64>,
+    pub cpu_stat: CpuStat,
+}
+
+impl CgroupStats {
+    /// Whether any limit is set; without one the host figures already tell the story.
+    pub fn is_limited(&self) -> bool {
+        self.memory_max.is_some() || self.cpu_quota_cores.is_some()
+    }
+}
+
+/// Reads limits and usage for the cgroup `pid` belongs to.
+pub fn read_cgroup_stats(pid: u32) -> Option<CgroupStats> {
+    read_stats(&cgroup_dir(pid)?)
+}
+
+/// Reads limits and usage of the cgroup at `dir`.
+pub fn read_stats(dir: &Path) -> Option<CgroupStats> {
+    let dir = dir.to_path_buf();
+    if !dir.is_dir() {
+        return None;
+    }
+
+    let read = |name: &str| fs::read_to_string(dir.join(name)).ok();
+
+    let memory_current = read("memory.current").and_then(|s| s.trim().parse().ok());
+    let memory_max = read("memory.max").and_then(|s| s.trim().parse().ok());
+
+    // cpu.max is "<quota> <period>" or "max <period>"
+    let cpu_quota_cores = read("cpu.max").and_then(|s| {
+        let mut parts = s.split_whitespace();
+        let quota: f64 = parts.next()?.parse().ok()?;
+        let period: f64 = parts.next()?.parse().ok()?;
+        (period > 0.0).then(|| quota / period)
+    });
+
+    let mut cpu_stat = CpuStat::default();
+    for line in read("cpu.stat").unwrap_or_default().lines() {
+        let mut parts = line.split_whitespace();
+        let (Some(key), Some(value)) = (parts.next(), parts.next().and_then(|v| v.parse().ok())) else {
+            continue;
+        };
+        match key {
+            "usage_usec" => cpu_stat.usage_usec = value,
+            "nr_periods" => cpu_stat.nr_periods = value,
+            "nr_throttled" => cpu_stat.nr_throttled = value,
+            "throttled_usec" => cpu_stat.throttled_usec = value,
+            _ => {}
+        }
+    }
+
+    Some(CgroupStats {
+        path: dir,
+        memory_current,
+        memory_max,
+        cpu_quota_cores,
+        cpu_stat,
+    })
+}
@@ -0,0 +1,242 @@
+//! Line charts as standalone SVG, for the HTML reports and chart exports,
+//! or as PNG. Hand-drawn rather than through a plotting crate: a few
+//! polylines, grid lines and labels are all we need. PNGs are drawn in
+//! process on a `raster::Bitmap`, to the same layout as the SVG.
+
+use std::fmt::Write as _;
+use std::fs;
+use std::path::Path;
+
+use anyhow::{Context, Result};
+use chrono::{Local, TimeZone};
+
+use crate::raster::{Anchor, Bitmap, GLYPH_ADVANCE};
+
+/// One line on a chart: `(x, y)` points in drawing order.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Series {
+    pub label: String,
+    /// Any SVG colour, e.g. `#1f77b4`.
+    pub color: &'static str,
+    pub points: Vec<(f64, f64)>,
+}
+
+/// Colours for the first, second, ... series.
+pub const PALETTE: [&str; 4] = ["#1f77b4", "#d62728", "#2ca02c", "#9467bd"];
+
+pub struct Chart<'a> {
+    pub title: &'a str,
+    pub series: &'a [Series],
+    /// Formats an x value for the axis, e.g. seconds as `5m`.
+    pub x_label: fn(f64) -> String,
+    /// Formats a y value for the axis, e.g. bytes as `12.0 MB`.
+    pub y_label: fn(f64) -> String,
+}
+
+const MARGIN_LEFT: f64 = 80.0;
+const MARGIN_RIGHT: f64 = 20.0;
+const MARGIN_TOP: f64 = 34.0;
+const MARGIN_BOTTOM: f64 = 46.0;
+const GRID_LINES: usize = 4;
+
+/// Where values land on a chart of a given size.
+struct Frame {
+    x_min: f64,
+    x_max: f64,
+    y_max: f64,
+    width: f64,
+    height: f64,
+    plot_w: f64,
+    plot_h: f64,
+}
+
+impl Frame {
+    fn sx(&self, x: f64) -> f64 {
+        MARGIN_LEFT + (x - self.x_min) / (self.x_max - self.x_min) * self.plot_w
+    }
+
+    fn sy(&self, y: f64) -> f64 {
+        MARGIN_TOP + self.plot_h - y / self.y_max * self.plot_h
+    }
+
+    /// The `n`th of the grid's values up each axis: `(x, y)`.
+    fn grid(&self, n: usize) -> (f64, f64) {
+        let step = n as f64 / GRID_LINES as f64;
+        (self.x_min + (self.x_max - self.x_min) * step, self.y_max * step)
+    }
+}
+
+impl Chart<'_> {
+    /// The y axis starts at zero so two lines of different height compare honestly.
+    fn frame(&self, width: f64, height: f64) -> Frame {
+        let points = self.series.iter().flat_map(|s| s.points.iter());
+        let (mut x_min, mut x_max, mut y_max) = (f64::INFINITY, f64::NEG_INFINITY, 0.0f64);
+        for &(x, y) in points {
+            x_min = x_min.min(x);
+            x_max = x_max.max(x);
+            y_max = y_max.max(y);
+        }
+        if !x_min.is_finite() {
+            (x_min, x_max) = (0.0, 1.0);
+        }
+        if x_max <= x_min {
+            x_max = x_min + 1.0;
+        }
+        if y_max <= 0.0 {
+            y_max = 1.0;
+        }
+        Frame {
+            x_min,
+            x_max,
+            y_max,
+            width,
+            height,
+            plot_w: width - MARGIN_LEFT - MARGIN_RIGHT,
+            plot_h: height - MARGIN_TOP - MARGIN_BOTTOM,
+        }
+    }
+
+    /// The chart as an `<svg>` element, `width` by `height` pixels.
+    pub fn render(&self, width: f64, height: f64) -> String {
+        let frame = self.frame(width, height);
+        let (plot_w, plot_h) = (frame.plot_w, frame.plot_h);
+        let sx = |x: f64| frame.sx(x);
+        let sy = |y: f64| frame.sy(y);
+
+        let mut svg = format!(
+            "<svg xmlns=\"http://www.w3.org/2000/svg\" width=\"{w}\" height=\"{h}\" viewBox=\"0 0 {w} {h}\" font-family=\"sans-serif\" font-size=\"11\">\n\
+             <rect width=\"100%\" height=\"100%\" fill=\"#ffffff\"/>\n\
+             <text x=\"{cx}\" y=\"20\" text-anchor=\"middle\" font-size=\"14\">{t}</text>\n",
+            w = width,
+            h = height,
+            cx = width / 2.0,
+            t = escape(self.title)
+        );
+        for n in 0..=GRID_LINES {
+            let (x_value, value) = frame.grid(n);
+            let y = sy(value);
+            let _ = writeln!(
+                svg,
+                "<line x1=\"{:.1}\" y1=\"{y:.1}\" x2=\"{:.1}\" y2=\"{y:.1}\" stroke=\"#e0e0e0\"/>\
+                 <text x=\"{:.1}\" y=\"{:.1}\" text-anchor=\"end\" fill=\"#555\">{}</text>",
+                MARGIN_LEFT,
+                width - MARGIN_RIGHT,
+                MARGIN_LEFT - 6.0,
+                y + 4.0,
+                escape(&(self.y_label)(value)),
+            );
+            let anchor = match n {
+                0 => "start",
+                n if n == GRID_LINES => "end",
+                _ => "middle",
+            };
+            let _ = writeln!(
+                svg,
+                "<text x=\"{:.1}\" y=\"{:.1}\" text-anchor=\"{}\" fill=\"#555\">{}</text>",
+                sx(x_value),
+                height - MARGIN_BOTTOM + 16.0,
+                anchor,
+                escape(&(self.x_label)(x_value)),
+            );
+        }
+        let _ = writeln!(
+            svg,
+            "<rect x=\"{:.1}\" y=\"{:.1}\" width=\"{:.1}\" height=\"{:.1}\" fill=\"none\" stroke=\"#999\"/>",
+            MARGIN_LEFT, MARGIN_TOP, plot_w, plot_h
+        );
+        let mut legend_x = MARGIN_LEFT;
+        for series in self.series {
+            let path: Vec<String> = series.points.iter().map(|&(x, y)| format!("{:.1},{:.1}", sx(x), sy(y))).collect();
+            let _ = writeln!(
+                svg,
+                "<polyline points=\"{}\" fill=\"none\" stroke=\"{}\" stroke-width=\"1.5\"/>",
+                path.join(" "),
+                series.color
+            );
+            let legend_y = height - 12.0;
+            let _ = writeln!(
+                svg,
+                "<rect x=\"{:.1}\" y=\"{:.1}\" width=\"12\" height=\"3\" fill=\"{}\"/>\
+                 <text x=\"{:.1}\" y=\"{:.1}\">{}</text>",
+                legend_x,
+                legend_y - 4.0,
+                series.color,
+                legend_x + 16.0,
+                legend_y,
+                escape(&series.label)
+            );
+            // About 6px per character at this font size.
+            legend_x += 36.0 + series.label.chars().count() as f64 * 6.0;
+        }
+        svg.push_str("</svg>\n");
+        svg
+    }
+
+    /// The same chart as a PNG file, `width` by `height` pixels.
+    pub fn render_png(&self, width: u32, height: u32) -> Vec<u8> {
+        let frame = self.frame(width as f64, height as f64);
+        let mut bitmap = Bitmap::new(width as usize, height as usize, "#ffffff");
+        bitmap.text(frame.width / 2.0, 20.0, Anchor::Middle, 2, "#000", self.title);
+        for n in 0..=GRID_LINES {
+            let (x_value, value) = frame.grid(n);
+            let y = frame.sy(value);
+            bitmap.line((MARGIN_LEFT, y), (frame.width - MARGIN_RIGHT, y), 1.0, "#e0e0e0");
+            bitmap.text(MARGIN_LEFT - 6.0, y + 4.0, Anchor::End, 1, "#555", &(self.y_label)(value));
+            let anchor = match n {
+                0 => Anchor::Start,
+                n if n == GRID_LINES => Anchor::End,
+                _ => Anchor::Middle,
+            };
+            bitmap.text(frame.sx(x_value), frame.height - MARGIN_BOTTOM + 16.0, anchor, 1, "#555", &(self.x_label)(x_value));
+        }
+        bitmap.stroke_rect(MARGIN_LEFT, MARGIN_TOP, frame.plot_w, frame.plot_h, "#999");
+        let mut legend_x = MARGIN_LEFT;
+        for series in self.series {
+            let points: Vec<(f64, f64)> = series.points.iter().map(|&(x, y)| (frame.sx(x), frame.sy(y))).collect();
+            for pair in points.windows(2) {
+                bitmap.line(pair[0], pair[1], 1.5, series.color);
+            }
+            let legend_y = frame.height - 12.0;
+            bitmap.fill_rect(legend_x, legend_y - 4.0, 12.0, 3.0, series.color);
+            bitmap.text(legend_x + 16.0, legend_y, Anchor::Start, 1, "#000", &series.label);
+            legend_x += 36.0 + (series.label.chars().count() * GLYPH_ADVANCE) as f64;
+        }
+        bitmap.to_png()
+    }
+}
+
+/// Seconds as the axis shows them: `45s`, `5m`, `1h30m`.
+pub fn elapsed_label(secs: f64) -> String {
+    let secs = secs.max(0.0).round() as u64;
+    match secs {
+        0..=119 => format!("{}s", secs),
+        120..=7199 => format!("{}m", secs / 60),
+        _ if secs % 3600 / 60 == 0 => format!("{}h", secs / 3600),
+        _ => format!("{}h{}m", secs / 3600, secs % 3600 / 60),
+    }
+}
+
+/// Unix seconds as local time of day: `14:05:00`.
+pub fn clock_label(secs: f64) -> String {
+    Local
+        .timestamp_opt(secs as i64, 0)
+        .single()
+        .map_or_else(String::new, |t| t.format("%H:%M:%S").to_string())
+}
+
+/// Writes `chart` to `path`, `width` by `height` pixels: as PNG when the
+/// path ends in `.png`, as SVG otherwise.
+pub fn write_chart(path: &Path, chart: &Chart<'_>, width: u32, height: u32) -> Result<()> {
+    let is_png = path.extension().and_then(|e| e.to_str()).is_some_and(|e| e.eq_ignore_ascii_case("png"));
+    let bytes = if is_png {
+        chart.render_png(width, height)
+    } else {
+        chart.render(width as f64, height as f64).into_bytes()
+    };
+    fs::write(path, bytes).with_context(|| format!("Failed to write {}", path.display()))
+}
+
+/// Escapes text for SVG and HTML.
+pub fn escape(text: &str) -> String {
+    text.replace('&', "&amp;").replace('<', "&lt;").replace('>', "&gt;").replace('"', "&quot;")
+}
@@ -0,0 +1,113 @@
+//! The `ask` REPL: a conversation about one process, where every question
+//! carries a fresh sample so answers reflect what the process is doing now.
+
+use anyhow::Result;
+use chrono::NaiveDateTime;
+
+use crate::ai::{Message, Provider};
+use crate::history::ProcessHistory;
+use crate::port::ConnectionSummary;
+use crate::process::{ProcessInfo, SystemSnapshot};
+use crate::units::{format_duration, format_mb};
+
+const SYSTEM_PROMPT: &str = "You are a tech genius performance analyst helping an operator understand one process. Each question comes with a live context block sampled just before it was asked; prefer the newest context, and say so when it doesn't contain what's needed. Answer concisely.";
+
+/// A conversation that lasts for the REPL session.
+pub struct Chat {
+    provider: Provider,
+    messages: Vec<Message>,
+}
+
+impl Chat {
+    pub fn new(provider: Provider) -> Self {
+        Self {
+            provider,
+            messages: vec![Message::system(SYSTEM_PROMPT)],
+        }
+    }
+
+    pub fn provider(&self) -> &Provider {
+        &self.provider
+    }
+
+    /// Asks `question` with `context` prepended and records both sides. A
+    /// failed call leaves the conversation as it was.
+    pub async fn ask(&mut self, question: &str, context: &str) -> Result<String> {
+        self.messages
+            .push(Message::user(format!("{}\n\nQuestion: {}", context, question)));
+        match self.provider.complete(&self.messages).await {
+            Ok(answer) => {
+                self.messages.push(Message::assistant(answer.trim()));
+                Ok(answer.trim().to_string())
+            }
+            Err(err) => {
+                self.messages.pop();
+                Err(err)
+            }
+        }
+    }
+
+    /// Forgets everything but the system prompt.
+    pub fn reset(&mut self) {
+        self.messages.truncate(1);
+    }
+
+    /// Questions answered so far.
+    pub fn turns(&self) -> usize {
+        (self.messages.len() - 1) / 2
+    }
+}
+
+/// The live context block sent with each question.
+pub fn context_block(
+    info: &ProcessInfo,
+    history: &ProcessHistory,
+    system: Option<&SystemSnapshot>,
+    port: u16,
+    connections: Option<&ConnectionSummary>,
+    sampled_at: NaiveDateTime,
+) -> String {
+    let mut lines = vec![
+        format!("Live context (sampled {}):", sampled_at.format("%Y-%m-%d %H:%M:%S")),
+        format!(
+            "Process: {} (PID {}, user {}), up {}",
+            info.name,
+            info.pid,
+            info.user.as_deref().unwrap_or("-"),
+            format_duration(info.uptime_secs)
+        ),
+        format!("Command: {}", info.cmdline.join(" ")),
+        format!(
+            "CPU: {:.2}% now, {:.2}% average, {:.2}% peak, trend {:+.2} points over {} samples",
+            info.cpu_percent,
+            history.avg_cpu(),
+            history.max_cpu(),
+            history.cpu_trend(),
+            history.cpu_history.len()
+        ),
+        format!(
+            "Memory: {} now, {} average, {} peak",
+            format_mb(info.memory_bytes),
+            format_mb(history.avg_mem() as u64),
+            format_mb(history.max_mem())
+        ),
+    ];
+    if let Some(system) = system {
+        lines.push(format!(
+            "System: load {:.2} {:.2} {:.2} on {} CPUs, memory {} of {} used, swap {} of {} used",
+            system.load_average.0,
+            system.load_average.1,
+            system.load_average.2,
+            system.cpu_count,
+            format_mb(system.total_memory_bytes.saturating_sub(system.available_memory_bytes)),
+            format_mb(system.total_memory_bytes),
+            format_mb(system.used_swap_bytes),
+            format_mb(system.total_swap_bytes)
+        ));
+    }
+    match connections {
+        Some(c) => lines.push(format!("Connections on port {}: {}", port, c)),
+        None => lines.push(format!("Connections on port {}: unknown", port)),
+    }
+    lines.join("\n")
+}
@@ -0,0 +1,213 @@
+//! The feed of the watched processes' children being spawned and exiting,
+//! for servers that fork per request or shell out when they shouldn't.
+//!
+//! Children are polled from `/proc` each sample, so one that comes and goes
+//! between two samples isn't seen. An exit code is only known when the child
+//! was caught as a zombie, exited but not yet reaped by its parent; a child
+//! reaped between samples is seen to be gone, without a code.
+//!
+//! Zombies that stay unreaped are counted too: a parent missing its
+//! `waitpid` leaves one behind for every child, each holding a PID, until
+//! the PID space runs out.
+
+use std::collections::{HashMap, VecDeque};
+use std::fmt;
+use std::fs;
+
+use chrono::{DateTime, Local};
+
+use crate::procfs::parse_stat;
+
+/// How many spawns and exits the feed keeps.
+pub const CHILD_EVENTS: usize = 100;
+
+/// Samples over which the zombie count must keep growing to be warned of.
+pub const ZOMBIE_WINDOW: usize = 5;
+
+/// Fewest zombies worth a warning; a few can be a parent slow to reap.
+pub const MIN_ZOMBIES: usize = 3;
+
+/// How a child ended, from the wait status the kernel keeps for a zombie.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ChildExit {
+    Code(i32),
+    Signal(i32),
+}
+
+impl ChildExit {
+    /// Decodes a `waitpid(2)` status.
+    pub fn from_wait_status(status: i32) -> Self {
+        match status & 0x7f {
+            0 => ChildExit::Code((status >> 8) & 0xff),
+            signal => ChildExit::Signal(signal),
+        }
+    }
+
+    pub fn is_failure(self) -> bool {
+        self != ChildExit::Code(0)
+    }
+}
+
+impl fmt::Display for ChildExit {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ChildExit::Code(code) => write!(f, "code {}", code),
+            ChildExit::Signal(signal) => write!(f, "signal {}", signal),
+        }
+    }
+}
+
+/// A child of a watched process, as one sample found it.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ChildProcess {
+    pub pid: u32,
+    pub ppid: u32,
+    /// Its command line, or its name when that can't be read (a zombie's).
+    pub command: String,
+    /// Exited but not reaped yet.
+    pub zombie: bool,
+    /// How it exited, when the kernel lets us read that of a zombie.
+    pub exit: Option<ChildExit>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ChildEventKind {
+    Spawned,
+    /// With how it ended, when it was caught before being reaped.
+    Exited(Option<ChildExit>),
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ChildEvent {
+    pub at: DateTime<Local>,
+    pub pid: u32,
+    pub ppid: u32,
+    pub kind: ChildEventKind,
+    pub command: String,
+}
+
+struct Known {
+    ppid: u32,
+    command: String,
+    exited: bool,
+}
+
+/// The children followed from one sample to the next.
+#[derive(Default)]
+pub struct ChildFeed {
+    known: HashMap<u32, Known>,
+    /// Spawns and exits, oldest first.
+    pub events: VecDeque<ChildEvent>,
+    pub spawned: u64,
+    pub exited: u64,
+    /// Children exited and waiting to be reaped, now.
+    pub zombies: usize,
+    /// The zombie count of the latest samples, oldest first.
+    zombie_counts: VecDeque<usize>,
+    /// Children already running at the first sample aren't "spawned".
+    started: bool,
+}
+
+impl ChildFeed {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn running(&self) -> usize {
+        self.known.values().filter(|k| !k.exited).count()
+    }
+
+    /// The zombie count has kept going up over the last `ZOMBIE_WINDOW`
+    /// samples, to at least `MIN_ZOMBIES`: `(then, now)`.
+    pub fn zombies_growing(&self) -> Option<(usize, usize)> {
+        if self.zombie_counts.len() <= ZOMBIE_WINDOW || self.zombies < MIN_ZOMBIES {
+            return None;
+        }
+        let counts: Vec<usize> = self.zombie_counts.iter().copied().collect();
+        let rising = counts.windows(2).all(|w| w[1] >= w[0]) && counts[counts.len() - 1] > counts[0];
+        rising.then_some((counts[0], self.zombies))
+    }
+
+    /// Takes this sample's children in.
+    pub fn update(&mut self, children: Vec<ChildProcess>, at: DateTime<Local>) {
+        let mut seen = Vec::with_capacity(children.len());
+        self.zombies = children.iter().filter(|c| c.zombie).count();
+        self.zombie_counts.push_back(self.zombies);
+        if self.zombie_counts.len() > ZOMBIE_WINDOW + 1 {
+            self.zombie_counts.pop_front();
+        }
+        for child in children {
+            seen.push(child.pid);
+            if !self.known.contains_key(&child.pid) {
+                if self.started {
+                    self.spawned += 1;
+                    self.push(at, child.pid, child.ppid, ChildEventKind::Spawned, child.command.clone());
+                }
+                self.known.insert(
+                    child.pid,
+                    Known {
+                        ppid: child.ppid,
+                        command: child.command,
+                        exited: false,
+                    },
+                );
+            }
+            let known = self.known.get_mut(&child.pid).expect("inserted above");
+            if child.zombie && !known.exited {
+                known.exited = true;
+                let (ppid, command) = (known.ppid, known.command.clone());
+                self.exited += 1;
+                self.push(at, child.pid, ppid, ChildEventKind::Exited(child.exit), command);
+            }
+        }
+
+        let mut gone: Vec<u32> = self.known.keys().copied().filter(|pid| !seen.contains(pid)).collect();
+        gone.sort_unstable();
+        for pid in gone {
+            let known = self.known.remove(&pid).expect("listed from the map");
+            if !known.exited {
+                self.exited += 1;
+                self.push(at, pid, known.ppid, ChildEventKind::Exited(None), known.command);
+            }
+        }
+        self.started = true;
+    }
+
+    fn push(&mut self, at: DateTime<Local>, pid: u32, ppid: u32, kind: ChildEventKind, command: String) {
+        self.events.push_back(ChildEvent { at, pid, ppid, kind, command });
+        if self.events.len() > CHILD_EVENTS {
+            self.events.pop_front();
+        }
+    }
+}
+
+/// The children of `parents` on this host, zombies included, in one pass
+/// over `/proc`.
+pub fn local_children(parents: &[u32]) -> Vec<ChildProcess> {
+    let Ok(entries) = fs::read_dir("/proc") else {
+        return Vec::new();
+    };
+    let mut children: Vec<ChildProcess> = entries
+        .flatten()
+        .filter_map(|entry| {
+            let pid: u32 = entry.file_name().to_str()?.parse().ok()?;
+            let stat = parse_stat(&fs::read_to_string(entry.path().join("stat")).ok()?)?;
+            if !parents.contains(&stat.ppid) || parents.contains(&pid) {
+                return None;
+            }
+            let zombie = stat.state == 'Z';
+            let exit = zombie.then(|| stat.exit_code.map(ChildExit::from_wait_status)).flatten();
+            let cmdline = fs::read(entry.path().join("cmdline")).unwrap_or_default();
+            let cmdline = String::from_utf8_lossy(&cmdline).split('\0').filter(|a| !a.is_empty()).collect::<Vec<_>>().join(" ");
+            Some(ChildProcess {
+                pid,
+                ppid: stat.ppid,
+                command: if cmdline.is_empty() { stat.comm } else { cmdline },
+                zombie,
+                exit,
+            })
+        })
+        .collect();
+    children.sort_by_key(|c| c.pid);
+    children
+}
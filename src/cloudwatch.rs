@@ -0,0 +1,486 @@
+//! Sending watch samples to CloudWatch as custom metrics, through the
+//! `PutMetricData` query API.
+//!
+//! Requests are signed with Signature Version 4 using `ring`, which the TLS
+//! stack already pulls in, rather than the AWS SDK and its dependency tree.
+//! Credentials are looked for where the SDKs look: the standard environment
+//! variables (`AWS_ACCESS_KEY_ID`, `AWS_SECRET_ACCESS_KEY`,
+//! `AWS_SESSION_TOKEN`, as `aws-vault exec` sets them), an EKS service
+//! account's web identity token, the ECS task role (or EKS Pod Identity)
+//! endpoint, and last the EC2 instance profile over IMDSv2. Temporary ones
+//! are fetched again shortly before they expire.
+//!
+//! The exporter and signer are only built with the `cloudwatch` feature;
+//! `put_metric_data` is always there.
+
+use std::fmt::Write as _;
+
+use chrono::SecondsFormat;
+
+use crate::export::ExportSample;
+
+/// The metric namespace unless the config names another.
+pub const DEFAULT_NAMESPACE: &str = "crabtop";
+
+/// The form fields of a `PutMetricData` call for one sample: a datum per
+/// figure, with `Port`, `Process` and (when remote) `Host` dimensions.
+pub fn put_metric_data(sample: &ExportSample, namespace: &str) -> Vec<(String, String)> {
+    let mut params = vec![
+        ("Action".to_string(), "PutMetricData".to_string()),
+        ("Version".to_string(), "2010-08-01".to_string()),
+        ("Namespace".to_string(), namespace.to_string()),
+    ];
+    let mut dimensions = vec![("Port", sample.port.to_string()), ("Process", sample.process.name.clone())];
+    dimensions.extend(sample.host.as_ref().map(|host| ("Host", host.clone())));
+    let timestamp = sample.timestamp.to_utc().to_rfc3339_opts(SecondsFormat::Secs, true);
+    let data = [
+        ("CpuPercent", sample.process.cpu_percent as f64, "Percent"),
+        ("MemoryBytes", sample.process.memory_bytes as f64, "Bytes"),
+        ("UptimeSeconds", sample.process.uptime_secs as f64, "Seconds"),
+    ];
+    for (n, (name, value, unit)) in data.iter().enumerate() {
+        let member = format!("MetricData.member.{}", n + 1);
+        params.push((format!("{}.MetricName", member), name.to_string()));
+        params.push((format!("{}.Value", member), value.to_string()));
+        params.push((format!("{}.Unit", member), unit.to_string()));
+        params.push((format!("{}.Timestamp", member), timestamp.clone()));
+        for (d, (dimension, value)) in dimensions.iter().enumerate() {
+            let prefix = format!("{}.Dimensions.member.{}", member, d + 1);
+            params.push((format!("{}.Name", prefix), dimension.to_string()));
+            params.push((format!("{}.Value", prefix), value.clone()));
+        }
+    }
+    params
+}
+
+/// `key=value&...`, encoded the way SigV4 expects (RFC 3986 unreserved
+/// characters kept, everything else `%XX`).
+pub fn form_encode(params: &[(String, String)]) -> String {
+    params
+        .iter()
+        .map(|(key, value)| format!("{}={}", uri_encode(key), uri_encode(value)))
+        .collect::<Vec<_>>()
+        .join("&")
+}
+
+fn uri_encode(value: &str) -> String {
+    let mut out = String::new();
+    for byte in value.bytes() {
+        match byte {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'.' | b'_' | b'~' => out.push(byte as char),
+            _ => {
+                let _ = write!(out, "%{:02X}", byte);
+            }
+        }
+    }
+    out
+}
+
+#[cfg(feature = "cloudwatch")]
+pub use signing::{sign, Credentials, Request};
+
+#[cfg(feature = "cloudwatch")]
+pub use credentials::CredentialSource;
+
+#[cfg(feature = "cloudwatch")]
+pub use exporter::CloudWatchExporter;
+
+#[cfg(feature = "cloudwatch")]
+mod signing {
+    use std::fmt::Write as _;
+
+    use chrono::{DateTime, Utc};
+    use ring::{digest, hmac};
+
+    #[derive(Debug, Clone)]
+    pub struct Credentials {
+        pub access_key_id: String,
+        pub secret_access_key: String,
+        pub session_token: Option<String>,
+        /// When temporary credentials stop working; `None` for long-lived keys.
+        pub expires: Option<DateTime<Utc>>,
+    }
+
+    /// What gets signed about a request. `headers` are the ones sent besides
+    /// the signature's own, `host` included; `query` is already encoded.
+    #[derive(Debug, Clone, Copy)]
+    pub struct Request<'a> {
+        pub method: &'a str,
+        pub path: &'a str,
+        pub query: &'a str,
+        pub headers: &'a [(&'a str, &'a str)],
+        pub body: &'a [u8],
+    }
+
+    /// The headers that sign `request` for `service` in `region`:
+    /// `x-amz-date`, the session token if there is one, and `authorization`.
+    pub fn sign(credentials: &Credentials, region: &str, service: &str, request: Request<'_>, now: DateTime<Utc>) -> Vec<(String, String)> {
+        let Request {
+            method,
+            path,
+            query,
+            headers,
+            body,
+        } = request;
+        let amz_date = now.format("%Y%m%dT%H%M%SZ").to_string();
+        let date = &amz_date[..8];
+        let mut signed: Vec<(String, String)> = headers
+            .iter()
+            .map(|(name, value)| (name.to_ascii_lowercase(), value.trim().to_string()))
+            .collect();
+        signed.push(("x-amz-date".to_string(), amz_date.clone()));
+        if let Some(token) = &credentials.session_token {
+            signed.push(("x-amz-security-token".to_string(), token.clone()));
+        }
+        signed.sort();
+
+        let mut canonical = format!("{}\n{}\n{}\n", method, path, query);
+        for (name, value) in &signed {
+            let _ = writeln!(canonical, "{}:{}", name, value);
+        }
+        let signed_names = signed.iter().map(|(name, _)| name.as_str()).collect::<Vec<_>>().join(";");
+        let _ = write!(canonical, "\n{}\n{}", signed_names, hex(digest::digest(&digest::SHA256, body).as_ref()));
+
+        let scope = format!("{}/{}/{}/aws4_request", date, region, service);
+        let to_sign = format!(
+            "AWS4-HMAC-SHA256\n{}\n{}\n{}",
+            amz_date,
+            scope,
+            hex(digest::digest(&digest::SHA256, canonical.as_bytes()).as_ref())
+        );
+        let mut key = format!("AWS4{}", credentials.secret_access_key).into_bytes();
+        for part in [date, region, service, "aws4_request"] {
+            key = mac(&key, part.as_bytes());
+        }
+        let signature = hex(&mac(&key, to_sign.as_bytes()));
+
+        let mut out = vec![("x-amz-date".to_string(), amz_date)];
+        if let Some(token) = &credentials.session_token {
+            out.push(("x-amz-security-token".to_string(), token.clone()));
+        }
+        out.push((
+            "authorization".to_string(),
+            format!(
+                "AWS4-HMAC-SHA256 Credential={}/{}, SignedHeaders={}, Signature={}",
+                credentials.access_key_id, scope, signed_names, signature
+            ),
+        ));
+        out
+    }
+
+    fn mac(key: &[u8], data: &[u8]) -> Vec<u8> {
+        hmac::sign(&hmac::Key::new(hmac::HMAC_SHA256, key), data).as_ref().to_vec()
+    }
+
+    fn hex(bytes: &[u8]) -> String {
+        bytes.iter().fold(String::new(), |mut out, byte| {
+            let _ = write!(out, "{:02x}", byte);
+            out
+        })
+    }
+}
+
+#[cfg(feature = "cloudwatch")]
+mod credentials {
+    use std::path::PathBuf;
+    use std::time::Duration;
+
+    use anyhow::{anyhow, bail, Context, Result};
+    use chrono::{DateTime, Utc};
+    use serde::Deserialize;
+
+    use super::signing::Credentials;
+
+    /// How long each call to a credentials endpoint may take.
+    const TIMEOUT: Duration = Duration::from_secs(2);
+    /// Where `AWS_CONTAINER_CREDENTIALS_RELATIVE_URI` is relative to.
+    const CONTAINER_ENDPOINT: &str = "http://169.254.170.2";
+    const IMDS_ENDPOINT: &str = "http://169.254.169.254";
+
+    /// Where credentials come from, in the order the AWS SDKs look.
+    #[derive(Debug, Clone, PartialEq, Eq)]
+    pub enum CredentialSource {
+        /// `AWS_ACCESS_KEY_ID`, `AWS_SECRET_ACCESS_KEY` and `AWS_SESSION_TOKEN`.
+        Environment,
+        /// An EKS service account (IRSA): `AWS_ROLE_ARN`, assumed through STS
+        /// with the token in `AWS_WEB_IDENTITY_TOKEN_FILE`.
+        WebIdentity {
+            role_arn: String,
+            token_file: PathBuf,
+            session_name: String,
+        },
+        /// The ECS task role or EKS Pod Identity endpoint, with the token
+        /// it wants from `AWS_CONTAINER_AUTHORIZATION_TOKEN(_FILE)`.
+        Container {
+            url: String,
+            token: Option<String>,
+            token_file: Option<PathBuf>,
+        },
+        /// The EC2 instance profile, over IMDSv2.
+        Instance,
+    }
+
+    impl CredentialSource {
+        /// The first source the environment points at.
+        pub fn detect() -> Result<Self> {
+            Self::from_vars(|name| std::env::var(name).ok())
+        }
+
+        /// `detect`, with the environment read through `var`.
+        pub fn from_vars(var: impl Fn(&str) -> Option<String>) -> Result<Self> {
+            let var = |name: &str| var(name).filter(|value| !value.is_empty());
+            if var("AWS_ACCESS_KEY_ID").is_some() {
+                return match var("AWS_SECRET_ACCESS_KEY") {
+                    Some(_) => Ok(CredentialSource::Environment),
+                    None => bail!("$AWS_ACCESS_KEY_ID is set but $AWS_SECRET_ACCESS_KEY isn't"),
+                };
+            }
+            if let (Some(role_arn), Some(token_file)) = (var("AWS_ROLE_ARN"), var("AWS_WEB_IDENTITY_TOKEN_FILE")) {
+                return Ok(CredentialSource::WebIdentity {
+                    role_arn,
+                    token_file: token_file.into(),
+                    session_name: var("AWS_ROLE_SESSION_NAME").unwrap_or_else(|| "crabtop".to_string()),
+                });
+            }
+            let url = var("AWS_CONTAINER_CREDENTIALS_RELATIVE_URI")
+                .map(|path| format!("{}{}", CONTAINER_ENDPOINT, path))
+                .or_else(|| var("AWS_CONTAINER_CREDENTIALS_FULL_URI"));
+            if let Some(url) = url {
+                return Ok(CredentialSource::Container {
+                    url,
+                    token: var("AWS_CONTAINER_AUTHORIZATION_TOKEN"),
+                    token_file: var("AWS_CONTAINER_AUTHORIZATION_TOKEN_FILE").map(PathBuf::from),
+                });
+            }
+            if var("AWS_EC2_METADATA_DISABLED").is_some_and(|value| value.eq_ignore_ascii_case("true")) {
+                bail!("The CloudWatch exporter found no AWS credentials: set $AWS_ACCESS_KEY_ID and $AWS_SECRET_ACCESS_KEY, or run it with a role");
+            }
+            Ok(CredentialSource::Instance)
+        }
+
+        /// Fresh credentials from this source; `region` is where STS is asked.
+        pub async fn fetch(&self, client: &reqwest::Client, region: &str) -> Result<Credentials> {
+            match self {
+                CredentialSource::Environment => {
+                    let var = |name: &str| std::env::var(name).ok().filter(|value| !value.is_empty());
+                    Ok(Credentials {
+                        access_key_id: var("AWS_ACCESS_KEY_ID").context("$AWS_ACCESS_KEY_ID is no longer set")?,
+                        secret_access_key: var("AWS_SECRET_ACCESS_KEY").context("$AWS_SECRET_ACCESS_KEY is no longer set")?,
+                        session_token: var("AWS_SESSION_TOKEN"),
+                        expires: None,
+                    })
+                }
+                CredentialSource::WebIdentity {
+                    role_arn,
+                    token_file,
+                    session_name,
+                } => {
+                    let token = std::fs::read_to_string(token_file).with_context(|| format!("Failed to read {}", token_file.display()))?;
+                    let query = [
+                        ("Action", "AssumeRoleWithWebIdentity"),
+                        ("Version", "2011-06-15"),
+                        ("RoleArn", role_arn.as_str()),
+                        ("RoleSessionName", session_name.as_str()),
+                        ("WebIdentityToken", token.trim()),
+                    ];
+                    let reply = client
+                        .get(format!("https://sts.{}.amazonaws.com/", region))
+                        .query(&query)
+                        .timeout(TIMEOUT)
+                        .send()
+                        .await
+                        .context("Failed to reach STS")?;
+                    let status = reply.status();
+                    let text = reply.text().await?;
+                    Credentials::from_sts_reply(&text).with_context(|| format!("STS didn't let crabtop assume {} (HTTP {})", role_arn, status))
+                }
+                CredentialSource::Container { url, token, token_file } => {
+                    let token = match token_file {
+                        // Rotated under us, so read every time.
+                        Some(file) => Some(std::fs::read_to_string(file).with_context(|| format!("Failed to read {}", file.display()))?),
+                        None => token.clone(),
+                    };
+                    let mut request = client.get(url).timeout(TIMEOUT);
+                    if let Some(token) = token {
+                        request = request.header(reqwest::header::AUTHORIZATION, token.trim());
+                    }
+                    let text = checked(request.send().await, url).await?;
+                    Credentials::from_json(&text)
+                }
+                CredentialSource::Instance => {
+                    let tokens = format!("{}/latest/api/token", IMDS_ENDPOINT);
+                    let token = client.put(&tokens).header("X-aws-ec2-metadata-token-ttl-seconds", "21600").timeout(TIMEOUT).send().await;
+                    let token = checked(token, &tokens).await.context("No AWS credentials in the environment, and no instance metadata service")?;
+                    let roles = format!("{}/latest/meta-data/iam/security-credentials/", IMDS_ENDPOINT);
+                    let get = |url: String| client.get(url).header("X-aws-ec2-metadata-token", token.trim()).timeout(TIMEOUT).send();
+                    let role = checked(get(roles.clone()).await, &roles).await.context("The instance has no instance profile")?;
+                    let role = role.lines().next().context("The instance has no instance profile")?.trim().to_string();
+                    let text = checked(get(format!("{}{}", roles, role)).await, &roles).await?;
+                    Credentials::from_json(&text)
+                }
+            }
+        }
+    }
+
+    /// The body of a successful reply from a credentials endpoint.
+    async fn checked(reply: reqwest::Result<reqwest::Response>, url: &str) -> Result<String> {
+        let reply = reply.with_context(|| format!("Failed to reach {}", url))?;
+        if !reply.status().is_success() {
+            bail!("{} answered HTTP {}", url, reply.status());
+        }
+        Ok(reply.text().await?)
+    }
+
+    /// What the container and instance metadata endpoints return.
+    #[derive(Deserialize)]
+    #[serde(rename_all = "PascalCase")]
+    struct Issued {
+        access_key_id: String,
+        secret_access_key: String,
+        token: Option<String>,
+        expiration: Option<DateTime<Utc>>,
+    }
+
+    impl Credentials {
+        /// The JSON that the ECS, EKS Pod Identity and EC2 endpoints hand out.
+        pub fn from_json(text: &str) -> Result<Self> {
+            let issued: Issued = serde_json::from_str(text).context("Failed to parse the credentials")?;
+            Ok(Self {
+                access_key_id: issued.access_key_id,
+                secret_access_key: issued.secret_access_key,
+                session_token: issued.token,
+                expires: issued.expiration,
+            })
+        }
+
+        /// STS's `AssumeRoleWithWebIdentity` reply, or the error in it.
+        pub fn from_sts_reply(xml: &str) -> Result<Self> {
+            let field = |tag: &str| {
+                let start = xml.find(&format!("<{}>", tag))? + tag.len() + 2;
+                let end = start + xml[start..].find(&format!("</{}>", tag))?;
+                Some(xml[start..end].replace("&amp;", "&").replace("&lt;", "<").replace("&gt;", ">"))
+            };
+            if let Some(code) = field("Code") {
+                return Err(anyhow!("{}: {}", code, field("Message").unwrap_or_default()));
+            }
+            Ok(Self {
+                access_key_id: field("AccessKeyId").context("No AccessKeyId in STS's reply")?,
+                secret_access_key: field("SecretAccessKey").context("No SecretAccessKey in STS's reply")?,
+                session_token: field("SessionToken"),
+                expires: field("Expiration").and_then(|at| at.parse().ok()),
+            })
+        }
+
+        /// Whether they'll have stopped working by `at`.
+        pub fn expired_by(&self, at: DateTime<Utc>) -> bool {
+            self.expires.is_some_and(|expires| expires <= at)
+        }
+    }
+}
+
+#[cfg(feature = "cloudwatch")]
+mod exporter {
+    use std::sync::{Arc, Mutex};
+
+    use anyhow::{anyhow, bail, Context, Result};
+    use chrono::Utc;
+
+    use super::credentials::CredentialSource;
+    use super::signing::{sign, Credentials, Request};
+    use crate::export::{ExportSample, Exporter};
+
+    const CONTENT_TYPE: &str = "application/x-www-form-urlencoded; charset=utf-8";
+    /// Temporary credentials are fetched again this long before they expire.
+    const REFRESH_BEFORE: chrono::Duration = chrono::Duration::minutes(5);
+
+    /// Calls `PutMetricData` for each sample in the background; a failed
+    /// call is reported with the next sample.
+    pub struct CloudWatchExporter {
+        client: reqwest::Client,
+        source: Arc<CredentialSource>,
+        /// The last credentials fetched; a task holds the lock while it
+        /// fetches new ones, so the others wait for those.
+        credentials: Arc<tokio::sync::Mutex<Option<Credentials>>>,
+        region: String,
+        namespace: String,
+        last_error: Arc<Mutex<Option<String>>>,
+    }
+
+    impl CloudWatchExporter {
+        /// `region` falls back to `$AWS_REGION`, then `$AWS_DEFAULT_REGION`.
+        /// A missing region fails right away; credentials are fetched with
+        /// the first sample.
+        pub fn new(region: Option<&str>, namespace: &str) -> Result<Self> {
+            let region = region
+                .map(str::to_string)
+                .or_else(|| std::env::var("AWS_REGION").ok())
+                .or_else(|| std::env::var("AWS_DEFAULT_REGION").ok())
+                .filter(|region| !region.is_empty())
+                .context("The CloudWatch exporter needs a region (`region` in the config, or $AWS_REGION)")?;
+            Ok(Self {
+                client: reqwest::Client::new(),
+                source: Arc::new(CredentialSource::detect()?),
+                credentials: Arc::default(),
+                region,
+                namespace: namespace.to_string(),
+                last_error: Arc::default(),
+            })
+        }
+
+        fn take_error(&self) -> Result<()> {
+            match self.last_error.lock().unwrap().take() {
+                Some(message) => Err(anyhow!(message)),
+                None => Ok(()),
+            }
+        }
+    }
+
+    impl Exporter for CloudWatchExporter {
+        fn name(&self) -> &str {
+            "cloudwatch"
+        }
+
+        fn on_sample(&mut self, sample: &ExportSample) -> Result<()> {
+            let body = super::form_encode(&super::put_metric_data(sample, &self.namespace));
+            let (client, source, credentials, region) = (self.client.clone(), Arc::clone(&self.source), Arc::clone(&self.credentials), self.region.clone());
+            let last_error = Arc::clone(&self.last_error);
+            tokio::spawn(async move {
+                let put = async {
+                    let credentials = {
+                        let mut cached = credentials.lock().await;
+                        match cached.as_ref().filter(|c| !c.expired_by(Utc::now() + REFRESH_BEFORE)) {
+                            Some(current) => current.clone(),
+                            None => cached.insert(source.fetch(&client, &region).await?).clone(),
+                        }
+                    };
+                    let host = format!("monitoring.{}.amazonaws.com", region);
+                    let headers = [("content-type", CONTENT_TYPE), ("host", host.as_str())];
+                    let signed = Request {
+                        method: "POST",
+                        path: "/",
+                        query: "",
+                        headers: &headers,
+                        body: body.as_bytes(),
+                    };
+                    let signature = sign(&credentials, &region, "monitoring", signed, Utc::now());
+                    let mut request = client.post(format!("https://{}/", host)).header(reqwest::header::CONTENT_TYPE, CONTENT_TYPE).body(body);
+                    for (name, value) in signature {
+                        request = request.header(name, value);
+                    }
+                    match request.send().await? {
+                        resp if resp.status().is_success() => Ok(()),
+                        resp => bail!("HTTP {}", resp.status()),
+                    }
+                };
+                if let Err(err) = put.await {
+                    *last_error.lock().unwrap() = Some(format!("{:#}", err));
+                }
+            });
+            self.take_error()
+        }
+
+        fn on_shutdown(&mut self) -> Result<()> {
+            self.take_error()
+        }
+    }
+}
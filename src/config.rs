@@ -0,0 +1,493 @@
+//! The optional TOML config file.
+//!
+//! Looked up at `--config <path>` (or `$CRABTOP_CONFIG`), else taken from
+//! `$CRABTOP_CONFIG_TOML` itself, for containers with no file mounted, else
+//! `$XDG_CONFIG_HOME/crabtop/config.toml` (`~/.config/crabtop/config.toml`).
+//! A missing default file is not an error.
+//!
+//! ```toml
+//! [[exporter]]
+//! type = "csv"
+//! path = "samples.csv"
+//!
+//! [[exporter]]
+//! type = "prometheus"
+//! listen = "127.0.0.1:9184"
+//! ```
+
+use std::fs;
+use std::net::SocketAddr;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use anyhow::{Context, Result};
+use serde::Deserialize;
+
+use crate::ai::ProviderKind;
+use crate::email::Security;
+use crate::statsd::StatsdFormat;
+use crate::syslog::{Facility, Severity};
+
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Config {
+    #[serde(default)]
+    pub ai: AiConfig,
+    /// Sinks every watch sample is sent to, in addition to the screen.
+    #[serde(default, rename = "exporter")]
+    pub exporters: Vec<ExporterConfig>,
+    /// Alerts on expressions over the samples; see `rules`.
+    #[serde(default, rename = "rule")]
+    pub rules: Vec<RuleConfig>,
+    /// Ports and processes watched together with `--service`.
+    #[serde(default, rename = "service")]
+    pub services: Vec<ServiceConfig>,
+    /// Acts on the process when it stays unhealthy; off without the table.
+    pub watchdog: Option<WatchdogConfig>,
+}
+
+/// The `[watchdog]` table: what counts as unhealthy, what is done about
+/// it, and how often that may happen.
+///
+/// ```toml
+/// [watchdog]
+/// health = "/healthz"
+/// failures = 3
+/// memory_max = "2GB"
+/// action = "systemd"
+/// unit = "api.service"
+/// max_actions = 3
+/// window = "1h"
+/// cooldown = "5m"
+/// ```
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct WatchdogConfig {
+    /// Checked every sample, as `--http-check` is: a path on the port, or a URL.
+    pub health: Option<String>,
+    #[serde(default = "default_expect_status")]
+    pub expect_status: u16,
+    /// Failed checks in a row that make the process unhealthy.
+    #[serde(default = "default_watchdog_failures")]
+    pub failures: u32,
+    /// Memory over which the process is unhealthy at once, e.g. `"2GB"`.
+    #[serde(default, deserialize_with = "optional_size_text")]
+    pub memory_max: Option<u64>,
+    #[serde(default)]
+    pub action: WatchdogAction,
+    /// The unit for `action = "systemd"` [default: the process's own].
+    pub unit: Option<String>,
+    /// The container for `action = "docker"` [default: the process's own].
+    pub container: Option<String>,
+    /// Run with `sh -c` for `action = "command"`; `{pid}` and `{port}` are filled in.
+    pub command: Option<String>,
+    /// At most this many actions in any `window`.
+    #[serde(default = "default_watchdog_max_actions")]
+    pub max_actions: u32,
+    #[serde(default = "default_watchdog_window", deserialize_with = "duration_text")]
+    pub window: Duration,
+    /// After acting, nothing more is done for this long.
+    #[serde(default = "default_watchdog_cooldown", deserialize_with = "duration_text")]
+    pub cooldown: Duration,
+    /// An action still running after this long is killed, and has failed.
+    #[serde(default = "default_watchdog_timeout", deserialize_with = "duration_text")]
+    pub timeout: Duration,
+    /// Where each action is logged [default: ~/.local/state/crabtop/watchdog.log].
+    pub audit_log: Option<PathBuf>,
+    /// Log what would be run instead of running it.
+    #[serde(default)]
+    pub dry_run: bool,
+}
+
+/// What the watchdog does to an unhealthy process.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum WatchdogAction {
+    /// Whatever `crabtop restart` would do.
+    #[default]
+    Restart,
+    /// `systemctl restart`.
+    Systemd,
+    /// `docker restart`.
+    Docker,
+    /// The configured `command`.
+    Command,
+}
+
+/// A `[[service]]` table: the ports and process patterns one service runs
+/// as, watched together under its name.
+///
+/// ```toml
+/// [[service]]
+/// name = "web"
+/// ports = [8080, 8443]
+/// match = ["celery .*worker"]
+/// ```
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ServiceConfig {
+    pub name: String,
+    #[serde(default)]
+    pub ports: Vec<u16>,
+    /// Command-line patterns, as for `--match`, for members that don't
+    /// listen anywhere, such as queue workers.
+    #[serde(default, rename = "match")]
+    pub patterns: Vec<String>,
+}
+
+/// A `[[rule]]` table.
+///
+/// ```toml
+/// [[rule]]
+/// name = "busy"
+/// when = "avg(cpu, 5m) > 80 && connections > 100"
+/// severity = "error"
+/// cooldown = "15m"
+/// sinks = ["email"]
+/// ```
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct RuleConfig {
+    /// The alert's rule name, e.g. for `journalctl CRABTOP_RULE=busy`.
+    pub name: String,
+    pub when: String,
+    #[serde(default = "default_min_severity")]
+    pub severity: Severity,
+    /// After firing, the rule stays quiet this long even if it turns true again.
+    #[serde(default, deserialize_with = "duration_text")]
+    pub cooldown: Duration,
+    /// Exporters (by type) the alert goes to; every exporter when empty.
+    #[serde(default)]
+    pub sinks: Vec<String>,
+    pub message: Option<String>,
+}
+
+/// The `[ai]` table. Command-line flags take precedence over each field.
+///
+/// ```toml
+/// [ai]
+/// provider = "azure"
+/// url = "https://my-res.openai.azure.com/openai/deployments/gpt-4o-mini"
+/// api_key_env = "MY_AZURE_KEY"
+/// ```
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct AiConfig {
+    pub provider: Option<ProviderKind>,
+    pub url: Option<String>,
+    pub model: Option<String>,
+    /// Environment variable holding the API key; keys never live in the file.
+    pub api_key_env: Option<String>,
+    /// Azure OpenAI `api-version`.
+    pub api_version: Option<String>,
+    pub temperature: Option<f64>,
+    /// File holding the insight prompt; see `ai::PromptTemplate` for placeholders.
+    pub prompt_template: Option<PathBuf>,
+    #[serde(default)]
+    pub redact: RedactConfig,
+    #[serde(default)]
+    pub budget: BudgetConfig,
+}
+
+/// The `[ai.budget]` table. Limits are off unless set; hitting one makes
+/// further AI calls fail until the run (or the day) is over.
+///
+/// ```toml
+/// [ai.budget]
+/// max_requests_per_run = 60
+/// max_tokens_per_day = 200000
+/// ```
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct BudgetConfig {
+    pub max_requests_per_run: Option<u64>,
+    pub max_tokens_per_run: Option<u64>,
+    pub max_requests_per_day: Option<u64>,
+    pub max_tokens_per_day: Option<u64>,
+    /// Reuse the reply to a byte-identical request instead of sending it again.
+    pub cache: bool,
+    /// Where daily usage is kept [default: ~/.local/state/crabtop/ai-usage.json].
+    pub state_file: Option<PathBuf>,
+}
+
+impl Default for BudgetConfig {
+    fn default() -> Self {
+        Self {
+            max_requests_per_run: None,
+            max_tokens_per_run: None,
+            max_requests_per_day: None,
+            max_tokens_per_day: None,
+            cache: true,
+            state_file: None,
+        }
+    }
+}
+
+/// The `[ai.redact]` table: what is scrubbed before anything reaches an AI
+/// backend. Everything is on by default.
+///
+/// ```toml
+/// [ai.redact]
+/// command_args = false          # send only the program name
+/// literals = ["acme-internal"]
+/// ```
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct RedactConfig {
+    /// Values of `--token=…`-style arguments and of our own secret-looking
+    /// environment variables.
+    pub secrets: bool,
+    /// Send command-line arguments at all; `false` keeps only the program.
+    pub command_args: bool,
+    /// This machine's hostname.
+    pub hostnames: bool,
+    /// `$HOME`, shown as `~`.
+    pub home: bool,
+    /// Extra strings to replace wherever they appear.
+    pub literals: Vec<String>,
+}
+
+impl Default for RedactConfig {
+    fn default() -> Self {
+        Self {
+            secrets: true,
+            command_args: true,
+            hostnames: true,
+            home: true,
+            literals: Vec::new(),
+        }
+    }
+}
+
+/// One `[[exporter]]` table; `type` picks the sink.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(tag = "type", rename_all = "lowercase", deny_unknown_fields)]
+pub enum ExporterConfig {
+    /// Appends one row per sample.
+    Csv { path: PathBuf },
+    /// Serves the latest sample on `http://<listen>/metrics`.
+    Prometheus { listen: SocketAddr },
+    /// Writes the latest sample to `.prom` files in `dir` for node_exporter's textfile collector.
+    Textfile { dir: PathBuf },
+    /// PUTs every sample to a Pushgateway at `url` (under `job="crabtop"`).
+    Pushgateway { url: String },
+    /// Sends every sample to a Prometheus remote_write endpoint.
+    #[serde(rename = "remote_write")]
+    RemoteWrite { url: String },
+    Statsd {
+        address: String,
+        #[serde(default = "default_statsd_prefix")]
+        prefix: String,
+        #[serde(default)]
+        format: StatsdFormat,
+        #[serde(default)]
+        tags: Vec<String>,
+    },
+    /// Posts every sample to Datadog's metrics API (`datadog` feature).
+    Datadog {
+        /// `datadoghq.com`, `datadoghq.eu`, `us5.datadoghq.com`...
+        #[serde(default = "default_datadog_site")]
+        site: String,
+        /// Environment variable holding the API key; keys never live in the file.
+        #[serde(default = "default_datadog_key_env")]
+        api_key_env: String,
+        #[serde(default)]
+        tags: Vec<String>,
+    },
+    /// Puts every sample to CloudWatch as custom metrics (`cloudwatch` feature).
+    Cloudwatch {
+        /// Defaults to `$AWS_REGION`.
+        region: Option<String>,
+        #[serde(default = "default_cloudwatch_namespace")]
+        namespace: String,
+    },
+    /// Publishes every sample as JSON to a Kafka topic (`kafka` feature).
+    Kafka {
+        /// Bootstrap `host:port` addresses.
+        brokers: Vec<String>,
+        topic: String,
+    },
+    /// Publishes every sample as JSON to a NATS subject (`nats` feature).
+    Nats { url: String, subject: String },
+    /// Publishes every sample to an MQTT broker, announcing Home Assistant
+    /// sensors unless `discovery = false` (`mqtt` feature).
+    Mqtt {
+        url: String,
+        #[serde(default = "default_mqtt_prefix")]
+        prefix: String,
+        #[serde(default = "default_true")]
+        discovery: bool,
+        #[serde(default = "default_discovery_prefix")]
+        discovery_prefix: String,
+    },
+    /// Sends alerts (not samples) to the local syslog daemon.
+    Syslog {
+        #[serde(default)]
+        facility: Facility,
+    },
+    /// Sends alerts (not samples) to the systemd journal, with their fields.
+    Journald {
+        #[serde(default)]
+        facility: Facility,
+    },
+    /// Mails alerts (not samples) at or above `min_severity`, at most one
+    /// per rule and target every `cooldown`.
+    Email {
+        /// `host:port` of the SMTP server.
+        server: String,
+        #[serde(default)]
+        security: Security,
+        username: Option<String>,
+        /// Environment variable holding the password; passwords never live in the file.
+        password_env: Option<String>,
+        from: String,
+        to: Vec<String>,
+        #[serde(default = "default_min_severity")]
+        min_severity: Severity,
+        #[serde(default = "default_email_cooldown", deserialize_with = "duration_text")]
+        cooldown: Duration,
+    },
+    /// Opens PagerDuty incidents for exits and for breaches lasting
+    /// `sustain`, and resolves them when the condition clears.
+    Pagerduty {
+        /// Environment variable holding the integration's routing key.
+        #[serde(default = "default_routing_key_env")]
+        routing_key_env: String,
+        /// Replaces the Events API URL, e.g. `https://events.eu.pagerduty.com/v2/enqueue`.
+        url: Option<String>,
+        #[serde(default = "default_sustain", deserialize_with = "duration_text")]
+        sustain: Duration,
+    },
+    /// The same, as Opsgenie alerts closed on recovery.
+    Opsgenie {
+        #[serde(default = "default_opsgenie_key_env")]
+        api_key_env: String,
+        /// Replaces the API base, e.g. `https://api.eu.opsgenie.com`.
+        url: Option<String>,
+        #[serde(default = "default_sustain", deserialize_with = "duration_text")]
+        sustain: Duration,
+    },
+    /// POSTs alerts (and, with `samples = true`, every sample) as JSON.
+    Webhook {
+        url: String,
+        #[serde(default)]
+        samples: bool,
+    },
+}
+
+fn default_statsd_prefix() -> String {
+    "crabtop".to_string()
+}
+
+fn default_true() -> bool {
+    true
+}
+
+fn default_mqtt_prefix() -> String {
+    "crabtop".to_string()
+}
+
+fn default_discovery_prefix() -> String {
+    "homeassistant".to_string()
+}
+
+fn default_min_severity() -> Severity {
+    Severity::Warning
+}
+
+fn default_email_cooldown() -> Duration {
+    Duration::from_secs(15 * 60)
+}
+
+fn default_routing_key_env() -> String {
+    crate::incident::DEFAULT_ROUTING_KEY_ENV.to_string()
+}
+
+fn default_opsgenie_key_env() -> String {
+    crate::incident::DEFAULT_OPSGENIE_KEY_ENV.to_string()
+}
+
+fn default_expect_status() -> u16 {
+    200
+}
+
+fn default_watchdog_failures() -> u32 {
+    3
+}
+
+fn default_watchdog_max_actions() -> u32 {
+    3
+}
+
+fn default_watchdog_window() -> Duration {
+    Duration::from_secs(60 * 60)
+}
+
+fn default_watchdog_cooldown() -> Duration {
+    Duration::from_secs(5 * 60)
+}
+
+fn default_watchdog_timeout() -> Duration {
+    Duration::from_secs(60)
+}
+
+fn default_sustain() -> Duration {
+    Duration::from_secs(60)
+}
+
+/// A duration written the way the command line takes them, e.g. `"15m"`.
+fn duration_text<'de, D: serde::Deserializer<'de>>(deserializer: D) -> Result<Duration, D::Error> {
+    let text = String::deserialize(deserializer)?;
+    crate::profile::parse_duration(&text).map_err(serde::de::Error::custom)
+}
+
+/// A size written the way the command line takes them, e.g. `"2GB"`.
+fn optional_size_text<'de, D: serde::Deserializer<'de>>(deserializer: D) -> Result<Option<u64>, D::Error> {
+    let text = String::deserialize(deserializer)?;
+    crate::rotate::parse_size(&text).map(Some).map_err(serde::de::Error::custom)
+}
+
+fn default_datadog_site() -> String {
+    "datadoghq.com".to_string()
+}
+
+fn default_datadog_key_env() -> String {
+    crate::datadog::DEFAULT_API_KEY_ENV.to_string()
+}
+
+fn default_cloudwatch_namespace() -> String {
+    crate::cloudwatch::DEFAULT_NAMESPACE.to_string()
+}
+
+/// `$XDG_CONFIG_HOME/crabtop/config.toml`, falling back to `~/.config`.
+pub fn default_path() -> Option<PathBuf> {
+    let base = std::env::var_os("XDG_CONFIG_HOME")
+        .map(PathBuf::from)
+        .or_else(|| std::env::var_os("HOME").map(|home| PathBuf::from(home).join(".config")))?;
+    Some(base.join("crabtop").join("config.toml"))
+}
+
+/// The variable holding the whole config as TOML.
+pub const INLINE_ENV: &str = "CRABTOP_CONFIG_TOML";
+
+/// Loads `path`, or `$CRABTOP_CONFIG_TOML`, or the default file if it
+/// exists, or an empty config.
+pub fn load(path: Option<&Path>) -> Result<Config> {
+    let path = match path {
+        Some(path) => path.to_path_buf(),
+        None if std::env::var_os(INLINE_ENV).is_some() => {
+            let text = std::env::var(INLINE_ENV).with_context(|| format!("{} is not valid UTF-8", INLINE_ENV))?;
+            return parse(&text).with_context(|| format!("Invalid config in {}", INLINE_ENV));
+        }
+        None => match default_path().filter(|p| p.exists()) {
+            Some(path) => path,
+            None => return Ok(Config::default()),
+        },
+    };
+    let text = fs::read_to_string(&path).with_context(|| format!("Failed to read config {}", path.display()))?;
+    parse(&text).with_context(|| format!("Invalid config {}", path.display()))
+}
+
+pub fn parse(text: &str) -> Result<Config> {
+    Ok(toml::from_str(text)?)
+}
@@ -0,0 +1,278 @@
+//! The control socket of a running watch session: a Unix socket that other
+//! programs can write one command per line to, e.g. from a deploy script:
+//!
+//! ```text
+//! echo 'deploy v1.2 rolled out' | socat - UNIX-CONNECT:$XDG_RUNTIME_DIR/crabtop-8080.sock
+//! ```
+//!
+//! or with `crabtop ctl deploy "v1.2 rolled out"`. Each line gets `ok`,
+//! `ok <answer>` or `error: ...` back. Commands are queued for the watch
+//! loop, which answers them between samples.
+
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use anyhow::{anyhow, Result};
+use tokio::sync::{broadcast, mpsc, oneshot};
+
+use crate::annotation::AnnotationKind;
+use crate::export::ExportSample;
+use crate::profile::parse_duration;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum ControlCommand {
+    /// `annotate TEXT` (a marker) or `deploy TEXT`.
+    Annotate { kind: AnnotationKind, text: String },
+    /// `interval DURATION`: seconds between samples from now on.
+    Interval(u64),
+    /// `snapshot [PATH]`: write the dashboard out as plain text.
+    Snapshot(Option<PathBuf>),
+    /// `stats`: the latest sample, as one line of JSON.
+    Stats,
+    /// `stream`: `ok`, then every sample as a line of JSON until the client hangs up.
+    Stream,
+    /// `stop`: end the session.
+    Stop,
+}
+
+const USAGE: &str = "try `annotate TEXT`, `deploy TEXT`, `interval SECS`, `snapshot [PATH]`, `stats`, `stream` or `stop`";
+
+pub fn parse_command(line: &str) -> Result<ControlCommand, String> {
+    let line = line.trim();
+    let (verb, rest) = line.split_once(char::is_whitespace).unwrap_or((line, ""));
+    let text = rest.trim();
+    let kind = match verb {
+        "annotate" => AnnotationKind::Marker,
+        "deploy" => AnnotationKind::Deploy,
+        "interval" => {
+            let interval = parse_duration(text)?;
+            if interval < Duration::from_secs(1) {
+                return Err("the interval must be at least 1s".to_string());
+            }
+            return Ok(ControlCommand::Interval(interval.as_secs()));
+        }
+        "snapshot" => return Ok(ControlCommand::Snapshot((!text.is_empty()).then(|| PathBuf::from(text)))),
+        "stats" | "stream" | "stop" if !text.is_empty() => return Err(format!("`{}` takes no arguments", verb)),
+        "stats" => return Ok(ControlCommand::Stats),
+        "stream" => return Ok(ControlCommand::Stream),
+        "stop" => return Ok(ControlCommand::Stop),
+        "" => return Err("empty command".to_string()),
+        other => return Err(format!("unknown command `{}` ({})", other, USAGE)),
+    };
+    if text.is_empty() {
+        return Err(format!("`{}` needs some text", verb));
+    }
+    Ok(ControlCommand::Annotate {
+        kind,
+        text: text.to_string(),
+    })
+}
+
+/// A command waiting for the watch loop's answer.
+pub struct ControlRequest {
+    pub command: ControlCommand,
+    reply: oneshot::Sender<Result<String, String>>,
+}
+
+impl ControlRequest {
+    /// Sends `ok`, followed by `answer` unless it's empty.
+    pub fn answer(self, answer: impl Into<String>) {
+        let _ = self.reply.send(Ok(answer.into()));
+    }
+
+    pub fn fail(self, message: impl Into<String>) {
+        let _ = self.reply.send(Err(message.into()));
+    }
+}
+
+/// `$XDG_RUNTIME_DIR`, or the temp directory without one: where sockets
+/// and daemon state files go.
+pub fn runtime_dir() -> PathBuf {
+    std::env::var_os("XDG_RUNTIME_DIR")
+        .map(PathBuf::from)
+        .unwrap_or_else(std::env::temp_dir)
+}
+
+/// `crabtop-<port>.sock` in the runtime directory.
+pub fn default_path(port: u16) -> PathBuf {
+    runtime_dir().join(format!("crabtop-{}.sock", port))
+}
+
+/// The socket `crabtop ctl` should talk to: the one for `port`, or the only
+/// one in the runtime directory when no port is given.
+pub fn find_socket(port: Option<u16>) -> Result<PathBuf> {
+    if let Some(port) = port {
+        return Ok(default_path(port));
+    }
+    let dir = runtime_dir();
+    let mut found: Vec<PathBuf> = std::fs::read_dir(&dir)
+        .map(|entries| {
+            entries
+                .flatten()
+                .map(|entry| entry.path())
+                .filter(|path| {
+                    let name = path.file_name().and_then(|n| n.to_str()).unwrap_or("");
+                    name.starts_with("crabtop-") && name.ends_with(".sock")
+                })
+                .collect()
+        })
+        .unwrap_or_default();
+    found.sort();
+    match found.len() {
+        0 => Err(anyhow!("No running watch session found in {} (is crabtop --watch running?)", dir.display())),
+        1 => Ok(found.remove(0)),
+        _ => Err(anyhow!(
+            "Several watch sessions are running ({}); pass --port or --socket",
+            found.iter().map(|p| p.display().to_string()).collect::<Vec<_>>().join(", ")
+        )),
+    }
+}
+
+/// Sends one command to the session listening on `path` and returns its
+/// answer, or the session's error as an `Err`.
+#[cfg(unix)]
+pub fn send(path: &Path, command: &str) -> Result<String> {
+    use std::io::{BufRead, BufReader, Write};
+    use std::os::unix::net::UnixStream;
+
+    use anyhow::Context;
+
+    let mut stream = UnixStream::connect(path)
+        .with_context(|| format!("Failed to connect to {} (is crabtop --watch running?)", path.display()))?;
+    writeln!(stream, "{}", command.replace('\n', " "))?;
+    let mut reply = String::new();
+    BufReader::new(stream).read_line(&mut reply)?;
+    let reply = reply.trim_end();
+    match reply.strip_prefix("error: ") {
+        Some(message) => Err(anyhow!("{}", message)),
+        None if reply == "ok" => Ok(String::new()),
+        None => reply
+            .strip_prefix("ok ")
+            .map(str::to_string)
+            .ok_or_else(|| anyhow!("Unexpected reply from {}: {:?}", path.display(), reply)),
+    }
+}
+
+#[cfg(not(unix))]
+pub fn send(_path: &Path, _command: &str) -> Result<String> {
+    Err(anyhow!("The control socket needs a Unix domain socket, which this platform doesn't have"))
+}
+
+/// A bound control socket; the file is removed on drop.
+pub struct ControlSocket {
+    path: PathBuf,
+    requests: mpsc::UnboundedReceiver<ControlRequest>,
+    /// Samples for `stream` clients, already as JSON lines.
+    samples: broadcast::Sender<String>,
+}
+
+impl ControlSocket {
+    /// Binds `path` and starts accepting connections in the background, so
+    /// this must run inside a Tokio runtime. A leftover socket nobody answers
+    /// on is replaced; one another crabtop is listening on is not.
+    #[cfg(unix)]
+    pub fn bind(path: &Path) -> Result<Self> {
+        use std::os::unix::fs::PermissionsExt;
+
+        use anyhow::Context;
+        use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
+        use tokio::net::UnixListener;
+
+        if path.exists() {
+            if std::os::unix::net::UnixStream::connect(path).is_ok() {
+                return Err(anyhow!("{} is already in use by another crabtop", path.display()));
+            }
+            std::fs::remove_file(path).with_context(|| format!("Failed to remove stale socket {}", path.display()))?;
+        }
+        let listener = UnixListener::bind(path).with_context(|| format!("Failed to bind {}", path.display()))?;
+        // Other users on the host shouldn't be able to drive the session.
+        std::fs::set_permissions(path, std::fs::Permissions::from_mode(0o600))?;
+
+        let (sender, requests) = mpsc::unbounded_channel();
+        // A client slower than this many samples skips ahead rather than holding them up.
+        let (samples, _) = broadcast::channel::<String>(16);
+        let published = samples.clone();
+        tokio::spawn(async move {
+            while let Ok((stream, _)) = listener.accept().await {
+                let sender = sender.clone();
+                let published = published.clone();
+                tokio::spawn(async move {
+                    let (reader, mut writer) = stream.into_split();
+                    let mut lines = BufReader::new(reader).lines();
+                    while let Ok(Some(line)) = lines.next_line().await {
+                        if let Ok(ControlCommand::Stream) = parse_command(&line) {
+                            let mut feed = published.subscribe();
+                            if writer.write_all(b"ok\n").await.is_err() {
+                                return;
+                            }
+                            loop {
+                                match feed.recv().await {
+                                    Ok(sample) => {
+                                        if writer.write_all(sample.as_bytes()).await.is_err() {
+                                            return;
+                                        }
+                                    }
+                                    Err(broadcast::error::RecvError::Lagged(_)) => continue,
+                                    Err(broadcast::error::RecvError::Closed) => return,
+                                }
+                            }
+                        }
+                        let answer = match parse_command(&line) {
+                            Ok(command) => {
+                                let (reply, answered) = oneshot::channel();
+                                if sender.send(ControlRequest { command, reply }).is_err() {
+                                    return;
+                                }
+                                answered.await.unwrap_or_else(|_| Err("the watch session ended".to_string()))
+                            }
+                            Err(message) => Err(message),
+                        };
+                        let reply = match answer {
+                            Ok(answer) if answer.is_empty() => "ok\n".to_string(),
+                            Ok(answer) => format!("ok {}\n", answer.replace('\n', " ")),
+                            Err(message) => format!("error: {}\n", message),
+                        };
+                        if writer.write_all(reply.as_bytes()).await.is_err() {
+                            return;
+                        }
+                    }
+                });
+            }
+        });
+        Ok(Self {
+            path: path.to_path_buf(),
+            requests,
+            samples,
+        })
+    }
+
+    #[cfg(not(unix))]
+    pub fn bind(_path: &Path) -> Result<Self> {
+        Err(anyhow!("The control socket needs a Unix domain socket, which this platform doesn't have"))
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Sends `sample` to every `stream` client.
+    pub fn publish(&self, sample: &ExportSample) {
+        if self.samples.receiver_count() == 0 {
+            return;
+        }
+        if let Ok(json) = serde_json::to_string(sample) {
+            let _ = self.samples.send(json + "\n");
+        }
+    }
+
+    /// The next queued request, without waiting.
+    pub fn try_recv(&mut self) -> Option<ControlRequest> {
+        self.requests.try_recv().ok()
+    }
+}
+
+impl Drop for ControlSocket {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_file(&self.path);
+    }
+}
@@ -0,0 +1,78 @@
+//! Which cores the process's threads run on, from each thread's `stat`
+//! (`utime`, `stime` and the `processor` it last ran on) and the per-core
+//! lines of `/proc/stat`.
+
+use crate::exec::Runner;
+use crate::procfs::parse_stat;
+
+/// CPU time of one thread and the core it was last seen on.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ThreadTime {
+    pub tid: u32,
+    pub cpu: u32,
+    /// `utime + stime`, in clock ticks.
+    pub ticks: u64,
+}
+
+/// One reading of the host's cores and the process's threads.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct CoreTimes {
+    /// Total ticks of each core, indexed by CPU number; offline cores are 0.
+    pub core_ticks: Vec<u64>,
+    pub threads: Vec<ThreadTime>,
+}
+
+/// Parses `/proc/stat` followed by the `stat` lines of the process's tasks.
+pub fn parse_core_times(text: &str) -> Option<CoreTimes> {
+    let mut times = CoreTimes::default();
+    for line in text.lines() {
+        if let Some(rest) = line.strip_prefix("cpu") {
+            // The aggregate `cpu ` line has no number and isn't a core.
+            let mut fields = rest.split_whitespace();
+            let Some(cpu) = fields.next().filter(|_| !rest.starts_with(' ')).and_then(|n| n.parse::<usize>().ok()) else {
+                continue;
+            };
+            // user nice system idle iowait irq softirq steal; guest time is already in user.
+            let ticks = fields.take(8).filter_map(|f| f.parse::<u64>().ok()).sum();
+            if times.core_ticks.len() <= cpu {
+                times.core_ticks.resize(cpu + 1, 0);
+            }
+            times.core_ticks[cpu] = ticks;
+        } else if let (Some(tid), Some(stat)) = (line.split_whitespace().next(), parse_stat(line)) {
+            let (Ok(tid), Some(cpu)) = (tid.parse(), stat.processor) else {
+                continue;
+            };
+            times.threads.push(ThreadTime {
+                tid,
+                cpu,
+                ticks: stat.utime + stat.stime,
+            });
+        }
+    }
+    (!times.core_ticks.is_empty() && !times.threads.is_empty()).then_some(times)
+}
+
+#[cfg(target_os = "linux")]
+pub fn core_times(pid: u32) -> Option<CoreTimes> {
+    let mut text = std::fs::read_to_string("/proc/stat").ok()?;
+    for task in std::fs::read_dir(format!("/proc/{}/task", pid)).ok()?.flatten() {
+        // Threads can exit between the listing and the read.
+        if let Ok(stat) = std::fs::read_to_string(task.path().join("stat")) {
+            text.push_str(&stat);
+        }
+    }
+    parse_core_times(&text)
+}
+
+#[cfg(not(target_os = "linux"))]
+pub fn core_times(_pid: u32) -> Option<CoreTimes> {
+    None
+}
+
+/// `core_times` through `runner`: the per-CPU lines of `/proc/stat` and
+/// each thread's stat, gathered by a single shell command.
+pub fn core_times_on(runner: &Runner, pid: u32) -> Option<CoreTimes> {
+    let script = format!("grep '^cpu[0-9]' /proc/stat && cat /proc/{}/task/*/stat 2>/dev/null; true", pid);
+    let out = runner.shell(&script).ok()?;
+    parse_core_times(&String::from_utf8_lossy(&out.stdout))
+}
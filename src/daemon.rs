@@ -0,0 +1,268 @@
+//! `crabtop daemon`: watch mode without a terminal. `daemon start` runs
+//! crabtop again as `daemon run` in a process group of its own, with its
+//! output going to a log file, and the daemon leaves a state file next to
+//! its control socket so `status`, `stop` and `attach` can find it later.
+//!
+//! Samples go wherever the exporters send them (`--record`, or a CSV,
+//! Prometheus, StatsD or webhook sink in the config file), and alerts to the
+//! exporters that take them.
+
+use std::path::{Path, PathBuf};
+use std::process::Child;
+
+use anyhow::{Context, Result};
+use chrono::{DateTime, Local};
+use serde::{Deserialize, Serialize};
+
+use crate::control::runtime_dir;
+
+#[cfg(unix)]
+pub use attach::DaemonSource;
+
+/// `crabtop-daemon-<port>.json`, written by the daemon once it is sampling.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct DaemonState {
+    /// The daemon's own PID, not the watched process's.
+    pub pid: u32,
+    pub port: u16,
+    pub interval_secs: u64,
+    pub started: DateTime<Local>,
+    /// `start_ticks(pid)` when the daemon wrote the file, so a process that
+    /// has since been given the same PID isn't taken for it.
+    #[serde(default)]
+    pub start_ticks: Option<u64>,
+    pub socket: PathBuf,
+    pub log: PathBuf,
+}
+
+impl DaemonState {
+    pub fn path(port: u16) -> PathBuf {
+        runtime_dir().join(format!("crabtop-daemon-{}.json", port))
+    }
+
+    /// Where `daemon start` sends the daemon's output.
+    pub fn log_path(port: u16) -> PathBuf {
+        runtime_dir().join(format!("crabtop-daemon-{}.log", port))
+    }
+
+    pub fn save(&self) -> Result<()> {
+        let path = Self::path(self.port);
+        std::fs::write(&path, serde_json::to_string_pretty(self)?)
+            .with_context(|| format!("Failed to write {}", path.display()))
+    }
+
+    /// The daemon for `port`, if a state file says there is one.
+    pub fn load(port: u16) -> Result<Option<Self>> {
+        let path = Self::path(port);
+        match std::fs::read_to_string(&path) {
+            Ok(text) => Ok(Some(
+                serde_json::from_str(&text).with_context(|| format!("Failed to parse {}", path.display()))?,
+            )),
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => Ok(None),
+            Err(err) => Err(err).with_context(|| format!("Failed to read {}", path.display())),
+        }
+    }
+
+    /// Every daemon with a state file, by port; unreadable files are skipped.
+    pub fn all() -> Vec<Self> {
+        let mut states: Vec<Self> = std::fs::read_dir(runtime_dir())
+            .map(|entries| {
+                entries
+                    .flatten()
+                    .filter_map(|entry| {
+                        let name = entry.file_name().into_string().ok()?;
+                        let port = name.strip_prefix("crabtop-daemon-")?.strip_suffix(".json")?.parse().ok()?;
+                        Self::load(port).ok().flatten()
+                    })
+                    .collect()
+            })
+            .unwrap_or_default();
+        states.sort_by_key(|state| state.port);
+        states
+    }
+
+    /// Whether the daemon is still there: its PID is alive and is the same
+    /// process, by start time where the kernel tells it, or else by a
+    /// command line of `... daemon run ...`.
+    pub fn is_running(&self) -> bool {
+        if !process_alive(self.pid) {
+            return false;
+        }
+        match (self.start_ticks, start_ticks(self.pid)) {
+            (Some(recorded), Some(now)) => recorded == now,
+            _ => command_line(self.pid).is_some_and(|args| args.windows(2).any(|pair| pair == ["daemon", "run"])),
+        }
+    }
+
+    /// Removes the state file, e.g. when the daemon exits.
+    pub fn remove(port: u16) {
+        let _ = std::fs::remove_file(Self::path(port));
+    }
+}
+
+/// Whether a process with this PID exists (it may belong to another user).
+pub fn process_alive(pid: u32) -> bool {
+    if cfg!(target_os = "linux") {
+        Path::new(&format!("/proc/{}", pid)).exists()
+    } else {
+        std::process::Command::new("kill")
+            .args(["-0", &pid.to_string()])
+            .stderr(std::process::Stdio::null())
+            .status()
+            .is_ok_and(|status| status.success())
+    }
+}
+
+/// When the process started, in clock ticks after boot (field 22 of
+/// `/proc/<pid>/stat`); `None` off Linux.
+#[cfg(target_os = "linux")]
+pub fn start_ticks(pid: u32) -> Option<u64> {
+    let stat = std::fs::read_to_string(format!("/proc/{}/stat", pid)).ok()?;
+    crate::procfs::parse_stat(&stat).map(|stat| stat.start_ticks)
+}
+
+#[cfg(not(target_os = "linux"))]
+pub fn start_ticks(_pid: u32) -> Option<u64> {
+    None
+}
+
+/// The process's arguments, from `/proc/<pid>/cmdline` or `ps`.
+fn command_line(pid: u32) -> Option<Vec<String>> {
+    if cfg!(target_os = "linux") {
+        let cmdline = std::fs::read(format!("/proc/{}/cmdline", pid)).ok()?;
+        let args = cmdline.split(|&b| b == 0).filter(|arg| !arg.is_empty());
+        return Some(args.map(|arg| String::from_utf8_lossy(arg).into_owned()).collect());
+    }
+    let out = std::process::Command::new("ps").args(["-o", "args=", "-p", &pid.to_string()]).output().ok()?;
+    out.status.success().then(|| String::from_utf8_lossy(&out.stdout).split_whitespace().map(str::to_string).collect())
+}
+
+/// Starts `exe` with `args` detached from this terminal: its own process
+/// group, no stdin, and stdout and stderr appended to `log`. The caller
+/// can watch the child for an early exit; dropping it leaves it running.
+#[cfg(unix)]
+pub fn spawn_detached(exe: &Path, args: &[String], log: &Path) -> Result<Child> {
+    use std::os::unix::process::CommandExt;
+    use std::process::{Command, Stdio};
+
+    let out = std::fs::OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(log)
+        .with_context(|| format!("Failed to open {}", log.display()))?;
+    Command::new(exe)
+        .args(args)
+        .stdin(Stdio::null())
+        .stdout(out.try_clone()?)
+        .stderr(out)
+        // Out of the terminal's process group, so closing it doesn't hang the daemon up.
+        .process_group(0)
+        .spawn()
+        .with_context(|| format!("Failed to start {}", exe.display()))
+}
+
+#[cfg(not(unix))]
+pub fn spawn_detached(_exe: &Path, _args: &[String], _log: &Path) -> Result<Child> {
+    Err(anyhow::anyhow!("Daemon mode needs a Unix domain socket, which this platform doesn't have"))
+}
+
+#[cfg(unix)]
+mod attach {
+    use std::path::Path;
+
+    use anyhow::{anyhow, Context, Result};
+    use tokio::sync::watch;
+
+    use crate::cgroup::CgroupStats;
+    use crate::export::ExportSample;
+    use crate::port::Listener;
+    use crate::process::{ProcessInfo, SystemSnapshot};
+    use crate::source::MetricsSource;
+
+    /// A `MetricsSource` fed by a daemon's `stream`, for `daemon attach`.
+    ///
+    /// The daemon does the sampling; each `sample` waits for the next line it
+    /// sends and returns its process figures. Everything else the daemon
+    /// doesn't send (listeners, cgroup, system) is missing from the dashboard.
+    pub struct DaemonSource {
+        port: u16,
+        pids: Vec<u32>,
+        /// The sample `connect` waited for, returned by the first `sample`.
+        first: Option<ProcessInfo>,
+        latest: watch::Receiver<ExportSample>,
+    }
+
+    impl DaemonSource {
+        /// Asks the daemon on `socket` to stream and waits for its first sample.
+        pub async fn connect(socket: &Path) -> Result<Self> {
+            use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
+
+            let stream = tokio::net::UnixStream::connect(socket)
+                .await
+                .with_context(|| format!("Failed to connect to {} (is the daemon running?)", socket.display()))?;
+            let (reader, mut writer) = stream.into_split();
+            writer.write_all(b"stream\n").await?;
+            let mut lines = BufReader::new(reader).lines();
+            match lines.next_line().await?.as_deref() {
+                Some("ok") => {}
+                Some(reply) => return Err(anyhow!("The daemon refused to stream: {}", reply)),
+                None => return Err(anyhow!("The daemon hung up")),
+            }
+            let first: ExportSample = match lines.next_line().await? {
+                Some(line) => serde_json::from_str(&line).context("Failed to parse a sample from the daemon")?,
+                None => return Err(anyhow!("The daemon hung up")),
+            };
+            let (port, pids) = (first.port, first.pids.clone());
+            let process = first.process.clone();
+            let (sender, latest) = watch::channel(first);
+            tokio::spawn(async move {
+                // Dropping the writer would shut down this end of the socket.
+                let _writer = writer;
+                while let Ok(Some(line)) = lines.next_line().await {
+                    if let Ok(sample) = serde_json::from_str(&line) {
+                        if sender.send(sample).is_err() {
+                            return;
+                        }
+                    }
+                }
+            });
+            Ok(Self {
+                port,
+                pids,
+                first: Some(process),
+                latest,
+            })
+        }
+    }
+
+    impl MetricsSource for DaemonSource {
+        fn resolve_pids(&self, port: u16) -> Result<Vec<u32>> {
+            if port != self.port {
+                return Err(anyhow!("The daemon watches port {}, not {}", self.port, port));
+            }
+            Ok(self.pids.clone())
+        }
+
+        async fn sample(&mut self, _pid: u32) -> Result<ProcessInfo> {
+            if let Some(first) = self.first.take() {
+                return Ok(first);
+            }
+            self.latest.changed().await.map_err(|_| anyhow!("The daemon stopped"))?;
+            let sample = self.latest.borrow_and_update().clone();
+            self.pids = sample.pids;
+            Ok(sample.process)
+        }
+
+        fn system(&mut self) -> Result<SystemSnapshot> {
+            Err(anyhow!("The daemon doesn't send system figures"))
+        }
+
+        fn listeners(&mut self, _pid: u32) -> Result<Vec<Listener>> {
+            Ok(Vec::new())
+        }
+
+        fn cgroup(&mut self, _pid: u32) -> Option<CgroupStats> {
+            None
+        }
+    }
+}
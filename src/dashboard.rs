@@ -0,0 +1,2962 @@
+use std::io::Write;
+
+use anyhow::Result;
+use chrono::NaiveDateTime;
+use crossterm::{
+    execute,
+    style::{Color, Print, ResetColor, SetForegroundColor},
+};
+
+use crate::ai::{Severity, Verdict};
+use crate::annotation::{Annotation, AnnotationKind};
+use crate::appmetrics::{AppMetrics, MetricKind};
+use crate::cgroup::CgroupStats;
+use crate::children::{ChildEventKind, ChildFeed};
+use crate::docker::Container;
+use crate::ebpf::EbpfStats;
+use crate::files::{FileKind, FileListing};
+use crate::fingerprint::Fingerprint;
+use crate::history::{ChartPoint, ChartView, ProcessHistory, CPU_BUCKETS, CPU_BUCKET_WIDTH};
+use crate::logging::{Level, LogLine};
+use crate::logs::LogTail;
+use crate::oom::{MemoryPressure, OomStatus};
+use crate::peers::Peer;
+use crate::pool::WorkerPool;
+use crate::port::{Listener, TcpStats};
+use crate::posture::{Confinement, Exposure, Posture};
+use crate::power::PowerMeter;
+use crate::probe::{HttpCheck, Probe};
+use crate::process::{CpuMode, ProcessCounters, ProcessInfo, SystemSnapshot};
+use crate::runtime::{Runtime, StackDump, DUMP_LINES};
+use crate::segments::{SegmentHistory, SEGMENTS};
+use crate::service::Member;
+use crate::socket::ListenerSockets;
+use crate::systemd::UnitStatus;
+use crate::tls::{TlsCheck, TlsInfo, EXPIRY_WARNING_DAYS};
+use crate::watch::{AcceptQueueHistory, ChurnHistory, CoreUsage, CounterHistory, EbpfHistory, SwapHistory, SyscallActivity, Tab, TABS};
+use crate::upstream::ProxyChain;
+use crate::units::{format_bytes, format_duration, format_mb, mb_unit, to_mb};
+
+/// Everything needed to draw one dashboard frame.
+pub struct Frame<'a> {
+    pub info: &'a ProcessInfo,
+    pub history: &'a ProcessHistory,
+    pub port: u16,
+    pub iteration: u64,
+    pub timestamp: NaiveDateTime,
+    pub width: u16,
+    /// `--cpu-mode`, named in the CPU section's title.
+    pub cpu_mode: CpuMode,
+    /// Cores of the process's host, for `CpuMode::System`.
+    pub cpu_count: usize,
+    /// The tab to draw, with the tab bar; `None` stacks every panel.
+    pub tab: Option<Tab>,
+    /// 'h': show the session's CPU samples as a histogram instead of a sparkline.
+    pub cpu_histogram: bool,
+    /// The stretch of the session the CPU and memory sparklines show.
+    pub chart: ChartView,
+    /// 'o': also draw CPU and memory on one chart, each scaled to its own range.
+    pub overlay: bool,
+    /// Restarts, breaches and markers, drawn under the CPU sparkline.
+    pub annotations: &'a [Annotation],
+    /// PIDs being followed; more than one means the figures are aggregated.
+    pub pids: &'a [u32],
+    /// Set when the port is published by a Docker container.
+    pub container: Option<&'a Container>,
+    /// Every socket the process listens on; may be empty if lookup failed.
+    pub listeners: &'a [Listener],
+    /// What the port speaks, if detection recognised it.
+    pub protocol: Option<&'a Fingerprint>,
+    /// Cgroup limits, shown only when the process runs under one.
+    pub cgroup: Option<&'a CgroupStats>,
+    /// Share of CFS periods throttled since the previous sample.
+    pub throttled_ratio: Option<f64>,
+    /// Listen-socket accept queue, where the platform reports one.
+    pub accept_queue: Option<&'a AcceptQueueHistory>,
+    /// OOM-killer score and host memory pressure.
+    pub oom: Option<&'a OomStatus>,
+    /// How much of the process is swapped out, sample by sample.
+    pub swap: Option<&'a SwapHistory>,
+    /// Resident memory by kind of mapping, with which one is growing.
+    pub segments: Option<&'a SegmentHistory>,
+    /// Page-fault and context-switch rates of the process.
+    pub counters: Option<&'a CounterHistory>,
+    /// Accepts, retransmits and syscall time from eBPF probes.
+    pub ebpf: Option<&'a EbpfHistory>,
+    /// What the process's syscalls went to, with `--syscalls`.
+    pub syscalls: Option<&'a SyscallActivity>,
+    /// RTT and retransmits of the port's established connections.
+    pub tcp_stats: Option<&'a TcpStats>,
+    /// Backlog, buffers and port sharing of the listening sockets.
+    pub sockets: Option<&'a ListenerSockets>,
+    /// The process's user, capabilities, binary and confinement.
+    pub posture: Option<&'a Posture>,
+    /// Connections opened and closed on the port per second.
+    pub churn: Option<&'a ChurnHistory>,
+    /// Who the open connections are from, busiest first.
+    pub peers: Option<&'a [Peer]>,
+    /// Retransmit percentage at which the TCP panel turns red.
+    pub retransmit_alert: f64,
+    /// Connect-latency probe, when `--probe` is on.
+    pub probe: Option<&'a Probe>,
+    /// HTTP health check, when `--http-check` is on.
+    pub http_check: Option<&'a HttpCheck>,
+    /// TLS handshake and certificate, when `--tls` is on.
+    pub tls: Option<&'a TlsCheck>,
+    /// Which cores the process's threads kept busy.
+    pub cores: Option<&'a CoreUsage>,
+    /// The workers of a prefork server, summed and one by one.
+    pub pool: Option<&'a WorkerPool>,
+    /// The processes' children being spawned and exiting.
+    pub children: Option<&'a ChildFeed>,
+    /// The backends behind a reverse proxy, with `--upstream`.
+    pub upstream: Option<&'a ProxyChain>,
+    /// RAPL-based power estimate, when the host exposes readable counters.
+    pub power: Option<&'a PowerMeter>,
+    /// The app's own `/metrics` or `/debug/pprof` readings, when it exposes them.
+    pub app: Option<&'a AppMetrics>,
+    /// The systemd service the process runs under.
+    pub unit: Option<&'a UnitStatus>,
+    /// A pending restart/stop confirmation, or how the last one went.
+    pub unit_prompt: Option<&'a str>,
+    /// Log pane; `None` while it is toggled off.
+    pub logs: Option<&'a LogTail>,
+    /// 'L': crabtop's own log, oldest first; `None` while it is toggled off.
+    pub crabtop_log: Option<&'a [LogLine]>,
+    /// Open-files listing; `None` until 'f' is pressed.
+    pub open_files: Option<&'a FileListing>,
+    /// The process's language runtime, when 'd' can dump its stacks.
+    pub runtime: Option<Runtime>,
+    /// Stack-dump pane; `None` until 'd' is pressed.
+    pub stack_dump: Option<&'a StackDump>,
+    /// Machine-wide panel; `None` while the panel is toggled off.
+    pub system: Option<&'a SystemSnapshot>,
+    /// Latest AI verdict, shown in the AI Insight panel.
+    pub verdict: Option<&'a Verdict>,
+    /// Status line under the verdict ("Asking…", an error, when it was updated).
+    pub insight: Option<&'a str>,
+    /// Whether an AI provider is configured, which adds 'a' to the footer.
+    pub ai_enabled: bool,
+    /// crabtop's own CPU and memory, above the footer: on a small box it is
+    /// part of what's being measured.
+    pub own: Option<&'a ProcessInfo>,
+}
+
+/// Renders the full watch-mode dashboard for one frame.
+pub fn render_dashboard<W: Write>(out: &mut W, frame: &Frame) -> Result<()> {
+    let Frame {
+        info,
+        history,
+        port,
+        iteration,
+        ..
+    } = *frame;
+    let timestamp = frame.timestamp.format("%Y-%m-%d %H:%M:%S");
+    let width = frame.width as usize;
+    
+    // Ensure minimum width
+    let min_width = 60;
+    let effective_width = width.max(min_width);
+    
+    // Calculate responsive sizes
+    let bar_width = (effective_width.saturating_sub(30)).clamp(20, 80);
+    let sparkline_width = sparkline_width(frame.width);
+    // Kept on the chart when zooming out leaves fewer columns.
+    let cursor = frame
+        .chart
+        .cursor
+        .map(|column| column.min(history.cpu_chart(&frame.chart, sparkline_width).len().saturating_sub(1)));
+    
+    // Room left for values after the "   Label:     " prefix
+    let value_width = effective_width.saturating_sub(14);
+
+    // Dynamic header
+    let header_text = match port {
+        // A `--target-*` session follows a container, not a port.
+        0 => " PORT INSPECTOR - Real-time Monitoring ".to_string(),
+        port => format!(" PORT INSPECTOR - Real-time Monitoring (Port {}) ", port),
+    };
+    let header_text_len = header_text.len().min(effective_width.saturating_sub(2));
+    let header_padding = effective_width.saturating_sub(header_text_len + 2);
+    let left_pad = header_padding / 2;
+    let right_pad = header_padding.saturating_sub(left_pad);
+    let display_text = if header_text.len() > header_text_len {
+        &header_text[..header_text_len]
+    } else {
+        &header_text
+    };
+    
+    execute!(
+        out,
+        SetForegroundColor(Color::Cyan),
+        Print("╔"),
+        Print("═".repeat(effective_width.saturating_sub(2))),
+        Print("╗\n"),
+        Print("║"),
+        SetForegroundColor(Color::Yellow),
+        Print(" ".repeat(left_pad)),
+        Print(display_text),
+        Print(" ".repeat(right_pad)),
+        SetForegroundColor(Color::Cyan),
+        Print("║\n"),
+        Print("╚"),
+        Print("═".repeat(effective_width.saturating_sub(2))),
+        Print("╝\n"),
+        ResetColor,
+    )?;
+    if let Some(tab) = frame.tab {
+        render_tab_bar(out, tab)?;
+    }
+    let shows = |tab: Tab| frame.tab.is_none_or(|shown| shown == tab);
+
+    // Process Info
+    execute!(
+        out,
+        Print("\n"),
+        SetForegroundColor(Color::Green),
+        Print("📊 Process Information\n"),
+        ResetColor,
+        Print(format!("   Name:      {}\n", info.name)),
+        Print(format!("   PID:       {}\n", truncate(&pid_list(info.pid, frame.pids), value_width))),
+        Print(format!("   User:      {}\n", info.user.as_deref().unwrap_or("-"))),
+        Print(format!("   Command:   {}\n", truncate(&command_line(info), value_width))),
+        Print(format!("   Exe:       {}\n", truncate(&display_path(&info.exe), value_width))),
+        Print(format!("   Cwd:       {}\n", truncate(&display_path(&info.cwd), value_width))),
+        Print(format!("   Uptime:    {}\n", format_duration(info.uptime_secs))),
+        Print(format!("   Port:      {}\n", port_label(port))),
+        Print(match frame.protocol {
+            Some(p) => format!("   Protocol:  {}\n", truncate(&p.to_string(), value_width)),
+            None => String::new(),
+        }),
+        Print(match frame.container {
+            Some(c) => format!("   Container: {}\n", truncate(&format!("{} ({}, {})", c.name, c.image, short_id(&c.id)), value_width)),
+            None => String::new(),
+        }),
+        Print(format!("   Listens:   {}\n", truncate(&listener_list(frame.listeners, port), value_width))),
+        Print(format!("   Time:      {}\n", timestamp)),
+        Print(format!("   Samples:   {}\n", iteration)),
+    )?;
+
+    if shows(Tab::Resources) {
+        // CPU Section
+        execute!(
+            out,
+            Print("\n"),
+            SetForegroundColor(Color::Magenta),
+            Print(format!("⚡ CPU Usage (% {})\n", frame.cpu_mode.label(frame.cpu_count))),
+            ResetColor,
+        )?;
+
+        let cpu_color = if info.cpu_percent > 80.0 {
+            Color::Red
+        } else if info.cpu_percent > 50.0 {
+            Color::Yellow
+        } else {
+            Color::Green
+        };
+
+        execute!(
+            out,
+            Print("   Current:   "),
+            SetForegroundColor(cpu_color),
+            Print(format!("{:>6.2}%", info.cpu_percent)),
+            ResetColor,
+            Print("  "),
+        )?;
+        render_bar(out, info.cpu_percent as f64, 100.0, bar_width, cpu_color)?;
+        execute!(out, Print("\n"))?;
+
+        if !history.cpu_history.is_empty() {
+            execute!(
+                out,
+                Print(format!("   Average:   {:>6.2}%\n", history.avg_cpu())),
+                Print(format!("   Peak:      {:>6.2}%\n", history.max_cpu())),
+            )?;
+
+            if frame.cpu_histogram {
+                render_cpu_histogram(out, history, bar_width)?;
+            } else {
+                // CPU Sparkline
+                execute!(
+                    out,
+                    Print("   History:   "),
+                )?;
+                render_sparkline(out, &history.cpu_chart(&frame.chart, sparkline_width), sparkline_width)?;
+                execute!(out, Print("\n"))?;
+                if let Some(column) = cursor {
+                    render_cursor_row(out, column)?;
+                }
+                if let Some(latest) = frame.annotations.last() {
+                    render_annotation_row(out, history, frame, sparkline_width, latest, value_width)?;
+                }
+                if !frame.chart.is_live() {
+                    execute!(
+                        out,
+                        SetForegroundColor(Color::DarkGrey),
+                        Print(format!(
+                            "   Chart:     {}x zoom, {} of {} samples back | ←/→ scroll, +/- zoom, 0 live\n",
+                            frame.chart.zoom,
+                            frame.chart.offset,
+                            history.recorded()
+                        )),
+                        ResetColor,
+                    )?;
+                }
+            }
+        }
+
+        // Under the CPU figures when everything is stacked, on their own tab otherwise.
+        if let Some(cores) = frame.cores.filter(|c| frame.tab.is_none() && !c.percent.is_empty()) {
+            render_core_strip(out, cores, value_width)?;
+        }
+
+        // Memory Section
+        execute!(
+            out,
+            Print("\n"),
+            SetForegroundColor(Color::Blue),
+            Print("💾 Memory Usage\n"),
+            ResetColor,
+        )?;
+
+        let memory_mb = to_mb(info.memory_bytes);
+        let mem_color = if memory_mb > 1000.0 {
+            Color::Red
+        } else if memory_mb > 500.0 {
+            Color::Yellow
+        } else {
+            Color::Green
+        };
+
+        let max_mem_display = to_mb(history.max_mem().max(info.memory_bytes));
+        let mem_max = (max_mem_display * 1.2).max(100.0); // Add 20% headroom
+
+        execute!(
+            out,
+            Print("   Current:   "),
+            SetForegroundColor(mem_color),
+            Print(format!("{:>8.2} {}", memory_mb, mb_unit())),
+            ResetColor,
+            Print("  "),
+        )?;
+        render_bar(out, memory_mb, mem_max, bar_width, mem_color)?;
+        execute!(out, Print("\n"))?;
+        render_memory_breakdown(out, info, bar_width)?;
+        if let Some(swap) = frame.swap.filter(|s| s.latest() > 0) {
+            render_swap(out, swap, frame.oom.and_then(|o| o.pressure))?;
+        }
+        if let Some(segments) = frame.segments {
+            render_segments(out, segments, value_width)?;
+        }
+
+        if !history.mem_history.is_empty() {
+            execute!(
+                out,
+                Print(format!("   Average:   {:>8.2} {}\n", to_mb(history.avg_mem() as u64), mb_unit())),
+                Print(format!("   Peak:      {:>8.2} {}\n", to_mb(history.max_mem()), mb_unit())),
+            )?;
+
+            // Memory Sparkline
+            execute!(
+                out,
+                Print("   History:   "),
+            )?;
+            let mem_history_mb: Vec<f64> = history.mem_chart(&frame.chart, sparkline_width).into_iter().map(to_mb).collect();
+            render_sparkline_mem(out, &mem_history_mb, sparkline_width)?;
+            execute!(out, Print("\n"))?;
+            if let Some(column) = cursor {
+                render_cursor_row(out, column)?;
+            }
+        }
+
+        if frame.overlay && !history.mem_history.is_empty() {
+            render_overlay_chart(out, history, &frame.chart, frame.annotations, sparkline_width)?;
+        }
+    }
+
+    if frame.tab == Some(Tab::Threads) {
+        execute!(out, Print("\n"), SetForegroundColor(Color::Magenta), Print("🧵 Threads\n"), ResetColor)?;
+        if let Some(cores) = frame.cores.filter(|c| !c.percent.is_empty()) {
+            render_core_strip(out, cores, value_width)?;
+        }
+    }
+
+    if let Some(pool) = frame.pool.filter(|_| shows(Tab::Threads)) {
+        render_pool_panel(out, pool, sparkline_width)?;
+    }
+
+    if let Some(children) = frame.children.filter(|c| (!c.events.is_empty() || c.zombies > 0) && shows(Tab::Threads)) {
+        render_children_panel(out, children, effective_width)?;
+    }
+
+    if let Some(chain) = frame.upstream.filter(|_| shows(Tab::Network)) {
+        render_upstream_panel(out, chain, frame.info, frame.port)?;
+    }
+
+    if let Some(oom) = frame.oom.filter(|_| shows(Tab::Resources)) {
+        render_oom_panel(out, oom)?;
+    }
+
+    if let Some(power) = frame.power.filter(|_| shows(Tab::Resources)) {
+        render_power_panel(out, power)?;
+    }
+
+    if let Some(counters) = frame.counters.filter(|c| c.latest().is_some() && shows(Tab::Resources)) {
+        render_counters_panel(out, counters, sparkline_width)?;
+    }
+
+    if let Some(ebpf) = frame.ebpf.filter(|e| e.latest().is_some() && shows(Tab::Network)) {
+        render_ebpf_panel(out, ebpf, sparkline_width)?;
+    }
+
+    if let Some(syscalls) = frame.syscalls.filter(|s| s.summary().total() > 0 && shows(Tab::Threads)) {
+        render_syscalls_panel(out, syscalls, bar_width)?;
+    }
+
+    if let Some(queue) = frame.accept_queue.filter(|_| shows(Tab::Network)) {
+        render_accept_queue_panel(out, queue, sparkline_width)?;
+    }
+
+    if let Some(sockets) = frame.sockets.filter(|_| shows(Tab::Network)) {
+        render_socket_panel(out, sockets, effective_width)?;
+    }
+
+    if let Some(posture) = frame.posture.filter(|_| shows(Tab::Network)) {
+        render_security_panel(out, frame, posture, effective_width)?;
+    }
+
+    if let Some(stats) = frame.tcp_stats.filter(|_| shows(Tab::Network)) {
+        render_tcp_panel(out, stats, frame.retransmit_alert)?;
+    }
+
+    if let Some(churn) = frame.churn.filter(|c| !c.seconds.is_empty() && shows(Tab::Network)) {
+        render_churn_panel(out, churn, sparkline_width)?;
+    }
+
+    if let Some(peers) = frame.peers.filter(|_| shows(Tab::Network)) {
+        render_peers_panel(out, peers, effective_width)?;
+    }
+
+    if let Some(probe) = frame.probe.filter(|_| shows(Tab::Network)) {
+        render_latency_panel(out, probe, sparkline_width)?;
+    }
+
+    if let Some(check) = frame.http_check.filter(|_| shows(Tab::Network)) {
+        render_http_panel(out, check, sparkline_width, value_width)?;
+    }
+
+    if let Some(check) = frame.tls.filter(|_| shows(Tab::Network)) {
+        render_tls_panel(out, check, frame.timestamp, value_width)?;
+    }
+
+    if let Some(app) = frame.app.filter(|_| shows(Tab::Resources)) {
+        render_app_panel(out, app, effective_width)?;
+    }
+
+    if let Some(unit) = frame.unit.filter(|_| shows(Tab::Resources)) {
+        render_unit_panel(out, unit, frame.unit_prompt, bar_width)?;
+    }
+
+    if let Some(cgroup) = frame.cgroup.filter(|c| c.is_limited() && shows(Tab::Resources)) {
+        render_cgroup_panel(out, cgroup, frame.throttled_ratio, info.cpu_percent, bar_width)?;
+    }
+
+    if let Some(system) = frame.system.filter(|_| shows(Tab::Resources)) {
+        render_system_panel(out, system, bar_width)?;
+    }
+
+    if let Some(logs) = frame.logs.filter(|_| shows(Tab::Logs)) {
+        render_log_pane(out, logs, effective_width)?;
+    }
+
+    if let Some(lines) = frame.crabtop_log.filter(|_| shows(Tab::Logs)) {
+        render_crabtop_log_pane(out, lines, effective_width)?;
+    }
+
+    if let Some(files) = frame.open_files.filter(|_| shows(Tab::Files)) {
+        render_open_files_panel(out, files, effective_width)?;
+    }
+
+    if let Some(dump) = frame.stack_dump.filter(|_| shows(Tab::Threads)) {
+        render_stack_dump_pane(out, dump, effective_width)?;
+    }
+
+    if frame.verdict.is_some() || frame.insight.is_some() {
+        render_insight_panel(out, frame.verdict, frame.insight, effective_width)?;
+    }
+
+    // Footer, after the cursor readout when there is one
+    let point = cursor
+        .filter(|_| shows(Tab::Resources))
+        .and_then(|column| history.chart_point(&frame.chart, sparkline_width, column));
+    if let Some(point) = point {
+        execute!(
+            out,
+            Print("\n"),
+            SetForegroundColor(Color::Cyan),
+            Print(format!("▸ {} | ←/→ move, 'i' close", cursor_readout(&point))),
+            ResetColor,
+        )?;
+    }
+    if let Some(own) = frame.own {
+        execute!(
+            out,
+            Print("\n"),
+            SetForegroundColor(Color::DarkGrey),
+            Print(format!("crabtop itself: {:.2}% CPU, {}", own.cpu_percent, format_mb(own.memory_bytes))),
+            ResetColor,
+        )?;
+    }
+    let mut hints = Vec::new();
+    if frame.tab.is_some() {
+        hints.push("1-5/Tab views".to_string());
+    }
+    hints.extend(
+        ["Press 'q' or 'c' to quit", "←/→ +/- history", "'i' cursor", "'h' CPU histogram", "'o' overlay", "'m' mark", "'s' system panel", "'l' logs", "'L' crabtop log", "'f' open files"]
+            .map(str::to_string),
+    );
+    if let Some(runtime) = frame.runtime {
+        hints.push(format!("'d' {} stack dump", runtime.label()));
+    }
+    if frame.open_files.is_some() || frame.stack_dump.is_some() {
+        hints.extend(["'n'/'p' page", "'r' reload"].map(str::to_string));
+    }
+    if frame.ai_enabled {
+        hints.push("'a' AI insight".to_string());
+    }
+    hints.push("Updates every second".to_string());
+    execute!(out, Print("\n"), SetForegroundColor(Color::DarkGrey))?;
+    for line in wrap_hints(&hints, width) {
+        execute!(out, Print(format!("{}\n", line)))?;
+    }
+    execute!(out, ResetColor)?;
+
+    Ok(())
+}
+
+/// Columns the history sparklines take in a terminal `width` wide.
+pub fn sparkline_width(width: u16) -> usize {
+    (width as usize).max(60).saturating_sub(20).clamp(20, 100)
+}
+
+/// Where the sparklines start, after "   History:   ".
+pub const CHART_LEFT: u16 = 14;
+
+/// `10:30:41  CPU 5.30%  Memory 41.50 MB`; a zoomed-out column gives its
+/// time span and sample count, with the highest values as drawn.
+pub fn cursor_readout(point: &ChartPoint) -> String {
+    let time = if point.samples > 1 {
+        format!(
+            "{}–{} ({} samples, peak)",
+            point.first.format("%H:%M:%S"),
+            point.last.format("%H:%M:%S"),
+            point.samples
+        )
+    } else {
+        point.first.format("%H:%M:%S").to_string()
+    };
+    format!("{}  CPU {:.2}%  Memory {:.2} {}", time, point.cpu_percent, to_mb(point.memory_bytes), mb_unit())
+}
+
+/// The dashboard for one frame without colours or cursor moves, as it would
+/// read on screen; for snapshots written to a file.
+pub fn render_plain(frame: &Frame) -> Result<String> {
+    let mut buf = Vec::new();
+    render_dashboard(&mut buf, frame)?;
+    let raw = String::from_utf8_lossy(&buf);
+    let mut out = String::with_capacity(raw.len());
+    let mut chars = raw.chars().peekable();
+    while let Some(c) = chars.next() {
+        if c == '\u{1b}' && chars.peek() == Some(&'[') {
+            chars.next();
+            // A CSI sequence ends at its first byte in '@'..='~'.
+            for c in chars.by_ref() {
+                if ('@'..='~').contains(&c) {
+                    break;
+                }
+            }
+        } else {
+            out.push(c);
+        }
+    }
+    Ok(out)
+}
+
+/// One host/port in the multi-host grid.
+pub struct GridCell<'a> {
+    pub label: &'a str,
+    pub info: Option<&'a ProcessInfo>,
+    pub pids: &'a [u32],
+    pub history: Option<&'a ProcessHistory>,
+    /// Why the cell has no data, e.g. the host is unreachable.
+    pub error: Option<&'a str>,
+}
+
+/// Width of one grid cell including its border.
+const CELL_WIDTH: usize = 40;
+
+/// How many cells fit side by side in a terminal `width` columns wide.
+pub fn grid_columns(width: u16) -> usize {
+    (width as usize / CELL_WIDTH).max(1)
+}
+
+/// Renders the multi-host grid: one mini-dashboard per cell, `selected` highlighted.
+pub fn render_grid<W: Write>(
+    out: &mut W,
+    cells: &[GridCell],
+    selected: usize,
+    width: u16,
+    timestamp: NaiveDateTime,
+) -> Result<()> {
+    let columns = grid_columns(width);
+
+    execute!(
+        out,
+        SetForegroundColor(Color::Yellow),
+        Print(format!(
+            " PORT INSPECTOR - Fleet ({} targets)  {}\n",
+            cells.len(),
+            timestamp.format("%Y-%m-%d %H:%M:%S")
+        )),
+        ResetColor,
+    )?;
+
+    for (row, chunk) in cells.chunks(columns).enumerate() {
+        let rendered: Vec<Vec<(String, usize)>> = chunk
+            .iter()
+            .enumerate()
+            .map(|(col, cell)| grid_cell_lines(cell, row * columns + col == selected))
+            .collect::<Result<_>>()?;
+        for line in 0..rendered[0].len() {
+            for cell in &rendered {
+                let (text, visible) = &cell[line];
+                execute!(out, Print(text), Print(" ".repeat(CELL_WIDTH.saturating_sub(*visible))))?;
+            }
+            execute!(out, Print("\n"))?;
+        }
+    }
+
+    execute!(
+        out,
+        Print("\n"),
+        SetForegroundColor(Color::DarkGrey),
+        Print("Arrows/hjkl move | Enter details | Esc back | 'q' quit\n"),
+        ResetColor,
+    )?;
+
+    Ok(())
+}
+
+/// One process in the `compare` view.
+pub struct CompareSide<'a> {
+    pub port: u16,
+    pub info: &'a ProcessInfo,
+    pub pids: &'a [u32],
+    pub history: &'a ProcessHistory,
+}
+
+/// Relative change from `a` to `b` in percent; `None` when `a` is zero.
+pub fn relative_change(a: f64, b: f64) -> Option<f64> {
+    (a != 0.0).then(|| (b - a) / a * 100.0)
+}
+
+/// Renders two processes side by side, sampled together, with a row of
+/// deltas of the second against the first.
+pub fn render_compare<W: Write>(
+    out: &mut W,
+    a: &CompareSide,
+    b: &CompareSide,
+    width: u16,
+    timestamp: NaiveDateTime,
+) -> Result<()> {
+    let column = (width as usize).saturating_sub(3).div_euclid(2).clamp(30, 60);
+    execute!(
+        out,
+        SetForegroundColor(Color::Yellow),
+        Print(format!(
+            " PORT INSPECTOR - Compare {} vs {}  {}\n\n",
+            a.port,
+            b.port,
+            timestamp.format("%Y-%m-%d %H:%M:%S")
+        )),
+        ResetColor,
+    )?;
+
+    let left = compare_column_lines(a, column)?;
+    let right = compare_column_lines(b, column)?;
+    for ((text, visible), (right, _)) in left.iter().zip(&right) {
+        execute!(out, Print(text), Print(" ".repeat(column.saturating_sub(*visible) + 3)), Print(right), Print("\n"))?;
+    }
+
+    execute!(
+        out,
+        Print("\n"),
+        SetForegroundColor(Color::Cyan),
+        Print(format!("Δ {} vs {}\n", b.port, a.port)),
+        ResetColor,
+    )?;
+    let (ha, hb) = (a.history, b.history);
+    let rows = [
+        ("CPU now", a.info.cpu_percent as f64, b.info.cpu_percent as f64, "%"),
+        ("CPU avg", ha.avg_cpu() as f64, hb.avg_cpu() as f64, "%"),
+        ("Mem now", to_mb(a.info.memory_bytes), to_mb(b.info.memory_bytes), mb_unit()),
+        ("Mem avg", to_mb(ha.avg_mem() as u64), to_mb(hb.avg_mem() as u64), mb_unit()),
+    ];
+    for (label, va, vb, unit) in rows {
+        let (delta, color) = match relative_change(va, vb) {
+            // Within 10% is noise for a process sampled once a second.
+            Some(change) if change > 10.0 => (format!("{:+.1}%", change), Color::Red),
+            Some(change) if change < -10.0 => (format!("{:+.1}%", change), Color::Green),
+            Some(change) => (format!("{:+.1}%", change), Color::Reset),
+            None => ("-".to_string(), Color::Reset),
+        };
+        let gap = if unit == "%" { "" } else { " " };
+        execute!(
+            out,
+            Print(format!("   {}:  ", label)),
+            SetForegroundColor(color),
+            Print(format!("{:>8}", delta)),
+            ResetColor,
+            Print(format!("   ({:.2}{gap}{unit} → {:.2}{gap}{unit})\n", va, vb)),
+        )?;
+    }
+
+    execute!(
+        out,
+        Print("\n"),
+        SetForegroundColor(Color::DarkGrey),
+        Print("Press 'q' or 'c' to quit | Both sides are sampled together each interval\n"),
+        ResetColor,
+    )?;
+    Ok(())
+}
+
+/// What the `top` view is ordered by, busiest first.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+pub enum TopSort {
+    Cpu,
+    Memory,
+    Connections,
+}
+
+impl TopSort {
+    pub fn label(&self) -> &'static str {
+        match self {
+            TopSort::Cpu => "CPU",
+            TopSort::Memory => "memory",
+            TopSort::Connections => "connections",
+        }
+    }
+
+    /// The order `s` cycles through.
+    pub fn next(&self) -> Self {
+        match self {
+            TopSort::Cpu => TopSort::Memory,
+            TopSort::Memory => TopSort::Connections,
+            TopSort::Connections => TopSort::Cpu,
+        }
+    }
+}
+
+/// One listening process in the `top` view.
+pub struct TopRow<'a> {
+    pub info: &'a ProcessInfo,
+    pub ports: &'a [u16],
+    /// Established connections across all its ports, where they could be counted.
+    pub connections: Option<usize>,
+    pub history: &'a ProcessHistory,
+}
+
+/// Orders `rows` busiest first by `sort`, lowest PID first on ties.
+pub fn sort_top_rows(rows: &mut [TopRow], sort: TopSort) {
+    rows.sort_by(|a, b| {
+        let order = match sort {
+            TopSort::Cpu => b.info.cpu_percent.total_cmp(&a.info.cpu_percent),
+            TopSort::Memory => b.info.memory_bytes.cmp(&a.info.memory_bytes),
+            TopSort::Connections => b.connections.cmp(&a.connections),
+        };
+        order.then(a.info.pid.cmp(&b.info.pid))
+    });
+}
+
+/// Renders the `top` view: one line per process, `rows` already sorted and
+/// cut to what fits, `selected` highlighted. `total` is how many processes
+/// are listening in all.
+pub fn render_top<W: Write>(
+    out: &mut W,
+    rows: &[TopRow],
+    total: usize,
+    sort: TopSort,
+    selected: usize,
+    width: u16,
+    timestamp: NaiveDateTime,
+) -> Result<()> {
+    let width = (width as usize).max(60);
+    execute!(
+        out,
+        SetForegroundColor(Color::Yellow),
+        Print(format!(
+            " PORT INSPECTOR - Top {} of {} listening processes by {}  {}\n\n",
+            rows.len(),
+            total,
+            sort.label(),
+            timestamp.format("%Y-%m-%d %H:%M:%S")
+        )),
+        ResetColor,
+    )?;
+    // Fixed columns take 57; the process name and the sparkline share the rest.
+    let name_width = ((width - 57) / 2).clamp(8, 24);
+    let spark_width = width.saturating_sub(57 + name_width).clamp(4, 30);
+    let header = format!(
+        "  {:>7}  {:<nw$}  {:<16} {:>7} {:>10} {:>6}  CPU HISTORY",
+        "PID",
+        "PROCESS",
+        "PORTS",
+        "CPU%",
+        "MEMORY",
+        "CONNS",
+        nw = name_width
+    );
+    execute!(out, SetForegroundColor(Color::Cyan), Print(truncate(&header, width)), Print("\n"), ResetColor)?;
+
+    for (n, row) in rows.iter().enumerate() {
+        let info = row.info;
+        let ports: Vec<String> = row.ports.iter().map(|p| p.to_string()).collect();
+        let cpu_color = if info.cpu_percent > 80.0 {
+            Color::Red
+        } else if info.cpu_percent > 50.0 {
+            Color::Yellow
+        } else {
+            Color::Green
+        };
+        let marker = if n == selected { "▶ " } else { "  " };
+        execute!(
+            out,
+            SetForegroundColor(if n == selected { Color::Yellow } else { Color::Reset }),
+            Print(format!(
+                "{}{:>7}  {:<nw$}  {:<16}",
+                marker,
+                info.pid,
+                truncate(&info.name, name_width),
+                truncate(&ports.join(","), 16),
+                nw = name_width
+            )),
+            SetForegroundColor(cpu_color),
+            Print(format!(" {:>7.2}", info.cpu_percent)),
+            ResetColor,
+            Print(format!(
+                " {:>10} {:>6}  ",
+                format_bytes(info.memory_bytes),
+                row.connections.map_or("-".to_string(), |c| c.to_string())
+            )),
+        )?;
+        render_sparkline(out, &row.history.cpu_history, spark_width)?;
+        execute!(out, Print("\n"))?;
+    }
+    if rows.is_empty() {
+        execute!(out, Print("  Nothing is listening on a TCP port.\n"))?;
+    }
+
+    execute!(
+        out,
+        Print("\n"),
+        SetForegroundColor(Color::DarkGrey),
+        Print("Arrows/jk select | Enter watch | 's' sort (CPU/memory/connections) | 'q' quit\n"),
+        ResetColor,
+    )?;
+    Ok(())
+}
+
+/// Renders the `--service` view: the service's total, then one line per
+/// member with `selected` highlighted.
+#[allow(clippy::too_many_arguments)]
+pub fn render_service<W: Write>(
+    out: &mut W,
+    name: &str,
+    total: Option<&ProcessInfo>,
+    history: &ProcessHistory,
+    members: &[Member],
+    selected: usize,
+    width: u16,
+    timestamp: NaiveDateTime,
+) -> Result<()> {
+    let width = (width as usize).max(60);
+    let running = members.iter().filter(|m| m.info.is_some()).count();
+    execute!(
+        out,
+        SetForegroundColor(Color::Yellow),
+        Print(format!(
+            " PORT INSPECTOR - Service {} ({} of {} members running)  {}\n\n",
+            name,
+            running,
+            members.len(),
+            timestamp.format("%Y-%m-%d %H:%M:%S")
+        )),
+        ResetColor,
+    )?;
+    // Fixed columns take 42; the member and the sparkline share the rest.
+    let member_width = ((width - 42) / 2).clamp(12, 32);
+    let spark_width = width.saturating_sub(42 + member_width).clamp(4, 30);
+    let header = format!("  {:<mw$}  {:<12} {:>8} {:>10}  CPU HISTORY", "MEMBER", "PIDS", "CPU%", "MEMORY", mw = member_width);
+    execute!(out, SetForegroundColor(Color::Cyan), Print(truncate(&header, width)), Print("\n"), ResetColor)?;
+
+    let pids = |pids: &[u32]| match pids {
+        [] => "-".to_string(),
+        [pid] => pid.to_string(),
+        [first, rest @ ..] => format!("{} +{}", first, rest.len()),
+    };
+    let mut all: Vec<u32> = members.iter().flat_map(|m| m.pids.iter().copied()).collect();
+    all.sort_unstable();
+    all.dedup();
+    execute!(
+        out,
+        SetForegroundColor(Color::White),
+        Print(format!("  {:<mw$}  {:<12}", "Total", format!("{} procs", all.len()), mw = member_width)),
+        Print(format!(
+            " {:>8} {:>10}  ",
+            total.map_or("-".to_string(), |t| format!("{:.2}", t.cpu_percent)),
+            total.map_or("-".to_string(), |t| format_bytes(t.memory_bytes))
+        )),
+        ResetColor,
+    )?;
+    render_sparkline(out, &history.cpu_history, spark_width)?;
+    execute!(out, Print("\n"))?;
+
+    for (n, member) in members.iter().enumerate() {
+        let marker = if n == selected { "▶ " } else { "  " };
+        execute!(
+            out,
+            SetForegroundColor(if n == selected { Color::Yellow } else { Color::Reset }),
+            Print(format!(
+                "{}{:<mw$}  {:<12}",
+                marker,
+                truncate(&member.kind.to_string(), member_width),
+                pids(&member.pids),
+                mw = member_width
+            )),
+            ResetColor,
+        )?;
+        match &member.info {
+            Some(info) => {
+                let cpu_color = if info.cpu_percent > 80.0 {
+                    Color::Red
+                } else if info.cpu_percent > 50.0 {
+                    Color::Yellow
+                } else {
+                    Color::Green
+                };
+                execute!(
+                    out,
+                    SetForegroundColor(cpu_color),
+                    Print(format!(" {:>8.2}", info.cpu_percent)),
+                    ResetColor,
+                    Print(format!(" {:>10}  ", format_bytes(info.memory_bytes))),
+                )?;
+                render_sparkline(out, &member.history.cpu_history, spark_width)?;
+            }
+            None => execute!(out, SetForegroundColor(Color::Red), Print(format!(" {:>8} {:>10}", "down", "-")), ResetColor)?,
+        }
+        execute!(out, Print("\n"))?;
+    }
+
+    execute!(
+        out,
+        Print("\n"),
+        SetForegroundColor(Color::DarkGrey),
+        Print("Arrows/jk select | Enter watch the selected port | 'q' quit\n"),
+        ResetColor,
+    )?;
+    Ok(())
+}
+
+/// One side of the compare view as lines paired with their visible widths.
+fn compare_column_lines(side: &CompareSide, width: usize) -> Result<Vec<(String, usize)>> {
+    let info = side.info;
+    let history = side.history;
+    let mut lines = Vec::new();
+    let plain = |text: String| {
+        let visible = text.chars().count();
+        (text, visible)
+    };
+
+    let who = if side.pids.len() > 1 {
+        format!("Port {}: {} ({} pids)", side.port, info.name, side.pids.len())
+    } else {
+        format!("Port {}: {} (PID {})", side.port, info.name, info.pid)
+    };
+    let who = truncate(&who, width);
+    let mut title = Vec::new();
+    execute!(title, SetForegroundColor(Color::Cyan), Print(&who), ResetColor)?;
+    lines.push((String::from_utf8_lossy(&title).into_owned(), who.chars().count()));
+
+    let bar_width = width.saturating_sub(20).max(5);
+    let cpu_color = if info.cpu_percent > 80.0 {
+        Color::Red
+    } else if info.cpu_percent > 50.0 {
+        Color::Yellow
+    } else {
+        Color::Green
+    };
+    let mut cpu = Vec::new();
+    execute!(cpu, Print("CPU "), SetForegroundColor(cpu_color), Print(format!("{:>9.2}%", info.cpu_percent)), ResetColor, Print("  "))?;
+    render_bar(&mut cpu, info.cpu_percent as f64, 100.0, bar_width, cpu_color)?;
+    lines.push((String::from_utf8_lossy(&cpu).into_owned(), 16 + bar_width + 2));
+    lines.push(plain(format!("    avg {:.2}%  peak {:.2}%", history.avg_cpu(), history.max_cpu())));
+    let mut spark = Vec::new();
+    execute!(spark, Print("    "))?;
+    render_sparkline(&mut spark, &history.cpu_history, width - 4)?;
+    lines.push((String::from_utf8_lossy(&spark).into_owned(), 4 + history.cpu_history.len().min(width - 4)));
+
+    let memory_mb = to_mb(info.memory_bytes);
+    let mem_color = if memory_mb > 1000.0 {
+        Color::Red
+    } else if memory_mb > 500.0 {
+        Color::Yellow
+    } else {
+        Color::Green
+    };
+    let mem_max = (to_mb(history.max_mem().max(info.memory_bytes)) * 1.2).max(100.0);
+    let mut mem = Vec::new();
+    execute!(mem, Print("Mem "), SetForegroundColor(mem_color), Print(format!("{:>7.1} {}", memory_mb, mb_unit())), ResetColor, Print("  "))?;
+    render_bar(&mut mem, memory_mb, mem_max, bar_width, mem_color)?;
+    lines.push((String::from_utf8_lossy(&mem).into_owned(), 16 + bar_width + 2));
+    lines.push(plain(format!(
+        "    avg {:.1} {unit}  peak {:.1} {unit}",
+        to_mb(history.avg_mem() as u64),
+        to_mb(history.max_mem()),
+        unit = mb_unit()
+    )));
+    let mem_history_mb: Vec<f64> = history.mem_history.iter().map(|&bytes| to_mb(bytes)).collect();
+    let mut spark = Vec::new();
+    execute!(spark, Print("    "))?;
+    render_sparkline_mem(&mut spark, &mem_history_mb, width - 4)?;
+    lines.push((String::from_utf8_lossy(&spark).into_owned(), 4 + mem_history_mb.len().min(width - 4)));
+
+    lines.push(plain(format!("Up {}", format_duration(info.uptime_secs))));
+    Ok(lines)
+}
+
+/// A cell as pre-rendered lines, each paired with its visible width so the
+/// grid can pad around colour escapes.
+fn grid_cell_lines(cell: &GridCell, selected: bool) -> Result<Vec<(String, usize)>> {
+    let inner = CELL_WIDTH - 4;
+    let border = if selected { Color::Yellow } else { Color::DarkGrey };
+    let mut lines = Vec::new();
+
+    let title = truncate(cell.label, inner - 2);
+    let title_len = title.chars().count();
+    let mut top = Vec::new();
+    execute!(
+        top,
+        SetForegroundColor(border),
+        Print("┌ "),
+        ResetColor,
+        Print(&title),
+        SetForegroundColor(border),
+        Print(" "),
+        Print("─".repeat(inner.saturating_sub(title_len))),
+        Print("┐"),
+        ResetColor,
+    )?;
+    lines.push((String::from_utf8_lossy(&top).into_owned(), inner + 4));
+
+    let mut body: Vec<(Vec<u8>, usize)> = Vec::new();
+    match (cell.info, cell.error) {
+        (Some(info), _) => {
+            let who = if cell.pids.len() > 1 {
+                format!("{} ({} pids)", info.name, cell.pids.len())
+            } else {
+                format!("{} ({})", info.name, info.pid)
+            };
+            let who = truncate(&who, inner);
+            body.push((who.clone().into_bytes(), who.chars().count()));
+
+            let cpu_color = if info.cpu_percent > 80.0 {
+                Color::Red
+            } else if info.cpu_percent > 50.0 {
+                Color::Yellow
+            } else {
+                Color::Green
+            };
+            let bar_width = inner - 17;
+            let mut cpu = Vec::new();
+            execute!(cpu, Print("CPU "))?;
+            render_bar(&mut cpu, info.cpu_percent as f64, 100.0, bar_width, cpu_color)?;
+            execute!(cpu, SetForegroundColor(cpu_color), Print(format!(" {:>7.2}%", info.cpu_percent)), ResetColor)?;
+            body.push((cpu, bar_width + 15));
+
+            let mem = format!("Mem {:>10.2} {}", to_mb(info.memory_bytes), mb_unit());
+            body.push((mem.clone().into_bytes(), mem.chars().count()));
+
+            let mut spark = Vec::new();
+            let samples = cell.history.map(|h| h.cpu_history.as_slice()).unwrap_or_default();
+            render_sparkline(&mut spark, samples, inner)?;
+            body.push((spark, samples.len().min(inner)));
+        }
+        (None, error) => {
+            let (label, color) = match error {
+                Some(_) => ("unavailable", Color::Red),
+                None => ("connecting…", Color::DarkGrey),
+            };
+            let mut status = Vec::new();
+            execute!(status, SetForegroundColor(color), Print(label), ResetColor)?;
+            body.push((status, label.chars().count()));
+            // Long errors wrap over the remaining lines.
+            let chars: Vec<char> = error.unwrap_or_default().chars().collect();
+            for chunk in chars.chunks(inner).take(3) {
+                let text: String = chunk.iter().collect();
+                body.push((text.into_bytes(), chunk.len()));
+            }
+        }
+    }
+    body.resize_with(4, || (Vec::new(), 0));
+
+    for (text, visible) in body {
+        let mut line = Vec::new();
+        execute!(line, SetForegroundColor(border), Print("│ "), ResetColor)?;
+        line.extend_from_slice(&text);
+        execute!(
+            line,
+            Print(" ".repeat(inner.saturating_sub(visible))),
+            SetForegroundColor(border),
+            Print(" │"),
+            ResetColor,
+        )?;
+        lines.push((String::from_utf8_lossy(&line).into_owned(), inner + 4));
+    }
+
+    let mut bottom = Vec::new();
+    execute!(
+        bottom,
+        SetForegroundColor(border),
+        Print("└"),
+        Print("─".repeat(inner + 2)),
+        Print("┘"),
+        ResetColor,
+    )?;
+    lines.push((String::from_utf8_lossy(&bottom).into_owned(), inner + 4));
+
+    Ok(lines)
+}
+
+/// Full command line, falling back to the process name when it isn't readable.
+fn command_line(info: &ProcessInfo) -> String {
+    if info.cmdline.is_empty() {
+        info.name.clone()
+    } else {
+        info.cmdline.join(" ")
+    }
+}
+
+fn short_id(id: &str) -> &str {
+    &id[..id.len().min(12)]
+}
+
+fn pid_list(pid: u32, pids: &[u32]) -> String {
+    if pid == 0 {
+        "-".to_string()
+    } else if pids.len() > 1 {
+        let list: Vec<String> = pids.iter().map(|p| p.to_string()).collect();
+        format!("{} (aggregated)", list.join(", "))
+    } else {
+        pid.to_string()
+    }
+}
+
+/// Comma-separated listeners, or just the queried port when none were found.
+fn listener_list(listeners: &[Listener], port: u16) -> String {
+    if listeners.is_empty() {
+        return port_label(port);
+    }
+    listeners
+        .iter()
+        .map(|l| l.to_string())
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+/// `-` for the port 0 of a `--target-*` session.
+fn port_label(port: u16) -> String {
+    match port {
+        0 => "-".to_string(),
+        port => port.to_string(),
+    }
+}
+
+fn display_path(path: &Option<std::path::PathBuf>) -> String {
+    path.as_ref()
+        .map(|p| p.display().to_string())
+        .unwrap_or_else(|| "-".to_string())
+}
+
+/// Cuts `text` to at most `max` characters, marking the cut with an ellipsis.
+fn truncate(text: &str, max: usize) -> String {
+    if text.chars().count() <= max {
+        text.to_string()
+    } else {
+        let kept: String = text.chars().take(max.saturating_sub(1)).collect();
+        format!("{}…", kept)
+    }
+}
+
+fn annotation_color(kind: AnnotationKind) -> Color {
+    match kind {
+        AnnotationKind::Restart => Color::Red,
+        AnnotationKind::Threshold => Color::Yellow,
+        AnnotationKind::Marker => Color::Cyan,
+        AnnotationKind::Deploy => Color::Green,
+        AnnotationKind::Anomaly => Color::Magenta,
+        AnnotationKind::Watchdog => Color::Red,
+        AnnotationKind::Listener => Color::Blue,
+    }
+}
+
+/// For each chart column, the kind of the latest annotation that falls in it.
+fn annotation_columns(
+    history: &ProcessHistory,
+    view: &ChartView,
+    annotations: &[Annotation],
+    width: usize,
+) -> Vec<Option<AnnotationKind>> {
+    let mut columns = vec![None; history.cpu_chart(view, width).len()];
+    for annotation in annotations {
+        if let Some(column) = history.chart_column(view, width, annotation.sample) {
+            columns[column] = Some(annotation.kind);
+        }
+    }
+    columns
+}
+
+/// A `▲` under each sparkline column with an event in it, then the latest event.
+fn render_annotation_row<W: Write>(
+    out: &mut W,
+    history: &ProcessHistory,
+    frame: &Frame,
+    width: usize,
+    latest: &Annotation,
+    value_width: usize,
+) -> Result<()> {
+    execute!(out, Print("   Events:    "))?;
+    for mark in annotation_columns(history, &frame.chart, frame.annotations, width) {
+        match mark {
+            Some(kind) => execute!(out, SetForegroundColor(annotation_color(kind)), Print("▲"), ResetColor)?,
+            None => execute!(out, Print(" "))?,
+        }
+    }
+    let line = format!("{} {}: {}", latest.timestamp.format("%H:%M:%S"), latest.kind.label(), latest.text);
+    execute!(
+        out,
+        Print("\n               "),
+        SetForegroundColor(annotation_color(latest.kind)),
+        Print(truncate(&line, value_width.saturating_sub(1))),
+        ResetColor,
+        Print("\n"),
+    )?;
+    Ok(())
+}
+
+/// A `↑` under the cursor's column of the sparkline above.
+fn render_cursor_row<W: Write>(out: &mut W, column: usize) -> Result<()> {
+    execute!(
+        out,
+        Print(" ".repeat(CHART_LEFT as usize + column)),
+        SetForegroundColor(Color::Cyan),
+        Print("↑\n"),
+        ResetColor,
+    )?;
+    Ok(())
+}
+
+/// Rows of the CPU and memory overlay chart.
+const OVERLAY_ROWS: usize = 8;
+
+/// CPU (scaled from 0 to its peak) and memory (from its low to its high)
+/// over the same stretch as the sparklines, so a CPU spike that comes with
+/// a memory jump lines up in the same column.
+fn render_overlay_chart<W: Write>(
+    out: &mut W,
+    history: &ProcessHistory,
+    view: &ChartView,
+    annotations: &[Annotation],
+    width: usize,
+) -> Result<()> {
+    let marks = annotation_columns(history, view, annotations, width);
+    let cpu = history.cpu_chart(view, width);
+    let mem = history.mem_chart(view, width);
+    let cpu_max = cpu.iter().copied().fold(0.0f32, f32::max).max(1.0);
+    let (mem_min, mem_max) = (mem.iter().copied().min().unwrap_or(0), mem.iter().copied().max().unwrap_or(0));
+    let row_of = |fraction: f64| (fraction.clamp(0.0, 1.0) * (OVERLAY_ROWS - 1) as f64).round() as usize;
+    let cpu_rows: Vec<usize> = cpu.iter().map(|&c| row_of((c / cpu_max) as f64)).collect();
+    let mem_rows: Vec<usize> = mem
+        .iter()
+        .map(|&m| {
+            // A flat memory line sits in the middle rather than on the floor.
+            if mem_max == mem_min {
+                OVERLAY_ROWS / 2
+            } else {
+                row_of((m - mem_min) as f64 / (mem_max - mem_min) as f64)
+            }
+        })
+        .collect();
+
+    execute!(
+        out,
+        Print("\n"),
+        SetForegroundColor(Color::Cyan),
+        Print("📈 CPU & Memory (each scaled to its own range)\n"),
+        ResetColor,
+    )?;
+    for row in (0..OVERLAY_ROWS).rev() {
+        execute!(out, Print("   │"))?;
+        for (column, (&c, &m)) in cpu_rows.iter().zip(&mem_rows).enumerate() {
+            let (glyph, color) = match (c == row, m == row) {
+                (true, true) => ("◆", Color::White),
+                (true, false) => ("●", Color::Magenta),
+                (false, true) => ("■", Color::Blue),
+                (false, false) => match marks.get(column).copied().flatten() {
+                    Some(kind) => ("┊", annotation_color(kind)),
+                    None => (" ", Color::Reset),
+                },
+            };
+            execute!(out, SetForegroundColor(color), Print(glyph), ResetColor)?;
+        }
+        execute!(out, Print("\n"))?;
+    }
+    execute!(
+        out,
+        Print(format!("   └{}\n", "─".repeat(cpu_rows.len()))),
+        Print("    "),
+        SetForegroundColor(Color::Magenta),
+        Print("●"),
+        ResetColor,
+        Print(format!(" CPU 0-{:.1}%  ", cpu_max)),
+        SetForegroundColor(Color::Blue),
+        Print("■"),
+        ResetColor,
+        Print(format!(" Memory {:.1}-{:.1} {}  ", to_mb(mem_min), to_mb(mem_max), mb_unit())),
+        SetForegroundColor(Color::White),
+        Print("◆"),
+        ResetColor,
+        Print(" both\n"),
+    )?;
+    Ok(())
+}
+
+/// One row per CPU bucket, up to the highest one seen, scaled to the
+/// fullest. A single tall row is a steady load; a spread-out one is bursty.
+fn render_cpu_histogram<W: Write>(out: &mut W, history: &ProcessHistory, bar_width: usize) -> Result<()> {
+    let buckets = history.cpu_histogram();
+    let samples: u64 = buckets.iter().sum();
+    let fullest = buckets.iter().copied().max().unwrap_or(0).max(1) as f64;
+    let last = buckets.iter().rposition(|&n| n > 0).unwrap_or(0);
+    execute!(out, Print(format!("   Spread:    all {} samples of the session\n", samples)))?;
+    let bar_width = bar_width.saturating_sub(16).max(10);
+    for (i, &count) in buckets.iter().enumerate().take(last + 1) {
+        let low = i as f32 * CPU_BUCKET_WIDTH;
+        let label = if i == CPU_BUCKETS - 1 {
+            format!("{}%+", low)
+        } else {
+            format!("{}-{}%", low, low + CPU_BUCKET_WIDTH)
+        };
+        execute!(out, Print(format!("   {:>9}  ", label)))?;
+        render_bar(out, count as f64, fullest, bar_width, Color::Magenta)?;
+        execute!(out, Print(format!(" {:>6} {:>4.0}%\n", count, count as f64 / samples.max(1) as f64 * 100.0)))?;
+    }
+    Ok(())
+}
+
+/// ` [1 Resources]  2 Network  3 Threads…`, the shown tab highlighted.
+fn render_tab_bar<W: Write>(out: &mut W, shown: Tab) -> Result<()> {
+    for tab in TABS {
+        let label = format!(" {} {} ", tab.key(), tab.label());
+        if tab == shown {
+            execute!(out, SetForegroundColor(Color::Yellow), Print(format!("[{}]", label)), ResetColor)?;
+        } else {
+            execute!(out, SetForegroundColor(Color::DarkGrey), Print(format!(" {} ", label)), ResetColor)?;
+        }
+    }
+    execute!(out, Print("\n"))?;
+    Ok(())
+}
+
+fn render_oom_panel<W: Write>(out: &mut W, oom: &OomStatus) -> Result<()> {
+    let score_color = if oom.at_risk() {
+        Color::Red
+    } else if oom.is_next_victim() {
+        Color::Yellow
+    } else {
+        Color::Green
+    };
+    execute!(
+        out,
+        Print("\n"),
+        SetForegroundColor(Color::Blue),
+        Print("💀 OOM Killer\n"),
+        ResetColor,
+        Print("   Score:     "),
+        SetForegroundColor(score_color),
+        Print(oom.score),
+        ResetColor,
+        Print(format!(" (adj {}), #{} of {} processes\n", oom.score_adj, oom.rank, oom.processes)),
+    )?;
+    if let Some(pressure) = oom.pressure {
+        execute!(
+            out,
+            Print("   Pressure:  "),
+            SetForegroundColor(if pressure.is_high() { Color::Red } else { Color::Green }),
+            Print(format!("some {:.1}%, full {:.1}%", pressure.some_avg10, pressure.full_avg10)),
+            ResetColor,
+            Print(format!(" (10s); some {:.1}%, full {:.1}% (60s)\n", pressure.some_avg60, pressure.full_avg60)),
+        )?;
+    }
+    if oom.is_next_victim() {
+        let message = if oom.at_risk() {
+            "   ⚠ Next OOM-kill victim, and the host is short of memory\n"
+        } else {
+            "   ⚠ Highest score on the host: first to go if memory runs out\n"
+        };
+        execute!(out, SetForegroundColor(score_color), Print(message), ResetColor)?;
+    }
+    Ok(())
+}
+
+/// Spawns and exits shown, the latest last.
+const CHILD_ROWS: usize = 8;
+
+fn render_children_panel<W: Write>(out: &mut W, feed: &ChildFeed, width: usize) -> Result<()> {
+    execute!(
+        out,
+        Print("\n"),
+        SetForegroundColor(Color::Cyan),
+        Print(format!("👶 Child Processes ({} running, {} spawned, {} exited)\n", feed.running(), feed.spawned, feed.exited)),
+        ResetColor,
+    )?;
+    if feed.zombies > 0 {
+        let (note, color) = match feed.zombies_growing() {
+            Some((then, _)) => (format!(", up from {}: the parent isn't reaping them", then), Color::Red),
+            None => (String::new(), Color::Yellow),
+        };
+        execute!(
+            out,
+            SetForegroundColor(color),
+            Print(format!("   Zombies:   {} exited, not reaped{}\n", feed.zombies, note)),
+            ResetColor,
+        )?;
+    }
+    for event in feed.events.iter().skip(feed.events.len().saturating_sub(CHILD_ROWS)) {
+        let (what, color) = match event.kind {
+            ChildEventKind::Spawned => ("spawned".to_string(), Color::Green),
+            ChildEventKind::Exited(Some(exit)) if exit.is_failure() => (format!("exited, {}", exit), Color::Red),
+            ChildEventKind::Exited(Some(exit)) => (format!("exited, {}", exit), Color::Reset),
+            ChildEventKind::Exited(None) => ("exited".to_string(), Color::DarkGrey),
+        };
+        execute!(
+            out,
+            Print(format!("   {}  {:<8}", event.at.format("%H:%M:%S"), event.pid)),
+            SetForegroundColor(color),
+            Print(format!("{:<18}", what)),
+            ResetColor,
+            Print(format!("{}\n", truncate(&event.command, width.saturating_sub(39)))),
+        )?;
+    }
+    Ok(())
+}
+
+/// Workers listed one per line before the rest are summarised.
+const POOL_ROWS: usize = 12;
+
+fn render_pool_panel<W: Write>(out: &mut W, pool: &WorkerPool, sparkline_width: usize) -> Result<()> {
+    let Some(total) = &pool.total else {
+        return Ok(());
+    };
+    execute!(
+        out,
+        Print("\n"),
+        SetForegroundColor(Color::Cyan),
+        Print(format!("👷 Worker Pool ({} × {})\n", pool.workers.len(), pool.name)),
+        ResetColor,
+        Print(format!(
+            "   Total:     {:>6.2}% CPU  {:.2} {} (master and workers)\n",
+            total.cpu_percent,
+            to_mb(total.memory_bytes),
+            mb_unit()
+        )),
+        Print("   History:   "),
+    )?;
+    render_sparkline(out, &pool.totals.cpu_history, sparkline_width)?;
+    execute!(out, Print("\n"))?;
+
+    for worker in pool.workers.iter().take(POOL_ROWS) {
+        let color = if worker.cpu_percent > 80.0 {
+            Color::Red
+        } else if worker.cpu_percent > 50.0 {
+            Color::Yellow
+        } else {
+            Color::Green
+        };
+        execute!(out, Print(format!("   {:<11}", worker.pid)))?;
+        render_bar(out, worker.cpu_percent as f64, 100.0, 10, color)?;
+        execute!(
+            out,
+            Print(format!(" {:>6.2}%  {:>8.2} {}\n", worker.cpu_percent, to_mb(worker.memory_bytes), mb_unit())),
+        )?;
+    }
+    if pool.workers.len() > POOL_ROWS {
+        execute!(out, Print(format!("   … and {} more\n", pool.workers.len() - POOL_ROWS)))?;
+    }
+
+    let respawns = format!("   Respawns:  {} exited, {} spawned", pool.exited, pool.spawned);
+    match pool.events.last() {
+        Some(event) => execute!(
+            out,
+            SetForegroundColor(Color::Yellow),
+            Print(format!(
+                "{} (last: PID {} {} at {})\n",
+                respawns,
+                event.pid,
+                event.kind.label(),
+                event.at.format("%H:%M:%S")
+            )),
+            ResetColor,
+        )?,
+        None => execute!(out, Print(format!("{}\n", respawns)))?,
+    }
+    Ok(())
+}
+
+fn render_upstream_panel<W: Write>(out: &mut W, chain: &ProxyChain, proxy: &ProcessInfo, port: u16) -> Result<()> {
+    let usage = |info: &ProcessInfo| format!("{:>6.2}% CPU  {:>8.2} {}", info.cpu_percent, to_mb(info.memory_bytes), mb_unit());
+    execute!(
+        out,
+        Print("\n"),
+        SetForegroundColor(Color::Cyan),
+        Print("🔗 Proxy Chain\n"),
+        ResetColor,
+        Print(format!("   {:<32}{}\n", format!("{} :{}", proxy.name, port), usage(proxy))),
+    )?;
+    if chain.backends.is_empty() {
+        execute!(out, Print("   No upstreams on this host seen yet\n"))?;
+        return Ok(());
+    }
+    for backend in &chain.backends {
+        let pids = match backend.pids.as_slice() {
+            [pid] => format!("PID {}", pid),
+            pids => format!("{} PIDs", pids.len()),
+        };
+        match &backend.latest {
+            Some(info) => execute!(
+                out,
+                Print(format!("   → {:<30}{}\n", format!(":{} {} ({})", backend.port, info.name, pids), usage(info))),
+            )?,
+            None if !backend.pids.is_empty() => execute!(out, Print(format!("   → :{} ({})\n", backend.port, pids)))?,
+            None => execute!(
+                out,
+                SetForegroundColor(Color::Yellow),
+                Print(format!("   → :{} nothing listening\n", backend.port)),
+                ResetColor,
+            )?,
+        }
+    }
+    execute!(out, Print(format!("   Total:     {} (proxy and backends)\n", usage(&chain.total(proxy)).trim_start())))?;
+    Ok(())
+}
+
+/// A core used this much by one process is the bottleneck, however idle the rest are.
+const CORE_SATURATED_PERCENT: f64 = 90.0;
+
+/// One tiny bar per core, like htop's header, wrapped to the terminal.
+fn render_core_strip<W: Write>(out: &mut W, cores: &CoreUsage, value_width: usize) -> Result<()> {
+    const LEVELS: [char; 8] = ['▁', '▂', '▃', '▄', '▅', '▆', '▇', '█'];
+    for (row, chunk) in cores.percent.chunks(value_width.max(1)).enumerate() {
+        execute!(out, Print(if row == 0 { "   Cores:     " } else { "              " }))?;
+        for &percent in chunk {
+            let level = ((percent / 100.0 * LEVELS.len() as f64) as usize).min(LEVELS.len() - 1);
+            let color = if percent >= CORE_SATURATED_PERCENT {
+                Color::Red
+            } else if percent >= 50.0 {
+                Color::Yellow
+            } else {
+                Color::Green
+            };
+            execute!(out, SetForegroundColor(color), Print(LEVELS[level]), ResetColor)?;
+        }
+        execute!(out, Print("\n"))?;
+    }
+    if let Some((cpu, percent)) = cores.busiest() {
+        let threads = cores.threads.get(cpu).copied().unwrap_or(0);
+        execute!(
+            out,
+            Print(format!(
+                "   Busiest:   cpu{} {:.0}% ({} thread{})\n",
+                cpu,
+                percent,
+                threads,
+                if threads == 1 { "" } else { "s" }
+            )),
+        )?;
+        let saturated = cores.percent.iter().filter(|&&p| p >= CORE_SATURATED_PERCENT).count();
+        if saturated == 1 && cores.percent.len() > 1 {
+            execute!(
+                out,
+                SetForegroundColor(Color::Red),
+                Print(format!("   ⚠ Pinned to one core: cpu{} is saturated while others have room\n", cpu)),
+                ResetColor,
+            )?;
+        }
+    }
+    Ok(())
+}
+
+fn render_power_panel<W: Write>(out: &mut W, power: &PowerMeter) -> Result<()> {
+    execute!(
+        out,
+        Print("\n"),
+        SetForegroundColor(Color::Green),
+        Print("🔋 Power (RAPL estimate)\n"),
+        ResetColor,
+    )?;
+    let (Some(process), Some(package)) = (power.process_watts, power.package_watts) else {
+        execute!(out, Print("   Measuring…\n"))?;
+        return Ok(());
+    };
+    let share = if package > 0.0 { process / package * 100.0 } else { 0.0 };
+    execute!(
+        out,
+        Print(format!("   Process:   {:>8.2} W ({:.0}% of {:.1} W package)\n", process, share, package)),
+        Print(format!(
+            "   Energy:    {:>8.1} J this session ({:.4} Wh)\n",
+            power.process_joules,
+            power.process_joules / 3600.0
+        )),
+    )?;
+    Ok(())
+}
+
+fn render_app_panel<W: Write>(out: &mut W, app: &AppMetrics, width: usize) -> Result<()> {
+    execute!(
+        out,
+        Print("\n"),
+        SetForegroundColor(Color::Cyan),
+        Print(format!("📈 App Metrics ({})\n", truncate(&app.describe(), width.saturating_sub(18)))),
+        ResetColor,
+    )?;
+    if app.goroutines.is_some() || app.threads.is_some() {
+        let goroutines = app.goroutines.map_or("-".to_string(), |n| n.to_string());
+        let threads = app.threads.map(|n| format!("  Threads: {}", n)).unwrap_or_default();
+        execute!(out, Print(format!("   Goroutines: {:>7}{}\n", goroutines, threads)))?;
+    }
+    if let Some(heap) = app.heap {
+        execute!(
+            out,
+            Print(format!(
+                "   Heap:       {} in use, {} allocated of {}, {} GCs\n",
+                format_bytes(heap.inuse_bytes),
+                format_bytes(heap.alloc_bytes),
+                format_bytes(heap.sys_bytes),
+                heap.num_gc
+            )),
+        )?;
+    }
+    let name_width = width.saturating_sub(20).clamp(12, 48);
+    for metric in &app.values {
+        let value = match (metric.kind, metric.value) {
+            (_, None) => "…".to_string(),
+            (MetricKind::Counter, Some(rate)) => format!("{:.1}/s", rate),
+            (MetricKind::Gauge, Some(value)) if metric.name.ends_with("_bytes") => format_bytes(value.max(0.0) as u64),
+            (MetricKind::Gauge, Some(value)) if value.fract() == 0.0 => format!("{:.0}", value),
+            (MetricKind::Gauge, Some(value)) => format!("{:.3}", value),
+        };
+        execute!(
+            out,
+            Print(format!("   {:<name_width$} {:>12}\n", truncate(&metric.name, name_width), value, name_width = name_width)),
+        )?;
+    }
+    if app.goroutines.is_none() && app.heap.is_none() && app.values.is_empty() && app.error.is_none() {
+        execute!(out, Print("   Waiting for the first scrape…\n"))?;
+    }
+    if let Some(error) = &app.error {
+        execute!(
+            out,
+            SetForegroundColor(Color::Red),
+            Print(format!("   ✗ {}\n", truncate(error, width.saturating_sub(5)))),
+            ResetColor,
+        )?;
+    }
+    Ok(())
+}
+
+type CounterField = fn(&ProcessCounters) -> u64;
+
+fn render_counters_panel<W: Write>(out: &mut W, counters: &CounterHistory, sparkline_width: usize) -> Result<()> {
+    let Some(latest) = counters.latest() else {
+        return Ok(());
+    };
+    execute!(
+        out,
+        Print("\n"),
+        SetForegroundColor(Color::Magenta),
+        Print("🔀 Faults & Context Switches (per interval)\n"),
+        ResetColor,
+    )?;
+    // Major faults wait on disk and involuntary switches on a busy CPU, so
+    // any sustained number of either is worth a colour.
+    let rows: [(&str, CounterField, u64); 4] = [
+        ("Minor:", |c| c.minor_faults, u64::MAX),
+        ("Major:", |c| c.major_faults, 1),
+        ("Voluntary:", |c| c.voluntary_switches, u64::MAX),
+        ("Forced:", |c| c.involuntary_switches, 100),
+    ];
+    let spark_width = sparkline_width.saturating_sub(12).max(1);
+    for (label, field, warn_at) in rows {
+        let value = field(latest);
+        let color = if value >= warn_at.saturating_mul(10) {
+            Color::Red
+        } else if value >= warn_at {
+            Color::Yellow
+        } else {
+            Color::Reset
+        };
+        execute!(
+            out,
+            Print(format!("   {:<11}", label)),
+            SetForegroundColor(color),
+            Print(format!("{:>8}", value)),
+            ResetColor,
+            Print("  "),
+        )?;
+        render_sparkline(out, &counters.series(field), spark_width)?;
+        execute!(out, Print("\n"))?;
+    }
+    Ok(())
+}
+
+fn render_accept_queue_panel<W: Write>(out: &mut W, queue: &AcceptQueueHistory, sparkline_width: usize) -> Result<()> {
+    let latest = queue.latest;
+    execute!(
+        out,
+        Print("\n"),
+        SetForegroundColor(Color::Yellow),
+        Print("📥 Accept Queue\n"),
+        ResetColor,
+        Print("   Queued:    "),
+        SetForegroundColor(ratio_color(latest.fill_ratio())),
+        Print(format!("{} of {} backlog ({:.0}%)", latest.queued, latest.backlog, latest.fill_ratio() * 100.0)),
+        ResetColor,
+        Print("\n"),
+        Print(format!("   Peak:      {}\n", queue.peak())),
+    )?;
+    if let Some(overflows) = queue.new_overflows {
+        execute!(
+            out,
+            Print("   Overflows: "),
+            SetForegroundColor(if overflows > 0 { Color::Red } else { Color::Green }),
+            Print(format!("{} since last sample", overflows)),
+            ResetColor,
+            Print(" (host-wide)\n"),
+        )?;
+    }
+    if queue.depths.len() > 1 {
+        let depths: Vec<f32> = queue.depths.iter().map(|&d| d as f32).collect();
+        execute!(out, Print("   History:   "))?;
+        render_sparkline(out, &depths, sparkline_width)?;
+        execute!(out, Print("\n"))?;
+    }
+    Ok(())
+}
+
+/// A row per listening address: `0.0.0.0:8080 ×4  backlog 4096 (max), recv
+/// 2.0 MB (set), send 16.0 KB`, yellow when the backlog is at `somaxconn` and
+/// may have been cut down; then the addresses shared with `SO_REUSEPORT` and
+/// the host defaults.
+fn render_socket_panel<W: Write>(out: &mut W, sockets: &ListenerSockets, width: usize) -> Result<()> {
+    execute!(
+        out,
+        Print("\n"),
+        SetForegroundColor(Color::Yellow),
+        Print("🔧 Socket\n"),
+        ResetColor,
+    )?;
+    let set = |size: u64, default: Option<u64>| match default {
+        Some(default) if default != size => format!("{} (set)", format_bytes(size)),
+        _ => format_bytes(size),
+    };
+    for socket in &sockets.sockets {
+        let address = match socket.count {
+            1 => socket.listener.to_string(),
+            n => format!("{} ×{}", socket.listener, n),
+        };
+        let capped = sockets.backlog_capped(socket);
+        let options = format!(
+            "backlog {}{}, recv {}, send {}",
+            socket.backlog,
+            if capped { " (max)" } else { "" },
+            set(socket.recv_buffer, sockets.default_recv_buffer),
+            set(socket.send_buffer, sockets.default_send_buffer),
+        );
+        execute!(
+            out,
+            SetForegroundColor(if capped { Color::Yellow } else { Color::Reset }),
+            Print(truncate(&format!("   {:<18}{}", address, options), width)),
+            ResetColor,
+            Print("\n"),
+        )?;
+    }
+    let mut shared: Vec<String> = Vec::new();
+    for socket in sockets.sockets.iter().filter(|s| s.reuses_port()) {
+        let text = format!("{} ({} sockets)", socket.listener, socket.sharing);
+        if !shared.contains(&text) {
+            shared.push(text);
+        }
+    }
+    if !shared.is_empty() {
+        execute!(out, Print(truncate(&format!("   SO_REUSEPORT: {}", shared.join(", ")), width)), Print("\n"))?;
+    }
+    let mut defaults = Vec::new();
+    if let Some(somaxconn) = sockets.somaxconn {
+        defaults.push(format!("somaxconn {}", somaxconn));
+    }
+    if let Some(size) = sockets.default_recv_buffer {
+        defaults.push(format!("recv {}", format_bytes(size)));
+    }
+    if let Some(size) = sockets.default_send_buffer {
+        defaults.push(format!("send {}", format_bytes(size)));
+    }
+    if !defaults.is_empty() {
+        execute!(
+            out,
+            SetForegroundColor(Color::DarkGrey),
+            Print(truncate(&format!("   Defaults:  {}", defaults.join(", ")), width)),
+            ResetColor,
+            Print("\n"),
+        )?;
+    }
+    Ok(())
+}
+
+type EbpfField = fn(&EbpfStats) -> u64;
+
+fn render_ebpf_panel<W: Write>(out: &mut W, ebpf: &EbpfHistory, sparkline_width: usize) -> Result<()> {
+    let Some(latest) = ebpf.latest() else {
+        return Ok(());
+    };
+    execute!(
+        out,
+        Print("\n"),
+        SetForegroundColor(Color::Magenta),
+        Print("🐝 Kernel Probes (eBPF, per interval)\n"),
+        ResetColor,
+    )?;
+    let rows: [(&str, EbpfField); 3] = [
+        ("Accepts:", |e| e.accepts),
+        ("Retransmit:", |e| e.retransmits),
+        ("Syscalls:", |e| e.syscalls),
+    ];
+    let spark_width = sparkline_width.saturating_sub(12).max(1);
+    for (label, field) in rows {
+        let value = field(latest);
+        // Any retransmit from the port is worth a look.
+        let color = if label == "Retransmit:" && value > 0 { Color::Yellow } else { Color::Reset };
+        execute!(
+            out,
+            Print(format!("   {:<11}", label)),
+            SetForegroundColor(color),
+            Print(format!("{:>8}", value)),
+            ResetColor,
+            Print("  "),
+        )?;
+        render_sparkline(out, &ebpf.series(field), spark_width)?;
+        execute!(out, Print("\n"))?;
+    }
+    if let Some(avg) = latest.syscall_avg_us() {
+        execute!(
+            out,
+            Print(format!(
+                "   Latency:   {:.1} µs average, slowest so far {:.1} ms (waiting syscalls left out)\n",
+                avg,
+                latest.syscall_max_ns as f64 / 1e6
+            )),
+        )?;
+    }
+    Ok(())
+}
+
+fn render_syscalls_panel<W: Write>(out: &mut W, activity: &SyscallActivity, bar_width: usize) -> Result<()> {
+    let summary = activity.summary();
+    let title = if activity.counted {
+        "🧮 Syscalls (eBPF, per interval)".to_string()
+    } else {
+        format!("🧮 Syscalls (threads seen in each, last {} samples)", activity.samples())
+    };
+    execute!(out, Print("\n"), SetForegroundColor(Color::Blue), Print(format!("{}\n", title)), ResetColor)?;
+    let mut rows = vec![
+        ("Read:", summary.read),
+        ("Write:", summary.write),
+        ("Poll:", summary.poll),
+        ("Futex:", summary.futex),
+        ("Other:", summary.other),
+    ];
+    // Only sampling can see a thread that isn't in a syscall at all.
+    if !activity.counted {
+        rows.push(("Running:", summary.running));
+    }
+    let total = summary.total().max(1) as f64;
+    // Leaves room for the count and share after the bar.
+    let bar_width = bar_width.saturating_sub(16).max(10);
+    for (label, count) in rows {
+        let share = count as f64 / total * 100.0;
+        execute!(out, Print(format!("   {:<11}", label)))?;
+        render_bar(out, share, 100.0, bar_width, Color::Blue)?;
+        if activity.counted {
+            execute!(out, Print(format!(" {:>8} {:>4.0}%\n", count, share)))?;
+        } else {
+            execute!(out, Print(format!(" {:>4.0}%\n", share)))?;
+        }
+    }
+    if let Some(busy) = summary.dominant() {
+        execute!(out, Print(format!("   Mostly:    {}\n", busy)))?;
+    }
+    Ok(())
+}
+
+/// `203.0.113.9         3   12.4 kB/s  GB AS2856 British Telecom  host.example.net`:
+/// the top talkers, green when internal and yellow from the internet. The
+/// traffic column shows once the connections' counters have been read twice.
+fn render_peers_panel<W: Write>(out: &mut W, peers: &[Peer], width: usize) -> Result<()> {
+    execute!(
+        out,
+        Print("\n"),
+        SetForegroundColor(Color::Cyan),
+        Print("🌐 Top Talkers\n"),
+        ResetColor,
+    )?;
+    if peers.is_empty() {
+        execute!(out, Print("   No open connections\n"))?;
+        return Ok(());
+    }
+    let traffic = peers.iter().any(|p| p.bytes_per_sec.is_some());
+    let header = if traffic {
+        format!("   {:<24}{:>5}  {:>11}\n", "Peer", "Conns", "Traffic")
+    } else {
+        format!("   {:<24}{:>5}\n", "Peer", "Conns")
+    };
+    execute!(out, SetForegroundColor(Color::DarkGrey), Print(header), ResetColor)?;
+    for peer in peers {
+        let mut address = format!("   {:<24}{:>5}  ", peer.address.to_string(), peer.connections);
+        if traffic {
+            let rate = peer.bytes_per_sec.map_or("-".to_string(), |r| format!("{}/s", format_bytes(r as u64)));
+            address.push_str(&format!("{:>11}  ", rate));
+        }
+        let place = if peer.is_internal() {
+            "internal".to_string()
+        } else {
+            let mut parts = vec![peer.geo.country.clone().unwrap_or_else(|| "internet".to_string())];
+            if let Some(asn) = peer.geo.asn {
+                parts.push(format!("AS{}", asn));
+            }
+            parts.extend(peer.geo.organization.clone());
+            parts.join(" ")
+        };
+        let room = width.saturating_sub(address.chars().count());
+        let place = truncate(&place, room);
+        let name = match &peer.hostname {
+            Some(name) => truncate(&format!("  {}", name), room.saturating_sub(place.chars().count())),
+            None => String::new(),
+        };
+        execute!(
+            out,
+            Print(address),
+            SetForegroundColor(if peer.is_internal() { Color::Green } else { Color::Yellow }),
+            Print(place),
+            ResetColor,
+            Print(name),
+            Print("\n"),
+        )?;
+    }
+    Ok(())
+}
+
+/// A rate at least this many times the session's mean, and at least
+/// `CHURN_SPIKE_MIN` per second, is flagged as a spike.
+const CHURN_SPIKE_FACTOR: f64 = 3.0;
+const CHURN_SPIKE_MIN: f64 = 5.0;
+
+fn render_churn_panel<W: Write>(out: &mut W, churn: &ChurnHistory, sparkline_width: usize) -> Result<()> {
+    execute!(
+        out,
+        Print("\n"),
+        SetForegroundColor(Color::Cyan),
+        Print(format!("🔁 Connection Churn ({} open)\n", churn.open_now)),
+        ResetColor,
+    )?;
+    let spark_width = sparkline_width.saturating_sub(12).max(1);
+    for (label, counts) in [("Opened:", &churn.opened), ("Closed:", &churn.closed)] {
+        let rates = churn.per_second(counts);
+        let latest = rates.last().copied().unwrap_or(0.0);
+        let mean = rates.iter().sum::<f64>() / rates.len().max(1) as f64;
+        let spike = latest >= CHURN_SPIKE_MIN && latest >= mean * CHURN_SPIKE_FACTOR && rates.len() > 1;
+        execute!(
+            out,
+            Print(format!("   {:<11}", label)),
+            SetForegroundColor(if spike { Color::Yellow } else { Color::Reset }),
+            Print(format!("{:>6.1}/s", latest)),
+            ResetColor,
+            Print("  "),
+        )?;
+        let series: Vec<f32> = rates.iter().map(|&r| r as f32).collect();
+        render_sparkline(out, &series, spark_width)?;
+        execute!(out, Print("\n"))?;
+    }
+    let peak = |counts: &[u32]| churn.per_second(counts).into_iter().fold(0.0, f64::max);
+    execute!(
+        out,
+        Print(format!(
+            "   Peak:      {:.1}/s opened, {:.1}/s closed\n",
+            peak(&churn.opened),
+            peak(&churn.closed)
+        )),
+    )?;
+    Ok(())
+}
+
+/// One line per fact, green when it's the safe way round, yellow when worth
+/// a second look and red when it's an exposure: the port's addresses, the
+/// user, the binary's mode, the LSM label and the capabilities held.
+fn render_security_panel<W: Write>(out: &mut W, frame: &Frame, posture: &Posture, width: usize) -> Result<()> {
+    execute!(
+        out,
+        Print("\n"),
+        SetForegroundColor(Color::Cyan),
+        Print("🔐 Security\n"),
+        ResetColor,
+    )?;
+    let room = width.saturating_sub(14);
+    let line = |out: &mut W, label: &str, color: Color, text: String| -> Result<()> {
+        execute!(
+            out,
+            Print(format!("   {:<11}", label)),
+            SetForegroundColor(color),
+            Print(truncate(&text, room)),
+            ResetColor,
+            Print("\n"),
+        )?;
+        Ok(())
+    };
+
+    let bound: Vec<&Listener> = frame.listeners.iter().filter(|l| frame.port == 0 || l.port == frame.port).collect();
+    let widest = bound.iter().map(|l| Exposure::of(&l.address)).max();
+    if let Some(widest) = widest {
+        let color = match widest {
+            Exposure::Everywhere => Color::Yellow,
+            _ => Color::Green,
+        };
+        let addresses: Vec<String> = bound.iter().map(|l| l.to_string()).collect();
+        line(out, "Bound:", color, format!("{} ({})", addresses.join(", "), widest.label()))?;
+    }
+
+    let user = frame.info.user.as_deref().unwrap_or("?");
+    let color = if posture.runs_as_root() { Color::Red } else { Color::Green };
+    line(out, "User:", color, format!("{} (uid {})", user, posture.uid))?;
+
+    let exe = posture.exe.clone().or_else(|| frame.info.exe.as_ref().map(|p| p.display().to_string()));
+    let (color, text) = match (posture.exe_world_writable(), posture.exe_mode) {
+        (Some(true), Some(mode)) => (Color::Red, format!("world-writable ({:o}) {}", mode, exe.unwrap_or_default())),
+        (_, Some(mode)) => (Color::Green, format!("{} ({:o})", exe.unwrap_or_default(), mode)),
+        _ => (Color::DarkGrey, format!("{} (mode unreadable)", exe.as_deref().unwrap_or("?"))),
+    };
+    line(out, "Binary:", color, text)?;
+
+    let (color, text) = match &posture.confinement {
+        Confinement::Unknown => (Color::DarkGrey, "no SELinux or AppArmor label".to_string()),
+        Confinement::AppArmor(profile) => (if posture.confinement.is_confined() { Color::Green } else { Color::Yellow }, format!("AppArmor {}", profile)),
+        Confinement::SeLinux(context) => (if posture.confinement.is_confined() { Color::Green } else { Color::Yellow }, format!("SELinux {}", context)),
+    };
+    line(out, "Confined:", color, text)?;
+
+    let names = posture.capability_names();
+    let (color, text) = if posture.has_all_capabilities() {
+        (Color::Red, format!("all ({})", names.len()))
+    } else if names.is_empty() {
+        (Color::Green, "none".to_string())
+    } else {
+        (Color::Yellow, names.join(", "))
+    };
+    line(out, "Caps:", color, text)?;
+
+    if posture.runs_as_root() && widest == Some(Exposure::Everywhere) {
+        execute!(
+            out,
+            SetForegroundColor(Color::Red),
+            Print("   ⚠ Root, listening on every interface\n"),
+            ResetColor,
+        )?;
+    }
+    Ok(())
+}
+
+fn render_tcp_panel<W: Write>(out: &mut W, stats: &TcpStats, retransmit_alert: f64) -> Result<()> {
+    execute!(
+        out,
+        Print("\n"),
+        SetForegroundColor(Color::Yellow),
+        Print(format!("📶 TCP ({} established)\n", stats.connections)),
+        ResetColor,
+    )?;
+    if stats.connections == 0 {
+        execute!(out, Print("   No established connections\n"))?;
+        return Ok(());
+    }
+    if let (Some(avg), Some(max)) = (stats.rtt_avg_ms, stats.rtt_max_ms) {
+        execute!(
+            out,
+            Print("   RTT:       "),
+            SetForegroundColor(latency_color(max)),
+            Print(format!("{:.2} ms avg, {:.2} ms max", avg, max)),
+            ResetColor,
+            Print("\n"),
+        )?;
+    }
+    if let Some(cwnd) = stats.cwnd_avg {
+        execute!(out, Print(format!("   Cwnd:      {:.1} segments avg\n", cwnd)))?;
+    }
+    execute!(out, Print("   Retrans:   "))?;
+    match stats.retransmit_percent() {
+        Some(percent) => {
+            let alert = percent >= retransmit_alert;
+            execute!(
+                out,
+                SetForegroundColor(if alert {
+                    Color::Red
+                } else if percent >= retransmit_alert / 2.0 {
+                    Color::Yellow
+                } else {
+                    Color::Green
+                }),
+                Print(format!("{:.2}% of {} segments ({})", percent, stats.segments_out, stats.retransmits)),
+                ResetColor,
+            )?;
+            if alert {
+                execute!(
+                    out,
+                    SetForegroundColor(Color::Red),
+                    Print(format!("  ⚠ over {}%", retransmit_alert)),
+                    ResetColor,
+                )?;
+            }
+        }
+        None => execute!(out, Print("-"))?,
+    }
+    execute!(out, Print("\n"))?;
+    Ok(())
+}
+
+fn render_latency_panel<W: Write>(out: &mut W, probe: &Probe, sparkline_width: usize) -> Result<()> {
+    let history = &probe.history;
+    let ms = |v: Option<f64>| v.map(|v| format!("{:>8.2} ms", v)).unwrap_or_else(|| "       -".to_string());
+    execute!(
+        out,
+        Print("\n"),
+        SetForegroundColor(Color::Yellow),
+        Print(format!("⏱  Connect Latency ({})\n", probe.target)),
+        ResetColor,
+        Print("   Current:   "),
+    )?;
+    match history.last() {
+        Some(Some(latency)) => execute!(
+            out,
+            SetForegroundColor(latency_color(latency)),
+            Print(format!("{:>8.2} ms", latency)),
+            ResetColor,
+        )?,
+        Some(None) => execute!(out, SetForegroundColor(Color::Red), Print("  failed"), ResetColor)?,
+        None => execute!(out, Print("       -"))?,
+    }
+    execute!(
+        out,
+        Print("\n"),
+        Print(format!("   p50:       {}\n", ms(history.percentile(50.0)))),
+        Print(format!("   p99:       {}\n", ms(history.percentile(99.0)))),
+        Print(format!("   Failures:  {} of {}\n", history.failures(), history.samples.len())),
+    )?;
+    let ok: Vec<f32> = history.successes().iter().map(|&v| v as f32).collect();
+    if !ok.is_empty() {
+        execute!(out, Print("   History:   "))?;
+        render_sparkline(out, &ok, sparkline_width)?;
+        execute!(out, Print("\n"))?;
+    }
+    Ok(())
+}
+
+fn render_http_panel<W: Write>(out: &mut W, check: &HttpCheck, sparkline_width: usize, value_width: usize) -> Result<()> {
+    let history = &check.history;
+    let ms = |v: Option<f64>| v.map(|v| format!("{:>8.2} ms", v)).unwrap_or_else(|| "       -".to_string());
+    execute!(
+        out,
+        Print("\n"),
+        SetForegroundColor(Color::Yellow),
+        Print(format!("🌐 HTTP Check ({})\n", truncate(&check.url, value_width))),
+        ResetColor,
+        Print("   Status:    "),
+    )?;
+    match &check.last {
+        Some(Ok(status)) => {
+            let color = if *status == check.expect_status { Color::Green } else { Color::Red };
+            execute!(out, SetForegroundColor(color), Print(status), ResetColor)?;
+            execute!(out, Print(format!(" (expect {})", check.expect_status)))?;
+        }
+        Some(Err(err)) => execute!(out, SetForegroundColor(Color::Red), Print(truncate(err, value_width)), ResetColor)?,
+        None => execute!(out, Print("-"))?,
+    }
+    let codes: Vec<String> = check.statuses.iter().map(|(code, n)| format!("{}×{}", code, n)).collect();
+    execute!(
+        out,
+        Print("\n"),
+        Print(format!("   Codes:     {}\n", if codes.is_empty() { "-".to_string() } else { codes.join("  ") })),
+        Print(format!("   p50:       {}\n", ms(history.percentile(50.0)))),
+        Print(format!("   p99:       {}\n", ms(history.percentile(99.0)))),
+    )?;
+    let failures = history.failures();
+    execute!(
+        out,
+        Print("   Failures:  "),
+        SetForegroundColor(if failures > 0 { Color::Red } else { Color::Green }),
+        Print(format!("{} of {}", failures, history.samples.len())),
+        ResetColor,
+        Print("\n"),
+    )?;
+    let ok: Vec<f32> = history.successes().iter().map(|&v| v as f32).collect();
+    if !ok.is_empty() {
+        execute!(out, Print("   History:   "))?;
+        render_sparkline(out, &ok, sparkline_width)?;
+        execute!(out, Print("\n"))?;
+    }
+    Ok(())
+}
+
+fn render_tls_panel<W: Write>(out: &mut W, check: &TlsCheck, now: NaiveDateTime, value_width: usize) -> Result<()> {
+    execute!(
+        out,
+        Print("\n"),
+        SetForegroundColor(Color::Yellow),
+        Print(format!("🔒 TLS ({})\n", check.target)),
+        ResetColor,
+    )?;
+    let tls = match &check.last {
+        Some(Ok(tls)) => tls,
+        Some(Err(err)) => {
+            execute!(
+                out,
+                Print("   Status:    "),
+                SetForegroundColor(Color::Red),
+                Print(truncate(err, value_width)),
+                ResetColor,
+                Print("\n"),
+            )?;
+            return Ok(());
+        }
+        None => {
+            execute!(out, Print("   Status:    handshaking...\n"))?;
+            return Ok(());
+        }
+    };
+    render_tls_details(out, tls, now, value_width)
+}
+
+fn render_tls_details<W: Write>(out: &mut W, tls: &TlsInfo, now: NaiveDateTime, value_width: usize) -> Result<()> {
+    let protocol = match &tls.alpn {
+        Some(alpn) => format!("{} ({})", tls.protocol, alpn),
+        None => tls.protocol.clone(),
+    };
+    execute!(
+        out,
+        Print(format!("   Protocol:  {}\n", protocol)),
+        Print(format!("   Cipher:    {}\n", tls.cipher)),
+    )?;
+    if let Some(cert) = &tls.certificate {
+        let san = if cert.san.is_empty() { "-".to_string() } else { cert.san.join(", ") };
+        // The frame clock is local time; a few hours either way doesn't change a day count.
+        let days = cert.days_until_expiry(now.and_utc());
+        let color = if days < EXPIRY_WARNING_DAYS {
+            Color::Red
+        } else if days < EXPIRY_WARNING_DAYS * 2 {
+            Color::Yellow
+        } else {
+            Color::Green
+        };
+        let expiry = if days < 0 {
+            format!("{} (expired {} days ago)", cert.not_after.format("%Y-%m-%d"), -days)
+        } else {
+            format!("{} ({} days)", cert.not_after.format("%Y-%m-%d"), days)
+        };
+        execute!(
+            out,
+            Print(format!("   Subject:   {}\n", truncate(&cert.subject, value_width))),
+            Print(format!("   Issuer:    {}\n", truncate(&cert.issuer, value_width))),
+            Print(format!("   SAN:       {}\n", truncate(&san, value_width))),
+            Print("   Expires:   "),
+            SetForegroundColor(color),
+            Print(expiry),
+            ResetColor,
+            Print("\n"),
+        )?;
+    }
+    execute!(out, Print("   Verified:  "))?;
+    match &tls.verified {
+        Ok(()) => execute!(out, SetForegroundColor(Color::Green), Print("yes"), ResetColor)?,
+        Err(reason) => execute!(
+            out,
+            SetForegroundColor(Color::Red),
+            Print(format!("no, {}", truncate(reason, value_width.saturating_sub(4)))),
+            ResetColor,
+        )?,
+    }
+    execute!(out, Print("\n"))?;
+    Ok(())
+}
+
+/// Loopback-ish connects are well under a millisecond; tens of ms means a backlog.
+fn latency_color(ms: f64) -> Color {
+    if ms > 100.0 {
+        Color::Red
+    } else if ms > 10.0 {
+        Color::Yellow
+    } else {
+        Color::Green
+    }
+}
+
+fn render_insight_panel<W: Write>(
+    out: &mut W,
+    verdict: Option<&Verdict>,
+    status: Option<&str>,
+    width: usize,
+) -> Result<()> {
+    execute!(
+        out,
+        Print("\n"),
+        SetForegroundColor(Color::Cyan),
+        Print("🤖 AI Insight\n"),
+        ResetColor,
+    )?;
+    // "   Severity:  " and friends are 14 columns wide.
+    let text_width = width.saturating_sub(14);
+    if let Some(verdict) = verdict {
+        execute!(
+            out,
+            Print("   Severity:  "),
+            SetForegroundColor(severity_color(verdict.severity)),
+            Print(verdict.severity.label()),
+            ResetColor,
+            Print("\n"),
+        )?;
+        for (label, text) in [("Summary:", &verdict.summary), ("Cause:", &verdict.cause), ("Action:", &verdict.action)] {
+            if text.is_empty() {
+                continue;
+            }
+            for (i, line) in wrap(text, text_width).iter().enumerate() {
+                let label = if i == 0 { label } else { "" };
+                execute!(out, Print(format!("   {:<11}{}\n", label, line)))?;
+            }
+        }
+    }
+    if let Some(status) = status {
+        execute!(out, SetForegroundColor(Color::DarkGrey))?;
+        for line in wrap(status, width.saturating_sub(3)) {
+            execute!(out, Print(format!("   {}\n", line)))?;
+        }
+        execute!(out, ResetColor)?;
+    }
+    Ok(())
+}
+
+/// Green for ok, cyan for info, yellow for warnings and red when critical.
+pub fn severity_color(severity: Severity) -> Color {
+    match severity {
+        Severity::Ok => Color::Green,
+        Severity::Info => Color::Cyan,
+        Severity::Warning => Color::Yellow,
+        Severity::Critical => Color::Red,
+    }
+}
+
+/// Greedy word wrap; words longer than `width` get a line of their own.
+fn wrap(text: &str, width: usize) -> Vec<String> {
+    let mut lines = Vec::new();
+    for paragraph in text.lines() {
+        let mut line = String::new();
+        for word in paragraph.split_whitespace() {
+            if !line.is_empty() && line.chars().count() + 1 + word.chars().count() > width {
+                lines.push(std::mem::take(&mut line));
+            }
+            if !line.is_empty() {
+                line.push(' ');
+            }
+            line.push_str(word);
+        }
+        lines.push(line);
+    }
+    lines
+}
+
+/// Key hints joined with ` | ` into lines of at most `width` columns,
+/// breaking only between hints.
+fn wrap_hints(hints: &[String], width: usize) -> Vec<String> {
+    let mut lines: Vec<String> = Vec::new();
+    for hint in hints {
+        match lines.last_mut() {
+            Some(line) if line.chars().count() + 3 + hint.chars().count() <= width => {
+                line.push_str(" | ");
+                line.push_str(hint);
+            }
+            _ => lines.push(hint.clone()),
+        }
+    }
+    lines
+}
+
+/// Green/yellow/red for a usage ratio against a limit.
+fn ratio_color(ratio: f64) -> Color {
+    if ratio > 0.9 {
+        Color::Red
+    } else if ratio > 0.7 {
+        Color::Yellow
+    } else {
+        Color::Green
+    }
+}
+
+fn render_cgroup_panel<W: Write>(
+    out: &mut W,
+    cgroup: &CgroupStats,
+    throttled_ratio: Option<f64>,
+    cpu_percent: f32,
+    bar_width: usize,
+) -> Result<()> {
+    execute!(
+        out,
+        Print("\n"),
+        SetForegroundColor(Color::Cyan),
+        Print("📦 Cgroup Limits\n"),
+        ResetColor,
+    )?;
+
+    if let (Some(current), Some(max)) = (cgroup.memory_current, cgroup.memory_max) {
+        let ratio = current as f64 / (max as f64).max(1.0);
+        let color = ratio_color(ratio);
+        execute!(
+            out,
+            Print("   Memory:    "),
+            SetForegroundColor(color),
+            Print(format!("{:>8.2} / {:.2} {}", to_mb(current), to_mb(max), mb_unit())),
+            ResetColor,
+            Print("  "),
+        )?;
+        render_bar(out, current as f64, max as f64, bar_width, color)?;
+        execute!(out, Print("\n"))?;
+    }
+
+    if let Some(quota) = cgroup.cpu_quota_cores {
+        let limit_percent = quota * 100.0;
+        let ratio = cpu_percent as f64 / limit_percent.max(0.1);
+        let color = ratio_color(ratio);
+        execute!(
+            out,
+            Print("   CPU:       "),
+            SetForegroundColor(color),
+            Print(format!("{:>6.2}% of {:.0}%", cpu_percent, limit_percent)),
+            ResetColor,
+            Print(format!(" ({:.2} cores)  ", quota)),
+        )?;
+        render_bar(out, cpu_percent as f64, limit_percent, bar_width.saturating_sub(14).max(10), color)?;
+        execute!(out, Print("\n"))?;
+    }
+
+    let stat = &cgroup.cpu_stat;
+    if stat.nr_periods > 0 {
+        let recent = throttled_ratio.unwrap_or(0.0);
+        let color = if recent > 0.25 {
+            Color::Red
+        } else if recent > 0.0 {
+            Color::Yellow
+        } else {
+            Color::Green
+        };
+        execute!(
+            out,
+            Print("   Throttled: "),
+            SetForegroundColor(color),
+            Print(format!("{:>5.1}% of periods", recent * 100.0)),
+            ResetColor,
+            Print(format!(
+                "  (total {}/{}, {:.1}s)\n",
+                stat.nr_throttled,
+                stat.nr_periods,
+                stat.throttled_usec as f64 / 1_000_000.0
+            )),
+        )?;
+    }
+
+    Ok(())
+}
+
+fn render_unit_panel<W: Write>(out: &mut W, unit: &UnitStatus, prompt: Option<&str>, bar_width: usize) -> Result<()> {
+    let state_color = match unit.active_state.as_str() {
+        "active" => Color::Green,
+        "failed" => Color::Red,
+        _ => Color::Yellow,
+    };
+    let restarts = match unit.restarts {
+        Some(n) if n > 0 => format!(", {} restart{}", n, if n == 1 { "" } else { "s" }),
+        _ => String::new(),
+    };
+    execute!(
+        out,
+        Print("\n"),
+        SetForegroundColor(Color::Cyan),
+        Print(format!("⚙  systemd: {}\n", unit.name)),
+        ResetColor,
+        Print("   State:     "),
+        SetForegroundColor(state_color),
+        Print(format!("{}/{}", unit.active_state, unit.sub_state)),
+        ResetColor,
+        Print(format!("{}\n", restarts)),
+        Print(format!(
+            "   CPUQuota:  {}\n",
+            unit.cpu_quota_percent.map_or("unlimited".to_string(), |q| format!("{:.0}%", q))
+        )),
+    )?;
+    match (unit.memory_max, unit.memory_ratio()) {
+        (Some(max), Some(ratio)) => {
+            let color = ratio_color(ratio);
+            execute!(
+                out,
+                Print("   MemoryMax: "),
+                SetForegroundColor(color),
+                Print(format!("{:>5.1}%", ratio * 100.0)),
+                ResetColor,
+                Print("  "),
+            )?;
+            render_bar(out, ratio * 100.0, 100.0, bar_width, color)?;
+            execute!(out, Print(format!(" of {}\n", format_bytes(max))))?;
+        }
+        (max, _) => execute!(out, Print(format!("   MemoryMax: {}\n", max.map_or("unlimited".to_string(), format_bytes))))?,
+    }
+    if let Some(tasks) = unit.tasks {
+        execute!(out, Print(format!("   Tasks:     {}\n", tasks)))?;
+    }
+    match prompt {
+        Some(prompt) => execute!(out, SetForegroundColor(Color::Yellow), Print(format!("   {}\n", prompt)), ResetColor)?,
+        None => execute!(out, SetForegroundColor(Color::DarkGrey), Print("   'R' restart | 'X' stop\n"), ResetColor)?,
+    }
+    Ok(())
+}
+
+fn render_crabtop_log_pane<W: Write>(out: &mut W, lines: &[LogLine], width: usize) -> Result<()> {
+    execute!(
+        out,
+        Print("\n"),
+        SetForegroundColor(Color::Cyan),
+        Print("🦀 crabtop log\n"),
+        ResetColor,
+    )?;
+    if lines.is_empty() {
+        execute!(out, Print("   Nothing logged yet (-v and -vv log more)\n"))?;
+    }
+    for line in lines {
+        let color = match line.level {
+            Level::ERROR => Color::Red,
+            Level::WARN => Color::Yellow,
+            Level::INFO => Color::Reset,
+            _ => Color::DarkGrey,
+        };
+        let text = format!("{} {:>5} {}", line.timestamp.format("%H:%M:%S"), line.level, line.message);
+        execute!(
+            out,
+            SetForegroundColor(color),
+            Print(format!("   {}\n", truncate(&text, width.saturating_sub(3)))),
+            ResetColor,
+        )?;
+    }
+    Ok(())
+}
+
+fn render_log_pane<W: Write>(out: &mut W, logs: &LogTail, width: usize) -> Result<()> {
+    execute!(
+        out,
+        Print("\n"),
+        SetForegroundColor(Color::Cyan),
+        Print(format!("📜 Logs ({})\n", truncate(&logs.source, width.saturating_sub(12)))),
+        ResetColor,
+    )?;
+    let lines = match &logs.lines {
+        Ok(lines) => lines,
+        Err(e) => {
+            execute!(
+                out,
+                SetForegroundColor(Color::Red),
+                Print(format!("   {}\n", truncate(e, width.saturating_sub(3)))),
+                ResetColor,
+            )?;
+            return Ok(());
+        }
+    };
+    if lines.is_empty() {
+        execute!(out, Print("   No entries yet\n"))?;
+    }
+    for line in lines {
+        let lower = line.to_ascii_lowercase();
+        let color = if ["error", "panic", "fatal", "exception"].iter().any(|w| lower.contains(w)) {
+            Color::Red
+        } else if lower.contains("warn") {
+            Color::Yellow
+        } else {
+            Color::Reset
+        };
+        execute!(
+            out,
+            SetForegroundColor(color),
+            Print(format!("   {}\n", truncate(line, width.saturating_sub(3)))),
+            ResetColor,
+        )?;
+    }
+    Ok(())
+}
+
+fn render_open_files_panel<W: Write>(out: &mut W, files: &FileListing, width: usize) -> Result<()> {
+    execute!(
+        out,
+        Print("\n"),
+        SetForegroundColor(Color::Cyan),
+        Print(format!("📂 Open Files (page {}/{})\n", files.page + 1, files.pages())),
+        ResetColor,
+    )?;
+    if files.files.is_empty() {
+        execute!(out, Print("   None readable (the process may belong to another user)\n"))?;
+        return Ok(());
+    }
+    let kinds = [FileKind::File, FileKind::Socket, FileKind::Pipe, FileKind::Device, FileKind::Anon];
+    let counts: Vec<String> = kinds
+        .iter()
+        .map(|&kind| (kind, files.count(kind)))
+        .filter(|&(_, count)| count > 0)
+        .map(|(kind, count)| {
+            let plural = if count == 1 || kind == FileKind::Anon { "" } else { "s" };
+            format!("{} {}{}", count, kind.label(), plural)
+        })
+        .collect();
+    execute!(out, Print(format!("   {} open: {}\n", files.files.len(), counts.join(", "))))?;
+    // The fd, kind and size columns take 27; the target gets the rest.
+    let target_width = width.saturating_sub(27).max(10);
+    for file in files.current() {
+        let size = file.size.map(format_bytes).unwrap_or_default();
+        execute!(
+            out,
+            Print(format!("   {:>4} {:<6} {:>10}  ", file.fd, file.kind.label(), size)),
+            SetForegroundColor(if file.kind == FileKind::File { Color::White } else { Color::DarkGrey }),
+            Print(truncate(&file.target, target_width)),
+            ResetColor,
+            Print("\n"),
+        )?;
+    }
+    Ok(())
+}
+
+fn render_stack_dump_pane<W: Write>(out: &mut W, dump: &StackDump, width: usize) -> Result<()> {
+    let total = dump.lines.as_ref().map_or(0, Vec::len);
+    let position = if total > DUMP_LINES {
+        format!(", lines {}-{} of {}", dump.offset + 1, (dump.offset + DUMP_LINES).min(total), total)
+    } else {
+        String::new()
+    };
+    execute!(
+        out,
+        Print("\n"),
+        SetForegroundColor(Color::Cyan),
+        Print(format!(
+            "🧵 {} Stacks ({}{})\n",
+            dump.runtime.label(),
+            truncate(&dump.command, width.saturating_sub(24 + position.len())),
+            position
+        )),
+        ResetColor,
+    )?;
+    if let Err(e) = &dump.lines {
+        execute!(
+            out,
+            SetForegroundColor(Color::Red),
+            Print(format!("   {}\n", truncate(e, width.saturating_sub(3)))),
+            ResetColor,
+        )?;
+        return Ok(());
+    }
+    if total == 0 {
+        execute!(out, Print("   The dump was empty\n"))?;
+    }
+    for line in dump.visible() {
+        // Thread headers start at the margin; frames are indented under them.
+        let color = if line.starts_with(char::is_whitespace) { Color::Reset } else { Color::White };
+        execute!(
+            out,
+            SetForegroundColor(color),
+            Print(format!("   {}\n", truncate(line, width.saturating_sub(3)))),
+            ResetColor,
+        )?;
+    }
+    Ok(())
+}
+
+fn render_system_panel<W: Write>(out: &mut W, system: &SystemSnapshot, bar_width: usize) -> Result<()> {
+    let (one, five, fifteen) = system.load_average;
+    let total_memory_mb = to_mb(system.total_memory_bytes);
+    let used_memory_mb = to_mb(system.total_memory_bytes.saturating_sub(system.available_memory_bytes));
+    let mem_ratio = used_memory_mb / total_memory_mb.max(0.1);
+    let load_ratio = one / system.cpu_count.max(1) as f64;
+
+    let mem_color = ratio_color(mem_ratio);
+    let load_color = if load_ratio > 1.0 {
+        Color::Red
+    } else if load_ratio > 0.7 {
+        Color::Yellow
+    } else {
+        Color::Green
+    };
+
+    execute!(
+        out,
+        Print("\n"),
+        SetForegroundColor(Color::Cyan),
+        Print("🖥  System\n"),
+        ResetColor,
+        Print("   Load:      "),
+        SetForegroundColor(load_color),
+        Print(format!("{:.2} {:.2} {:.2}", one, five, fifteen)),
+        ResetColor,
+        Print(format!("  ({} CPUs)\n", system.cpu_count)),
+        Print("   Memory:    "),
+        SetForegroundColor(mem_color),
+        Print(format!("{:>8.2} / {:.2} {}", used_memory_mb, total_memory_mb, mb_unit())),
+        ResetColor,
+        Print("  "),
+    )?;
+    render_bar(out, used_memory_mb, total_memory_mb, bar_width, mem_color)?;
+    execute!(
+        out,
+        Print("\n"),
+        Print(format!(
+            "   Swap:      {:>8.2} / {:.2} {}\n",
+            to_mb(system.used_swap_bytes),
+            to_mb(system.total_swap_bytes),
+            mb_unit()
+        )),
+    )?;
+
+    Ok(())
+}
+
+/// RSS split into private and shared pages, plus swap, as one stacked bar.
+/// "Resident" alone overstates forking servers, whose children share most pages.
+fn render_memory_breakdown<W: Write>(out: &mut W, info: &ProcessInfo, bar_width: usize) -> Result<()> {
+    if let Some(shared) = info.shared_bytes {
+        let swap = info.swap_bytes.unwrap_or(0);
+        let private = info.memory_bytes.saturating_sub(shared);
+        let segments = [
+            (private, '█', Color::Blue, "private"),
+            (shared.min(info.memory_bytes), '▓', Color::Cyan, "shared"),
+            (swap, '▒', Color::Magenta, "swap"),
+        ];
+        let total = segments.iter().map(|s| s.0).sum::<u64>().max(1) as f64;
+        let width = bar_width.max(1);
+        execute!(out, Print("   Breakdown: ["))?;
+        let mut drawn = 0;
+        for (i, &(bytes, glyph, color, _)) in segments.iter().enumerate() {
+            // The last segment takes the rounding slack so the bar is always full width.
+            let cells = if i == segments.len() - 1 {
+                width - drawn
+            } else {
+                ((bytes as f64 / total * width as f64).round() as usize).min(width - drawn)
+            };
+            drawn += cells;
+            execute!(out, SetForegroundColor(color), Print(glyph.to_string().repeat(cells)), ResetColor)?;
+        }
+        execute!(out, Print("]\n"), Print("              "))?;
+        for (i, &(bytes, glyph, color, label)) in segments.iter().enumerate() {
+            execute!(
+                out,
+                Print(if i == 0 { "" } else { "  " }),
+                SetForegroundColor(color),
+                Print(glyph),
+                ResetColor,
+                Print(format!(" {} {:.2} {}", label, to_mb(bytes), mb_unit())),
+            )?;
+        }
+        execute!(out, Print("\n"))?;
+    }
+    if info.virtual_bytes > 0 {
+        execute!(out, Print(format!("   Virtual:   {:>8.2} {}\n", to_mb(info.virtual_bytes), mb_unit())))?;
+    }
+    if let Some(commit) = info.commit_bytes {
+        execute!(out, Print(format!("   Commit:    {:>8.2} {}\n", to_mb(commit), mb_unit())))?;
+    }
+    Ok(())
+}
+
+/// `Segments: heap 48.0 MB  anon mmap 250.0 MB ...`, and which of them
+/// accounts for the recent growth, with a hint of what grows it.
+fn render_segments<W: Write>(out: &mut W, segments: &SegmentHistory, width: usize) -> Result<()> {
+    let Some(latest) = segments.latest() else {
+        return Ok(());
+    };
+    let parts: Vec<String> = SEGMENTS.iter().map(|&s| format!("{} {}", s, format_bytes(latest.get(s)))).collect();
+    execute!(out, Print(format!("   Segments:  {}\n", parts.join("  "))))?;
+    if let Some((segment, growth, since)) = segments.growing() {
+        execute!(
+            out,
+            SetForegroundColor(Color::Yellow),
+            Print(format!("   ⚠ Growing: {}, +{} since {}\n", segment, format_bytes(growth), since.format("%H:%M:%S"))),
+            SetForegroundColor(Color::DarkGrey),
+            Print(format!("              {}\n", truncate(segment.hint(), width))),
+            ResetColor,
+        )?;
+    }
+    Ok(())
+}
+
+/// `Swapped: 12.00 MB (8.1% of the process)`, and a warning while more of
+/// it is going out, with the host's memory pressure when PSI is there.
+fn render_swap<W: Write>(out: &mut W, swap: &SwapHistory, pressure: Option<MemoryPressure>) -> Result<()> {
+    let color = if swap.is_swapping() { Color::Red } else { Color::Magenta };
+    execute!(
+        out,
+        Print("   Swapped:   "),
+        SetForegroundColor(color),
+        Print(format!("{:>8.2} {}", to_mb(swap.latest()), mb_unit())),
+        ResetColor,
+        Print(format!(" ({:.1}% of the process)", swap.share_percent())),
+    )?;
+    if swap.is_swapping() {
+        execute!(out, SetForegroundColor(color), Print(format!(", +{} since the last sample", format_mb(swap.growth()))), ResetColor)?;
+    }
+    execute!(out, Print("\n"))?;
+    if swap.is_swapping() {
+        let pressure = pressure.map_or(String::new(), |p| {
+            format!(" (pressure some {:.1}%, full {:.1}%)", p.some_avg10, p.full_avg10)
+        });
+        execute!(
+            out,
+            SetForegroundColor(color),
+            Print(format!("   ⚠ Swapping out: expect latency spikes{}\n", pressure)),
+            ResetColor,
+        )?;
+    }
+    Ok(())
+}
+
+fn render_bar<W: Write>(
+    out: &mut W,
+    value: f64,
+    max: f64,
+    width: usize,
+    color: Color,
+) -> Result<()> {
+    let width = width.max(1); // Ensure at least 1 character width
+    let filled = ((value / max.max(0.1)) * width as f64).round() as usize;
+    let filled = filled.min(width);
+    let empty = width.saturating_sub(filled);
+
+    execute!(
+        out,
+        Print("["),
+        SetForegroundColor(color),
+        Print("█".repeat(filled)),
+        ResetColor,
+        SetForegroundColor(Color::DarkGrey),
+        Print("░".repeat(empty)),
+        ResetColor,
+        Print("]"),
+    )?;
+
+    Ok(())
+}
+
+fn render_sparkline<W: Write>(out: &mut W, data: &[f32], width: usize) -> Result<()> {
+    if data.is_empty() || width == 0 {
+        return Ok(());
+    }
+
+    let sparkline_chars = ['▁', '▂', '▃', '▄', '▅', '▆', '▇', '█'];
+    let max_val = data.iter().copied().fold(0.0f32, f32::max).max(1.0);
+
+    let step = if data.len() > width {
+        data.len() / width
+    } else {
+        1
+    };
+
+    let samples: Vec<f32> = data.iter().step_by(step).copied().collect();
+    let display_samples = if samples.len() > width {
+        &samples[samples.len() - width..]
+    } else {
+        &samples
+    };
+
+    for &val in display_samples {
+        let normalized = (val / max_val).min(1.0);
+        let idx = (normalized * (sparkline_chars.len() - 1) as f32).round() as usize;
+        let color = if val > 80.0 {
+            Color::Red
+        } else if val > 50.0 {
+            Color::Yellow
+        } else {
+            Color::Green
+        };
+        execute!(
+            out,
+            SetForegroundColor(color),
+            Print(sparkline_chars[idx]),
+            ResetColor,
+        )?;
+    }
+
+    Ok(())
+}
+
+fn render_sparkline_mem<W: Write>(out: &mut W, data: &[f64], width: usize) -> Result<()> {
+    if data.is_empty() || width == 0 {
+        return Ok(());
+    }
+
+    let sparkline_chars = ['▁', '▂', '▃', '▄', '▅', '▆', '▇', '█'];
+    let max_val = data.iter().copied().fold(0.0f64, f64::max).max(1.0);
+
+    let step = if data.len() > width {
+        data.len() / width
+    } else {
+        1
+    };
+
+    let samples: Vec<f64> = data.iter().step_by(step).copied().collect();
+    let display_samples = if samples.len() > width {
+        &samples[samples.len() - width..]
+    } else {
+        &samples
+    };
+
+    for &val in display_samples {
+        let normalized = (val / max_val).min(1.0);
+        let idx = (normalized * (sparkline_chars.len() - 1) as f64).round() as usize;
+        let color = if val > 1000.0 {
+            Color::Red
+        } else if val > 500.0 {
+            Color::Yellow
+        } else {
+            Color::Blue
+        };
+        execute!(
+            out,
+            SetForegroundColor(color),
+            Print(sparkline_chars[idx]),
+            ResetColor,
+        )?;
+    }
+
+    Ok(())
+}
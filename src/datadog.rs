@@ -0,0 +1,118 @@
+//! Sending watch samples to Datadog's metrics API, for hosts without an
+//! agent to take DogStatsD. The gauges and tags are the ones `--statsd`
+//! sends, so a dashboard works whichever way the figures arrive.
+//!
+//! The exporter is only built with the `datadog` feature; `series` is
+//! always there.
+
+use serde_json::{json, Value};
+
+use crate::export::ExportSample;
+
+/// Where the API key is read from unless the config names another variable.
+pub const DEFAULT_API_KEY_ENV: &str = "DD_API_KEY";
+
+/// The `POST /api/v2/series` body for one sample: a gauge per figure, tagged
+/// with the port, process and (when remote) host, plus `tags`.
+pub fn series(sample: &ExportSample, tags: &[String]) -> Value {
+    let mut all_tags = vec![format!("port:{}", sample.port), format!("process:{}", sample.process.name)];
+    all_tags.extend(sample.host.as_ref().map(|host| format!("host:{}", host)));
+    all_tags.extend(tags.iter().cloned());
+    let timestamp = sample.timestamp.timestamp();
+    let gauges = [
+        ("crabtop.cpu_percent", sample.process.cpu_percent as f64),
+        ("crabtop.memory_bytes", sample.process.memory_bytes as f64),
+        ("crabtop.uptime_secs", sample.process.uptime_secs as f64),
+    ];
+    let series: Vec<Value> = gauges
+        .iter()
+        .map(|(metric, value)| {
+            json!({
+                "metric": metric,
+                // 3 is a gauge.
+                "type": 3,
+                "points": [{ "timestamp": timestamp, "value": value }],
+                "tags": all_tags,
+            })
+        })
+        .collect();
+    json!({ "series": series })
+}
+
+#[cfg(feature = "datadog")]
+pub use exporter::DatadogExporter;
+
+#[cfg(feature = "datadog")]
+mod exporter {
+    use std::sync::{Arc, Mutex};
+
+    use anyhow::{anyhow, Context, Result};
+
+    use crate::export::{ExportSample, Exporter};
+
+    /// POSTs each sample in the background; a failed request is reported
+    /// with the next sample.
+    pub struct DatadogExporter {
+        client: reqwest::Client,
+        url: String,
+        api_key: String,
+        tags: Vec<String>,
+        last_error: Arc<Mutex<Option<String>>>,
+    }
+
+    impl DatadogExporter {
+        /// `site` is the Datadog site (`datadoghq.com`, `datadoghq.eu`,
+        /// `us5.datadoghq.com`...); the key comes from `api_key_env`, and a
+        /// missing one fails right away.
+        pub fn new(site: &str, api_key_env: &str, tags: Vec<String>) -> Result<Self> {
+            let api_key = std::env::var(api_key_env)
+                .ok()
+                .filter(|key| !key.is_empty())
+                .with_context(|| format!("The Datadog exporter needs an API key in ${}", api_key_env))?;
+            Ok(Self {
+                client: reqwest::Client::new(),
+                url: format!("https://api.{}/api/v2/series", site.trim_start_matches("api.")),
+                api_key,
+                tags,
+                last_error: Arc::default(),
+            })
+        }
+
+        fn take_error(&self) -> Result<()> {
+            match self.last_error.lock().unwrap().take() {
+                Some(message) => Err(anyhow!(message)),
+                None => Ok(()),
+            }
+        }
+    }
+
+    impl Exporter for DatadogExporter {
+        fn name(&self) -> &str {
+            "datadog"
+        }
+
+        fn on_sample(&mut self, sample: &ExportSample) -> Result<()> {
+            let request = self
+                .client
+                .post(&self.url)
+                .header("DD-API-KEY", &self.api_key)
+                .json(&super::series(sample, &self.tags));
+            let last_error = Arc::clone(&self.last_error);
+            tokio::spawn(async move {
+                let failure = match request.send().await {
+                    Ok(resp) if resp.status().is_success() => None,
+                    Ok(resp) => Some(format!("HTTP {}", resp.status())),
+                    Err(err) => Some(err.to_string()),
+                };
+                if let Some(message) = failure {
+                    *last_error.lock().unwrap() = Some(message);
+                }
+            });
+            self.take_error()
+        }
+
+        fn on_shutdown(&mut self) -> Result<()> {
+            self.take_error()
+        }
+    }
+}
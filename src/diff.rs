@@ -0,0 +1,154 @@
+//! `diff`: two `--record` recordings lined up by time since each one
+//! started, so "the same minute of the load test" is compared across runs.
+
+use std::fmt::Write as _;
+
+use crate::baseline::Summary;
+use crate::chart::{self, Chart, Series};
+use crate::dashboard::relative_change;
+use crate::export::ExportSample;
+use crate::recording::{self, Metric};
+
+/// Columns of the terminal sparklines; the default bucket fits the longer
+/// recording into this many.
+pub const SPARK_WIDTH: usize = 60;
+
+/// One metric of both recordings: per-bucket means, and the figures over
+/// every sample.
+#[derive(Debug, Clone, PartialEq)]
+pub struct MetricDiff {
+    pub metric: Metric,
+    pub a: Vec<Option<f64>>,
+    pub b: Vec<Option<f64>>,
+    pub summary_a: Option<Summary>,
+    pub summary_b: Option<Summary>,
+}
+
+impl MetricDiff {
+    /// Change of the average from the first recording to the second, in percent.
+    pub fn change(&self) -> Option<f64> {
+        relative_change(self.summary_a?.avg, self.summary_b?.avg)
+    }
+
+    /// The bucket where the two differ most, and both values there; `None`
+    /// when they never differ.
+    pub fn widest_gap(&self) -> Option<(usize, f64, f64)> {
+        self.a
+            .iter()
+            .zip(&self.b)
+            .enumerate()
+            .filter_map(|(n, (a, b))| Some((n, (*a)?, (*b)?)))
+            .filter(|(_, a, b)| a != b)
+            .max_by(|x, y| (x.2 - x.1).abs().total_cmp(&(y.2 - y.1).abs()))
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Diff {
+    pub bucket_secs: f64,
+    /// Only the metrics at least one recording has values for.
+    pub metrics: Vec<MetricDiff>,
+}
+
+/// A bucket long enough that the longer recording fits `SPARK_WIDTH`
+/// columns, in whole seconds.
+pub fn default_bucket(a: &[ExportSample], b: &[ExportSample]) -> f64 {
+    let span = recording::span_secs(a).max(recording::span_secs(b));
+    (span / SPARK_WIDTH as f64).ceil().max(1.0)
+}
+
+/// Compares `a` against `b`, averaging each into `bucket_secs` slots
+/// counted from its own first sample.
+pub fn diff(a: &[ExportSample], b: &[ExportSample], bucket_secs: f64) -> Diff {
+    let bucket_secs = bucket_secs.max(0.001);
+    let buckets = ((recording::span_secs(a).max(recording::span_secs(b)) / bucket_secs).floor() as usize) + 1;
+    let metrics = Metric::ALL
+        .iter()
+        .map(|&metric| {
+            let values = |samples: &[ExportSample]| samples.iter().filter_map(|s| metric.value(s)).collect::<Vec<_>>();
+            MetricDiff {
+                metric,
+                a: recording::bucketed(a, metric, bucket_secs, buckets),
+                b: recording::bucketed(b, metric, bucket_secs, buckets),
+                summary_a: Summary::of(&values(a)),
+                summary_b: Summary::of(&values(b)),
+            }
+        })
+        .filter(|d| d.summary_a.is_some() || d.summary_b.is_some())
+        .collect();
+    Diff { bucket_secs, metrics }
+}
+
+/// One character per bucket, scaled to `max`; a gap where there was no sample.
+pub fn sparkline(values: &[Option<f64>], max: f64) -> String {
+    const BARS: [char; 8] = ['▁', '▂', '▃', '▄', '▅', '▆', '▇', '█'];
+    values
+        .iter()
+        .map(|value| match value {
+            Some(v) => BARS[((v / max.max(f64::MIN_POSITIVE)).clamp(0.0, 1.0) * (BARS.len() - 1) as f64).round() as usize],
+            None => ' ',
+        })
+        .collect()
+}
+
+/// A standalone page with the summary table and one chart per metric.
+pub fn render_html(diff: &Diff, a_name: &str, b_name: &str) -> String {
+    let mut html = format!(
+        "<!DOCTYPE html>\n<html><head><meta charset=\"utf-8\"><title>crabtop diff</title>\n\
+         <style>body{{font-family:sans-serif;margin:2em}}table{{border-collapse:collapse}}\
+         td,th{{border:1px solid #ccc;padding:4px 10px;text-align:right}}th:first-child,td:first-child{{text-align:left}}</style>\n\
+         </head><body>\n<h1>{} vs {}</h1>\n<p>Aligned by time since each recording started, in {}s buckets.</p>\n\
+         <table><tr><th>Metric</th><th>A avg</th><th>B avg</th><th>Change</th><th>A p95</th><th>B p95</th><th>A max</th><th>B max</th></tr>\n",
+        chart::escape(a_name),
+        chart::escape(b_name),
+        diff.bucket_secs
+    );
+    for d in &diff.metrics {
+        let show = |summary: Option<Summary>, field: fn(&Summary) -> f64| {
+            summary.map_or("–".to_string(), |s| d.metric.format(field(&s)))
+        };
+        let _ = writeln!(
+            html,
+            "<tr><td>{}</td><td>{}</td><td>{}</td><td>{}</td><td>{}</td><td>{}</td><td>{}</td><td>{}</td></tr>",
+            d.metric.label(),
+            show(d.summary_a, |s| s.avg),
+            show(d.summary_b, |s| s.avg),
+            d.change().map_or("–".to_string(), |c| format!("{:+.1}%", c)),
+            show(d.summary_a, |s| s.p95),
+            show(d.summary_b, |s| s.p95),
+            show(d.summary_a, |s| s.max),
+            show(d.summary_b, |s| s.max)
+        );
+    }
+    html.push_str("</table>\n");
+    for d in &diff.metrics {
+        let points = |values: &[Option<f64>]| {
+            values
+                .iter()
+                .enumerate()
+                .filter_map(|(n, v)| Some((n as f64 * diff.bucket_secs, (*v)?)))
+                .collect()
+        };
+        let series = [
+            Series {
+                label: format!("A: {}", a_name),
+                color: chart::PALETTE[0],
+                points: points(&d.a),
+            },
+            Series {
+                label: format!("B: {}", b_name),
+                color: chart::PALETTE[1],
+                points: points(&d.b),
+            },
+        ];
+        let chart = Chart {
+            title: d.metric.label(),
+            series: &series,
+            x_label: chart::elapsed_label,
+            y_label: d.metric.axis_label(),
+        };
+        let _ = write!(html, "<h2>{}</h2>\n{}", d.metric.label(), chart.render(900.0, 280.0));
+    }
+    html.push_str("</body></html>\n");
+    html
+}
@@ -0,0 +1,102 @@
+//! Seeing through `docker-proxy` to the container that actually publishes a port.
+
+use std::process::Command;
+
+use anyhow::{anyhow, Context, Result};
+use serde::Serialize;
+
+use crate::cgroup::cgroup_pids;
+use crate::process::process_name;
+
+#[derive(Debug, Clone, Serialize)]
+pub struct Container {
+    pub id: String,
+    pub name: String,
+    pub image: String,
+    /// Host PIDs of the processes inside the container.
+    pub pids: Vec<u32>,
+}
+
+/// Whether the listener is Docker's userland proxy rather than the real service.
+pub fn is_docker_proxy(pid: u32) -> bool {
+    process_name(pid).as_deref() == Some("docker-proxy")
+}
+
+/// Finds the running container that publishes `port` on the host, using the
+/// `docker` CLI. `Ok(None)` means docker answered but no container matched.
+pub fn container_for_port(port: u16) -> Result<Option<Container>> {
+    let out = Command::new("docker")
+        .args(["ps", "--format", "{{.ID}}\t{{.Names}}\t{{.Image}}\t{{.Ports}}"])
+        .output()
+        .context("Failed to run `docker ps`")?;
+    if !out.status.success() {
+        return Err(anyhow!(
+            "`docker ps` failed: {}",
+            String::from_utf8_lossy(&out.stderr).trim()
+        ));
+    }
+
+    let stdout = String::from_utf8_lossy(&out.stdout);
+    for line in stdout.lines() {
+        let mut cols = line.split('\t');
+        let (Some(id), Some(name), Some(image), Some(ports)) = (cols.next(), cols.next(), cols.next(), cols.next())
+        else {
+            continue;
+        };
+        if publishes_port(ports, port) {
+            let init_pid = container_init_pid(id)?;
+            let mut pids = cgroup_pids(init_pid);
+            if pids.is_empty() {
+                pids.push(init_pid);
+            }
+            return Ok(Some(Container {
+                id: id.to_string(),
+                name: name.to_string(),
+                image: image.to_string(),
+                pids,
+            }));
+        }
+    }
+
+    Ok(None)
+}
+
+/// The container whose cgroup `pid` is in, from `/proc/<pid>/cgroup`: the
+/// 64-hex-digit id in `/docker/<id>` (cgroupfs) or `docker-<id>.scope` (systemd).
+pub fn container_id_from_cgroup(text: &str) -> Option<String> {
+    text.lines()
+        .flat_map(|line| line.split('/'))
+        .map(|part| part.strip_prefix("docker-").and_then(|p| p.strip_suffix(".scope")).unwrap_or(part))
+        .find(|part| part.len() == 64 && part.bytes().all(|b| b.is_ascii_hexdigit()))
+        .map(str::to_string)
+}
+
+/// Whether a `docker ps` PORTS column publishes TCP `port` on the host. It
+/// reads like "0.0.0.0:8080->80/tcp, :::8080->80/tcp", with
+/// "0.0.0.0:8000-8010->8000-8010/tcp" for a range. UDP mappings don't count:
+/// only TCP listeners are looked up.
+pub fn publishes_port(ports: &str, port: u16) -> bool {
+    ports.split(", ").any(|mapping| {
+        let Some((host, container)) = mapping.split_once("->") else {
+            return false;
+        };
+        let Some((_, host_ports)) = host.rsplit_once(':').filter(|_| container.ends_with("/tcp")) else {
+            return false;
+        };
+        match host_ports.split_once('-') {
+            Some((first, last)) => matches!((first.parse::<u16>(), last.parse::<u16>()), (Ok(first), Ok(last)) if (first..=last).contains(&port)),
+            None => host_ports.parse() == Ok(port),
+        }
+    })
+}
+
+fn container_init_pid(id: &str) -> Result<u32> {
+    let out = Command::new("docker")
+        .args(["inspect", "--format", "{{.State.Pid}}", id])
+        .output()
+        .context("Failed to run `docker inspect`")?;
+    String::from_utf8_lossy(&out.stdout)
+        .trim()
+        .parse()
+        .with_context(|| format!("Failed to read the main PID of container {}", id))
+}
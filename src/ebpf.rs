@@ -0,0 +1,545 @@
+//! Kernel-side counters for the watched process, from eBPF programs on
+//! tracepoints: accepted connections, TCP retransmits on the port, time
+//! spent in syscalls and what those syscalls were. procfs polling sees none
+//! of these per process.
+//!
+//! Only built with the `ebpf` feature, on Linux, and only attaches when the
+//! kernel lets us (root or CAP_BPF + CAP_PERFMON, tracefs mounted). In every
+//! other case `EbpfCollector::attach` returns `None` and watch mode carries
+//! on with procfs alone. The programs are a few dozen instructions each and
+//! are assembled here and loaded through `bpf(2)` directly, so there's no
+//! BPF toolchain or object file involved.
+
+use std::ops::Sub;
+
+use crate::syscalls::SyscallCounts;
+
+/// Counts since the probes were attached.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct EbpfStats {
+    /// Successful `accept()`/`accept4()` calls by the process.
+    pub accepts: u64,
+    /// Segments retransmitted from the port.
+    pub retransmits: u64,
+    /// Syscalls completed, leaving out those that wait by design
+    /// (`epoll_wait`, `futex`, `accept`, `nanosleep`...).
+    pub syscalls: u64,
+    pub syscall_ns: u64,
+    /// Slowest of those syscalls.
+    pub syscall_max_ns: u64,
+    /// Every syscall made, waiting ones included, by category.
+    pub calls: SyscallCounts,
+}
+
+impl EbpfStats {
+    pub fn syscall_avg_us(&self) -> Option<f64> {
+        (self.syscalls > 0).then(|| self.syscall_ns as f64 / self.syscalls as f64 / 1000.0)
+    }
+}
+
+/// What happened over one interval; `syscall_max_ns` stays the lifetime maximum.
+impl Sub for EbpfStats {
+    type Output = EbpfStats;
+
+    fn sub(self, earlier: EbpfStats) -> EbpfStats {
+        EbpfStats {
+            accepts: self.accepts.saturating_sub(earlier.accepts),
+            retransmits: self.retransmits.saturating_sub(earlier.retransmits),
+            syscalls: self.syscalls.saturating_sub(earlier.syscalls),
+            syscall_ns: self.syscall_ns.saturating_sub(earlier.syscall_ns),
+            syscall_max_ns: self.syscall_max_ns,
+            calls: self.calls - earlier.calls,
+        }
+    }
+}
+
+/// Probes attached for one process and port; detached on drop.
+#[derive(Debug)]
+pub struct EbpfCollector {
+    #[cfg(all(feature = "ebpf", target_os = "linux"))]
+    probes: probes::Probes,
+}
+
+impl EbpfCollector {
+    /// `None` without the `ebpf` feature, off Linux, or when the kernel
+    /// refuses (usually for lack of privileges).
+    pub fn attach(pid: u32, port: u16) -> Option<Self> {
+        #[cfg(all(feature = "ebpf", target_os = "linux"))]
+        {
+            probes::Probes::attach(pid, port).ok().map(|probes| Self { probes })
+        }
+        #[cfg(not(all(feature = "ebpf", target_os = "linux")))]
+        {
+            let _ = (pid, port);
+            None
+        }
+    }
+
+    pub fn read(&self) -> Option<EbpfStats> {
+        #[cfg(all(feature = "ebpf", target_os = "linux"))]
+        {
+            self.probes.read()
+        }
+        #[cfg(not(all(feature = "ebpf", target_os = "linux")))]
+        {
+            None
+        }
+    }
+}
+
+#[cfg(all(feature = "ebpf", target_os = "linux"))]
+mod probes {
+    use std::fs;
+    use std::io;
+    use std::path::{Path, PathBuf};
+
+    use anyhow::{anyhow, Context, Result};
+
+    use super::EbpfStats;
+    use crate::syscalls::SyscallCategory;
+
+    // Slots of the per-CPU stats value, in u64s.
+    const ACCEPTS: i16 = 0;
+    const RETRANSMITS: i16 = 1;
+    const SYSCALLS: i16 = 2;
+    const SYSCALL_NS: i16 = 3;
+    const SYSCALL_MAX_NS: i16 = 4;
+    const READ_CALLS: i16 = 5;
+    const WRITE_CALLS: i16 = 6;
+    const POLL_CALLS: i16 = 7;
+    const FUTEX_CALLS: i16 = 8;
+    const OTHER_CALLS: i16 = 9;
+    const STATS_LEN: usize = 10;
+    const CATEGORIES: [(SyscallCategory, i16); 4] = [
+        (SyscallCategory::Read, READ_CALLS),
+        (SyscallCategory::Write, WRITE_CALLS),
+        (SyscallCategory::Poll, POLL_CALLS),
+        (SyscallCategory::Futex, FUTEX_CALLS),
+    ];
+
+    /// Threads that can be inside a syscall at once.
+    const MAX_THREADS: u32 = 16384;
+
+    // Syscalls that block until something happens; timing them would just
+    // measure how idle the process is.
+    #[cfg(target_arch = "x86_64")]
+    const WAITING_SYSCALLS: &[i32] = &[7, 23, 34, 35, 43, 61, 128, 130, 202, 208, 230, 232, 270, 271, 281, 288, 426, 441];
+    #[cfg(target_arch = "aarch64")]
+    const WAITING_SYSCALLS: &[i32] = &[4, 22, 72, 73, 98, 101, 115, 133, 137, 202, 242, 260, 426, 441];
+    #[cfg(not(any(target_arch = "x86_64", target_arch = "aarch64")))]
+    const WAITING_SYSCALLS: &[i32] = &[];
+
+    // bpf(2) commands, map and program types, helpers.
+    const BPF_MAP_CREATE: i32 = 0;
+    const BPF_MAP_LOOKUP_ELEM: i32 = 1;
+    const BPF_PROG_LOAD: i32 = 5;
+    const BPF_MAP_TYPE_HASH: u32 = 1;
+    const BPF_MAP_TYPE_PERCPU_ARRAY: u32 = 6;
+    const BPF_PROG_TYPE_TRACEPOINT: u32 = 5;
+    const HELPER_MAP_LOOKUP: i32 = 1;
+    const HELPER_MAP_UPDATE: i32 = 2;
+    const HELPER_MAP_DELETE: i32 = 3;
+    const HELPER_KTIME_GET_NS: i32 = 5;
+    const HELPER_GET_CURRENT_PID_TGID: i32 = 14;
+
+    const PERF_TYPE_TRACEPOINT: u32 = 2;
+    const PERF_FLAG_FD_CLOEXEC: libc::c_ulong = 8;
+    const PERF_EVENT_IOC_ENABLE: libc::c_ulong = 0x2400;
+    const PERF_EVENT_IOC_SET_BPF: libc::c_ulong = 0x4004_2408;
+
+    #[derive(Debug)]
+    struct Fd(i32);
+
+    impl Drop for Fd {
+        fn drop(&mut self) {
+            unsafe { libc::close(self.0) };
+        }
+    }
+
+    #[derive(Debug)]
+    pub struct Probes {
+        stats: Fd,
+        possible_cpus: usize,
+        // Kept open for as long as the probes should stay attached.
+        _maps: Vec<Fd>,
+        _programs: Vec<Fd>,
+        _events: Vec<Fd>,
+    }
+
+    impl Probes {
+        pub fn attach(pid: u32, port: u16) -> Result<Self> {
+            let tracefs = ["/sys/kernel/tracing", "/sys/kernel/debug/tracing"]
+                .into_iter()
+                .map(PathBuf::from)
+                .find(|root| root.join("events").is_dir())
+                .context("tracefs isn't mounted")?;
+            let pid = i32::try_from(pid)?;
+            let stats = create_map(BPF_MAP_TYPE_PERCPU_ARRAY, 4, (STATS_LEN * 8) as u32, 1)?;
+            let starts = create_map(BPF_MAP_TYPE_HASH, 4, 8, MAX_THREADS)?;
+
+            let mut programs = Vec::new();
+            let mut events = Vec::new();
+            let cpus = cpu_list(&fs::read_to_string("/sys/devices/system/cpu/online")?);
+            let mut attach = |category: &str, name: &str, program: &[Insn]| -> Result<()> {
+                let program = load_program(program).with_context(|| format!("loading the {} probe", name))?;
+                let id = tracepoint_id(&tracefs, category, name)?;
+                for &cpu in &cpus {
+                    events.push(open_tracepoint(id, cpu, &program)?);
+                }
+                programs.push(program);
+                Ok(())
+            };
+
+            for name in ["sys_exit_accept", "sys_exit_accept4"] {
+                let ret = field_offset(&tracefs, "syscalls", name, "ret")?;
+                attach("syscalls", name, &accept_program(pid, ret, stats.0))?;
+            }
+            let sport = field_offset(&tracefs, "tcp", "tcp_retransmit_skb", "sport")?;
+            attach("tcp", "tcp_retransmit_skb", &retransmit_program(port, sport, stats.0))?;
+            let id = field_offset(&tracefs, "raw_syscalls", "sys_enter", "id")?;
+            attach("raw_syscalls", "sys_enter", &enter_program(pid, id, starts.0, stats.0))?;
+            attach("raw_syscalls", "sys_exit", &exit_program(pid, starts.0, stats.0))?;
+
+            Ok(Self {
+                stats,
+                possible_cpus: cpu_list(&fs::read_to_string("/sys/devices/system/cpu/possible")?).len(),
+                _maps: vec![starts],
+                _programs: programs,
+                _events: events,
+            })
+        }
+
+        /// Sums the per-CPU counters (and takes the largest maximum).
+        pub fn read(&self) -> Option<EbpfStats> {
+            let key = 0u32;
+            let mut values = vec![0u64; STATS_LEN * self.possible_cpus];
+            let mut attr = Attr::default();
+            attr.put_u32(0, self.stats.0 as u32);
+            attr.put_u64(8, &key as *const u32 as u64);
+            attr.put_u64(16, values.as_mut_ptr() as u64);
+            bpf(BPF_MAP_LOOKUP_ELEM, &mut attr).ok()?;
+            let mut stats = EbpfStats::default();
+            for cpu in values.chunks(STATS_LEN) {
+                stats.accepts += cpu[ACCEPTS as usize];
+                stats.retransmits += cpu[RETRANSMITS as usize];
+                stats.syscalls += cpu[SYSCALLS as usize];
+                stats.syscall_ns += cpu[SYSCALL_NS as usize];
+                stats.syscall_max_ns = stats.syscall_max_ns.max(cpu[SYSCALL_MAX_NS as usize]);
+                stats.calls.read += cpu[READ_CALLS as usize];
+                stats.calls.write += cpu[WRITE_CALLS as usize];
+                stats.calls.poll += cpu[POLL_CALLS as usize];
+                stats.calls.futex += cpu[FUTEX_CALLS as usize];
+                stats.calls.other += cpu[OTHER_CALLS as usize];
+            }
+            Some(stats)
+        }
+    }
+
+    /// `0-3,6` as in `/sys/devices/system/cpu/online`.
+    fn cpu_list(list: &str) -> Vec<i32> {
+        list.trim()
+            .split(',')
+            .filter_map(|range| match range.split_once('-') {
+                Some((low, high)) => Some((low.parse().ok()?..=high.parse().ok()?).collect::<Vec<i32>>()),
+                None => Some(vec![range.parse().ok()?]),
+            })
+            .flatten()
+            .collect()
+    }
+
+    fn tracepoint_id(tracefs: &Path, category: &str, name: &str) -> Result<u64> {
+        let path = tracefs.join("events").join(category).join(name).join("id");
+        let id = fs::read_to_string(&path).with_context(|| format!("no tracepoint {}:{}", category, name))?;
+        Ok(id.trim().parse()?)
+    }
+
+    /// Where `field` sits in the tracepoint's record, from its `format` file
+    /// (`field:__u16 sport; offset:28; size:2; signed:0;`).
+    fn field_offset(tracefs: &Path, category: &str, name: &str, field: &str) -> Result<i16> {
+        let format = fs::read_to_string(tracefs.join("events").join(category).join(name).join("format"))?;
+        format
+            .lines()
+            .find(|line| {
+                line.trim_start()
+                    .strip_prefix("field:")
+                    .and_then(|decl| decl.split(';').next())
+                    .and_then(|decl| decl.split_whitespace().last())
+                    == Some(field)
+            })
+            .and_then(|line| line.split("offset:").nth(1)?.split(';').next()?.parse().ok())
+            .ok_or_else(|| anyhow!("{}:{} has no field {}", category, name, field))
+    }
+
+    /// A zeroed `union bpf_attr` or `perf_event_attr`, filled in by offset.
+    #[derive(Default)]
+    struct Attr([u64; 16]);
+
+    impl Attr {
+        fn put_u32(&mut self, offset: usize, value: u32) {
+            let bytes = bytemuck(&mut self.0);
+            bytes[offset..offset + 4].copy_from_slice(&value.to_ne_bytes());
+        }
+
+        fn put_u64(&mut self, offset: usize, value: u64) {
+            self.0[offset / 8] = value;
+        }
+    }
+
+    fn bytemuck(words: &mut [u64; 16]) -> &mut [u8; 128] {
+        // SAFETY: [u64; 16] and [u8; 128] have the same size, and u8 has no alignment needs.
+        unsafe { &mut *(words as *mut [u64; 16] as *mut [u8; 128]) }
+    }
+
+    fn bpf(command: i32, attr: &mut Attr) -> io::Result<i32> {
+        let result = unsafe { libc::syscall(libc::SYS_bpf, command, attr.0.as_mut_ptr(), std::mem::size_of::<Attr>()) };
+        if result < 0 {
+            return Err(io::Error::last_os_error());
+        }
+        Ok(result as i32)
+    }
+
+    fn create_map(map_type: u32, key_size: u32, value_size: u32, max_entries: u32) -> Result<Fd> {
+        let mut attr = Attr::default();
+        attr.put_u32(0, map_type);
+        attr.put_u32(4, key_size);
+        attr.put_u32(8, value_size);
+        attr.put_u32(12, max_entries);
+        Ok(Fd(bpf(BPF_MAP_CREATE, &mut attr).context("creating a BPF map")?))
+    }
+
+    fn load_program(insns: &[Insn]) -> Result<Fd> {
+        let license = c"GPL";
+        let mut log = vec![0u8; 1 << 16];
+        let mut attr = Attr::default();
+        attr.put_u32(0, BPF_PROG_TYPE_TRACEPOINT);
+        attr.put_u32(4, insns.len() as u32);
+        attr.put_u64(8, insns.as_ptr() as u64);
+        attr.put_u64(16, license.as_ptr() as u64);
+        match bpf(BPF_PROG_LOAD, &mut attr) {
+            Ok(fd) => Ok(Fd(fd)),
+            Err(error) => {
+                // Load again with the verifier's log, to say why.
+                attr.put_u32(24, 1);
+                attr.put_u32(28, log.len() as u32);
+                attr.put_u64(32, log.as_mut_ptr() as u64);
+                let _ = bpf(BPF_PROG_LOAD, &mut attr);
+                let log = String::from_utf8_lossy(&log[..log.iter().position(|&b| b == 0).unwrap_or(0)]).into_owned();
+                Err(anyhow!("{}: {}", error, log.trim()))
+            }
+        }
+    }
+
+    fn open_tracepoint(id: u64, cpu: i32, program: &Fd) -> Result<Fd> {
+        // PERF_ATTR_SIZE_VER5; sample_period 1 so every hit runs the program.
+        let mut attr = Attr::default();
+        attr.put_u32(0, PERF_TYPE_TRACEPOINT);
+        attr.put_u32(4, 112);
+        attr.put_u64(8, id);
+        attr.put_u64(16, 1);
+        let fd = unsafe { libc::syscall(libc::SYS_perf_event_open, attr.0.as_ptr(), -1, cpu, -1, PERF_FLAG_FD_CLOEXEC) };
+        if fd < 0 {
+            return Err(io::Error::last_os_error()).context("opening a tracepoint");
+        }
+        let event = Fd(fd as i32);
+        unsafe {
+            if libc::ioctl(event.0, PERF_EVENT_IOC_SET_BPF as _, program.0) < 0 || libc::ioctl(event.0, PERF_EVENT_IOC_ENABLE as _, 0) < 0 {
+                return Err(io::Error::last_os_error()).context("attaching a BPF program");
+            }
+        }
+        Ok(event)
+    }
+
+    // ---- The programs ----
+
+    /// One `struct bpf_insn`.
+    #[repr(C)]
+    #[derive(Debug, Clone, Copy)]
+    struct Insn {
+        code: u8,
+        regs: u8,
+        off: i16,
+        imm: i32,
+    }
+
+    const R0: u8 = 0;
+    const R1: u8 = 1;
+    const R2: u8 = 2;
+    const R3: u8 = 3;
+    const R4: u8 = 4;
+    const R6: u8 = 6;
+    const R7: u8 = 7;
+    const R8: u8 = 8;
+    const R9: u8 = 9;
+    const R10: u8 = 10;
+
+    // Opcodes: class | size/op | source.
+    const LDX_H: u8 = 0x69;
+    const LDX_DW: u8 = 0x79;
+    const ST_W: u8 = 0x62;
+    const STX_W: u8 = 0x63;
+    const STX_DW: u8 = 0x7b;
+    const ADD_K: u8 = 0x07;
+    const ADD_X: u8 = 0x0f;
+    const SUB_X: u8 = 0x1f;
+    const RSH_K: u8 = 0x77;
+    const MOV_K: u8 = 0xb7;
+    const MOV_X: u8 = 0xbf;
+    const JEQ_K: u8 = 0x15;
+    const JGE_X: u8 = 0x3d;
+    const JNE_K: u8 = 0x55;
+    const JSLT_K: u8 = 0xc5;
+    const CALL: u8 = 0x85;
+    const EXIT: u8 = 0x95;
+    const LD_DW_IMM: u8 = 0x18;
+    const PSEUDO_MAP_FD: u8 = 1;
+
+    /// Builds a program; every jump goes forward to the single `out` label,
+    /// which returns 0.
+    #[derive(Default)]
+    struct Asm {
+        insns: Vec<Insn>,
+        jumps_to_out: Vec<usize>,
+    }
+
+    impl Asm {
+        fn op(&mut self, code: u8, dst: u8, src: u8, off: i16, imm: i32) -> &mut Self {
+            self.insns.push(Insn {
+                code,
+                regs: dst | (src << 4),
+                off,
+                imm,
+            });
+            self
+        }
+
+        fn jump_out(&mut self, code: u8, dst: u8, src: u8, imm: i32) -> &mut Self {
+            self.jumps_to_out.push(self.insns.len());
+            self.op(code, dst, src, 0, imm)
+        }
+
+        fn call(&mut self, helper: i32) -> &mut Self {
+            self.op(CALL, 0, 0, 0, helper)
+        }
+
+        fn load_map(&mut self, dst: u8, map_fd: i32) -> &mut Self {
+            self.op(LD_DW_IMM, dst, PSEUDO_MAP_FD, 0, map_fd).op(0, 0, 0, 0, 0)
+        }
+
+        /// r2 = r10 + offset, the address of a stack slot.
+        fn stack_address(&mut self, dst: u8, offset: i32) -> &mut Self {
+            self.op(MOV_X, dst, R10, 0, 0).op(ADD_K, dst, 0, 0, offset)
+        }
+
+        /// r0 = &stats (leaving if the lookup fails).
+        fn lookup_stats(&mut self, stats_fd: i32) -> &mut Self {
+            self.op(ST_W, R10, 0, -8, 0)
+                .stack_address(R2, -8)
+                .load_map(R1, stats_fd)
+                .call(HELPER_MAP_LOOKUP)
+                .jump_out(JEQ_K, R0, 0, 0)
+        }
+
+        fn increment(&mut self, slot: i16) -> &mut Self {
+            self.op(LDX_DW, R1, R0, slot * 8, 0)
+                .op(ADD_K, R1, 0, 0, 1)
+                .op(STX_DW, R0, R1, slot * 8, 0)
+        }
+
+        /// Leaves unless the current process is `pid`, keeping pid_tgid in r7.
+        fn only_pid(&mut self, pid: i32) -> &mut Self {
+            self.call(HELPER_GET_CURRENT_PID_TGID)
+                .op(MOV_X, R7, R0, 0, 0)
+                .op(RSH_K, R0, 0, 0, 32)
+                .jump_out(JNE_K, R0, 0, pid)
+        }
+
+        fn finish(mut self) -> Vec<Insn> {
+            let out = self.insns.len();
+            for at in std::mem::take(&mut self.jumps_to_out) {
+                self.insns[at].off = (out - at - 1) as i16;
+            }
+            self.op(MOV_K, R0, 0, 0, 0).op(EXIT, 0, 0, 0, 0);
+            self.insns
+        }
+    }
+
+    fn accept_program(pid: i32, ret_offset: i16, stats_fd: i32) -> Vec<Insn> {
+        let mut asm = Asm::default();
+        asm.op(MOV_X, R6, R1, 0, 0)
+            .only_pid(pid)
+            .op(LDX_DW, R1, R6, ret_offset, 0)
+            .jump_out(JSLT_K, R1, 0, 0)
+            .lookup_stats(stats_fd)
+            .increment(ACCEPTS);
+        asm.finish()
+    }
+
+    fn retransmit_program(port: u16, sport_offset: i16, stats_fd: i32) -> Vec<Insn> {
+        let mut asm = Asm::default();
+        asm.op(LDX_H, R1, R1, sport_offset, 0)
+            .jump_out(JNE_K, R1, 0, port as i32)
+            .lookup_stats(stats_fd)
+            .increment(RETRANSMITS);
+        asm.finish()
+    }
+
+    /// Counts the process's syscalls by category and notes when each of its
+    /// threads entered one.
+    fn enter_program(pid: i32, id_offset: i16, starts_fd: i32, stats_fd: i32) -> Vec<Insn> {
+        let mut asm = Asm::default();
+        asm.op(MOV_X, R6, R1, 0, 0)
+            .only_pid(pid)
+            .op(LDX_DW, R9, R6, id_offset, 0)
+            .op(MOV_K, R8, 0, 0, OTHER_CALLS as i32);
+        // r8 = the slot for this syscall, one "skip the next instruction if not" at a time.
+        for (category, slot) in CATEGORIES {
+            for &id in category.numbers() {
+                asm.op(JNE_K, R9, 0, 1, id as i32).op(MOV_K, R8, 0, 0, slot as i32);
+            }
+        }
+        asm.lookup_stats(stats_fd);
+        for slot in READ_CALLS..=OTHER_CALLS {
+            asm.op(JNE_K, R8, 0, 3, slot as i32).increment(slot);
+        }
+        asm.op(MOV_X, R1, R9, 0, 0);
+        for &id in WAITING_SYSCALLS {
+            asm.jump_out(JEQ_K, R1, 0, id);
+        }
+        asm.call(HELPER_KTIME_GET_NS)
+            .op(STX_DW, R10, R0, -16, 0)
+            .op(STX_W, R10, R7, -4, 0)
+            .load_map(R1, starts_fd)
+            .stack_address(R2, -4)
+            .stack_address(R3, -16)
+            .op(MOV_K, R4, 0, 0, 0)
+            .call(HELPER_MAP_UPDATE);
+        asm.finish()
+    }
+
+    /// Adds the time since the matching `enter_program` to the totals.
+    fn exit_program(pid: i32, starts_fd: i32, stats_fd: i32) -> Vec<Insn> {
+        let mut asm = Asm::default();
+        asm.only_pid(pid)
+            .op(STX_W, R10, R7, -4, 0)
+            .load_map(R1, starts_fd)
+            .stack_address(R2, -4)
+            .call(HELPER_MAP_LOOKUP)
+            .jump_out(JEQ_K, R0, 0, 0)
+            .op(LDX_DW, R8, R0, 0, 0)
+            .call(HELPER_KTIME_GET_NS)
+            .op(SUB_X, R0, R8, 0, 0)
+            .op(MOV_X, R8, R0, 0, 0)
+            .load_map(R1, starts_fd)
+            .stack_address(R2, -4)
+            .call(HELPER_MAP_DELETE)
+            .lookup_stats(stats_fd)
+            .increment(SYSCALLS)
+            .op(LDX_DW, R1, R0, SYSCALL_NS * 8, 0)
+            .op(ADD_X, R1, R8, 0, 0)
+            .op(STX_DW, R0, R1, SYSCALL_NS * 8, 0)
+            .op(LDX_DW, R1, R0, SYSCALL_MAX_NS * 8, 0)
+            .jump_out(JGE_X, R1, R8, 0)
+            .op(STX_DW, R0, R8, SYSCALL_MAX_NS * 8, 0);
+        asm.finish()
+    }
+}
@@ -0,0 +1,363 @@
+//! Alerts by email, for servers where nobody reads a chat webhook.
+//!
+//! SMTP is spoken here: STARTTLS (port 587) or implicit TLS (465) through
+//! the same rustls stack `tls` uses, `AUTH PLAIN` when a username is set,
+//! and one plain-text message per alert. Alerts below `min_severity` are
+//! skipped, and a rule that fires again for the same target within the
+//! cooldown is held back; the next message that goes out says how many
+//! were.
+
+use std::collections::HashMap;
+use std::fmt::Write as _;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use anyhow::{anyhow, bail, Context, Result};
+use chrono::{DateTime, Local};
+use serde::Deserialize;
+use tokio::io::{AsyncBufReadExt, AsyncRead, AsyncWrite, AsyncWriteExt, BufReader};
+use tokio::net::TcpStream;
+use tokio::runtime::{Handle, RuntimeFlavor};
+use tokio::task::JoinHandle;
+
+use crate::export::{Alert, ExportSample, Exporter};
+use crate::syslog::Severity;
+
+/// How the connection to the server is secured.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Security {
+    /// Plain TCP upgraded with `STARTTLS`, as on port 587.
+    #[default]
+    Starttls,
+    /// TLS from the first byte, as on port 465.
+    Tls,
+    /// No TLS at all; only for a relay on localhost or a trusted network.
+    None,
+}
+
+/// Where and how alerts are mailed.
+#[derive(Debug, Clone)]
+pub struct Mailer {
+    /// `host:port`.
+    pub server: String,
+    pub security: Security,
+    /// Username and password for `AUTH PLAIN`, when the server wants them.
+    pub login: Option<(String, String)>,
+    pub from: String,
+    pub to: Vec<String>,
+}
+
+/// Where the password is looked up when `password_env` isn't set.
+pub const DEFAULT_PASSWORD_ENV: &str = "CRABTOP_SMTP_PASSWORD";
+
+/// The `AUTH PLAIN` login for `username`, with the password from
+/// `$password_env` (or `$CRABTOP_SMTP_PASSWORD`).
+pub fn login(username: Option<&str>, password_env: Option<&str>) -> Result<Option<(String, String)>> {
+    let Some(user) = username else { return Ok(None) };
+    let env = password_env.unwrap_or(DEFAULT_PASSWORD_ENV);
+    let password = std::env::var(env).with_context(|| format!("The email exporter's password goes in ${}", env))?;
+    Ok(Some((user.to_string(), password)))
+}
+
+/// How long shutdown waits for messages still being sent.
+const SEND_DRAIN: Duration = Duration::from_secs(10);
+const TIMEOUT: Duration = Duration::from_secs(30);
+
+/// Which alerts are worth a message: severe enough, and not a repeat
+/// within `cooldown` of the last one for that rule and target.
+#[derive(Debug, Clone)]
+pub struct AlertFilter {
+    min_severity: Severity,
+    cooldown: Duration,
+    /// (host, port, rule) → when it was last mailed, and how many have been held back since.
+    last: HashMap<(Option<String>, u16, String), (Instant, u64)>,
+}
+
+impl AlertFilter {
+    pub fn new(min_severity: Severity, cooldown: Duration) -> Self {
+        Self {
+            min_severity,
+            cooldown,
+            last: HashMap::new(),
+        }
+    }
+
+    /// `Some(held back)` if `alert` should be mailed now, counting the
+    /// repeats held back since the last message for it.
+    pub fn admit(&mut self, alert: &Alert, now: Instant) -> Option<u64> {
+        if alert.severity > self.min_severity {
+            return None;
+        }
+        let key = (alert.host.clone(), alert.port, alert.rule.clone());
+        match self.last.get_mut(&key) {
+            Some((sent, held)) if now.duration_since(*sent) < self.cooldown => {
+                *held += 1;
+                None
+            }
+            Some((sent, held)) => {
+                *sent = now;
+                Some(std::mem::take(held))
+            }
+            None => {
+                self.last.insert(key, (now, 0));
+                Some(0)
+            }
+        }
+    }
+}
+
+/// The message for one alert, headers and all, with CRLF line endings.
+pub fn message(alert: &Alert, held_back: u64, from: &str, to: &[String], now: DateTime<Local>) -> String {
+    let target = match &alert.host {
+        Some(host) => format!("{} port {}", host, alert.port),
+        None => format!("port {}", alert.port),
+    };
+    let severity = format!("{:?}", alert.severity).to_lowercase();
+    let mut text = String::new();
+    let _ = write!(text, "From: {}\r\nTo: {}\r\n", from, to.join(", "));
+    // Only the first line, so a message can't add headers of its own.
+    let summary = alert.message.lines().next().unwrap_or_default();
+    let _ = write!(text, "Subject: [crabtop] {}: {}\r\n", target, summary);
+    let _ = write!(text, "Date: {}\r\n", now.to_rfc2822());
+    let _ = write!(
+        text,
+        "Message-ID: <crabtop.{}.{}.{}@{}>\r\n",
+        alert.rule,
+        alert.port,
+        now.timestamp_millis(),
+        address(from).rsplit('@').next().unwrap_or("localhost")
+    );
+    text.push_str("MIME-Version: 1.0\r\nContent-Type: text/plain; charset=utf-8\r\nContent-Transfer-Encoding: 8bit\r\n\r\n");
+    for line in alert.message.lines() {
+        let _ = write!(text, "{}\r\n", line);
+    }
+    text.push_str("\r\n");
+    let _ = write!(text, "Target:   {}\r\n", target);
+    let _ = write!(text, "Rule:     {} ({})\r\n", alert.rule, severity);
+    let _ = write!(text, "Value:    {}\r\n", alert.value);
+    let _ = write!(text, "Raised:   {}\r\n", alert.timestamp.format("%Y-%m-%d %H:%M:%S %Z"));
+    if held_back > 0 {
+        let _ = write!(
+            text,
+            "\r\n{} more {} alert{} for this target {} held back by the cooldown since the last message.\r\n",
+            held_back,
+            alert.rule,
+            if held_back == 1 { "" } else { "s" },
+            if held_back == 1 { "was" } else { "were" }
+        );
+    }
+    text
+}
+
+/// `alerts@example.com` out of `crabtop <alerts@example.com>`.
+fn address(mailbox: &str) -> &str {
+    match (mailbox.rfind('<'), mailbox.rfind('>')) {
+        (Some(start), Some(end)) if start < end => &mailbox[start + 1..end],
+        _ => mailbox.trim(),
+    }
+}
+
+impl Mailer {
+    /// Delivers `message` to every recipient in one SMTP session.
+    pub async fn send(&self, message: &str) -> Result<()> {
+        tokio::time::timeout(TIMEOUT, self.deliver(message))
+            .await
+            .map_err(|_| anyhow!("{} didn't finish within {}s", self.server, TIMEOUT.as_secs()))?
+    }
+
+    async fn deliver(&self, message: &str) -> Result<()> {
+        let tcp = TcpStream::connect(&self.server)
+            .await
+            .with_context(|| format!("Failed to connect to {}", self.server))?;
+        match self.security {
+            Security::Tls => {
+                let tls = self.tls(tcp).await?;
+                let mut smtp = BufReader::new(tls);
+                reply(&mut smtp, 220).await?;
+                self.session(&mut smtp, message).await
+            }
+            Security::Starttls => {
+                let mut smtp = BufReader::new(tcp);
+                reply(&mut smtp, 220).await?;
+                command(&mut smtp, &format!("EHLO {}", helo_name()), 250).await?;
+                command(&mut smtp, "STARTTLS", 220)
+                    .await
+                    .context("The server doesn't offer STARTTLS (use security = \"tls\" for port 465)")?;
+                let tls = self.tls(smtp.into_inner()).await?;
+                self.session(&mut BufReader::new(tls), message).await
+            }
+            Security::None => {
+                let mut smtp = BufReader::new(tcp);
+                reply(&mut smtp, 220).await?;
+                self.session(&mut smtp, message).await
+            }
+        }
+    }
+
+    async fn tls(&self, tcp: TcpStream) -> Result<tokio_rustls::client::TlsStream<TcpStream>> {
+        use rustls::pki_types::ServerName;
+
+        let host = self.server.rsplit_once(':').map_or(self.server.as_str(), |(host, _)| host);
+        let name = ServerName::try_from(host.to_string()).with_context(|| format!("Invalid TLS server name {}", host))?;
+        let roots = rustls::RootCertStore {
+            roots: webpki_roots::TLS_SERVER_ROOTS.to_vec(),
+        };
+        let config = rustls::ClientConfig::builder_with_provider(Arc::new(rustls::crypto::ring::default_provider()))
+            .with_safe_default_protocol_versions()
+            .context("Failed to set up TLS")?
+            .with_root_certificates(roots)
+            .with_no_client_auth();
+        tokio_rustls::TlsConnector::from(Arc::new(config))
+            .connect(name, tcp)
+            .await
+            .with_context(|| format!("TLS handshake with {} failed", self.server))
+    }
+
+    /// Everything after the greeting (and STARTTLS): EHLO, AUTH, the envelope and the message.
+    async fn session<S: AsyncRead + AsyncWrite + Unpin>(&self, smtp: &mut BufReader<S>, message: &str) -> Result<()> {
+        command(smtp, &format!("EHLO {}", helo_name()), 250).await?;
+        if let Some((user, password)) = &self.login {
+            let credentials = base64(format!("\0{}\0{}", user, password).as_bytes());
+            command(smtp, &format!("AUTH PLAIN {}", credentials), 235)
+                .await
+                .context("The server refused the login")?;
+        }
+        command(smtp, &format!("MAIL FROM:<{}>", address(&self.from)), 250).await?;
+        for to in &self.to {
+            command(smtp, &format!("RCPT TO:<{}>", address(to)), 250)
+                .await
+                .with_context(|| format!("The server won't deliver to {}", to))?;
+        }
+        command(smtp, "DATA", 354).await?;
+        // Dot-stuffing: a line starting with '.' gets another.
+        let mut body = String::with_capacity(message.len() + 8);
+        for line in message.split("\r\n") {
+            if line.starts_with('.') {
+                body.push('.');
+            }
+            body.push_str(line);
+            body.push_str("\r\n");
+        }
+        body.push_str(".\r\n");
+        smtp.get_mut().write_all(body.as_bytes()).await?;
+        reply(smtp, 250).await.context("The server didn't accept the message")?;
+        let _ = command(smtp, "QUIT", 221).await;
+        Ok(())
+    }
+}
+
+fn helo_name() -> String {
+    sysinfo::System::host_name().filter(|name| !name.is_empty()).unwrap_or_else(|| "localhost".to_string())
+}
+
+async fn command<S: AsyncRead + AsyncWrite + Unpin>(smtp: &mut BufReader<S>, line: &str, expect: u16) -> Result<()> {
+    smtp.get_mut().write_all(format!("{}\r\n", line).as_bytes()).await?;
+    smtp.get_mut().flush().await?;
+    reply(smtp, expect).await
+}
+
+/// Reads a (possibly multi-line) reply and checks its code.
+async fn reply<S: AsyncRead + Unpin>(smtp: &mut BufReader<S>, expect: u16) -> Result<()> {
+    loop {
+        let mut line = String::new();
+        if smtp.read_line(&mut line).await? == 0 {
+            bail!("The server closed the connection");
+        }
+        let line = line.trim_end();
+        let code: u16 = line.get(..3).and_then(|code| code.parse().ok()).context("Not an SMTP reply")?;
+        // "250-..." is continued, "250 ..." is the last line.
+        if line.as_bytes().get(3) == Some(&b'-') {
+            continue;
+        }
+        if code != expect {
+            bail!("The server said {}", line);
+        }
+        return Ok(());
+    }
+}
+
+fn base64(data: &[u8]) -> String {
+    const ALPHABET: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+    let mut out = String::with_capacity(data.len().div_ceil(3) * 4);
+    for chunk in data.chunks(3) {
+        let n = chunk.iter().enumerate().fold(0u32, |n, (i, byte)| n | (*byte as u32) << (16 - 8 * i));
+        for i in 0..4 {
+            if i <= chunk.len() {
+                out.push(ALPHABET[(n >> (18 - 6 * i) & 63) as usize] as char);
+            } else {
+                out.push('=');
+            }
+        }
+    }
+    out
+}
+
+/// Mails each alert that gets through the filter, in the background.
+pub struct EmailExporter {
+    mailer: Arc<Mailer>,
+    filter: AlertFilter,
+    sending: Vec<JoinHandle<()>>,
+    last_error: Arc<Mutex<Option<String>>>,
+}
+
+impl EmailExporter {
+    /// Must be called inside a Tokio runtime.
+    pub fn new(mailer: Mailer, filter: AlertFilter) -> Result<Self> {
+        if mailer.to.is_empty() {
+            bail!("The email exporter needs at least one address in `to`");
+        }
+        Ok(Self {
+            mailer: Arc::new(mailer),
+            filter,
+            sending: Vec::new(),
+            last_error: Arc::default(),
+        })
+    }
+
+    fn take_error(&self) -> Result<()> {
+        match self.last_error.lock().unwrap().take() {
+            Some(message) => Err(anyhow!(message)),
+            None => Ok(()),
+        }
+    }
+}
+
+impl Exporter for EmailExporter {
+    fn name(&self) -> &str {
+        "email"
+    }
+
+    fn on_sample(&mut self, _sample: &ExportSample) -> Result<()> {
+        self.take_error()
+    }
+
+    fn on_alert(&mut self, alert: &Alert) -> Result<()> {
+        self.sending.retain(|task| !task.is_finished());
+        if let Some(held_back) = self.filter.admit(alert, Instant::now()) {
+            let text = message(alert, held_back, &self.mailer.from, &self.mailer.to, Local::now());
+            let mailer = Arc::clone(&self.mailer);
+            let last_error = Arc::clone(&self.last_error);
+            self.sending.push(tokio::spawn(async move {
+                if let Err(err) = mailer.send(&text).await {
+                    *last_error.lock().unwrap() = Some(format!("{:#}", err));
+                }
+            }));
+        }
+        self.take_error()
+    }
+
+    fn on_shutdown(&mut self) -> Result<()> {
+        let sending = std::mem::take(&mut self.sending);
+        // An alert raised as watching stops (the process exiting) is the one most worth waiting for.
+        if let Ok(handle) = Handle::try_current() {
+            if handle.runtime_flavor() == RuntimeFlavor::MultiThread && !sending.is_empty() {
+                let all = futures_util::future::join_all(sending);
+                if tokio::task::block_in_place(|| handle.block_on(tokio::time::timeout(SEND_DRAIN, all))).is_err() {
+                    bail!("the last alerts weren't mailed within {}s", SEND_DRAIN.as_secs());
+                }
+            }
+        }
+        self.take_error()
+    }
+}
@@ -0,0 +1,69 @@
+//! A process's environment, from `/proc/<pid>/environ`, with the values of
+//! secret-looking variables masked unless asked otherwise.
+
+use anyhow::{anyhow, Context, Result};
+use serde::Serialize;
+
+use crate::exec::Runner;
+use crate::redact::{is_secret_name, REDACTED};
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct EnvVar {
+    pub name: String,
+    pub value: String,
+    /// The real value was replaced with `[REDACTED]`.
+    pub masked: bool,
+}
+
+/// Splits the NUL-separated `NAME=value` entries of an environ file, sorted
+/// by name. Secret values are masked unless `show_secrets`.
+pub fn parse_environ(raw: &[u8], show_secrets: bool) -> Vec<EnvVar> {
+    let mut vars: Vec<EnvVar> = raw
+        .split(|&b| b == 0)
+        .filter(|entry| !entry.is_empty())
+        .map(|entry| {
+            let entry = String::from_utf8_lossy(entry);
+            let (name, value) = entry.split_once('=').unwrap_or((&entry, ""));
+            let masked = !show_secrets && !value.is_empty() && is_secret_name(name);
+            EnvVar {
+                name: name.to_string(),
+                value: if masked { REDACTED.to_string() } else { value.to_string() },
+                masked,
+            }
+        })
+        .collect();
+    vars.sort_by(|a, b| a.name.cmp(&b.name));
+    vars
+}
+
+/// The raw environ of `pid` on this machine. Only the owner or root may read it.
+#[cfg(target_os = "linux")]
+pub fn read_environ(pid: u32) -> Result<Vec<u8>> {
+    std::fs::read(format!("/proc/{}/environ", pid)).with_context(|| format!("Cannot read the environment of PID {}", pid))
+}
+
+#[cfg(not(target_os = "linux"))]
+pub fn read_environ(pid: u32) -> Result<Vec<u8>> {
+    use sysinfo::{Pid, System};
+
+    let mut system = System::new();
+    system.refresh_process(Pid::from_u32(pid));
+    let process = system.process(Pid::from_u32(pid)).with_context(|| format!("No process with PID {}", pid))?;
+    Ok(process.environ().join("\0").into_bytes())
+}
+
+/// The same, read on a remote host.
+pub fn read_environ_on(runner: &Runner, pid: u32) -> Result<Vec<u8>> {
+    let out = runner
+        .output("cat", &[&format!("/proc/{}/environ", pid)])
+        .with_context(|| format!("Failed to run cat{}", runner.describe()))?;
+    if !out.status.success() {
+        return Err(anyhow!(
+            "Cannot read the environment of PID {}{}: {}",
+            pid,
+            runner.describe(),
+            String::from_utf8_lossy(&out.stderr).trim()
+        ));
+    }
+    Ok(out.stdout)
+}
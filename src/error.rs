@@ -0,0 +1,123 @@
+//! The failures a script may want to tell apart, e.g. "nothing listens on
+//! that port" from "lsof isn't installed". They travel inside `anyhow`
+//! errors like any other, so context can still be added on the way up;
+//! `main` looks for one in the chain to pick the exit code and, with
+//! `--format json`, writes it out as JSON.
+//!
+//! The exit codes are a contract with wrappers and CI jobs, so they don't
+//! change once given out:
+//!
+//! | Code | Meaning |
+//! |------|---------|
+//! | 0    | Success |
+//! | 1    | Any error without a code of its own |
+//! | 2    | A threshold was breached (`baseline check`) |
+//! | 3    | Nothing listens on the port |
+//! | 4    | Permission denied |
+//! | 5    | The process exited (during watch mode, or before it was read) |
+//! | 6    | A lookup tool (`lsof`, `sudo`…) is missing |
+//! | 7    | An HTTP API answered with an error |
+//! | 64   | Bad command-line arguments (`EX_USAGE`) |
+
+use std::fmt;
+
+use serde::Serialize;
+use serde_json::{json, Value};
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum Error {
+    /// Figures went past their limits, e.g. in `baseline check`.
+    ThresholdBreached { breached: usize, checked: usize },
+    /// Nothing listens on the port.
+    PortNotFound { port: u16 },
+    /// Something listens on `port`, or `pid` exists, but this user isn't
+    /// allowed to see whose it is or read it.
+    PermissionDenied {
+        #[serde(skip_serializing_if = "Option::is_none")]
+        port: Option<u16>,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        pid: Option<u32>,
+    },
+    /// A program crabtop runs (lsof, sudo…) couldn't be started.
+    ToolMissing { tool: String },
+    /// The process exited, or was never there.
+    ProcessGone { pid: u32 },
+    /// An HTTP API (a `serve` host, an AI provider) answered with an error.
+    ApiError { service: String, status: u16, message: String },
+}
+
+impl Error {
+    /// Stable name for scripts, as in the JSON `kind`.
+    pub fn kind(&self) -> &'static str {
+        match self {
+            Error::ThresholdBreached { .. } => "threshold_breached",
+            Error::PortNotFound { .. } => "port_not_found",
+            Error::PermissionDenied { .. } => "permission_denied",
+            Error::ToolMissing { .. } => "tool_missing",
+            Error::ProcessGone { .. } => "process_gone",
+            Error::ApiError { .. } => "api_error",
+        }
+    }
+
+    /// See the table above; 1 is left for every other error.
+    pub fn exit_code(&self) -> i32 {
+        match self {
+            Error::ThresholdBreached { .. } => 2,
+            Error::PortNotFound { .. } => 3,
+            Error::PermissionDenied { .. } => 4,
+            Error::ProcessGone { .. } => 5,
+            Error::ToolMissing { .. } => 6,
+            Error::ApiError { .. } => 7,
+        }
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::ThresholdBreached { breached, checked } => {
+                write!(f, "{} of {} figures exceeded the baseline", breached, checked)
+            }
+            Error::PortNotFound { port } => write!(f, "No process found listening on port {}", port),
+            Error::PermissionDenied { port: Some(port), .. } => write!(
+                f,
+                "Port {} is in use, but by a process this user isn't allowed to see (another user's, or root's)",
+                port
+            ),
+            Error::PermissionDenied { pid: Some(pid), .. } => write!(f, "Access denied reading PID {}", pid),
+            Error::PermissionDenied { .. } => write!(f, "Permission denied"),
+            Error::ToolMissing { tool } => write!(f, "`{}` isn't installed (or isn't on PATH)", tool),
+            Error::ProcessGone { pid } => write!(f, "Failed to read process info for PID {}; it has exited", pid),
+            Error::ApiError { service, status, message } => write!(f, "{} returned {}: {}", service, status, message),
+        }
+    }
+}
+
+impl std::error::Error for Error {}
+
+/// The first `Error` in `err`'s chain, if it has one.
+pub fn find(err: &anyhow::Error) -> Option<&Error> {
+    err.chain().find_map(|cause| cause.downcast_ref::<Error>())
+}
+
+/// Command-line arguments clap rejected; 2, its own choice, is taken.
+pub const USAGE_EXIT_CODE: i32 = 64;
+
+/// What the process exits with after `err`.
+pub fn exit_code(err: &anyhow::Error) -> i32 {
+    find(err).map_or(1, Error::exit_code)
+}
+
+/// `{"error": {"kind": ..., "message": ..., "exit_code": ..., ...}}`, with the
+/// typed error's fields (`port`, `tool`…) when there is one and `"kind": "other"`
+/// when there isn't. `message` is what the text output would say.
+pub fn to_json(err: &anyhow::Error) -> Value {
+    let mut fields = match find(err) {
+        Some(typed) => serde_json::to_value(typed).unwrap_or_else(|_| json!({ "kind": typed.kind() })),
+        None => json!({ "kind": "other" }),
+    };
+    fields["message"] = Value::String(err.to_string());
+    fields["exit_code"] = exit_code(err).into();
+    json!({ "error": fields })
+}
@@ -0,0 +1,117 @@
+//! Running the external tools we lean on (lsof, ss, netstat…) either locally
+//! or on a remote host over SSH.
+
+use std::io;
+use std::process::{Command, Output};
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub enum Runner {
+    #[default]
+    Local,
+    /// `user@host` (or any ssh destination, including `~/.ssh/config` aliases).
+    Ssh(String),
+    /// This machine, with every command run as root through `sudo` or `pkexec`.
+    Elevated(Elevation),
+}
+
+/// How `--sudo` gets root for port lookups.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+pub enum Elevation {
+    Sudo,
+    Pkexec,
+}
+
+impl Elevation {
+    pub fn program(&self) -> &'static str {
+        match self {
+            Elevation::Sudo => "sudo",
+            Elevation::Pkexec => "pkexec",
+        }
+    }
+}
+
+impl Runner {
+    pub fn is_remote(&self) -> bool {
+        matches!(self, Runner::Ssh(_))
+    }
+
+    /// Runs `program` with `args` and captures its output.
+    pub fn output(&self, program: &str, args: &[&str]) -> io::Result<Output> {
+        let output = match self {
+            Runner::Local => Command::new(program).args(args).output(),
+            // A password prompt goes to the terminal; stdin and the tty aren't captured.
+            Runner::Elevated(elevation) => Command::new(elevation.program()).arg(program).args(args).output(),
+            Runner::Ssh(_) => {
+                let mut words = vec![shell_quote(program)];
+                words.extend(args.iter().map(|a| shell_quote(a)));
+                return self.shell(&words.join(" "));
+            }
+        };
+        self.trace(&format!("{} {}", program, args.join(" ")), &output);
+        output
+    }
+
+    /// Runs a `sh` script and captures its output.
+    pub fn shell(&self, script: &str) -> io::Result<Output> {
+        let output = match self {
+            Runner::Local => Command::new("sh").args(["-c", script]).output(),
+            Runner::Elevated(elevation) => Command::new(elevation.program()).args(["sh", "-c", script]).output(),
+            Runner::Ssh(target) => Command::new("ssh")
+                .args([
+                    // never prompt; reuse one connection across the many small commands we run
+                    "-o",
+                    "BatchMode=yes",
+                    "-o",
+                    "ControlMaster=auto",
+                    "-o",
+                    "ControlPath=/tmp/crabtop-ssh-%C",
+                    "-o",
+                    "ControlPersist=60",
+                    target,
+                    "--",
+                    script,
+                ])
+                .output(),
+        };
+        self.trace(script, &output);
+        output
+    }
+
+    /// What `-vvv` shows of each command: how it exited and how much it printed.
+    fn trace(&self, command: &str, output: &io::Result<Output>) {
+        match output {
+            Ok(out) => {
+                let stderr = String::from_utf8_lossy(&out.stderr);
+                let stderr = stderr.trim();
+                if stderr.is_empty() {
+                    tracing::trace!(status = %out.status, stdout_bytes = out.stdout.len(), "ran `{}`{}", command, self.describe());
+                } else {
+                    tracing::trace!(status = %out.status, stdout_bytes = out.stdout.len(), stderr, "ran `{}`{}", command, self.describe());
+                }
+            }
+            Err(err) => tracing::trace!("couldn't run `{}`{}: {}", command, self.describe(), err),
+        }
+    }
+
+    /// Suffix for error messages, e.g. " on deploy@web-1".
+    pub fn describe(&self) -> String {
+        match self {
+            Runner::Local => String::new(),
+            Runner::Ssh(target) => format!(" on {}", target),
+            Runner::Elevated(elevation) => format!(" through {}", elevation.program()),
+        }
+    }
+}
+
+/// Quotes `word` for a POSIX shell unless it is plainly safe.
+pub fn shell_quote(word: &str) -> String {
+    let safe = !word.is_empty()
+        && word
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || "-_./:=@,+%".contains(c));
+    if safe {
+        word.to_string()
+    } else {
+        format!("'{}'", word.replace('\'', r"'\''"))
+    }
+}
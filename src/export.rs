@@ -0,0 +1,534 @@
+//! Sinks for watch-mode samples and alerts.
+//!
+//! Every backend implements `Exporter`; the watch loop only talks to an
+//! `ExporterRegistry`, which fans each event out to all configured sinks and
+//! keeps a failing one from affecting the others or the dashboard.
+
+use std::collections::BTreeMap;
+use std::io::Write;
+use std::path::Path;
+use std::sync::mpsc::{self, Receiver, RecvTimeoutError, SyncSender};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use anyhow::{anyhow, bail, Context, Result};
+use chrono::{DateTime, Local};
+use serde::{Deserialize, Serialize};
+use tokio::runtime::{Handle, RuntimeFlavor};
+
+use crate::annotation::Annotation;
+use crate::config::ExporterConfig;
+use crate::incident::{IncidentExporter, Service};
+use crate::process::ProcessInfo;
+use crate::prometheus::{PrometheusExporter, PushExporter, PushProtocol, TextfileExporter};
+use crate::rotate::{RotatingFile, Rotation};
+use crate::statsd::StatsdEmitter;
+use crate::syslog::Severity;
+
+/// One sample as handed to exporters.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ExportSample {
+    pub timestamp: DateTime<Local>,
+    /// The `serve` host the sample came from, when watching remotely.
+    pub host: Option<String>,
+    pub port: u16,
+    /// Every PID behind the figures; more than one means they are aggregated.
+    pub pids: Vec<u32>,
+    pub process: ProcessInfo,
+    /// Open TCP connections on the port, when they could be counted.
+    #[serde(default)]
+    pub connections: Option<usize>,
+    /// Timeline events since the previous sample (restarts, markers, deploys...).
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub annotations: Vec<Annotation>,
+}
+
+/// Something worth telling a human about, raised against a watched port.
+#[derive(Debug, Clone, Serialize)]
+pub struct Alert {
+    pub timestamp: DateTime<Local>,
+    pub host: Option<String>,
+    pub port: u16,
+    /// Short machine-readable name, e.g. `cpu_high`.
+    pub rule: String,
+    /// `syslog::severity` of the rule for built-in alerts; the configured one for `[[rule]]`s.
+    pub severity: Severity,
+    pub message: String,
+    pub value: f64,
+}
+
+/// A sink for samples and alerts. Calls happen on the watch loop's thread, so
+/// implementations should hand slow work (network I/O) off rather than block.
+pub trait Exporter: Send {
+    /// Short name used in error reports, e.g. "csv".
+    fn name(&self) -> &str;
+
+    fn on_sample(&mut self, sample: &ExportSample) -> Result<()>;
+
+    fn on_alert(&mut self, _alert: &Alert) -> Result<()> {
+        Ok(())
+    }
+
+    /// Called when the condition behind an earlier alert with the same rule
+    /// clears, e.g. retransmits falling back under the threshold.
+    fn on_recovery(&mut self, _recovery: &Alert) -> Result<()> {
+        Ok(())
+    }
+
+    /// Called as a timeline event happens; it also rides along with the
+    /// next sample, so sinks that only keep samples needn't implement this.
+    fn on_annotation(&mut self, _annotation: &Annotation) -> Result<()> {
+        Ok(())
+    }
+
+    /// Flushes and releases resources; called once when watching stops.
+    fn on_shutdown(&mut self) -> Result<()> {
+        Ok(())
+    }
+}
+
+/// All configured exporters. Failures are counted per exporter and reported
+/// by `on_shutdown` rather than interrupting the watch loop.
+#[derive(Default)]
+pub struct ExporterRegistry {
+    exporters: Vec<Box<dyn Exporter>>,
+    /// Exporter name → (failure count, last error).
+    failures: BTreeMap<String, (u64, String)>,
+    /// Rule → the exporters its alerts go to, when not all of them.
+    routes: BTreeMap<String, Vec<String>>,
+}
+
+impl ExporterRegistry {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Builds every exporter in `configs`. Must run inside a Tokio runtime,
+    /// since the Prometheus, push and webhook exporters spawn tasks.
+    pub fn from_config(configs: &[ExporterConfig]) -> Result<Self> {
+        Self::from_config_rotating(configs, Rotation::default())
+    }
+
+    /// Like `from_config`, with file exporters rotated as `rotation` says.
+    pub fn from_config_rotating(configs: &[ExporterConfig], rotation: Rotation) -> Result<Self> {
+        let mut registry = Self::new();
+        for config in configs {
+            registry.push(build(config, rotation)?);
+        }
+        Ok(registry)
+    }
+
+    pub fn push(&mut self, exporter: Box<dyn Exporter>) {
+        self.exporters.push(exporter);
+    }
+
+    /// Sends `rule`'s alerts and recoveries only to the exporters named in
+    /// `sinks` (e.g. `email`), each of which must be configured.
+    pub fn route(&mut self, rule: &str, sinks: &[String]) -> Result<()> {
+        for sink in sinks {
+            if !self.exporters.iter().any(|exporter| exporter.name() == sink) {
+                let names: Vec<_> = self.exporters.iter().map(|exporter| exporter.name()).collect();
+                return Err(anyhow!("Rule `{}` sends to `{}`, which isn't configured (exporters: {})", rule, sink, names.join(", ")));
+            }
+        }
+        self.routes.insert(rule.to_string(), sinks.to_vec());
+        Ok(())
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.exporters.is_empty()
+    }
+
+    pub fn len(&self) -> usize {
+        self.exporters.len()
+    }
+
+    pub fn on_sample(&mut self, sample: &ExportSample) {
+        for exporter in &mut self.exporters {
+            if let Err(err) = exporter.on_sample(sample) {
+                record(&mut self.failures, exporter.name(), err);
+            }
+        }
+    }
+
+    pub fn on_alert(&mut self, alert: &Alert) {
+        let sinks = self.routes.get(&alert.rule);
+        for exporter in &mut self.exporters {
+            if sinks.is_some_and(|sinks| !sinks.iter().any(|sink| sink == exporter.name())) {
+                continue;
+            }
+            if let Err(err) = exporter.on_alert(alert) {
+                record(&mut self.failures, exporter.name(), err);
+            }
+        }
+    }
+
+    pub fn on_recovery(&mut self, recovery: &Alert) {
+        let sinks = self.routes.get(&recovery.rule);
+        for exporter in &mut self.exporters {
+            if sinks.is_some_and(|sinks| !sinks.iter().any(|sink| sink == exporter.name())) {
+                continue;
+            }
+            if let Err(err) = exporter.on_recovery(recovery) {
+                record(&mut self.failures, exporter.name(), err);
+            }
+        }
+    }
+
+    pub fn on_annotation(&mut self, annotation: &Annotation) {
+        for exporter in &mut self.exporters {
+            if let Err(err) = exporter.on_annotation(annotation) {
+                record(&mut self.failures, exporter.name(), err);
+            }
+        }
+    }
+
+    /// Shuts every exporter down and returns one line per exporter that failed
+    /// at any point, e.g. "csv failed 3 times; last error: disk full".
+    pub fn on_shutdown(&mut self) -> Vec<String> {
+        for exporter in &mut self.exporters {
+            if let Err(err) = exporter.on_shutdown() {
+                record(&mut self.failures, exporter.name(), err);
+            }
+        }
+        self.failures
+            .iter()
+            .map(|(name, (count, last))| match count {
+                1 => format!("{} failed once; last error: {}", name, last),
+                n => format!("{} failed {} times; last error: {}", name, n, last),
+            })
+            .collect()
+    }
+}
+
+fn record(failures: &mut BTreeMap<String, (u64, String)>, name: &str, err: anyhow::Error) {
+    let entry = failures.entry(name.to_string()).or_default();
+    entry.0 += 1;
+    entry.1 = format!("{:#}", err);
+}
+
+/// Samples waiting for a `QueuedWorker`; past this the sink is falling
+/// behind and new ones are dropped.
+const WORKER_QUEUE: usize = 16;
+
+/// How long shutdown waits for a `QueuedWorker` to send what's left.
+pub const WORKER_DRAIN: Duration = Duration::from_secs(5);
+
+/// Where a `QueuedWorker`'s thread leaves its latest failure.
+pub type LastError = Arc<Mutex<Option<String>>>;
+
+/// Sends samples from a background thread, in order, for exporters whose
+/// every send goes over the network. `send` never waits: a full queue drops
+/// the sample. A failure is reported with the next sample, and shutdown
+/// gives the worker `WORKER_DRAIN` to finish.
+pub struct QueuedWorker {
+    queue: Option<SyncSender<ExportSample>>,
+    /// Disconnected once the worker has returned.
+    finished: Receiver<()>,
+    last_error: LastError,
+    /// What falls behind when the queue is full, e.g. "the broker".
+    sink: &'static str,
+}
+
+impl QueuedWorker {
+    /// Starts `work` on a thread named `crabtop-<name>`. It gets the queue,
+    /// which it should read until it closes, and the slot for its failures.
+    pub fn spawn<F>(name: &str, sink: &'static str, work: F) -> Result<Self>
+    where
+        F: FnOnce(Receiver<ExportSample>, LastError) + Send + 'static,
+    {
+        let (queue, samples) = mpsc::sync_channel(WORKER_QUEUE);
+        let (done, finished) = mpsc::channel::<()>();
+        let last_error: LastError = Arc::default();
+        let errors = Arc::clone(&last_error);
+        std::thread::Builder::new()
+            .name(format!("crabtop-{}", name))
+            .spawn(move || {
+                let _done = done;
+                work(samples, errors);
+            })
+            .with_context(|| format!("Failed to start the {} exporter", name))?;
+        Ok(Self {
+            queue: Some(queue),
+            finished,
+            last_error,
+            sink,
+        })
+    }
+
+    /// Queues `sample` and reports the worker's latest failure.
+    pub fn send(&self, sample: &ExportSample) -> Result<()> {
+        if let Some(queue) = &self.queue {
+            if queue.try_send(sample.clone()).is_err() {
+                bail!("{} is falling behind; a sample was dropped", self.sink);
+            }
+        }
+        self.take_error()
+    }
+
+    /// Closes the queue, so the worker sends what's left and returns, and
+    /// waits for that up to `WORKER_DRAIN`.
+    pub fn drain(&mut self) -> Result<()> {
+        if self.queue.take().is_some() {
+            let wait = || self.finished.recv_timeout(WORKER_DRAIN);
+            // Off the runtime's worker, where a multi-threaded runtime allows it.
+            let waited = match Handle::try_current() {
+                Ok(handle) if handle.runtime_flavor() == RuntimeFlavor::MultiThread => tokio::task::block_in_place(wait),
+                _ => wait(),
+            };
+            if waited == Err(RecvTimeoutError::Timeout) {
+                bail!("the last samples weren't sent within {}s", WORKER_DRAIN.as_secs());
+            }
+        }
+        self.take_error()
+    }
+
+    fn take_error(&self) -> Result<()> {
+        match self.last_error.lock().unwrap().take() {
+            Some(message) => Err(anyhow!(message)),
+            None => Ok(()),
+        }
+    }
+}
+
+/// A key that belongs in the environment rather than the config file.
+fn secret(env: &str, exporter: &str) -> Result<String> {
+    std::env::var(env)
+        .ok()
+        .filter(|key| !key.is_empty())
+        .ok_or_else(|| anyhow!("The {} exporter needs a key in ${}", exporter, env))
+}
+
+fn build(config: &ExporterConfig, rotation: Rotation) -> Result<Box<dyn Exporter>> {
+    Ok(match config {
+        ExporterConfig::Csv { path } => Box::new(CsvExporter::rotating(path, rotation)?),
+        ExporterConfig::Prometheus { listen } => Box::new(PrometheusExporter::bind(*listen)?),
+        ExporterConfig::Textfile { dir } => Box::new(TextfileExporter::new(dir)?),
+        ExporterConfig::Pushgateway { url } => Box::new(PushExporter::new(url, PushProtocol::Pushgateway)?),
+        ExporterConfig::RemoteWrite { url } => Box::new(PushExporter::new(url, PushProtocol::RemoteWrite)?),
+        ExporterConfig::Statsd {
+            address,
+            prefix,
+            format,
+            tags,
+        } => Box::new(StatsdEmitter::connect(address, prefix, *format, tags.clone())?),
+        ExporterConfig::Webhook { url, samples } => Box::new(WebhookExporter::new(url, *samples)),
+        #[cfg(unix)]
+        ExporterConfig::Syslog { facility } => Box::new(crate::syslog::SyslogExporter::new(*facility)?),
+        #[cfg(unix)]
+        ExporterConfig::Journald { facility } => Box::new(crate::syslog::JournaldExporter::new(*facility)?),
+        #[cfg(not(unix))]
+        ExporterConfig::Syslog { .. } | ExporterConfig::Journald { .. } => {
+            return Err(anyhow!("The syslog and journald exporters need a Unix system"))
+        }
+        ExporterConfig::Email {
+            server,
+            security,
+            username,
+            password_env,
+            from,
+            to,
+            min_severity,
+            cooldown,
+        } => {
+            let mailer = crate::email::Mailer {
+                server: server.clone(),
+                security: *security,
+                login: crate::email::login(username.as_deref(), password_env.as_deref())?,
+                from: from.clone(),
+                to: to.clone(),
+            };
+            Box::new(crate::email::EmailExporter::new(mailer, crate::email::AlertFilter::new(*min_severity, *cooldown))?)
+        }
+        ExporterConfig::Pagerduty {
+            routing_key_env,
+            url,
+            sustain,
+        } => {
+            let routing_key = secret(routing_key_env, "pagerduty")?;
+            Box::new(IncidentExporter::new(Service::PagerDuty { routing_key }, url.as_deref(), *sustain))
+        }
+        ExporterConfig::Opsgenie { api_key_env, url, sustain } => {
+            let api_key = secret(api_key_env, "opsgenie")?;
+            Box::new(IncidentExporter::new(Service::Opsgenie { api_key }, url.as_deref(), *sustain))
+        }
+        #[cfg(feature = "datadog")]
+        ExporterConfig::Datadog { site, api_key_env, tags } => {
+            Box::new(crate::datadog::DatadogExporter::new(site, api_key_env, tags.clone())?)
+        }
+        #[cfg(not(feature = "datadog"))]
+        ExporterConfig::Datadog { .. } => return Err(missing_feature("datadog")),
+        #[cfg(feature = "cloudwatch")]
+        ExporterConfig::Cloudwatch { region, namespace } => {
+            Box::new(crate::cloudwatch::CloudWatchExporter::new(region.as_deref(), namespace)?)
+        }
+        #[cfg(not(feature = "cloudwatch"))]
+        ExporterConfig::Cloudwatch { .. } => return Err(missing_feature("cloudwatch")),
+        #[cfg(feature = "kafka")]
+        ExporterConfig::Kafka { brokers, topic } => Box::new(crate::kafka::KafkaExporter::new(brokers, topic)?),
+        #[cfg(not(feature = "kafka"))]
+        ExporterConfig::Kafka { .. } => return Err(missing_feature("kafka")),
+        #[cfg(feature = "nats")]
+        ExporterConfig::Nats { url, subject } => Box::new(crate::nats::NatsExporter::new(url, subject)?),
+        #[cfg(not(feature = "nats"))]
+        ExporterConfig::Nats { .. } => return Err(missing_feature("nats")),
+        #[cfg(feature = "mqtt")]
+        ExporterConfig::Mqtt {
+            url,
+            prefix,
+            discovery,
+            discovery_prefix,
+        } => {
+            let topics = crate::mqtt::Topics {
+                prefix: prefix.clone(),
+                discovery_prefix: discovery.then(|| discovery_prefix.clone()),
+            };
+            Box::new(crate::mqtt::MqttExporter::new(url, topics)?)
+        }
+        #[cfg(not(feature = "mqtt"))]
+        ExporterConfig::Mqtt { .. } => return Err(missing_feature("mqtt")),
+    })
+}
+
+/// A configured exporter this build left out.
+#[cfg(not(all(feature = "datadog", feature = "cloudwatch", feature = "kafka", feature = "nats", feature = "mqtt")))]
+fn missing_feature(feature: &str) -> anyhow::Error {
+    anyhow!(
+        "The `{}` exporter isn't in this build of crabtop; rebuild with `cargo build --features {}`",
+        feature,
+        feature
+    )
+}
+
+/// Appends one row per sample, writing the header when the file is new.
+pub struct CsvExporter {
+    writer: RotatingFile,
+}
+
+const CSV_HEADER: &str = "timestamp,host,port,pids,process,cpu_percent,memory_bytes,uptime_secs";
+
+impl CsvExporter {
+    pub fn create(path: &Path) -> Result<Self> {
+        Self::rotating(path, Rotation::default())
+    }
+
+    /// Like `create`, starting a new file (with its own header) as `rotation` says.
+    pub fn rotating(path: &Path, rotation: Rotation) -> Result<Self> {
+        let mut writer = RotatingFile::open(path, rotation, false)?;
+        if writer.is_empty() {
+            writeln!(writer, "{}", CSV_HEADER)?;
+        }
+        Ok(Self { writer })
+    }
+}
+
+impl Exporter for CsvExporter {
+    fn name(&self) -> &str {
+        "csv"
+    }
+
+    fn on_sample(&mut self, sample: &ExportSample) -> Result<()> {
+        if self.writer.rotate_if_due()? {
+            writeln!(self.writer, "{}", CSV_HEADER)?;
+        }
+        let pids: Vec<String> = sample.pids.iter().map(|p| p.to_string()).collect();
+        writeln!(
+            self.writer,
+            "{},{},{},{},{},{:.2},{},{}",
+            sample.timestamp.to_rfc3339(),
+            csv_field(sample.host.as_deref().unwrap_or_default()),
+            sample.port,
+            csv_field(&pids.join(" ")),
+            csv_field(&sample.process.name),
+            sample.process.cpu_percent,
+            sample.process.memory_bytes,
+            sample.process.uptime_secs
+        )?;
+        // Rows should be visible to `tail -f` as they arrive.
+        self.writer.flush()?;
+        Ok(())
+    }
+
+    fn on_shutdown(&mut self) -> Result<()> {
+        Ok(self.writer.flush()?)
+    }
+}
+
+/// Quotes a field per RFC 4180 when it contains a delimiter, quote or newline.
+fn csv_field(value: &str) -> String {
+    if value.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", value.replace('"', "\"\""))
+    } else {
+        value.to_string()
+    }
+}
+
+/// POSTs alerts, and optionally samples, as JSON. Requests run in the
+/// background; the most recent failure is reported on the next call.
+pub struct WebhookExporter {
+    client: reqwest::Client,
+    url: String,
+    samples: bool,
+    last_error: Arc<Mutex<Option<String>>>,
+}
+
+impl WebhookExporter {
+    pub fn new(url: &str, samples: bool) -> Self {
+        Self {
+            client: reqwest::Client::new(),
+            url: url.to_string(),
+            samples,
+            last_error: Arc::default(),
+        }
+    }
+
+    fn post<T: Serialize>(&self, kind: &str, payload: &T) -> Result<()> {
+        let body = serde_json::json!({ "kind": kind, "data": payload });
+        let request = self.client.post(&self.url).json(&body);
+        let last_error = Arc::clone(&self.last_error);
+        tokio::spawn(async move {
+            let result = match request.send().await {
+                Ok(resp) if resp.status().is_success() => None,
+                Ok(resp) => Some(format!("HTTP {}", resp.status())),
+                Err(err) => Some(err.to_string()),
+            };
+            if let Some(message) = result {
+                *last_error.lock().unwrap() = Some(message);
+            }
+        });
+        self.take_error()
+    }
+
+    fn take_error(&self) -> Result<()> {
+        match self.last_error.lock().unwrap().take() {
+            Some(message) => Err(anyhow!(message)),
+            None => Ok(()),
+        }
+    }
+}
+
+impl Exporter for WebhookExporter {
+    fn name(&self) -> &str {
+        "webhook"
+    }
+
+    fn on_sample(&mut self, sample: &ExportSample) -> Result<()> {
+        if self.samples {
+            self.post("sample", sample)
+        } else {
+            self.take_error()
+        }
+    }
+
+    fn on_alert(&mut self, alert: &Alert) -> Result<()> {
+        self.post("alert", alert)
+    }
+
+    fn on_annotation(&mut self, annotation: &Annotation) -> Result<()> {
+        self.post("annotation", annotation)
+    }
+
+    fn on_shutdown(&mut self) -> Result<()> {
+        self.take_error()
+    }
+}
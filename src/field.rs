@@ -0,0 +1,107 @@
+//! `--field` selectors for snapshot mode: single values, one per line, for
+//! shell scripts that would otherwise pipe `--format json` through `jq`.
+//!
+//! ```text
+//! if [ "$(port-inspector -p 8080 --field mem_mb)" -gt 500 ]; then ...
+//! ```
+
+use std::fmt;
+
+use crate::port::Listener;
+use crate::process::ProcessInfo;
+use crate::units::to_mb;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Field {
+    Port,
+    Pid,
+    /// Every PID followed, space-separated.
+    Pids,
+    Name,
+    User,
+    /// Percent of one core, two decimals.
+    Cpu,
+    /// Resident memory in bytes.
+    Mem,
+    /// Resident memory in whole megabytes (mebibytes with `--units binary`),
+    /// for `[ ... -gt N ]`.
+    MemMb,
+    VirtualMb,
+    SharedMb,
+    SwapMb,
+    /// Seconds running.
+    Uptime,
+    /// Seconds since the Unix epoch.
+    StartTime,
+    Cmdline,
+    Exe,
+    Cwd,
+    /// Addresses the process listens on, space-separated.
+    Listeners,
+}
+
+const FIELDS: [(&str, Field); 17] = [
+    ("port", Field::Port),
+    ("pid", Field::Pid),
+    ("pids", Field::Pids),
+    ("name", Field::Name),
+    ("user", Field::User),
+    ("cpu", Field::Cpu),
+    ("mem", Field::Mem),
+    ("mem_mb", Field::MemMb),
+    ("virtual_mb", Field::VirtualMb),
+    ("shared_mb", Field::SharedMb),
+    ("swap_mb", Field::SwapMb),
+    ("uptime", Field::Uptime),
+    ("start_time", Field::StartTime),
+    ("cmdline", Field::Cmdline),
+    ("exe", Field::Exe),
+    ("cwd", Field::Cwd),
+    ("listeners", Field::Listeners),
+];
+
+/// Parses a field name; `-` and `_` are interchangeable (`mem-mb` works too).
+pub fn parse_field(text: &str) -> Result<Field, String> {
+    let name = text.trim().to_ascii_lowercase().replace('-', "_");
+    FIELDS.iter().find(|(n, _)| *n == name).map(|(_, field)| *field).ok_or_else(|| {
+        let names: Vec<&str> = FIELDS.iter().map(|(n, _)| *n).collect();
+        format!("unknown field `{}` (one of {})", text, names.join(", "))
+    })
+}
+
+impl fmt::Display for Field {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = FIELDS.iter().find(|(_, field)| field == self).map_or("?", |(n, _)| n);
+        f.write_str(name)
+    }
+}
+
+impl Field {
+    /// The value as printed: bare, no unit. Something the platform doesn't
+    /// report (swap on macOS, the user of a pod) is an empty string, so each
+    /// field still gets its line.
+    pub fn value(&self, port: u16, pids: &[u32], info: &ProcessInfo, listeners: &[Listener]) -> String {
+        let mb = |bytes: u64| format!("{:.0}", to_mb(bytes));
+        let path = |path: &Option<std::path::PathBuf>| path.as_ref().map(|p| p.display().to_string()).unwrap_or_default();
+        match self {
+            Field::Port => port.to_string(),
+            Field::Pid => info.pid.to_string(),
+            Field::Pids => pids.iter().map(u32::to_string).collect::<Vec<_>>().join(" "),
+            Field::Name => info.name.clone(),
+            Field::User => info.user.clone().unwrap_or_default(),
+            Field::Cpu => format!("{:.2}", info.cpu_percent),
+            Field::Mem => info.memory_bytes.to_string(),
+            Field::MemMb => mb(info.memory_bytes),
+            Field::VirtualMb if info.virtual_bytes == 0 => String::new(),
+            Field::VirtualMb => mb(info.virtual_bytes),
+            Field::SharedMb => info.shared_bytes.map(mb).unwrap_or_default(),
+            Field::SwapMb => info.swap_bytes.map(mb).unwrap_or_default(),
+            Field::Uptime => info.uptime_secs.to_string(),
+            Field::StartTime => info.start_time.to_string(),
+            Field::Cmdline => info.cmdline.join(" "),
+            Field::Exe => path(&info.exe),
+            Field::Cwd => path(&info.cwd),
+            Field::Listeners => listeners.iter().map(Listener::to_string).collect::<Vec<_>>().join(" "),
+        }
+    }
+}
@@ -0,0 +1,150 @@
+//! What a process has open: the targets of `/proc/<pid>/fd/*` and, for
+//! regular files, their sizes. Read on demand for the watch-mode listing.
+
+use crate::exec::Runner;
+
+/// Rows shown per page of the open-files listing.
+pub const PAGE_SIZE: usize = 15;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FileKind {
+    File,
+    Socket,
+    Pipe,
+    Device,
+    /// eventfd, epoll, timerfd and other `anon_inode:` descriptors.
+    Anon,
+}
+
+impl FileKind {
+    /// Classifies a descriptor by its link target, e.g. `socket:[4521]`.
+    pub fn from_target(target: &str) -> Self {
+        if target.starts_with("socket:") {
+            FileKind::Socket
+        } else if target.starts_with("pipe:") {
+            FileKind::Pipe
+        } else if target.starts_with("anon_inode:") {
+            FileKind::Anon
+        } else if target.starts_with("/dev/") {
+            FileKind::Device
+        } else {
+            FileKind::File
+        }
+    }
+
+    pub fn label(&self) -> &'static str {
+        match self {
+            FileKind::File => "file",
+            FileKind::Socket => "socket",
+            FileKind::Pipe => "pipe",
+            FileKind::Device => "device",
+            FileKind::Anon => "anon",
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct OpenFile {
+    pub fd: u32,
+    pub kind: FileKind,
+    /// The link target: a path, or something like `socket:[4521]`.
+    pub target: String,
+    /// Size of regular files; `None` for everything else.
+    pub size: Option<u64>,
+}
+
+impl OpenFile {
+    pub fn new(fd: u32, target: String, size: Option<u64>) -> Self {
+        let kind = FileKind::from_target(&target);
+        Self {
+            fd,
+            kind,
+            target,
+            size: size.filter(|_| kind == FileKind::File),
+        }
+    }
+}
+
+/// A read of the open files, and which page of it is on screen.
+#[derive(Debug, Clone, Default)]
+pub struct FileListing {
+    pub files: Vec<OpenFile>,
+    pub page: usize,
+}
+
+impl FileListing {
+    pub fn new(files: Vec<OpenFile>) -> Self {
+        Self { files, page: 0 }
+    }
+
+    pub fn pages(&self) -> usize {
+        self.files.len().div_ceil(PAGE_SIZE).max(1)
+    }
+
+    /// The rows of the current page.
+    pub fn current(&self) -> &[OpenFile] {
+        let start = (self.page * PAGE_SIZE).min(self.files.len());
+        &self.files[start..(start + PAGE_SIZE).min(self.files.len())]
+    }
+
+    pub fn next_page(&mut self) {
+        self.page = (self.page + 1).min(self.pages() - 1);
+    }
+
+    pub fn previous_page(&mut self) {
+        self.page = self.page.saturating_sub(1);
+    }
+
+    pub fn count(&self, kind: FileKind) -> usize {
+        self.files.iter().filter(|f| f.kind == kind).count()
+    }
+}
+
+/// Parses `fd<TAB>size<TAB>target` lines; the size may be empty.
+pub fn parse_fd_listing(text: &str) -> Vec<OpenFile> {
+    let mut files: Vec<OpenFile> = text
+        .lines()
+        .filter_map(|line| {
+            let mut fields = line.splitn(3, '\t');
+            let fd = fields.next()?.parse().ok()?;
+            let size = fields.next()?.parse().ok();
+            Some(OpenFile::new(fd, fields.next()?.to_string(), size))
+        })
+        .collect();
+    files.sort_by_key(|f| f.fd);
+    files
+}
+
+#[cfg(target_os = "linux")]
+pub fn open_files(pid: u32) -> Option<Vec<OpenFile>> {
+    let dir = format!("/proc/{}/fd", pid);
+    let mut files: Vec<OpenFile> = std::fs::read_dir(&dir)
+        .ok()?
+        .flatten()
+        .filter_map(|entry| {
+            let fd = entry.file_name().to_str()?.parse().ok()?;
+            // Descriptors can be closed between the listing and the readlink.
+            let target = std::fs::read_link(entry.path()).ok()?.display().to_string();
+            let size = std::fs::metadata(entry.path()).ok().map(|m| m.len());
+            Some(OpenFile::new(fd, target, size))
+        })
+        .collect();
+    files.sort_by_key(|f| f.fd);
+    Some(files)
+}
+
+#[cfg(not(target_os = "linux"))]
+pub fn open_files(_pid: u32) -> Option<Vec<OpenFile>> {
+    None
+}
+
+/// `open_files` through `runner`, for `--ssh` and `--sudo`: a shell loop
+/// prints each descriptor's number, size and target on a line.
+pub fn open_files_on(runner: &Runner, pid: u32) -> Option<Vec<OpenFile>> {
+    let script = format!(
+        "cd /proc/{pid}/fd || exit 1; for fd in *; do target=$(readlink \"$fd\") || continue; \
+         printf '%s\\t%s\\t%s\\n' \"$fd\" \"$(stat -Lc %s \"$fd\" 2>/dev/null)\" \"$target\"; done"
+    );
+    let out = runner.shell(&script).ok().filter(|out| out.status.success())?;
+    Some(parse_fd_listing(&String::from_utf8_lossy(&out.stdout)))
+}
@@ -0,0 +1,240 @@
+//! What protocol a port speaks, found by reading its banner or by sending
+//! one harmless request and looking at the reply.
+//!
+//! Each step uses its own short-lived connection:
+//!
+//! 1. Wait briefly for a server-first greeting (SSH, MySQL, SMTP, FTP, POP3, IMAP).
+//! 2. Send `OPTIONS / HTTP/1.0`. HTTP/1.x answers with a status line, HTTP/2
+//!    (cleartext, as gRPC usually is) with a frame, Redis and memcached with
+//!    their error replies, and TLS servers with an alert.
+//! 3. Try a TLS handshake, offering `h2` and `http/1.1` over ALPN.
+//! 4. Send a PostgreSQL SSLRequest, which gets a one-byte `S` or `N`.
+//!
+//! Nothing is written that could change server state; the worst case is an
+//! "unknown command" line in someone's log. Still, only `banner` (step 1,
+//! which writes nothing) runs unless asked for; `detect` runs them all.
+
+use std::fmt;
+use std::time::Duration;
+
+use serde::Serialize;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::TcpStream;
+
+use crate::tls;
+
+/// How long to wait for a greeting before speaking first.
+const BANNER_WAIT: Duration = Duration::from_millis(300);
+/// How long to wait for the reply to a probe.
+const REPLY_WAIT: Duration = Duration::from_millis(700);
+
+const HTTP_PROBE: &[u8] = b"OPTIONS / HTTP/1.0\r\n\r\n";
+/// PostgreSQL's SSLRequest: length 8, code 80877103.
+const POSTGRES_SSL_REQUEST: &[u8] = &[0x00, 0x00, 0x00, 0x08, 0x04, 0xd2, 0x16, 0x2f];
+
+/// The protocols that can be recognised.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Protocol {
+    Http,
+    Http2,
+    Tls,
+    Ssh,
+    Redis,
+    Postgres,
+    Mysql,
+    Memcached,
+    Smtp,
+    Ftp,
+    Pop3,
+    Imap,
+}
+
+impl Protocol {
+    pub fn label(self) -> &'static str {
+        match self {
+            Protocol::Http => "HTTP",
+            Protocol::Http2 => "HTTP/2",
+            Protocol::Tls => "TLS",
+            Protocol::Ssh => "SSH",
+            Protocol::Redis => "Redis",
+            Protocol::Postgres => "PostgreSQL",
+            Protocol::Mysql => "MySQL",
+            Protocol::Memcached => "memcached",
+            Protocol::Smtp => "SMTP",
+            Protocol::Ftp => "FTP",
+            Protocol::Pop3 => "POP3",
+            Protocol::Imap => "IMAP",
+        }
+    }
+}
+
+/// A recognised protocol plus whatever the reply gave away (server software
+/// and version, HTTP version, negotiated ALPN).
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct Fingerprint {
+    pub protocol: Protocol,
+    pub detail: Option<String>,
+}
+
+impl Fingerprint {
+    fn new(protocol: Protocol) -> Self {
+        Self { protocol, detail: None }
+    }
+
+    fn with_detail(protocol: Protocol, detail: impl Into<String>) -> Self {
+        let detail = detail.into();
+        Self {
+            protocol,
+            detail: (!detail.is_empty()).then_some(detail),
+        }
+    }
+}
+
+impl fmt::Display for Fingerprint {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.detail {
+            Some(detail) => write!(f, "{} ({})", self.protocol.label(), detail),
+            None => write!(f, "{}", self.protocol.label()),
+        }
+    }
+}
+
+/// Runs the detection steps against `target` (`host:port`). `None` when the
+/// port is unreachable or nothing matched.
+pub async fn detect(target: &str, connect_timeout: Duration) -> Option<Fingerprint> {
+    let banner = exchange(target, None, connect_timeout, BANNER_WAIT).await?;
+    if let Some(found) = classify_banner(&banner) {
+        return Some(found);
+    }
+
+    let reply = exchange(target, Some(HTTP_PROBE), connect_timeout, REPLY_WAIT).await;
+    let hint = reply.as_deref().and_then(classify_reply);
+    if let Some(found) = hint.as_ref().filter(|f| f.protocol != Protocol::Tls) {
+        return Some(found.clone());
+    }
+
+    if let Ok(info) = tls::inspect(target, None, connect_timeout.max(REPLY_WAIT)).await {
+        return Some(match info.alpn.as_deref() {
+            Some("h2") => Fingerprint::with_detail(Protocol::Tls, "HTTP/2"),
+            Some("http/1.1") => Fingerprint::with_detail(Protocol::Tls, "HTTP/1.1"),
+            _ => Fingerprint::with_detail(Protocol::Tls, info.protocol),
+        });
+    }
+
+    let reply = exchange(target, Some(POSTGRES_SSL_REQUEST), connect_timeout, REPLY_WAIT).await;
+    match reply.as_deref() {
+        Some([b'S'] | [b'N']) => Some(Fingerprint::new(Protocol::Postgres)),
+        // It sent a TLS alert but wouldn't finish a handshake.
+        _ => hint,
+    }
+}
+
+/// Step 1 alone: one connection, nothing written, so only servers that
+/// greet first are recognised.
+pub async fn banner(target: &str, connect_timeout: Duration) -> Option<Fingerprint> {
+    classify_banner(&exchange(target, None, connect_timeout, BANNER_WAIT).await?)
+}
+
+/// Connects, optionally writes `probe`, and returns whatever arrives within
+/// `wait` (possibly nothing). `None` only if the connection failed.
+async fn exchange(target: &str, probe: Option<&[u8]>, connect_timeout: Duration, wait: Duration) -> Option<Vec<u8>> {
+    let mut stream = tokio::time::timeout(connect_timeout, TcpStream::connect(target))
+        .await
+        .ok()?
+        .ok()?;
+    if let Some(probe) = probe {
+        stream.write_all(probe).await.ok()?;
+    }
+    let mut buf = vec![0u8; 1024];
+    let n = match tokio::time::timeout(wait, stream.read(&mut buf)).await {
+        Ok(Ok(n)) => n,
+        _ => 0,
+    };
+    buf.truncate(n);
+    Some(buf)
+}
+
+/// Protocols where the server talks first.
+pub fn classify_banner(banner: &[u8]) -> Option<Fingerprint> {
+    if banner.is_empty() {
+        return None;
+    }
+    let text = String::from_utf8_lossy(banner);
+    let first_line = text.lines().next().unwrap_or("").trim();
+
+    if let Some(version) = first_line.strip_prefix("SSH-") {
+        // SSH-2.0-OpenSSH_9.6p1 Ubuntu-3 → OpenSSH_9.6p1
+        let software = version.split_once('-').map_or("", |(_, s)| s);
+        return Some(Fingerprint::with_detail(
+            Protocol::Ssh,
+            software.split_whitespace().next().unwrap_or(""),
+        ));
+    }
+    if let Some(rest) = first_line.strip_prefix("220") {
+        let protocol = if rest.to_ascii_uppercase().contains("FTP") {
+            Protocol::Ftp
+        } else {
+            Protocol::Smtp
+        };
+        return Some(Fingerprint::new(protocol));
+    }
+    if first_line.starts_with("+OK") {
+        return Some(Fingerprint::new(Protocol::Pop3));
+    }
+    if first_line.starts_with("* OK") {
+        return Some(Fingerprint::new(Protocol::Imap));
+    }
+    mysql_greeting(banner)
+}
+
+/// MySQL's initial handshake packet: a 3-byte length, sequence 0, then
+/// protocol version 10 and a NUL-terminated server version; or an error
+/// packet (0xff) when this host isn't allowed to connect.
+fn mysql_greeting(packet: &[u8]) -> Option<Fingerprint> {
+    let [l0, l1, l2, 0, kind, body @ ..] = packet else {
+        return None;
+    };
+    let len = u32::from_le_bytes([*l0, *l1, *l2, 0]) as usize;
+    if len == 0 || len > body.len() + 1 {
+        return None;
+    }
+    match kind {
+        0x0a => {
+            let version = body.split(|&b| b == 0).next()?;
+            Some(Fingerprint::with_detail(Protocol::Mysql, String::from_utf8_lossy(version)))
+        }
+        0xff => Some(Fingerprint::new(Protocol::Mysql)),
+        _ => None,
+    }
+}
+
+/// Replies to the `OPTIONS / HTTP/1.0` probe.
+pub fn classify_reply(reply: &[u8]) -> Option<Fingerprint> {
+    let text = String::from_utf8_lossy(reply);
+    if let Some(status_line) = text.strip_prefix("HTTP/") {
+        let version = status_line.split_whitespace().next().unwrap_or("");
+        let server = text
+            .lines()
+            .find_map(|line| {
+                let (name, value) = line.split_once(':')?;
+                name.eq_ignore_ascii_case("server").then(|| value.trim().to_string())
+            })
+            .map(|server| format!(", {}", server))
+            .unwrap_or_default();
+        return Some(Fingerprint::with_detail(Protocol::Http, format!("HTTP/{}{}", version, server)));
+    }
+    if text.starts_with("-ERR") || text.starts_with("-NOAUTH") || text.starts_with("-DENIED") {
+        return Some(Fingerprint::new(Protocol::Redis));
+    }
+    if text.starts_with("ERROR\r\n") {
+        return Some(Fingerprint::new(Protocol::Memcached));
+    }
+    match reply {
+        // An HTTP/2 frame header: 24-bit length, then SETTINGS (4) or GOAWAY (7).
+        [0, _, _, 0x04 | 0x07, ..] => Some(Fingerprint::with_detail(Protocol::Http2, "cleartext")),
+        // A TLS alert record.
+        [0x15, 0x03, ..] => Some(Fingerprint::new(Protocol::Tls)),
+        _ => None,
+    }
+}
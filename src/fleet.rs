@@ -0,0 +1,89 @@
+//! Watching several `serve` hosts at once for the multi-host grid.
+
+use anyhow::{anyhow, Result};
+
+use crate::process::ProcessInfo;
+use crate::remote::RemoteSource;
+use crate::source::MetricsSource;
+use crate::watch::WatchSession;
+
+/// One cell of the grid: a `serve` address and the port to inspect on it.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FleetTarget {
+    pub addr: String,
+    pub port: u16,
+}
+
+/// Parses a hosts file: one `host:7070 [port]` per line, `#` comments allowed.
+/// Lines without a port use `default_port`.
+pub fn parse_hosts_file(text: &str, default_port: u16) -> Result<Vec<FleetTarget>> {
+    let mut targets = Vec::new();
+    for (n, line) in text.lines().enumerate() {
+        let line = line.split('#').next().unwrap_or_default().trim();
+        if line.is_empty() {
+            continue;
+        }
+        let mut words = line.split_whitespace();
+        let addr = words.next().unwrap_or_default().to_string();
+        let port = match words.next() {
+            Some(port) => port
+                .parse()
+                .map_err(|_| anyhow!("hosts file line {}: invalid port {:?}", n + 1, port))?,
+            None => default_port,
+        };
+        if words.next().is_some() {
+            return Err(anyhow!("hosts file line {}: expected `host:port [port]`", n + 1));
+        }
+        targets.push(FleetTarget { addr, port });
+    }
+    Ok(targets)
+}
+
+/// A grid cell's state. The session is dropped on any error and rebuilt on
+/// the next tick, so a restarted service (new PIDs) or a host that comes back
+/// is picked up without restarting the grid.
+pub struct FleetMember {
+    pub target: FleetTarget,
+    pub session: Option<WatchSession<RemoteSource>>,
+    pub last: Option<ProcessInfo>,
+    pub error: Option<String>,
+}
+
+impl FleetMember {
+    pub fn new(target: FleetTarget) -> Self {
+        Self {
+            target,
+            session: None,
+            last: None,
+            error: None,
+        }
+    }
+
+    /// "host:7070 · 8080", as shown in the cell header.
+    pub fn label(&self) -> String {
+        format!("{} · {}", self.target.addr, self.target.port)
+    }
+
+    /// Connects if needed and takes one sample. PIDs sharing the port are aggregated.
+    pub async fn tick(&mut self, token: &str) {
+        if let Err(err) = self.try_tick(token).await {
+            self.session = None;
+            self.last = None;
+            self.error = Some(err.to_string());
+        }
+    }
+
+    async fn try_tick(&mut self, token: &str) -> Result<()> {
+        let session = match &mut self.session {
+            Some(session) => session,
+            None => {
+                let source = RemoteSource::connect(&self.target.addr, token.to_string(), self.target.port).await?;
+                let pids = source.resolve_pids(self.target.port)?;
+                self.session.insert(WatchSession::with_pids(source, self.target.port, pids)?)
+            }
+        };
+        self.last = Some(session.tick().await?);
+        self.error = None;
+        Ok(())
+    }
+}
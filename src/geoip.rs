@@ -0,0 +1,264 @@
+//! Country and network of an IP address from a MaxMind database (`.mmdb`,
+//! e.g. GeoLite2-Country and GeoLite2-ASN), read with a small decoder of the
+//! format's search tree and data section rather than another dependency.
+//! The file is read into memory once; lookups don't touch the disk.
+
+use std::collections::HashMap;
+use std::net::IpAddr;
+use std::path::Path;
+
+use anyhow::{anyhow, bail, Context, Result};
+
+/// Starts the metadata, which is at the end of the file.
+const METADATA_MARKER: &[u8] = b"\xab\xcd\xefMaxMind.com";
+/// Zero bytes between the search tree and the data section.
+const DATA_SEPARATOR: usize = 16;
+/// Real databases nest a few levels (`city.names.en`).
+const MAX_DEPTH: usize = 32;
+
+/// What the databases know of an address.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct GeoInfo {
+    /// ISO 3166 code, `US`, from a Country or City database.
+    pub country: Option<String>,
+    /// From an ASN database.
+    pub asn: Option<u32>,
+    pub organization: Option<String>,
+}
+
+impl GeoInfo {
+    pub fn is_empty(&self) -> bool {
+        self.country.is_none() && self.asn.is_none()
+    }
+
+    /// Fills in what `other` knows and this doesn't.
+    fn merge(&mut self, other: GeoInfo) {
+        self.country = self.country.take().or(other.country);
+        self.asn = self.asn.or(other.asn);
+        self.organization = self.organization.take().or(other.organization);
+    }
+}
+
+/// A value of the data section; only strings, unsigned numbers and maps are
+/// needed here, the rest is skipped over.
+#[derive(Debug, Clone, PartialEq)]
+enum Value {
+    String(String),
+    Uint(u128),
+    Map(HashMap<String, Value>),
+    Other,
+}
+
+impl Value {
+    fn get(&self, key: &str) -> Option<&Value> {
+        match self {
+            Value::Map(map) => map.get(key),
+            _ => None,
+        }
+    }
+
+    fn as_str(&self) -> Option<&str> {
+        match self {
+            Value::String(s) => Some(s),
+            _ => None,
+        }
+    }
+
+    fn as_uint(&self) -> Option<u128> {
+        match self {
+            Value::Uint(n) => Some(*n),
+            _ => None,
+        }
+    }
+}
+
+/// One `.mmdb` file.
+#[derive(Debug)]
+pub struct Database {
+    bytes: Vec<u8>,
+    node_count: usize,
+    record_size: usize,
+    ip_version: u16,
+    /// Where the data section starts.
+    data: usize,
+}
+
+impl Database {
+    pub fn open(path: &Path) -> Result<Self> {
+        let bytes = std::fs::read(path).with_context(|| format!("Failed to read {}", path.display()))?;
+        Self::from_bytes(bytes).with_context(|| format!("{} isn't a MaxMind database", path.display()))
+    }
+
+    pub fn from_bytes(bytes: Vec<u8>) -> Result<Self> {
+        let start = bytes
+            .windows(METADATA_MARKER.len())
+            .rposition(|w| w == METADATA_MARKER)
+            .context("no metadata")?
+            + METADATA_MARKER.len();
+        let (metadata, _) = decode(&bytes, start, start)?;
+        let field = |name: &str| metadata.get(name).and_then(Value::as_uint).with_context(|| format!("no `{}` in the metadata", name));
+        let node_count = field("node_count")? as usize;
+        let record_size = field("record_size")? as usize;
+        let ip_version = field("ip_version")? as u16;
+        if ![24, 28, 32].contains(&record_size) {
+            bail!("unsupported record size {}", record_size);
+        }
+        let data = node_count * record_size / 4 + DATA_SEPARATOR;
+        if data > start {
+            bail!("the search tree runs past the metadata");
+        }
+        Ok(Self {
+            bytes,
+            node_count,
+            record_size,
+            ip_version,
+            data,
+        })
+    }
+
+    /// The country and network the database has for `ip`.
+    pub fn lookup(&self, ip: IpAddr) -> Option<GeoInfo> {
+        let record = self.find(ip)?;
+        let (value, _) = decode(&self.bytes, self.data, self.data + record).ok()?;
+        let country = ["country", "registered_country"]
+            .iter()
+            .find_map(|key| value.get(key)?.get("iso_code")?.as_str())
+            .map(str::to_string);
+        Some(GeoInfo {
+            country,
+            asn: value.get("autonomous_system_number").and_then(Value::as_uint).map(|n| n as u32),
+            organization: value.get("autonomous_system_organization").and_then(Value::as_str).map(str::to_string),
+        })
+    }
+
+    /// Walks the search tree one bit of the address at a time, to the
+    /// offset of its record in the data section.
+    fn find(&self, ip: IpAddr) -> Option<usize> {
+        let bits: u128 = match (ip, self.ip_version) {
+            // IPv4 lives under ::/96 of an IPv6 tree.
+            (IpAddr::V4(v4), 6) => u32::from(v4) as u128,
+            (IpAddr::V4(v4), _) => (u32::from(v4) as u128) << 96,
+            (IpAddr::V6(v6), 6) => u128::from(v6),
+            (IpAddr::V6(v6), _) => (u32::from(v6.to_ipv4_mapped()?) as u128) << 96,
+        };
+        let depth = if self.ip_version == 6 { 128 } else { 32 };
+        let mut node = 0;
+        for i in 0..depth {
+            if node >= self.node_count {
+                break;
+            }
+            node = self.record(node, (bits >> (127 - i)) & 1 == 1)?;
+        }
+        // `node_count` itself means the address isn't in the database.
+        (node > self.node_count).then(|| node - self.node_count - DATA_SEPARATOR)
+    }
+
+    fn record(&self, node: usize, right: bool) -> Option<usize> {
+        let size = self.record_size / 4;
+        let b = self.bytes.get(node * size..(node + 1) * size)?;
+        let be = |bytes: &[u8]| bytes.iter().fold(0usize, |n, &b| n << 8 | b as usize);
+        Some(match (self.record_size, right) {
+            (24, false) => be(&b[..3]),
+            (24, true) => be(&b[3..]),
+            (28, false) => (b[3] as usize & 0xf0) << 20 | be(&b[..3]),
+            (28, true) => (b[3] as usize & 0x0f) << 24 | be(&b[4..]),
+            (_, false) => be(&b[..4]),
+            (_, true) => be(&b[4..]),
+        })
+    }
+}
+
+/// Decodes the value at `at`; pointers are offsets from `section`. Returns
+/// it and where the next value starts.
+fn decode(bytes: &[u8], section: usize, at: usize) -> Result<(Value, usize)> {
+    decode_nested(bytes, section, at, 0)
+}
+
+/// Maps and pointers within each other, no deeper than a corrupt file would.
+fn decode_nested(bytes: &[u8], section: usize, at: usize, depth: usize) -> Result<(Value, usize)> {
+    if depth > MAX_DEPTH {
+        bail!("data nested deeper than {} at byte {}", MAX_DEPTH, at);
+    }
+    let byte = |i: usize| bytes.get(i).copied().ok_or_else(|| anyhow!("truncated at byte {}", i));
+    let be = |from: usize, len: usize| -> Result<u128> {
+        let slice = bytes.get(from..from + len).ok_or_else(|| anyhow!("truncated at byte {}", from))?;
+        Ok(slice.iter().fold(0u128, |n, &b| n << 8 | b as u128))
+    };
+    let control = byte(at)?;
+    let mut next = at + 1;
+    let mut kind = control >> 5;
+    if kind == 1 {
+        let size = (control >> 3) & 0x3;
+        let high = (control & 0x7) as u128;
+        let offset = match size {
+            0 => (high << 8 | be(next, 1)?, 1),
+            1 => ((high << 16 | be(next, 2)?) + 2048, 2),
+            2 => ((high << 24 | be(next, 3)?) + 526_336, 3),
+            _ => (be(next, 4)?, 4),
+        };
+        let (value, _) = decode_nested(bytes, section, section + offset.0 as usize, depth + 1)?;
+        return Ok((value, next + offset.1));
+    }
+    if kind == 0 {
+        kind = 7 + byte(next)?;
+        next += 1;
+    }
+    let mut size = (control & 0x1f) as usize;
+    if size >= 29 {
+        let extra = size - 28;
+        size = [29, 285, 65_821][extra - 1] + be(next, extra)? as usize;
+        next += extra;
+    }
+    let value = match kind {
+        2 => Value::String(String::from_utf8_lossy(bytes.get(next..next + size).context("truncated string")?).into_owned()),
+        5 | 6 | 9 | 10 => Value::Uint(be(next, size)?),
+        // Doubles, bytes, signed numbers, floats.
+        3 | 4 | 8 | 15 => Value::Other,
+        // A boolean's size is its value.
+        14 => return Ok((Value::Other, next)),
+        7 => {
+            let mut map = HashMap::with_capacity(size);
+            for _ in 0..size {
+                let (key, after) = decode_nested(bytes, section, next, depth + 1)?;
+                let (value, after) = decode_nested(bytes, section, after, depth + 1)?;
+                map.insert(key.as_str().context("a map key that isn't a string")?.to_string(), value);
+                next = after;
+            }
+            return Ok((Value::Map(map), next));
+        }
+        11 => {
+            for _ in 0..size {
+                next = decode_nested(bytes, section, next, depth + 1)?.1;
+            }
+            return Ok((Value::Other, next));
+        }
+        other => bail!("unknown data type {}", other),
+    };
+    let len = match kind {
+        3 => 8,
+        15 => 4,
+        _ => size,
+    };
+    Ok((value, next + len))
+}
+
+/// `--geoip`: one or more databases, asked in turn until each field is known.
+#[derive(Debug, Default)]
+pub struct GeoIp {
+    databases: Vec<Database>,
+}
+
+impl GeoIp {
+    pub fn open(paths: &[impl AsRef<Path>]) -> Result<Self> {
+        let databases = paths.iter().map(|p| Database::open(p.as_ref())).collect::<Result<_>>()?;
+        Ok(Self { databases })
+    }
+
+    pub fn lookup(&self, ip: IpAddr) -> GeoInfo {
+        let mut info = GeoInfo::default();
+        for found in self.databases.iter().filter_map(|db| db.lookup(ip)) {
+            info.merge(found);
+        }
+        info
+    }
+}
@@ -0,0 +1,297 @@
+use std::collections::VecDeque;
+
+use chrono::{DateTime, Local};
+
+use crate::process::ProcessInfo;
+
+/// CPU percent covered by each bucket of `ProcessHistory::cpu_histogram`.
+pub const CPU_BUCKET_WIDTH: f32 = 10.0;
+/// Buckets up to 100%, plus one for everything above (several cores' worth).
+pub const CPU_BUCKETS: usize = 11;
+
+/// Samples per column the history chart steps through when zooming.
+pub const ZOOM_LEVELS: [usize; 7] = [1, 2, 5, 10, 30, 60, 300];
+/// Columns one press of left/right moves the history chart.
+const SCROLL_COLUMNS: usize = 10;
+
+/// Which stretch of the session the history sparklines show: `zoom`
+/// samples per column, ending `offset` samples before the newest.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ChartView {
+    pub zoom: usize,
+    pub offset: usize,
+    /// 'i': the column whose time and values are read out, counted from the left.
+    pub cursor: Option<usize>,
+}
+
+impl Default for ChartView {
+    fn default() -> Self {
+        Self {
+            zoom: 1,
+            offset: 0,
+            cursor: None,
+        }
+    }
+}
+
+impl ChartView {
+    /// One sample per column, newest on the right.
+    pub fn is_live(&self) -> bool {
+        self.zoom == 1 && self.offset == 0
+    }
+
+    /// Puts the cursor on the newest of `columns`, or takes it away.
+    pub fn toggle_cursor(&mut self, columns: usize) {
+        self.cursor = match self.cursor {
+            Some(_) => None,
+            None => Some(columns.saturating_sub(1)),
+        };
+    }
+
+    /// Moves the cursor `by` columns, staying within the `columns` shown.
+    pub fn move_cursor(&mut self, by: isize, columns: usize) {
+        if let Some(cursor) = self.cursor {
+            self.cursor = Some(cursor.saturating_add_signed(by).min(columns.saturating_sub(1)));
+        }
+    }
+
+    pub fn zoom_in(&mut self) {
+        self.zoom = ZOOM_LEVELS.iter().rev().copied().find(|&z| z < self.zoom).unwrap_or(ZOOM_LEVELS[0]);
+    }
+
+    pub fn zoom_out(&mut self) {
+        self.zoom = ZOOM_LEVELS.iter().copied().find(|&z| z > self.zoom).unwrap_or(self.zoom);
+    }
+
+    /// Towards older samples, no further than the oldest of `recorded`.
+    pub fn scroll_back(&mut self, recorded: usize) {
+        self.offset = (self.offset + SCROLL_COLUMNS * self.zoom).min(recorded.saturating_sub(1));
+    }
+
+    pub fn scroll_forward(&mut self) {
+        self.offset = self.offset.saturating_sub(SCROLL_COLUMNS * self.zoom);
+    }
+
+    /// Called once per new sample, so a scrolled-back chart stays on the
+    /// same stretch rather than drifting with the live end.
+    pub fn advance(&mut self) {
+        if self.offset > 0 {
+            self.offset += 1;
+        }
+    }
+}
+
+/// The samples under one chart column; with more than one, the values are
+/// their highest, as the column is drawn.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ChartPoint {
+    pub first: DateTime<Local>,
+    pub last: DateTime<Local>,
+    pub samples: usize,
+    pub cpu_percent: f32,
+    pub memory_bytes: u64,
+}
+
+/// Rolling window of recent samples used for averages, peaks and sparklines.
+#[derive(Clone)]
+pub struct ProcessHistory {
+    pub cpu_history: Vec<f32>,
+    pub mem_history: Vec<u64>,
+    max_history: usize,
+    /// The session's samples for the history chart, up to `session_len`.
+    session_cpu: VecDeque<f32>,
+    session_mem: VecDeque<u64>,
+    /// When each of the session's samples was taken.
+    session_time: VecDeque<DateTime<Local>>,
+    session_len: usize,
+    /// Samples ever added, including those dropped from the session.
+    added: u64,
+    /// Samples per CPU bucket over the whole session, not just the window.
+    cpu_buckets: [u64; CPU_BUCKETS],
+}
+
+impl ProcessHistory {
+    pub fn new(max_history: usize) -> Self {
+        Self {
+            cpu_history: Vec::new(),
+            mem_history: Vec::new(),
+            max_history,
+            session_cpu: VecDeque::new(),
+            session_mem: VecDeque::new(),
+            session_time: VecDeque::new(),
+            session_len: max_history,
+            added: 0,
+            cpu_buckets: [0; CPU_BUCKETS],
+        }
+    }
+
+    /// Keeps up to `samples` for the history chart (never fewer than the window).
+    pub fn keep_session(&mut self, samples: usize) {
+        self.session_len = samples.max(self.max_history);
+    }
+
+    /// Samples the history chart can show.
+    pub fn recorded(&self) -> usize {
+        self.session_cpu.len()
+    }
+
+    /// Samples added so far, including any the session no longer holds.
+    pub fn added(&self) -> u64 {
+        self.added
+    }
+
+    /// The chart column (of `cpu_chart`'s) holding sample number `sample`,
+    /// counted from the first ever added; one not taken yet goes in the
+    /// newest column. `None` when it's outside the view.
+    pub fn chart_column(&self, view: &ChartView, width: usize, sample: u64) -> Option<usize> {
+        let recorded = self.session_cpu.len();
+        let first = self.added - recorded as u64;
+        if recorded == 0 || sample < first {
+            return None;
+        }
+        let index = ((sample - first) as usize).min(recorded - 1);
+        let zoom = view.zoom.max(1);
+        let end = recorded.saturating_sub(view.offset);
+        let start = end.saturating_sub(width * zoom);
+        if index < start || index >= end {
+            return None;
+        }
+        let columns = (end - start).div_ceil(zoom);
+        Some(columns - 1 - (end - 1 - index) / zoom)
+    }
+
+    /// CPU for `width` columns of the chart; each column is the highest
+    /// sample it covers, so a spike survives zooming out.
+    pub fn cpu_chart(&self, view: &ChartView, width: usize) -> Vec<f32> {
+        chart_columns(&self.session_cpu, view, width, |a, b| a.max(b))
+    }
+
+    /// Like `cpu_chart`, in bytes.
+    pub fn mem_chart(&self, view: &ChartView, width: usize) -> Vec<u64> {
+        chart_columns(&self.session_mem, view, width, Ord::max)
+    }
+
+    /// What chart column `column` (of `cpu_chart`'s) covers, for the
+    /// cursor readout; `None` past the last column.
+    pub fn chart_point(&self, view: &ChartView, width: usize, column: usize) -> Option<ChartPoint> {
+        let zoom = view.zoom.max(1);
+        let end = self.session_cpu.len().saturating_sub(view.offset);
+        let start = end.saturating_sub(width * zoom);
+        let columns = (end - start).div_ceil(zoom);
+        if column >= columns {
+            return None;
+        }
+        // As in `chart_columns`, whole columns from the newest end.
+        let last = end - (columns - 1 - column) * zoom;
+        let first = last.saturating_sub(zoom).max(start);
+        Some(ChartPoint {
+            first: self.session_time[first],
+            last: self.session_time[last - 1],
+            samples: last - first,
+            cpu_percent: self.session_cpu.range(first..last).copied().fold(0.0, f32::max),
+            memory_bytes: self.session_mem.range(first..last).copied().max().unwrap_or(0),
+        })
+    }
+
+    pub fn add(&mut self, info: &ProcessInfo) {
+        self.add_at(info, Local::now());
+    }
+
+    /// Like `add`, for a sample taken at `at`.
+    pub fn add_at(&mut self, info: &ProcessInfo, at: DateTime<Local>) {
+        self.cpu_history.push(info.cpu_percent);
+        self.mem_history.push(info.memory_bytes);
+        self.added += 1;
+        self.session_cpu.push_back(info.cpu_percent);
+        self.session_mem.push_back(info.memory_bytes);
+        self.session_time.push_back(at);
+        if self.session_cpu.len() > self.session_len {
+            self.session_cpu.pop_front();
+            self.session_mem.pop_front();
+            self.session_time.pop_front();
+        }
+        let bucket = (info.cpu_percent.max(0.0) / CPU_BUCKET_WIDTH) as usize;
+        self.cpu_buckets[bucket.min(CPU_BUCKETS - 1)] += 1;
+
+        if self.cpu_history.len() > self.max_history {
+            self.cpu_history.remove(0);
+        }
+        if self.mem_history.len() > self.max_history {
+            self.mem_history.remove(0);
+        }
+    }
+
+    pub fn avg_cpu(&self) -> f32 {
+        if self.cpu_history.is_empty() {
+            0.0
+        } else {
+            self.cpu_history.iter().sum::<f32>() / self.cpu_history.len() as f32
+        }
+    }
+
+    pub fn max_cpu(&self) -> f32 {
+        self.cpu_history.iter().copied().fold(0.0f32, f32::max)
+    }
+
+    /// Average memory in bytes.
+    pub fn avg_mem(&self) -> f64 {
+        if self.mem_history.is_empty() {
+            0.0
+        } else {
+            self.mem_history.iter().sum::<u64>() as f64 / self.mem_history.len() as f64
+        }
+    }
+
+    /// Average CPU of the newer half of the window minus the older half, in
+    /// percentage points; positive means usage is rising.
+    pub fn cpu_trend(&self) -> f32 {
+        let (older, newer) = halves(&self.cpu_history);
+        mean(newer.iter().map(|&c| c as f64)) as f32 - mean(older.iter().map(|&c| c as f64)) as f32
+    }
+
+    /// Like `cpu_trend`, in bytes.
+    pub fn mem_trend(&self) -> f64 {
+        let (older, newer) = halves(&self.mem_history);
+        mean(newer.iter().map(|&m| m as f64)) - mean(older.iter().map(|&m| m as f64))
+    }
+
+    /// How many samples of the session fell in each CPU bucket: 0-10%,
+    /// 10-20%... and 100% or more last.
+    pub fn cpu_histogram(&self) -> &[u64] {
+        &self.cpu_buckets
+    }
+
+    /// Peak memory in bytes.
+    pub fn max_mem(&self) -> u64 {
+        self.mem_history.iter().copied().max().unwrap_or(0)
+    }
+}
+
+// Columns are grouped from the newest end, so the rightmost one is always whole.
+fn chart_columns<T: Copy>(data: &VecDeque<T>, view: &ChartView, width: usize, max: fn(T, T) -> T) -> Vec<T> {
+    let zoom = view.zoom.max(1);
+    let end = data.len().saturating_sub(view.offset);
+    let start = end.saturating_sub(width * zoom);
+    let shown: Vec<T> = data.range(start..end).copied().collect();
+    let mut columns: Vec<T> = shown
+        .rchunks(zoom)
+        .map(|chunk| chunk.iter().copied().reduce(max).expect("chunks are non-empty"))
+        .collect();
+    columns.reverse();
+    columns
+}
+
+// Splits a window in two; with an odd length the middle sample is left out.
+fn halves<T>(data: &[T]) -> (&[T], &[T]) {
+    let half = data.len() / 2;
+    (&data[..half], &data[data.len() - half..])
+}
+
+fn mean(values: impl ExactSizeIterator<Item = f64>) -> f64 {
+    let len = values.len();
+    if len == 0 {
+        0.0
+    } else {
+        values.sum::<f64>() / len as f64
+    }
+}
@@ -0,0 +1,327 @@
+//! Incidents in PagerDuty (Events API v2) or Opsgenie for the alerts worth
+//! waking someone for, resolved again when the condition clears.
+//!
+//! The process exiting opens an incident straight away. A threshold breach
+//! (retransmits, OOM risk, swapping) only does once it has lasted
+//! `sustain`; one that clears sooner never pages. Restarts aren't paged,
+//! since the service is back by the time they're noticed. Each incident is
+//! keyed by host, port and rule, so repeats fold into the open one and the
+//! recovery resolves it.
+
+use std::collections::{HashMap, HashSet};
+use std::fmt::Write as _;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use anyhow::{anyhow, bail, Result};
+use serde_json::{json, Value};
+use tokio::runtime::{Handle, RuntimeFlavor};
+use tokio::sync::mpsc;
+use tokio::task::JoinHandle;
+
+use crate::export::{Alert, ExportSample, Exporter};
+use crate::syslog::Severity;
+
+pub const PAGERDUTY_URL: &str = "https://events.pagerduty.com/v2/enqueue";
+pub const OPSGENIE_URL: &str = "https://api.opsgenie.com";
+pub const DEFAULT_ROUTING_KEY_ENV: &str = "PAGERDUTY_ROUTING_KEY";
+pub const DEFAULT_OPSGENIE_KEY_ENV: &str = "OPSGENIE_API_KEY";
+
+const QUEUE: usize = 16;
+/// How long shutdown waits for queued events.
+const DRAIN: Duration = Duration::from_secs(5);
+
+/// Something to tell the incident service.
+#[derive(Debug, Clone)]
+pub enum Event {
+    Trigger(Alert),
+    /// The recovery, carrying the same rule as the alert it clears.
+    Resolve(Alert),
+}
+
+type Key = (Option<String>, u16, String);
+
+fn key(alert: &Alert) -> Key {
+    (alert.host.clone(), alert.port, alert.rule.clone())
+}
+
+/// Which alerts open incidents, and when.
+#[derive(Debug, Clone)]
+pub struct Incidents {
+    sustain: Duration,
+    /// Breaches that haven't lasted `sustain` yet, with when they started.
+    pending: HashMap<Key, (Alert, Instant)>,
+    open: HashSet<Key>,
+}
+
+impl Incidents {
+    pub fn new(sustain: Duration) -> Self {
+        Self {
+            sustain,
+            pending: HashMap::new(),
+            open: HashSet::new(),
+        }
+    }
+
+    /// A trigger now for an exit, or for any alert when `sustain` is zero;
+    /// other breaches wait for `due`.
+    pub fn breach(&mut self, alert: &Alert, now: Instant) -> Option<Event> {
+        let key = key(alert);
+        if alert.rule == "process_restarted" || self.open.contains(&key) {
+            return None;
+        }
+        if alert.rule == "process_exited" || self.sustain.is_zero() {
+            self.open.insert(key);
+            return Some(Event::Trigger(alert.clone()));
+        }
+        self.pending.entry(key).or_insert_with(|| (alert.clone(), now));
+        None
+    }
+
+    /// A resolve if the condition had an open incident; a breach that
+    /// hadn't lasted long enough is forgotten.
+    pub fn recovery(&mut self, recovery: &Alert) -> Option<Event> {
+        let key = key(recovery);
+        self.pending.remove(&key);
+        self.open.remove(&key).then(|| Event::Resolve(recovery.clone()))
+    }
+
+    /// Triggers for the breaches that have now lasted `sustain`.
+    pub fn due(&mut self, now: Instant) -> Vec<Event> {
+        let sustain = self.sustain;
+        let due: Vec<Key> = self
+            .pending
+            .iter()
+            .filter(|(_, (_, since))| now.duration_since(*since) >= sustain)
+            .map(|(key, _)| key.clone())
+            .collect();
+        let mut triggered: Vec<Alert> = due
+            .into_iter()
+            .filter_map(|key| {
+                let (alert, _) = self.pending.remove(&key)?;
+                self.open.insert(key);
+                Some(alert)
+            })
+            .collect();
+        triggered.sort_by_key(|alert| alert.timestamp);
+        triggered.into_iter().map(Event::Trigger).collect()
+    }
+}
+
+/// The same incident for the same host, port and rule, e.g.
+/// `crabtop/db-1/5432/oom_at_risk`. `source` is this machine's name, used
+/// when the alert isn't for a remote target.
+pub fn dedup_key(alert: &Alert, source: &str) -> String {
+    format!("crabtop/{}/{}/{}", alert.host.as_deref().unwrap_or(source), alert.port, alert.rule)
+}
+
+fn summary(alert: &Alert, source: &str) -> String {
+    format!("{} port {}: {}", alert.host.as_deref().unwrap_or(source), alert.port, alert.message)
+}
+
+/// At most `max` characters, as both services cap their text fields.
+fn clip(text: String, max: usize) -> String {
+    match text.char_indices().nth(max) {
+        Some((at, _)) => text[..at].to_string(),
+        None => text,
+    }
+}
+
+/// The Events API v2 body for `event`.
+pub fn pagerduty_event(routing_key: &str, event: &Event, source: &str) -> Value {
+    match event {
+        Event::Trigger(alert) => json!({
+            "routing_key": routing_key,
+            "event_action": "trigger",
+            "dedup_key": dedup_key(alert, source),
+            "client": "crabtop",
+            "payload": {
+                "summary": clip(summary(alert, source), 1024),
+                "source": alert.host.as_deref().unwrap_or(source),
+                "severity": match alert.severity {
+                    Severity::Critical => "critical",
+                    Severity::Error => "error",
+                    Severity::Warning => "warning",
+                    Severity::Notice => "info",
+                },
+                "timestamp": alert.timestamp.to_rfc3339(),
+                "component": format!("port {}", alert.port),
+                "class": alert.rule,
+                "custom_details": { "rule": alert.rule, "port": alert.port, "value": alert.value },
+            },
+        }),
+        Event::Resolve(recovery) => json!({
+            "routing_key": routing_key,
+            "event_action": "resolve",
+            "dedup_key": dedup_key(recovery, source),
+        }),
+    }
+}
+
+/// The path (under the API base) and body of the Opsgenie request for `event`.
+pub fn opsgenie_request(event: &Event, source: &str) -> (String, Value) {
+    match event {
+        Event::Trigger(alert) => {
+            let body = json!({
+                "message": clip(summary(alert, source), 130),
+                "alias": dedup_key(alert, source),
+                "description": alert.message,
+                "priority": match alert.severity {
+                    Severity::Critical => "P1",
+                    Severity::Error => "P2",
+                    Severity::Warning => "P3",
+                    Severity::Notice => "P4",
+                },
+                "source": "crabtop",
+                "entity": alert.host.as_deref().unwrap_or(source),
+                "tags": ["crabtop", alert.rule],
+                "details": { "rule": alert.rule, "port": alert.port.to_string(), "value": alert.value.to_string() },
+            });
+            ("/v2/alerts".to_string(), body)
+        }
+        Event::Resolve(recovery) => {
+            let path = format!("/v2/alerts/{}/close?identifierType=alias", encode(&dedup_key(recovery, source)));
+            (path, json!({ "source": "crabtop", "note": recovery.message }))
+        }
+    }
+}
+
+fn encode(value: &str) -> String {
+    let mut out = String::new();
+    for byte in value.bytes() {
+        match byte {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'.' | b'_' | b'~' => out.push(byte as char),
+            _ => {
+                let _ = write!(out, "%{:02X}", byte);
+            }
+        }
+    }
+    out
+}
+
+/// Where incidents go, with the key read from the environment.
+#[derive(Debug, Clone)]
+pub enum Service {
+    PagerDuty { routing_key: String },
+    Opsgenie { api_key: String },
+}
+
+/// Opens and resolves incidents from a background task, in order.
+pub struct IncidentExporter {
+    name: &'static str,
+    incidents: Incidents,
+    queue: Option<mpsc::Sender<Event>>,
+    worker: Option<JoinHandle<()>>,
+    last_error: Arc<Mutex<Option<String>>>,
+}
+
+impl IncidentExporter {
+    /// `url` replaces the service's default endpoint (PagerDuty's EU one,
+    /// or a proxy). Must be called inside a Tokio runtime.
+    pub fn new(service: Service, url: Option<&str>, sustain: Duration) -> Self {
+        let source = sysinfo::System::host_name().filter(|name| !name.is_empty()).unwrap_or_else(|| "localhost".to_string());
+        let name = match service {
+            Service::PagerDuty { .. } => "pagerduty",
+            Service::Opsgenie { .. } => "opsgenie",
+        };
+        let url = url
+            .unwrap_or(match service {
+                Service::PagerDuty { .. } => PAGERDUTY_URL,
+                Service::Opsgenie { .. } => OPSGENIE_URL,
+            })
+            .trim_end_matches('/')
+            .to_string();
+        let (queue, mut events) = mpsc::channel::<Event>(QUEUE);
+        let last_error: Arc<Mutex<Option<String>>> = Arc::default();
+        let errors = Arc::clone(&last_error);
+        let client = reqwest::Client::new();
+        let worker = tokio::spawn(async move {
+            while let Some(event) = events.recv().await {
+                let request = match &service {
+                    Service::PagerDuty { routing_key } => client.post(&url).json(&pagerduty_event(routing_key, &event, &source)),
+                    Service::Opsgenie { api_key } => {
+                        let (path, body) = opsgenie_request(&event, &source);
+                        client
+                            .post(format!("{}{}", url, path))
+                            .header(reqwest::header::AUTHORIZATION, format!("GenieKey {}", api_key))
+                            .json(&body)
+                    }
+                };
+                let failure = match request.send().await {
+                    Ok(resp) if resp.status().is_success() => None,
+                    Ok(resp) => Some(format!("HTTP {}", resp.status())),
+                    Err(err) => Some(err.to_string()),
+                };
+                if let Some(message) = failure {
+                    *errors.lock().unwrap() = Some(message);
+                }
+            }
+        });
+        Self {
+            name,
+            incidents: Incidents::new(sustain),
+            queue: Some(queue),
+            worker: Some(worker),
+            last_error,
+        }
+    }
+
+    fn send(&self, event: Event) -> Result<()> {
+        if let Some(queue) = &self.queue {
+            if queue.try_send(event).is_err() {
+                bail!("the service is falling behind; an event was dropped");
+            }
+        }
+        Ok(())
+    }
+
+    fn take_error(&self) -> Result<()> {
+        match self.last_error.lock().unwrap().take() {
+            Some(message) => Err(anyhow!(message)),
+            None => Ok(()),
+        }
+    }
+}
+
+impl Exporter for IncidentExporter {
+    fn name(&self) -> &str {
+        self.name
+    }
+
+    fn on_sample(&mut self, _sample: &ExportSample) -> Result<()> {
+        for event in self.incidents.due(Instant::now()) {
+            self.send(event)?;
+        }
+        self.take_error()
+    }
+
+    fn on_alert(&mut self, alert: &Alert) -> Result<()> {
+        if let Some(event) = self.incidents.breach(alert, Instant::now()) {
+            self.send(event)?;
+        }
+        self.take_error()
+    }
+
+    fn on_recovery(&mut self, recovery: &Alert) -> Result<()> {
+        if let Some(event) = self.incidents.recovery(recovery) {
+            self.send(event)?;
+        }
+        self.take_error()
+    }
+
+    /// Open incidents stay open: as far as crabtop knows, the problem is still there.
+    fn on_shutdown(&mut self) -> Result<()> {
+        self.queue = None;
+        if let Some(worker) = self.worker.take() {
+            if let Ok(handle) = Handle::try_current() {
+                if handle.runtime_flavor() == RuntimeFlavor::MultiThread {
+                    let drained = tokio::task::block_in_place(|| handle.block_on(tokio::time::timeout(DRAIN, worker)));
+                    if drained.is_err() {
+                        bail!("the last events weren't sent within {}s", DRAIN.as_secs());
+                    }
+                }
+            }
+        }
+        self.take_error()
+    }
+}
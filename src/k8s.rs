@@ -0,0 +1,268 @@
+//! Inspecting a Kubernetes pod instead of a local PID.
+//!
+//! With `kubectl port-forward` the local listener is kubectl itself, so its
+//! numbers say nothing about the service. `KubeSource` asks the cluster
+//! instead: the pod spec for image, ports and limits, and metrics-server
+//! (`kubectl top`) for usage.
+
+use std::future::Future;
+use std::process::Command;
+
+use anyhow::{anyhow, Context, Result};
+use chrono::{DateTime, Utc};
+use serde_json::Value;
+
+use crate::cgroup::CgroupStats;
+use crate::docker::Container;
+use crate::port::Listener;
+use crate::process::{ProcessInfo, SystemSnapshot};
+use crate::source::MetricsSource;
+
+/// The container inside the pod we're reporting on.
+#[derive(Debug, Clone, Default)]
+pub struct PodContainer {
+    pub pod: String,
+    pub pod_uid: String,
+    pub name: String,
+    pub image: String,
+    pub ports: Vec<u16>,
+    pub cpu_limit_cores: Option<f64>,
+    pub memory_limit_bytes: Option<u64>,
+    pub started_at: Option<DateTime<Utc>>,
+}
+
+/// A `MetricsSource` backed by `kubectl`. Pods have no local PID, so samples
+/// report PID 0 and `resolve_pids` only confirms the pod is reachable.
+#[derive(Debug, Clone)]
+pub struct KubeSource {
+    namespace: Option<String>,
+    context: Option<String>,
+    container: PodContainer,
+    last_memory_bytes: Option<u64>,
+}
+
+impl KubeSource {
+    /// Looks the pod up and picks the container: the one named by `container`,
+    /// else the one declaring `port` as a containerPort, else the first.
+    pub fn connect(
+        pod: &str,
+        port: u16,
+        namespace: Option<String>,
+        context: Option<String>,
+        container: Option<&str>,
+    ) -> Result<Self> {
+        let args = kubectl_args(&context, &namespace, &["get", "pod", pod, "-o", "json"]);
+        let json = run_kubectl(&args)?;
+        let spec: Value = serde_json::from_str(&json).context("Failed to parse `kubectl get pod` output")?;
+        let container = select_container(&spec, port, container)?;
+
+        Ok(Self {
+            namespace,
+            context,
+            container,
+            last_memory_bytes: None,
+        })
+    }
+
+    pub fn pod_container(&self) -> &PodContainer {
+        &self.container
+    }
+
+    /// The pod/container in the shape the dashboard's container line expects.
+    pub fn as_container(&self) -> Container {
+        Container {
+            id: self.container.pod_uid.clone(),
+            name: format!("{}/{}", self.container.pod, self.container.name),
+            image: self.container.image.clone(),
+            pids: Vec::new(),
+        }
+    }
+}
+
+impl MetricsSource for KubeSource {
+    fn resolve_pids(&self, _port: u16) -> Result<Vec<u32>> {
+        Ok(vec![0])
+    }
+
+    fn sample(&mut self, _pid: u32) -> impl Future<Output = Result<ProcessInfo>> + Send {
+        let args = kubectl_args(
+            &self.context,
+            &self.namespace,
+            &["top", "pod", &self.container.pod, "--containers", "--no-headers"],
+        );
+        async move {
+            let output = tokio::task::spawn_blocking(move || run_kubectl(&args))
+                .await
+                .context("Failed to spawn kubectl")??;
+
+            // POD  CONTAINER  CPU(cores)  MEMORY(bytes)
+            let (cpu, memory) = output
+                .lines()
+                .map(|l| l.split_whitespace().collect::<Vec<_>>())
+                .find(|cols| cols.len() >= 4 && cols[1] == self.container.name)
+                .and_then(|cols| Some((parse_cpu_quantity(cols[2])?, parse_memory_quantity(cols[3])?)))
+                .ok_or_else(|| {
+                    anyhow!(
+                        "metrics-server has no usage for container {} in pod {}",
+                        self.container.name,
+                        self.container.pod
+                    )
+                })?;
+
+            self.last_memory_bytes = Some(memory);
+            let uptime_secs = self
+                .container
+                .started_at
+                .map(|t| (Utc::now() - t).num_seconds().max(0) as u64)
+                .unwrap_or(0);
+
+            Ok(ProcessInfo {
+                name: self.container.name.clone(),
+                pid: 0,
+                cpu_percent: (cpu * 100.0) as f32,
+                memory_bytes: memory,
+                start_time: self.container.started_at.map(|t| t.timestamp() as u64).unwrap_or(0),
+                uptime_secs,
+                ..Default::default()
+            })
+        }
+    }
+
+    fn system(&mut self) -> Result<SystemSnapshot> {
+        Err(anyhow!("System figures are not available for pods"))
+    }
+
+    fn listeners(&mut self, _pid: u32) -> Result<Vec<Listener>> {
+        Ok(self
+            .container
+            .ports
+            .iter()
+            .map(|&port| Listener {
+                address: self.container.pod.clone(),
+                port,
+            })
+            .collect())
+    }
+
+    fn cgroup(&mut self, _pid: u32) -> Option<CgroupStats> {
+        Some(CgroupStats {
+            path: format!("pod/{}/{}", self.container.pod, self.container.name).into(),
+            memory_current: self.last_memory_bytes,
+            memory_max: self.container.memory_limit_bytes,
+            cpu_quota_cores: self.container.cpu_limit_cores,
+            ..Default::default()
+        })
+    }
+}
+
+fn kubectl_args(context: &Option<String>, namespace: &Option<String>, args: &[&str]) -> Vec<String> {
+    let mut full = Vec::new();
+    if let Some(context) = context {
+        full.push("--context".to_string());
+        full.push(context.clone());
+    }
+    if let Some(namespace) = namespace {
+        full.push("--namespace".to_string());
+        full.push(namespace.clone());
+    }
+    full.extend(args.iter().map(|a| a.to_string()));
+    full
+}
+
+fn run_kubectl(args: &[String]) -> Result<String> {
+    let out = Command::new("kubectl")
+        .args(args)
+        .output()
+        .context("Failed to run `kubectl`. Is it installed and on PATH?")?;
+    if !out.status.success() {
+        return Err(anyhow!(
+            "`kubectl {}` failed: {}",
+            args.join(" "),
+            String::from_utf8_lossy(&out.stderr).trim()
+        ));
+    }
+    Ok(String::from_utf8_lossy(&out.stdout).into_owned())
+}
+
+fn select_container(pod: &Value, port: u16, wanted: Option<&str>) -> Result<PodContainer> {
+    let pod_name = pod["metadata"]["name"].as_str().unwrap_or_default();
+    let containers = pod["spec"]["containers"]
+        .as_array()
+        .filter(|c| !c.is_empty())
+        .ok_or_else(|| anyhow!("Pod {} has no containers", pod_name))?;
+
+    let declares_port = |c: &&Value| {
+        c["ports"]
+            .as_array()
+            .is_some_and(|ports| ports.iter().any(|p| p["containerPort"].as_u64() == Some(port as u64)))
+    };
+
+    let chosen = match wanted {
+        Some(name) => containers
+            .iter()
+            .find(|c| c["name"].as_str() == Some(name))
+            .ok_or_else(|| anyhow!("Pod {} has no container named {}", pod_name, name))?,
+        None => containers.iter().find(declares_port).unwrap_or(&containers[0]),
+    };
+
+    let name = chosen["name"].as_str().unwrap_or_default().to_string();
+    let limits = &chosen["resources"]["limits"];
+    let started_at = pod["status"]["containerStatuses"]
+        .as_array()
+        .and_then(|statuses| statuses.iter().find(|s| s["name"].as_str() == Some(name.as_str())))
+        .and_then(|s| s["state"]["running"]["startedAt"].as_str())
+        .and_then(|t| DateTime::parse_from_rfc3339(t).ok())
+        .map(|t| t.with_timezone(&Utc));
+
+    Ok(PodContainer {
+        pod: pod_name.to_string(),
+        pod_uid: pod["metadata"]["uid"].as_str().unwrap_or_default().to_string(),
+        image: chosen["image"].as_str().unwrap_or_default().to_string(),
+        ports: chosen["ports"]
+            .as_array()
+            .map(|ports| {
+                ports
+                    .iter()
+                    .filter_map(|p| p["containerPort"].as_u64())
+                    .filter_map(|p| u16::try_from(p).ok())
+                    .collect()
+            })
+            .unwrap_or_default(),
+        cpu_limit_cores: limits["cpu"].as_str().and_then(parse_cpu_quantity),
+        memory_limit_bytes: limits["memory"].as_str().and_then(parse_memory_quantity),
+        started_at,
+        name,
+    })
+}
+
+/// Parses a Kubernetes CPU quantity ("250m", "2", "1.5") into cores.
+pub fn parse_cpu_quantity(q: &str) -> Option<f64> {
+    match q.strip_suffix('m') {
+        Some(milli) => milli.parse::<f64>().ok().map(|m| m / 1000.0),
+        None => q.parse().ok(),
+    }
+}
+
+/// Parses a Kubernetes memory quantity ("128Mi", "1G", "512000") into bytes.
+pub fn parse_memory_quantity(q: &str) -> Option<u64> {
+    const SUFFIXES: [(&str, f64); 12] = [
+        ("Ki", 1024.0),
+        ("Mi", 1024.0 * 1024.0),
+        ("Gi", 1024.0 * 1024.0 * 1024.0),
+        ("Ti", 1024.0 * 1024.0 * 1024.0 * 1024.0),
+        ("Pi", 1024.0 * 1024.0 * 1024.0 * 1024.0 * 1024.0),
+        ("Ei", 1024.0 * 1024.0 * 1024.0 * 1024.0 * 1024.0 * 1024.0),
+        ("k", 1e3),
+        ("M", 1e6),
+        ("G", 1e9),
+        ("T", 1e12),
+        ("P", 1e15),
+        ("E", 1e18),
+    ];
+    for (suffix, factor) in SUFFIXES {
+        if let Some(number) = q.strip_suffix(suffix) {
+            return number.parse::<f64>().ok().map(|n| (n * factor) as u64);
+        }
+    }
+    q.parse::<f64>().ok().map(|n| n as u64)
+}
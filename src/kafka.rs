@@ -0,0 +1,365 @@
+//! Publishing each watch sample to a Kafka topic, as the same JSON the
+//! webhook and `--record` get, so daemons across a fleet can feed one
+//! pipeline.
+//!
+//! This speaks the Kafka protocol itself, over plain TCP: a metadata request
+//! to find the partition's leader, then one produce request (v3, a single
+//! v2 record batch) per sample with `acks=1`. That covers brokers from 0.11
+//! through 4.x. There's no TLS, SASL, batching or compression; put a client
+//! library in front for those. Samples for one target are keyed by its host
+//! and port, so they stay in order on one partition.
+//!
+//! The exporter is only built with the `kafka` feature; the record encoding
+//! is always there.
+
+/// CRC-32C (Castagnoli), which record batches are checked with.
+pub fn crc32c(data: &[u8]) -> u32 {
+    let mut crc = !0u32;
+    for byte in data {
+        crc ^= *byte as u32;
+        for _ in 0..8 {
+            crc = if crc & 1 == 1 { (crc >> 1) ^ 0x82F6_3B78 } else { crc >> 1 };
+        }
+    }
+    !crc
+}
+
+/// A v2 record batch holding one record, as a producer with neither
+/// idempotence nor transactions sends it.
+pub fn record_batch(key: &[u8], value: &[u8], timestamp_ms: i64) -> Vec<u8> {
+    let mut record = Vec::new();
+    record.push(0); // attributes
+    put_varint(&mut record, 0); // timestamp delta
+    put_varint(&mut record, 0); // offset delta
+    put_varint(&mut record, key.len() as i64);
+    record.extend_from_slice(key);
+    put_varint(&mut record, value.len() as i64);
+    record.extend_from_slice(value);
+    put_varint(&mut record, 0); // headers
+
+    // Everything the CRC covers: attributes to the end.
+    let mut checked = Vec::new();
+    checked.extend_from_slice(&0i16.to_be_bytes()); // attributes: no compression, create time
+    checked.extend_from_slice(&0i32.to_be_bytes()); // last offset delta
+    checked.extend_from_slice(&timestamp_ms.to_be_bytes());
+    checked.extend_from_slice(&timestamp_ms.to_be_bytes());
+    checked.extend_from_slice(&(-1i64).to_be_bytes()); // producer id
+    checked.extend_from_slice(&(-1i16).to_be_bytes()); // producer epoch
+    checked.extend_from_slice(&(-1i32).to_be_bytes()); // base sequence
+    checked.extend_from_slice(&1i32.to_be_bytes()); // records
+    put_varint(&mut checked, record.len() as i64);
+    checked.extend_from_slice(&record);
+
+    let mut batch = Vec::with_capacity(checked.len() + 21);
+    batch.extend_from_slice(&0i64.to_be_bytes()); // base offset
+    batch.extend_from_slice(&((checked.len() + 9) as i32).to_be_bytes());
+    batch.extend_from_slice(&(-1i32).to_be_bytes()); // partition leader epoch
+    batch.push(2); // magic
+    batch.extend_from_slice(&crc32c(&checked).to_be_bytes());
+    batch.extend_from_slice(&checked);
+    batch
+}
+
+/// The partition `key` goes to, out of `partitions`; stable across runs.
+pub fn partition_for(key: &[u8], partitions: usize) -> usize {
+    // FNV-1a: Kafka's own murmur2 isn't needed, only a spread that crabtop agrees with itself on.
+    let hash = key
+        .iter()
+        .fold(0xcbf2_9ce4_8422_2325u64, |hash, byte| (hash ^ *byte as u64).wrapping_mul(0x100_0000_01b3));
+    (hash % partitions.max(1) as u64) as usize
+}
+
+/// Zigzag varint, as records use.
+fn put_varint(out: &mut Vec<u8>, value: i64) {
+    let mut zigzag = ((value << 1) ^ (value >> 63)) as u64;
+    while zigzag >= 0x80 {
+        out.push(zigzag as u8 | 0x80);
+        zigzag >>= 7;
+    }
+    out.push(zigzag as u8);
+}
+
+#[cfg(feature = "kafka")]
+pub use exporter::KafkaExporter;
+
+#[cfg(feature = "kafka")]
+mod exporter {
+    use std::io::{Read, Write};
+    use std::net::{TcpStream, ToSocketAddrs};
+    use std::sync::mpsc::Receiver;
+    use std::time::Duration;
+
+    use anyhow::{anyhow, bail, Context, Result};
+    use sysinfo::System;
+
+    use crate::export::{ExportSample, Exporter, LastError, QueuedWorker};
+
+    const CLIENT_ID: &str = "crabtop";
+    const TIMEOUT: Duration = Duration::from_secs(5);
+
+    const PRODUCE: i16 = 0;
+    const METADATA: i16 = 3;
+
+    /// Sends samples through a `QueuedWorker`; after a failure the
+    /// connection is made afresh.
+    pub struct KafkaExporter {
+        worker: QueuedWorker,
+    }
+
+    impl KafkaExporter {
+        /// `brokers` are `host:port` bootstrap addresses; none are contacted
+        /// until the first sample.
+        pub fn new(brokers: &[String], topic: &str) -> Result<Self> {
+            if brokers.is_empty() {
+                bail!("The Kafka exporter needs at least one broker");
+            }
+            let mut producer = Producer {
+                brokers: brokers.to_vec(),
+                topic: topic.to_string(),
+                leaders: None,
+                correlation: 0,
+            };
+            let worker = QueuedWorker::spawn("kafka", "the cluster", move |samples, errors| producer.run(samples, errors))?;
+            Ok(Self { worker })
+        }
+    }
+
+    impl Exporter for KafkaExporter {
+        fn name(&self) -> &str {
+            "kafka"
+        }
+
+        fn on_sample(&mut self, sample: &ExportSample) -> Result<()> {
+            self.worker.send(sample)
+        }
+
+        fn on_shutdown(&mut self) -> Result<()> {
+            self.worker.drain()
+        }
+    }
+
+    /// A connection to each partition's leader, found through metadata.
+    struct Leaders {
+        /// Leader address per partition, by partition index.
+        partitions: Vec<String>,
+        connections: Vec<(String, TcpStream)>,
+    }
+
+    struct Producer {
+        brokers: Vec<String>,
+        topic: String,
+        leaders: Option<Leaders>,
+        correlation: i32,
+    }
+
+    impl Producer {
+        fn run(&mut self, samples: Receiver<ExportSample>, errors: LastError) {
+            let local = System::host_name().unwrap_or_default();
+            for sample in samples {
+                let key = format!("{}:{}", sample.host.as_deref().unwrap_or(&local), sample.port);
+                let result = serde_json::to_vec(&sample)
+                    .map_err(anyhow::Error::from)
+                    .and_then(|value| self.send(key.as_bytes(), &value, sample.timestamp.timestamp_millis()));
+                if let Err(err) = result {
+                    // Start over with fresh metadata; leaders may have moved.
+                    self.leaders = None;
+                    *errors.lock().unwrap() = Some(format!("{:#}", err));
+                }
+            }
+        }
+
+        fn send(&mut self, key: &[u8], value: &[u8], timestamp_ms: i64) -> Result<()> {
+            if self.leaders.is_none() {
+                self.leaders = Some(self.discover()?);
+            }
+            let leaders = self.leaders.as_mut().unwrap();
+            let partition = super::partition_for(key, leaders.partitions.len());
+            let address = leaders.partitions[partition].clone();
+            let position = match leaders.connections.iter().position(|(addr, _)| *addr == address) {
+                Some(position) => position,
+                None => {
+                    leaders.connections.push((address.clone(), connect(&address)?));
+                    leaders.connections.len() - 1
+                }
+            };
+
+            let mut body = Vec::new();
+            put_string(&mut body, None); // transactional id
+            body.extend_from_slice(&1i16.to_be_bytes()); // acks: the leader
+            body.extend_from_slice(&(TIMEOUT.as_millis() as i32).to_be_bytes());
+            body.extend_from_slice(&1i32.to_be_bytes());
+            put_string(&mut body, Some(&self.topic));
+            body.extend_from_slice(&1i32.to_be_bytes());
+            body.extend_from_slice(&(partition as i32).to_be_bytes());
+            let batch = super::record_batch(key, value, timestamp_ms);
+            body.extend_from_slice(&(batch.len() as i32).to_be_bytes());
+            body.extend_from_slice(&batch);
+
+            self.correlation += 1;
+            let stream = &mut self.leaders.as_mut().unwrap().connections[position].1;
+            let mut reply = call(stream, PRODUCE, 3, self.correlation, &body)?;
+            // responses: [topic, [partition, error_code, base_offset, log_append_time]]
+            reply.array_len()?;
+            reply.string()?;
+            reply.array_len()?;
+            reply.i32()?;
+            match reply.i16()? {
+                0 => Ok(()),
+                code => bail!("Kafka refused the sample for {} (error code {})", self.topic, code),
+            }
+        }
+
+        /// Asks the bootstrap brokers, in turn, where the topic's partitions live.
+        fn discover(&mut self) -> Result<Leaders> {
+            let mut last = anyhow!("no brokers");
+            for broker in self.brokers.clone() {
+                match self.metadata(&broker) {
+                    Ok(leaders) => return Ok(leaders),
+                    Err(err) => last = err.context(format!("Failed to read Kafka metadata from {}", broker)),
+                }
+            }
+            Err(last)
+        }
+
+        fn metadata(&mut self, broker: &str) -> Result<Leaders> {
+            let mut stream = connect(broker)?;
+            let mut body = Vec::new();
+            body.extend_from_slice(&1i32.to_be_bytes());
+            put_string(&mut body, Some(&self.topic));
+            body.push(1); // allow auto topic creation
+            self.correlation += 1;
+            let mut reply = call(&mut stream, METADATA, 4, self.correlation, &body)?;
+
+            reply.i32()?; // throttle time
+            let mut brokers = Vec::new();
+            for _ in 0..reply.array_len()? {
+                let node = reply.i32()?;
+                let host = reply.string()?.unwrap_or_default();
+                let port = reply.i32()?;
+                reply.string()?; // rack
+                brokers.push((node, format!("{}:{}", host, port)));
+            }
+            reply.string()?; // cluster id
+            reply.i32()?; // controller
+            for _ in 0..reply.array_len()? {
+                let error = reply.i16()?;
+                let name = reply.string()?.unwrap_or_default();
+                reply.skip(1)?; // is internal
+                let mut partitions = Vec::new();
+                for _ in 0..reply.array_len()? {
+                    reply.i16()?;
+                    let index = reply.i32()?;
+                    let leader = reply.i32()?;
+                    for _ in 0..2 {
+                        let nodes = reply.array_len()?;
+                        reply.skip(nodes * 4)?;
+                    }
+                    partitions.push((index, leader));
+                }
+                if name != self.topic {
+                    continue;
+                }
+                if error != 0 || partitions.is_empty() {
+                    bail!("Kafka has no partitions for {} (error code {})", self.topic, error);
+                }
+                partitions.sort();
+                let partitions = partitions
+                    .iter()
+                    .map(|(_, leader)| {
+                        brokers
+                            .iter()
+                            .find(|(node, _)| node == leader)
+                            .map(|(_, addr)| addr.clone())
+                            .with_context(|| format!("A partition of {} has no leader right now", self.topic))
+                    })
+                    .collect::<Result<Vec<_>>>()?;
+                return Ok(Leaders {
+                    partitions,
+                    connections: Vec::new(),
+                });
+            }
+            bail!("Kafka didn't describe {}", self.topic)
+        }
+    }
+
+    fn connect(address: &str) -> Result<TcpStream> {
+        let addr = address
+            .to_socket_addrs()
+            .with_context(|| format!("Failed to resolve {}", address))?
+            .next()
+            .with_context(|| format!("{} didn't resolve to an address", address))?;
+        let stream = TcpStream::connect_timeout(&addr, TIMEOUT).with_context(|| format!("Failed to connect to {}", address))?;
+        stream.set_read_timeout(Some(TIMEOUT))?;
+        stream.set_write_timeout(Some(TIMEOUT))?;
+        Ok(stream)
+    }
+
+    /// Sends a request with a v1 header and returns its reply past the correlation id.
+    fn call(stream: &mut TcpStream, api_key: i16, version: i16, correlation: i32, body: &[u8]) -> Result<Reply> {
+        let mut request = Vec::with_capacity(body.len() + 24);
+        request.extend_from_slice(&api_key.to_be_bytes());
+        request.extend_from_slice(&version.to_be_bytes());
+        request.extend_from_slice(&correlation.to_be_bytes());
+        put_string(&mut request, Some(CLIENT_ID));
+        request.extend_from_slice(body);
+        stream.write_all(&(request.len() as i32).to_be_bytes())?;
+        stream.write_all(&request)?;
+
+        let mut size = [0u8; 4];
+        stream.read_exact(&mut size)?;
+        let mut reply = vec![0u8; i32::from_be_bytes(size).max(0) as usize];
+        stream.read_exact(&mut reply)?;
+        let mut reply = Reply { data: reply, at: 0 };
+        if reply.i32()? != correlation {
+            bail!("Kafka answered a different request");
+        }
+        Ok(reply)
+    }
+
+    fn put_string(out: &mut Vec<u8>, value: Option<&str>) {
+        match value {
+            Some(value) => {
+                out.extend_from_slice(&(value.len() as i16).to_be_bytes());
+                out.extend_from_slice(value.as_bytes());
+            }
+            None => out.extend_from_slice(&(-1i16).to_be_bytes()),
+        }
+    }
+
+    struct Reply {
+        data: Vec<u8>,
+        at: usize,
+    }
+
+    impl Reply {
+        fn take(&mut self, n: usize) -> Result<&[u8]> {
+            let bytes = self.data.get(self.at..self.at + n).context("Kafka's reply was cut short")?;
+            self.at += n;
+            Ok(bytes)
+        }
+
+        fn skip(&mut self, n: usize) -> Result<()> {
+            self.take(n).map(|_| ())
+        }
+
+        fn i16(&mut self) -> Result<i16> {
+            Ok(i16::from_be_bytes(self.take(2)?.try_into()?))
+        }
+
+        fn i32(&mut self) -> Result<i32> {
+            Ok(i32::from_be_bytes(self.take(4)?.try_into()?))
+        }
+
+        fn array_len(&mut self) -> Result<usize> {
+            Ok(self.i32()?.max(0) as usize)
+        }
+
+        fn string(&mut self) -> Result<Option<String>> {
+            let len = self.i16()?;
+            if len < 0 {
+                return Ok(None);
+            }
+            Ok(Some(String::from_utf8_lossy(self.take(len as usize)?).into_owned()))
+        }
+    }
+}
@@ -0,0 +1,87 @@
+//! Port-to-process resolution and sampling, shared by the `port-inspector`
+//! binary and anything that wants to embed it.
+
+pub mod ai;
+pub mod annotation;
+pub mod anomaly;
+pub mod appmetrics;
+pub mod baseline;
+pub mod budget;
+pub mod cgroup;
+pub mod chart;
+pub mod children;
+pub mod chat;
+pub mod cloudwatch;
+pub mod config;
+pub mod control;
+pub mod cores;
+pub mod daemon;
+pub mod datadog;
+pub mod dashboard;
+pub mod diff;
+pub mod docker;
+pub mod ebpf;
+pub mod email;
+pub mod environ;
+pub mod error;
+pub mod exec;
+pub mod export;
+pub mod field;
+pub mod files;
+pub mod fingerprint;
+pub mod fleet;
+pub mod geoip;
+pub mod history;
+pub mod incident;
+pub mod k8s;
+pub mod kafka;
+pub mod logging;
+pub mod logs;
+pub mod mqtt;
+pub mod nats;
+pub mod oom;
+pub mod pattern;
+pub mod peers;
+pub mod pool;
+pub mod posture;
+pub mod port;
+pub mod power;
+pub mod probe;
+pub mod process;
+pub mod profile;
+pub mod procfs;
+pub mod prometheus;
+pub mod raster;
+pub mod recording;
+pub mod redact;
+pub mod remote;
+pub mod report;
+pub mod restart;
+pub mod rollup;
+pub mod rotate;
+pub mod rules;
+pub mod runtime;
+pub mod segments;
+pub mod server;
+pub mod service;
+pub mod socket;
+pub mod source;
+pub mod ssh;
+pub mod statsd;
+pub mod stream;
+pub mod syscalls;
+pub mod syslog;
+pub mod systemd;
+pub mod target;
+pub mod tls;
+pub mod units;
+pub mod upstream;
+pub mod watch;
+pub mod watchdog;
+
+pub use error::Error;
+pub use history::ProcessHistory;
+pub use port::{find_pid_by_port, find_pids_by_port, listening_ports, Listener};
+pub use process::{collect_process_info, collect_system_snapshot, ProcessInfo, SystemSnapshot};
+pub use source::{MetricsSource, ScriptedSource, SystemSource};
+pub use stream::{sample_stream, Sample};
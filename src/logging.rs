@@ -0,0 +1,230 @@
+//! crabtop's own diagnostics, through `tracing`: warnings and errors go to
+//! stderr as before, and `-v`/`-vv`/`-vvv` add what crabtop is doing (which
+//! lookup tools it ran and what they found, why a fallback was taken). The
+//! last lines are kept in memory for the 'L' pane in watch mode, where
+//! stderr would draw over the dashboard, and `--log-file` appends them all
+//! to a file.
+
+use std::collections::VecDeque;
+use std::fmt::{self, Write as _};
+use std::fs::{File, OpenOptions};
+use std::io::Write;
+use std::path::Path;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::{Arc, Mutex, OnceLock};
+
+use anyhow::{anyhow, Context, Result};
+use chrono::{DateTime, Local};
+use tracing::field::{Field, Visit};
+use tracing::span::{Attributes, Id, Record};
+use tracing::{Event, Metadata, Subscriber};
+
+pub use tracing::Level;
+
+/// Lines the in-memory buffer keeps.
+pub const LOG_BUFFER_LEN: usize = 500;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct LogLine {
+    pub timestamp: DateTime<Local>,
+    pub level: Level,
+    /// The module it came from, e.g. `crabtop::port`.
+    pub target: String,
+    /// The message, followed by any fields as `key=value`.
+    pub message: String,
+}
+
+impl fmt::Display for LogLine {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} {:>5} {}: {}",
+            self.timestamp.format("%Y-%m-%d %H:%M:%S%.3f"),
+            self.level,
+            self.target,
+            self.message
+        )
+    }
+}
+
+/// The most recent `LOG_BUFFER_LEN` lines, shared between the subscriber and
+/// whoever shows them.
+#[derive(Debug, Clone, Default)]
+pub struct LogBuffer(Arc<Mutex<VecDeque<LogLine>>>);
+
+impl LogBuffer {
+    pub fn push(&self, line: LogLine) {
+        let mut lines = self.0.lock().expect("log buffer lock");
+        if lines.len() == LOG_BUFFER_LEN {
+            lines.pop_front();
+        }
+        lines.push_back(line);
+    }
+
+    /// The last `n` lines, oldest first.
+    pub fn recent(&self, n: usize) -> Vec<LogLine> {
+        let lines = self.0.lock().expect("log buffer lock");
+        lines.iter().skip(lines.len().saturating_sub(n)).cloned().collect()
+    }
+
+    pub fn len(&self) -> usize {
+        self.0.lock().expect("log buffer lock").len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+/// Warnings only by default; each `-v` adds a level.
+pub fn level_for(verbosity: u8) -> Level {
+    match verbosity {
+        0 => Level::WARN,
+        1 => Level::INFO,
+        2 => Level::DEBUG,
+        _ => Level::TRACE,
+    }
+}
+
+/// The `tracing` subscriber behind all of this.
+pub struct Logger {
+    level: Level,
+    buffer: LogBuffer,
+    file: Option<Mutex<File>>,
+    stderr: AtomicBool,
+    next_span: AtomicU64,
+}
+
+impl Logger {
+    /// Keeps events at `level` and above, appending them to `file` if given.
+    pub fn new(level: Level, file: Option<&Path>) -> Result<Self> {
+        let file = match file {
+            Some(path) => Some(Mutex::new(
+                OpenOptions::new()
+                    .create(true)
+                    .append(true)
+                    .open(path)
+                    .with_context(|| format!("Failed to open {}", path.display()))?,
+            )),
+            None => None,
+        };
+        Ok(Self {
+            level,
+            buffer: LogBuffer::default(),
+            file,
+            stderr: AtomicBool::new(true),
+            next_span: AtomicU64::new(1),
+        })
+    }
+
+    pub fn buffer(&self) -> LogBuffer {
+        self.buffer.clone()
+    }
+
+    /// Stops (or resumes) printing to stderr; the buffer and file still get everything.
+    pub fn set_stderr(&self, enabled: bool) {
+        self.stderr.store(enabled, Ordering::Relaxed);
+    }
+}
+
+impl Subscriber for Logger {
+    fn enabled(&self, metadata: &Metadata<'_>) -> bool {
+        // Past warnings, only crabtop's own chatter; hyper and friends have plenty of their own.
+        metadata.level() <= &self.level
+            && (metadata.level() <= &Level::WARN
+                || metadata.target().starts_with("crabtop")
+                || metadata.target().starts_with("port_inspector"))
+    }
+
+    fn new_span(&self, _span: &Attributes<'_>) -> Id {
+        Id::from_u64(self.next_span.fetch_add(1, Ordering::Relaxed))
+    }
+
+    fn record(&self, _span: &Id, _values: &Record<'_>) {}
+
+    fn record_follows_from(&self, _span: &Id, _follows: &Id) {}
+
+    fn event(&self, event: &Event<'_>) {
+        let mut message = Message::default();
+        event.record(&mut message);
+        let line = LogLine {
+            timestamp: Local::now(),
+            level: *event.metadata().level(),
+            target: event.metadata().target().to_string(),
+            message: message.0,
+        };
+        if self.stderr.load(Ordering::Relaxed) {
+            match line.level {
+                Level::ERROR => eprintln!("Error: {}", line.message),
+                Level::WARN => eprintln!("Warning: {}", line.message),
+                _ => eprintln!("{}", line),
+            }
+        }
+        if let Some(file) = &self.file {
+            let mut file = file.lock().expect("log file lock");
+            let _ = writeln!(file, "{}", line);
+        }
+        self.buffer.push(line);
+    }
+
+    fn enter(&self, _span: &Id) {}
+
+    fn exit(&self, _span: &Id) {}
+}
+
+/// The message field, then the others as ` key=value`.
+#[derive(Default)]
+struct Message(String);
+
+impl Visit for Message {
+    fn record_str(&mut self, field: &Field, value: &str) {
+        if field.name() == "message" {
+            self.0.insert_str(0, value);
+        } else {
+            let _ = write!(self.0, " {}={}", field.name(), value);
+        }
+    }
+
+    fn record_debug(&mut self, field: &Field, value: &dyn fmt::Debug) {
+        if field.name() == "message" {
+            self.0.insert_str(0, &format!("{:?}", value));
+        } else {
+            let _ = write!(self.0, " {}={:?}", field.name(), value);
+        }
+    }
+}
+
+static LOGGER: OnceLock<Arc<Logger>> = OnceLock::new();
+
+/// Installs the process-wide logger, keeping `level` and above; call once,
+/// before anything logs.
+pub fn init(level: Level, file: Option<&Path>) -> Result<()> {
+    let logger = Arc::new(Logger::new(level, file)?);
+    tracing::subscriber::set_global_default(logger.clone()).map_err(|_| anyhow!("The logger is already set up"))?;
+    let _ = LOGGER.set(logger);
+    Ok(())
+}
+
+/// The last `n` lines logged, for the 'L' pane; empty before `init`.
+pub fn recent(n: usize) -> Vec<LogLine> {
+    LOGGER.get().map(|logger| logger.buffer.recent(n)).unwrap_or_default()
+}
+
+/// Keeps log lines off stderr until dropped, while a full-screen view owns
+/// the terminal.
+pub struct StderrPaused(());
+
+pub fn pause_stderr() -> StderrPaused {
+    if let Some(logger) = LOGGER.get() {
+        logger.set_stderr(false);
+    }
+    StderrPaused(())
+}
+
+impl Drop for StderrPaused {
+    fn drop(&mut self) {
+        if let Some(logger) = LOGGER.get() {
+            logger.set_stderr(true);
+        }
+    }
+}
@@ -0,0 +1,102 @@
+//! The log pane: the last lines of the process's journal (by unit, else by
+//! PID) or of a log file named with `--log-path`.
+
+use std::fs::File;
+use std::io::{Read, Seek, SeekFrom};
+use std::path::{Path, PathBuf};
+
+use anyhow::{anyhow, Context, Result};
+
+use crate::exec::Runner;
+
+/// Lines shown in the log pane.
+pub const LOG_LINES: usize = 10;
+
+/// How far back from the end of a log file we look for `LOG_LINES` lines.
+const TAIL_BYTES: u64 = 64 * 1024;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum LogSource {
+    File(PathBuf),
+    /// journald entries of the unit when there is one, else of the PID.
+    Journal { unit: Option<String>, pid: u32 },
+}
+
+impl LogSource {
+    /// What the pane title says it is showing, e.g. `journalctl -u api.service`.
+    pub fn describe(&self) -> String {
+        match self {
+            LogSource::File(path) => path.display().to_string(),
+            LogSource::Journal { unit: Some(unit), .. } => format!("journalctl -u {}", unit),
+            LogSource::Journal { unit: None, pid } => format!("journalctl _PID={}", pid),
+        }
+    }
+
+    fn command(&self, lines: usize) -> (&'static str, Vec<String>) {
+        let n = lines.to_string();
+        match self {
+            LogSource::File(path) => ("tail", vec!["-n".into(), n, path.display().to_string()]),
+            LogSource::Journal { unit, pid } => {
+                let filter = match unit {
+                    Some(unit) => format!("--unit={}", unit),
+                    None => format!("_PID={}", pid),
+                };
+                let args = ["--no-pager", "--quiet", "-o", "short-iso", "-n", &n, &filter];
+                ("journalctl", args.iter().map(|a| a.to_string()).collect())
+            }
+        }
+    }
+}
+
+/// One read of the log pane.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LogTail {
+    pub source: String,
+    pub lines: Result<Vec<String>, String>,
+}
+
+/// The last `lines` lines of `source`, run through `runner`. Local files are
+/// read directly, from the end, so a huge log costs no more than a small one.
+pub fn tail(runner: &Runner, source: &LogSource, lines: usize) -> LogTail {
+    let result = match (runner, source) {
+        (Runner::Local, LogSource::File(path)) => tail_file(path, lines),
+        _ => {
+            let (program, args) = source.command(lines);
+            let args: Vec<&str> = args.iter().map(String::as_str).collect();
+            run_tail(runner, program, &args).map(|text| last_lines(&text, lines))
+        }
+    };
+    LogTail {
+        source: source.describe(),
+        lines: result.map_err(|e| format!("{:#}", e)),
+    }
+}
+
+fn run_tail(runner: &Runner, program: &str, args: &[&str]) -> Result<String> {
+    let out = runner
+        .output(program, args)
+        .with_context(|| format!("Failed to run {}{}", program, runner.describe()))?;
+    if !out.status.success() {
+        return Err(anyhow!("{}", String::from_utf8_lossy(&out.stderr).trim()));
+    }
+    Ok(String::from_utf8_lossy(&out.stdout).into_owned())
+}
+
+pub fn tail_file(path: &Path, lines: usize) -> Result<Vec<String>> {
+    let mut file = File::open(path).with_context(|| format!("Cannot open {}", path.display()))?;
+    let len = file.metadata()?.len();
+    let start = len.saturating_sub(TAIL_BYTES);
+    file.seek(SeekFrom::Start(start))?;
+    let mut bytes = Vec::new();
+    file.read_to_end(&mut bytes)?;
+    let text = String::from_utf8_lossy(&bytes);
+    // Starting mid-file, the first line is probably cut short.
+    let text = if start > 0 { text.split_once('\n').map_or("", |(_, rest)| rest) } else { &text };
+    Ok(last_lines(text, lines))
+}
+
+/// The last `n` non-empty lines of `text`, oldest first.
+pub fn last_lines(text: &str, n: usize) -> Vec<String> {
+    let lines: Vec<&str> = text.lines().filter(|l| !l.trim().is_empty()).collect();
+    lines[lines.len().saturating_sub(n)..].iter().map(|l| l.to_string()).collect()
+}
@@ -1,154 +1,3037 @@
+use std::collections::{BTreeMap, HashMap};
 use std::env;
-use std::process::Command;
-use std::io::{self, Write};
-use std::time::Duration;
+use std::fs;
+use std::io::{self, IsTerminal, Write};
+use std::net::SocketAddr;
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
 
 use anyhow::{anyhow, Context, Result};
 use chrono::Local;
-use clap::Parser;
+use clap::{Args, Parser, Subcommand};
+use serde::Serialize;
+use tracing::{debug, info, warn};
+use futures_util::future::join_all;
 use crossterm::{
     cursor,
-    event::{self, Event, KeyCode},
+    event::{self, DisableMouseCapture, EnableMouseCapture, Event, KeyCode, MouseButton, MouseEventKind},
     execute,
     style::{Color, Print, ResetColor, SetForegroundColor},
     terminal::{self, ClearType, size as terminal_size},
 };
-use reqwest::header::{AUTHORIZATION, CONTENT_TYPE};
-use serde::Deserialize;
-use serde_json::json;
-use sysinfo::{Pid, System};
+
+use crabtop::ai::{generate_verdict, DryRun, PromptTemplate, Provider, ProviderKind, ProviderOptions, Verdict};
+use crabtop::appmetrics::AppMetrics;
+use crabtop::baseline::{self, Baseline};
+use crabtop::cgroup::CgroupStats;
+use crabtop::chat::{context_block, Chat};
+use crabtop::dashboard::{
+    grid_columns, render_compare, render_dashboard, render_grid, render_plain, render_service, render_top, severity_color, sort_top_rows, sparkline_width, CompareSide, Frame, GridCell, TopRow,
+    TopSort, CHART_LEFT,
+};
+use crabtop::diff;
+use crabtop::config::{self, AiConfig, BudgetConfig, Config};
+use crabtop::docker::{container_for_port, is_docker_proxy, Container};
+use crabtop::error::{self, Error};
+use crabtop::environ::{parse_environ, read_environ, read_environ_on};
+use crabtop::exec::{Elevation, Runner};
+use crabtop::export::{ExportSample, ExporterRegistry};
+use crabtop::field::{self, Field};
+use crabtop::files::FileListing;
+use crabtop::fingerprint::{self, Fingerprint};
+use crabtop::fleet::{parse_hosts_file, FleetMember, FleetTarget};
+use crabtop::geoip::GeoIp;
+use crabtop::k8s::KubeSource;
+use crabtop::logging;
+use crabtop::logs::LOG_LINES;
+use crabtop::oom::OomStatus;
+use crabtop::port::{connection_counts_on, connection_summary, find_pids_by_port, find_pids_by_port_on, listening_sockets, DEFAULT_RETRANSMIT_ALERT};
+use crabtop::pattern::Pattern;
+use crabtop::peers::{PeerResolver, DEFAULT_PEER_ROWS};
+use crabtop::power::PowerMeter;
+use crabtop::probe::{local_target, HttpCheck, Probe};
+use crabtop::process::{aggregate, CpuMode, ProcessSampler, SystemSnapshot, MIN_MEASURE_WINDOW};
+use crabtop::profile::{self, Folded};
+use crabtop::prometheus::{PushExporter, PushProtocol, TextfileExporter};
+#[cfg(unix)]
+use crabtop::syslog::{Facility, JournaldExporter, SyslogExporter};
+use crabtop::recording::{self, Metric, Recorder};
+use crabtop::rotate::{self, Rotation};
+use crabtop::rules::RuleEngine;
+use crabtop::redact::Redactor;
+use crabtop::remote::RemoteSource;
+use crabtop::report::{self, ReportFormat};
+use crabtop::restart::{self, RestartPlan};
+use crabtop::rollup::Resolution;
+use crabtop::target::Target;
+use crabtop::service::{self, Member, MemberKind, ServiceSession};
+use crabtop::runtime::StackDump;
+use crabtop::server;
+use crabtop::source::{MetricsSource, SystemSource};
+use crabtop::ssh::SshSource;
+use crabtop::statsd::{StatsdEmitter, StatsdFormat};
+use crabtop::systemd::UnitAction;
+use crabtop::tls::{self, TlsCheck, TlsInfo};
+use crabtop::units::{self, format_bytes, format_duration, format_mb, Units};
+use crabtop::watch::{matching_pids, Tab, WatchSession, HISTORY_LEN};
+use crabtop::watchdog::Watchdog;
+use crabtop::anomaly;
+use crabtop::annotation::{Annotation, AnnotationKind};
+use crabtop::control::{self, ControlCommand, ControlRequest, ControlSocket};
+use crabtop::daemon::{self, DaemonState};
+use crabtop::history::ChartView;
+use crabtop::{collect_process_info, Listener, ProcessHistory, ProcessInfo};
 
 #[derive(Parser, Debug)]
-#[command(name = "port-inspector", about = "Inspect the process listening on a given port.")]
+#[command(
+    name = "port-inspector",
+    about = "Inspect the process listening on a given port.",
+    args_conflicts_with_subcommands = true,
+    subcommand_negates_reqs = true
+)]
 struct Cli {
+    #[command(subcommand)]
+    command: Option<Commands>,
+
     /// Target port to inspect
-    #[arg(short = 'p', long = "port")]
-    port: u16,
+    #[arg(short = 'p', long = "port", env = "CRABTOP_PORT", required_unless_present_any = ["service", "target_cgroup", "target_container"])]
+    port: Option<u16>,
 
     /// Enable real-time monitoring mode
-    #[arg(short = 'w', long = "watch", default_value = "false")]
+    #[arg(short = 'w', long = "watch", env = "CRABTOP_WATCH", default_value = "false")]
     watch: bool,
 
     /// Update interval in seconds for watch mode
-    #[arg(short = 'i', long = "interval", default_value = "1")]
+    #[arg(short = 'i', long = "interval", env = "CRABTOP_INTERVAL", default_value = "1")]
     interval: u64,
+
+    /// Output format for snapshot mode
+    #[arg(long = "format", env = "CRABTOP_FORMAT", value_enum, default_value_t = OutputFormat::Text, conflicts_with = "watch")]
+    format: OutputFormat,
+
+    /// In snapshot mode, print just these values, one per line (repeatable or comma-separated): cpu, mem_mb, pid, name, uptime, …
+    #[arg(long = "field", value_name = "NAME", value_delimiter = ',', value_parser = field::parse_field, conflicts_with_all = ["watch", "format"])]
+    fields: Vec<Field>,
+
+    /// When several processes listen on the port, inspect this one
+    #[arg(long = "pid", env = "CRABTOP_PID", conflicts_with = "aggregate")]
+    pid: Option<u32>,
+
+    /// When several processes listen on the port, sum their CPU and memory
+    #[arg(long = "aggregate", default_value = "false")]
+    aggregate: bool,
+
+    /// Only consider the port's processes (and their children) whose command line matches REGEX; in watch mode, follow a matching process when it respawns
+    #[arg(long = "match", value_name = "REGEX", env = "CRABTOP_MATCH")]
+    matcher: Option<Pattern>,
+
+    /// Look the port up as root (through sudo, or pkexec with --sudo=pkexec) when it belongs to another user's process
+    #[arg(long = "sudo", value_name = "HELPER", num_args = 0..=1, default_missing_value = "sudo", conflicts_with_all = ["ssh", "remote", "hosts_file"])]
+    sudo: Option<Elevation>,
+
+    /// In watch mode, when the port is served by a reverse proxy (nginx, haproxy, caddy, envoy, traefik), also watch the backends on this host it forwards to
+    #[arg(long = "upstream", default_value = "false")]
+    upstream: bool,
+
+    /// In watch mode, how much history the chart keeps for scrolling back (←/→) and zooming out (+/-)
+    #[arg(long = "history-duration", value_name = "DURATION", default_value = "1h", value_parser = profile::parse_duration)]
+    history_duration: Duration,
+
+    /// In watch mode, show what the process's syscalls go to (read, write, poll, futex), counted with the ebpf feature or sampled from its threads
+    #[arg(long = "syscalls", default_value = "false")]
+    syscalls: bool,
+
+    /// In watch mode, how many peers of the port's open connections the Network tab lists, most connections first
+    #[arg(long = "top-peers", value_name = "N", default_value_t = DEFAULT_PEER_ROWS)]
+    top_peers: usize,
+
+    /// In watch mode, look the Network tab's peers' hostnames up (reverse lookups, cached and rate-limited)
+    #[arg(long = "resolve-peers", default_value = "false")]
+    resolve_peers: bool,
+
+    /// MaxMind database (.mmdb) to look the Network tab's peers' country and network up in; repeat for a country and an ASN database
+    #[arg(long = "geoip", value_name = "PATH")]
+    geoip: Vec<PathBuf>,
+
+    /// Don't look through docker-proxy to the container publishing the port
+    #[arg(long = "no-docker", default_value = "false")]
+    no_docker: bool,
+
+    /// Retransmitted share of segments (percent) at which the TCP panel turns red
+    #[arg(long = "retransmit-alert", value_name = "PCT", default_value_t = DEFAULT_RETRANSMIT_ALERT)]
+    retransmit_alert: f64,
+
+    /// How many standard deviations from its usual CPU or memory mark a sample as an anomaly (0 turns it off)
+    #[arg(long = "anomaly-sigma", value_name = "Z", default_value_t = anomaly::DEFAULT_SIGMA)]
+    anomaly_sigma: f64,
+
+    /// Also raise cpu_anomaly and memory_anomaly alerts for the exporters
+    #[arg(long = "anomaly-alert")]
+    anomaly_alert: bool,
+
+    /// What 100% CPU means: one core (process, as in top, so a busy process can pass 100%) or the whole machine (system)
+    #[arg(long = "cpu-mode", value_enum, default_value_t = CpuMode::Process)]
+    cpu_mode: CpuMode,
+
+    /// How long a snapshot measures CPU over, in milliseconds; in watch mode only the first sample does, later ones cover the interval
+    #[arg(long = "measure-ms", value_name = "MS", default_value_t = 200)]
+    measure_ms: u64,
+
+    /// Work out which protocol the port speaks by sending it probes (an HTTP OPTIONS, a TLS handshake, a PostgreSQL SSLRequest), not only by reading its greeting
+    #[arg(long = "fingerprint", default_value = "false")]
+    fingerprint: bool,
+
+    /// Don't connect to the port to work out which protocol it speaks
+    #[arg(long = "no-fingerprint", default_value = "false", conflicts_with = "fingerprint")]
+    no_fingerprint: bool,
+
+    /// In watch mode, time a TCP connect to the port every interval (to HOST:PORT if given)
+    #[arg(long = "probe", value_name = "HOST:PORT", num_args = 0..=1, default_missing_value = "")]
+    probe: Option<String>,
+
+    /// In watch mode, GET this path (or full URL) on the port every interval and track the responses
+    #[arg(long = "http-check", value_name = "PATH")]
+    http_check: Option<String>,
+
+    /// Status code --http-check treats as healthy
+    #[arg(long = "expect-status", default_value = "200", requires = "http_check")]
+    expect_status: u16,
+
+    /// In watch mode, show the port's TLS version, cipher and certificate (rechecked every 5 minutes)
+    #[arg(long = "tls", default_value = "false")]
+    tls: bool,
+
+    /// Server name to send with --tls [default: the probed host]
+    #[arg(long = "sni", value_name = "NAME", requires = "tls")]
+    sni: Option<String>,
+
+    /// In watch mode, tail this file in the log pane ('l') instead of the process's journal
+    #[arg(long = "log-path", value_name = "PATH")]
+    log_path: Option<PathBuf>,
+
+    /// In watch mode, take commands (`annotate TEXT`, `deploy TEXT`) on this Unix socket [default: $XDG_RUNTIME_DIR/crabtop-<PORT>.sock]
+    #[arg(long = "control", value_name = "PATH")]
+    control: Option<PathBuf>,
+
+    /// Metric from the app's own /metrics to show in watch mode (repeatable) [default: request counters and a few common ones]
+    #[arg(long = "app-metric", value_name = "NAME")]
+    app_metric: Vec<String>,
+
+    /// Don't look for /metrics or /debug/pprof on the process's ports
+    #[arg(long = "no-app-metrics")]
+    no_app_metrics: bool,
+
+    /// Inspect the port on a remote Linux host over SSH (key or agent auth)
+    #[arg(long = "ssh", value_name = "USER@HOST", conflicts_with_all = ["remote", "hosts_file", "target_cgroup", "target_container"])]
+    ssh: Option<String>,
+
+    /// Inspect the port on a host running `port-inspector serve`; repeat for a multi-host grid
+    #[arg(long = "remote", value_name = "HOST:PORT", conflicts_with_all = ["target_cgroup", "target_container"])]
+    remote: Vec<String>,
+
+    /// File of `serve` hosts, one `host:7070 [port]` per line, for the multi-host grid
+    #[arg(long = "hosts-file", value_name = "PATH", conflicts_with_all = ["target_cgroup", "target_container"])]
+    hosts_file: Option<PathBuf>,
+
+    /// Watch the ports and processes of this [[service]] from the config file together
+    #[arg(long = "service", value_name = "NAME", env = "CRABTOP_SERVICE", conflicts_with_all = ["port", "ssh", "remote", "hosts_file", "target_cgroup", "target_container"])]
+    service: Option<String>,
+
+    #[command(flatten)]
+    target: TargetArgs,
+
+    /// API token for --remote and --hosts-file
+    #[arg(long = "token", env = "CRABTOP_TOKEN", hide_env_values = true)]
+    token: Option<String>,
+
+    /// Log what crabtop is doing to stderr (-v), with lookup details (-vv) and every command it runs (-vvv); in watch mode, see them with 'L'
+    #[arg(short = 'v', long = "verbose", action = clap::ArgAction::Count, global = true)]
+    verbose: u8,
+
+    /// Print only what was asked for: no warnings, and no header or notes around a snapshot's figures
+    #[arg(short = 'q', long = "quiet", conflicts_with = "verbose", global = true)]
+    quiet: bool,
+
+    /// Also append crabtop's own log to this file, at the -v level
+    #[arg(long = "log-file", value_name = "PATH", global = true)]
+    log_file: Option<PathBuf>,
+
+    /// Memory in powers of 1000 (MB, GB) or of 1024 (MiB, GiB)
+    #[arg(long = "units", value_enum, default_value_t = Units::Decimal, global = true)]
+    units: Units,
+
+    #[command(flatten)]
+    export: ExportArgs,
+
+    #[command(flatten)]
+    ai: AiArgs,
+}
+
+/// A container to follow instead of a port, for a sidecar.
+#[derive(Args, Debug)]
+struct TargetArgs {
+    /// Watch the processes of this cgroup (its directory, or a path under /sys/fs/cgroup) instead of a port's
+    #[arg(long = "target-cgroup", value_name = "PATH", env = "CRABTOP_TARGET_CGROUP", conflicts_with_all = ["port", "pid", "target_container"])]
+    target_cgroup: Option<PathBuf>,
+
+    /// Watch this container of the pod (an ID prefix, or the name of its first process) instead of a port; needs a shared process namespace
+    #[arg(long = "target-container", value_name = "NAME|ID", env = "CRABTOP_TARGET_CONTAINER", conflicts_with_all = ["port", "pid"])]
+    target_container: Option<String>,
+}
+
+impl TargetArgs {
+    fn target(&self) -> Option<Target> {
+        match (&self.target_cgroup, &self.target_container) {
+            (Some(path), _) => Some(Target::Cgroup(path.clone())),
+            (None, Some(wanted)) => Some(Target::Container(wanted.clone())),
+            (None, None) => None,
+        }
+    }
+}
+
+/// Which LLM, if any, writes the snapshot insight.
+#[derive(Args, Debug)]
+struct AiArgs {
+    /// LLM backend for insights [default: `[ai] provider` from the config, else openai when OPENAI_API_KEY is set]
+    #[arg(long = "ai-provider", env = "CRABTOP_AI_PROVIDER", value_enum)]
+    ai_provider: Option<ProviderKind>,
+
+    /// Base URL of the AI backend (e.g. http://localhost:11434 for Ollama)
+    #[arg(long = "ai-url", value_name = "URL", env = "CRABTOP_AI_URL")]
+    ai_url: Option<String>,
+
+    /// Model to ask [default: depends on the provider]
+    #[arg(long = "ai-model", value_name = "MODEL", env = "CRABTOP_AI_MODEL")]
+    ai_model: Option<String>,
+
+    /// Environment variable holding the API key [default: depends on the provider]
+    #[arg(long = "ai-key-env", value_name = "VAR", env = "CRABTOP_AI_KEY_ENV")]
+    ai_key_env: Option<String>,
+
+    /// Sampling temperature for the AI backend [default: 0.7]
+    #[arg(long = "ai-temperature", value_name = "T")]
+    ai_temperature: Option<f64>,
+
+    /// File with the insight prompt; {name}, {pid}, {cpu}, {mem}, {avg_cpu}, … are filled in
+    #[arg(long = "ai-prompt", value_name = "PATH")]
+    ai_prompt: Option<PathBuf>,
+
+    /// In watch mode, ask for a fresh insight every SECS seconds (press 'a' to ask at any time)
+    #[arg(long = "ai-interval", value_name = "SECS", env = "CRABTOP_AI_INTERVAL")]
+    ai_interval: Option<u64>,
+
+    /// Print the (redacted) request each AI call would make instead of sending it
+    #[arg(long = "ai-dry-run")]
+    ai_dry_run: bool,
+
+    /// Stop making AI calls after this many in one run [default: config, else unlimited]
+    #[arg(long = "ai-max-requests", value_name = "N", env = "CRABTOP_AI_MAX_REQUESTS")]
+    ai_max_requests: Option<u64>,
+}
+
+/// One line of AI spending, printed when a session ends; `None` if nothing was asked.
+fn ai_usage_line(provider: &Provider) -> Option<String> {
+    let (usage, hits) = provider.usage();
+    (usage.requests > 0 || hits > 0).then(|| {
+        format!(
+            "{}: {} requests, ~{} tokens, {} answered from cache",
+            provider.kind().label(),
+            usage.requests,
+            usage.tokens,
+            hits
+        )
+    })
 }
 
-#[derive(Debug, Clone)]
-struct ProcessInfo {
-    name: String,
-    pid: u32,
-    cpu_percent: f32,
-    memory_mb: f64,
+/// How a failed AI call is reported; a dry run shows the request it built.
+fn ai_failure(provider: &Provider, err: &anyhow::Error) -> String {
+    match err.downcast_ref::<DryRun>() {
+        Some(dry) => dry.to_string(),
+        None => format!("{} call failed: {:#}", provider.kind().label(), err),
+    }
+}
+
+/// A provider plus the prompt it is asked with.
+struct Insights {
+    provider: Provider,
+    /// User-supplied prompt; each mode has its own default.
+    template: Option<PromptTemplate>,
+    /// How often watch mode asks on its own; `None` means only on 'a'.
+    interval: Option<Duration>,
 }
 
-struct ProcessHistory {
-    cpu_history: Vec<f32>,
-    mem_history: Vec<f64>,
-    max_history: usize,
+/// Watch mode's AI Insight panel: at most one request in flight, run in the
+/// background so the dashboard keeps ticking while the backend thinks.
+struct WatchInsights {
+    insights: Insights,
+    template: PromptTemplate,
+    pending: Option<tokio::task::JoinHandle<Result<Verdict>>>,
+    last_asked: Option<Instant>,
+    verdict: Option<Verdict>,
+    /// Line under the verdict: progress, the last error, or when it was updated.
+    status: Option<String>,
 }
 
-impl ProcessHistory {
-    fn new(max_history: usize) -> Self {
+impl WatchInsights {
+    fn new(insights: Insights) -> Self {
+        let template = insights.template.clone().unwrap_or_else(PromptTemplate::watch);
         Self {
-            cpu_history: Vec::new(),
-            mem_history: Vec::new(),
-            max_history,
+            insights,
+            template,
+            pending: None,
+            last_asked: None,
+            verdict: None,
+            status: None,
         }
     }
 
-    fn add(&mut self, info: &ProcessInfo) {
-        self.cpu_history.push(info.cpu_percent);
-        self.mem_history.push(info.memory_mb);
+    /// Whether `--ai-interval` says it's time to ask again.
+    fn due(&self) -> bool {
+        match (self.insights.interval, self.last_asked) {
+            (Some(_), None) => true,
+            (Some(every), Some(at)) => at.elapsed() >= every,
+            (None, _) => false,
+        }
+    }
 
-        if self.cpu_history.len() > self.max_history {
-            self.cpu_history.remove(0);
+    /// Starts a request unless one is already running.
+    fn ask(&mut self, info: &ProcessInfo, history: &ProcessHistory) {
+        if self.pending.is_some() {
+            return;
         }
-        if self.mem_history.len() > self.max_history {
-            self.mem_history.remove(0);
+        let provider = self.insights.provider.clone();
+        let template = self.template.clone();
+        let (info, history) = (info.clone(), history.clone());
+        self.pending = Some(tokio::spawn(async move {
+            generate_verdict(&provider, &template, &info, &history).await
+        }));
+        self.last_asked = Some(Instant::now());
+        self.status = Some(format!("Asking {} ({})…", self.insights.provider.kind().label(), self.insights.provider.model()));
+    }
+
+    /// Picks up a finished request; true when the panel text changed.
+    async fn poll(&mut self) -> bool {
+        if !self.pending.as_ref().is_some_and(|h| h.is_finished()) {
+            return false;
         }
+        let handle = self.pending.take().expect("checked above");
+        // A failed call keeps the previous verdict on screen under the error.
+        self.status = Some(match handle.await {
+            Ok(Ok(verdict)) => {
+                self.verdict = Some(verdict);
+                format!("Updated {}", Local::now().format("%H:%M:%S"))
+            }
+            Ok(Err(err)) => ai_failure(&self.insights.provider, &err),
+            Err(err) => format!("{} call failed: {}", self.insights.provider.kind().label(), err),
+        });
+        true
     }
+}
 
-    fn avg_cpu(&self) -> f32 {
-        if self.cpu_history.is_empty() {
-            0.0
-        } else {
-            self.cpu_history.iter().sum::<f32>() / self.cpu_history.len() as f32
+impl AiArgs {
+    /// The configured provider, `None` when insights are off. Flags win over
+    /// the config's `[ai]` table; with neither, OpenAI is used only if
+    /// OPENAI_API_KEY is set.
+    fn insights(&self, config: &AiConfig) -> Result<Option<Insights>> {
+        let has_openai_key = env::var("OPENAI_API_KEY").is_ok_and(|k| !k.trim().is_empty());
+        let kind = match self.ai_provider.or(config.provider) {
+            Some(kind) => kind,
+            None if has_openai_key => ProviderKind::Openai,
+            None => return Ok(None),
+        };
+        let key_env = self
+            .ai_key_env
+            .clone()
+            .or_else(|| config.api_key_env.clone())
+            .or_else(|| kind.default_key_env().map(str::to_string));
+
+        let provider = Provider::new(ProviderOptions {
+            kind,
+            url: self.ai_url.clone().or_else(|| config.url.clone()),
+            model: self.ai_model.clone().or_else(|| config.model.clone()),
+            api_key: key_env.and_then(|var| env::var(var).ok()),
+            api_version: config.api_version.clone(),
+            temperature: self.ai_temperature.or(config.temperature),
+            redactor: Redactor::from_config(&config.redact),
+            dry_run: self.ai_dry_run,
+            budget: BudgetConfig {
+                max_requests_per_run: self.ai_max_requests.or(config.budget.max_requests_per_run),
+                ..config.budget.clone()
+            },
+        })?;
+        let template = match self.ai_prompt.as_ref().or(config.prompt_template.as_ref()) {
+            Some(path) => Some(PromptTemplate::load(path)?),
+            None => None,
+        };
+        Ok(Some(Insights {
+            provider,
+            template,
+            interval: self.ai_interval.map(|secs| Duration::from_secs(secs.max(1))),
+        }))
+    }
+}
+
+/// Where watch-mode samples are sent besides the screen.
+#[derive(Args, Debug)]
+struct ExportArgs {
+    /// Config file with `[ai]` settings and `[[exporter]]` sinks [default: ~/.config/crabtop/config.toml]
+    #[arg(long = "config", value_name = "PATH", env = "CRABTOP_CONFIG")]
+    config: Option<PathBuf>,
+
+    /// Emit each watch sample as gauges to this StatsD/DogStatsD collector
+    #[arg(long = "statsd", value_name = "HOST:PORT", env = "CRABTOP_STATSD")]
+    statsd: Option<String>,
+
+    /// Metric name prefix for --statsd
+    #[arg(long = "statsd-prefix", env = "CRABTOP_STATSD_PREFIX", default_value = "crabtop")]
+    statsd_prefix: String,
+
+    /// Extra `key:value` tag for --statsd (repeatable; DogStatsD format only)
+    #[arg(long = "statsd-tag", value_name = "KEY:VALUE")]
+    statsd_tag: Vec<String>,
+
+    /// Line format for --statsd
+    #[arg(long = "statsd-format", value_enum, default_value_t = StatsdFormat::Dogstatsd)]
+    statsd_format: StatsdFormat,
+
+    /// Write each sample as a .prom file in this directory, for node_exporter's textfile collector
+    #[arg(long = "textfile-dir", value_name = "DIR", env = "CRABTOP_TEXTFILE_DIR")]
+    textfile_dir: Option<PathBuf>,
+
+    /// Push each sample to this Pushgateway, or remote_write endpoint if the URL ends in /write or /push
+    #[arg(long = "prom-push-url", value_name = "URL", env = "CRABTOP_PROM_PUSH_URL")]
+    prom_push_url: Option<String>,
+
+    /// Send alerts to the local syslog daemon, at a priority per rule
+    #[arg(long = "syslog")]
+    syslog: bool,
+
+    /// Send alerts to the systemd journal, with the rule and port as fields
+    #[arg(long = "journald")]
+    journald: bool,
+
+    /// Record every sample to this file (JSON lines), for `diff` to compare later
+    #[arg(long = "record", value_name = "FILE", env = "CRABTOP_RECORD")]
+    record: Option<PathBuf>,
+
+    /// Start the chart from the --record file's latest samples of the same port, and add to the file instead of replacing it
+    #[arg(long = "resume", requires = "record")]
+    resume: bool,
+
+    /// Start a new --record or CSV file once the current one reaches this size (e.g. 100MB)
+    #[arg(long = "log-max-size", value_name = "SIZE", value_parser = rotate::parse_size)]
+    log_max_size: Option<u64>,
+
+    /// Start a new --record or CSV file once the current one is this old (e.g. 1d)
+    #[arg(long = "log-max-age", value_name = "DURATION", value_parser = profile::parse_duration)]
+    log_max_age: Option<Duration>,
+
+    /// Delete rotated --record and CSV files older than this (e.g. 7d); rotates daily unless --log-max-size/--log-max-age say otherwise
+    #[arg(long = "retention", value_name = "DURATION", value_parser = profile::parse_duration)]
+    retention: Option<Duration>,
+}
+
+impl ExportArgs {
+    fn rotation(&self) -> Rotation {
+        Rotation {
+            max_size: self.log_max_size,
+            max_age: self.log_max_age,
+            retention: self.retention,
         }
     }
 
-    fn max_cpu(&self) -> f32 {
-        self.cpu_history.iter().copied().fold(0.0f32, f32::max)
+    /// `--resume`: the latest `keep` samples of `port` in the `--record`
+    /// file, read before `registry` opens it.
+    fn resumed(&self, port: u16, keep: usize) -> Result<Vec<ExportSample>> {
+        match &self.record {
+            Some(path) if self.resume => recording::recent(path, port, keep),
+            _ => Ok(Vec::new()),
+        }
     }
 
-    fn avg_mem(&self) -> f64 {
-        if self.mem_history.is_empty() {
-            0.0
-        } else {
-            self.mem_history.iter().sum::<f64>() / self.mem_history.len() as f64
+    /// The config file's exporters, plus `--statsd`, `--textfile-dir`, `--prom-push-url`,
+    /// `--syslog`, `--journald` and `--record` when given.
+    fn registry(&self, config: &Config) -> Result<ExporterRegistry> {
+        let mut registry = ExporterRegistry::from_config_rotating(&config.exporters, self.rotation())?;
+        if let Some(addr) = &self.statsd {
+            let statsd = StatsdEmitter::connect(addr, &self.statsd_prefix, self.statsd_format, self.statsd_tag.clone())?;
+            registry.push(Box::new(statsd));
+        }
+        if let Some(dir) = &self.textfile_dir {
+            registry.push(Box::new(TextfileExporter::new(dir)?));
+        }
+        if let Some(url) = &self.prom_push_url {
+            registry.push(Box::new(PushExporter::new(url, PushProtocol::for_url(url))?));
+        }
+        #[cfg(unix)]
+        if self.syslog {
+            registry.push(Box::new(SyslogExporter::new(Facility::default())?));
+        }
+        #[cfg(unix)]
+        if self.journald {
+            registry.push(Box::new(JournaldExporter::new(Facility::default())?));
+        }
+        #[cfg(not(unix))]
+        if self.syslog || self.journald {
+            return Err(anyhow!("--syslog and --journald need a Unix system"));
+        }
+        if let Some(path) = &self.record {
+            let recorder = if self.resume {
+                Recorder::appending(path, self.rotation())?
+            } else {
+                if !self.rotation().is_enabled() && fs::metadata(path).is_ok_and(|m| m.len() > 0) {
+                    warn!("Replacing the recording in {}; --resume would add to it and start the chart from it", path.display());
+                }
+                Recorder::rotating(path, self.rotation())?
+            };
+            registry.push(Box::new(recorder.with_rollups(path)?));
+        }
+        for rule in config.rules.iter().filter(|rule| !rule.sinks.is_empty()) {
+            registry.route(&rule.name, &rule.sinks)?;
+        }
+        Ok(registry)
+    }
+}
+
+/// Prints what went wrong with exporters during the session, once the terminal is back.
+fn report_exporter_failures(registry: &mut ExporterRegistry) {
+    for failure in registry.on_shutdown() {
+        warn!("exporter {}", failure);
+    }
+}
+
+// Parsed once at startup; boxing the bigger variants would only add noise.
+#[allow(clippy::large_enum_variant)]
+#[derive(Subcommand, Debug)]
+enum Commands {
+    /// Inspect a container in a Kubernetes pod (via kubectl and metrics-server)
+    K8s(K8sArgs),
+    /// Serve this host's ports and process metrics over HTTP/WebSocket
+    Serve(ServeArgs),
+    /// Chat with the AI backend about the process on a port, with live stats as context
+    Ask(AskArgs),
+    /// Show the TLS version, cipher and certificate a port serves
+    Tls(TlsArgs),
+    /// Show the environment variables of the process on a port, secrets masked
+    Env(EnvArgs),
+    /// Restart the process on a port the way it is managed (docker, systemd, supervisord or SIGHUP)
+    Restart(RestartArgs),
+    /// Sample the stacks of the process on a port into folded stacks and a flamegraph
+    Profile(ProfileArgs),
+    /// Watch the processes on two ports side by side, with their CPU and memory deltas
+    Compare(CompareArgs),
+    /// Save a CPU/memory baseline of the process on a port, or check a later run against one
+    Baseline(BaselineArgs),
+    /// Compare two `--record` recordings, lined up by time since each started
+    Diff(DiffArgs),
+    /// Write a `--record` recording up as a standalone HTML or Markdown report
+    Report(ReportArgs),
+    /// Draw the CPU, memory or connection history of a recording or a live process as SVG or PNG
+    Export(ChartArgs),
+    /// Show the busiest listening processes, by CPU, memory or connections, and drill into one
+    Top(TopArgs),
+    /// Send a command to a running watch session over its control socket (annotate, deploy, interval, snapshot, stats)
+    Ctl(CtlArgs),
+    /// Keep watching a port in the background, exporting samples and alerts, with no terminal open
+    Daemon(DaemonArgs),
+    /// Take a fixed number of samples of the process on a port and print them, with no dashboard
+    Sample(SampleArgs),
+}
+
+#[derive(Args, Debug)]
+struct SampleArgs {
+    /// Port whose process to sample
+    #[arg(short = 'p', long = "port")]
+    port: u16,
+
+    /// When several processes listen on the port, sample this one [default: the lowest PID]
+    #[arg(long = "pid")]
+    pid: Option<u32>,
+
+    /// How many samples to take
+    #[arg(short = 'n', long = "count", default_value_t = 10, value_parser = clap::value_parser!(u64).range(1..))]
+    count: u64,
+
+    /// Time between samples (e.g. 500ms, 5s), at least 200ms
+    #[arg(short = 'i', long = "interval", default_value = "1s", value_parser = profile::parse_duration)]
+    interval: Duration,
+
+    /// A line per sample as it is taken, or one JSON array once they all are
+    #[arg(long = "format", value_enum, default_value_t = OutputFormat::Text)]
+    format: OutputFormat,
+}
+
+#[derive(Args, Debug)]
+struct BaselineArgs {
+    #[command(subcommand)]
+    action: BaselineAction,
+}
+
+#[derive(Subcommand, Debug)]
+enum BaselineAction {
+    /// Sample the process for a while and write its averages and p95s to a file
+    Save(BaselineSaveArgs),
+    /// Sample the process again and fail if it uses more than the baseline allows
+    Check(BaselineCheckArgs),
+}
+
+#[derive(Args, Debug)]
+struct BaselineSaveArgs {
+    /// Port whose process to sample
+    #[arg(short = 'p', long = "port")]
+    port: u16,
+
+    /// When several processes listen on the port, sample this one [default: the lowest PID]
+    #[arg(long = "pid")]
+    pid: Option<u32>,
+
+    /// How long to sample for (e.g. 30s, 5m)
+    #[arg(long = "duration", default_value = "60s", value_parser = profile::parse_duration)]
+    duration: Duration,
+
+    /// Seconds between samples
+    #[arg(short = 'i', long = "interval", default_value = "1")]
+    interval: u64,
+
+    /// Where to write the baseline
+    #[arg(value_name = "FILE")]
+    output: PathBuf,
+}
+
+#[derive(Args, Debug)]
+struct BaselineCheckArgs {
+    /// The file written by `baseline save`
+    #[arg(long = "against", value_name = "FILE")]
+    against: PathBuf,
+
+    /// Port whose process to sample [default: the baseline's port]
+    #[arg(short = 'p', long = "port")]
+    port: Option<u16>,
+
+    /// When several processes listen on the port, sample this one [default: the lowest PID]
+    #[arg(long = "pid")]
+    pid: Option<u32>,
+
+    /// How long to sample for [default: as long as the baseline did]
+    #[arg(long = "duration", value_parser = profile::parse_duration)]
+    duration: Option<Duration>,
+
+    /// Seconds between samples
+    #[arg(short = 'i', long = "interval", default_value = "1")]
+    interval: u64,
+
+    /// How much higher than the baseline each figure may be
+    #[arg(long = "tolerance", default_value = "20%", value_parser = baseline::parse_tolerance)]
+    tolerance: f64,
+}
+
+#[derive(Args, Debug)]
+struct TopArgs {
+    /// How many processes to show
+    #[arg(short = 'n', long = "count", default_value = "10")]
+    count: usize,
+
+    /// What to order by at first; 's' changes it
+    #[arg(long = "sort", value_enum, default_value_t = TopSort::Cpu)]
+    sort: TopSort,
+
+    /// Seconds between samples
+    #[arg(short = 'i', long = "interval", default_value = "1")]
+    interval: u64,
+}
+
+#[derive(Args, Debug)]
+struct CtlArgs {
+    /// Port of the watch session to talk to [default: the only one running]
+    #[arg(short = 'p', long = "port")]
+    port: Option<u16>,
+
+    /// The session's control socket, when it was started with --control
+    #[arg(long = "socket", value_name = "PATH", conflicts_with = "port")]
+    socket: Option<PathBuf>,
+
+    /// The command and its arguments, e.g. `annotate "deployed v1.2"`, `interval 5s`, `snapshot`, `stats`
+    #[arg(required = true, num_args = 1.., trailing_var_arg = true)]
+    command: Vec<String>,
+}
+
+#[derive(Args, Debug)]
+struct DaemonArgs {
+    #[command(subcommand)]
+    action: DaemonAction,
+}
+
+#[derive(Subcommand, Debug)]
+enum DaemonAction {
+    /// Start watching a port in the background
+    Start(DaemonStartArgs),
+    /// Watch in the foreground, as `start` does in the background (e.g. as a container's command)
+    Run(DaemonStartArgs),
+    /// Show the running daemons and what they last sampled
+    Status(DaemonPortArgs),
+    /// Stop a daemon
+    Stop(DaemonPortArgs),
+    /// Open the watch dashboard on a daemon's live samples
+    Attach(DaemonPortArgs),
+}
+
+#[derive(Args, Debug)]
+struct DaemonStartArgs {
+    /// Port whose process to watch
+    #[arg(short = 'p', long = "port", env = "CRABTOP_PORT", required_unless_present_any = ["target_cgroup", "target_container"])]
+    port: Option<u16>,
+
+    #[command(flatten)]
+    target: TargetArgs,
+
+    /// When several processes listen on the port, watch this one [default: the lowest PID]
+    #[arg(long = "pid", env = "CRABTOP_PID")]
+    pid: Option<u32>,
+
+    /// Seconds between samples
+    #[arg(short = 'i', long = "interval", env = "CRABTOP_INTERVAL", default_value = "1")]
+    interval: u64,
+
+    /// Retransmitted share of segments (percent) that raises an alert
+    #[arg(long = "retransmit-alert", value_name = "PCT", default_value_t = DEFAULT_RETRANSMIT_ALERT)]
+    retransmit_alert: f64,
+
+    /// Standard deviations from its usual CPU or memory that mark a sample as an anomaly (0 turns it off)
+    #[arg(long = "anomaly-sigma", value_name = "Z", default_value_t = anomaly::DEFAULT_SIGMA)]
+    anomaly_sigma: f64,
+
+    /// Also raise cpu_anomaly and memory_anomaly alerts
+    #[arg(long = "anomaly-alert")]
+    anomaly_alert: bool,
+
+    #[command(flatten)]
+    export: ExportArgs,
+}
+
+impl DaemonStartArgs {
+    /// The port, or 0 for a `--target-*` daemon, which is kept under it.
+    fn port(&self) -> u16 {
+        self.port.unwrap_or(0)
+    }
+
+    /// What is watched, for the log: `port 8080` or `container app`.
+    fn watched(&self) -> String {
+        match self.target.target() {
+            Some(target) => target.to_string(),
+            None => format!("port {}", self.port()),
+        }
+    }
+
+    /// The processes to start with.
+    fn pids(&self) -> Result<Vec<u32>> {
+        match self.target.target() {
+            Some(target) => target.pids(),
+            None => Ok(vec![listener_pid(&Runner::Local, self.port(), self.pid)?]),
         }
     }
+}
+
+#[derive(Args, Debug)]
+struct DaemonPortArgs {
+    /// The daemon's port [default: the only one running; every one for `status`]
+    #[arg(short = 'p', long = "port")]
+    port: Option<u16>,
+}
+
+#[derive(Args, Debug)]
+struct CompareArgs {
+    /// The two ports to compare; give it twice (-p 8080 -p 8081)
+    #[arg(short = 'p', long = "port", required = true, num_args = 1)]
+    ports: Vec<u16>,
+
+    /// Compare processes on a remote Linux host over SSH
+    #[arg(long = "ssh", value_name = "USER@HOST")]
+    ssh: Option<String>,
+
+    /// Seconds between samples
+    #[arg(short = 'i', long = "interval", default_value = "1")]
+    interval: u64,
+}
+
+#[derive(Args, Debug)]
+struct DiffArgs {
+    /// The first recording, the one compared against
+    #[arg(value_name = "A")]
+    a: PathBuf,
+
+    /// The second recording
+    #[arg(value_name = "B")]
+    b: PathBuf,
+
+    /// Average samples over buckets this long [default: fits the longer recording into 60]
+    #[arg(long = "bucket", value_parser = profile::parse_duration)]
+    bucket: Option<Duration>,
+
+    /// Also write an HTML report with charts here
+    #[arg(long = "html", value_name = "FILE")]
+    html: Option<PathBuf>,
+}
+
+#[derive(Args, Debug)]
+struct ReportArgs {
+    /// The recording, as written by --record
+    #[arg(long = "from", value_name = "FILE")]
+    from: PathBuf,
+
+    /// Only the process on this port [default: every port in the recording]
+    #[arg(short = 'p', long = "port")]
+    port: Option<u16>,
+
+    /// Only this much before the recording's last sample, e.g. 2h
+    #[arg(long = "last", value_parser = profile::parse_duration)]
+    last: Option<Duration>,
+
+    /// Where to write the report; Markdown for .md, HTML otherwise
+    #[arg(short = 'o', long = "out", value_name = "FILE")]
+    out: PathBuf,
+
+    /// Draw from every sample (raw) or the recording's 1m or 1h rollups [default: raw for small recordings, else the finest rollup that keeps the report fast]
+    #[arg(long = "resolution", value_enum)]
+    resolution: Option<Resolution>,
+}
+
+#[derive(Args, Debug)]
+struct ChartArgs {
+    /// What to draw
+    #[arg(long = "chart", value_enum)]
+    chart: Metric,
+
+    /// Where to write the chart; PNG for .png, SVG otherwise
+    #[arg(short = 'o', long = "out", value_name = "FILE")]
+    out: PathBuf,
+
+    /// Draw this recording, as written by --record
+    #[arg(long = "from", value_name = "FILE", conflicts_with = "pid")]
+    from: Option<PathBuf>,
+
+    /// With --from, only this port; otherwise, sample the process on it now
+    #[arg(short = 'p', long = "port", required_unless_present = "from")]
+    port: Option<u16>,
+
+    /// When several processes listen on the port, sample this one [default: the lowest PID]
+    #[arg(long = "pid")]
+    pid: Option<u32>,
+
+    /// How long to sample a live process for
+    #[arg(long = "duration", default_value = "60s", value_parser = profile::parse_duration)]
+    duration: Duration,
+
+    /// Seconds between samples of a live process
+    #[arg(short = 'i', long = "interval", default_value = "1")]
+    interval: u64,
+
+    /// Chart width in pixels
+    #[arg(long = "width", default_value = "1000")]
+    width: u32,
+
+    /// Chart height in pixels
+    #[arg(long = "height", default_value = "360")]
+    height: u32,
+}
+
+#[derive(Args, Debug)]
+struct ProfileArgs {
+    /// Port whose process to profile
+    #[arg(short = 'p', long = "port")]
+    port: u16,
+
+    /// When several processes listen on the port, profile this one [default: the lowest PID]
+    #[arg(long = "pid")]
+    pid: Option<u32>,
+
+    /// How long to sample for (e.g. 500ms, 30s, 2m)
+    #[arg(long = "duration", default_value = "30s", value_parser = profile::parse_duration)]
+    duration: Duration,
+
+    /// Samples per second
+    #[arg(long = "frequency", default_value_t = 99)]
+    frequency: u32,
+
+    /// Where to write the folded stacks [default: crabtop-<pid>.folded]
+    #[arg(short = 'o', long = "output", value_name = "PATH")]
+    output: Option<PathBuf>,
+
+    /// Also draw the stacks as an SVG flamegraph
+    #[arg(long = "svg", value_name = "PATH")]
+    svg: Option<PathBuf>,
+
+    /// Use the built-in /proc sampler even when perf is available
+    #[arg(long = "no-perf")]
+    no_perf: bool,
+}
+
+#[derive(Args, Debug)]
+struct RestartArgs {
+    /// Port whose process to restart
+    #[arg(short = 'p', long = "port")]
+    port: u16,
+
+    /// When several processes listen on the port, restart this one [default: the lowest PID]
+    #[arg(long = "pid")]
+    pid: Option<u32>,
+
+    /// Restart the process on a remote Linux host over SSH
+    #[arg(long = "ssh", value_name = "USER@HOST")]
+    ssh: Option<String>,
+
+    /// Print what would be run without running it
+    #[arg(long = "dry-run")]
+    dry_run: bool,
+}
+
+#[derive(Args, Debug)]
+struct EnvArgs {
+    /// Port whose process to read
+    #[arg(short = 'p', long = "port")]
+    port: u16,
+
+    /// When several processes listen on the port, read this one [default: the lowest PID]
+    #[arg(long = "pid")]
+    pid: Option<u32>,
+
+    /// Read the process on a remote Linux host over SSH
+    #[arg(long = "ssh", value_name = "USER@HOST")]
+    ssh: Option<String>,
+
+    /// Print the values of TOKEN, KEY, PASSWORD and similar variables too
+    #[arg(long = "show-secrets")]
+    show_secrets: bool,
+
+    /// Output format
+    #[arg(long = "format", value_enum, default_value_t = OutputFormat::Text)]
+    format: OutputFormat,
+}
+
+#[derive(Args, Debug)]
+struct TlsArgs {
+    /// Port to handshake with
+    #[arg(short = 'p', long = "port")]
+    port: u16,
+
+    /// Host to connect to
+    #[arg(long = "host", default_value = "127.0.0.1")]
+    host: String,
+
+    /// Server name to send and verify [default: --host]
+    #[arg(long = "sni", value_name = "NAME")]
+    sni: Option<String>,
+
+    /// Seconds to wait for the handshake
+    #[arg(long = "timeout", default_value = "5")]
+    timeout: u64,
+
+    /// Output format
+    #[arg(long = "format", value_enum, default_value_t = OutputFormat::Text)]
+    format: OutputFormat,
+}
+
+#[derive(Args, Debug)]
+struct AskArgs {
+    /// Target port to inspect
+    #[arg(short = 'p', long = "port")]
+    port: u16,
+
+    /// When several processes listen on the port, ask about this one
+    #[arg(long = "pid")]
+    pid: Option<u32>,
+
+    /// Seconds between background samples feeding the history
+    #[arg(short = 'i', long = "interval", default_value = "1")]
+    interval: u64,
+
+    /// Config file with `[ai]` settings [default: ~/.config/crabtop/config.toml]
+    #[arg(long = "config", value_name = "PATH", env = "CRABTOP_CONFIG")]
+    config: Option<PathBuf>,
 
-    fn max_mem(&self) -> f64 {
-        self.mem_history.iter().copied().fold(0.0f64, f64::max)
+    #[command(flatten)]
+    ai: AiArgs,
+}
+
+#[derive(Args, Debug)]
+struct ServeArgs {
+    /// Address to listen on
+    #[arg(long = "listen", env = "CRABTOP_LISTEN", default_value = "127.0.0.1:7070")]
+    listen: SocketAddr,
+
+    /// Token clients must present (`Authorization: Bearer <token>`)
+    #[arg(long = "token", env = "CRABTOP_TOKEN", hide_env_values = true)]
+    token: Option<String>,
+}
+
+/// How snapshot results are printed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+enum OutputFormat {
+    /// Human-readable lines
+    Text,
+    /// One JSON document, for scripts
+    Json,
+}
+
+/// How `run_snapshot` prints what it found.
+#[derive(Debug, Clone, Copy)]
+struct SnapshotOutput<'a> {
+    format: SnapshotFormat<'a>,
+    cpu_mode: CpuMode,
+}
+
+#[derive(Debug, Clone, Copy)]
+enum SnapshotFormat<'a> {
+    Text { quiet: bool },
+    Json,
+    /// `--field`: the values alone, one per line.
+    Fields(&'a [Field]),
+}
+
+impl SnapshotOutput<'_> {
+    fn from_format(format: OutputFormat) -> Self {
+        let format = match format {
+            OutputFormat::Text => SnapshotFormat::Text { quiet: false },
+            OutputFormat::Json => SnapshotFormat::Json,
+        };
+        SnapshotOutput {
+            format,
+            cpu_mode: CpuMode::Process,
+        }
+    }
+}
+
+impl Cli {
+    fn snapshot_output(&self) -> SnapshotOutput<'_> {
+        let format = match self.format {
+            _ if !self.fields.is_empty() => SnapshotFormat::Fields(&self.fields),
+            OutputFormat::Text => SnapshotFormat::Text { quiet: self.quiet },
+            OutputFormat::Json => SnapshotFormat::Json,
+        };
+        SnapshotOutput {
+            format,
+            cpu_mode: self.cpu_mode,
+        }
+    }
+}
+
+/// `--format json` output of a snapshot.
+#[derive(Serialize)]
+struct SnapshotReport<'a> {
+    port: u16,
+    pids: &'a [u32],
+    /// What `process.cpu_percent` is a share of.
+    cpu_mode: CpuMode,
+    process: &'a ProcessInfo,
+    listeners: &'a [Listener],
+    protocol: Option<&'a Fingerprint>,
+    container: Option<&'a Container>,
+    cgroup: Option<&'a CgroupStats>,
+    oom: Option<&'a OomStatus>,
+    insight: Option<&'a Verdict>,
+    insight_error: Option<String>,
+}
+
+/// One host of the multi-host summary under `--format json`.
+#[derive(Serialize)]
+struct FleetReport<'a> {
+    host: &'a str,
+    port: u16,
+    pids: &'a [u32],
+    process: Option<&'a ProcessInfo>,
+    error: Option<&'a str>,
+}
+
+#[derive(Args, Debug)]
+struct K8sArgs {
+    /// Pod to inspect
+    #[arg(long = "pod")]
+    pod: String,
+
+    /// Container port of the service; picks the container that declares it
+    #[arg(short = 'p', long = "port")]
+    port: u16,
+
+    /// Namespace of the pod (defaults to kubectl's current namespace)
+    #[arg(short = 'n', long = "namespace")]
+    namespace: Option<String>,
+
+    /// Container to inspect when the port alone doesn't identify it
+    #[arg(short = 'c', long = "container")]
+    container: Option<String>,
+
+    /// kubectl context to use
+    #[arg(long = "context")]
+    context: Option<String>,
+
+    /// Enable real-time monitoring mode
+    #[arg(short = 'w', long = "watch", default_value = "false")]
+    watch: bool,
+
+    /// Update interval in seconds for watch mode (metrics-server itself only refreshes every ~15s)
+    #[arg(short = 'i', long = "interval", default_value = "5")]
+    interval: u64,
+
+    /// Output format for snapshot mode
+    #[arg(long = "format", value_enum, default_value_t = OutputFormat::Text, conflicts_with = "watch")]
+    format: OutputFormat,
+
+    #[command(flatten)]
+    export: ExportArgs,
+
+    #[command(flatten)]
+    ai: AiArgs,
+}
+
+#[tokio::main]
+async fn main() {
+    let cli = Cli::try_parse().unwrap_or_else(|err| {
+        // --help and --version print to stdout and exit 0 as usual.
+        if !err.use_stderr() {
+            err.exit();
+        }
+        let _ = err.print();
+        std::process::exit(error::USAGE_EXIT_CODE);
+    });
+    // A script asking for JSON gets its errors as JSON too, on the same stream.
+    let json_errors = match &cli.command {
+        None => cli.format == OutputFormat::Json,
+        Some(Commands::Sample(args)) => args.format == OutputFormat::Json,
+        Some(_) => false,
+    };
+    if let Err(e) = run(cli).await {
+        if json_errors {
+            println!("{}", error::to_json(&e));
+        } else {
+            eprintln!("Error: {}", e);
+        }
+        std::process::exit(error::exit_code(&e));
+    }
+}
+
+async fn run(cli: Cli) -> Result<()> {
+    let level = if cli.quiet { logging::Level::ERROR } else { logging::level_for(cli.verbose) };
+    logging::init(level, cli.log_file.as_deref())?;
+    units::set_units(cli.units);
+    let config_path = match &cli.command {
+        Some(Commands::K8s(args)) => args.export.config.as_deref(),
+        Some(Commands::Daemon(DaemonArgs {
+            action: DaemonAction::Start(args) | DaemonAction::Run(args),
+        })) => args.export.config.as_deref(),
+        Some(Commands::Ask(args)) => args.config.as_deref(),
+        _ => cli.export.config.as_deref(),
+    };
+    let config = config::load(config_path)?;
+
+    match cli.command {
+        Some(Commands::K8s(ref args)) => run_k8s(args, &config).await,
+        Some(Commands::Serve(ref args)) => run_serve(args).await,
+        Some(Commands::Ask(ref args)) => run_ask(args, &config).await,
+        Some(Commands::Tls(ref args)) => run_tls(args).await,
+        Some(Commands::Env(ref args)) => run_env(args),
+        Some(Commands::Restart(ref args)) => run_restart(args).await,
+        Some(Commands::Profile(ref args)) => run_profile(args).await,
+        Some(Commands::Compare(ref args)) => run_compare(args).await,
+        Some(Commands::Baseline(ref args)) => run_baseline(args).await,
+        Some(Commands::Diff(ref args)) => run_diff(args),
+        Some(Commands::Report(ref args)) => run_report(args),
+        Some(Commands::Export(ref args)) => run_chart_export(args).await,
+        Some(Commands::Top(ref args)) => run_top(args).await,
+        Some(Commands::Ctl(ref args)) => run_ctl(args),
+        Some(Commands::Daemon(ref args)) => run_daemon(args, &config).await,
+        Some(Commands::Sample(ref args)) => run_sample(args).await,
+        None if cli.service.is_some() => run_service(&cli, &config).await,
+        None if cli.target.target().is_some() => run_target(&cli, &config).await,
+        None if cli.remote.len() > 1 || cli.hosts_file.is_some() => run_fleet(&cli, &config).await,
+        None if !cli.remote.is_empty() => run_remote(&cli, &config).await,
+        None if cli.ssh.is_some() => run_ssh(&cli, &config).await,
+        None => run_local(&cli, &config).await,
+    }
+}
+
+async fn run_local(cli: &Cli, config: &Config) -> Result<()> {
+    let port = cli.port.expect("clap requires --port without a subcommand");
+    if cli.sudo.is_some() && cfg!(target_os = "windows") {
+        return Err(anyhow!("--sudo isn't available on Windows; run from an elevated (Administrator) prompt instead"));
+    }
+    let mut source = cli.sudo.map(SystemSource::elevated).unwrap_or_default().measure_window(measure_window(cli));
+    let pids = select_pids(&source, port, cli)?;
+    let (pids, container) = resolve_container(port, pids, cli.no_docker);
+    let listeners = pids.first().and_then(|&pid| source.listeners(pid).ok()).unwrap_or_default();
+    let bound = listeners.iter().find(|l| l.port == port);
+    let target = local_target(bound.map_or("*", |l| l.address.as_str()), port);
+
+    if cli.watch {
+        // Real-time monitoring mode
+        let session = WatchSession::with_pids(source, port, pids)?.follow_matching(cli.matcher.clone())
+            .follow_upstream(cli.upstream)
+            .follow_syscalls(cli.syscalls)
+            .top_peers(cli.top_peers)
+            .resolve_peers(peer_resolver(cli)?)
+            .keep_history(history_samples(cli))
+            .cpu_mode(cli.cpu_mode)
+            .alert_on_retransmits(cli.retransmit_alert)
+            .detect_anomalies(cli.anomaly_sigma, cli.anomaly_alert)
+            .with_rules(RuleEngine::from_config(&config.rules)?)
+            .with_watchdog(watchdog(config, Some(&target))?)
+            .resume(&cli.export.resumed(port, history_samples(cli))?);
+        let mut probes = Probes::from_cli(cli, target, &session.listeners).await?;
+        probes.power = PowerMeter::detect();
+        run_watch_mode(session, container, cli.interval, cli.export.registry(config)?, cli.ai.insights(&config.ai)?, probes, cli.retransmit_alert).await
+    } else {
+        // Single snapshot mode
+        let protocol = detect_protocol(cli, &target).await;
+        run_snapshot(&mut source, port, &pids, container.as_ref(), protocol, cli.ai.insights(&config.ai)?, cli.snapshot_output()).await
+    }
+}
+
+/// `--resolve-peers` and `--geoip`; `None` without either.
+fn peer_resolver(cli: &Cli) -> Result<Option<PeerResolver>> {
+    if !cli.resolve_peers && cli.geoip.is_empty() {
+        return Ok(None);
+    }
+    let geoip = if cli.geoip.is_empty() { None } else { Some(GeoIp::open(&cli.geoip)?) };
+    PeerResolver::new(cli.resolve_peers, geoip).map(Some)
+}
+
+/// The `[watchdog]` of the config file, health checks going to `target`.
+fn watchdog(config: &Config, target: Option<&str>) -> Result<Option<Watchdog>> {
+    config.watchdog.as_ref().map(|c| Watchdog::new(c, target)).transpose()
+}
+
+/// `--target-cgroup`/`--target-container`: a snapshot or watch of a
+/// container's processes, found without a port.
+async fn run_target(cli: &Cli, config: &Config) -> Result<()> {
+    let target = cli.target.target().expect("run_target requires a --target-* flag");
+    let mut source = SystemSource::default().measure_window(measure_window(cli));
+    let pids = target.pids()?;
+
+    if cli.watch {
+        let session = WatchSession::with_pids(source, 0, pids)?
+            .follow_target(Some(target))
+            .follow_syscalls(cli.syscalls)
+            .keep_history(history_samples(cli))
+            .cpu_mode(cli.cpu_mode)
+            .detect_anomalies(cli.anomaly_sigma, cli.anomaly_alert)
+            .with_rules(RuleEngine::from_config(&config.rules)?)
+            .with_watchdog(watchdog(config, None)?)
+            .resume(&cli.export.resumed(0, history_samples(cli))?);
+        run_watch_mode(session, None, cli.interval, cli.export.registry(config)?, cli.ai.insights(&config.ai)?, Probes::default(), cli.retransmit_alert).await
+    } else {
+        run_snapshot(&mut source, 0, &pids, None, None, cli.ai.insights(&config.ai)?, cli.snapshot_output()).await
+    }
+}
+
+async fn run_ssh(cli: &Cli, config: &Config) -> Result<()> {
+    let port = cli.port.expect("clap requires --port without a subcommand");
+    let target = cli.ssh.as_deref().expect("run_ssh requires --ssh");
+    let mut source = SshSource::connect(target)?.measure_window(measure_window(cli));
+    let pids = select_pids(&source, port, cli)?;
+
+    let host = target.rsplit_once('@').map_or(target, |(_, host)| host);
+    let target = format!("{}:{}", host, port);
+
+    // docker-proxy lookups need the local docker CLI, so they don't apply here.
+    if cli.watch {
+        let session = WatchSession::with_pids(source, port, pids)?.follow_matching(cli.matcher.clone())
+            .follow_upstream(cli.upstream)
+            .follow_syscalls(cli.syscalls)
+            .top_peers(cli.top_peers)
+            .resolve_peers(peer_resolver(cli)?)
+            .keep_history(history_samples(cli))
+            .cpu_mode(cli.cpu_mode)
+            .alert_on_retransmits(cli.retransmit_alert)
+            .detect_anomalies(cli.anomaly_sigma, cli.anomaly_alert)
+            .with_rules(RuleEngine::from_config(&config.rules)?)
+            .resume(&cli.export.resumed(port, history_samples(cli))?);
+        let probes = Probes::from_cli(cli, target, &session.listeners).await?;
+        run_watch_mode(session, None, cli.interval, cli.export.registry(config)?, cli.ai.insights(&config.ai)?, probes, cli.retransmit_alert).await
+    } else {
+        let protocol = detect_protocol(cli, &target).await;
+        run_snapshot(&mut source, port, &pids, None, protocol, cli.ai.insights(&config.ai)?, cli.snapshot_output()).await
+    }
+}
+
+async fn run_remote(cli: &Cli, config: &Config) -> Result<()> {
+    let port = cli.port.expect("clap requires --port without a subcommand");
+    let addr = &cli.remote[0];
+    let token = remote_token(cli)?;
+    let mut source = RemoteSource::connect(addr, token, port).await?;
+    let pids = select_pids(&source, port, cli)?;
+
+    let host = addr.rsplit_once(':').map_or(addr.as_str(), |(host, _)| host);
+    let target = format!("{}:{}", host, port);
+
+    // docker-proxy lookups need the docker CLI on the serving host, so they are skipped here too.
+    if cli.watch {
+        let session = WatchSession::with_pids(source, port, pids)?.follow_matching(cli.matcher.clone())
+            .follow_upstream(cli.upstream)
+            .follow_syscalls(cli.syscalls)
+            .top_peers(cli.top_peers)
+            .resolve_peers(peer_resolver(cli)?)
+            .keep_history(history_samples(cli))
+            .cpu_mode(cli.cpu_mode)
+            .alert_on_retransmits(cli.retransmit_alert)
+            .detect_anomalies(cli.anomaly_sigma, cli.anomaly_alert)
+            .with_rules(RuleEngine::from_config(&config.rules)?)
+            .resume(&cli.export.resumed(port, history_samples(cli))?);
+        let probes = Probes::from_cli(cli, target, &session.listeners).await?;
+        run_watch_mode(session, None, cli.interval, cli.export.registry(config)?, cli.ai.insights(&config.ai)?, probes, cli.retransmit_alert).await
+    } else {
+        let protocol = detect_protocol(cli, &target).await;
+        run_snapshot(&mut source, port, &pids, None, protocol, cli.ai.insights(&config.ai)?, cli.snapshot_output()).await
+    }
+}
+
+/// The responsiveness checks and extra readings taken alongside each watch-mode sample.
+#[derive(Default)]
+struct Probes {
+    tcp: Option<Probe>,
+    http: Option<HttpCheck>,
+    tls: Option<TlsCheck>,
+    protocol: Option<Fingerprint>,
+    /// Only for local processes: RAPL counts the energy of this machine.
+    power: Option<PowerMeter>,
+    /// `--log-path`, read on the process's host; `None` means its journal.
+    log_path: Option<PathBuf>,
+    /// The app's own `/metrics` or `/debug/pprof`, when one answered.
+    app: Option<AppMetrics>,
+    /// Where scripts send annotations, when the socket could be bound.
+    control: Option<ControlSocket>,
+}
+
+impl Probes {
+    /// `--probe`, `--http-check` and `--tls`, aimed at `default` (`host:port`) unless
+    /// the flags name their own target. App metrics are looked for on `default`
+    /// and the process's other `listeners`.
+    async fn from_cli(cli: &Cli, default: String, listeners: &[Listener]) -> Result<Self> {
+        // Never let a hung check hold up the next tick.
+        let timeout = Duration::from_secs(cli.interval.clamp(1, 2));
+        let tcp = match cli.probe.as_deref() {
+            None => None,
+            Some("") => Some(Probe::resolve(&default, timeout, HISTORY_LEN).await?),
+            Some(target) => Some(Probe::resolve(target, timeout, HISTORY_LEN).await?),
+        };
+        let http = match &cli.http_check {
+            Some(path) => Some(HttpCheck::new(&default, path, cli.expect_status, timeout, HISTORY_LEN)?),
+            None => None,
+        };
+        let protocol = detect_protocol(cli, &default).await;
+        let app = if cli.no_app_metrics {
+            None
+        } else {
+            let host = default.rsplit_once(':').map_or(default.as_str(), |(host, _)| host);
+            let mut targets = vec![default.clone()];
+            targets.extend(listeners.iter().map(|l| format!("{}:{}", host, l.port)).filter(|t| *t != default));
+            targets.dedup();
+            AppMetrics::discover(&targets, cli.app_metric.clone(), timeout).await
+        };
+        let tls = cli.tls.then(|| TlsCheck::new(default, cli.sni.clone(), Duration::from_secs(5)));
+        // Only an explicit --control has to work; the default is a convenience.
+        let control = match (&cli.control, cli.port) {
+            (Some(path), _) => Some(ControlSocket::bind(path)?),
+            (None, Some(port)) => ControlSocket::bind(&control::default_path(port)).ok(),
+            (None, None) => None,
+        };
+        Ok(Self {
+            tcp,
+            http,
+            tls,
+            protocol,
+            power: None,
+            log_path: cli.log_path.clone(),
+            app,
+            control,
+        })
+    }
+
+    /// Failures are part of the picture; the panels count them.
+    async fn tick(&mut self, info: &ProcessInfo) {
+        if let Some(power) = self.power.as_mut() {
+            power.update(info.cpu_percent);
+        }
+        if let Some(tcp) = self.tcp.as_mut() {
+            let _ = tcp.tick().await;
+        }
+        if let Some(http) = self.http.as_mut() {
+            http.tick().await;
+        }
+        if let Some(tls) = self.tls.as_mut() {
+            tls.tick().await;
+        }
+        if let Some(app) = self.app.as_mut() {
+            app.tick().await;
+        }
+    }
+}
+
+/// Samples `--history-duration` covers at the watch interval.
+fn history_samples(cli: &Cli) -> usize {
+    (cli.history_duration.as_secs() / cli.interval.max(1)) as usize
+}
+
+/// `--measure-ms`: how long a first sample measures CPU over.
+fn measure_window(cli: &Cli) -> Duration {
+    Duration::from_millis(cli.measure_ms)
+}
+
+/// Follows the port to the restarted service's new process, giving it up to
+/// five seconds to listen again. Returns the line to show under the panel.
+async fn reattach_after_restart<S: MetricsSource>(session: &mut WatchSession<S>, unit: &str) -> String {
+    for _ in 0..10 {
+        if session.reattach().is_ok() {
+            return format!("✓ Restarted {} (PID {})", unit, session.pid);
+        }
+        tokio::time::sleep(Duration::from_millis(500)).await;
+    }
+    format!("Restarted {}, but nothing is listening on port {} yet", unit, session.port)
+}
+
+/// A one-off protocol fingerprint of `target`: from its greeting alone,
+/// with probes under `--fingerprint`, or none under `--no-fingerprint`.
+async fn detect_protocol(cli: &Cli, target: &str) -> Option<Fingerprint> {
+    if cli.no_fingerprint {
+        return None;
+    }
+    if cli.fingerprint {
+        return fingerprint::detect(target, Duration::from_secs(1)).await;
+    }
+    fingerprint::banner(target, Duration::from_secs(1)).await
+}
+
+fn remote_token(cli: &Cli) -> Result<String> {
+    cli.token
+        .clone()
+        .context("--remote needs the server's token (--token or CRABTOP_TOKEN)")
+}
+
+/// Several `serve` hosts at once: a grid in watch mode, one line per host otherwise.
+async fn run_fleet(cli: &Cli, config: &Config) -> Result<()> {
+    let port = cli.port.expect("clap requires --port without a subcommand");
+    let token = remote_token(cli)?;
+    let mut targets: Vec<FleetTarget> = cli
+        .remote
+        .iter()
+        .map(|addr| FleetTarget {
+            addr: addr.clone(),
+            port,
+        })
+        .collect();
+    if let Some(path) = &cli.hosts_file {
+        let text = fs::read_to_string(path).with_context(|| format!("Failed to read hosts file {}", path.display()))?;
+        targets.extend(parse_hosts_file(&text, port)?);
+    }
+    if targets.is_empty() {
+        return Err(anyhow!("No hosts to watch"));
+    }
+    let mut members: Vec<FleetMember> = targets.into_iter().map(FleetMember::new).collect();
+
+    if cli.watch {
+        return run_fleet_watch(members, &token, cli.interval, cli.export.registry(config)?).await;
+    }
+
+    join_all(members.iter_mut().map(|m| m.tick(&token))).await;
+    if cli.format == OutputFormat::Json {
+        let hosts: Vec<FleetReport> = members
+            .iter()
+            .map(|m| FleetReport {
+                host: &m.target.addr,
+                port: m.target.port,
+                pids: m.session.as_ref().map(|s| s.pids.as_slice()).unwrap_or_default(),
+                process: m.last.as_ref(),
+                error: m.error.as_deref(),
+            })
+            .collect();
+        println!("{}", serde_json::to_string_pretty(&hosts)?);
+        return Ok(());
+    }
+    for member in &members {
+        match (&member.last, &member.error) {
+            (Some(info), _) => println!(
+                "{}: {} (PID {}) CPU {:.2}% Memory {}",
+                member.label(),
+                info.name,
+                member.session.as_ref().map(|s| join_pids(&s.pids)).unwrap_or_default(),
+                info.cpu_percent,
+                format_mb(info.memory_bytes)
+            ),
+            (None, error) => println!("{}: unavailable: {}", member.label(), error.as_deref().unwrap_or("-")),
+        }
+    }
+    Ok(())
+}
+
+async fn run_serve(args: &ServeArgs) -> Result<()> {
+    let token = args
+        .token
+        .clone()
+        .filter(|t| !t.trim().is_empty())
+        .context("serve needs a token (--token or CRABTOP_TOKEN); the API exposes process details")?;
+    eprintln!("Serving on http://{} (Ctrl-C to stop)", args.listen);
+    server::serve(args.listen, token).await
+}
+
+async fn run_k8s(args: &K8sArgs, config: &Config) -> Result<()> {
+    let source = KubeSource::connect(
+        &args.pod,
+        args.port,
+        args.namespace.clone(),
+        args.context.clone(),
+        args.container.as_deref(),
+    )?;
+    let container = source.as_container();
+
+    if args.watch {
+        let session = WatchSession::new(source, args.port)?;
+        run_watch_mode(session, Some(container), args.interval, args.export.registry(config)?, args.ai.insights(&config.ai)?, Probes::default(), DEFAULT_RETRANSMIT_ALERT).await
+    } else {
+        let mut source = source;
+        run_snapshot(&mut source, args.port, &[0], Some(&container), None, args.ai.insights(&config.ai)?, SnapshotOutput::from_format(args.format)).await
+    }
+}
+
+/// What the `ask` sampler has seen most recently.
+struct Live {
+    info: ProcessInfo,
+    history: ProcessHistory,
+    system: Option<SystemSnapshot>,
+    error: Option<String>,
+}
+
+/// `crabtop tls`: one handshake, printed.
+async fn run_tls(args: &TlsArgs) -> Result<()> {
+    let host = if args.host.contains(':') && !args.host.starts_with('[') {
+        format!("[{}]", args.host)
+    } else {
+        args.host.clone()
+    };
+    let target = format!("{}:{}", host, args.port);
+    let info = tls::inspect(&target, args.sni.as_deref(), Duration::from_secs(args.timeout)).await?;
+    match args.format {
+        OutputFormat::Json => println!("{}", serde_json::to_string_pretty(&info)?),
+        OutputFormat::Text => print_tls(&info)?,
+    }
+    Ok(())
+}
+
+fn print_tls(info: &TlsInfo) -> Result<()> {
+    println!("TLS on {} (SNI {})", info.target, info.server_name);
+    println!("Protocol: {}", info.protocol);
+    println!("Cipher: {}", info.cipher);
+    if let Some(alpn) = &info.alpn {
+        println!("ALPN: {}", alpn);
+    }
+    println!("Handshake: {:.2} ms", info.handshake_ms);
+    if let Some(cert) = &info.certificate {
+        println!("Subject: {}", cert.subject);
+        println!("Issuer: {}", cert.issuer);
+        if !cert.san.is_empty() {
+            println!("SAN: {}", cert.san.join(", "));
+        }
+        println!("Valid: {} to {}", cert.not_before.format("%Y-%m-%d"), cert.not_after.format("%Y-%m-%d"));
+        let now = chrono::Utc::now();
+        let days = cert.days_until_expiry(now);
+        let expiry = if days < 0 {
+            format!("Expired {} days ago", -days)
+        } else {
+            format!("Expires in {} days", days)
+        };
+        if cert.expires_soon(now) {
+            let mut stdout = io::stdout();
+            if stdout.is_terminal() {
+                execute!(
+                    stdout,
+                    SetForegroundColor(Color::Red),
+                    Print(format!("Warning: {}\n", expiry)),
+                    ResetColor,
+                )?;
+            } else {
+                println!("Warning: {}", expiry);
+            }
+        } else {
+            println!("{}", expiry);
+        }
+    }
+    match &info.verified {
+        Ok(()) => println!("Verified: yes"),
+        Err(reason) => println!("Verified: no ({})", reason),
+    }
+    Ok(())
+}
+
+/// The `ask` REPL. A background task keeps sampling so the history (and its
+/// averages and trend) grows between questions.
+/// `--ssh` for the one-shot subcommands.
+fn subcommand_runner(ssh: &Option<String>) -> Runner {
+    match ssh {
+        Some(target) => Runner::Ssh(target.clone()),
+        None => Runner::Local,
+    }
+}
+
+/// The PID listening on `port` (or `--pid`, checked against the listeners); the lowest if several.
+fn listener_pid(runner: &Runner, port: u16, pid: Option<u32>) -> Result<u32> {
+    let pids = find_pids_by_port_on(runner, port)
+        .with_context(|| format!("No process found listening on port {}{}", port, runner.describe()))?;
+    match pid {
+        Some(pid) if !pids.contains(&pid) => {
+            Err(anyhow!("PID {} is not listening on port {} (listeners: {})", pid, port, join_pids(&pids)))
+        }
+        Some(pid) => Ok(pid),
+        None => Ok(pids[0]),
+    }
+}
+
+fn run_env(args: &EnvArgs) -> Result<()> {
+    let runner = subcommand_runner(&args.ssh);
+    let pid = listener_pid(&runner, args.port, args.pid)?;
+    let raw = match runner {
+        Runner::Local | Runner::Elevated(_) => read_environ(pid)?,
+        Runner::Ssh(_) => read_environ_on(&runner, pid)?,
+    };
+    let vars = parse_environ(&raw, args.show_secrets);
+    match args.format {
+        OutputFormat::Json => println!("{}", serde_json::to_string_pretty(&vars)?),
+        OutputFormat::Text => {
+            let masked = vars.iter().filter(|v| v.masked).count();
+            println!("Environment of PID {} ({} variables, {} masked)", pid, vars.len(), masked);
+            for var in &vars {
+                println!("{}={}", var.name, var.value);
+            }
+            if masked > 0 {
+                eprintln!("Secret-looking values are masked; pass --show-secrets to print them.");
+            }
+        }
+    }
+    Ok(())
+}
+
+async fn run_restart(args: &RestartArgs) -> Result<()> {
+    let runner = subcommand_runner(&args.ssh);
+    let pid = listener_pid(&runner, args.port, args.pid)?;
+    let plan = restart::plan(&runner, args.port, pid);
+    let command = plan.command().join(" ");
+    println!("Port {} is served by PID {}{} ({}).", args.port, pid, runner.describe(), plan);
+    if args.dry_run {
+        println!("Would run: {}", command);
+        return Ok(());
+    }
+    println!("Running: {}", command);
+    restart::execute(&runner, &plan)?;
+
+    // Give the service a moment to come back, and say whether it did.
+    for _ in 0..20 {
+        tokio::time::sleep(Duration::from_millis(500)).await;
+        if let Ok(pids) = find_pids_by_port_on(&runner, args.port) {
+            if !pids.contains(&pid) {
+                println!("Port {} is being served by PID {}.", args.port, join_pids(&pids));
+                return Ok(());
+            }
+            // A HUP usually means a reload in place, so the same PID is the good outcome.
+            if matches!(plan, RestartPlan::Sighup { .. }) {
+                println!("PID {} is still serving port {}.", pid, args.port);
+                return Ok(());
+            }
+        }
+    }
+    Err(anyhow!("Ran `{}`, but nothing new is listening on port {} after 10 seconds", command, args.port))
+}
+
+async fn run_profile(args: &ProfileArgs) -> Result<()> {
+    let pid = listener_pid(&Runner::Local, args.port, args.pid)?;
+    println!("Profiling PID {} on port {} for {:?}...", pid, args.port, args.duration);
+    let (duration, frequency, no_perf) = (args.duration, args.frequency, args.no_perf);
+    let (folded, sampler) = tokio::task::spawn_blocking(move || -> Result<(Folded, &str)> {
+        if !no_perf {
+            match profile::perf_profile(pid, duration, frequency) {
+                Ok(folded) if !folded.is_empty() => return Ok((folded, "perf")),
+                Ok(_) => warn!("perf recorded no samples; falling back to the built-in sampler"),
+                Err(err) => warn!("{:#}; falling back to the built-in sampler", err),
+            }
+        }
+        Ok((profile::procfs_profile(pid, duration, frequency)?, "built-in /proc sampler"))
+    })
+    .await??;
+
+    let output = args.output.clone().unwrap_or_else(|| PathBuf::from(format!("crabtop-{}.folded", pid)));
+    profile::write_folded(&output, &folded)?;
+    let samples: u64 = folded.values().sum();
+    println!("{} samples ({} distinct stacks) from {} written to {}", samples, folded.len(), sampler, output.display());
+    if let Some(svg) = &args.svg {
+        let title = format!("PID {} on port {} ({})", pid, args.port, sampler);
+        fs::write(svg, profile::render_flamegraph(&folded, &title))
+            .with_context(|| format!("Failed to write {}", svg.display()))?;
+        println!("Flamegraph written to {}", svg.display());
+    }
+    Ok(())
+}
+
+async fn run_compare(args: &CompareArgs) -> Result<()> {
+    let &[a, b] = args.ports.as_slice() else {
+        return Err(anyhow!("compare takes exactly two ports (-p A -p B), got {}", args.ports.len()));
+    };
+    match &args.ssh {
+        Some(target) => {
+            let sessions = (
+                WatchSession::new(SshSource::connect(target)?, a)?,
+                WatchSession::new(SshSource::connect(target)?, b)?,
+            );
+            run_compare_mode(sessions, args.interval).await
+        }
+        None => run_compare_mode((WatchSession::new(SystemSource::default(), a)?, WatchSession::new(SystemSource::default(), b)?), args.interval).await,
+    }
+}
+
+/// Both sessions tick together so each frame compares the same interval.
+async fn run_compare_mode<S: MetricsSource>(
+    (mut a, mut b): (WatchSession<S>, WatchSession<S>),
+    interval_secs: u64,
+) -> Result<()> {
+    let mut stdout = io::stdout();
+    // Log lines would draw over the dashboard; they're kept for 'L' instead.
+    let quiet = logging::pause_stderr();
+    terminal::enable_raw_mode().context("Failed to enable raw mode")?;
+
+    let result = async {
+        loop {
+            let (info_a, info_b) = futures_util::join!(a.tick(), b.tick());
+            let (info_a, info_b) = (info_a?, info_b?);
+
+            execute!(stdout, terminal::Clear(ClearType::All), cursor::MoveTo(0, 0))?;
+            let left = CompareSide {
+                port: a.port,
+                info: &info_a,
+                pids: &a.pids,
+                history: &a.history,
+            };
+            let right = CompareSide {
+                port: b.port,
+                info: &info_b,
+                pids: &b.pids,
+                history: &b.history,
+            };
+            render_compare(&mut stdout, &left, &right, get_terminal_size().0, Local::now().naive_local())?;
+            stdout.flush()?;
+
+            let poll_duration = Duration::from_millis(100);
+            let total_sleep = Duration::from_secs(interval_secs.max(1));
+            let mut elapsed = Duration::ZERO;
+            while elapsed < total_sleep {
+                if event::poll(poll_duration)? {
+                    match event::read()? {
+                        Event::Key(key) if matches!(key.code, KeyCode::Char('q' | 'c') | KeyCode::Esc) => return Ok(()),
+                        Event::Resize(..) => break,
+                        _ => {}
+                    }
+                }
+                elapsed += poll_duration;
+            }
+        }
+    }
+    .await;
+
+    terminal::disable_raw_mode()?;
+    execute!(stdout, cursor::Show)?;
+    drop(quiet);
+    result
+}
+
+/// A listening process as `top` follows it from one sample to the next.
+struct TopProcess {
+    ports: Vec<u16>,
+    info: Option<ProcessInfo>,
+    history: ProcessHistory,
+}
+
+/// What `top` keeps across drilling into a process and coming back.
+struct TopState {
+    processes: BTreeMap<u32, TopProcess>,
+    sort: TopSort,
+    selected: Option<u32>,
+}
+
+fn run_ctl(args: &CtlArgs) -> Result<()> {
+    let socket = match &args.socket {
+        Some(path) => path.clone(),
+        None => control::find_socket(args.port)?,
+    };
+    let mut line = args.command.join(" ");
+    // The session may run in another directory; make the path mean this one.
+    if let Ok(ControlCommand::Snapshot(Some(path))) = control::parse_command(&line) {
+        line = format!("snapshot {}", std::path::absolute(&path)?.display());
+    }
+    let answer = control::send(&socket, &line)?;
+    if !answer.is_empty() {
+        println!("{}", answer);
+    }
+    Ok(())
+}
+
+async fn run_daemon(args: &DaemonArgs, config: &Config) -> Result<()> {
+    match &args.action {
+        DaemonAction::Start(args) => start_daemon(args),
+        DaemonAction::Run(args) => run_daemon_loop(args, config).await,
+        DaemonAction::Status(args) => daemon_status(args.port),
+        DaemonAction::Stop(args) => stop_daemon(args.port).await,
+        DaemonAction::Attach(args) => attach_daemon(args.port).await,
+    }
+}
+
+/// The running daemon for `port`, or the only one when no port is given.
+fn running_daemon(port: Option<u16>) -> Result<DaemonState> {
+    let running: Vec<DaemonState> = match port {
+        Some(port) => DaemonState::load(port)?.into_iter().collect(),
+        None => DaemonState::all(),
+    };
+    let (mut running, stale): (Vec<DaemonState>, Vec<DaemonState>) = running.into_iter().partition(DaemonState::is_running);
+    for state in stale {
+        DaemonState::remove(state.port);
+    }
+    match (running.len(), port) {
+        (1, _) => Ok(running.remove(0)),
+        (0, Some(port)) => Err(anyhow!("No daemon is watching port {}", port)),
+        (0, None) => Err(anyhow!("No daemon is running (start one with `crabtop daemon start --port <PORT>`)")),
+        _ => Err(anyhow!(
+            "Several daemons are running (ports {}); pass --port",
+            running.iter().map(|d| d.port.to_string()).collect::<Vec<_>>().join(", ")
+        )),
+    }
+}
+
+/// Runs this binary again as `daemon run` with the same options, detached,
+/// and waits for it to start sampling.
+fn start_daemon(args: &DaemonStartArgs) -> Result<()> {
+    if let Some(running) = DaemonState::load(args.port())?.filter(DaemonState::is_running) {
+        return Err(anyhow!("A daemon is already watching {} (PID {})", args.watched(), running.pid));
+    }
+    // Fail here, where someone reads it, rather than in the log.
+    args.pids()?;
+
+    let mut argv: Vec<String> = std::env::args().skip(1).collect();
+    let daemon = argv.iter().position(|a| a == "daemon").context("`start` runs under `daemon`")?;
+    let start = argv[daemon..].iter().position(|a| a == "start").context("`start` runs under `daemon`")? + daemon;
+    argv[start] = "run".to_string();
+    let log = DaemonState::log_path(args.port());
+    let mut child = daemon::spawn_detached(&std::env::current_exe()?, &argv, &log)?;
+
+    let deadline = Instant::now() + Duration::from_secs(10);
+    while Instant::now() < deadline {
+        if let Some(status) = child.try_wait()? {
+            return Err(anyhow!("The daemon exited ({}) before it started sampling; see {}", status, log.display()));
+        }
+        if let Some(state) = DaemonState::load(args.port())?.filter(|s| s.pid == child.id()) {
+            println!("Watching {} in the background (daemon PID {}).", args.watched(), state.pid);
+            println!("Log: {}", state.log.display());
+            println!("Check on it with `crabtop daemon status`, watch it with `crabtop daemon attach`.");
+            return Ok(());
+        }
+        std::thread::sleep(Duration::from_millis(100));
+    }
+    Err(anyhow!("The daemon (PID {}) didn't start sampling within 10s; see {}", child.id(), log.display()))
+}
+
+/// Watch mode with no terminal: every sample, annotation and alert goes to
+/// the exporters, and the control socket is the way in.
+async fn run_daemon_loop(args: &DaemonStartArgs, config: &Config) -> Result<()> {
+    let port = args.port();
+    let mut session = WatchSession::with_pids(SystemSource::default(), port, args.pids()?)?
+        .follow_target(args.target.target())
+        .alert_on_retransmits(args.retransmit_alert)
+        .detect_anomalies(args.anomaly_sigma, args.anomaly_alert)
+        .with_rules(RuleEngine::from_config(&config.rules)?)
+        .with_watchdog(watchdog(config, args.target.target().is_none().then(|| local_target("*", port)).as_deref())?)
+        .resume(&args.export.resumed(port, HISTORY_LEN)?);
+    let mut exporters = args.export.registry(config)?;
+    let socket = control::default_path(port);
+    let mut control = ControlSocket::bind(&socket)?;
+    let mut interval_secs = args.interval.max(1);
+    let shutdown = shutdown_signal();
+    tokio::pin!(shutdown);
+
+    DaemonState {
+        pid: std::process::id(),
+        port,
+        interval_secs,
+        started: Local::now(),
+        start_ticks: daemon::start_ticks(std::process::id()),
+        socket,
+        log: DaemonState::log_path(port),
+    }
+    .save()?;
+    println!("{} Watching PID {} on {} every {}s", Local::now().format("%F %T"), join_pids(&session.pids), args.watched(), interval_secs);
+
+    // The last good sample, for `stats` while the process is gone.
+    let mut last: Option<ProcessInfo> = None;
+    let result = async {
+        loop {
+            match session.tick().await {
+                Ok(info) => {
+                    export_tick(&mut session, &info, &mut exporters, Some(&control));
+                    last = Some(info);
+                }
+                // Keep trying the port: whatever comes back on it is the restart.
+                Err(err) if last.is_some() => {
+                    export_alerts(&mut session, &mut exporters);
+                    println!("{} {:#}; waiting for {} to be back", Local::now().format("%F %T"), err, args.watched());
+                    last = None;
+                }
+                Err(_) => {
+                    if session.reattach().is_ok() {
+                        println!("{} {} is PID {} now", Local::now().format("%F %T"), args.watched(), join_pids(&session.pids));
+                    }
+                }
+            }
+
+            let wake = tokio::time::Instant::now() + Duration::from_secs(interval_secs);
+            loop {
+                tokio::select! {
+                    _ = tokio::time::sleep_until(wake) => break,
+                    _ = &mut shutdown => return Ok(()),
+                    _ = tokio::time::sleep(Duration::from_millis(100)) => {}
+                }
+                while let Some(request) = control.try_recv() {
+                    let Some(info) = last.as_ref() else {
+                        request.fail(format!("{} isn't running right now", args.watched()));
+                        continue;
+                    };
+                    match handle_control(request, &mut session, info, &mut interval_secs) {
+                        ControlOutcome::Snapshot(_, request) => {
+                            request.fail("a daemon has no dashboard to snapshot; `crabtop daemon attach` shows it")
+                        }
+                        ControlOutcome::Stop => return Ok(()),
+                        ControlOutcome::Answered | ControlOutcome::Changed => {}
+                    }
+                }
+            }
+        }
+    }
+    .await;
+
+    println!("{} Stopping", Local::now().format("%F %T"));
+    for failure in exporters.on_shutdown() {
+        println!("Warning: exporter {}", failure);
+    }
+    DaemonState::remove(port);
+    result
+}
+
+/// Ctrl-C, or SIGTERM from `kill` or a service manager.
+async fn shutdown_signal() {
+    #[cfg(unix)]
+    if let Ok(mut terminate) = tokio::signal::unix::signal(tokio::signal::unix::SignalKind::terminate()) {
+        tokio::select! {
+            _ = terminate.recv() => {}
+            _ = tokio::signal::ctrl_c() => {}
+        }
+        return;
+    }
+    let _ = tokio::signal::ctrl_c().await;
+}
+
+fn daemon_status(port: Option<u16>) -> Result<()> {
+    let states: Vec<DaemonState> = match port {
+        Some(port) => DaemonState::load(port)?.into_iter().collect(),
+        None => DaemonState::all(),
+    };
+    let mut shown = 0;
+    for state in states {
+        if !state.is_running() {
+            // Killed without the chance to clean up after itself.
+            DaemonState::remove(state.port);
+            continue;
+        }
+        shown += 1;
+        let uptime = (Local::now() - state.started).num_seconds().max(0) as u64;
+        println!("Port {}: daemon PID {}, up {}, log {}", state.port, state.pid, format_duration(uptime), state.log.display());
+        let latest = control::send(&state.socket, "stats")
+            .and_then(|json| serde_json::from_str::<ExportSample>(&json).map_err(anyhow::Error::from));
+        match latest {
+            Ok(sample) => println!(
+                "  {} (PID {}): CPU {:.1}%, memory {}{}",
+                sample.process.name,
+                sample.process.pid,
+                sample.process.cpu_percent,
+                format_bytes(sample.process.memory_bytes),
+                sample.connections.map(|n| format!(", {} connections", n)).unwrap_or_default()
+            ),
+            Err(err) => println!("  {}", err),
+        }
+    }
+    match (shown, port) {
+        (0, Some(port)) => Err(anyhow!("No daemon is watching port {}", port)),
+        (0, None) => {
+            println!("No daemons running.");
+            Ok(())
+        }
+        _ => Ok(()),
+    }
+}
+
+/// Asks the daemon to stop, so its exporters flush, and signals it if it doesn't answer.
+async fn stop_daemon(port: Option<u16>) -> Result<()> {
+    let state = running_daemon(port)?;
+    if control::send(&state.socket, "stop").is_err() {
+        // Checked again just before signalling: it may have exited, and its PID gone to another process.
+        if !state.is_running() {
+            DaemonState::remove(state.port);
+            return Err(anyhow!("PID {} is no longer the daemon for port {}; removed its stale state file", state.pid, state.port));
+        }
+        std::process::Command::new("kill").arg(state.pid.to_string()).status().context("Failed to run kill")?;
+    }
+    for _ in 0..50 {
+        if !state.is_running() {
+            println!("Stopped the daemon for port {}.", state.port);
+            return Ok(());
+        }
+        tokio::time::sleep(Duration::from_millis(100)).await;
+    }
+    Err(anyhow!("The daemon for port {} (PID {}) is still running after 5s", state.port, state.pid))
+}
+
+#[cfg(unix)]
+async fn attach_daemon(port: Option<u16>) -> Result<()> {
+    let state = running_daemon(port)?;
+    let source = daemon::DaemonSource::connect(&state.socket).await?;
+    let session = WatchSession::new(source, state.port)?;
+    run_watch_mode(session, None, state.interval_secs, ExporterRegistry::new(), None, Probes::default(), DEFAULT_RETRANSMIT_ALERT).await
+}
+
+#[cfg(not(unix))]
+async fn attach_daemon(_port: Option<u16>) -> Result<()> {
+    Err(anyhow!("Daemon mode needs a Unix domain socket, which this platform doesn't have"))
+}
+
+async fn run_top(args: &TopArgs) -> Result<()> {
+    let mut state = TopState {
+        processes: BTreeMap::new(),
+        sort: args.sort,
+        selected: None,
+    };
+    loop {
+        let mut stdout = io::stdout();
+        let quiet = logging::pause_stderr();
+        terminal::enable_raw_mode().context("Failed to enable raw mode")?;
+        let picked = run_top_mode(&mut state, args).await;
+        terminal::disable_raw_mode()?;
+        drop(quiet);
+        execute!(stdout, cursor::Show)?;
+        // Enter watches the selected process; quitting that comes back here.
+        let Some((port, pid)) = picked? else {
+            return Ok(());
+        };
+        let session = WatchSession::with_pids(SystemSource::default(), port, vec![pid])?;
+        run_watch_mode(session, None, args.interval, ExporterRegistry::new(), None, Probes::default(), DEFAULT_RETRANSMIT_ALERT).await?;
+    }
+}
+
+/// Samples every listening process each interval until one is picked
+/// (its first port and PID) or the user quits.
+async fn run_top_mode(state: &mut TopState, args: &TopArgs) -> Result<Option<(u16, u32)>> {
+    let mut stdout = io::stdout();
+    // Moving the selection or re-sorting redraws without re-sampling.
+    let mut resample = true;
+    let mut counts = None;
+    loop {
+        if resample {
+            let mut ports: BTreeMap<u32, Vec<u16>> = BTreeMap::new();
+            for socket in listening_sockets()? {
+                ports.entry(socket.pid).or_default().push(socket.listener.port);
+            }
+            state.processes.retain(|pid, _| ports.contains_key(pid));
+            for (pid, mut pid_ports) in ports {
+                pid_ports.dedup();
+                let process = state.processes.entry(pid).or_insert_with(|| TopProcess {
+                    ports: Vec::new(),
+                    info: None,
+                    history: ProcessHistory::new(HISTORY_LEN),
+                });
+                process.ports = pid_ports;
+            }
+            let infos = join_all(state.processes.keys().map(|&pid| collect_process_info(pid))).await;
+            for (process, info) in state.processes.values_mut().zip(infos) {
+                process.info = info.ok();
+                if let Some(info) = &process.info {
+                    process.history.add(info);
+                }
+            }
+            counts = connection_counts_on(&Runner::Local);
+        }
+        resample = true;
+
+        let mut rows: Vec<TopRow> = state
+            .processes
+            .values()
+            .filter_map(|p| {
+                Some(TopRow {
+                    info: p.info.as_ref()?,
+                    ports: &p.ports,
+                    connections: counts.as_ref().map(|c: &HashMap<u16, usize>| p.ports.iter().filter_map(|port| c.get(port)).sum()),
+                    history: &p.history,
+                })
+            })
+            .collect();
+        let total = rows.len();
+        sort_top_rows(&mut rows, state.sort);
+        let (width, height) = get_terminal_size();
+        rows.truncate(args.count.min((height as usize).saturating_sub(6)).max(1));
+        let mut selected = state
+            .selected
+            .and_then(|pid| rows.iter().position(|r| r.info.pid == pid))
+            .unwrap_or(0);
+        state.selected = rows.get(selected).map(|r| r.info.pid);
+
+        execute!(stdout, terminal::Clear(ClearType::All), cursor::MoveTo(0, 0))?;
+        render_top(&mut stdout, &rows, total, state.sort, selected, width, Local::now().naive_local())?;
+        stdout.flush()?;
+
+        let poll_duration = Duration::from_millis(100);
+        let total_sleep = Duration::from_secs(args.interval.max(1));
+        let mut elapsed = Duration::ZERO;
+        while elapsed < total_sleep {
+            if event::poll(poll_duration)? {
+                match event::read()? {
+                    Event::Key(key) => match key.code {
+                        KeyCode::Char('q' | 'c') | KeyCode::Esc => return Ok(None),
+                        KeyCode::Up | KeyCode::Char('k') => selected = selected.saturating_sub(1),
+                        KeyCode::Down | KeyCode::Char('j') => selected = (selected + 1).min(rows.len().saturating_sub(1)),
+                        KeyCode::Char('s') => state.sort = state.sort.next(),
+                        KeyCode::Enter => {
+                            if let Some(row) = rows.get(selected) {
+                                return Ok(row.ports.first().map(|&port| (port, row.info.pid)));
+                            }
+                            continue;
+                        }
+                        _ => continue,
+                    },
+                    Event::Resize(..) => {}
+                    _ => continue,
+                }
+                state.selected = rows.get(selected).map(|r| r.info.pid);
+                resample = false;
+                break;
+            }
+            elapsed += poll_duration;
+        }
+    }
+}
+
+async fn run_service(cli: &Cli, config: &Config) -> Result<()> {
+    let name = cli.service.as_deref().expect("run_service requires --service");
+    let mut session = ServiceSession::new(SystemSource::default(), service::find(&config.services, name)?)?;
+    session.tick().await?;
+    let mut selected = 0;
+    loop {
+        let mut stdout = io::stdout();
+        let quiet = logging::pause_stderr();
+        terminal::enable_raw_mode().context("Failed to enable raw mode")?;
+        let picked = run_service_mode(&mut session, &mut selected, cli.interval).await;
+        terminal::disable_raw_mode()?;
+        drop(quiet);
+        execute!(stdout, cursor::Show)?;
+        // Enter watches the selected port; quitting that comes back here.
+        let Some((port, pids)) = picked? else {
+            return Ok(());
+        };
+        let member = WatchSession::with_pids(SystemSource::default(), port, pids)?;
+        run_watch_mode(member, None, cli.interval, ExporterRegistry::new(), None, Probes::default(), DEFAULT_RETRANSMIT_ALERT).await?;
+    }
+}
+
+/// Samples the service each interval until a running port member is picked
+/// (its port and PIDs) or the user quits. The first sample is already taken.
+async fn run_service_mode(session: &mut ServiceSession<SystemSource>, selected: &mut usize, interval: u64) -> Result<Option<(u16, Vec<u32>)>> {
+    let mut stdout = io::stdout();
+    // Moving the selection redraws without re-sampling.
+    let mut resample = false;
+    loop {
+        if resample {
+            // With every member down, the rows say so; it may come back.
+            let _ = session.tick().await;
+        }
+        resample = true;
+
+        let (width, _) = get_terminal_size();
+        execute!(stdout, terminal::Clear(ClearType::All), cursor::MoveTo(0, 0))?;
+        render_service(&mut stdout, &session.name, session.total.as_ref(), &session.history, &session.members, *selected, width, Local::now().naive_local())?;
+        stdout.flush()?;
+
+        let poll_duration = Duration::from_millis(100);
+        let total_sleep = Duration::from_secs(interval.max(1));
+        let mut elapsed = Duration::ZERO;
+        while elapsed < total_sleep {
+            if event::poll(poll_duration)? {
+                match event::read()? {
+                    Event::Key(key) => match key.code {
+                        KeyCode::Char('q' | 'c') | KeyCode::Esc => return Ok(None),
+                        KeyCode::Up | KeyCode::Char('k') => *selected = selected.saturating_sub(1),
+                        KeyCode::Down | KeyCode::Char('j') => *selected = (*selected + 1).min(session.members.len().saturating_sub(1)),
+                        KeyCode::Enter => match session.members.get(*selected) {
+                            Some(Member {
+                                kind: MemberKind::Port(port),
+                                pids,
+                                ..
+                            }) if !pids.is_empty() => return Ok(Some((*port, pids.clone()))),
+                            _ => continue,
+                        },
+                        _ => continue,
+                    },
+                    Event::Resize(..) => {}
+                    _ => continue,
+                }
+                resample = false;
+                break;
+            }
+            elapsed += poll_duration;
+        }
+    }
+}
+
+async fn run_baseline(args: &BaselineArgs) -> Result<()> {
+    match &args.action {
+        BaselineAction::Save(args) => {
+            let captured = capture_baseline(args.port, args.pid, args.duration, args.interval).await?;
+            captured.save(&args.output)?;
+            println!(
+                "Saved baseline of {} on port {} ({} samples over {:.0}s) to {}",
+                captured.process,
+                captured.port,
+                captured.samples,
+                captured.duration_secs,
+                args.output.display()
+            );
+            print_baseline_summary(&captured);
+            Ok(())
+        }
+        BaselineAction::Check(args) => {
+            let saved = Baseline::load(&args.against)?;
+            let duration = args.duration.unwrap_or_else(|| Duration::from_secs_f64(saved.duration_secs));
+            let current = capture_baseline(args.port.unwrap_or(saved.port), args.pid, duration, args.interval).await?;
+            println!(
+                "Checking {} on port {} against {} (saved {}), tolerance {}%",
+                current.process,
+                current.port,
+                args.against.display(),
+                saved.captured_at.format("%Y-%m-%d %H:%M"),
+                args.tolerance
+            );
+            let rows = baseline::check(&saved, &current, args.tolerance);
+            for row in &rows {
+                let show = |value: f64| if row.is_memory() { format_bytes(value as u64) } else { format!("{:.2}%", value) };
+                println!(
+                    "  {} {:<10}  baseline {:>10}  now {:>10}  limit {:>10}",
+                    if row.passed() { "✓" } else { "✗" },
+                    row.metric,
+                    show(row.baseline),
+                    show(row.current),
+                    show(row.limit)
+                );
+            }
+            let failed = rows.iter().filter(|r| !r.passed()).count();
+            if failed > 0 {
+                return Err(Error::ThresholdBreached {
+                    breached: failed,
+                    checked: rows.len(),
+                }
+                .into());
+            }
+            println!("Within the baseline.");
+            Ok(())
+        }
+    }
+}
+
+/// Samples the process on `port` every `interval_secs` for `duration`.
+async fn capture_baseline(port: u16, pid: Option<u32>, duration: Duration, interval_secs: u64) -> Result<Baseline> {
+    let started = Instant::now();
+    let samples = capture_samples(port, pid, duration, interval_secs).await?;
+    let infos: Vec<ProcessInfo> = samples.into_iter().map(|s| s.process).collect();
+    Baseline::from_samples(port, Local::now(), started.elapsed().as_secs_f64(), &infos).context("No samples were taken")
+}
+
+async fn run_sample(args: &SampleArgs) -> Result<()> {
+    let interval = args.interval.max(MIN_MEASURE_WINDOW);
+    let json = args.format == OutputFormat::Json;
+    if !json {
+        println!("{:<19}  {:>7}  {:>8}  {:>12}  {:>5}", "TIME", "PID", "CPU", "MEMORY", "CONNS");
+    }
+    let samples = take_samples(args.port, args.pid, args.count as usize, interval, |_, sample| {
+        if !json {
+            let conns = sample.connections.map_or("-".to_string(), |c| c.to_string());
+            let info = &sample.process;
+            println!(
+                "{:<19}  {:>7}  {:>7.2}%  {:>12}  {:>5}",
+                sample.timestamp.format("%Y-%m-%d %H:%M:%S"),
+                info.pid,
+                info.cpu_percent,
+                format_mb(info.memory_bytes),
+                conns
+            );
+        }
+    })
+    .await?;
+    if json {
+        println!("{}", serde_json::to_string_pretty(&samples)?);
+    }
+    Ok(())
+}
+
+/// Samples the process on `port` every `interval_secs` for `duration`, at
+/// least twice, showing progress on stderr.
+async fn capture_samples(port: u16, pid: Option<u32>, duration: Duration, interval_secs: u64) -> Result<Vec<ExportSample>> {
+    let interval = Duration::from_secs(interval_secs.max(1));
+    let count = ((duration.as_secs_f64() / interval.as_secs_f64()).ceil() as usize).max(2);
+    let samples = take_samples(port, pid, count, interval, |n, sample| {
+        eprint!("\rSampling PID {} on port {}: {}/{}", sample.pids[0], port, n, count);
+    })
+    .await?;
+    eprintln!();
+    Ok(samples)
+}
+
+/// Takes `count` samples of the process on `port`, `interval` apart, handing
+/// each to `each` (with its number, from 1) as it comes in.
+async fn take_samples(
+    port: u16,
+    pid: Option<u32>,
+    count: usize,
+    interval: Duration,
+    mut each: impl FnMut(usize, &ExportSample),
+) -> Result<Vec<ExportSample>> {
+    let pid = listener_pid(&Runner::Local, port, pid)?;
+    let mut session = WatchSession::with_pids(SystemSource::default(), port, vec![pid])?;
+    let mut ticker = tokio::time::interval(interval);
+    ticker.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+    let mut samples = Vec::with_capacity(count);
+    for n in 1..=count {
+        ticker.tick().await;
+        let info = session.tick().await?;
+        let sample = ExportSample {
+            timestamp: Local::now(),
+            host: None,
+            port,
+            pids: session.pids.clone(),
+            process: info,
+            connections: session.tcp_stats.as_ref().map(|s| s.connections),
+            annotations: Vec::new(),
+        };
+        each(n, &sample);
+        samples.push(sample);
+    }
+    Ok(samples)
+}
+
+fn print_baseline_summary(saved: &Baseline) {
+    let (cpu, memory) = (saved.cpu_percent, saved.memory_bytes);
+    println!("  CPU     avg {:.2}%  p95 {:.2}%  max {:.2}%", cpu.avg, cpu.p95, cpu.max);
+    println!(
+        "  Memory  avg {}  p95 {}  max {}",
+        format_bytes(memory.avg as u64),
+        format_bytes(memory.p95 as u64),
+        format_bytes(memory.max as u64)
+    );
+}
+
+fn run_diff(args: &DiffArgs) -> Result<()> {
+    let a = recording::load(&args.a)?;
+    let b = recording::load(&args.b)?;
+    for (label, path, samples) in [("A", &args.a, &a), ("B", &args.b, &b)] {
+        let (Some(first), Some(last)) = (samples.first(), samples.last()) else {
+            return Err(anyhow!("{} has no samples", path.display()));
+        };
+        println!(
+            "{}: {}  {} on port {}, {} samples from {} over {}",
+            label,
+            path.display(),
+            first.process.name,
+            first.port,
+            samples.len(),
+            first.timestamp.format("%Y-%m-%d %H:%M:%S"),
+            format_duration((last.timestamp - first.timestamp).num_seconds().max(0) as u64)
+        );
+    }
+    let bucket = args.bucket.map_or_else(|| diff::default_bucket(&a, &b), |d| d.as_secs_f64());
+    let result = diff::diff(&a, &b, bucket);
+    println!("Aligned by time since each started, in {}s buckets\n", result.bucket_secs);
+
+    println!("  {:<12} {:>10} {:>10} {:>8} {:>10} {:>10}", "", "A avg", "B avg", "change", "A p95", "B p95");
+    for d in &result.metrics {
+        let show = |summary: Option<baseline::Summary>, p95: bool| {
+            summary.map_or("–".to_string(), |s| d.metric.format(if p95 { s.p95 } else { s.avg }))
+        };
+        println!(
+            "  {:<12} {:>10} {:>10} {:>8} {:>10} {:>10}",
+            d.metric.label(),
+            show(d.summary_a, false),
+            show(d.summary_b, false),
+            d.change().map_or("–".to_string(), |c| format!("{:+.1}%", c)),
+            show(d.summary_a, true),
+            show(d.summary_b, true)
+        );
+    }
+    for d in &result.metrics {
+        // Both lines on one scale, so their heights compare.
+        let max = d.a.iter().chain(&d.b).flatten().copied().fold(0.0, f64::max);
+        println!("\n  {:<12} A {}", d.metric.label(), diff::sparkline(&d.a, max));
+        println!("  {:<12} B {}", "", diff::sparkline(&d.b, max));
+        if let Some((at, va, vb)) = d.widest_gap() {
+            println!(
+                "  {:<12}   widest gap at +{}: {} vs {}",
+                "",
+                format_duration((at as f64 * result.bucket_secs) as u64),
+                d.metric.format(va),
+                d.metric.format(vb)
+            );
+        }
+    }
+
+    if let Some(path) = &args.html {
+        let html = diff::render_html(&result, &args.a.display().to_string(), &args.b.display().to_string());
+        fs::write(path, html).with_context(|| format!("Failed to write {}", path.display()))?;
+        println!("\nWrote {}", path.display());
+    }
+    Ok(())
+}
+
+fn run_report(args: &ReportArgs) -> Result<()> {
+    let sections = report::load_sections(&args.from, args.resolution, args.port, args.last)?;
+    if sections.is_empty() {
+        return Err(match args.port {
+            Some(port) => anyhow!("{} has no samples for port {}", args.from.display(), port),
+            None => anyhow!("{} has no samples", args.from.display()),
+        });
+    }
+    let name = args.from.file_name().map_or_else(|| args.from.display().to_string(), |n| n.to_string_lossy().into_owned());
+    let format = ReportFormat::from_path(&args.out);
+    let text = report::render(&sections, &format!("crabtop report: {}", name), format);
+    fs::write(&args.out, text).with_context(|| format!("Failed to write {}", args.out.display()))?;
+    for section in &sections {
+        println!("{}: {}", section.heading(), section.period());
+    }
+    println!("Wrote {}", args.out.display());
+    Ok(())
+}
+
+async fn run_chart_export(args: &ChartArgs) -> Result<()> {
+    let samples = match (&args.from, args.port) {
+        (Some(path), _) => recording::load(path)?,
+        (None, Some(port)) => capture_samples(port, args.pid, args.duration, args.interval).await?,
+        (None, None) => unreachable!("clap requires --port without --from"),
+    };
+    let port = args.from.as_ref().and(args.port);
+    let sections = report::sections(&samples, port, None);
+    if sections.is_empty() {
+        return Err(anyhow!("No samples to draw"));
+    }
+    report::write_history_chart(&args.out, &sections, args.chart, args.width, args.height)?;
+    println!("Wrote {} {} chart to {}", args.chart.label(), if sections.len() == 1 { "history" } else { "histories" }, args.out.display());
+    Ok(())
+}
+
+async fn run_ask(args: &AskArgs, config: &Config) -> Result<()> {
+    let insights = args
+        .ai
+        .insights(&config.ai)?
+        .context("ask needs an AI backend (--ai-provider, an [ai] table in the config, or OPENAI_API_KEY)")?;
+    let pids = match args.pid {
+        Some(pid) => vec![pid],
+        None => find_pids_by_port(args.port).with_context(|| format!("No process found listening on port {}", args.port))?,
+    };
+    let mut session = WatchSession::with_pids(SystemSource::default(), args.port, pids)?;
+    let info = session.tick().await?;
+    let live = Arc::new(Mutex::new(Live {
+        info,
+        history: session.history.clone(),
+        system: session.refresh_system().cloned(),
+        error: None,
+    }));
+
+    let interval = Duration::from_secs(args.interval.max(1));
+    let shared = Arc::clone(&live);
+    let sampler = tokio::spawn(async move {
+        loop {
+            tokio::time::sleep(interval).await;
+            let result = session.tick().await;
+            let system = session.refresh_system().cloned();
+            let mut live = shared.lock().expect("sampler lock");
+            match result {
+                Ok(info) => {
+                    live.info = info;
+                    live.history = session.history.clone();
+                    live.system = system;
+                    live.error = None;
+                }
+                Err(err) => live.error = Some(format!("{:#}", err)),
+            }
+        }
+    });
+
+    let mut chat = Chat::new(insights.provider);
+    {
+        let live = live.lock().expect("sampler lock");
+        println!(
+            "Asking {} ({}) about {} (PID {}) on port {}.",
+            chat.provider().kind().label(),
+            chat.provider().model(),
+            live.info.name,
+            live.info.pid,
+            args.port
+        );
+    }
+    println!("Type a question, /reset to start a new conversation, or exit to quit.");
+
+    loop {
+        print!("> ");
+        io::stdout().flush()?;
+        let line = tokio::task::spawn_blocking(|| {
+            let mut line = String::new();
+            io::stdin().read_line(&mut line).map(|n| (n > 0).then_some(line))
+        })
+        .await??;
+        let Some(line) = line else {
+            println!();
+            break;
+        };
+        let question = line.trim();
+        match question {
+            "" => continue,
+            "exit" | "quit" => break,
+            "/reset" => {
+                chat.reset();
+                println!("Conversation cleared.");
+                continue;
+            }
+            _ => {}
+        }
+
+        let connections = connection_summary(args.port).ok();
+        let context = {
+            let live = live.lock().expect("sampler lock");
+            if let Some(err) = &live.error {
+                warn!("Sampling failed, using the last good sample: {}", err);
+            }
+            context_block(
+                &chat.provider().redactor().process(&live.info),
+                &live.history,
+                live.system.as_ref(),
+                args.port,
+                connections.as_ref(),
+                Local::now().naive_local(),
+            )
+        };
+        match chat.ask(question, &context).await {
+            Ok(answer) => println!("{}\n", answer),
+            Err(err) if err.is::<DryRun>() => println!("{}\n", ai_failure(chat.provider(), &err)),
+            Err(err) => warn!("{}", ai_failure(chat.provider(), &err)),
+        }
+    }
+
+    sampler.abort();
+    if let Some(line) = ai_usage_line(chat.provider()) {
+        eprintln!("{}", line);
+    }
+    Ok(())
+}
+
+async fn run_snapshot<S: MetricsSource>(
+    source: &mut S,
+    port: u16,
+    pids: &[u32],
+    container: Option<&Container>,
+    protocol: Option<Fingerprint>,
+    ai: Option<Insights>,
+    output: SnapshotOutput<'_>,
+) -> Result<()> {
+    let mut infos = Vec::with_capacity(pids.len());
+    for &pid in pids {
+        infos.push(source.sample(pid).await?);
+    }
+    let mut info = aggregate(&infos).context("No process selected")?;
+    let cpu_count = match output.cpu_mode {
+        CpuMode::System => source.system().map_or(0, |system| system.cpu_count),
+        CpuMode::Process => 0,
+    };
+    info.cpu_percent = output.cpu_mode.scale(info.cpu_percent, cpu_count);
+    let mut history = ProcessHistory::new(1);
+    history.add(&info);
+    let listeners = source.listeners(info.pid).unwrap_or_default();
+
+    if let SnapshotFormat::Fields(fields) = output.format {
+        for field in fields {
+            println!("{}", field.value(port, pids, &info, &listeners));
+        }
+        return Ok(());
+    }
+    let cgroup = source.cgroup(info.pid);
+    let oom = source.oom_status(info.pid);
+
+    let (verdict, ai_error, dry_run) = match ai {
+        Some(Insights { provider, template, .. }) => {
+            match generate_verdict(&provider, &template.unwrap_or_default(), &info, &history).await {
+                Ok(verdict) => (Some(verdict), None, false),
+                Err(err) => (None, Some(ai_failure(&provider, &err)), err.is::<DryRun>()),
+            }
+        }
+        None => (None, None, false),
+    };
+
+    if let SnapshotFormat::Json = output.format {
+        let report = SnapshotReport {
+            port,
+            pids,
+            cpu_mode: output.cpu_mode,
+            process: &info,
+            listeners: &listeners,
+            protocol: protocol.as_ref(),
+            container,
+            cgroup: cgroup.as_ref(),
+            oom: oom.as_ref(),
+            insight: verdict.as_ref(),
+            insight_error: ai_error,
+        };
+        println!("{}", serde_json::to_string_pretty(&report)?);
+        return Ok(());
+    }
+
+    let quiet = matches!(output.format, SnapshotFormat::Text { quiet: true });
+    if let Some(c) = container.filter(|_| !quiet) {
+        println!("Container: {} ({}, {})", c.name, c.image, c.id);
+    }
+    if let Some(cgroup) = cgroup.filter(|c| c.is_limited()) {
+        print_cgroup(&cgroup);
+    }
+    if let Some(oom) = oom.filter(|o| o.is_next_victim()) {
+        warn!(
+            "PID {} has the highest OOM score on the host ({}); it is the next OOM-kill victim{}",
+            info.pid,
+            oom.score,
+            if oom.at_risk() { ", and memory pressure is high" } else { "" }
+        );
+    }
+    if pids.len() > 1 && !quiet {
+        println!("Aggregated over {} processes: {}", pids.len(), join_pids(pids));
+    }
+    match ai_error {
+        Some(request) if dry_run => println!("{}\n", request),
+        Some(err) => warn!("{}", err),
+        None => {}
+    }
+
+    match verdict {
+        Some(verdict) => print_verdict(&verdict)?,
+        None => print_plain(&info, &listeners, &output.cpu_mode.label(cpu_count), quiet),
+    }
+    if let Some(protocol) = &protocol {
+        println!("Protocol: {}", protocol);
+    }
+    if let Some(oom) = &oom {
+        print_oom(oom);
+    }
+
+    Ok(())
+}
+
+fn print_verdict(verdict: &Verdict) -> Result<()> {
+    let mut stdout = io::stdout();
+    if stdout.is_terminal() {
+        execute!(
+            stdout,
+            SetForegroundColor(severity_color(verdict.severity)),
+            Print(format!("[{}]", verdict.severity.label())),
+            ResetColor,
+        )?;
+    } else {
+        print!("[{}]", verdict.severity.label());
+    }
+    println!(" {}", verdict.summary);
+    if !verdict.cause.is_empty() {
+        println!("Cause: {}", verdict.cause);
+    }
+    if !verdict.action.is_empty() {
+        println!("Action: {}", verdict.action);
+    }
+    Ok(())
+}
+
+fn print_oom(oom: &OomStatus) {
+    println!("OOM score: {} (adj {}), #{} of {} processes", oom.score, oom.score_adj, oom.rank, oom.processes);
+    if let Some(p) = oom.pressure {
+        println!(
+            "Memory pressure: some {:.1}% / full {:.1}% (10s), some {:.1}% / full {:.1}% (60s)",
+            p.some_avg10, p.full_avg10, p.some_avg60, p.full_avg60
+        );
+    }
+}
+
+fn print_cgroup(cgroup: &CgroupStats) {
+    let memory = match (cgroup.memory_current, cgroup.memory_max) {
+        (Some(current), Some(max)) => format!("{} / {}", format_mb(current), format_mb(max)),
+        (Some(current), None) => format!("{} (no limit)", format_mb(current)),
+        _ => "-".to_string(),
+    };
+    let cpu = cgroup
+        .cpu_quota_cores
+        .map(|q| format!("{:.2} cores", q))
+        .unwrap_or_else(|| "no limit".to_string());
+    println!(
+        "Cgroup: memory {memory}, cpu quota {cpu}, throttled {throttled}/{periods} periods",
+        throttled = cgroup.cpu_stat.nr_throttled,
+        periods = cgroup.cpu_stat.nr_periods
+    );
+}
+
+/// Decides which of the port's listeners to inspect. With a single listener
+/// that's easy; with several (SO_REUSEPORT, pre-fork servers) it honours
+/// `--pid`/`--aggregate`, otherwise asks on a TTY or warns and takes the first.
+fn select_pids<S: MetricsSource>(source: &S, port: u16, cli: &Cli) -> Result<Vec<u32>> {
+    let pids = match &cli.matcher {
+        Some(pattern) => matching_pids(source, port, pattern)?,
+        None => source.resolve_pids(port).map_err(|e| match error::find(&e) {
+            Some(hidden @ Error::PermissionDenied { .. }) if cli.sudo.is_none() && cli.ssh.is_none() => {
+                let hint = format!("{}. Re-run with --sudo (or --sudo=pkexec) to look it up as root.", hidden);
+                e.context(hint)
+            }
+            // With --sudo the lookup's own error says what went wrong with elevating.
+            _ if cli.sudo.is_some() => e,
+            Some(_) => e,
+            None => e.context(format!("No process found listening on port {}", port)),
+        })?,
+    };
+
+    if let Some(pid) = cli.pid {
+        if !pids.contains(&pid) {
+            return Err(anyhow!(
+                "PID {} is not listening on port {} (listeners: {})",
+                pid,
+                port,
+                join_pids(&pids)
+            ));
+        }
+        return Ok(vec![pid]);
+    }
+
+    if pids.len() == 1 || cli.aggregate {
+        return Ok(pids);
+    }
+
+    if !io::stdin().is_terminal() {
+        warn!(
+            "{} processes listen on port {} ({}); inspecting PID {}. Use --pid or --aggregate to choose.",
+            pids.len(),
+            port,
+            join_pids(&pids),
+            pids[0]
+        );
+        return Ok(vec![pids[0]]);
+    }
+
+    println!("{} processes listen on port {}:", pids.len(), port);
+    for (i, pid) in pids.iter().enumerate() {
+        let name = source.process_name(*pid).unwrap_or_else(|| "?".to_string());
+        println!("  [{}] {} ({})", i + 1, pid, name);
+    }
+    loop {
+        print!("Select 1-{} or 'a' to aggregate all: ", pids.len());
+        io::stdout().flush()?;
+
+        let mut answer = String::new();
+        if io::stdin().read_line(&mut answer)? == 0 {
+            return Err(anyhow!("No process selected"));
+        }
+        let answer = answer.trim();
+        if answer.eq_ignore_ascii_case("a") {
+            return Ok(pids);
+        }
+        if let Some(pid) = answer
+            .parse::<usize>()
+            .ok()
+            .and_then(|n| n.checked_sub(1))
+            .and_then(|i| pids.get(i))
+        {
+            return Ok(vec![*pid]);
+        }
+    }
+}
+
+/// If the listener is docker-proxy, swaps it for the processes inside the
+/// container publishing the port. Any failure just keeps the proxy PID.
+fn resolve_container(port: u16, pids: Vec<u32>, disabled: bool) -> (Vec<u32>, Option<Container>) {
+    if disabled || pids.len() != 1 || !is_docker_proxy(pids[0]) {
+        return (pids, None);
+    }
+
+    match container_for_port(port) {
+        Ok(Some(container)) => {
+            info!("port {} is published by container {}; following {:?}", port, container.name, container.pids);
+            (container.pids.clone(), Some(container))
+        }
+        Ok(None) => {
+            warn!("port {} is served by docker-proxy but no container publishes it", port);
+            (pids, None)
+        }
+        Err(err) => {
+            warn!("port {} is served by docker-proxy; container lookup failed: {}", port, err);
+            (pids, None)
+        }
+    }
+}
+
+/// The latest sample as the exporters and `stream` clients see it.
+fn export_sample<S: MetricsSource>(session: &WatchSession<S>, info: &ProcessInfo, annotations: Vec<Annotation>) -> ExportSample {
+    ExportSample {
+        timestamp: Local::now(),
+        host: None,
+        port: session.port,
+        pids: session.pids.clone(),
+        process: info.clone(),
+        connections: session.tcp_stats.as_ref().map(|s| s.connections),
+        annotations,
+    }
+}
+
+/// Hands the session's new alerts to the exporters; a failed tick (the
+/// process exiting) can raise one too.
+fn export_alerts<S: MetricsSource>(session: &mut WatchSession<S>, exporters: &mut ExporterRegistry) {
+    for alert in session.take_fresh_alerts() {
+        exporters.on_alert(&alert);
+    }
+    for recovery in session.take_fresh_recoveries() {
+        exporters.on_recovery(&recovery);
+    }
+}
+
+/// Hands a tick's sample, annotations and alerts to the exporters and the
+/// control socket's `stream` clients.
+fn export_tick<S: MetricsSource>(
+    session: &mut WatchSession<S>,
+    info: &ProcessInfo,
+    exporters: &mut ExporterRegistry,
+    control: Option<&ControlSocket>,
+) {
+    let annotations = session.take_fresh_annotations();
+    for annotation in &annotations {
+        exporters.on_annotation(annotation);
+    }
+    export_alerts(session, exporters);
+    let sample = export_sample(session, info, annotations);
+    exporters.on_sample(&sample);
+    if let Some(control) = control {
+        control.publish(&sample);
+    }
+}
+
+/// What the watch loop has to do after a control request.
+enum ControlOutcome {
+    /// Answered; nothing on screen changed.
+    Answered,
+    /// Answered, and worth a redraw.
+    Changed,
+    /// To be written out, and answered, with the next frame.
+    Snapshot(PathBuf, ControlRequest),
+    Stop,
+}
+
+fn handle_control<S: MetricsSource>(
+    request: ControlRequest,
+    session: &mut WatchSession<S>,
+    info: &ProcessInfo,
+    interval_secs: &mut u64,
+) -> ControlOutcome {
+    debug!("control command: {:?}", request.command);
+    match request.command.clone() {
+        ControlCommand::Annotate { kind, text } => {
+            session.annotate(kind, text);
+            request.answer("");
+            ControlOutcome::Changed
+        }
+        ControlCommand::Interval(secs) => {
+            *interval_secs = secs;
+            request.answer(format!("sampling every {}s", secs));
+            ControlOutcome::Changed
+        }
+        ControlCommand::Snapshot(path) => {
+            let path = path.unwrap_or_else(|| {
+                let name = format!("crabtop-{}-{}.txt", session.port, Local::now().format("%Y%m%d-%H%M%S"));
+                std::env::current_dir().unwrap_or_default().join(name)
+            });
+            ControlOutcome::Snapshot(path, request)
+        }
+        ControlCommand::Stats => {
+            match serde_json::to_string(&export_sample(session, info, Vec::new())) {
+                Ok(json) => request.answer(json),
+                Err(err) => request.fail(err.to_string()),
+            }
+            ControlOutcome::Answered
+        }
+        // The socket serves streams itself, so these never reach the loop.
+        ControlCommand::Stream => {
+            request.fail("`stream` is served by the socket");
+            ControlOutcome::Answered
+        }
+        ControlCommand::Stop => {
+            request.answer("stopping");
+            ControlOutcome::Stop
+        }
     }
 }
 
-#[tokio::main]
-async fn main() {
-    if let Err(e) = run().await {
-        eprintln!("Error: {}", e);
-        std::process::exit(1);
+/// The tab a key switches the watch dashboard to: 1-5, Tab and Shift-Tab,
+/// and the keys that opened those panes before there were tabs.
+fn tab_for_key(code: KeyCode, shown: Tab) -> Option<Tab> {
+    match code {
+        KeyCode::Char(key @ '1'..='5') => Tab::from_key(key),
+        KeyCode::Tab => Some(shown.next()),
+        KeyCode::BackTab => Some(shown.previous()),
+        KeyCode::Char('l' | 'L') => Some(Tab::Logs),
+        KeyCode::Char('f') => Some(Tab::Files),
+        _ => None,
     }
 }
 
-async fn run() -> Result<()> {
-    let cli = Cli::parse();
-
-    let pid = find_pid_by_port(cli.port)
-        .with_context(|| format!("No process found listening on port {}", cli.port))?;
-
-    if cli.watch {
-        // Real-time monitoring mode
-        run_watch_mode(pid, cli.port, cli.interval).await?;
-    } else {
-        // Single snapshot mode
-        let info = collect_process_info(pid).await?;
-
-        match env::var("OPENAI_API_KEY") {
-            Ok(api_key) if !api_key.trim().is_empty() => {
-                match generate_openai_insight(&api_key, &info).await {
-                    Ok(text) => {
-                        println!("{}", text);
-                    }
-                    Err(err) => {
-                        eprintln!("OpenAI call failed: {}", err);
-                        print_plain(&info);
-                    }
-                }
-            }
-            _ => {
-                print_plain(&info);
-            }
-        }
-    }
-
-    Ok(())
+fn join_pids(pids: &[u32]) -> String {
+    pids.iter().map(|p| p.to_string()).collect::<Vec<_>>().join(", ")
 }
 
-async fn run_watch_mode(pid: u32, port: u16, interval_secs: u64) -> Result<()> {
+async fn run_watch_mode<S: MetricsSource>(
+    mut session: WatchSession<S>,
+    container: Option<Container>,
+    mut interval_secs: u64,
+    mut exporters: ExporterRegistry,
+    ai: Option<Insights>,
+    mut probes: Probes,
+    retransmit_alert: f64,
+) -> Result<()> {
     let mut stdout = io::stdout();
-    let mut history = ProcessHistory::new(60); // Keep last 60 samples
-    let mut iteration = 0u64;
     let mut last_terminal_size = get_terminal_size();
-
+    let mut show_system = false;
+    let mut show_histogram = false;
+    let mut show_overlay = false;
+    let mut markers = 0;
+    // A `snapshot` from the control socket, written out with the next frame.
+    let mut pending_snapshot: Option<(PathBuf, ControlRequest)> = None;
+    let mut chart = ChartView::default();
+    // crabtop's own figures, read the same way as the process's.
+    let own = ProcessSampler::default();
+    let mut open_files: Option<FileListing> = None;
+    let mut stack_dump: Option<StackDump> = None;
+    // A restart/stop waiting for 'y', and the line shown under the systemd panel.
+    let mut pending_action: Option<UnitAction> = None;
+    let mut unit_message: Option<String> = None;
+    let mut stopped_unit: Option<String> = None;
+    let mut ai = ai.map(WatchInsights::new);
+    session.show_tab(Tab::Resources);
+    // Keys and control requests only repaint; a sample is taken on the
+    // interval alone, so history and exports stay evenly spaced.
+    let mut latest: Option<ProcessInfo> = None;
+    let mut sampled_at: Option<Instant> = None;
+    let mut own_usage = None;
+
+    // Log lines would draw over the dashboard; they're kept for 'L' instead.
+    let quiet = logging::pause_stderr();
     // Enable raw mode for better terminal control
     terminal::enable_raw_mode().context("Failed to enable raw mode")?;
 
     let result = async {
         loop {
-            iteration += 1;
-
             // Check for terminal resize
             let current_size = get_terminal_size();
             if current_size != last_terminal_size {
@@ -156,16 +3039,48 @@ async fn run_watch_mode(pid: u32, port: u16, interval_secs: u64) -> Result<()> {
                 // Force redraw on resize
             }
 
-            // Collect process info
-            let info = match collect_process_info(pid).await {
-                Ok(info) => info,
-                Err(e) => {
-                    terminal::disable_raw_mode()?;
-                    return Err(e);
+            let due = sampled_at.is_none_or(|at| at.elapsed() >= Duration::from_secs(interval_secs));
+            if due {
+                sampled_at = Some(Instant::now());
+                let info = match session.tick().await {
+                    Ok(info) => info,
+                    Err(e) => {
+                        terminal::disable_raw_mode()?;
+                        export_alerts(&mut session, &mut exporters);
+                        return Err(e);
+                    }
+                };
+
+                probes.tick(&info).await;
+                chart.advance();
+                own_usage = own.sample(std::process::id()).await.ok();
+
+                export_tick(&mut session, &info, &mut exporters, probes.control.as_ref());
+                if let Some(ai) = ai.as_mut() {
+                    if ai.due() {
+                        ai.ask(&info, &session.history);
+                    }
                 }
+                latest = Some(info);
+            }
+            let info = latest.clone().expect("sampled above");
+
+            let system = if show_system {
+                session.refresh_system().cloned()
+            } else {
+                None
+            };
+            let shown = session.tab.unwrap_or(Tab::Resources);
+            let logs = if shown == Tab::Logs {
+                session.log_tail(probes.log_path.as_deref())
+            } else {
+                None
             };
+            let crabtop_log = (shown == Tab::Logs).then(|| logging::recent(LOG_LINES));
 
-            history.add(&info);
+            if let Some(ai) = ai.as_mut() {
+                ai.poll().await;
+            }
 
             // Clear screen and move cursor to top
             execute!(
@@ -175,7 +3090,70 @@ async fn run_watch_mode(pid: u32, port: u16, interval_secs: u64) -> Result<()> {
             )?;
 
             // Render the dashboard with current terminal width
-            render_dashboard(&mut stdout, &info, &history, port, iteration, last_terminal_size.0)?;
+            let frame = Frame {
+                info: &info,
+                history: &session.history,
+                port: session.port,
+                iteration: session.iteration,
+                timestamp: Local::now().naive_local(),
+                width: last_terminal_size.0,
+                cpu_mode: session.cpu_mode,
+                cpu_count: session.cpu_count,
+                tab: session.tab,
+                cpu_histogram: show_histogram,
+                chart,
+                overlay: show_overlay,
+                annotations: &session.annotations,
+                pids: &session.pids,
+                container: container.as_ref(),
+                cgroup: session.cgroup.as_ref(),
+                throttled_ratio: session.throttled_ratio,
+                accept_queue: session.accept_queue.as_ref(),
+                oom: session.oom.as_ref(),
+                swap: session.swap.as_ref(),
+                segments: session.segments.as_ref(),
+                own: own_usage.as_ref(),
+                cores: session.cores.as_ref(),
+                pool: session.pool.as_ref(),
+                children: Some(&session.children),
+                upstream: session.upstream.as_ref(),
+                counters: session.counters.as_ref(),
+                ebpf: session.ebpf.as_ref(),
+                syscalls: session.syscalls.as_ref(),
+                tcp_stats: session.tcp_stats.as_ref(),
+                sockets: session.sockets.as_ref(),
+                posture: session.posture.as_ref(),
+                churn: session.churn.as_ref(),
+                peers: session.peers.as_deref(),
+                retransmit_alert,
+                listeners: &session.listeners,
+                protocol: probes.protocol.as_ref(),
+                probe: probes.tcp.as_ref(),
+                http_check: probes.http.as_ref(),
+                tls: probes.tls.as_ref(),
+                power: probes.power.as_ref(),
+                app: probes.app.as_ref(),
+                unit: session.unit.as_ref(),
+                unit_prompt: unit_message.as_deref(),
+                logs: logs.as_ref(),
+                crabtop_log: crabtop_log.as_deref(),
+                open_files: open_files.as_ref(),
+                runtime: session.runtime,
+                stack_dump: stack_dump.as_ref(),
+                system: system.as_ref(),
+                verdict: ai.as_ref().and_then(|ai| ai.verdict.as_ref()),
+                insight: ai.as_ref().and_then(|ai| ai.status.as_deref()),
+                ai_enabled: ai.is_some(),
+            };
+            render_dashboard(&mut stdout, &frame)?;
+            if let Some((path, request)) = pending_snapshot.take() {
+                let written = render_plain(&frame)
+                    .and_then(|text| fs::write(&path, text).with_context(|| format!("Failed to write {}", path.display())));
+                match written {
+                    Ok(()) => request.answer(path.display().to_string()),
+                    Err(err) => request.fail(err.to_string()),
+                }
+            }
 
             stdout.flush()?;
 
@@ -185,18 +3163,207 @@ async fn run_watch_mode(pid: u32, port: u16, interval_secs: u64) -> Result<()> {
             
             // Poll for events multiple times during the interval to be responsive
             let poll_duration = Duration::from_millis(100);
-            let total_sleep = Duration::from_secs(interval_secs);
-            let mut elapsed = Duration::ZERO;
-            
-            while elapsed < total_sleep {
-                if event::poll(poll_duration)? {
+            let next_sample = sampled_at.expect("sampled at least once") + Duration::from_secs(interval_secs);
+
+            while Instant::now() < next_sample {
+                if let Some(request) = probes.control.as_mut().and_then(ControlSocket::try_recv) {
+                    match handle_control(request, &mut session, &info, &mut interval_secs) {
+                        ControlOutcome::Answered => continue,
+                        ControlOutcome::Changed => {}
+                        ControlOutcome::Snapshot(path, request) => pending_snapshot = Some((path, request)),
+                        ControlOutcome::Stop => should_break = true,
+                    }
+                    should_redraw = true;
+                    break;
+                }
+                if event::poll(poll_duration.min(next_sample.saturating_duration_since(Instant::now())))? {
                     match event::read()? {
-                        Event::Key(key_event) => {
+                        // Any key but 'y' cancels a pending restart or stop.
+                        Event::Key(key_event) if pending_action.is_some() => {
+                            let action = pending_action.take().expect("guarded by is_some");
+                            unit_message = None;
+                            if key_event.code == KeyCode::Char('y') {
+                                let name = session.unit.as_ref().map(|u| u.name.clone()).unwrap_or_default();
+                                match session.unit_action(action) {
+                                    Ok(()) if action == UnitAction::Stop => {
+                                        stopped_unit = Some(name);
+                                        should_break = true;
+                                        break;
+                                    }
+                                    Ok(()) => {
+                                        unit_message = Some(reattach_after_restart(&mut session, &name).await);
+                                        // The new process is sampled straight away.
+                                        sampled_at = None;
+                                    }
+                                    Err(e) => unit_message = Some(format!("✗ {:#}", e)),
+                                }
+                            }
+                            should_redraw = true;
+                            break;
+                        }
+                        Event::Key(key_event)
+                            if session.unit.is_some() && matches!(key_event.code, KeyCode::Char('R' | 'X')) =>
+                        {
+                            let action = if key_event.code == KeyCode::Char('R') {
+                                UnitAction::Restart
+                            } else {
+                                UnitAction::Stop
+                            };
+                            let name = session.unit.as_ref().map_or("", |u| u.name.as_str());
+                            unit_message = Some(format!(
+                                "{} {}? Press 'y' to confirm, any other key to cancel",
+                                if action == UnitAction::Restart { "Restart" } else { "Stop" },
+                                name
+                            ));
+                            pending_action = Some(action);
+                            should_redraw = true;
+                            break;
+                        }
+                        Event::Key(key_event)
                             if key_event.code == KeyCode::Char('q')
                                 || key_event.code == KeyCode::Char('c')
-                                || key_event.code == KeyCode::Esc
-                            {
-                                should_break = true;
+                                || key_event.code == KeyCode::Esc =>
+                        {
+                            should_break = true;
+                            break;
+                        }
+                        Event::Key(key_event) if key_event.code == KeyCode::Char('s') => {
+                            show_system = !show_system;
+                            should_redraw = true;
+                            break;
+                        }
+                        Event::Key(key_event) if key_event.code == KeyCode::Char('h') => {
+                            show_histogram = !show_histogram;
+                            should_redraw = true;
+                            break;
+                        }
+                        Event::Key(key_event) if key_event.code == KeyCode::Char('o') => {
+                            show_overlay = !show_overlay;
+                            should_redraw = true;
+                            break;
+                        }
+                        Event::Key(key_event) if key_event.code == KeyCode::Char('m') => {
+                            markers += 1;
+                            session.annotate(AnnotationKind::Marker, format!("Marker {}", markers));
+                            should_redraw = true;
+                            break;
+                        }
+                        Event::Key(key_event)
+                            if matches!(
+                                key_event.code,
+                                KeyCode::Left | KeyCode::Right | KeyCode::Char('+' | '=' | '-' | '0')
+                            ) =>
+                        {
+                            let columns = session.history.cpu_chart(&chart, sparkline_width(last_terminal_size.0)).len();
+                            match key_event.code {
+                                // With the cursor out, the arrows move it rather than the chart.
+                                KeyCode::Left if chart.cursor.is_some() => chart.move_cursor(-1, columns),
+                                KeyCode::Right if chart.cursor.is_some() => chart.move_cursor(1, columns),
+                                KeyCode::Left => chart.scroll_back(session.history.recorded()),
+                                KeyCode::Right => chart.scroll_forward(),
+                                KeyCode::Char('+' | '=') => chart.zoom_in(),
+                                KeyCode::Char('-') => chart.zoom_out(),
+                                _ => chart = ChartView::default(),
+                            }
+                            should_redraw = true;
+                            break;
+                        }
+                        Event::Key(key_event) if key_event.code == KeyCode::Char('i') => {
+                            chart.toggle_cursor(session.history.cpu_chart(&chart, sparkline_width(last_terminal_size.0)).len());
+                            // Only while the cursor is out, so the terminal can still select text otherwise.
+                            if chart.cursor.is_some() {
+                                execute!(stdout, EnableMouseCapture)?;
+                            } else {
+                                execute!(stdout, DisableMouseCapture)?;
+                            }
+                            should_redraw = true;
+                            break;
+                        }
+                        // A click moves the cursor to that column (moves alone would repaint on every mouse twitch).
+                        Event::Mouse(mouse)
+                            if chart.cursor.is_some() && mouse.kind == MouseEventKind::Down(MouseButton::Left) =>
+                        {
+                            let columns = session.history.cpu_chart(&chart, sparkline_width(last_terminal_size.0)).len();
+                            if let Some(column) = mouse.column.checked_sub(CHART_LEFT).map(usize::from).filter(|&c| c < columns) {
+                                chart.cursor = Some(column);
+                                should_redraw = true;
+                                break;
+                            }
+                        }
+                        Event::Key(key_event) if tab_for_key(key_event.code, shown).is_some() => {
+                            let tab = tab_for_key(key_event.code, shown).expect("guarded by is_some");
+                            // Like the other tabs' readings, the listing is only taken while shown.
+                            if tab != Tab::Files {
+                                open_files = None;
+                            } else if open_files.is_none() {
+                                open_files = Some(FileListing::new(session.open_files().unwrap_or_default()));
+                            }
+                            if tab != Tab::Threads {
+                                stack_dump = None;
+                            }
+                            session.show_tab(tab);
+                            should_redraw = true;
+                            break;
+                        }
+                        Event::Key(key_event) if session.runtime.is_some() && key_event.code == KeyCode::Char('d') => {
+                            stack_dump = match stack_dump {
+                                Some(_) => None,
+                                None => session.stack_dump(),
+                            };
+                            open_files = None;
+                            session.show_tab(Tab::Threads);
+                            should_redraw = true;
+                            break;
+                        }
+                        Event::Key(key_event)
+                            if stack_dump.is_some()
+                                && matches!(
+                                    key_event.code,
+                                    KeyCode::Char('n' | 'p' | 'r')
+                                        | KeyCode::PageDown
+                                        | KeyCode::PageUp
+                                        | KeyCode::Down
+                                        | KeyCode::Up
+                                ) =>
+                        {
+                            if key_event.code == KeyCode::Char('r') {
+                                stack_dump = session.stack_dump();
+                            } else if let Some(dump) = stack_dump.as_mut() {
+                                match key_event.code {
+                                    KeyCode::Char('p') | KeyCode::PageUp => dump.previous_page(),
+                                    KeyCode::Up => dump.scroll(-1),
+                                    KeyCode::Down => dump.scroll(1),
+                                    _ => dump.next_page(),
+                                }
+                            }
+                            should_redraw = true;
+                            break;
+                        }
+                        Event::Key(key_event)
+                            if open_files.is_some()
+                                && matches!(
+                                    key_event.code,
+                                    KeyCode::Char('n' | 'p' | 'r') | KeyCode::PageDown | KeyCode::PageUp
+                                ) =>
+                        {
+                            if let Some(files) = open_files.as_mut() {
+                                match key_event.code {
+                                    KeyCode::Char('r') => {
+                                        let page = files.page;
+                                        *files = FileListing::new(session.open_files().unwrap_or_default());
+                                        files.page = page.min(files.pages() - 1);
+                                    }
+                                    KeyCode::Char('p') | KeyCode::PageUp => files.previous_page(),
+                                    _ => files.next_page(),
+                                }
+                            }
+                            should_redraw = true;
+                            break;
+                        }
+                        Event::Key(key_event) if key_event.code == KeyCode::Char('a') => {
+                            if let Some(ai) = ai.as_mut() {
+                                ai.ask(&info, &session.history);
+                                should_redraw = true;
                                 break;
                             }
                         }
@@ -208,7 +3375,11 @@ async fn run_watch_mode(pid: u32, port: u16, interval_secs: u64) -> Result<()> {
                         _ => {}
                     }
                 }
-                elapsed += poll_duration;
+                // Show an answer as soon as it arrives rather than next tick.
+                if ai.as_ref().and_then(|ai| ai.pending.as_ref()).is_some_and(|h| h.is_finished()) {
+                    should_redraw = true;
+                    break;
+                }
             }
 
             if should_break {
@@ -216,7 +3387,7 @@ async fn run_watch_mode(pid: u32, port: u16, interval_secs: u64) -> Result<()> {
             }
 
             if should_redraw {
-                continue; // Redraw immediately
+                continue; // Redraw immediately, from the samples already taken
             }
         }
 
@@ -226,503 +3397,254 @@ async fn run_watch_mode(pid: u32, port: u16, interval_secs: u64) -> Result<()> {
 
     // Restore terminal
     terminal::disable_raw_mode()?;
-    execute!(stdout, cursor::Show)?;
-
-    result
-}
-
-fn get_terminal_size() -> (u16, u16) {
-    terminal_size().unwrap_or((80, 24))
-}
-
-fn print_plain(info: &ProcessInfo) {
-    println!(
-        "Process on port:\nName: {name}\nPID: {pid}\nCPU: {cpu:.2}%\nMemory: {mem:.2} MB",
-        name = info.name,
-        pid = info.pid,
-        cpu = info.cpu_percent,
-        mem = info.memory_mb
-    );
-}
-
-fn render_dashboard(
-    stdout: &mut io::Stdout,
-    info: &ProcessInfo,
-    history: &ProcessHistory,
-    port: u16,
-    iteration: u64,
-    terminal_width: u16,
-) -> Result<()> {
-    let timestamp = Local::now().format("%Y-%m-%d %H:%M:%S");
-    let width = terminal_width as usize;
-    
-    // Ensure minimum width
-    let min_width = 60;
-    let effective_width = width.max(min_width);
-    
-    // Calculate responsive sizes
-    let bar_width = (effective_width.saturating_sub(30)).max(20).min(80);
-    let sparkline_width = (effective_width.saturating_sub(20)).max(20).min(100);
-    
-    // Dynamic header
-    let header_text = format!(" PORT INSPECTOR - Real-time Monitoring (Port {}) ", port);
-    let header_text_len = header_text.len().min(effective_width.saturating_sub(2));
-    let header_padding = effective_width.saturating_sub(header_text_len + 2);
-    let left_pad = header_padding / 2;
-    let right_pad = header_padding.saturating_sub(left_pad);
-    let display_text = if header_text.len() > header_text_len {
-        &header_text[..header_text_len]
-    } else {
-        &header_text
-    };
-    
-    execute!(
-        stdout,
-        SetForegroundColor(Color::Cyan),
-        Print("╔"),
-        Print("═".repeat(effective_width.saturating_sub(2))),
-        Print("╗\n"),
-        Print("║"),
-        SetForegroundColor(Color::Yellow),
-        Print(" ".repeat(left_pad)),
-        Print(display_text),
-        Print(" ".repeat(right_pad)),
-        SetForegroundColor(Color::Cyan),
-        Print("║\n"),
-        Print("╚"),
-        Print("═".repeat(effective_width.saturating_sub(2))),
-        Print("╝\n"),
-        ResetColor,
-    )?;
-
-    // Process Info
-    execute!(
-        stdout,
-        Print("\n"),
-        SetForegroundColor(Color::Green),
-        Print("📊 Process Information\n"),
-        ResetColor,
-        Print(format!("   Name:      {}\n", info.name)),
-        Print(format!("   PID:       {}\n", info.pid)),
-        Print(format!("   Port:      {}\n", port)),
-        Print(format!("   Time:      {}\n", timestamp)),
-        Print(format!("   Samples:   {}\n", iteration)),
-    )?;
-
-    // CPU Section
-    execute!(
-        stdout,
-        Print("\n"),
-        SetForegroundColor(Color::Magenta),
-        Print("⚡ CPU Usage\n"),
-        ResetColor,
-    )?;
-
-    let cpu_color = if info.cpu_percent > 80.0 {
-        Color::Red
-    } else if info.cpu_percent > 50.0 {
-        Color::Yellow
-    } else {
-        Color::Green
-    };
-
-    execute!(
-        stdout,
-        Print("   Current:   "),
-        SetForegroundColor(cpu_color),
-        Print(format!("{:>6.2}%", info.cpu_percent)),
-        ResetColor,
-        Print("  "),
-    )?;
-    render_bar(stdout, info.cpu_percent as f64, 100.0, bar_width, cpu_color)?;
-    execute!(stdout, Print("\n"))?;
-
-    if !history.cpu_history.is_empty() {
-        execute!(
-            stdout,
-            Print(format!("   Average:   {:>6.2}%\n", history.avg_cpu())),
-            Print(format!("   Peak:      {:>6.2}%\n", history.max_cpu())),
-        )?;
-
-        // CPU Sparkline
-        execute!(
-            stdout,
-            Print("   History:   "),
-        )?;
-        render_sparkline(stdout, &history.cpu_history, sparkline_width)?;
-        execute!(stdout, Print("\n"))?;
+    execute!(stdout, DisableMouseCapture, cursor::Show)?;
+    drop(quiet);
+    report_exporter_failures(&mut exporters);
+    if let Some(unit) = stopped_unit {
+        println!("Stopped {}.", unit);
     }
-
-    // Memory Section
-    execute!(
-        stdout,
-        Print("\n"),
-        SetForegroundColor(Color::Blue),
-        Print("💾 Memory Usage\n"),
-        ResetColor,
-    )?;
-
-    let mem_color = if info.memory_mb > 1000.0 {
-        Color::Red
-    } else if info.memory_mb > 500.0 {
-        Color::Yellow
-    } else {
-        Color::Green
-    };
-
-    let max_mem_display = if history.max_mem() > info.memory_mb {
-        history.max_mem()
-    } else {
-        info.memory_mb
-    };
-    let mem_max = (max_mem_display * 1.2).max(100.0); // Add 20% headroom
-
-    execute!(
-        stdout,
-        Print("   Current:   "),
-        SetForegroundColor(mem_color),
-        Print(format!("{:>8.2} MB", info.memory_mb)),
-        ResetColor,
-        Print("  "),
-    )?;
-    render_bar(stdout, info.memory_mb, mem_max, bar_width, mem_color)?;
-    execute!(stdout, Print("\n"))?;
-
-    if !history.mem_history.is_empty() {
-        execute!(
-            stdout,
-            Print(format!("   Average:   {:>8.2} MB\n", history.avg_mem())),
-            Print(format!("   Peak:      {:>8.2} MB\n", history.max_mem())),
-        )?;
-
-        // Memory Sparkline
-        execute!(
-            stdout,
-            Print("   History:   "),
-        )?;
-        render_sparkline_mem(stdout, &history.mem_history, sparkline_width)?;
-        execute!(stdout, Print("\n"))?;
+    if let Some(line) = ai.as_ref().and_then(|ai| ai_usage_line(&ai.insights.provider)) {
+        eprintln!("{}", line);
     }
 
-    // Footer
-    execute!(
-        stdout,
-        Print("\n"),
-        SetForegroundColor(Color::DarkGrey),
-        Print("Press 'q' or 'c' to quit | Updates every second\n"),
-        ResetColor,
-    )?;
-
-    Ok(())
+    result
 }
 
-fn render_bar(
-    stdout: &mut io::Stdout,
-    value: f64,
-    max: f64,
-    width: usize,
-    color: Color,
+/// The multi-host grid. Every member samples concurrently each interval;
+/// Enter opens the usual dashboard for the selected cell and Esc returns.
+async fn run_fleet_watch(
+    mut members: Vec<FleetMember>,
+    token: &str,
+    interval_secs: u64,
+    mut exporters: ExporterRegistry,
 ) -> Result<()> {
-    let width = width.max(1); // Ensure at least 1 character width
-    let filled = ((value / max.max(0.1)) * width as f64).round() as usize;
-    let filled = filled.min(width);
-    let empty = width.saturating_sub(filled);
-
-    execute!(
-        stdout,
-        Print("["),
-        SetForegroundColor(color),
-        Print("█".repeat(filled)),
-        ResetColor,
-        SetForegroundColor(Color::DarkGrey),
-        Print("░".repeat(empty)),
-        ResetColor,
-        Print("]"),
-    )?;
-
-    Ok(())
-}
-
-fn render_sparkline(stdout: &mut io::Stdout, data: &[f32], width: usize) -> Result<()> {
-    if data.is_empty() || width == 0 {
-        return Ok(());
-    }
-
-    let sparkline_chars = ['▁', '▂', '▃', '▄', '▅', '▆', '▇', '█'];
-    let max_val = data.iter().copied().fold(0.0f32, f32::max).max(1.0);
-
-    let step = if data.len() > width {
-        data.len() / width
-    } else {
-        1
-    };
-
-    let samples: Vec<f32> = data.iter().step_by(step).copied().collect();
-    let display_samples = if samples.len() > width {
-        &samples[samples.len() - width..]
-    } else {
-        &samples
-    };
-
-    for &val in display_samples {
-        let normalized = (val / max_val).min(1.0);
-        let idx = (normalized * (sparkline_chars.len() - 1) as f32).round() as usize;
-        let color = if val > 80.0 {
-            Color::Red
-        } else if val > 50.0 {
-            Color::Yellow
-        } else {
-            Color::Green
-        };
-        execute!(
-            stdout,
-            SetForegroundColor(color),
-            Print(sparkline_chars[idx]),
-            ResetColor,
-        )?;
-    }
-
-    Ok(())
-}
-
-fn render_sparkline_mem(stdout: &mut io::Stdout, data: &[f64], width: usize) -> Result<()> {
-    if data.is_empty() || width == 0 {
-        return Ok(());
-    }
+    let mut stdout = io::stdout();
+    let mut last_terminal_size = get_terminal_size();
+    let mut selected = 0;
+    let mut detail = false;
+    let mut show_system = false;
+    let mut show_histogram = false;
+    let mut show_overlay = false;
 
-    let sparkline_chars = ['▁', '▂', '▃', '▄', '▅', '▆', '▇', '█'];
-    let max_val = data.iter().copied().fold(0.0f64, f64::max).max(1.0);
+    let quiet = logging::pause_stderr();
+    terminal::enable_raw_mode().context("Failed to enable raw mode")?;
 
-    let step = if data.len() > width {
-        data.len() / width
-    } else {
-        1
-    };
+    let result = async {
+        // Moving the selection or opening a cell redraws without re-sampling.
+        let mut resample = true;
+        loop {
+            if resample {
+                join_all(members.iter_mut().map(|m| m.tick(token))).await;
+                for member in &members {
+                    if let (Some(session), Some(info)) = (&member.session, &member.last) {
+                        exporters.on_sample(&ExportSample {
+                            timestamp: Local::now(),
+                            host: Some(member.target.addr.clone()),
+                            port: member.target.port,
+                            pids: session.pids.clone(),
+                            process: info.clone(),
+                            connections: session.tcp_stats.as_ref().map(|s| s.connections),
+                            annotations: Vec::new(),
+                        });
+                    }
+                }
+            }
+            resample = true;
 
-    let samples: Vec<f64> = data.iter().step_by(step).copied().collect();
-    let display_samples = if samples.len() > width {
-        &samples[samples.len() - width..]
-    } else {
-        &samples
-    };
+            execute!(
+                stdout,
+                terminal::Clear(ClearType::All),
+                cursor::MoveTo(0, 0)
+            )?;
 
-    for &val in display_samples {
-        let normalized = (val / max_val).min(1.0);
-        let idx = (normalized * (sparkline_chars.len() - 1) as f64).round() as usize;
-        let color = if val > 1000.0 {
-            Color::Red
-        } else if val > 500.0 {
-            Color::Yellow
-        } else {
-            Color::Blue
-        };
-        execute!(
-            stdout,
-            SetForegroundColor(color),
-            Print(sparkline_chars[idx]),
-            ResetColor,
-        )?;
-    }
+            let member = &mut members[selected];
+            match (detail, member.session.as_mut(), member.last.as_ref()) {
+                (true, Some(session), Some(info)) => {
+                    let system = if show_system {
+                        session.refresh_system().cloned()
+                    } else {
+                        None
+                    };
+                    let frame = Frame {
+                        info,
+                        history: &session.history,
+                        port: session.port,
+                        iteration: session.iteration,
+                        timestamp: Local::now().naive_local(),
+                        width: last_terminal_size.0,
+                        cpu_mode: session.cpu_mode,
+                        cpu_count: session.cpu_count,
+                        tab: None,
+                        cpu_histogram: show_histogram,
+                        chart: ChartView::default(),
+                        overlay: show_overlay,
+                        annotations: &session.annotations,
+                        pids: &session.pids,
+                        container: None,
+                        cgroup: session.cgroup.as_ref(),
+                        throttled_ratio: session.throttled_ratio,
+                        accept_queue: session.accept_queue.as_ref(),
+                        oom: session.oom.as_ref(),
+                        swap: session.swap.as_ref(),
+                        segments: session.segments.as_ref(),
+                        own: None,
+                        cores: None,
+                        pool: session.pool.as_ref(),
+                        children: Some(&session.children),
+                        upstream: session.upstream.as_ref(),
+                        counters: session.counters.as_ref(),
+                        ebpf: session.ebpf.as_ref(),
+                        syscalls: session.syscalls.as_ref(),
+                        tcp_stats: session.tcp_stats.as_ref(),
+                        sockets: session.sockets.as_ref(),
+                        posture: session.posture.as_ref(),
+                        churn: session.churn.as_ref(),
+                        peers: session.peers.as_deref(),
+                        retransmit_alert: DEFAULT_RETRANSMIT_ALERT,
+                        listeners: &session.listeners,
+                        protocol: None,
+                        probe: None,
+                        http_check: None,
+                        tls: None,
+                        power: None,
+                        app: None,
+                        unit: None,
+                        unit_prompt: None,
+                        logs: None,
+                        crabtop_log: None,
+                        open_files: None,
+                        runtime: None,
+                        stack_dump: None,
+                        system: system.as_ref(),
+                        verdict: None,
+                        insight: None,
+                        ai_enabled: false,
+                    };
+                    render_dashboard(&mut stdout, &frame)?;
+                }
+                _ => {
+                    let labels: Vec<String> = members.iter().map(FleetMember::label).collect();
+                    let cells: Vec<GridCell> = members
+                        .iter()
+                        .zip(&labels)
+                        .map(|(m, label)| GridCell {
+                            label,
+                            info: m.last.as_ref(),
+                            pids: m.session.as_ref().map(|s| s.pids.as_slice()).unwrap_or_default(),
+                            history: m.session.as_ref().map(|s| &s.history),
+                            error: m.error.as_deref(),
+                        })
+                        .collect();
+                    render_grid(&mut stdout, &cells, selected, last_terminal_size.0, Local::now().naive_local())?;
+                }
+            }
+            stdout.flush()?;
 
-    Ok(())
-}
+            let columns = grid_columns(last_terminal_size.0);
+            let poll_duration = Duration::from_millis(100);
+            let total_sleep = Duration::from_secs(interval_secs);
+            let mut elapsed = Duration::ZERO;
+            let mut should_break = false;
 
-// Tries to resolve the PID listening on the given port using lsof first,
-// then platform-specific fallbacks on Linux.
-fn find_pid_by_port(port: u16) -> Result<u32> {
-    // Prefer lsof (works well on macOS and most Linux distros)
-    // lsof flags:
-    // -n: no DNS
-    // -P: no port service name translation
-    // -iTCP:<port>: filter TCP for specific port
-    // -sTCP:LISTEN: only listening sockets
-    // -t: terse output (just PIDs)
-    let lsof_args = [
-        "-n",
-        "-P",
-        &format!("-iTCP:{}", port),
-        "-sTCP:LISTEN",
-        "-t",
-    ];
-
-    let lsof_out = Command::new("lsof")
-        .args(&lsof_args)
-        .output();
-
-    if let Ok(out) = lsof_out {
-        if out.status.success() {
-            let stdout = String::from_utf8_lossy(&out.stdout);
-            if let Some(line) = stdout.lines().find(|l| !l.trim().is_empty()) {
-                let pid: u32 = line.trim().parse().context("Failed to parse PID from lsof")?;
-                return Ok(pid);
-            }
-        }
-    }
-
-    // Fallbacks for Linux: try `ss -lntp`
-    #[cfg(target_os = "linux")]
-    {
-        let ss_out = Command::new("ss")
-            .args(["-lntp"]) // listening, numeric, tcp, show process
-            .output();
-
-        if let Ok(out) = ss_out {
-            if out.status.success() {
-                let stdout = String::from_utf8_lossy(&out.stdout);
-                // Example line:
-                // LISTEN 0 128 0.0.0.0:80 ... users:(("nginx",pid=1234,fd=7))
-                for line in stdout.lines() {
-                    if line.contains(&format!(":{} ", port)) || line.ends_with(&format!(":{}", port)) {
-                        if let Some(pid_str) = line.split("pid=").nth(1) {
-                            let pid_part = pid_str.split(|c: char| !c.is_ascii_digit()).next().unwrap_or("");
-                            if !pid_part.is_empty() {
-                                let pid: u32 = pid_part.parse().context("Failed to parse PID from ss output")?;
-                                return Ok(pid);
+            while elapsed < total_sleep {
+                if event::poll(poll_duration)? {
+                    let before = (selected, detail, show_system, show_histogram, show_overlay);
+                    match event::read()? {
+                        Event::Key(key) => match key.code {
+                            KeyCode::Char('q') | KeyCode::Char('c') => should_break = true,
+                            KeyCode::Esc | KeyCode::Backspace if detail => detail = false,
+                            KeyCode::Esc => should_break = true,
+                            KeyCode::Enter => detail = true,
+                            KeyCode::Char('s') if detail => show_system = !show_system,
+                            KeyCode::Char('h') if detail => show_histogram = !show_histogram,
+                            KeyCode::Char('o') if detail => show_overlay = !show_overlay,
+                            KeyCode::Left | KeyCode::Char('h') if !detail => selected = selected.saturating_sub(1),
+                            KeyCode::Right | KeyCode::Char('l') if !detail => {
+                                selected = (selected + 1).min(members.len() - 1)
+                            }
+                            KeyCode::Up | KeyCode::Char('k') if !detail => {
+                                selected = selected.checked_sub(columns).unwrap_or(selected)
                             }
+                            KeyCode::Down | KeyCode::Char('j') if !detail && selected + columns < members.len() => {
+                                selected += columns
+                            }
+                            _ => {}
+                        },
+                        Event::Resize(width, height) => {
+                            last_terminal_size = (width, height);
+                            resample = false;
+                            break;
                         }
+                        _ => {}
+                    }
+                    if should_break {
+                        break;
+                    }
+                    if (selected, detail, show_system, show_histogram, show_overlay) != before {
+                        resample = false;
+                        break;
                     }
                 }
+                elapsed += poll_duration;
             }
-        }
 
-        // Try netstat as a last resort (may require `net-tools`)
-        let netstat_out = Command::new("netstat")
-            .args(["-lntp"]).output();
-        if let Ok(out) = netstat_out {
-            if out.status.success() {
-                let stdout = String::from_utf8_lossy(&out.stdout);
-                // Typical line contains "0.0.0.0:<port>" and "pid/program"
-                for line in stdout.lines() {
-                    if line.contains(&format!(":{}", port)) {
-                        // Extract pid from the last column like "1234/program"
-                        if let Some(last_col) = line.split_whitespace().last() {
-                            if let Some(pid_part) = last_col.split('/').next() {
-                                if let Ok(pid) = pid_part.parse::<u32>() {
-                                    return Ok(pid);
-                                }
-                            }
-                        }
-                    }
-                }
+            if should_break {
+                break;
             }
         }
-    }
-
-    #[cfg(target_os = "macos")]
-    {
-        // On macOS, lsof is the practical way; if it failed, surface error.
-        return Err(anyhow!(
-            "Failed to resolve PID on port {}. Ensure `lsof` is installed and accessible.",
-            port
-        ));
-    }
 
-    #[cfg(target_os = "windows")]
-    {
-        return Err(anyhow!(
-            "Port-to-PID resolution is not implemented on Windows in this tool.",
-        ));
+        Ok::<(), anyhow::Error>(())
     }
+    .await;
 
-    // Other platforms
-    #[cfg(not(any(target_os = "linux", target_os = "macos", target_os = "windows")))]
-    {
-        Err(anyhow!("Unsupported OS for port inspection."))
-    }
-}
-
-async fn collect_process_info(pid: u32) -> Result<ProcessInfo> {
-    let pid = Pid::from_u32(pid);
-
-    // Use tokio::task::spawn_blocking to run CPU measurement in a blocking context
-    // This is necessary because sysinfo's CPU calculation works better with thread sleep
-    let info = tokio::task::spawn_blocking(move || {
-        let mut sys = System::new_all();
-        
-        // First refresh: Get baseline CPU measurement
-        sys.refresh_process(pid);
-        
-        // Wait for at least 200ms to allow accurate CPU usage calculation
-        // The sysinfo crate calculates CPU as a delta between two measurements
-        std::thread::sleep(std::time::Duration::from_millis(200));
-        
-        // Second refresh: Update to calculate CPU usage over the interval
-        sys.refresh_process(pid);
-
-        let proc = sys
-            .process(pid)
-            .ok_or_else(|| anyhow!("Failed to read process info for PID {}", pid.as_u32()))?;
-
-        let name = proc.name().to_string();
-        let cpu_percent = proc.cpu_usage();
-        let memory_mb = (proc.memory() as f64) / 1_000_000.0; // bytes -> MB (decimal)
-
-        Ok::<ProcessInfo, anyhow::Error>(ProcessInfo {
-            name,
-            pid: pid.as_u32(),
-            cpu_percent,
-            memory_mb,
-        })
-    })
-    .await
-    .context("Failed to spawn blocking task for process info collection")??;
-
-    Ok(info)
-}
-
-#[derive(Deserialize)]
-struct OpenAIChoice {
-    message: OpenAIMessage,
-}
+    terminal::disable_raw_mode()?;
+    execute!(stdout, cursor::Show)?;
+    drop(quiet);
+    report_exporter_failures(&mut exporters);
 
-#[derive(Deserialize)]
-struct OpenAIMessage {
-    content: String,
+    result
 }
 
-#[derive(Deserialize)]
-struct OpenAIResponse {
-    choices: Vec<OpenAIChoice>,
+fn get_terminal_size() -> (u16, u16) {
+    terminal_size().unwrap_or((80, 24))
 }
 
-async fn generate_openai_insight(api_key: &str, info: &ProcessInfo) -> Result<String> {
-    let client = reqwest::Client::new();
-
-    let prompt = format!(
-        "Process Insight Request:\nName: {}\nPID: {}\nCPU: {:.2}%\nMemory: {:.2} MB\n\nPlease produce a brief insight about this process's resource consumption (1-2 sentences).",
-        info.name, info.pid, info.cpu_percent, info.memory_mb
+/// `cpu_share` says what the CPU figure is a share of ("of one core").
+fn print_plain(info: &ProcessInfo, listeners: &[Listener], cpu_share: &str, quiet: bool) {
+    if !quiet {
+        println!("Process on port:");
+    }
+    println!(
+        "Name: {name}\nPID: {pid}\nCPU: {cpu:.2}% ({cpu_share})\nMemory: {mem}",
+        name = info.name,
+        pid = info.pid,
+        cpu = info.cpu_percent,
+        mem = format_mb(info.memory_bytes)
     );
-
-    let body = json!({
-        "model": "gpt-4o-mini",
-        "messages": [
-            {"role": "system", "content": "You are a tech genius performance analyst who summarizes resource usage succinctly."},
-            {"role": "user", "content": prompt}
-        ],
-        "temperature": 0.7
-    });
-
-    let res = client
-        .post("https://api.openai.com/v1/chat/completions")
-        .header(CONTENT_TYPE, "application/json")
-        .header(AUTHORIZATION, format!("Bearer {}", api_key))
-        .json(&body)
-        .send()
-        .await
-        .context("Failed to call OpenAI API")?;
-
-    if !res.status().is_success() {
-        let status = res.status();
-        let text = res.text().await.unwrap_or_default();
-        return Err(anyhow!("OpenAI API error: {} - {}", status, text));
-    }
-
-    let parsed: OpenAIResponse = res.json().await.context("Failed to parse OpenAI response")?;
-    let content = parsed
-        .choices
-        .get(0)
-        .map(|c| c.message.content.clone())
-        .ok_or_else(|| anyhow!("No choices returned by OpenAI"))?;
-
-    Ok(content)
-}
\ No newline at end of file
+    println!(
+        "User: {user}\nCommand: {cmd}\nExe: {exe}\nCwd: {cwd}\nUptime: {uptime}",
+        user = info.user.as_deref().unwrap_or("-"),
+        cmd = info.cmdline.join(" "),
+        exe = info.exe.as_ref().map(|p| p.display().to_string()).unwrap_or_else(|| "-".to_string()),
+        cwd = info.cwd.as_ref().map(|p| p.display().to_string()).unwrap_or_else(|| "-".to_string()),
+        uptime = format_duration(info.uptime_secs)
+    );
+    if let Some(shared) = info.shared_bytes {
+        println!(
+            "Memory breakdown: {} private, {} shared, {} swap",
+            format_mb(info.memory_bytes.saturating_sub(shared)),
+            format_mb(shared),
+            format_mb(info.swap_bytes.unwrap_or(0))
+        );
+    }
+    if info.virtual_bytes > 0 {
+        println!("Virtual: {}", format_mb(info.virtual_bytes));
+    }
+    if let Some(commit) = info.commit_bytes {
+        println!("Commit: {}", format_mb(commit));
+    }
+    if !listeners.is_empty() {
+        let ports: Vec<String> = listeners.iter().map(|l| l.to_string()).collect();
+        println!("Listening: {}", ports.join(", "));
+    }
+}
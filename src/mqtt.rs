@@ -0,0 +1,306 @@
+//! Publishing watch samples to an MQTT broker, with Home Assistant
+//! discovery so the watched service's CPU, memory, connections and uptime
+//! show up as sensors on their own.
+//!
+//! Per target (`<node>` is the host and port, e.g. `nas_8096`), with the
+//! default prefixes:
+//!
+//! | Topic | Payload |
+//! |-------|---------|
+//! | `crabtop/<node>/state` | `{"cpu_percent": .., "memory_bytes": .., "connections": .., "uptime_secs": ..}` each sample |
+//! | `crabtop/<node>/availability` | `online`, then `offline` (retained; also the will, so a crash marks it too); the first target's, shared by all of them |
+//! | `homeassistant/sensor/<node>/<sensor>/config` | discovery, retained, sent on each (re)connect |
+//!
+//! MQTT 3.1.1 is spoken here over plain TCP, QoS 0; `mqtt://` URLs may
+//! carry `user:password@`. There's no TLS.
+//!
+//! The exporter is only built with the `mqtt` feature; the topics and
+//! discovery payloads are always there.
+
+use anyhow::{bail, Result};
+use serde_json::{json, Value};
+
+use crate::export::ExportSample;
+
+/// Where an `mqtt://` URL points and how to log in.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MqttUrl {
+    /// `host:port`, 1883 unless given.
+    pub address: String,
+    pub user: Option<String>,
+    pub password: Option<String>,
+}
+
+/// Parses `mqtt://[user[:password]@]host[:port]`; the scheme may be left out.
+pub fn parse_url(url: &str) -> Result<MqttUrl> {
+    let rest = match url.split_once("://") {
+        Some(("mqtt" | "tcp", rest)) => rest,
+        Some((scheme, _)) => bail!("{}:// isn't supported for MQTT; use mqtt://", scheme),
+        None => url,
+    };
+    let rest = rest.trim_end_matches('/');
+    let (auth, host) = match rest.rsplit_once('@') {
+        Some((auth, host)) => (Some(auth), host),
+        None => (None, rest),
+    };
+    if host.is_empty() {
+        bail!("No MQTT broker in {}", url);
+    }
+    let address = if host.rsplit_once(':').is_some_and(|(_, port)| port.parse::<u16>().is_ok()) {
+        host.to_string()
+    } else {
+        format!("{}:1883", host)
+    };
+    let (user, password) = match auth.map(|auth| auth.split_once(':')) {
+        Some(Some((user, password))) => (Some(user.to_string()), Some(password.to_string())),
+        Some(None) => (auth.map(str::to_string), None),
+        None => (None, None),
+    };
+    Ok(MqttUrl { address, user, password })
+}
+
+/// A target's name in topics and entity IDs: the host (this machine's name
+/// when local) and port, with anything but letters and digits as `_`.
+pub fn node_id(sample: &ExportSample, local_host: &str) -> String {
+    let host = sample.host.as_deref().map(|host| host.split(':').next().unwrap_or(host)).unwrap_or(local_host);
+    let node: String = format!("{}_{}", host, sample.port)
+        .chars()
+        .map(|c| if c.is_ascii_alphanumeric() { c.to_ascii_lowercase() } else { '_' })
+        .collect();
+    node.trim_matches('_').to_string()
+}
+
+/// The state payload the sensors read their values from.
+pub fn state(sample: &ExportSample) -> Value {
+    json!({
+        "cpu_percent": (sample.process.cpu_percent as f64 * 100.0).round() / 100.0,
+        "memory_bytes": sample.process.memory_bytes,
+        "connections": sample.connections,
+        "uptime_secs": sample.process.uptime_secs,
+    })
+}
+
+/// Home Assistant discovery messages for one target: (topic, payload) per
+/// sensor, all on one device named after the process and port.
+pub fn discovery(sample: &ExportSample, node: &str, prefix: &str, discovery_prefix: &str, availability: &str) -> Vec<(String, Value)> {
+    let device = json!({
+        "identifiers": [format!("crabtop_{}", node)],
+        "name": format!("{} on port {}", sample.process.name, sample.port),
+        "manufacturer": "crabtop",
+        "sw_version": env!("CARGO_PKG_VERSION"),
+    });
+    let sensors = [
+        ("cpu", "CPU", "cpu_percent", Some("%"), None, "mdi:chip"),
+        ("memory", "Memory", "memory_bytes", Some("B"), Some("data_size"), "mdi:memory"),
+        ("connections", "Connections", "connections", None, None, "mdi:lan-connect"),
+        ("uptime", "Uptime", "uptime_secs", Some("s"), Some("duration"), "mdi:timer-outline"),
+    ];
+    sensors
+        .iter()
+        .map(|(id, name, field, unit, class, icon)| {
+            let mut config = json!({
+                "name": name,
+                "unique_id": format!("crabtop_{}_{}", node, id),
+                "object_id": format!("crabtop_{}_{}", node, id),
+                "state_topic": format!("{}/{}/state", prefix, node),
+                "value_template": format!("{{{{ value_json.{} }}}}", field),
+                "state_class": "measurement",
+                "availability_topic": availability,
+                "icon": icon,
+                "device": device,
+            });
+            if let Some(unit) = unit {
+                config["unit_of_measurement"] = (*unit).into();
+            }
+            if let Some(class) = class {
+                config["device_class"] = (*class).into();
+            }
+            (format!("{}/sensor/{}/{}/config", discovery_prefix, node, id), config)
+        })
+        .collect()
+}
+
+#[cfg(feature = "mqtt")]
+pub use exporter::{MqttExporter, Topics};
+
+#[cfg(feature = "mqtt")]
+mod exporter {
+    use std::collections::BTreeSet;
+    use std::io::{Read, Write};
+    use std::net::{TcpStream, ToSocketAddrs};
+    use std::sync::mpsc::Receiver;
+    use std::time::Duration;
+
+    use anyhow::{bail, Context, Result};
+    use sysinfo::System;
+
+    use super::MqttUrl;
+    use crate::export::{ExportSample, Exporter, LastError, QueuedWorker};
+
+    const TIMEOUT: Duration = Duration::from_secs(5);
+    /// Seconds of silence the broker allows before dropping us; samples
+    /// count, so only watches slower than this reconnect each time.
+    const KEEP_ALIVE: u16 = 300;
+
+    /// Where the exporter publishes, besides the broker's address.
+    #[derive(Debug, Clone)]
+    pub struct Topics {
+        /// Start of the state and availability topics, `crabtop` by default.
+        pub prefix: String,
+        /// Home Assistant's discovery prefix, `homeassistant` by default;
+        /// `None` sends no discovery messages.
+        pub discovery_prefix: Option<String>,
+    }
+
+    /// Publishes through a `QueuedWorker`; after a failure the connection
+    /// is made afresh.
+    pub struct MqttExporter {
+        worker: QueuedWorker,
+    }
+
+    impl MqttExporter {
+        /// The broker isn't contacted until the first sample.
+        pub fn new(url: &str, topics: Topics) -> Result<Self> {
+            let url = super::parse_url(url)?;
+            let worker = QueuedWorker::spawn("mqtt", "the broker", move |samples, errors| run(&url, &topics, samples, errors))?;
+            Ok(Self { worker })
+        }
+    }
+
+    impl Exporter for MqttExporter {
+        fn name(&self) -> &str {
+            "mqtt"
+        }
+
+        fn on_sample(&mut self, sample: &ExportSample) -> Result<()> {
+            self.worker.send(sample)
+        }
+
+        fn on_shutdown(&mut self) -> Result<()> {
+            // The worker says it's going once the queue is empty.
+            self.worker.drain()
+        }
+    }
+
+    /// A live connection and what's been announced on it.
+    struct Session {
+        stream: TcpStream,
+        availability: String,
+        announced: BTreeSet<String>,
+    }
+
+    fn run(url: &MqttUrl, topics: &Topics, samples: Receiver<ExportSample>, errors: LastError) {
+        let local = System::host_name().unwrap_or_else(|| "localhost".to_string());
+        let mut session: Option<Session> = None;
+        for sample in samples {
+            let node = super::node_id(&sample, &local);
+            let result = (|| {
+                if session.is_none() {
+                    session = Some(connect(url, &topics.prefix, &node)?);
+                }
+                let session = session.as_mut().unwrap();
+                if let Some(discovery_prefix) = &topics.discovery_prefix {
+                    if !session.announced.contains(&node) {
+                        for (topic, config) in super::discovery(&sample, &node, &topics.prefix, discovery_prefix, &session.availability) {
+                            publish(&mut session.stream, &topic, config.to_string().as_bytes(), true)?;
+                        }
+                        session.announced.insert(node.clone());
+                    }
+                }
+                let state = super::state(&sample).to_string();
+                publish(&mut session.stream, &format!("{}/{}/state", topics.prefix, node), state.as_bytes(), false)
+            })();
+            if let Err(err) = result {
+                session = None;
+                *errors.lock().unwrap() = Some(format!("{:#}", err));
+            }
+        }
+        if let Some(mut session) = session {
+            let availability = session.availability.clone();
+            let _ = publish(&mut session.stream, &availability, b"offline", true);
+            let _ = session.stream.write_all(&[0xE0, 0x00]);
+        }
+    }
+
+    /// Connects as `crabtop_<node>` of the first target, with `offline` as
+    /// the will on its availability topic, and says it's `online`.
+    fn connect(url: &MqttUrl, prefix: &str, node: &str) -> Result<Session> {
+        let addr = url
+            .address
+            .to_socket_addrs()
+            .with_context(|| format!("Failed to resolve {}", url.address))?
+            .next()
+            .with_context(|| format!("{} didn't resolve to an address", url.address))?;
+        let mut stream = TcpStream::connect_timeout(&addr, TIMEOUT).with_context(|| format!("Failed to connect to {}", url.address))?;
+        stream.set_read_timeout(Some(TIMEOUT))?;
+        stream.set_write_timeout(Some(TIMEOUT))?;
+
+        let availability = format!("{}/{}/availability", prefix, node);
+        // Clean session, with a retained QoS 0 will.
+        let mut flags = 0x02 | 0x04 | 0x20;
+        let mut packet = Vec::new();
+        put_string(&mut packet, b"MQTT");
+        packet.push(4); // 3.1.1
+        let flags_at = packet.len();
+        packet.push(0);
+        packet.extend_from_slice(&KEEP_ALIVE.to_be_bytes());
+        put_string(&mut packet, format!("crabtop_{}", node).as_bytes());
+        put_string(&mut packet, availability.as_bytes());
+        put_string(&mut packet, b"offline");
+        if let Some(user) = &url.user {
+            flags |= 0x80;
+            put_string(&mut packet, user.as_bytes());
+        }
+        if let Some(password) = &url.password {
+            flags |= 0x40;
+            put_string(&mut packet, password.as_bytes());
+        }
+        packet[flags_at] = flags;
+        send(&mut stream, 0x10, &packet)?;
+
+        let mut connack = [0u8; 4];
+        stream.read_exact(&mut connack).context("The MQTT broker didn't answer")?;
+        if connack[0] != 0x20 {
+            bail!("{} doesn't look like an MQTT broker", url.address);
+        }
+        match connack[3] {
+            0 => {}
+            4 | 5 => bail!("The MQTT broker refused the login"),
+            code => bail!("The MQTT broker refused the connection (code {})", code),
+        }
+        publish(&mut stream, &availability, b"online", true)?;
+        Ok(Session {
+            stream,
+            availability,
+            announced: BTreeSet::new(),
+        })
+    }
+
+    fn publish(stream: &mut TcpStream, topic: &str, payload: &[u8], retain: bool) -> Result<()> {
+        let mut packet = Vec::with_capacity(topic.len() + payload.len() + 2);
+        put_string(&mut packet, topic.as_bytes());
+        packet.extend_from_slice(payload);
+        send(stream, 0x30 | retain as u8, &packet)
+    }
+
+    fn send(stream: &mut TcpStream, header: u8, body: &[u8]) -> Result<()> {
+        let mut packet = vec![header];
+        let mut len = body.len();
+        loop {
+            let byte = (len % 128) as u8;
+            len /= 128;
+            packet.push(if len > 0 { byte | 0x80 } else { byte });
+            if len == 0 {
+                break;
+            }
+        }
+        packet.extend_from_slice(body);
+        stream.write_all(&packet)?;
+        Ok(())
+    }
+
+    fn put_string(out: &mut Vec<u8>, value: &[u8]) {
+        out.extend_from_slice(&(value.len() as u16).to_be_bytes());
+        out.extend_from_slice(value);
+    }
+}
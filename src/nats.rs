@@ -0,0 +1,194 @@
+//! Publishing each watch sample to a NATS subject, as the same JSON the
+//! webhook and `--record` get, so daemons across a fleet can feed one
+//! pipeline.
+//!
+//! The client protocol is a few text lines over TCP, so it's spoken here:
+//! `CONNECT`, then a `PUB` per sample followed by a `PING`, whose `PONG`
+//! confirms the server took it (or brings back its `-ERR`). `nats://` URLs
+//! may carry `user:password@` or a `token@`; there's no TLS or NKey auth.
+//!
+//! The exporter is only built with the `nats` feature; `parse_url` is
+//! always there.
+
+use anyhow::{bail, Result};
+
+/// Where a `nats://` URL points and how to log in.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct NatsUrl {
+    /// `host:port`, 4222 unless given.
+    pub address: String,
+    pub user: Option<String>,
+    pub password: Option<String>,
+    pub token: Option<String>,
+}
+
+/// Parses `nats://[user:password@|token@]host[:port]`; the scheme may be left out.
+pub fn parse_url(url: &str) -> Result<NatsUrl> {
+    let rest = match url.split_once("://") {
+        Some(("nats", rest)) => rest,
+        Some((scheme, _)) => bail!("{}:// isn't supported for NATS; use nats://", scheme),
+        None => url,
+    };
+    let rest = rest.trim_end_matches('/');
+    let (auth, host) = match rest.rsplit_once('@') {
+        Some((auth, host)) => (Some(auth), host),
+        None => (None, rest),
+    };
+    if host.is_empty() {
+        bail!("No NATS server in {}", url);
+    }
+    let address = if host.rsplit_once(':').is_some_and(|(_, port)| port.parse::<u16>().is_ok()) {
+        host.to_string()
+    } else {
+        format!("{}:4222", host)
+    };
+    let (mut user, mut password, mut token) = (None, None, None);
+    match auth.map(|auth| auth.split_once(':')) {
+        Some(Some((u, p))) => {
+            user = Some(u.to_string());
+            password = Some(p.to_string());
+        }
+        Some(None) => token = auth.map(str::to_string),
+        None => {}
+    }
+    Ok(NatsUrl {
+        address,
+        user,
+        password,
+        token,
+    })
+}
+
+#[cfg(feature = "nats")]
+pub use exporter::NatsExporter;
+
+#[cfg(feature = "nats")]
+mod exporter {
+    use std::io::{BufRead, BufReader, Write};
+    use std::net::{TcpStream, ToSocketAddrs};
+    use std::sync::mpsc::Receiver;
+    use std::time::Duration;
+
+    use anyhow::{bail, Context, Result};
+    use serde_json::json;
+
+    use super::NatsUrl;
+    use crate::export::{ExportSample, Exporter, LastError, QueuedWorker};
+
+    const TIMEOUT: Duration = Duration::from_secs(5);
+
+    /// Publishes samples through a `QueuedWorker`; after a failure the
+    /// connection is made afresh.
+    pub struct NatsExporter {
+        worker: QueuedWorker,
+    }
+
+    impl NatsExporter {
+        /// The server isn't contacted until the first sample.
+        pub fn new(url: &str, subject: &str) -> Result<Self> {
+            let url = super::parse_url(url)?;
+            if subject.is_empty() || subject.contains(char::is_whitespace) {
+                bail!("`{}` isn't a NATS subject", subject);
+            }
+            let subject = subject.to_string();
+            let worker = QueuedWorker::spawn("nats", "the server", move |samples, errors| run(&url, &subject, samples, errors))?;
+            Ok(Self { worker })
+        }
+    }
+
+    impl Exporter for NatsExporter {
+        fn name(&self) -> &str {
+            "nats"
+        }
+
+        fn on_sample(&mut self, sample: &ExportSample) -> Result<()> {
+            self.worker.send(sample)
+        }
+
+        fn on_shutdown(&mut self) -> Result<()> {
+            self.worker.drain()
+        }
+    }
+
+    fn run(url: &NatsUrl, subject: &str, samples: Receiver<ExportSample>, errors: LastError) {
+        let mut connection: Option<BufReader<TcpStream>> = None;
+        for sample in samples {
+            let result = serde_json::to_vec(&sample).map_err(anyhow::Error::from).and_then(|payload| {
+                if connection.is_none() {
+                    connection = Some(connect(url)?);
+                }
+                publish(connection.as_mut().unwrap(), subject, &payload)
+            });
+            if let Err(err) = result {
+                connection = None;
+                *errors.lock().unwrap() = Some(format!("{:#}", err));
+            }
+        }
+    }
+
+    fn connect(url: &NatsUrl) -> Result<BufReader<TcpStream>> {
+        let addr = url
+            .address
+            .to_socket_addrs()
+            .with_context(|| format!("Failed to resolve {}", url.address))?
+            .next()
+            .with_context(|| format!("{} didn't resolve to an address", url.address))?;
+        let stream = TcpStream::connect_timeout(&addr, TIMEOUT).with_context(|| format!("Failed to connect to {}", url.address))?;
+        stream.set_read_timeout(Some(TIMEOUT))?;
+        stream.set_write_timeout(Some(TIMEOUT))?;
+        let mut reader = BufReader::new(stream);
+
+        let info = read_line(&mut reader)?;
+        if !info.starts_with("INFO ") {
+            bail!("{} doesn't look like a NATS server (it said `{}`)", url.address, info);
+        }
+        let mut options = json!({
+            "verbose": false,
+            "pedantic": false,
+            "name": "crabtop",
+            "lang": "rust",
+            "version": env!("CARGO_PKG_VERSION"),
+        });
+        if let Some(user) = &url.user {
+            options["user"] = user.clone().into();
+            options["pass"] = url.password.clone().unwrap_or_default().into();
+        }
+        if let Some(token) = &url.token {
+            options["auth_token"] = token.clone().into();
+        }
+        write!(reader.get_mut(), "CONNECT {}\r\nPING\r\n", options)?;
+        wait_for_pong(&mut reader)?;
+        Ok(reader)
+    }
+
+    fn publish(reader: &mut BufReader<TcpStream>, subject: &str, payload: &[u8]) -> Result<()> {
+        let stream = reader.get_mut();
+        write!(stream, "PUB {} {}\r\n", subject, payload.len())?;
+        stream.write_all(payload)?;
+        stream.write_all(b"\r\nPING\r\n")?;
+        wait_for_pong(reader)
+    }
+
+    /// Reads up to our `PONG`, answering the server's own `PING`s on the way.
+    fn wait_for_pong(reader: &mut BufReader<TcpStream>) -> Result<()> {
+        loop {
+            let line = read_line(reader)?;
+            match line.as_str() {
+                "PONG" => return Ok(()),
+                "PING" => reader.get_mut().write_all(b"PONG\r\n")?,
+                "+OK" => {}
+                _ if line.starts_with("INFO ") => {}
+                _ if line.starts_with("-ERR") => bail!("NATS said {}", line.trim_start_matches("-ERR").trim()),
+                _ => bail!("Unexpected line from NATS: {}", line),
+            }
+        }
+    }
+
+    fn read_line(reader: &mut BufReader<TcpStream>) -> Result<String> {
+        let mut line = String::new();
+        if reader.read_line(&mut line)? == 0 {
+            bail!("NATS closed the connection");
+        }
+        Ok(line.trim_end().to_string())
+    }
+}
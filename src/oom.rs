@@ -0,0 +1,136 @@
+//! How exposed the process is to the OOM killer: its `oom_score`, where that
+//! ranks on the host, and how much memory pressure (PSI) the host is under.
+
+use serde::{Deserialize, Serialize};
+
+use crate::exec::Runner;
+
+/// PSI `some` share above which the host counts as under memory pressure.
+pub const PRESSURE_WARNING_PERCENT: f64 = 10.0;
+
+/// `/proc/pressure/memory`: the share of time some (or all) tasks were
+/// stalled waiting on memory, averaged over 10 and 60 seconds.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+pub struct MemoryPressure {
+    pub some_avg10: f64,
+    pub some_avg60: f64,
+    pub full_avg10: f64,
+    pub full_avg60: f64,
+}
+
+impl MemoryPressure {
+    pub fn is_high(&self) -> bool {
+        self.some_avg10 >= PRESSURE_WARNING_PERCENT || self.full_avg10 > 0.0
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct OomStatus {
+    /// 0–1000 (higher is killed first), after `oom_score_adj`.
+    pub score: u32,
+    /// -1000 (never kill) to 1000.
+    pub score_adj: i32,
+    /// 1 when no process on the host has a higher score.
+    pub rank: usize,
+    /// Processes whose score was compared.
+    pub processes: usize,
+    /// `None` without PSI (kernels before 4.20, or PSI disabled).
+    pub pressure: Option<MemoryPressure>,
+}
+
+impl OomStatus {
+    /// The kernel picks the highest score, so rank 1 goes first.
+    pub fn is_next_victim(&self) -> bool {
+        self.rank == 1 && self.score > 0 && self.score_adj > -1000
+    }
+
+    /// Next in line while the host is actually short of memory.
+    pub fn at_risk(&self) -> bool {
+        self.is_next_victim() && self.pressure.is_some_and(|p| p.is_high())
+    }
+
+    /// Builds a status from the process's own score and adjustment, the
+    /// scores of every process on the host, and the PSI file.
+    pub fn from_parts(score: u32, score_adj: i32, all_scores: &[u32], pressure: Option<MemoryPressure>) -> Self {
+        Self {
+            score,
+            score_adj,
+            rank: 1 + all_scores.iter().filter(|&&s| s > score).count(),
+            processes: all_scores.len().max(1),
+            pressure,
+        }
+    }
+}
+
+/// Parses `/proc/pressure/memory`.
+pub fn parse_pressure(text: &str) -> Option<MemoryPressure> {
+    let mut pressure = MemoryPressure::default();
+    let mut seen = false;
+    for line in text.lines() {
+        let mut words = line.split_whitespace();
+        let kind = words.next();
+        for word in words {
+            let Some((key, value)) = word.split_once('=') else {
+                continue;
+            };
+            let Ok(value) = value.parse::<f64>() else {
+                continue;
+            };
+            let slot = match (kind, key) {
+                (Some("some"), "avg10") => &mut pressure.some_avg10,
+                (Some("some"), "avg60") => &mut pressure.some_avg60,
+                (Some("full"), "avg10") => &mut pressure.full_avg10,
+                (Some("full"), "avg60") => &mut pressure.full_avg60,
+                _ => continue,
+            };
+            *slot = value;
+            seen = true;
+        }
+    }
+    seen.then_some(pressure)
+}
+
+#[cfg(target_os = "linux")]
+pub fn oom_status(pid: u32) -> Option<OomStatus> {
+    let read = |path: String| std::fs::read_to_string(path).ok();
+    let score = read(format!("/proc/{}/oom_score", pid))?.trim().parse().ok()?;
+    let score_adj = read(format!("/proc/{}/oom_score_adj", pid))?.trim().parse().ok()?;
+    let all_scores: Vec<u32> = std::fs::read_dir("/proc")
+        .ok()?
+        .flatten()
+        .filter(|entry| entry.file_name().to_string_lossy().bytes().all(|b| b.is_ascii_digit()))
+        .filter_map(|entry| read(format!("{}/oom_score", entry.path().display())))
+        .filter_map(|text| text.trim().parse().ok())
+        .collect();
+    let pressure = read("/proc/pressure/memory".to_string()).as_deref().and_then(parse_pressure);
+    Some(OomStatus::from_parts(score, score_adj, &all_scores, pressure))
+}
+
+#[cfg(not(target_os = "linux"))]
+pub fn oom_status(_pid: u32) -> Option<OomStatus> {
+    None
+}
+
+/// `oom_status` through `runner`. The scores, the memory pressure and every
+/// process's score come back from one command, split by a marker line.
+pub fn oom_status_on(runner: &Runner, pid: u32) -> Option<OomStatus> {
+    const MARKER: &str = "::crabtop::";
+    let script = format!(
+        "cat /proc/{pid}/oom_score /proc/{pid}/oom_score_adj || exit 1; echo {MARKER}; \
+         cat /proc/pressure/memory 2>/dev/null; echo {MARKER}; cat /proc/[0-9]*/oom_score 2>/dev/null; true"
+    );
+    let out = runner.shell(&script).ok().filter(|out| out.status.success())?;
+    let text = String::from_utf8_lossy(&out.stdout);
+    let mut sections = text.split(MARKER);
+    let mut own = sections.next()?.split_whitespace();
+    let score = own.next()?.parse().ok()?;
+    let score_adj = own.next()?.parse().ok()?;
+    let pressure = sections.next().and_then(parse_pressure);
+    let all_scores: Vec<u32> = sections
+        .next()
+        .unwrap_or_default()
+        .split_whitespace()
+        .filter_map(|s| s.parse().ok())
+        .collect();
+    Some(OomStatus::from_parts(score, score_adj, &all_scores, pressure))
+}
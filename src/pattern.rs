@@ -0,0 +1,406 @@
+//! A small regular-expression matcher for `--match`, so picking a process
+//! by its command line doesn't pull in a regex engine.
+//!
+//! Supports the everyday subset: literals, `.`, classes (`[a-z_]`, `[^0-9]`),
+//! `\d \w \s` and their negations, `^ $`, groups, `|`, and the quantifiers
+//! `* + ? {n} {n,} {n,m}` (greedy). Other letter escapes such as `\b` are
+//! rejected rather than guessed at. The pattern compiles to an NFA whose
+//! states are followed over the text all at once, so a match takes time
+//! linear in the text whatever the pattern: `(\S+ )*worker` can't hang on a
+//! long JVM command line the way a backtracking matcher would.
+
+use std::fmt;
+use std::mem;
+use std::str::FromStr;
+
+/// The largest count a `{n,m}` may give; each repeat is its own copy of the piece.
+const MAX_REPEAT: usize = 1000;
+
+/// Instructions a pattern may compile to, so nested repeats can't blow up.
+const MAX_PROGRAM: usize = 10_000;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Node {
+    Char(char),
+    Any,
+    Class { ranges: Vec<(char, char)>, negated: bool },
+    Start,
+    End,
+    Group(Vec<Vec<Node>>),
+    Repeat { node: Box<Node>, min: usize, max: Option<usize> },
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Inst {
+    /// One character matching a `Char`, `Any` or `Class` node.
+    Char(Node),
+    Start,
+    End,
+    /// Carry on at both.
+    Split(usize, usize),
+    Jump(usize),
+    Match,
+}
+
+/// A compiled pattern; matches anywhere in the text unless anchored.
+#[derive(Clone, PartialEq, Eq)]
+pub struct Pattern {
+    source: String,
+    program: Vec<Inst>,
+}
+
+impl Pattern {
+    pub fn new(source: &str) -> Result<Self, String> {
+        let chars: Vec<char> = source.chars().collect();
+        let mut parser = Parser { chars: &chars, pos: 0 };
+        let alternatives = parser.alternatives()?;
+        if parser.pos < chars.len() {
+            return Err(format!("unmatched ) in {}", source));
+        }
+        let mut compiler = Compiler { program: Vec::new() };
+        compiler.alternatives(&alternatives)?;
+        compiler.push(Inst::Match)?;
+        Ok(Self {
+            source: source.to_string(),
+            program: compiler.program,
+        })
+    }
+
+    pub fn is_match(&self, text: &str) -> bool {
+        let text: Vec<char> = text.chars().collect();
+        let mut current = Threads::new(self.program.len());
+        let mut next = Threads::new(self.program.len());
+        for pos in 0..=text.len() {
+            // Unanchored: a match may start at any position.
+            if self.add(&mut current, 0, pos, text.len()) {
+                return true;
+            }
+            let Some(&c) = text.get(pos) else {
+                break;
+            };
+            next.clear();
+            for &pc in &current.list {
+                if let Inst::Char(node) = &self.program[pc] {
+                    if matches_char(node, c) && self.add(&mut next, pc + 1, pos + 1, text.len()) {
+                        return true;
+                    }
+                }
+            }
+            mem::swap(&mut current, &mut next);
+        }
+        false
+    }
+
+    /// Adds `pc` and every state reachable from it without reading a
+    /// character; true once that reaches `Match`.
+    fn add(&self, threads: &mut Threads, pc: usize, pos: usize, len: usize) -> bool {
+        let mut stack = vec![pc];
+        while let Some(pc) = stack.pop() {
+            if !threads.visit(pc) {
+                continue;
+            }
+            match self.program[pc] {
+                Inst::Char(_) => threads.list.push(pc),
+                Inst::Start if pos == 0 => stack.push(pc + 1),
+                Inst::End if pos == len => stack.push(pc + 1),
+                Inst::Start | Inst::End => {}
+                Inst::Split(first, second) => stack.extend([second, first]),
+                Inst::Jump(to) => stack.push(to),
+                Inst::Match => return true,
+            }
+        }
+        false
+    }
+
+    pub fn as_str(&self) -> &str {
+        &self.source
+    }
+}
+
+impl FromStr for Pattern {
+    type Err = String;
+
+    fn from_str(source: &str) -> Result<Self, String> {
+        Self::new(source)
+    }
+}
+
+impl fmt::Debug for Pattern {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Pattern({:?})", self.source)
+    }
+}
+
+impl fmt::Display for Pattern {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.source)
+    }
+}
+
+struct Parser<'a> {
+    chars: &'a [char],
+    pos: usize,
+}
+
+impl Parser<'_> {
+    fn peek(&self) -> Option<char> {
+        self.chars.get(self.pos).copied()
+    }
+
+    fn alternatives(&mut self) -> Result<Vec<Vec<Node>>, String> {
+        let mut alternatives = vec![self.sequence()?];
+        while self.peek() == Some('|') {
+            self.pos += 1;
+            alternatives.push(self.sequence()?);
+        }
+        Ok(alternatives)
+    }
+
+    fn sequence(&mut self) -> Result<Vec<Node>, String> {
+        let mut nodes = Vec::new();
+        while let Some(c) = self.peek() {
+            if c == '|' || c == ')' {
+                break;
+            }
+            self.pos += 1;
+            let atom = match c {
+                '.' => Node::Any,
+                '^' => Node::Start,
+                '$' => Node::End,
+                '(' => {
+                    // `(?:...)` is accepted and means the same as `(...)`.
+                    if self.chars[self.pos..].starts_with(&['?', ':']) {
+                        self.pos += 2;
+                    }
+                    let inner = self.alternatives()?;
+                    if self.peek() != Some(')') {
+                        return Err("unclosed (".to_string());
+                    }
+                    self.pos += 1;
+                    Node::Group(inner)
+                }
+                '[' => self.class()?,
+                '\\' => self.escape()?,
+                '*' | '+' | '?' | '{' => return Err(format!("nothing to repeat before {}", c)),
+                c => Node::Char(c),
+            };
+            nodes.push(self.quantified(atom)?);
+        }
+        Ok(nodes)
+    }
+
+    fn quantified(&mut self, atom: Node) -> Result<Node, String> {
+        let (min, max) = match self.peek() {
+            Some('*') => (0, None),
+            Some('+') => (1, None),
+            Some('?') => (0, Some(1)),
+            Some('{') => {
+                let close = self.chars[self.pos..]
+                    .iter()
+                    .position(|&c| c == '}')
+                    .ok_or("unclosed {")?;
+                let body: String = self.chars[self.pos + 1..self.pos + close].iter().collect();
+                let number = |s: &str| s.trim().parse::<usize>().map_err(|_| format!("bad repeat {{{}}}", body));
+                let bounds = match body.split_once(',') {
+                    None => (number(&body)?, Some(number(&body)?)),
+                    Some((min, "")) => (number(min)?, None),
+                    Some((min, max)) => (number(min)?, Some(number(max)?)),
+                };
+                if bounds.1.is_some_and(|max| max < bounds.0) {
+                    return Err(format!("bad repeat {{{}}}: the minimum is above the maximum", body));
+                }
+                if bounds.1.unwrap_or(bounds.0) > MAX_REPEAT {
+                    return Err(format!("bad repeat {{{}}}: at most {} repeats", body, MAX_REPEAT));
+                }
+                self.pos += close;
+                bounds
+            }
+            _ => return Ok(atom),
+        };
+        self.pos += 1;
+        if matches!(atom, Node::Start | Node::End) {
+            return Err("nothing to repeat".to_string());
+        }
+        Ok(Node::Repeat {
+            node: Box::new(atom),
+            min,
+            max,
+        })
+    }
+
+    fn class(&mut self) -> Result<Node, String> {
+        let negated = self.peek() == Some('^');
+        if negated {
+            self.pos += 1;
+        }
+        let mut ranges = Vec::new();
+        let mut first = true;
+        loop {
+            let c = self.peek().ok_or("unclosed [")?;
+            self.pos += 1;
+            // A `]` straight after `[` or `[^` is a literal.
+            if c == ']' && !first {
+                break;
+            }
+            first = false;
+            let low = if c == '\\' {
+                match self.escape()? {
+                    Node::Char(c) => c,
+                    Node::Class {
+                        ranges: more,
+                        negated: false,
+                    } => {
+                        ranges.extend(more);
+                        continue;
+                    }
+                    _ => return Err("negated escapes aren't allowed in [...]".to_string()),
+                }
+            } else {
+                c
+            };
+            if self.peek() == Some('-') && self.chars.get(self.pos + 1).is_some_and(|&c| c != ']') {
+                let high = self.chars[self.pos + 1];
+                self.pos += 2;
+                ranges.push((low, high));
+            } else {
+                ranges.push((low, low));
+            }
+        }
+        Ok(Node::Class { ranges, negated })
+    }
+
+    fn escape(&mut self) -> Result<Node, String> {
+        let c = self.peek().ok_or("trailing \\")?;
+        self.pos += 1;
+        let class = |ranges: &[(char, char)], negated| Node::Class {
+            ranges: ranges.to_vec(),
+            negated,
+        };
+        const DIGIT: &[(char, char)] = &[('0', '9')];
+        const WORD: &[(char, char)] = &[('a', 'z'), ('A', 'Z'), ('0', '9'), ('_', '_')];
+        const SPACE: &[(char, char)] = &[(' ', ' '), ('\t', '\r')];
+        Ok(match c {
+            'd' => class(DIGIT, false),
+            'D' => class(DIGIT, true),
+            'w' => class(WORD, false),
+            'W' => class(WORD, true),
+            's' => class(SPACE, false),
+            'S' => class(SPACE, true),
+            't' => Node::Char('\t'),
+            'n' => Node::Char('\n'),
+            c if c.is_ascii_alphanumeric() => return Err(format!("unsupported escape \\{}", c)),
+            c => Node::Char(c),
+        })
+    }
+}
+
+struct Compiler {
+    program: Vec<Inst>,
+}
+
+impl Compiler {
+    fn push(&mut self, inst: Inst) -> Result<usize, String> {
+        if self.program.len() >= MAX_PROGRAM {
+            return Err("pattern is too large".to_string());
+        }
+        self.program.push(inst);
+        Ok(self.program.len() - 1)
+    }
+
+    /// Points the `Split` at `at` past what was compiled since.
+    fn skip_to_end(&mut self, at: usize) {
+        self.program[at] = Inst::Split(at + 1, self.program.len());
+    }
+
+    fn alternatives(&mut self, alternatives: &[Vec<Node>]) -> Result<(), String> {
+        let (last, others) = alternatives.split_last().expect("at least one alternative");
+        let mut jumps = Vec::new();
+        for sequence in others {
+            let split = self.push(Inst::Split(0, 0))?;
+            self.sequence(sequence)?;
+            jumps.push(self.push(Inst::Jump(0))?);
+            self.skip_to_end(split);
+        }
+        self.sequence(last)?;
+        let end = self.program.len();
+        for jump in jumps {
+            self.program[jump] = Inst::Jump(end);
+        }
+        Ok(())
+    }
+
+    fn sequence(&mut self, nodes: &[Node]) -> Result<(), String> {
+        nodes.iter().try_for_each(|node| self.node(node))
+    }
+
+    fn node(&mut self, node: &Node) -> Result<(), String> {
+        match node {
+            Node::Start => self.push(Inst::Start).map(drop),
+            Node::End => self.push(Inst::End).map(drop),
+            Node::Group(alternatives) => self.alternatives(alternatives),
+            Node::Repeat { node, min, max } => {
+                for _ in 0..*min {
+                    self.node(node)?;
+                }
+                match max {
+                    None => {
+                        let split = self.push(Inst::Split(0, 0))?;
+                        self.node(node)?;
+                        self.push(Inst::Jump(split))?;
+                        self.skip_to_end(split);
+                    }
+                    // Each optional copy may stop the run.
+                    Some(max) => {
+                        let mut splits = Vec::new();
+                        for _ in *min..*max {
+                            splits.push(self.push(Inst::Split(0, 0))?);
+                            self.node(node)?;
+                        }
+                        for split in splits {
+                            self.skip_to_end(split);
+                        }
+                    }
+                }
+                Ok(())
+            }
+            single => self.push(Inst::Char(single.clone())).map(drop),
+        }
+    }
+}
+
+/// The NFA states alive at one position of the text.
+struct Threads {
+    list: Vec<usize>,
+    /// The `generation` each state was last reached in.
+    seen: Vec<usize>,
+    generation: usize,
+}
+
+impl Threads {
+    fn new(states: usize) -> Self {
+        Self {
+            list: Vec::new(),
+            seen: vec![0; states],
+            generation: 1,
+        }
+    }
+
+    fn clear(&mut self) {
+        self.list.clear();
+        self.generation += 1;
+    }
+
+    /// Marks `pc` as reached; false if it already was.
+    fn visit(&mut self, pc: usize) -> bool {
+        mem::replace(&mut self.seen[pc], self.generation) != self.generation
+    }
+}
+
+fn matches_char(node: &Node, c: char) -> bool {
+    match node {
+        Node::Char(expected) => *expected == c,
+        Node::Any => c != '\n',
+        Node::Class { ranges, negated } => ranges.iter().any(|&(low, high)| low <= c && c <= high) != *negated,
+        _ => false,
+    }
+}
@@ -0,0 +1,231 @@
+//! Who is on the other end of the port's open connections, to find the
+//! client hammering it and tell internal traffic from the internet's at a
+//! glance: the top peer addresses by open connections, the bytes each moved
+//! in the last interval (from the connections' `tcp_info`, on Linux), whether
+//! it is on a private network and, opt-in, its hostname (`--resolve-peers`)
+//! and country and network (`--geoip`, with a MaxMind database).
+//!
+//! Reverse lookups go through `getent hosts` (or `host`) on a thread of
+//! their own, spaced out so a busy port can't flood the resolver; answers,
+//! failures too, are kept for `NAME_TTL`. They are made from the machine
+//! crabtop runs on.
+
+use std::collections::{HashMap, HashSet};
+use std::net::IpAddr;
+use std::sync::mpsc::{self, Receiver, SyncSender};
+use std::time::{Duration, Instant};
+
+use anyhow::{Context, Result};
+
+use crate::exec::Runner;
+use crate::geoip::{GeoInfo, GeoIp};
+use crate::port::{ConnectionBytes, PortConnection};
+
+/// Peers shown in the panel by default, most connections first.
+pub const DEFAULT_PEER_ROWS: usize = 8;
+
+/// How long a hostname (or the lack of one) is remembered.
+pub const NAME_TTL: Duration = Duration::from_secs(600);
+
+/// Time between two reverse lookups, so at most 5 a second.
+pub const LOOKUP_SPACING: Duration = Duration::from_millis(200);
+
+/// Addresses waiting for a lookup; more wait for the next tick.
+const QUEUE: usize = 64;
+
+/// One address at the other end of the port's connections.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Peer {
+    pub address: IpAddr,
+    /// Open connections from it.
+    pub connections: usize,
+    /// Sent and received over the last interval, in bytes a second; `None`
+    /// before two readings of the connections' counters.
+    pub bytes_per_sec: Option<f64>,
+    /// `None` until looked up, or when it has no name.
+    pub hostname: Option<String>,
+    pub geo: GeoInfo,
+}
+
+impl Peer {
+    pub fn is_internal(&self) -> bool {
+        is_internal(self.address)
+    }
+}
+
+/// Loopback, private (RFC 1918, `fc00::/7`), link-local and carrier-grade
+/// NAT (`100.64.0.0/10`) addresses: traffic that didn't come from the internet.
+pub fn is_internal(ip: IpAddr) -> bool {
+    match ip.to_canonical() {
+        IpAddr::V4(v4) => {
+            let [a, b, ..] = v4.octets();
+            v4.is_loopback() || v4.is_private() || v4.is_link_local() || (a == 100 && (64..128).contains(&b))
+        }
+        IpAddr::V6(v6) => {
+            let first = v6.segments()[0];
+            v6.is_loopback() || first & 0xfe00 == 0xfc00 || first & 0xffc0 == 0xfe80
+        }
+    }
+}
+
+/// The peer half of a connection's `local->peer` key, as ss prints it
+/// (`[::ffff:10.0.0.7]:51234`, `[fe80::1%eth0]:22`).
+pub fn peer_address(key: &str) -> Option<IpAddr> {
+    let (_, peer) = key.rsplit_once("->")?;
+    let (host, port) = peer.rsplit_once(':')?;
+    let host = host.trim_start_matches('[').trim_end_matches(']');
+    let ip: IpAddr = host.split('%').next()?.parse().ok()?;
+    port.parse::<u16>().ok()?;
+    Some(ip.to_canonical())
+}
+
+/// Open connections per peer address, most first (then by address).
+pub fn open_peers(connections: &[PortConnection]) -> Vec<(IpAddr, usize)> {
+    let mut counts: HashMap<IpAddr, usize> = HashMap::new();
+    for ip in connections.iter().filter(|c| c.open).filter_map(|c| peer_address(&c.key)) {
+        *counts.entry(ip).or_default() += 1;
+    }
+    let mut peers: Vec<(IpAddr, usize)> = counts.into_iter().collect();
+    peers.sort_by(|a, b| b.1.cmp(&a.1).then(a.0.cmp(&b.0)));
+    peers
+}
+
+/// The first `rows` of `open_peers`, not looked up yet.
+pub fn top_peers(connections: &[PortConnection], rows: usize) -> Vec<Peer> {
+    open_peers(connections)
+        .into_iter()
+        .take(rows)
+        .map(|(address, connections)| Peer {
+            address,
+            connections,
+            bytes_per_sec: None,
+            hostname: None,
+            geo: GeoInfo::default(),
+        })
+        .collect()
+}
+
+/// The connections' byte counters from one reading to the next, summed
+/// per peer.
+#[derive(Debug, Default)]
+pub struct PeerTraffic {
+    /// Sent plus received so far, per connection.
+    totals: HashMap<String, u64>,
+    read_at: Option<Instant>,
+}
+
+impl PeerTraffic {
+    /// Bytes a second each peer moved since the last reading, `None` on the
+    /// first. A connection new since then moved all of its bytes in between;
+    /// one closed in between is missed.
+    pub fn update(&mut self, bytes: &[ConnectionBytes], now: Instant) -> Option<HashMap<IpAddr, f64>> {
+        let totals: HashMap<String, u64> = bytes.iter().map(|c| (c.key.clone(), c.sent + c.received)).collect();
+        let previous = std::mem::replace(&mut self.totals, totals);
+        let elapsed = now.duration_since(self.read_at.replace(now)?).as_secs_f64();
+        if elapsed <= 0.0 {
+            return None;
+        }
+        let mut rates: HashMap<IpAddr, f64> = HashMap::new();
+        for (key, total) in &self.totals {
+            let Some(ip) = peer_address(key) else {
+                continue;
+            };
+            let moved = total.saturating_sub(previous.get(key).copied().unwrap_or(0));
+            *rates.entry(ip).or_default() += moved as f64 / elapsed;
+        }
+        Some(rates)
+    }
+
+    /// Starts over, as when the counters weren't read for a while.
+    pub fn reset(&mut self) {
+        self.totals.clear();
+        self.read_at = None;
+    }
+}
+
+/// The first name in `getent hosts` (`8.8.8.8  dns.google`) or `host`
+/// (`8.8.8.8.in-addr.arpa domain name pointer dns.google.`) output.
+pub fn parse_hostname(output: &str) -> Option<String> {
+    let line = output.lines().next()?;
+    let name = match line.split_once(" domain name pointer ") {
+        Some((_, name)) => name,
+        None => line.split_whitespace().nth(1)?,
+    };
+    Some(name.trim().trim_end_matches('.').to_string()).filter(|n| !n.is_empty())
+}
+
+/// `ip`'s hostname from the system resolver, `/etc/hosts` included.
+fn reverse_lookup(ip: IpAddr) -> Option<String> {
+    let ip = ip.to_string();
+    let out = match Runner::Local.output("getent", &["hosts", &ip]) {
+        Ok(out) => out,
+        Err(_) => Runner::Local.output("host", &[&ip]).ok()?,
+    };
+    out.status.success().then(|| parse_hostname(&String::from_utf8_lossy(&out.stdout)))?
+}
+
+/// A reverse lookup's answer, `None` when the address has no name.
+type Answer = (IpAddr, Option<String>);
+
+/// Enriches peers with what `--resolve-peers` and `--geoip` ask for.
+pub struct PeerResolver {
+    lookups: Option<(SyncSender<IpAddr>, Receiver<Answer>)>,
+    geoip: Option<GeoIp>,
+    names: HashMap<IpAddr, (Instant, Option<String>)>,
+    /// Sent for a lookup, not answered yet.
+    pending: HashSet<IpAddr>,
+}
+
+impl PeerResolver {
+    /// Starts the lookup thread when `resolve` is on.
+    pub fn new(resolve: bool, geoip: Option<GeoIp>) -> Result<Self> {
+        let lookups = if resolve {
+            let (queue, addresses) = mpsc::sync_channel::<IpAddr>(QUEUE);
+            let (answer, answers) = mpsc::channel();
+            std::thread::Builder::new()
+                .name("crabtop-resolver".to_string())
+                .spawn(move || {
+                    for ip in addresses {
+                        if answer.send((ip, reverse_lookup(ip))).is_err() {
+                            break;
+                        }
+                        std::thread::sleep(LOOKUP_SPACING);
+                    }
+                })
+                .context("Failed to start the peer resolver")?;
+            Some((queue, answers))
+        } else {
+            None
+        };
+        Ok(Self {
+            lookups,
+            geoip,
+            names: HashMap::new(),
+            pending: HashSet::new(),
+        })
+    }
+
+    /// Fills in what's known of `peers`; addresses not looked up yet (or
+    /// too long ago) are queued for the thread.
+    pub fn enrich(&mut self, peers: &mut [Peer]) {
+        let now = Instant::now();
+        if let Some((queue, answers)) = &self.lookups {
+            for (ip, name) in answers.try_iter() {
+                self.pending.remove(&ip);
+                self.names.insert(ip, (now, name));
+            }
+            self.names.retain(|_, (at, _)| now.duration_since(*at) < NAME_TTL);
+            for peer in peers.iter() {
+                let ip = peer.address;
+                if !self.names.contains_key(&ip) && !self.pending.contains(&ip) && queue.try_send(ip).is_ok() {
+                    self.pending.insert(ip);
+                }
+            }
+        }
+        for peer in peers {
+            peer.hostname = self.names.get(&peer.address).and_then(|(_, name)| name.clone());
+            // A walk of the in-memory tree: cheap enough not to keep.
+            peer.geo = self.geoip.as_ref().map(|g| g.lookup(peer.address)).unwrap_or_default();
+        }
+    }
+}
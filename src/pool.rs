@@ -0,0 +1,113 @@
+//! Worker pools of prefork servers (gunicorn, uWSGI, Puma, php-fpm...): the
+//! process holding the port forks identical workers that do the real work,
+//! so its own figures are a fraction of what the service uses.
+
+use std::collections::BTreeMap;
+
+use chrono::{DateTime, Local};
+
+use crate::history::ProcessHistory;
+use crate::process::{aggregate, ProcessInfo};
+
+/// Fewest same-named children that count as a pool.
+pub const MIN_WORKERS: usize = 2;
+
+/// How many recent spawns and exits are kept.
+const EVENT_LEN: usize = 5;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum WorkerEvent {
+    Spawned,
+    Exited,
+}
+
+impl WorkerEvent {
+    pub fn label(&self) -> &'static str {
+        match self {
+            WorkerEvent::Spawned => "spawned",
+            WorkerEvent::Exited => "exited",
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct PoolEvent {
+    pub at: DateTime<Local>,
+    pub pid: u32,
+    pub kind: WorkerEvent,
+}
+
+/// A master and its workers, as followed from one sample to the next.
+#[derive(Clone)]
+pub struct WorkerPool {
+    /// Process name the workers share, e.g. `gunicorn`.
+    pub name: String,
+    /// Latest sample of each live worker, lowest PID first.
+    pub workers: Vec<ProcessInfo>,
+    /// Master and workers summed.
+    pub total: Option<ProcessInfo>,
+    pub totals: ProcessHistory,
+    pub spawned: u64,
+    pub exited: u64,
+    /// The latest spawns and exits, oldest first.
+    pub events: Vec<PoolEvent>,
+}
+
+impl WorkerPool {
+    pub fn new(name: impl Into<String>, history_len: usize) -> Self {
+        Self {
+            name: name.into(),
+            workers: Vec::new(),
+            total: None,
+            totals: ProcessHistory::new(history_len),
+            spawned: 0,
+            exited: 0,
+            events: Vec::new(),
+        }
+    }
+
+    /// Records this interval's worker samples (children that aren't named
+    /// like the pool are ignored). Workers that appeared or disappeared since
+    /// the last update are counted as spawned or exited.
+    pub fn update(&mut self, master: &ProcessInfo, children: Vec<ProcessInfo>, at: DateTime<Local>) {
+        let mut workers: Vec<ProcessInfo> = children.into_iter().filter(|c| c.name == self.name).collect();
+        workers.sort_by_key(|w| w.pid);
+        if self.total.is_some() {
+            let was = |pid: u32| self.workers.iter().any(|w| w.pid == pid);
+            let is = |pid: u32| workers.iter().any(|w| w.pid == pid);
+            let mut events: Vec<PoolEvent> = self
+                .workers
+                .iter()
+                .filter(|w| !is(w.pid))
+                .map(|w| (w.pid, WorkerEvent::Exited))
+                .chain(workers.iter().filter(|w| !was(w.pid)).map(|w| (w.pid, WorkerEvent::Spawned)))
+                .map(|(pid, kind)| PoolEvent { at, pid, kind })
+                .collect();
+            self.exited += events.iter().filter(|e| e.kind == WorkerEvent::Exited).count() as u64;
+            self.spawned += events.iter().filter(|e| e.kind == WorkerEvent::Spawned).count() as u64;
+            self.events.append(&mut events);
+            let excess = self.events.len().saturating_sub(EVENT_LEN);
+            self.events.drain(..excess);
+        }
+
+        let mut all = vec![master.clone()];
+        all.extend(workers.iter().cloned());
+        let total = aggregate(&all).expect("master is always there");
+        self.totals.add(&total);
+        self.total = Some(total);
+        self.workers = workers;
+    }
+}
+
+/// The biggest set of at least `MIN_WORKERS` children sharing a name, if
+/// any: that name and those children. Ties go to the name sorting first.
+pub fn worker_group(children: Vec<ProcessInfo>) -> Option<(String, Vec<ProcessInfo>)> {
+    let mut by_name: BTreeMap<String, Vec<ProcessInfo>> = BTreeMap::new();
+    for child in children {
+        by_name.entry(child.name.clone()).or_default().push(child);
+    }
+    by_name
+        .into_iter()
+        .filter(|(_, group)| group.len() >= MIN_WORKERS)
+        .reduce(|best, next| if next.1.len() > best.1.len() { next } else { best })
+}
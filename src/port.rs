@@ -0,0 +1,767 @@
+use std::collections::HashMap;
+use std::fmt;
+use std::io;
+
+use anyhow::{anyhow, Context, Result};
+use serde::{Deserialize, Serialize};
+use tracing::debug;
+
+use crate::error::Error;
+use crate::exec::Runner;
+
+/// A listening TCP socket, e.g. `0.0.0.0:8080` or `[::1]:9090`.
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+pub struct Listener {
+    pub address: String,
+    pub port: u16,
+}
+
+impl fmt::Display for Listener {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}:{}", self.address, self.port)
+    }
+}
+
+// Splits "addr:port" as printed by lsof/ss, keeping IPv6 brackets intact.
+pub(crate) fn parse_listener(addr: &str) -> Option<Listener> {
+    let (address, port) = addr.rsplit_once(':')?;
+    Some(Listener {
+        address: address.to_string(),
+        port: port.parse().ok()?,
+    })
+}
+
+/// Whether `ss -lntp` lists a socket on `port` without its `users:(...)`,
+/// which is what it prints for processes we may not inspect.
+pub fn ss_hides_owner(ss: &str, port: u16) -> bool {
+    ss.lines()
+        .filter(|line| line.split_whitespace().nth(3).and_then(parse_listener).is_some_and(|l| l.port == port))
+        .any(|line| !line.contains("pid="))
+}
+
+/// Whether `netstat -anp tcp` (macOS) lists a listener on `port`, printed
+/// as `*.8080` or `127.0.0.1.8080`.
+pub fn macos_netstat_lists(netstat: &str, port: u16) -> bool {
+    let suffix = format!(".{}", port);
+    netstat.lines().any(|line| {
+        let cols: Vec<&str> = line.split_whitespace().collect();
+        cols.len() >= 6 && cols[5] == "LISTEN" && cols[3].ends_with(&suffix)
+    })
+}
+
+// Tries to resolve the PID listening on the given port using lsof first,
+// then `ss`/`netstat` on Linux or `netstat -ano` on Windows. When several processes share the
+// port (SO_REUSEPORT, pre-fork servers) this is the lowest PID; use
+// `find_pids_by_port` to see all of them.
+pub fn find_pid_by_port(port: u16) -> Result<u32> {
+    find_pids_by_port(port).map(|pids| pids[0])
+}
+
+// Resolves every PID listening on the given port, sorted and de-duplicated.
+// Never returns an empty list: "nobody is listening" is an `Error::PortNotFound`,
+// a listener we may not see `Error::PermissionDenied`, and no lookup tool to
+// run `Error::ToolMissing`.
+pub fn find_pids_by_port(port: u16) -> Result<Vec<u32>> {
+    find_pids_by_port_on(&Runner::Local, port)
+}
+
+// Same as `find_pids_by_port`, running the lookup tools through `runner`.
+// Remote hosts are assumed to be Linux, so the ss/netstat fallbacks apply.
+pub fn find_pids_by_port_on(runner: &Runner, port: u16) -> Result<Vec<u32>> {
+    // Prefer lsof (works well on macOS and most Linux distros)
+    // lsof flags:
+    // -n: no DNS
+    // -P: no port service name translation
+    // -iTCP:<port>: filter TCP for specific port
+    // -sTCP:LISTEN: only listening sockets
+    // -t: terse output (just PIDs, one per line)
+    let lsof_args = [
+        "-n",
+        "-P",
+        &format!("-iTCP:{}", port),
+        "-sTCP:LISTEN",
+        "-t",
+    ];
+
+    let lsof_out = runner.output("lsof", &lsof_args);
+    // A missing lsof shows up as the helper failing, so this is the helper itself.
+    if let (Runner::Elevated(elevation), Err(e)) = (runner, &lsof_out) {
+        if e.kind() == io::ErrorKind::NotFound {
+            return Err(Error::ToolMissing { tool: elevation.program().to_string() }.into());
+        }
+        return Err(anyhow!("Couldn't run `{}` for --sudo ({}); is it installed?", elevation.program(), e));
+    }
+    // Windows has no lsof to miss; its lookup is netstat's alone.
+    #[cfg_attr(not(any(target_os = "linux", target_os = "macos")), allow(unused_variables))]
+    let lsof_missing = lsof_out.as_ref().is_err_and(|e| e.kind() == io::ErrorKind::NotFound);
+
+    match lsof_out {
+        Ok(out) if out.status.success() => {
+            let stdout = String::from_utf8_lossy(&out.stdout);
+            let mut pids = Vec::new();
+            for line in stdout.lines().filter(|l| !l.trim().is_empty()) {
+                let pid: u32 = line.trim().parse().context("Failed to parse PID from lsof")?;
+                pids.push(pid);
+            }
+            if !pids.is_empty() {
+                debug!("lsof: port {} is served by {:?}", port, pids);
+                return Ok(sorted_unique(pids));
+            }
+            debug!("lsof: no listener on port {}", port);
+        }
+        // lsof exits 1 both when nothing matched and when it couldn't look.
+        Ok(out) => debug!("lsof: nothing for port {} ({})", port, out.status),
+        Err(err) => debug!("lsof: couldn't run it{}: {}", runner.describe(), err),
+    }
+
+    if runner.is_remote() {
+        return find_pids_fallback(runner, port, lsof_missing);
+    }
+
+    #[cfg(target_os = "linux")]
+    {
+        find_pids_fallback(runner, port, lsof_missing)
+    }
+
+    #[cfg(target_os = "macos")]
+    {
+        // lsof shows nothing for other users' sockets; netstat lists them, ownerless.
+        let listed = runner
+            .output("netstat", &["-anp", "tcp"])
+            .is_ok_and(|out| macos_netstat_lists(&String::from_utf8_lossy(&out.stdout), port));
+        if listed {
+            return Err(Error::PermissionDenied { port: Some(port), pid: None }.into());
+        }
+        // On macOS, lsof is the practical way.
+        if lsof_missing {
+            return Err(Error::ToolMissing { tool: "lsof".to_string() }.into());
+        }
+        Err(Error::PortNotFound { port }.into())
+    }
+
+    #[cfg(target_os = "windows")]
+    {
+        let pids: Vec<u32> = windows_listening_sockets(runner)
+            .into_iter()
+            .filter(|s| s.listener.port == port)
+            .map(|s| s.pid)
+            .collect();
+        if pids.is_empty() {
+            return Err(Error::PortNotFound { port }.into());
+        }
+        Ok(sorted_unique(pids))
+    }
+
+    // Other platforms
+    #[cfg(not(any(target_os = "linux", target_os = "macos", target_os = "windows")))]
+    {
+        Err(anyhow!("Unsupported OS for port inspection."))
+    }
+}
+
+// Linux fallbacks for when lsof is missing or came up empty: `ss -lntp`, then `netstat -lntp`.
+#[cfg_attr(not(target_os = "linux"), allow(dead_code))]
+fn find_pids_fallback(runner: &Runner, port: u16, lsof_missing: bool) -> Result<Vec<u32>> {
+    debug!("falling back to ss and netstat for port {}", port);
+    let ss_out = runner.output("ss", &["-lntp"]); // listening, numeric, tcp, show process
+    let mut hidden = false;
+
+    match &ss_out {
+        Ok(out) if !out.status.success() => debug!("ss: failed ({})", out.status),
+        Err(err) => debug!("ss: couldn't run it{}: {}", runner.describe(), err),
+        Ok(_) => {}
+    }
+    if let Ok(out) = &ss_out {
+        if out.status.success() {
+            let stdout = String::from_utf8_lossy(&out.stdout);
+            hidden = ss_hides_owner(&stdout, port);
+            let mut pids = Vec::new();
+            // Example line (one socket can be shared by several processes):
+            // LISTEN 0 128 0.0.0.0:80 ... users:(("nginx",pid=1234,fd=7),("nginx",pid=1235,fd=7))
+            for line in stdout.lines() {
+                if line.contains(&format!(":{} ", port)) || line.ends_with(&format!(":{}", port)) {
+                    for pid_str in line.split("pid=").skip(1) {
+                        let pid_part = pid_str.split(|c: char| !c.is_ascii_digit()).next().unwrap_or("");
+                        if !pid_part.is_empty() {
+                            let pid: u32 = pid_part.parse().context("Failed to parse PID from ss output")?;
+                            pids.push(pid);
+                        }
+                    }
+                }
+            }
+            if !pids.is_empty() {
+                debug!("ss: port {} is served by {:?}", port, pids);
+                return Ok(sorted_unique(pids));
+            }
+            debug!("ss: no listener with a visible owner on port {}", port);
+        }
+    }
+
+    // Try netstat as a last resort (may require `net-tools`)
+    let netstat_out = runner.output("netstat", &["-lntp"]);
+    match &netstat_out {
+        Ok(out) if !out.status.success() => debug!("netstat: failed ({})", out.status),
+        Err(err) => debug!("netstat: couldn't run it{}: {}", runner.describe(), err),
+        Ok(_) => {}
+    }
+    if let Ok(out) = &netstat_out {
+        if out.status.success() {
+            let stdout = String::from_utf8_lossy(&out.stdout);
+            let mut pids = Vec::new();
+            // Typical line: tcp 0 0 0.0.0.0:<port> 0.0.0.0:* LISTEN 1234/program
+            let suffix = format!(":{}", port);
+            for line in stdout.lines() {
+                if line.split_whitespace().nth(3).is_some_and(|local| local.ends_with(&suffix)) {
+                    // Extract pid from the last column like "1234/program"
+                    // ("-" when the process isn't ours to see)
+                    if let Some(last_col) = line.split_whitespace().last() {
+                        hidden |= last_col == "-";
+                        if let Some(pid_part) = last_col.split('/').next() {
+                            if let Ok(pid) = pid_part.parse::<u32>() {
+                                pids.push(pid);
+                            }
+                        }
+                    }
+                }
+            }
+            if !pids.is_empty() {
+                debug!("netstat: port {} is served by {:?}", port, pids);
+                return Ok(sorted_unique(pids));
+            }
+            debug!("netstat: no listener with a visible owner on port {}", port);
+        }
+    }
+
+    if hidden {
+        debug!("port {} has a listener whose owner we aren't allowed to see", port);
+        return Err(Error::PermissionDenied { port: Some(port), pid: None }.into());
+    }
+    let missing = |out: &io::Result<std::process::Output>| out.as_ref().is_err_and(|e| e.kind() == io::ErrorKind::NotFound);
+    if lsof_missing && missing(&ss_out) && missing(&netstat_out) {
+        return Err(Error::ToolMissing { tool: "lsof".to_string() }.into());
+    }
+    Err(Error::PortNotFound { port }.into())
+}
+
+fn sorted_unique(mut pids: Vec<u32>) -> Vec<u32> {
+    pids.sort_unstable();
+    pids.dedup();
+    pids
+}
+
+// Lists every TCP socket `pid` is listening on, sorted by port. Uses lsof,
+// falling back to `ss -lntp` on Linux.
+pub fn listening_ports(pid: u32) -> Result<Vec<Listener>> {
+    listening_ports_on(&Runner::Local, pid)
+}
+
+pub fn listening_ports_on(runner: &Runner, pid: u32) -> Result<Vec<Listener>> {
+    // -a ANDs the selectors, so this is "sockets of <pid> that are TCP LISTEN";
+    // -Fn prints one `n<addr>:<port>` line per socket.
+    let pid_arg = pid.to_string();
+    let lsof_out = runner.output("lsof", &["-n", "-P", "-a", "-p", &pid_arg, "-iTCP", "-sTCP:LISTEN", "-Fn"]);
+
+    let mut listeners: Vec<Listener> = Vec::new();
+
+    if let Ok(out) = lsof_out {
+        if out.status.success() {
+            let stdout = String::from_utf8_lossy(&out.stdout);
+            listeners = stdout
+                .lines()
+                .filter_map(|l| l.strip_prefix('n'))
+                .filter_map(parse_listener)
+                .collect();
+        }
+    }
+
+    if listeners.is_empty() && (runner.is_remote() || cfg!(target_os = "linux")) {
+        let out = runner
+            .output("ss", &["-lntpH"])
+            .context("Failed to run `ss -lntpH`")?;
+        let stdout = String::from_utf8_lossy(&out.stdout);
+        let needle = format!("pid={},", pid);
+        // LISTEN 0 128 0.0.0.0:80 0.0.0.0:* users:(("nginx",pid=1234,fd=7))
+        listeners = stdout
+            .lines()
+            .filter(|l| l.contains(&needle))
+            .filter_map(|l| l.split_whitespace().nth(3))
+            .filter_map(parse_listener)
+            .collect();
+    }
+
+    if listeners.is_empty() && cfg!(target_os = "windows") {
+        listeners = windows_listening_sockets(runner)
+            .into_iter()
+            .filter(|s| s.pid == pid)
+            .map(|s| s.listener)
+            .collect();
+    }
+
+    listeners.sort_by(|a, b| a.port.cmp(&b.port).then_with(|| a.address.cmp(&b.address)));
+    listeners.dedup();
+    Ok(listeners)
+}
+
+/// A listening TCP socket together with the process that owns it.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ListeningSocket {
+    pub pid: u32,
+    pub listener: Listener,
+}
+
+// Every listening TCP socket on the machine, sorted by port then PID. Uses
+// lsof, falling back to `ss -lntp` on Linux (or when `runner` is remote).
+pub fn listening_sockets() -> Result<Vec<ListeningSocket>> {
+    listening_sockets_on(&Runner::Local)
+}
+
+pub fn listening_sockets_on(runner: &Runner) -> Result<Vec<ListeningSocket>> {
+    // -Fpn prints a `p<pid>` line followed by one `n<addr>:<port>` line per socket.
+    let lsof_out = runner.output("lsof", &["-n", "-P", "-iTCP", "-sTCP:LISTEN", "-Fpn"]);
+
+    let mut sockets: Vec<ListeningSocket> = Vec::new();
+
+    if let Ok(out) = lsof_out {
+        if out.status.success() {
+            let stdout = String::from_utf8_lossy(&out.stdout);
+            let mut pid = None;
+            for line in stdout.lines() {
+                if let Some(p) = line.strip_prefix('p') {
+                    pid = p.parse().ok();
+                } else if let (Some(pid), Some(listener)) = (pid, line.strip_prefix('n').and_then(parse_listener)) {
+                    sockets.push(ListeningSocket { pid, listener });
+                }
+            }
+        }
+    }
+
+    if sockets.is_empty() && (runner.is_remote() || cfg!(target_os = "linux")) {
+        let out = runner
+            .output("ss", &["-lntpH"])
+            .context("Failed to run `ss -lntpH`")?;
+        let stdout = String::from_utf8_lossy(&out.stdout);
+        for line in stdout.lines() {
+            let Some(listener) = line.split_whitespace().nth(3).and_then(parse_listener) else {
+                continue;
+            };
+            for pid_str in line.split("pid=").skip(1) {
+                let pid_part = pid_str.split(|c: char| !c.is_ascii_digit()).next().unwrap_or("");
+                if let Ok(pid) = pid_part.parse() {
+                    sockets.push(ListeningSocket {
+                        pid,
+                        listener: listener.clone(),
+                    });
+                }
+            }
+        }
+    }
+
+    if sockets.is_empty() && cfg!(target_os = "windows") {
+        sockets = windows_listening_sockets(runner);
+    }
+
+    sockets.sort_by(|a, b| {
+        a.listener
+            .port
+            .cmp(&b.listener.port)
+            .then_with(|| a.pid.cmp(&b.pid))
+            .then_with(|| a.listener.address.cmp(&b.listener.address))
+    });
+    sockets.dedup();
+    Ok(sockets)
+}
+
+// Windows has no lsof or ss, but `netstat -ano` lists every socket with its PID.
+fn windows_listening_sockets(runner: &Runner) -> Vec<ListeningSocket> {
+    runner
+        .output("netstat", &["-ano"])
+        .map(|out| parse_windows_netstat(&String::from_utf8_lossy(&out.stdout)))
+        .unwrap_or_default()
+}
+
+/// The listening sockets in `netstat -ano` output as Windows prints it:
+/// `TCP    0.0.0.0:8080    0.0.0.0:0    LISTENING    4242`. The state column
+/// is translated on non-English systems, so listeners are told apart by
+/// their foreign address, which is port 0 only for them.
+pub fn parse_windows_netstat(output: &str) -> Vec<ListeningSocket> {
+    output
+        .lines()
+        .filter_map(|line| {
+            let fields: Vec<&str> = line.split_whitespace().collect();
+            let [proto, local, foreign, .., pid] = fields.as_slice() else {
+                return None;
+            };
+            if !proto.eq_ignore_ascii_case("tcp") || !foreign.ends_with(":0") {
+                return None;
+            }
+            Some(ListeningSocket {
+                pid: pid.parse().ok()?,
+                listener: parse_listener(local)?,
+            })
+        })
+        .collect()
+}
+
+/// Counts of TCP connections on a local port, by state.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ConnectionSummary {
+    pub established: usize,
+    pub time_wait: usize,
+    pub close_wait: usize,
+    /// SYN-RECV, FIN-WAIT and the other short-lived states.
+    pub other: usize,
+    /// Distinct remote addresses among established connections.
+    pub peers: usize,
+}
+
+impl fmt::Display for ConnectionSummary {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} established from {} peers, {} time-wait, {} close-wait, {} other",
+            self.established, self.peers, self.time_wait, self.close_wait, self.other
+        )
+    }
+}
+
+// Summarises the connections accepted on `port` (listening sockets excluded).
+pub fn connection_summary(port: u16) -> Result<ConnectionSummary> {
+    connection_summary_on(&Runner::Local, port)
+}
+
+// Prefers `ss -tanH` on Linux: TIME_WAIT sockets belong to no process, so lsof
+// never lists them. Elsewhere lsof's `-FnT` output is the only option.
+pub fn connection_summary_on(runner: &Runner, port: u16) -> Result<ConnectionSummary> {
+    let mut states: Vec<(String, String)> = Vec::new(); // (state, peer)
+
+    if runner.is_remote() || cfg!(target_os = "linux") {
+        if let Ok(out) = runner.output("ss", &["-tanH"]) {
+            if out.status.success() {
+                // ESTAB 0 0 127.0.0.1:8080 127.0.0.1:51234
+                let stdout = String::from_utf8_lossy(&out.stdout);
+                for line in stdout.lines() {
+                    let cols: Vec<&str> = line.split_whitespace().collect();
+                    if cols.len() < 5 || cols[0] == "LISTEN" {
+                        continue;
+                    }
+                    if parse_listener(cols[3]).is_some_and(|l| l.port == port) {
+                        states.push((cols[0].to_string(), peer_address(cols[4])));
+                    }
+                }
+                return Ok(summarize(&states));
+            }
+        }
+    }
+
+    // -a ANDs the selectors; -FnT prints `n<local>-><peer>` then `TST=<state>`.
+    let out = runner
+        .output("lsof", &["-n", "-P", "-a", &format!("-iTCP:{}", port), "-FnT"])
+        .context("Failed to run `lsof` for connection counts")?;
+    let stdout = String::from_utf8_lossy(&out.stdout);
+    let mut current: Option<String> = None;
+    for line in stdout.lines() {
+        if let Some(name) = line.strip_prefix('n') {
+            // Only the server side: the local half of "local->peer" is on `port`.
+            current = name
+                .split_once("->")
+                .filter(|(local, _)| parse_listener(local).is_some_and(|l| l.port == port))
+                .map(|(_, peer)| peer.to_string());
+        } else if let Some(state) = line.strip_prefix("TST=") {
+            if let Some(peer) = current.take() {
+                states.push((state.to_string(), peer_address(&peer)));
+            }
+        }
+    }
+    Ok(summarize(&states))
+}
+
+/// Established connections per local port, from one `ss -tanH` (Linux, or
+/// `runner` remote); `None` where ss isn't available.
+pub fn connection_counts_on(runner: &Runner) -> Option<HashMap<u16, usize>> {
+    if !runner.is_remote() && !cfg!(target_os = "linux") {
+        return None;
+    }
+    let out = runner.output("ss", &["-tanH"]).ok()?;
+    out.status.success().then(|| parse_connection_counts(&String::from_utf8_lossy(&out.stdout)))
+}
+
+/// Counts the `ESTAB` lines of `ss -tanH` by local port.
+pub fn parse_connection_counts(ss: &str) -> HashMap<u16, usize> {
+    let mut counts = HashMap::new();
+    for line in ss.lines() {
+        let cols: Vec<&str> = line.split_whitespace().collect();
+        if cols.len() >= 5 && cols[0] == "ESTAB" {
+            if let Some(local) = parse_listener(cols[3]) {
+                *counts.entry(local.port).or_insert(0) += 1;
+            }
+        }
+    }
+    counts
+}
+
+/// One TCP connection on a port, as listed by `ss -tanH`.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct PortConnection {
+    /// `local->peer`, which identifies the connection while it exists.
+    pub key: String,
+    /// Still open (SYN-RECV or ESTAB) rather than closing or in TIME-WAIT.
+    pub open: bool,
+}
+
+pub fn port_connections(port: u16) -> Option<Vec<PortConnection>> {
+    port_connections_on(&Runner::Local, port)
+}
+
+pub fn port_connections_on(runner: &Runner, port: u16) -> Option<Vec<PortConnection>> {
+    if !runner.is_remote() && !cfg!(target_os = "linux") {
+        return None;
+    }
+    let out = runner.output("ss", &["-tanH"]).ok()?;
+    out.status.success().then(|| parse_port_connections(&String::from_utf8_lossy(&out.stdout), port))
+}
+
+/// The connections whose local end is `port`, in any state but LISTEN.
+pub fn parse_port_connections(ss: &str, port: u16) -> Vec<PortConnection> {
+    ss.lines()
+        .filter_map(|line| {
+            let cols: Vec<&str> = line.split_whitespace().collect();
+            if cols.len() < 5 || cols[0] == "LISTEN" || parse_listener(cols[3]).is_none_or(|l| l.port != port) {
+                return None;
+            }
+            Some(PortConnection {
+                key: format!("{}->{}", cols[3], cols[4]),
+                open: matches!(cols[0], "ESTAB" | "SYN-RECV"),
+            })
+        })
+        .collect()
+}
+
+/// Bytes moved so far by one established connection on a port, from its `tcp_info`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ConnectionBytes {
+    /// `local->peer`, as in `PortConnection`.
+    pub key: String,
+    pub sent: u64,
+    pub received: u64,
+}
+
+pub fn connection_bytes(port: u16) -> Option<Vec<ConnectionBytes>> {
+    connection_bytes_on(&Runner::Local, port)
+}
+
+pub fn connection_bytes_on(runner: &Runner, port: u16) -> Option<Vec<ConnectionBytes>> {
+    if !runner.is_remote() && !cfg!(target_os = "linux") {
+        return None;
+    }
+    let sport = format!(":{}", port);
+    let out = runner
+        .output("ss", &["-tinH", "state", "established", "sport", "=", &sport])
+        .ok()
+        .filter(|out| out.status.success())?;
+    Some(parse_connection_bytes(&String::from_utf8_lossy(&out.stdout), port))
+}
+
+/// `bytes_sent` (or `bytes_acked`, on kernels without it) and
+/// `bytes_received` of each connection in `ss -tinH state established`
+/// output; connections without them are skipped.
+pub fn parse_connection_bytes(ss: &str, port: u16) -> Vec<ConnectionBytes> {
+    let mut found = Vec::new();
+    let mut key = None;
+    for line in ss.lines() {
+        if !line.starts_with(char::is_whitespace) {
+            let cols: Vec<&str> = line.split_whitespace().collect();
+            key = (cols.len() >= 4 && parse_listener(cols[2]).is_some_and(|l| l.port == port))
+                .then(|| format!("{}->{}", cols[2], cols[3]));
+            continue;
+        }
+        let Some(key) = key.take() else {
+            continue;
+        };
+        let field = |name: &str| line.split_whitespace().find_map(|f| f.strip_prefix(name)?.parse::<u64>().ok());
+        let sent = field("bytes_sent:").or_else(|| field("bytes_acked:"));
+        if let (Some(sent), Some(received)) = (sent, field("bytes_received:")) {
+            found.push(ConnectionBytes { key, sent, received });
+        }
+    }
+    found
+}
+
+/// Accept-queue state of the sockets listening on a port (Linux only).
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct AcceptQueue {
+    /// Connections the kernel has completed but the process hasn't `accept()`ed.
+    pub queued: u32,
+    /// The `listen()` backlog, i.e. how many may queue before SYNs get dropped.
+    pub backlog: u32,
+    /// Host-wide `TcpExt: ListenOverflows`, a running count of dropped connections.
+    pub listen_overflows: Option<u64>,
+}
+
+impl AcceptQueue {
+    pub fn fill_ratio(&self) -> f64 {
+        self.queued as f64 / (self.backlog as f64).max(1.0)
+    }
+}
+
+pub fn accept_queue(port: u16) -> Option<AcceptQueue> {
+    accept_queue_on(&Runner::Local, port)
+}
+
+// For a LISTEN socket, ss reports the accept queue as Recv-Q and the backlog
+// as Send-Q. Several sockets on the port (addresses, SO_REUSEPORT) are summed.
+pub fn accept_queue_on(runner: &Runner, port: u16) -> Option<AcceptQueue> {
+    if !runner.is_remote() && !cfg!(target_os = "linux") {
+        return None;
+    }
+    let out = runner.output("ss", &["-ltnH"]).ok().filter(|out| out.status.success())?;
+    let mut queue = parse_accept_queue(&String::from_utf8_lossy(&out.stdout), port)?;
+    let netstat = match runner {
+        Runner::Local | Runner::Elevated(_) => std::fs::read_to_string("/proc/net/netstat").ok(),
+        Runner::Ssh(_) => runner
+            .output("cat", &["/proc/net/netstat"])
+            .ok()
+            .map(|out| String::from_utf8_lossy(&out.stdout).into_owned()),
+    };
+    queue.listen_overflows = netstat.as_deref().and_then(parse_listen_overflows);
+    Some(queue)
+}
+
+/// Sums `ss -ltnH` rows (`LISTEN 0 4096 0.0.0.0:8080 0.0.0.0:*`) for `port`.
+pub fn parse_accept_queue(ss: &str, port: u16) -> Option<AcceptQueue> {
+    let mut found = None;
+    for line in ss.lines() {
+        let cols: Vec<&str> = line.split_whitespace().collect();
+        if cols.len() < 4 || cols[0] != "LISTEN" || parse_listener(cols[3]).is_none_or(|l| l.port != port) {
+            continue;
+        }
+        let (Ok(queued), Ok(backlog)) = (cols[1].parse::<u32>(), cols[2].parse::<u32>()) else {
+            continue;
+        };
+        let queue: &mut AcceptQueue = found.get_or_insert_with(AcceptQueue::default);
+        queue.queued += queued;
+        queue.backlog += backlog;
+    }
+    found
+}
+
+/// `ListenOverflows` from the `TcpExt:` header/value line pair of /proc/net/netstat.
+pub fn parse_listen_overflows(netstat: &str) -> Option<u64> {
+    let mut lines = netstat.lines();
+    while let Some(header) = lines.next() {
+        let Some(names) = header.strip_prefix("TcpExt:") else {
+            continue;
+        };
+        let values = lines.next()?.strip_prefix("TcpExt:")?;
+        return names
+            .split_whitespace()
+            .zip(values.split_whitespace())
+            .find(|(name, _)| *name == "ListenOverflows")
+            .and_then(|(_, value)| value.parse().ok());
+    }
+    None
+}
+
+/// Retransmit percentage above which the TCP panel raises an alert.
+pub const DEFAULT_RETRANSMIT_ALERT: f64 = 2.0;
+
+/// Kernel `tcp_info` summed over the established connections on a port (Linux only).
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+pub struct TcpStats {
+    pub connections: usize,
+    /// Mean and worst smoothed RTT, in milliseconds.
+    pub rtt_avg_ms: Option<f64>,
+    pub rtt_max_ms: Option<f64>,
+    /// Mean congestion window, in segments.
+    pub cwnd_avg: Option<f64>,
+    /// Segments retransmitted over the lifetime of the open connections.
+    pub retransmits: u64,
+    pub segments_out: u64,
+}
+
+impl TcpStats {
+    /// Retransmitted share of sent segments, as a percentage.
+    pub fn retransmit_percent(&self) -> Option<f64> {
+        (self.segments_out > 0).then(|| self.retransmits as f64 / self.segments_out as f64 * 100.0)
+    }
+}
+
+pub fn tcp_stats(port: u16) -> Option<TcpStats> {
+    tcp_stats_on(&Runner::Local, port)
+}
+
+pub fn tcp_stats_on(runner: &Runner, port: u16) -> Option<TcpStats> {
+    if !runner.is_remote() && !cfg!(target_os = "linux") {
+        return None;
+    }
+    let sport = format!(":{}", port);
+    let out = runner
+        .output("ss", &["-tinH", "state", "established", "sport", "=", &sport])
+        .ok()
+        .filter(|out| out.status.success())?;
+    Some(parse_tcp_info(&String::from_utf8_lossy(&out.stdout), port))
+}
+
+/// Parses `ss -tinH state established` output: a socket line
+/// (`0 0 127.0.0.1:8080 127.0.0.1:51234`) followed by an indented line of
+/// `key:value` fields (`rtt:0.05/0.02 cwnd:10 retrans:0/3 segs_out:120`).
+pub fn parse_tcp_info(ss: &str, port: u16) -> TcpStats {
+    let mut stats = TcpStats::default();
+    let (mut rtts, mut cwnds) = (Vec::new(), Vec::new());
+    let mut on_port = false;
+    for line in ss.lines() {
+        if !line.starts_with(char::is_whitespace) {
+            let cols: Vec<&str> = line.split_whitespace().collect();
+            on_port = cols.len() >= 4 && parse_listener(cols[2]).is_some_and(|l| l.port == port);
+            if on_port {
+                stats.connections += 1;
+            }
+            continue;
+        }
+        if !on_port {
+            continue;
+        }
+        for field in line.split_whitespace() {
+            let Some((key, value)) = field.split_once(':') else {
+                continue;
+            };
+            match key {
+                "rtt" => rtts.extend(value.split('/').next().and_then(|v| v.parse::<f64>().ok())),
+                "cwnd" => cwnds.extend(value.parse::<f64>().ok()),
+                // current/total; the total is what has been resent so far.
+                "retrans" => stats.retransmits += value.rsplit('/').next().and_then(|v| v.parse::<u64>().ok()).unwrap_or(0),
+                "segs_out" => stats.segments_out += value.parse::<u64>().unwrap_or(0),
+                _ => {}
+            }
+        }
+    }
+    let mean = |v: &[f64]| (!v.is_empty()).then(|| v.iter().sum::<f64>() / v.len() as f64);
+    stats.rtt_avg_ms = mean(&rtts);
+    stats.rtt_max_ms = rtts.iter().copied().reduce(f64::max);
+    stats.cwnd_avg = mean(&cwnds);
+    stats
+}
+
+fn peer_address(peer: &str) -> String {
+    parse_listener(peer).map(|l| l.address).unwrap_or_else(|| peer.to_string())
+}
+
+fn summarize(states: &[(String, String)]) -> ConnectionSummary {
+    let mut summary = ConnectionSummary::default();
+    let mut peers: Vec<&str> = Vec::new();
+    for (state, peer) in states {
+        // ss prints ESTAB/TIME-WAIT, lsof ESTABLISHED/TIME_WAIT.
+        match state.replace('_', "-").as_str() {
+            "ESTAB" | "ESTABLISHED" => {
+                summary.established += 1;
+                peers.push(peer);
+            }
+            "TIME-WAIT" => summary.time_wait += 1,
+            "CLOSE-WAIT" => summary.close_wait += 1,
+            _ => summary.other += 1,
+        }
+    }
+    peers.sort_unstable();
+    peers.dedup();
+    summary.peers = peers.len();
+    summary
+}
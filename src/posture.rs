@@ -0,0 +1,232 @@
+//! A quick exposure check of the process behind the port: whether it
+//! listens on every interface or only loopback, runs as root, holds
+//! capabilities, runs a binary anyone may overwrite, and is confined by
+//! SELinux or AppArmor. Everything comes from `/proc/<pid>` (Linux, or a
+//! host over ssh); another user's binary and LSM label may need `--sudo`.
+
+use serde::{Deserialize, Serialize};
+
+use crate::exec::Runner;
+
+/// `CAP_*` by bit number, as in `linux/capability.h`.
+const CAPABILITIES: [&str; 41] = [
+    "chown",
+    "dac_override",
+    "dac_read_search",
+    "fowner",
+    "fsetid",
+    "kill",
+    "setgid",
+    "setuid",
+    "setpcap",
+    "linux_immutable",
+    "net_bind_service",
+    "net_broadcast",
+    "net_admin",
+    "net_raw",
+    "ipc_lock",
+    "ipc_owner",
+    "sys_module",
+    "sys_rawio",
+    "sys_chroot",
+    "sys_ptrace",
+    "sys_pacct",
+    "sys_admin",
+    "sys_boot",
+    "sys_nice",
+    "sys_resource",
+    "sys_time",
+    "sys_tty_config",
+    "mknod",
+    "lease",
+    "audit_write",
+    "audit_control",
+    "setfcap",
+    "mac_override",
+    "mac_admin",
+    "syslog",
+    "wake_alarm",
+    "block_suspend",
+    "audit_read",
+    "perfmon",
+    "bpf",
+    "checkpoint_restore",
+];
+
+/// How far a listening address reaches.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Exposure {
+    /// `127.0.0.1`, `[::1]`: this host only.
+    Loopback,
+    /// One address of the host, e.g. a private interface's.
+    Interface,
+    /// `0.0.0.0`, `[::]`, `*`: every interface, the internet too if routed.
+    Everywhere,
+}
+
+impl Exposure {
+    pub fn of(address: &str) -> Self {
+        let address = address.trim_start_matches('[').trim_end_matches(']');
+        match address {
+            "0.0.0.0" | "::" | "*" => Exposure::Everywhere,
+            "localhost" => Exposure::Loopback,
+            _ if address.starts_with("127.") || address == "::1" || address == "::ffff:127.0.0.1" => Exposure::Loopback,
+            _ => Exposure::Interface,
+        }
+    }
+
+    pub fn label(self) -> &'static str {
+        match self {
+            Exposure::Loopback => "loopback only",
+            Exposure::Interface => "one interface",
+            Exposure::Everywhere => "all interfaces",
+        }
+    }
+}
+
+/// The Linux security module label the process runs under.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Confinement {
+    /// No label could be read: neither module is enabled, or the process
+    /// belongs to another user.
+    Unknown,
+    /// A profile and its mode, `nginx (enforce)`, or `unconfined`.
+    AppArmor(String),
+    /// A context, `system_u:system_r:httpd_t:s0`.
+    SeLinux(String),
+}
+
+impl Confinement {
+    /// `/proc/<pid>/attr/apparmor/current` (newer kernels, AppArmor only)
+    /// and `/proc/<pid>/attr/current` (whichever module is enabled).
+    pub fn parse(apparmor: Option<&str>, current: Option<&str>) -> Self {
+        let clean = |text: Option<&str>| text.map(|t| t.trim_matches(|c: char| c == '\0' || c.is_whitespace()).to_string()).filter(|t| !t.is_empty());
+        if let Some(profile) = clean(apparmor) {
+            return Confinement::AppArmor(profile);
+        }
+        match clean(current) {
+            // user:role:type:level
+            Some(label) if label.split(':').count() >= 4 => Confinement::SeLinux(label),
+            Some(label) if label == "unconfined" || label.ends_with(')') => Confinement::AppArmor(label),
+            // What another module, or none, leaves there (`kernel`).
+            _ => Confinement::Unknown,
+        }
+    }
+
+    /// Held to a policy, rather than unconfined or only logging (AppArmor's
+    /// complain mode).
+    pub fn is_confined(&self) -> bool {
+        match self {
+            Confinement::Unknown => false,
+            Confinement::AppArmor(profile) => profile != "unconfined" && !profile.ends_with("(complain)") && !profile.ends_with("(unconfined)"),
+            Confinement::SeLinux(context) => context.split(':').nth(2).is_some_and(|domain| !domain.starts_with("unconfined") && domain != "spc_t"),
+        }
+    }
+}
+
+/// What `/proc/<pid>` tells of the process's privileges.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Posture {
+    /// Effective UID; 0 is root.
+    pub uid: u32,
+    /// `CapEff`, one bit per capability.
+    pub capabilities: u64,
+    /// The binary, when `/proc/<pid>/exe` could be read.
+    pub exe: Option<String>,
+    /// Its permission bits, `0o755`.
+    pub exe_mode: Option<u32>,
+    pub confinement: Confinement,
+}
+
+impl Posture {
+    /// Builds a posture from `/proc/<pid>/status`, the LSM attribute files
+    /// and the binary's path and mode; `None` if the status lacks `Uid:`.
+    pub fn from_parts(status: &str, apparmor: Option<&str>, current: Option<&str>, exe: Option<String>, exe_mode: Option<u32>) -> Option<Self> {
+        let field = |name: &str| status.lines().find_map(|l| l.strip_prefix(name)).map(str::trim);
+        let uid = field("Uid:")?.split_whitespace().nth(1)?.parse().ok()?;
+        let capabilities = field("CapEff:").and_then(|hex| u64::from_str_radix(hex, 16).ok()).unwrap_or(0);
+        Some(Self {
+            uid,
+            capabilities,
+            exe,
+            exe_mode,
+            confinement: Confinement::parse(apparmor, current),
+        })
+    }
+
+    pub fn runs_as_root(&self) -> bool {
+        self.uid == 0
+    }
+
+    /// `None` when the binary's mode couldn't be read.
+    pub fn exe_world_writable(&self) -> Option<bool> {
+        self.exe_mode.map(|mode| mode & 0o002 != 0)
+    }
+
+    /// Every capability the kernel knows of, as root has by default.
+    pub fn has_all_capabilities(&self) -> bool {
+        let all = (1u64 << CAPABILITIES.len()) - 1;
+        self.capabilities & all == all
+    }
+
+    /// The capabilities held, `net_bind_service`; bits newer than this list
+    /// are named by number.
+    pub fn capability_names(&self) -> Vec<String> {
+        (0..64)
+            .filter(|bit| self.capabilities & (1 << bit) != 0)
+            .map(|bit| CAPABILITIES.get(bit).map_or_else(|| format!("cap_{}", bit), |name| name.to_string()))
+            .collect()
+    }
+}
+
+pub fn security_posture(pid: u32) -> Option<Posture> {
+    security_posture_on(&Runner::Local, pid)
+}
+
+pub fn security_posture_on(runner: &Runner, pid: u32) -> Option<Posture> {
+    match runner {
+        Runner::Local => read_local(pid),
+        // Another user's binary and label, or another host's.
+        Runner::Ssh(_) | Runner::Elevated(_) => read_shell(runner, pid),
+    }
+}
+
+#[cfg(target_os = "linux")]
+fn read_local(pid: u32) -> Option<Posture> {
+    use std::os::unix::fs::PermissionsExt;
+
+    let read = |path: String| std::fs::read_to_string(path).ok();
+    let status = read(format!("/proc/{}/status", pid))?;
+    let apparmor = read(format!("/proc/{}/attr/apparmor/current", pid));
+    let current = read(format!("/proc/{}/attr/current", pid));
+    let exe = std::fs::read_link(format!("/proc/{}/exe", pid)).ok();
+    let exe_mode = exe.as_ref().and_then(|path| std::fs::metadata(path).ok()).map(|m| m.permissions().mode() & 0o7777);
+    let exe = exe.map(|path| path.display().to_string());
+    Posture::from_parts(&status, apparmor.as_deref(), current.as_deref(), exe, exe_mode)
+}
+
+#[cfg(not(target_os = "linux"))]
+fn read_local(_pid: u32) -> Option<Posture> {
+    None
+}
+
+/// `read_local`'s files fetched by one script, as another user or on
+/// another host; the binary's path and octal mode close the output.
+fn read_shell(runner: &Runner, pid: u32) -> Option<Posture> {
+    const MARKER: &str = "::crabtop::";
+    let script = format!(
+        "cat /proc/{pid}/status || exit 1; echo {MARKER}; cat /proc/{pid}/attr/apparmor/current 2>/dev/null; echo {MARKER}; \
+         cat /proc/{pid}/attr/current 2>/dev/null; echo {MARKER}; exe=$(readlink /proc/{pid}/exe 2>/dev/null) && echo \"$exe\" && \
+         stat -Lc %a \"$exe\" 2>/dev/null; true"
+    );
+    let out = runner.shell(&script).ok().filter(|out| out.status.success())?;
+    let text = String::from_utf8_lossy(&out.stdout);
+    let mut sections = text.split(MARKER);
+    let status = sections.next()?;
+    let apparmor = sections.next();
+    let current = sections.next();
+    let mut exe = sections.next().unwrap_or_default().lines().map(str::trim).filter(|l| !l.is_empty());
+    let path = exe.next().map(str::to_string);
+    let mode = exe.next().and_then(|mode| u32::from_str_radix(mode, 8).ok());
+    Posture::from_parts(status, apparmor, current, path, mode)
+}
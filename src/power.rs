@@ -0,0 +1,165 @@
+//! Estimated power draw of the process from Intel/AMD RAPL energy counters
+//! (`/sys/class/powercap/intel-rapl:*`, Linux only).
+//!
+//! RAPL only measures whole CPU packages, so the process gets the share of
+//! package power matching its share of the CPU time the host actually used.
+//! It is an estimate: memory, disk and uncore activity are spread evenly.
+
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::Instant;
+
+/// One package zone and the value at which its counter wraps.
+#[derive(Debug, Clone)]
+struct Zone {
+    energy: PathBuf,
+    max_range_uj: u64,
+}
+
+#[derive(Debug, Clone)]
+struct Reading {
+    at: Instant,
+    /// Counter value per zone, in the order of `PowerMeter::zones`.
+    energy_uj: Vec<u64>,
+    host_busy: u64,
+    host_total: u64,
+}
+
+/// Tracks package energy between samples and the process's share of it.
+#[derive(Debug, Clone)]
+pub struct PowerMeter {
+    zones: Vec<Zone>,
+    proc_stat: PathBuf,
+    last: Option<Reading>,
+    /// Package power over the last interval, in watts.
+    pub package_watts: Option<f64>,
+    /// The process's estimated share of it.
+    pub process_watts: Option<f64>,
+    /// Estimated energy used by the process since the meter started.
+    pub process_joules: f64,
+}
+
+impl PowerMeter {
+    /// The host's RAPL package zones, if there are any and they're readable
+    /// (`energy_uj` is root-only on most kernels since 5.10).
+    pub fn detect() -> Option<Self> {
+        Self::from_paths(Path::new("/sys/class/powercap"), Path::new("/proc/stat"))
+    }
+
+    /// Like [`Self::detect`] with the sysfs and procfs paths supplied.
+    pub fn from_paths(powercap: &Path, proc_stat: &Path) -> Option<Self> {
+        let mut zones: Vec<Zone> = fs::read_dir(powercap)
+            .ok()?
+            .flatten()
+            .map(|entry| entry.path())
+            // Top-level zones are the packages; `intel-rapl:0:1` and so on are their subzones.
+            .filter(|path| {
+                path.file_name()
+                    .and_then(|n| n.to_str())
+                    .is_some_and(|n| n.starts_with("intel-rapl:") && n.matches(':').count() == 1)
+            })
+            .filter(|path| fs::read_to_string(path.join("name")).is_ok_and(|n| n.starts_with("package")))
+            .filter(|path| read_u64(&path.join("energy_uj")).is_some())
+            .map(|path| Zone {
+                max_range_uj: read_u64(&path.join("max_energy_range_uj")).unwrap_or(u64::MAX),
+                energy: path.join("energy_uj"),
+            })
+            .collect();
+        if zones.is_empty() || host_cpu_times(proc_stat).is_none() {
+            return None;
+        }
+        zones.sort_by(|a, b| a.energy.cmp(&b.energy));
+        Some(Self {
+            zones,
+            proc_stat: proc_stat.to_path_buf(),
+            last: None,
+            package_watts: None,
+            process_watts: None,
+            process_joules: 0.0,
+        })
+    }
+
+    /// Takes a reading and attributes the energy since the previous one,
+    /// given the process's CPU usage (100 = one full core) over that interval.
+    pub fn update(&mut self, cpu_percent: f32) {
+        self.update_at(Instant::now(), cpu_percent);
+    }
+
+    pub fn update_at(&mut self, at: Instant, cpu_percent: f32) {
+        let Some(HostCpu { busy: host_busy, total: host_total, cores }) = host_cpu_times(&self.proc_stat) else {
+            return;
+        };
+        let Some(energy_uj) = self.zones.iter().map(|z| read_u64(&z.energy)).collect::<Option<Vec<_>>>() else {
+            return;
+        };
+        let used_uj = self.last.as_ref().map(|last| self.energy_since(&last.energy_uj, &energy_uj));
+        let reading = Reading {
+            at,
+            energy_uj,
+            host_busy,
+            host_total,
+        };
+        if let (Some(last), Some(used_uj)) = (self.last.replace(reading), used_uj) {
+            let secs = at.saturating_duration_since(last.at).as_secs_f64();
+            if secs <= 0.0 {
+                return;
+            }
+            let joules = used_uj as f64 / 1e6;
+            let package_watts = joules / secs;
+
+            // Busy cores on the host over the interval, from /proc/stat.
+            let total = host_total.saturating_sub(last.host_total).max(1) as f64;
+            let busy_cores = host_busy.saturating_sub(last.host_busy) as f64 / total * cores as f64;
+            let share = if busy_cores > 0.0 {
+                (cpu_percent as f64 / 100.0 / busy_cores).clamp(0.0, 1.0)
+            } else {
+                0.0
+            };
+            self.package_watts = Some(package_watts);
+            self.process_watts = Some(package_watts * share);
+            self.process_joules += joules * share;
+        }
+    }
+
+    /// Microjoules used between two readings, allowing for counters that wrapped.
+    fn energy_since(&self, before: &[u64], after: &[u64]) -> u64 {
+        self.zones
+            .iter()
+            .zip(before.iter().zip(after))
+            .map(|(zone, (&before, &after))| {
+                if after >= before {
+                    after - before
+                } else {
+                    zone.max_range_uj.saturating_sub(before).saturating_add(after)
+                }
+            })
+            .sum()
+    }
+}
+
+fn read_u64(path: &Path) -> Option<u64> {
+    fs::read_to_string(path).ok()?.trim().parse().ok()
+}
+
+struct HostCpu {
+    busy: u64,
+    total: u64,
+    cores: usize,
+}
+
+/// Busy and total jiffies from the aggregate `cpu` line of /proc/stat, and
+/// the number of per-core `cpuN` lines.
+fn host_cpu_times(proc_stat: &Path) -> Option<HostCpu> {
+    let text = fs::read_to_string(proc_stat).ok()?;
+    let cores = text.lines().filter(|l| l.starts_with("cpu") && !l.starts_with("cpu ")).count();
+    let line = text.lines().find(|l| l.starts_with("cpu "))?;
+    let fields: Vec<u64> = line.split_whitespace().skip(1).filter_map(|f| f.parse().ok()).collect();
+    // user nice system idle iowait irq softirq steal; guest time is already in user.
+    let total: u64 = fields.iter().take(8).sum();
+    let idle = fields.get(3)? + fields.get(4).copied().unwrap_or(0);
+    Some(HostCpu {
+        busy: total.saturating_sub(idle),
+        total,
+        cores: cores.max(1),
+    })
+}
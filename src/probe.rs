@@ -0,0 +1,175 @@
+//! Responsiveness next to resource usage: a timed TCP connect (and close)
+//! against the watched port each tick, and optionally an HTTP health check.
+
+use std::collections::BTreeMap;
+use std::net::SocketAddr;
+use std::time::{Duration, Instant};
+
+use anyhow::{anyhow, Context, Result};
+use tokio::net::{lookup_host, TcpStream};
+
+/// Rolling window of connect latencies; `None` marks a failed attempt.
+#[derive(Debug, Clone)]
+pub struct LatencyHistory {
+    pub samples: Vec<Option<f64>>,
+    max_history: usize,
+}
+
+impl LatencyHistory {
+    pub fn new(max_history: usize) -> Self {
+        Self {
+            samples: Vec::new(),
+            max_history,
+        }
+    }
+
+    pub fn add(&mut self, latency_ms: Option<f64>) {
+        self.samples.push(latency_ms);
+        if self.samples.len() > self.max_history {
+            self.samples.remove(0);
+        }
+    }
+
+    /// Latest attempt: `Some(None)` if it failed, `None` before the first.
+    pub fn last(&self) -> Option<Option<f64>> {
+        self.samples.last().copied()
+    }
+
+    /// Successful latencies in milliseconds, oldest first.
+    pub fn successes(&self) -> Vec<f64> {
+        self.samples.iter().flatten().copied().collect()
+    }
+
+    pub fn failures(&self) -> usize {
+        self.samples.iter().filter(|s| s.is_none()).count()
+    }
+
+    /// Nearest-rank percentile (0–100) of the successful attempts.
+    pub fn percentile(&self, p: f64) -> Option<f64> {
+        let mut ok = self.successes();
+        if ok.is_empty() {
+            return None;
+        }
+        ok.sort_by(|a, b| a.total_cmp(b));
+        let rank = ((p / 100.0) * ok.len() as f64).ceil() as usize;
+        Some(ok[rank.clamp(1, ok.len()) - 1])
+    }
+}
+
+/// A resolved target to connect to every tick.
+pub struct Probe {
+    pub target: String,
+    addr: SocketAddr,
+    timeout: Duration,
+    pub history: LatencyHistory,
+}
+
+impl Probe {
+    /// Resolves `target` (`host:port`) once, so DNS isn't timed on every attempt.
+    pub async fn resolve(target: &str, timeout: Duration, max_history: usize) -> Result<Self> {
+        let addr = lookup_host(target)
+            .await
+            .with_context(|| format!("Failed to resolve probe target {}", target))?
+            .next()
+            .ok_or_else(|| anyhow!("Probe target {} has no addresses", target))?;
+        Ok(Self {
+            target: target.to_string(),
+            addr,
+            timeout,
+            history: LatencyHistory::new(max_history),
+        })
+    }
+
+    /// One connect-and-close, recorded in the history.
+    pub async fn tick(&mut self) -> Result<Duration> {
+        let result = connect_latency(self.addr, self.timeout).await;
+        self.history
+            .add(result.as_ref().ok().map(|d| d.as_secs_f64() * 1000.0));
+        result
+    }
+}
+
+/// Time taken to complete a TCP handshake with `addr`; the socket is closed straight away.
+pub async fn connect_latency(addr: SocketAddr, timeout: Duration) -> Result<Duration> {
+    let start = Instant::now();
+    let stream = tokio::time::timeout(timeout, TcpStream::connect(addr))
+        .await
+        .map_err(|_| anyhow!("Connect to {} timed out after {:?}", addr, timeout))?
+        .with_context(|| format!("Connect to {} failed", addr))?;
+    let elapsed = start.elapsed();
+    drop(stream);
+    Ok(elapsed)
+}
+
+/// The address to probe for a local listener: wildcard binds become loopback.
+pub fn local_target(address: &str, port: u16) -> String {
+    match address {
+        "*" | "0.0.0.0" => format!("127.0.0.1:{}", port),
+        "[::]" | "::" => format!("[::1]:{}", port),
+        other => format!("{}:{}", other, port),
+    }
+}
+
+/// An HTTP GET against the service each tick, e.g. its `/healthz`.
+pub struct HttpCheck {
+    pub url: String,
+    /// Status that counts as healthy.
+    pub expect_status: u16,
+    /// Response times of passing checks; failed ones are `None`.
+    pub history: LatencyHistory,
+    /// How often each status code came back.
+    pub statuses: BTreeMap<u16, usize>,
+    /// Outcome of the latest check: the status, or why there was none.
+    pub last: Option<Result<u16, String>>,
+    client: reqwest::Client,
+}
+
+impl HttpCheck {
+    /// `path` is either a full URL or a path appended to `http://<target>`.
+    pub fn new(target: &str, path: &str, expect_status: u16, timeout: Duration, max_history: usize) -> Result<Self> {
+        let url = if path.starts_with("http://") || path.starts_with("https://") {
+            path.to_string()
+        } else {
+            format!("http://{}/{}", target, path.trim_start_matches('/'))
+        };
+        let client = reqwest::Client::builder()
+            .timeout(timeout)
+            .build()
+            .context("Failed to build HTTP client")?;
+        Ok(Self {
+            url,
+            expect_status,
+            history: LatencyHistory::new(max_history),
+            statuses: BTreeMap::new(),
+            last: None,
+            client,
+        })
+    }
+
+    /// One request; true when it came back with the expected status.
+    pub async fn tick(&mut self) -> bool {
+        let start = Instant::now();
+        let outcome = self.client.get(&self.url).send().await;
+        let elapsed_ms = start.elapsed().as_secs_f64() * 1000.0;
+        let passed = match outcome {
+            Ok(res) => {
+                let status = res.status().as_u16();
+                *self.statuses.entry(status).or_default() += 1;
+                self.last = Some(Ok(status));
+                status == self.expect_status
+            }
+            Err(err) => {
+                self.last = Some(Err(if err.is_timeout() {
+                    "timed out".to_string()
+                } else if err.is_connect() {
+                    "connection failed".to_string()
+                } else {
+                    err.to_string()
+                }));
+                false
+            }
+        };
+        self.history.add(passed.then_some(elapsed_ms));
+        passed
+    }
+}
@@ -0,0 +1,434 @@
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use sysinfo::{Pid, ProcessRefreshKind, System, UpdateKind, Users, MINIMUM_CPU_UPDATE_INTERVAL};
+
+use crate::error::Error;
+use crate::exec::Runner;
+use crate::pattern::Pattern;
+use crate::procfs::{ProcStat, ProcStatus, SmapsRollup};
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct ProcessInfo {
+    pub name: String,
+    /// 0 when the sample isn't from a local process (e.g. a Kubernetes pod).
+    pub pid: u32,
+    pub cpu_percent: f32,
+    /// Resident set size.
+    pub memory_bytes: u64,
+    /// Size of the virtual address space; 0 when unknown.
+    #[serde(default)]
+    pub virtual_bytes: u64,
+    /// Resident pages also mapped by other processes, e.g. a forking server's
+    /// copy-on-write heap (Linux only).
+    #[serde(default)]
+    pub shared_bytes: Option<u64>,
+    /// Pages swapped out (Linux only).
+    #[serde(default)]
+    pub swap_bytes: Option<u64>,
+    /// Private memory committed, in RAM or the pagefile (Windows only).
+    #[serde(default)]
+    pub commit_bytes: Option<u64>,
+    /// Lifetime fault and context-switch counts (Linux only).
+    #[serde(default)]
+    pub counters: Option<ProcessCounters>,
+    /// Owning user name, or the numeric UID when it has no passwd entry.
+    pub user: Option<String>,
+    pub cmdline: Vec<String>,
+    pub exe: Option<PathBuf>,
+    pub cwd: Option<PathBuf>,
+    /// Seconds since the Unix epoch.
+    pub start_time: u64,
+    /// Seconds the process has been running at the time of the sample.
+    pub uptime_secs: u64,
+}
+
+/// Cumulative counters from `/proc/<pid>/stat` and `/proc/<pid>/status`;
+/// watch mode turns them into per-interval rates.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ProcessCounters {
+    pub minor_faults: u64,
+    /// Faults that had to wait for disk.
+    pub major_faults: u64,
+    pub voluntary_switches: u64,
+    /// Preemptions: the process wanted to keep running.
+    pub involuntary_switches: u64,
+}
+
+impl ProcessCounters {
+    /// Both files are already parsed; the switch counts are missing on very old kernels.
+    pub fn from_proc(stat: &ProcStat, status: &ProcStatus) -> Option<Self> {
+        Some(Self {
+            minor_faults: stat.minor_faults,
+            major_faults: stat.major_faults,
+            voluntary_switches: status.voluntary_switches?,
+            involuntary_switches: status.involuntary_switches?,
+        })
+    }
+
+    /// Growth since `earlier`; a counter that went backwards (a restarted
+    /// worker in an aggregate) counts as zero.
+    pub fn since(&self, earlier: &ProcessCounters) -> ProcessCounters {
+        ProcessCounters {
+            minor_faults: self.minor_faults.saturating_sub(earlier.minor_faults),
+            major_faults: self.major_faults.saturating_sub(earlier.major_faults),
+            voluntary_switches: self.voluntary_switches.saturating_sub(earlier.voluntary_switches),
+            involuntary_switches: self.involuntary_switches.saturating_sub(earlier.involuntary_switches),
+        }
+    }
+}
+
+impl std::iter::Sum for ProcessCounters {
+    fn sum<I: Iterator<Item = Self>>(iter: I) -> Self {
+        iter.fold(Self::default(), |a, b| Self {
+            minor_faults: a.minor_faults + b.minor_faults,
+            major_faults: a.major_faults + b.major_faults,
+            voluntary_switches: a.voluntary_switches + b.voluntary_switches,
+            involuntary_switches: a.involuntary_switches + b.involuntary_switches,
+        })
+    }
+}
+
+/// Machine-wide figures sampled alongside the target process.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SystemSnapshot {
+    pub load_average: (f64, f64, f64),
+    pub total_memory_bytes: u64,
+    pub available_memory_bytes: u64,
+    pub total_swap_bytes: u64,
+    pub used_swap_bytes: u64,
+    pub cpu_count: usize,
+}
+
+impl SystemSnapshot {
+    /// Builds a snapshot from a `System` whose memory and CPU list have been refreshed.
+    pub fn from_system(sys: &System) -> Self {
+        let load = System::load_average();
+        Self {
+            load_average: (load.one, load.five, load.fifteen),
+            total_memory_bytes: sys.total_memory(),
+            available_memory_bytes: sys.available_memory(),
+            total_swap_bytes: sys.total_swap(),
+            used_swap_bytes: sys.used_swap(),
+            cpu_count: sys.cpus().len(),
+        }
+    }
+}
+
+/// Takes a fresh machine-wide snapshot.
+pub fn collect_system_snapshot() -> SystemSnapshot {
+    let mut sys = System::new();
+    sys.refresh_memory();
+    sys.refresh_cpu();
+    SystemSnapshot::from_system(&sys)
+}
+
+/// `--cpu-mode`: what 100% CPU means.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize, clap::ValueEnum)]
+#[serde(rename_all = "lowercase")]
+pub enum CpuMode {
+    /// One core's worth, so a busy multi-threaded process can go past 100% (as in `top`).
+    #[default]
+    Process,
+    /// The whole machine: one core's percentages divided by the number of cores.
+    System,
+}
+
+impl CpuMode {
+    /// `percent` (of one core) in this mode, on a host with `cores` cores;
+    /// unchanged when the count is unknown.
+    pub fn scale(self, percent: f32, cores: usize) -> f32 {
+        match self {
+            CpuMode::System if cores > 0 => percent / cores as f32,
+            _ => percent,
+        }
+    }
+
+    /// What the figures are a share of: "of one core", "of 8 cores".
+    pub fn label(self, cores: usize) -> String {
+        match self {
+            CpuMode::System if cores > 0 => format!("of {} core{}", cores, if cores == 1 { "" } else { "s" }),
+            CpuMode::System => "of all cores".to_string(),
+            CpuMode::Process => "of one core".to_string(),
+        }
+    }
+}
+
+/// Combines samples from several processes sharing a port into one figure:
+/// CPU and memory are summed, metadata comes from the first (lowest-PID) one.
+pub fn aggregate(infos: &[ProcessInfo]) -> Option<ProcessInfo> {
+    let mut combined = infos.first()?.clone();
+    combined.cpu_percent = infos.iter().map(|i| i.cpu_percent).sum();
+    combined.memory_bytes = infos.iter().map(|i| i.memory_bytes).sum();
+    combined.virtual_bytes = infos.iter().map(|i| i.virtual_bytes).sum();
+    combined.shared_bytes = infos.iter().map(|i| i.shared_bytes).sum();
+    combined.swap_bytes = infos.iter().map(|i| i.swap_bytes).sum();
+    combined.commit_bytes = infos.iter().map(|i| i.commit_bytes).sum();
+    combined.counters = infos.iter().map(|i| i.counters).sum();
+    Some(combined)
+}
+
+/// Best-effort process name lookup, used when listing candidates for a port.
+pub fn process_name(pid: u32) -> Option<String> {
+    let pid = Pid::from_u32(pid);
+    let mut sys = System::new();
+    sys.refresh_process_specifics(pid, ProcessRefreshKind::new());
+    sys.process(pid).map(|p| p.name().to_string())
+}
+
+/// `pid`'s command line as `ps` shows it, read through `runner`.
+pub fn cmdline_on(runner: &Runner, pid: u32) -> Option<String> {
+    let out = runner.output("ps", &["-o", "args=", "-p", &pid.to_string()]).ok()?;
+    let line = String::from_utf8_lossy(&out.stdout).trim().to_string();
+    (out.status.success() && !line.is_empty()).then_some(line)
+}
+
+/// This machine's processes whose command line matches `pattern`, lowest
+/// PID first, leaving out crabtop itself.
+pub fn find_processes(pattern: &Pattern) -> Vec<u32> {
+    let mut sys = System::new();
+    sys.refresh_processes_specifics(ProcessRefreshKind::new().with_cmd(UpdateKind::OnlyIfNotSet));
+    let own = std::process::id();
+    let mut pids: Vec<u32> = sys
+        .processes()
+        .iter()
+        .filter(|(pid, process)| pid.as_u32() != own && !process.cmd().is_empty() && pattern.is_match(&process.cmd().join(" ")))
+        .map(|(pid, _)| pid.as_u32())
+        .collect();
+    pids.sort_unstable();
+    pids
+}
+
+/// The direct children of `pid`, read through `runner`.
+pub fn children_on(runner: &Runner, pid: u32) -> Vec<u32> {
+    runner
+        .output("pgrep", &["-P", &pid.to_string()])
+        .map(|out| String::from_utf8_lossy(&out.stdout).lines().filter_map(|l| l.trim().parse().ok()).collect())
+        .unwrap_or_default()
+}
+
+/// What the first refresh of a process needs so `read_process_info` can fill
+/// in the metadata (user, cmdline, cwd) as well as the usage figures.
+pub(crate) fn full_refresh_kind() -> ProcessRefreshKind {
+    ProcessRefreshKind::everything()
+}
+
+/// Enough for the figures that change from one sample to the next, once the
+/// metadata is in: not the executable, environment or disk IO.
+pub(crate) fn usage_refresh_kind() -> ProcessRefreshKind {
+    ProcessRefreshKind::new().with_cpu().with_memory()
+}
+
+/// Reads the current figures for `pid` out of an already-refreshed `System`.
+pub(crate) fn read_process_info(sys: &System, users: &Users, pid: Pid) -> Result<ProcessInfo> {
+    let proc = sys
+        .process(pid)
+        .ok_or(Error::ProcessGone { pid: pid.as_u32() })?;
+
+    let user = proc.user_id().map(|uid| {
+        users
+            .get_user_by_id(uid)
+            .map(|user| user.name().to_string())
+            .unwrap_or_else(|| uid.to_string())
+    });
+
+    let rollup = read_smaps_rollup(pid.as_u32());
+    let info = ProcessInfo {
+        name: proc.name().to_string(),
+        pid: pid.as_u32(),
+        cpu_percent: proc.cpu_usage(),
+        memory_bytes: proc.memory(),
+        virtual_bytes: proc.virtual_memory(),
+        shared_bytes: rollup.as_ref().map(SmapsRollup::shared_bytes),
+        swap_bytes: rollup.map(|r| r.swap_bytes),
+        commit_bytes: None,
+        counters: read_counters(pid.as_u32()),
+        user,
+        cmdline: proc.cmd().to_vec(),
+        exe: proc.exe().map(|p| p.to_path_buf()),
+        cwd: proc.cwd().map(|p| p.to_path_buf()),
+        start_time: proc.start_time(),
+        uptime_secs: proc.run_time(),
+    };
+    #[cfg(target_os = "windows")]
+    let info = windows_memory(info)?;
+    Ok(info)
+}
+
+// Needs the same user as the process (or root); without it the breakdown is just left out.
+#[cfg(target_os = "linux")]
+fn read_smaps_rollup(pid: u32) -> Option<SmapsRollup> {
+    std::fs::read_to_string(format!("/proc/{}/smaps_rollup", pid))
+        .ok()
+        .map(|text| crate::procfs::parse_smaps_rollup(&text))
+}
+
+#[cfg(not(target_os = "linux"))]
+fn read_smaps_rollup(_pid: u32) -> Option<SmapsRollup> {
+    None
+}
+
+/// sysinfo reports a Windows process's working set as its memory and its
+/// commit charge as "virtual" memory. Moves the commit charge where it
+/// belongs and splits the working set into private and shared pages, the
+/// way smaps does on Linux.
+#[cfg(target_os = "windows")]
+fn windows_memory(mut info: ProcessInfo) -> Result<ProcessInfo> {
+    info.commit_bytes = Some(info.virtual_bytes);
+    info.virtual_bytes = 0;
+    match read_working_set(info.pid) {
+        Ok(memory) => {
+            info.memory_bytes = memory.working_set;
+            info.shared_bytes = Some(memory.working_set.saturating_sub(memory.private_working_set));
+            info.commit_bytes = Some(memory.commit);
+        }
+        // Services and elevated processes can't be opened from a normal
+        // prompt; sysinfo then reports zeros, which would read as idle.
+        Err(e) if e.kind() == std::io::ErrorKind::PermissionDenied && info.memory_bytes == 0 => {
+            return Err(anyhow::Error::from(Error::PermissionDenied { port: None, pid: Some(info.pid) })
+                .context(format!(
+                    "Access denied reading PID {} ({}); run from an elevated (Administrator) prompt",
+                    info.pid, info.name
+                )));
+        }
+        Err(_) => {}
+    }
+    Ok(info)
+}
+
+#[cfg(target_os = "windows")]
+struct WorkingSet {
+    working_set: u64,
+    private_working_set: u64,
+    commit: u64,
+}
+
+#[cfg(target_os = "windows")]
+fn read_working_set(pid: u32) -> std::io::Result<WorkingSet> {
+    use windows_sys::Win32::Foundation::CloseHandle;
+    use windows_sys::Win32::System::ProcessStatus::{GetProcessMemoryInfo, PROCESS_MEMORY_COUNTERS, PROCESS_MEMORY_COUNTERS_EX2};
+    use windows_sys::Win32::System::Threading::{OpenProcess, PROCESS_QUERY_LIMITED_INFORMATION};
+
+    // SAFETY: the handle is checked before use and closed on every path, and
+    // the counters are plain data whose size is passed alongside them.
+    unsafe {
+        let handle = OpenProcess(PROCESS_QUERY_LIMITED_INFORMATION, 0, pid);
+        if handle.is_null() {
+            return Err(std::io::Error::last_os_error());
+        }
+        let mut counters: PROCESS_MEMORY_COUNTERS_EX2 = std::mem::zeroed();
+        counters.cb = std::mem::size_of::<PROCESS_MEMORY_COUNTERS_EX2>() as u32;
+        let ok = GetProcessMemoryInfo(handle, &mut counters as *mut _ as *mut PROCESS_MEMORY_COUNTERS, counters.cb);
+        let error = std::io::Error::last_os_error();
+        CloseHandle(handle);
+        if ok == 0 {
+            // Also what Windows before 10 1809, which lacks the EX2 counters, says.
+            return Err(error);
+        }
+        Ok(WorkingSet {
+            working_set: counters.WorkingSetSize as u64,
+            private_working_set: counters.PrivateWorkingSetSize as u64,
+            commit: counters.PrivateUsage as u64,
+        })
+    }
+}
+
+#[cfg(target_os = "linux")]
+fn read_counters(pid: u32) -> Option<ProcessCounters> {
+    let stat = std::fs::read_to_string(format!("/proc/{}/stat", pid)).ok()?;
+    let status = std::fs::read_to_string(format!("/proc/{}/status", pid)).ok()?;
+    ProcessCounters::from_proc(&crate::procfs::parse_stat(&stat)?, &crate::procfs::parse_status(&status))
+}
+
+#[cfg(not(target_os = "linux"))]
+fn read_counters(_pid: u32) -> Option<ProcessCounters> {
+    None
+}
+
+/// The shortest time CPU can be measured over: sysinfo won't reread the
+/// host's CPU times any sooner.
+pub const MIN_MEASURE_WINDOW: Duration = MINIMUM_CPU_UPDATE_INTERVAL;
+
+/// How long the first sample of a PID measures CPU over, when there is no
+/// earlier reading to take the difference from (`--measure-ms`).
+pub const DEFAULT_MEASURE_WINDOW: Duration = Duration::from_millis(200);
+
+/// Samples processes without sleeping. sysinfo keeps the CPU time of each
+/// process it has refreshed, so CPU is worked out from the time used since
+/// the sampler last read that PID: in watch mode, over the interval itself.
+/// Only a PID read for the first time is measured over `window`, read twice
+/// that far apart.
+#[derive(Debug, Clone)]
+pub struct ProcessSampler {
+    state: Arc<Mutex<Option<(System, Users)>>>,
+    window: Duration,
+}
+
+impl Default for ProcessSampler {
+    fn default() -> Self {
+        Self::new(DEFAULT_MEASURE_WINDOW)
+    }
+}
+
+impl ProcessSampler {
+    /// Windows shorter than `MIN_MEASURE_WINDOW` are rounded up to it.
+    pub fn new(window: Duration) -> Self {
+        Self {
+            state: Arc::default(),
+            window: window.max(MIN_MEASURE_WINDOW),
+        }
+    }
+
+    pub fn window(&self) -> Duration {
+        self.window
+    }
+
+    pub async fn sample(&self, pid: u32) -> Result<ProcessInfo> {
+        let mut infos = self.sample_all(vec![pid]).await?;
+        infos.pop().expect("one result per PID")
+    }
+
+    /// `sample` for several PIDs, new ones sharing one measurement window:
+    /// `None` for any that couldn't be read.
+    pub async fn sample_group(&self, pids: Vec<u32>) -> Option<Vec<Option<ProcessInfo>>> {
+        let infos = self.sample_all(pids).await.ok()?;
+        Some(infos.into_iter().map(Result::ok).collect())
+    }
+
+    async fn sample_all(&self, pids: Vec<u32>) -> Result<Vec<Result<ProcessInfo>>> {
+        let (state, window) = (self.state.clone(), self.window);
+        tokio::task::spawn_blocking(move || {
+            let mut state = state.lock().expect("sampler lock");
+            let (sys, users) = state.get_or_insert_with(|| (System::new(), Users::new_with_refreshed_list()));
+            let pids: Vec<Pid> = pids.into_iter().map(Pid::from_u32).collect();
+            let unseen: Vec<Pid> = pids.iter().copied().filter(|&pid| sys.process(pid).is_none()).collect();
+            if !unseen.is_empty() {
+                for &pid in &unseen {
+                    sys.refresh_process_specifics(pid, full_refresh_kind());
+                }
+                std::thread::sleep(window);
+            }
+            pids.into_iter()
+                .map(|pid| {
+                    // A process that has exited stays in sysinfo's list, with its last figures.
+                    if !sys.refresh_process_specifics(pid, usage_refresh_kind()) {
+                        return Err(Error::ProcessGone { pid: pid.as_u32() }.into());
+                    }
+                    read_process_info(sys, users, pid)
+                })
+                .collect()
+        })
+        .await
+        .context("Failed to spawn blocking task for process info collection")
+    }
+}
+
+/// One sample of `pid`, measured over `DEFAULT_MEASURE_WINDOW`. Anything
+/// sampling the same process repeatedly should keep a `ProcessSampler`
+/// instead, which doesn't wait.
+pub async fn collect_process_info(pid: u32) -> Result<ProcessInfo> {
+    ProcessSampler::default().sample(pid).await
+}
@@ -0,0 +1,169 @@
+//! Parsers for the handful of `/proc` files we read when sysinfo isn't an
+//! option (the process lives on another machine and we only have its text).
+
+use std::collections::HashMap;
+
+use crate::segments::{MemorySegments, Segment};
+
+/// The fields of `/proc/<pid>/stat` we care about. Times are in clock ticks.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ProcStat {
+    pub comm: String,
+    /// `R`, `S`, `Z`...
+    pub state: char,
+    pub ppid: u32,
+    pub utime: u64,
+    pub stime: u64,
+    /// Ticks after boot at which the process started.
+    pub start_ticks: u64,
+    pub minor_faults: u64,
+    pub major_faults: u64,
+    /// CPU the task last ran on (field 39, missing on very old kernels).
+    pub processor: Option<u32>,
+    /// A zombie's wait status (field 52, Linux 3.5+; 0 unless we may ptrace it).
+    pub exit_code: Option<i32>,
+}
+
+/// Parses a `/proc/<pid>/stat` line. The command name is parenthesised and may
+/// itself contain spaces or parens, so fields are counted from the last `)`.
+pub fn parse_stat(line: &str) -> Option<ProcStat> {
+    let open = line.find('(')?;
+    let close = line.rfind(')')?;
+    let comm = line.get(open + 1..close)?.to_string();
+    // Fields after the name start at field 3 (state), so utime (14) is index 11.
+    let fields: Vec<&str> = line[close + 1..].split_whitespace().collect();
+    Some(ProcStat {
+        comm,
+        state: fields.first()?.chars().next()?,
+        ppid: fields.get(1)?.parse().ok()?,
+        minor_faults: fields.get(7)?.parse().ok()?,
+        major_faults: fields.get(9)?.parse().ok()?,
+        utime: fields.get(11)?.parse().ok()?,
+        stime: fields.get(12)?.parse().ok()?,
+        start_ticks: fields.get(19)?.parse().ok()?,
+        processor: fields.get(36).and_then(|f| f.parse().ok()),
+        exit_code: fields.get(49).and_then(|f| f.parse().ok()),
+    })
+}
+
+/// The fields of `/proc/<pid>/status` we care about.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ProcStatus {
+    pub name: Option<String>,
+    /// Real UID.
+    pub uid: Option<u32>,
+    pub rss_bytes: Option<u64>,
+    pub vm_size_bytes: Option<u64>,
+    pub voluntary_switches: Option<u64>,
+    pub involuntary_switches: Option<u64>,
+}
+
+pub fn parse_status(text: &str) -> ProcStatus {
+    let mut status = ProcStatus::default();
+    for line in text.lines() {
+        let Some((key, value)) = line.split_once(':') else {
+            continue;
+        };
+        let value = value.trim();
+        match key {
+            "Name" => status.name = Some(value.to_string()),
+            "Uid" => status.uid = value.split_whitespace().next().and_then(|u| u.parse().ok()),
+            "VmRSS" => status.rss_bytes = parse_kb(value),
+            "VmSize" => status.vm_size_bytes = parse_kb(value),
+            "voluntary_ctxt_switches" => status.voluntary_switches = value.parse().ok(),
+            "nonvoluntary_ctxt_switches" => status.involuntary_switches = value.parse().ok(),
+            _ => {}
+        }
+    }
+    status
+}
+
+/// Totals from `/proc/<pid>/smaps_rollup` (Linux 4.14+), in bytes.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct SmapsRollup {
+    pub rss_bytes: u64,
+    pub pss_bytes: u64,
+    pub shared_clean_bytes: u64,
+    pub shared_dirty_bytes: u64,
+    pub private_clean_bytes: u64,
+    pub private_dirty_bytes: u64,
+    pub swap_bytes: u64,
+}
+
+impl SmapsRollup {
+    pub fn shared_bytes(&self) -> u64 {
+        self.shared_clean_bytes + self.shared_dirty_bytes
+    }
+
+    pub fn private_bytes(&self) -> u64 {
+        self.private_clean_bytes + self.private_dirty_bytes
+    }
+}
+
+pub fn parse_smaps_rollup(text: &str) -> SmapsRollup {
+    let mut rollup = SmapsRollup::default();
+    for line in text.lines() {
+        let Some((key, value)) = line.split_once(':') else {
+            continue;
+        };
+        let Some(bytes) = parse_kb(value.trim()) else {
+            continue;
+        };
+        match key {
+            "Rss" => rollup.rss_bytes = bytes,
+            "Pss" => rollup.pss_bytes = bytes,
+            "Shared_Clean" => rollup.shared_clean_bytes = bytes,
+            "Shared_Dirty" => rollup.shared_dirty_bytes = bytes,
+            "Private_Clean" => rollup.private_clean_bytes = bytes,
+            "Private_Dirty" => rollup.private_dirty_bytes = bytes,
+            "Swap" => rollup.swap_bytes = bytes,
+            _ => {}
+        }
+    }
+    rollup
+}
+
+/// Resident bytes of each kind of mapping, summed over `/proc/<pid>/smaps`.
+pub fn parse_smaps(text: &str) -> MemorySegments {
+    let mut segments = MemorySegments::default();
+    let mut current = None;
+    for line in text.lines() {
+        let mut fields = line.split_whitespace();
+        let first = fields.next().unwrap_or_default();
+        // A mapping's header starts with its address range, `start-end`.
+        if first.contains('-') && first.bytes().all(|b| b.is_ascii_hexdigit() || b == b'-') {
+            let path = fields.nth(4).unwrap_or_default();
+            current = Some(Segment::of_path(path));
+        } else if let (Some(segment), Some(value)) = (current, line.strip_prefix("Rss:")) {
+            *segments.get_mut(segment) += parse_kb(value.trim()).unwrap_or(0);
+        }
+    }
+    segments
+}
+
+/// Seconds since boot, from the first field of `/proc/uptime`.
+pub fn parse_uptime(text: &str) -> Option<f64> {
+    text.split_whitespace().next()?.parse().ok()
+}
+
+/// The 1, 5 and 15 minute figures from `/proc/loadavg`.
+pub fn parse_loadavg(text: &str) -> Option<(f64, f64, f64)> {
+    let mut fields = text.split_whitespace().map(|f| f.parse::<f64>().ok());
+    Some((fields.next()??, fields.next()??, fields.next()??))
+}
+
+/// `/proc/meminfo` as a map of field name to bytes.
+pub fn parse_meminfo(text: &str) -> HashMap<String, u64> {
+    text.lines()
+        .filter_map(|line| {
+            let (key, value) = line.split_once(':')?;
+            Some((key.to_string(), parse_kb(value.trim())?))
+        })
+        .collect()
+}
+
+// "123456 kB" -> bytes. The kernel's "kB" is KiB.
+fn parse_kb(value: &str) -> Option<u64> {
+    let number = value.strip_suffix("kB").unwrap_or(value).trim();
+    number.parse::<u64>().ok().map(|kb| kb * 1024)
+}
@@ -0,0 +1,253 @@
+//! `profile`: sample the process's stacks for a while and write them as
+//! folded stacks (`frame;frame;frame count`, what flamegraph tools read),
+//! optionally drawn as an SVG flamegraph.
+//!
+//! `perf` gives real user and kernel stacks. Without it we fall back to a
+//! built-in sampler that only sees each thread's name, state and the kernel
+//! function it is waiting in (`wchan`): coarse, but enough to tell a busy
+//! loop from a thread stuck on I/O.
+
+use std::collections::{BTreeMap, HashMap};
+use std::fmt::Write as _;
+use std::fs;
+use std::path::Path;
+use std::process::Command;
+use std::time::{Duration, Instant};
+
+use anyhow::{anyhow, Context, Result};
+
+use crate::chart::escape;
+
+/// Stack → number of samples in which it was seen.
+pub type Folded = BTreeMap<String, u64>;
+
+/// Parses `30s`, `2m`, `500ms`, `7d` or a bare number of seconds.
+pub fn parse_duration(text: &str) -> Result<Duration, String> {
+    let text = text.trim();
+    let (number, unit) = match text.find(|c: char| !c.is_ascii_digit() && c != '.') {
+        Some(at) => text.split_at(at),
+        None => (text, "s"),
+    };
+    let value: f64 = number.parse().map_err(|_| format!("invalid duration: {}", text))?;
+    let secs = match unit {
+        "ms" => value / 1000.0,
+        "s" => value,
+        "m" => value * 60.0,
+        "h" => value * 3600.0,
+        "d" => value * 86_400.0,
+        _ => return Err(format!("unknown unit in {} (use ms, s, m, h or d)", text)),
+    };
+    Ok(Duration::from_secs_f64(secs))
+}
+
+/// Records `pid` with `perf record -g` and folds what `perf script` prints.
+pub fn perf_profile(pid: u32, duration: Duration, frequency: u32) -> Result<Folded> {
+    let data = std::env::temp_dir().join(format!("crabtop-perf-{}.data", pid));
+    let status = Command::new("perf")
+        .args(["record", "-q", "-g", "-F", &frequency.to_string(), "-p", &pid.to_string(), "-o"])
+        .arg(&data)
+        .args(["--", "sleep", &format!("{:.3}", duration.as_secs_f64())])
+        .status()
+        .context("Failed to run perf (is it installed? e.g. linux-tools-$(uname -r))")?;
+    if !status.success() {
+        let _ = fs::remove_file(&data);
+        return Err(anyhow!(
+            "perf record failed; it may need root or a lower kernel.perf_event_paranoid"
+        ));
+    }
+    let out = Command::new("perf").args(["script", "-i"]).arg(&data).output();
+    let _ = fs::remove_file(&data);
+    let out = out.context("Failed to run perf script")?;
+    Ok(fold_perf_script(&String::from_utf8_lossy(&out.stdout)))
+}
+
+/// Folds `perf script` output: one header line per sample followed by its
+/// frames, leaf first, then a blank line.
+pub fn fold_perf_script(text: &str) -> Folded {
+    let mut folded = Folded::new();
+    let mut comm: Option<String> = None;
+    let mut frames: Vec<String> = Vec::new();
+    let mut flush = |comm: &mut Option<String>, frames: &mut Vec<String>| {
+        if let Some(comm) = comm.take() {
+            let mut stack = vec![comm];
+            stack.extend(frames.drain(..).rev());
+            *folded.entry(stack.join(";")).or_insert(0) += 1;
+        }
+        frames.clear();
+    };
+    for line in text.lines() {
+        if line.trim().is_empty() {
+            flush(&mut comm, &mut frames);
+        } else if line.starts_with(char::is_whitespace) {
+            // "\t    7f3a2b1c0d9e  do_work+0x1f (/srv/api/bin/api)"
+            let mut fields = line.split_whitespace();
+            fields.next();
+            let rest: Vec<&str> = fields.collect();
+            let symbol_end = rest.iter().rposition(|f| f.starts_with('(')).unwrap_or(rest.len());
+            let symbol = rest[..symbol_end].join(" ");
+            let symbol = symbol.rsplit_once("+0x").map_or(symbol.as_str(), |(name, _)| name);
+            frames.push(if symbol.is_empty() { "[unknown]".to_string() } else { symbol.to_string() });
+        } else {
+            flush(&mut comm, &mut frames);
+            // "api 4242/4250 [003] 12345.678901: 10101010 cpu-clock:" -> the thread name,
+            // which may contain spaces, is everything before the pid/tid.
+            let words: Vec<&str> = line.split_whitespace().collect();
+            let pid_at = words.iter().position(|w| w.split('/').all(|n| n.parse::<u32>().is_ok())).unwrap_or(1);
+            comm = Some(words[..pid_at.max(1)].join(" ").replace(';', ":"));
+        }
+    }
+    flush(&mut comm, &mut frames);
+    folded
+}
+
+/// The built-in fallback: polls every thread's state and `wchan` at
+/// `frequency` Hz. Sleeping threads are counted too, so waits show up.
+#[cfg(target_os = "linux")]
+pub fn procfs_profile(pid: u32, duration: Duration, frequency: u32) -> Result<Folded> {
+    let name = fs::read_to_string(format!("/proc/{}/comm", pid))
+        .with_context(|| format!("No process with PID {}", pid))?
+        .trim()
+        .to_string();
+    let period = Duration::from_secs_f64(1.0 / frequency.max(1) as f64);
+    let mut folded = Folded::new();
+    let started = Instant::now();
+    while started.elapsed() < duration {
+        let tasks = fs::read_dir(format!("/proc/{}/task", pid)).with_context(|| format!("PID {} exited", pid))?;
+        for task in tasks.flatten() {
+            let path = task.path();
+            let (Ok(stat), Ok(comm)) = (fs::read_to_string(path.join("stat")), fs::read_to_string(path.join("comm"))) else {
+                continue;
+            };
+            let state = stat.rsplit_once(')').and_then(|(_, rest)| rest.split_whitespace().next()).unwrap_or("?");
+            let wchan = fs::read_to_string(path.join("wchan")).unwrap_or_default();
+            let leaf = match (state, wchan.trim()) {
+                ("R", _) => "[running]".to_string(),
+                (state, "" | "0") => format!("[{}]", state),
+                (state, wchan) => format!("[{}] {}", state, wchan),
+            };
+            let stack = format!("{};{};{}", name, comm.trim().replace(';', ":"), leaf);
+            *folded.entry(stack).or_insert(0) += 1;
+        }
+        std::thread::sleep(period);
+    }
+    Ok(folded)
+}
+
+#[cfg(not(target_os = "linux"))]
+pub fn procfs_profile(_pid: u32, _duration: Duration, _frequency: u32) -> Result<Folded> {
+    Err(anyhow!("Profiling needs Linux (perf or /proc)"))
+}
+
+pub fn write_folded(path: &Path, folded: &Folded) -> Result<()> {
+    let mut text = String::new();
+    for (stack, count) in folded {
+        let _ = writeln!(text, "{} {}", stack, count);
+    }
+    fs::write(path, text).with_context(|| format!("Failed to write {}", path.display()))
+}
+
+#[derive(Default)]
+struct Node {
+    value: u64,
+    children: BTreeMap<String, Node>,
+}
+
+const SVG_WIDTH: f64 = 1200.0;
+const FRAME_HEIGHT: f64 = 16.0;
+
+/// Draws `folded` as a flamegraph: callers at the bottom, width proportional
+/// to samples, hover for the full frame name and count.
+pub fn render_flamegraph(folded: &Folded, title: &str) -> String {
+    let mut root = Node::default();
+    for (stack, &count) in folded {
+        root.value += count;
+        let mut node = &mut root;
+        for frame in stack.split(';') {
+            node = node.children.entry(frame.to_string()).or_default();
+            node.value += count;
+        }
+    }
+    let depth = max_depth(&root);
+    let height = (depth as f64 + 2.0) * FRAME_HEIGHT + 24.0;
+    let svg = format!(
+        "<svg xmlns=\"http://www.w3.org/2000/svg\" width=\"{w}\" height=\"{h}\" font-family=\"monospace\" font-size=\"11\">\n\
+         <rect width=\"100%\" height=\"100%\" fill=\"#f8f8f8\"/>\n\
+         <text x=\"{cx}\" y=\"16\" text-anchor=\"middle\" font-size=\"14\">{t}</text>\n",
+        w = SVG_WIDTH,
+        h = height,
+        cx = SVG_WIDTH / 2.0,
+        t = escape(title)
+    );
+    let mut canvas = Canvas {
+        svg,
+        height,
+        scale: SVG_WIDTH / root.value.max(1) as f64,
+        total: root.value,
+        colors: HashMap::new(),
+    };
+    canvas.draw("all", &root, 0.0, 0);
+    canvas.svg.push_str("</svg>\n");
+    canvas.svg
+}
+
+fn max_depth(node: &Node) -> usize {
+    node.children.values().map(|c| 1 + max_depth(c)).max().unwrap_or(0)
+}
+
+struct Canvas {
+    svg: String,
+    height: f64,
+    /// Pixels per sample.
+    scale: f64,
+    total: u64,
+    colors: HashMap<String, String>,
+}
+
+impl Canvas {
+    fn draw(&mut self, name: &str, node: &Node, x: f64, depth: usize) {
+        let width = node.value as f64 * self.scale;
+        if width < 0.1 {
+            return;
+        }
+        let y = self.height - (depth as f64 + 1.0) * FRAME_HEIGHT - 4.0;
+        let color = self.colors.entry(name.to_string()).or_insert_with(|| warm_color(name)).clone();
+        let percent = node.value as f64 / self.total.max(1) as f64 * 100.0;
+        let _ = write!(
+            self.svg,
+            "<g><title>{} ({} samples, {:.2}%)</title><rect x=\"{:.1}\" y=\"{:.1}\" width=\"{:.1}\" height=\"{}\" fill=\"{}\" rx=\"2\"/>",
+            escape(name),
+            node.value,
+            percent,
+            x,
+            y,
+            width,
+            FRAME_HEIGHT - 1.0,
+            color
+        );
+        // About 7px per character at this font size.
+        let fits = ((width - 6.0) / 7.0) as usize;
+        if fits >= 3 {
+            let label: String = if name.chars().count() > fits {
+                name.chars().take(fits - 2).chain("..".chars()).collect()
+            } else {
+                name.to_string()
+            };
+            let _ = write!(self.svg, "<text x=\"{:.1}\" y=\"{:.1}\">{}</text>", x + 3.0, y + 11.0, escape(&label));
+        }
+        self.svg.push_str("</g>\n");
+        let mut child_x = x;
+        for (child_name, child) in &node.children {
+            self.draw(child_name, child, child_x, depth + 1);
+            child_x += child.value as f64 * self.scale;
+        }
+    }
+}
+
+// The classic flamegraph palette: reds through yellows, stable per name.
+fn warm_color(name: &str) -> String {
+    let hash = name.bytes().fold(2166136261u32, |h, b| (h ^ b as u32).wrapping_mul(16777619));
+    let r = 205 + (hash % 50);
+    let g = (hash >> 8) % 230;
+    let b = (hash >> 16) % 55;
+    format!("rgb({},{},{})", r, g, b)
+}
@@ -0,0 +1,328 @@
+//! A Prometheus scrape endpoint for the latest watch sample, `.prom` files
+//! for node_exporter's textfile collector when opening a port isn't an
+//! option, and pushes to a Pushgateway or a remote_write endpoint for
+//! sessions too short to be scraped.
+
+use std::collections::BTreeMap;
+use std::fmt::Write as _;
+use std::fs;
+use std::net::SocketAddr;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+
+use anyhow::{bail, Context, Result};
+use axum::http::header;
+use axum::response::IntoResponse;
+use axum::routing::get;
+use axum::Router;
+
+use crate::export::{ExportSample, Exporter, QueuedWorker};
+
+/// Latest sample per (host, port), so a fleet grid exports every cell.
+type Latest = Arc<Mutex<BTreeMap<(String, u16), ExportSample>>>;
+
+/// Metric name, help text and how to read the value from a sample.
+type Gauge = (&'static str, &'static str, fn(&ExportSample) -> f64);
+
+/// Serves `GET /metrics` in the text exposition format.
+pub struct PrometheusExporter {
+    latest: Latest,
+}
+
+impl PrometheusExporter {
+    /// Binds `addr` right away so a taken port fails at startup, then serves
+    /// in the background. Must be called inside a Tokio runtime.
+    pub fn bind(addr: SocketAddr) -> Result<Self> {
+        let listener = std::net::TcpListener::bind(addr)
+            .with_context(|| format!("Failed to listen on {} for Prometheus", addr))?;
+        listener.set_nonblocking(true)?;
+        let listener = tokio::net::TcpListener::from_std(listener)?;
+
+        let latest = Latest::default();
+        let state = Arc::clone(&latest);
+        let app = Router::new().route(
+            "/metrics",
+            get(move || {
+                let body = render(&state.lock().unwrap());
+                async move { ([(header::CONTENT_TYPE, "text/plain; version=0.0.4")], body).into_response() }
+            }),
+        );
+        tokio::spawn(async move { axum::serve(listener, app).await });
+
+        Ok(Self { latest })
+    }
+}
+
+impl Exporter for PrometheusExporter {
+    fn name(&self) -> &str {
+        "prometheus"
+    }
+
+    fn on_sample(&mut self, sample: &ExportSample) -> Result<()> {
+        let key = (sample.host.clone().unwrap_or_default(), sample.port);
+        self.latest.lock().unwrap().insert(key, sample.clone());
+        Ok(())
+    }
+}
+
+/// Writes each target's latest sample to `<dir>/crabtop_<port>.prom` (with
+/// the host too when remote), so node_exporter's textfile collector picks it
+/// up. Each file is written beside itself and renamed into place, so a
+/// scrape never reads half of one; node_exporter's
+/// `node_textfile_mtime_seconds` tells a stale file from a live one.
+pub struct TextfileExporter {
+    dir: PathBuf,
+}
+
+impl TextfileExporter {
+    /// Fails right away if `dir` isn't an existing directory.
+    pub fn new(dir: &Path) -> Result<Self> {
+        if !dir.is_dir() {
+            bail!("{} isn't a directory, so there is nowhere to write .prom files", dir.display());
+        }
+        Ok(Self { dir: dir.to_path_buf() })
+    }
+
+    /// Where `sample` is written.
+    pub fn path_for(&self, sample: &ExportSample) -> PathBuf {
+        let name = match &sample.host {
+            Some(host) => {
+                let host: String = host.chars().map(|c| if c.is_ascii_alphanumeric() { c } else { '_' }).collect();
+                format!("crabtop_{}_{}.prom", host, sample.port)
+            }
+            None => format!("crabtop_{}.prom", sample.port),
+        };
+        self.dir.join(name)
+    }
+}
+
+impl Exporter for TextfileExporter {
+    fn name(&self) -> &str {
+        "textfile"
+    }
+
+    fn on_sample(&mut self, sample: &ExportSample) -> Result<()> {
+        let body = render_one(sample);
+        let path = self.path_for(sample);
+        // node_exporter only reads `*.prom`, so the half-written file is never seen.
+        let partial = path.with_extension(format!("prom.{}.tmp", std::process::id()));
+        fs::write(&partial, body).with_context(|| format!("Failed to write {}", partial.display()))?;
+        fs::rename(&partial, &path).with_context(|| format!("Failed to move {} into place", path.display()))
+    }
+}
+
+/// Renders the exposition text for every sample we hold.
+const GAUGES: [Gauge; 3] = [
+    ("crabtop_cpu_percent", "CPU usage of the watched process(es), in percent of one core.", |s| {
+        s.process.cpu_percent as f64
+    }),
+    ("crabtop_memory_bytes", "Resident memory of the watched process(es).", |s| {
+        s.process.memory_bytes as f64
+    }),
+    ("crabtop_uptime_seconds", "Seconds since the watched process started.", |s| {
+        s.process.uptime_secs as f64
+    }),
+];
+
+pub fn render(samples: &BTreeMap<(String, u16), ExportSample>) -> String {
+    let mut out = String::new();
+    for (name, help, value) in GAUGES {
+        let _ = writeln!(out, "# HELP {} {}", name, help);
+        let _ = writeln!(out, "# TYPE {} gauge", name);
+        for sample in samples.values() {
+            let mut labels = format!("port=\"{}\",process=\"{}\"", sample.port, escape(&sample.process.name));
+            if let Some(host) = &sample.host {
+                let _ = write!(labels, ",host=\"{}\"", escape(host));
+            }
+            let _ = writeln!(out, "{}{{{}}} {}", name, labels, value(sample));
+        }
+    }
+    out
+}
+
+fn render_one(sample: &ExportSample) -> String {
+    let key = (sample.host.clone().unwrap_or_default(), sample.port);
+    render(&BTreeMap::from([(key, sample.clone())]))
+}
+
+fn escape(value: &str) -> String {
+    value.replace('\\', r"\\").replace('"', "\\\"").replace('\n', "\\n")
+}
+
+/// How a push URL is spoken to.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PushProtocol {
+    /// `PUT <url>/metrics/job/crabtop/port/<port>` in the text format, which
+    /// replaces that group's last push.
+    Pushgateway,
+    /// A snappy-compressed protobuf `WriteRequest`, as Prometheus, Mimir,
+    /// Thanos and VictoriaMetrics accept.
+    RemoteWrite,
+}
+
+impl PushProtocol {
+    /// Remote write for URLs ending in `/write` or `/push` (Prometheus's
+    /// `/api/v1/write`, Mimir's `/api/v1/push`), the Pushgateway otherwise.
+    pub fn for_url(url: &str) -> Self {
+        let path = url.split(['?', '#']).next().unwrap_or_default().trim_end_matches('/');
+        if path.ends_with("/write") || path.ends_with("/push") {
+            PushProtocol::RemoteWrite
+        } else {
+            PushProtocol::Pushgateway
+        }
+    }
+}
+
+/// Pushes every sample, in order, through a `QueuedWorker`. Shutdown
+/// waits for the queue to drain, so the last figures of a session shorter
+/// than a scrape interval still arrive.
+pub struct PushExporter {
+    worker: QueuedWorker,
+}
+
+impl PushExporter {
+    pub fn new(url: &str, protocol: PushProtocol) -> Result<Self> {
+        let url = url.trim_end_matches('/').to_string();
+        let worker = QueuedWorker::spawn("push", "the endpoint", move |samples, errors| {
+            // The worker's own, so pushing doesn't depend on how the caller's runtime is built.
+            let runtime = match tokio::runtime::Builder::new_current_thread().enable_all().build() {
+                Ok(runtime) => runtime,
+                Err(err) => {
+                    *errors.lock().unwrap() = Some(format!("Failed to start the push runtime: {}", err));
+                    return;
+                }
+            };
+            let client = reqwest::Client::new();
+            for sample in samples {
+                let request = match protocol {
+                    PushProtocol::Pushgateway => client
+                        .put(pushgateway_url(&url, &sample))
+                        .header(reqwest::header::CONTENT_TYPE, "text/plain; version=0.0.4")
+                        .body(render_one(&sample)),
+                    PushProtocol::RemoteWrite => client
+                        .post(&url)
+                        .header(reqwest::header::CONTENT_TYPE, "application/x-protobuf")
+                        .header(reqwest::header::CONTENT_ENCODING, "snappy")
+                        .header("X-Prometheus-Remote-Write-Version", "0.1.0")
+                        .body(snappy_block(&write_request(&sample))),
+                };
+                let failure = match runtime.block_on(request.send()) {
+                    Ok(resp) if resp.status().is_success() => None,
+                    Ok(resp) => Some(format!("HTTP {}", resp.status())),
+                    Err(err) => Some(err.to_string()),
+                };
+                if let Some(message) = failure {
+                    *errors.lock().unwrap() = Some(message);
+                }
+            }
+        })?;
+        Ok(Self { worker })
+    }
+}
+
+impl Exporter for PushExporter {
+    fn name(&self) -> &str {
+        "prometheus push"
+    }
+
+    fn on_sample(&mut self, sample: &ExportSample) -> Result<()> {
+        self.worker.send(sample)
+    }
+
+    fn on_shutdown(&mut self) -> Result<()> {
+        self.worker.drain()
+    }
+}
+
+/// `<base>/metrics/job/crabtop/port/<port>`, plus `/host/<host>` for a remote
+/// target, so each target has its own group.
+pub fn pushgateway_url(base: &str, sample: &ExportSample) -> String {
+    let mut url = format!("{}/metrics/job/crabtop/port/{}", base.trim_end_matches('/'), sample.port);
+    if let Some(host) = &sample.host {
+        let _ = write!(url, "/host/{}", path_segment(host));
+    }
+    url
+}
+
+fn path_segment(value: &str) -> String {
+    let mut out = String::new();
+    for byte in value.bytes() {
+        match byte {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'.' | b'_' | b'~' | b':' => out.push(byte as char),
+            _ => {
+                let _ = write!(out, "%{:02X}", byte);
+            }
+        }
+    }
+    out
+}
+
+/// The remote_write `WriteRequest` for one sample: a series per gauge, with
+/// labels sorted by name as the protocol requires.
+pub fn write_request(sample: &ExportSample) -> Vec<u8> {
+    let mut request = Vec::new();
+    for (name, _, value) in GAUGES {
+        let mut labels = vec![("__name__", name.to_string())];
+        if let Some(host) = &sample.host {
+            labels.push(("host", host.clone()));
+        }
+        labels.push(("job", "crabtop".to_string()));
+        labels.push(("port", sample.port.to_string()));
+        labels.push(("process", sample.process.name.clone()));
+
+        let mut series = Vec::new();
+        for (name, value) in labels {
+            let mut label = Vec::new();
+            proto_bytes(&mut label, 1, name.as_bytes());
+            proto_bytes(&mut label, 2, value.as_bytes());
+            proto_bytes(&mut series, 1, &label);
+        }
+        let mut point = Vec::new();
+        proto_key(&mut point, 1, 1);
+        point.extend_from_slice(&value(sample).to_le_bytes());
+        proto_key(&mut point, 2, 0);
+        proto_varint(&mut point, sample.timestamp.timestamp_millis() as u64);
+        proto_bytes(&mut series, 2, &point);
+        proto_bytes(&mut request, 1, &series);
+    }
+    request
+}
+
+fn proto_key(out: &mut Vec<u8>, field: u64, wire_type: u64) {
+    proto_varint(out, field << 3 | wire_type);
+}
+
+fn proto_bytes(out: &mut Vec<u8>, field: u64, bytes: &[u8]) {
+    proto_key(out, field, 2);
+    proto_varint(out, bytes.len() as u64);
+    out.extend_from_slice(bytes);
+}
+
+fn proto_varint(out: &mut Vec<u8>, mut value: u64) {
+    while value >= 0x80 {
+        out.push(value as u8 | 0x80);
+        value >>= 7;
+    }
+    out.push(value as u8);
+}
+
+/// `data` as a snappy block made of a single literal: valid snappy that any
+/// decoder reads, without compressing, since a sample is a few hundred bytes.
+pub fn snappy_block(data: &[u8]) -> Vec<u8> {
+    let mut out = Vec::with_capacity(data.len() + 10);
+    proto_varint(&mut out, data.len() as u64);
+    if data.is_empty() {
+        return out;
+    }
+    let len = data.len() - 1;
+    if len < 60 {
+        out.push((len as u8) << 2);
+    } else {
+        let bytes = (len as u32).to_le_bytes();
+        let width = bytes.iter().rposition(|b| *b != 0).unwrap_or(0) + 1;
+        out.push(((59 + width) as u8) << 2);
+        out.extend_from_slice(&bytes[..width]);
+    }
+    out.extend_from_slice(data);
+    out
+}
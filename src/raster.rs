@@ -0,0 +1,366 @@
+//! A small RGB bitmap that `chart` draws PNGs on: lines, boxes and text in
+//! a built-in 5×7 font, saved as a deflate-compressed PNG. Enough for line
+//! charts, without a plotting or image crate.
+
+/// Where text sits relative to its `x`, as SVG's `text-anchor`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Anchor {
+    Start,
+    Middle,
+    End,
+}
+
+pub struct Bitmap {
+    width: usize,
+    height: usize,
+    /// Row-major RGB.
+    pixels: Vec<[u8; 3]>,
+}
+
+/// Glyph columns for `' '..='~'`, bit 0 at the top.
+const FONT: [[u8; 5]; 95] = [
+    [0x00, 0x00, 0x00, 0x00, 0x00],
+    [0x00, 0x00, 0x5F, 0x00, 0x00],
+    [0x00, 0x07, 0x00, 0x07, 0x00],
+    [0x14, 0x7F, 0x14, 0x7F, 0x14],
+    [0x24, 0x2A, 0x7F, 0x2A, 0x12],
+    [0x23, 0x13, 0x08, 0x64, 0x62],
+    [0x36, 0x49, 0x55, 0x22, 0x50],
+    [0x00, 0x05, 0x03, 0x00, 0x00],
+    [0x00, 0x1C, 0x22, 0x41, 0x00],
+    [0x00, 0x41, 0x22, 0x1C, 0x00],
+    [0x08, 0x2A, 0x1C, 0x2A, 0x08],
+    [0x08, 0x08, 0x3E, 0x08, 0x08],
+    [0x00, 0x50, 0x30, 0x00, 0x00],
+    [0x08, 0x08, 0x08, 0x08, 0x08],
+    [0x00, 0x60, 0x60, 0x00, 0x00],
+    [0x20, 0x10, 0x08, 0x04, 0x02],
+    [0x3E, 0x51, 0x49, 0x45, 0x3E],
+    [0x00, 0x42, 0x7F, 0x40, 0x00],
+    [0x42, 0x61, 0x51, 0x49, 0x46],
+    [0x21, 0x41, 0x45, 0x4B, 0x31],
+    [0x18, 0x14, 0x12, 0x7F, 0x10],
+    [0x27, 0x45, 0x45, 0x45, 0x39],
+    [0x3C, 0x4A, 0x49, 0x49, 0x30],
+    [0x01, 0x71, 0x09, 0x05, 0x03],
+    [0x36, 0x49, 0x49, 0x49, 0x36],
+    [0x06, 0x49, 0x49, 0x29, 0x1E],
+    [0x00, 0x36, 0x36, 0x00, 0x00],
+    [0x00, 0x56, 0x36, 0x00, 0x00],
+    [0x08, 0x14, 0x22, 0x41, 0x00],
+    [0x14, 0x14, 0x14, 0x14, 0x14],
+    [0x00, 0x41, 0x22, 0x14, 0x08],
+    [0x02, 0x01, 0x51, 0x09, 0x06],
+    [0x32, 0x49, 0x79, 0x41, 0x3E],
+    [0x7E, 0x11, 0x11, 0x11, 0x7E],
+    [0x7F, 0x49, 0x49, 0x49, 0x36],
+    [0x3E, 0x41, 0x41, 0x41, 0x22],
+    [0x7F, 0x41, 0x41, 0x22, 0x1C],
+    [0x7F, 0x49, 0x49, 0x49, 0x41],
+    [0x7F, 0x09, 0x09, 0x09, 0x01],
+    [0x3E, 0x41, 0x49, 0x49, 0x7A],
+    [0x7F, 0x08, 0x08, 0x08, 0x7F],
+    [0x00, 0x41, 0x7F, 0x41, 0x00],
+    [0x20, 0x40, 0x41, 0x3F, 0x01],
+    [0x7F, 0x08, 0x14, 0x22, 0x41],
+    [0x7F, 0x40, 0x40, 0x40, 0x40],
+    [0x7F, 0x02, 0x0C, 0x02, 0x7F],
+    [0x7F, 0x04, 0x08, 0x10, 0x7F],
+    [0x3E, 0x41, 0x41, 0x41, 0x3E],
+    [0x7F, 0x09, 0x09, 0x09, 0x06],
+    [0x3E, 0x41, 0x51, 0x21, 0x5E],
+    [0x7F, 0x09, 0x19, 0x29, 0x46],
+    [0x46, 0x49, 0x49, 0x49, 0x31],
+    [0x01, 0x01, 0x7F, 0x01, 0x01],
+    [0x3F, 0x40, 0x40, 0x40, 0x3F],
+    [0x1F, 0x20, 0x40, 0x20, 0x1F],
+    [0x3F, 0x40, 0x38, 0x40, 0x3F],
+    [0x63, 0x14, 0x08, 0x14, 0x63],
+    [0x07, 0x08, 0x70, 0x08, 0x07],
+    [0x61, 0x51, 0x49, 0x45, 0x43],
+    [0x00, 0x7F, 0x41, 0x41, 0x00],
+    [0x02, 0x04, 0x08, 0x10, 0x20],
+    [0x00, 0x41, 0x41, 0x7F, 0x00],
+    [0x04, 0x02, 0x01, 0x02, 0x04],
+    [0x40, 0x40, 0x40, 0x40, 0x40],
+    [0x00, 0x01, 0x02, 0x04, 0x00],
+    [0x20, 0x54, 0x54, 0x54, 0x78],
+    [0x7F, 0x48, 0x44, 0x44, 0x38],
+    [0x38, 0x44, 0x44, 0x44, 0x20],
+    [0x38, 0x44, 0x44, 0x48, 0x7F],
+    [0x38, 0x54, 0x54, 0x54, 0x18],
+    [0x08, 0x7E, 0x09, 0x01, 0x02],
+    [0x0C, 0x52, 0x52, 0x52, 0x3E],
+    [0x7F, 0x08, 0x04, 0x04, 0x78],
+    [0x00, 0x44, 0x7D, 0x40, 0x00],
+    [0x20, 0x40, 0x44, 0x3D, 0x00],
+    [0x7F, 0x10, 0x28, 0x44, 0x00],
+    [0x00, 0x41, 0x7F, 0x40, 0x00],
+    [0x7C, 0x04, 0x18, 0x04, 0x78],
+    [0x7C, 0x08, 0x04, 0x04, 0x78],
+    [0x38, 0x44, 0x44, 0x44, 0x38],
+    [0x7C, 0x14, 0x14, 0x14, 0x08],
+    [0x08, 0x14, 0x14, 0x18, 0x7C],
+    [0x7C, 0x08, 0x04, 0x04, 0x08],
+    [0x48, 0x54, 0x54, 0x54, 0x20],
+    [0x04, 0x3F, 0x44, 0x40, 0x20],
+    [0x3C, 0x40, 0x40, 0x20, 0x7C],
+    [0x1C, 0x20, 0x40, 0x20, 0x1C],
+    [0x3C, 0x40, 0x30, 0x40, 0x3C],
+    [0x44, 0x28, 0x10, 0x28, 0x44],
+    [0x0C, 0x50, 0x50, 0x50, 0x3C],
+    [0x44, 0x64, 0x54, 0x4C, 0x44],
+    [0x00, 0x08, 0x36, 0x41, 0x00],
+    [0x00, 0x00, 0x7F, 0x00, 0x00],
+    [0x00, 0x41, 0x36, 0x08, 0x00],
+    [0x02, 0x01, 0x02, 0x04, 0x02],
+];
+
+/// Pixels from one glyph's left edge to the next's, at scale 1.
+pub const GLYPH_ADVANCE: usize = 6;
+const GLYPH_HEIGHT: usize = 7;
+
+impl Bitmap {
+    pub fn new(width: usize, height: usize, background: &str) -> Self {
+        Self {
+            width,
+            height,
+            pixels: vec![rgb(background); width * height],
+        }
+    }
+
+    pub fn width(&self) -> usize {
+        self.width
+    }
+
+    pub fn height(&self) -> usize {
+        self.height
+    }
+
+    /// The colour at `(x, y)`, `None` outside the bitmap.
+    pub fn pixel(&self, x: usize, y: usize) -> Option<[u8; 3]> {
+        (x < self.width && y < self.height).then(|| self.pixels[y * self.width + x])
+    }
+
+    fn plot(&mut self, x: i64, y: i64, color: [u8; 3]) {
+        if x >= 0 && y >= 0 && (x as usize) < self.width && (y as usize) < self.height {
+            self.pixels[y as usize * self.width + x as usize] = color;
+        }
+    }
+
+    /// A filled box with its top left corner at `(x, y)`.
+    pub fn fill_rect(&mut self, x: f64, y: f64, width: f64, height: f64, color: &str) {
+        let color = rgb(color);
+        let (x0, y0) = (x.round() as i64, y.round() as i64);
+        let (x1, y1) = ((x + width).round() as i64, (y + height).round() as i64);
+        for y in y0..y1 {
+            for x in x0..x1 {
+                self.plot(x, y, color);
+            }
+        }
+    }
+
+    /// A box's outline, one pixel wide.
+    pub fn stroke_rect(&mut self, x: f64, y: f64, width: f64, height: f64, color: &str) {
+        let (right, bottom) = (x + width, y + height);
+        for (from, to) in [((x, y), (right, y)), ((right, y), (right, bottom)), ((right, bottom), (x, bottom)), ((x, bottom), (x, y))] {
+            self.line(from, to, 1.0, color);
+        }
+    }
+
+    /// A straight line `width` pixels thick, rounded to whole pixels.
+    pub fn line(&mut self, from: (f64, f64), to: (f64, f64), width: f64, color: &str) {
+        let color = rgb(color);
+        let thickness = width.round().max(1.0) as i64;
+        let steps = (to.0 - from.0).abs().max((to.1 - from.1).abs()).ceil().max(1.0);
+        for step in 0..=steps as i64 {
+            let t = step as f64 / steps;
+            let x = (from.0 + (to.0 - from.0) * t).round() as i64 - (thickness - 1) / 2;
+            let y = (from.1 + (to.1 - from.1) * t).round() as i64 - (thickness - 1) / 2;
+            for dy in 0..thickness {
+                for dx in 0..thickness {
+                    self.plot(x + dx, y + dy, color);
+                }
+            }
+        }
+    }
+
+    /// `text` with its baseline at `y`, each font pixel drawn `scale`
+    /// pixels square. Characters outside ASCII come out as `?`.
+    pub fn text(&mut self, x: f64, y: f64, anchor: Anchor, scale: usize, color: &str, text: &str) {
+        let color = rgb(color);
+        let width = (text.chars().count() * GLYPH_ADVANCE * scale) as f64;
+        let left = match anchor {
+            Anchor::Start => x,
+            Anchor::Middle => x - width / 2.0,
+            Anchor::End => x - width,
+        }
+        .round() as i64;
+        let top = y.round() as i64 - (GLYPH_HEIGHT * scale) as i64;
+        for (n, c) in text.chars().enumerate() {
+            let glyph = FONT[(c as usize).checked_sub(0x20).filter(|&i| i < FONT.len()).unwrap_or('?' as usize - 0x20)];
+            let at = left + (n * GLYPH_ADVANCE * scale) as i64;
+            for (column, bits) in glyph.iter().enumerate() {
+                for row in 0..GLYPH_HEIGHT {
+                    if bits & (1 << row) == 0 {
+                        continue;
+                    }
+                    for dy in 0..scale {
+                        for dx in 0..scale {
+                            self.plot(at + (column * scale + dx) as i64, top + (row * scale + dy) as i64, color);
+                        }
+                    }
+                }
+            }
+        }
+    }
+
+    /// The bitmap as a PNG file: 8-bit RGB, unfiltered rows.
+    pub fn to_png(&self) -> Vec<u8> {
+        let mut raw = Vec::with_capacity(self.height * (self.width * 3 + 1));
+        for row in self.pixels.chunks(self.width.max(1)) {
+            raw.push(0);
+            raw.extend(row.iter().flatten());
+        }
+        let mut ihdr = Vec::with_capacity(13);
+        ihdr.extend_from_slice(&(self.width as u32).to_be_bytes());
+        ihdr.extend_from_slice(&(self.height as u32).to_be_bytes());
+        ihdr.extend_from_slice(&[8, 2, 0, 0, 0]);
+
+        let mut png = b"\x89PNG\r\n\x1a\n".to_vec();
+        chunk(&mut png, b"IHDR", &ihdr);
+        chunk(&mut png, b"IDAT", &zlib(&raw, self.width * 3 + 1));
+        chunk(&mut png, b"IEND", &[]);
+        png
+    }
+}
+
+/// `#rrggbb` or `#rgb`; anything else is black.
+fn rgb(color: &str) -> [u8; 3] {
+    let hex = color.trim_start_matches('#');
+    let digits: Vec<u8> = match hex.len() {
+        3 => hex.chars().filter_map(|c| c.to_digit(16)).map(|d| d as u8 * 17).collect(),
+        6 => (0..3).filter_map(|i| u8::from_str_radix(hex.get(i * 2..i * 2 + 2)?, 16).ok()).collect(),
+        _ => Vec::new(),
+    };
+    match digits[..] {
+        [r, g, b] => [r, g, b],
+        _ => [0, 0, 0],
+    }
+}
+
+fn chunk(png: &mut Vec<u8>, kind: &[u8; 4], data: &[u8]) {
+    png.extend_from_slice(&(data.len() as u32).to_be_bytes());
+    let start = png.len();
+    png.extend_from_slice(kind);
+    png.extend_from_slice(data);
+    let crc = crc32(&png[start..]);
+    png.extend_from_slice(&crc.to_be_bytes());
+}
+
+fn crc32(data: &[u8]) -> u32 {
+    let mut crc = !0u32;
+    for byte in data {
+        crc ^= *byte as u32;
+        for _ in 0..8 {
+            crc = if crc & 1 == 1 { (crc >> 1) ^ 0xEDB8_8320 } else { crc >> 1 };
+        }
+    }
+    !crc
+}
+
+fn adler32(data: &[u8]) -> u32 {
+    let (mut a, mut b) = (1u32, 0u32);
+    for chunk in data.chunks(5552) {
+        for byte in chunk {
+            a += *byte as u32;
+            b += a;
+        }
+        a %= 65521;
+        b %= 65521;
+    }
+    (b << 16) | a
+}
+
+const LENGTH_BASE: [u16; 29] = [3, 4, 5, 6, 7, 8, 9, 10, 11, 13, 15, 17, 19, 23, 27, 31, 35, 43, 51, 59, 67, 83, 99, 115, 131, 163, 195, 227, 258];
+const LENGTH_EXTRA: [u8; 29] = [0, 0, 0, 0, 0, 0, 0, 0, 1, 1, 1, 1, 2, 2, 2, 2, 3, 3, 3, 3, 4, 4, 4, 4, 5, 5, 5, 5, 0];
+const DISTANCE_BASE: [u16; 30] = [
+    1, 2, 3, 4, 5, 7, 9, 13, 17, 25, 33, 49, 65, 97, 129, 193, 257, 385, 513, 769, 1025, 1537, 2049, 3073, 4097, 6145, 8193, 12289, 16385, 24577,
+];
+const DISTANCE_EXTRA: [u8; 30] = [0, 0, 0, 0, 1, 1, 2, 2, 3, 3, 4, 4, 5, 5, 6, 6, 7, 7, 8, 8, 9, 9, 10, 10, 11, 11, 12, 12, 13, 13];
+
+/// Deflate's bits, least significant first.
+struct Bits {
+    out: Vec<u8>,
+    buffer: u32,
+    count: u32,
+}
+
+impl Bits {
+    fn put(&mut self, value: u32, bits: u32) {
+        self.buffer |= value << self.count;
+        self.count += bits;
+        while self.count >= 8 {
+            self.out.push(self.buffer as u8);
+            self.buffer >>= 8;
+            self.count -= 8;
+        }
+    }
+
+    /// A Huffman code, which goes most significant bit first.
+    fn code(&mut self, code: u32, bits: u32) {
+        self.put(code.reverse_bits() >> (32 - bits), bits);
+    }
+
+    /// A literal or length symbol in the fixed code.
+    fn symbol(&mut self, symbol: u32) {
+        match symbol {
+            0..=143 => self.code(0x30 + symbol, 8),
+            144..=255 => self.code(0x190 + symbol - 144, 9),
+            256..=279 => self.code(symbol - 256, 7),
+            _ => self.code(0xC0 + symbol - 280, 8),
+        }
+    }
+}
+
+/// `data` in one fixed-Huffman deflate block, matching runs against the
+/// pixel to the left and the one above (`stride` bytes back): charts are
+/// mostly flat colour, which that squeezes well.
+fn zlib(data: &[u8], stride: usize) -> Vec<u8> {
+    let mut bits = Bits {
+        out: vec![0x78, 0x01],
+        buffer: 0,
+        count: 0,
+    };
+    bits.put(1, 1); // final block
+    bits.put(1, 2); // fixed codes
+    let distances: Vec<usize> = [3, stride].into_iter().filter(|&d| d <= 32768).collect();
+    let mut at = 0;
+    while at < data.len() {
+        let best = distances
+            .iter()
+            .filter(|&&d| d <= at)
+            .map(|&d| (d, (0..258.min(data.len() - at)).take_while(|&i| data[at + i] == data[at + i - d]).count()))
+            .max_by_key(|&(_, len)| len)
+            .filter(|&(_, len)| len >= 3);
+        let Some((distance, len)) = best else {
+            bits.symbol(data[at] as u32);
+            at += 1;
+            continue;
+        };
+        let code = LENGTH_BASE.iter().rposition(|&base| base as usize <= len).unwrap();
+        bits.symbol(257 + code as u32);
+        bits.put((len - LENGTH_BASE[code] as usize) as u32, LENGTH_EXTRA[code] as u32);
+        let code = DISTANCE_BASE.iter().rposition(|&base| base as usize <= distance).unwrap();
+        bits.code(code as u32, 5);
+        bits.put((distance - DISTANCE_BASE[code] as usize) as u32, DISTANCE_EXTRA[code] as u32);
+        at += len;
+    }
+    bits.symbol(256);
+    if bits.count > 0 {
+        bits.out.push(bits.buffer as u8);
+    }
+    let mut out = bits.out;
+    out.extend_from_slice(&adler32(data).to_be_bytes());
+    out
+}
@@ -0,0 +1,166 @@
+//! `--record`: every watch sample as one line of JSON, so a session can be
+//! compared with another one later (`diff`) or turned into a report.
+
+use std::fs;
+use std::io::Write;
+use std::path::Path;
+
+use anyhow::{Context, Result};
+
+use crate::export::{ExportSample, Exporter};
+use crate::rollup::RollupWriter;
+use crate::rotate::{RotatingFile, Rotation};
+use crate::units::format_bytes;
+
+/// Writes samples to a recording, replacing whatever the file held.
+pub struct Recorder {
+    writer: RotatingFile,
+    rollups: Option<RollupWriter>,
+}
+
+impl Recorder {
+    pub fn create(path: &Path) -> Result<Self> {
+        Self::rotating(path, Rotation::default())
+    }
+
+    /// Like `create`, but rotates as `rotation` says; the old recording is
+    /// then rotated away instead of replaced. Each file loads on its own.
+    pub fn rotating(path: &Path, rotation: Rotation) -> Result<Self> {
+        let writer = RotatingFile::open(path, rotation, true).with_context(|| format!("Failed to create recording {}", path.display()))?;
+        Ok(Self { writer, rollups: None })
+    }
+
+    /// Adds to the recording at `path` rather than starting it over, as
+    /// `--resume` does; rotates as `rotation` says.
+    pub fn appending(path: &Path, rotation: Rotation) -> Result<Self> {
+        let writer = RotatingFile::open(path, rotation, false).with_context(|| format!("Failed to open recording {}", path.display()))?;
+        Ok(Self { writer, rollups: None })
+    }
+
+    /// Also keeps the minute and hour rollups next to `path` (see `rollup`).
+    pub fn with_rollups(mut self, path: &Path) -> Result<Self> {
+        self.rollups = Some(RollupWriter::open(path)?);
+        Ok(self)
+    }
+}
+
+impl Exporter for Recorder {
+    fn name(&self) -> &str {
+        "record"
+    }
+
+    fn on_sample(&mut self, sample: &ExportSample) -> Result<()> {
+        self.writer.rotate_if_due()?;
+        serde_json::to_writer(&mut self.writer, sample)?;
+        self.writer.write_all(b"\n")?;
+        // A recording cut short by Ctrl-C or a crash should keep what it had.
+        self.writer.flush()?;
+        if let Some(rollups) = &mut self.rollups {
+            rollups.add(sample)?;
+        }
+        Ok(())
+    }
+
+    fn on_shutdown(&mut self) -> Result<()> {
+        if let Some(rollups) = &mut self.rollups {
+            rollups.finish()?;
+        }
+        Ok(self.writer.flush()?)
+    }
+}
+
+/// Reads a recording back, oldest sample first. A torn last line, from a
+/// session killed mid-write, is dropped.
+pub fn load(path: &Path) -> Result<Vec<ExportSample>> {
+    let text = fs::read_to_string(path).with_context(|| format!("Failed to read recording {}", path.display()))?;
+    let lines: Vec<&str> = text.lines().filter(|l| !l.trim().is_empty()).collect();
+    let mut samples = Vec::with_capacity(lines.len());
+    for (n, line) in lines.iter().enumerate() {
+        match serde_json::from_str::<ExportSample>(line) {
+            Ok(sample) => samples.push(sample),
+            Err(_) if n + 1 == lines.len() && n > 0 => break,
+            Err(e) => return Err(e).with_context(|| format!("{} line {} is not a crabtop sample", path.display(), n + 1)),
+        }
+    }
+    samples.sort_by_key(|s| s.timestamp);
+    Ok(samples)
+}
+
+/// The newest `keep` samples of `port`, watched from this machine, in the
+/// recording at `path`, for `--resume`. A recording not made yet has none.
+pub fn recent(path: &Path, port: u16, keep: usize) -> Result<Vec<ExportSample>> {
+    if !path.exists() {
+        return Ok(Vec::new());
+    }
+    let mut samples: Vec<ExportSample> = load(path)?.into_iter().filter(|s| s.port == port && s.host.is_none()).collect();
+    samples.drain(..samples.len().saturating_sub(keep));
+    Ok(samples)
+}
+
+/// Seconds from the first sample to the last.
+pub fn span_secs(samples: &[ExportSample]) -> f64 {
+    match (samples.first(), samples.last()) {
+        (Some(first), Some(last)) => (last.timestamp - first.timestamp).num_milliseconds() as f64 / 1000.0,
+        _ => 0.0,
+    }
+}
+
+/// Means of `metric` over `buckets` slots of `bucket_secs`, counted from the
+/// first sample; `None` for a slot without one.
+pub fn bucketed(samples: &[ExportSample], metric: Metric, bucket_secs: f64, buckets: usize) -> Vec<Option<f64>> {
+    let Some(start) = samples.first().map(|s| s.timestamp) else {
+        return vec![None; buckets];
+    };
+    let mut sums = vec![(0.0, 0u32); buckets];
+    for sample in samples {
+        let Some(value) = metric.value(sample) else {
+            continue;
+        };
+        let offset = (sample.timestamp - start).num_milliseconds() as f64 / 1000.0;
+        let slot = ((offset / bucket_secs).floor() as usize).min(buckets - 1);
+        sums[slot].0 += value;
+        sums[slot].1 += 1;
+    }
+    sums.into_iter().map(|(sum, n)| (n > 0).then(|| sum / n as f64)).collect()
+}
+
+/// The figures a recording is compared and charted on.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+pub enum Metric {
+    Cpu,
+    Memory,
+    Connections,
+}
+
+impl Metric {
+    pub const ALL: [Metric; 3] = [Metric::Cpu, Metric::Memory, Metric::Connections];
+
+    pub fn label(&self) -> &'static str {
+        match self {
+            Metric::Cpu => "CPU",
+            Metric::Memory => "Memory",
+            Metric::Connections => "Connections",
+        }
+    }
+
+    pub fn value(&self, sample: &ExportSample) -> Option<f64> {
+        match self {
+            Metric::Cpu => Some(sample.process.cpu_percent as f64),
+            Metric::Memory => Some(sample.process.memory_bytes as f64),
+            Metric::Connections => sample.connections.map(|c| c as f64),
+        }
+    }
+
+    pub fn format(&self, value: f64) -> String {
+        self.axis_label()(value)
+    }
+
+    /// `format` as a plain function, for chart axes.
+    pub fn axis_label(&self) -> fn(f64) -> String {
+        match self {
+            Metric::Cpu => |v| format!("{:.1}%", v),
+            Metric::Memory => |v| format_bytes(v as u64),
+            Metric::Connections => |v| format!("{:.1}", v),
+        }
+    }
+}
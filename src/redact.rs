@@ -0,0 +1,174 @@
+//! Scrubbing process details before they are sent to an AI backend.
+//!
+//! Two passes: `process` rewrites the structured `ProcessInfo` (secret-looking
+//! arguments, optionally the whole argument list), and `text` replaces
+//! hostnames, the home directory and configured literals in whatever string
+//! is finally sent. `Provider` runs `text` over every message.
+
+use std::cmp::Reverse;
+use std::env;
+
+use sysinfo::System;
+
+use crate::config::RedactConfig;
+use crate::process::ProcessInfo;
+
+pub const REDACTED: &str = "[REDACTED]";
+
+/// Argument and variable names whose values are treated as secrets.
+const SECRET_WORDS: [&str; 8] = ["password", "passwd", "secret", "token", "apikey", "api-key", "api_key", "credential"];
+
+/// The redaction rules in effect for one session.
+#[derive(Debug, Clone)]
+pub struct Redactor {
+    secrets: bool,
+    command_args: bool,
+    /// Longest needle first so overlaps resolve sensibly.
+    replacements: Vec<Replacement>,
+}
+
+#[derive(Debug, Clone)]
+struct Replacement {
+    needle: String,
+    with: String,
+    /// Only where it stands alone: a host called "web" leaves "webpack" be.
+    whole_word: bool,
+}
+
+impl Replacement {
+    fn new(needle: String, with: &str, whole_word: bool) -> Self {
+        Self { needle, with: with.to_string(), whole_word }
+    }
+}
+
+impl Default for Redactor {
+    fn default() -> Self {
+        Self::from_config(&RedactConfig::default())
+    }
+}
+
+impl Redactor {
+    /// Builds the rules, reading the hostname, `$HOME` and this process's own
+    /// secret-looking environment variables once.
+    pub fn from_config(config: &RedactConfig) -> Self {
+        Self::for_host(config, System::host_name())
+    }
+
+    /// `from_config` with `host` in place of this machine's name.
+    pub fn for_host(config: &RedactConfig, host: Option<String>) -> Self {
+        let mut replacements = Vec::new();
+        if config.hostnames {
+            if let Some(host) = host.filter(|h| !h.is_empty() && h != "localhost") {
+                // The short name too: "web-1" from "web-1.prod.example.com".
+                if let Some((short, _)) = host.split_once('.') {
+                    replacements.push(Replacement::new(short.to_string(), "[host]", true));
+                }
+                replacements.push(Replacement::new(host, "[host]", true));
+            }
+        }
+        if config.home {
+            if let Ok(home) = env::var("HOME") {
+                if home.len() > 1 {
+                    replacements.push(Replacement::new(home, "~", false));
+                }
+            }
+        }
+        if config.secrets {
+            // API keys pasted into a command line would otherwise go straight out.
+            for (key, value) in env::vars() {
+                if value.len() >= 8 && is_secret_name(&key) {
+                    replacements.push(Replacement::new(value, REDACTED, false));
+                }
+            }
+        }
+        for literal in config.literals.iter().filter(|l| !l.is_empty()) {
+            replacements.push(Replacement::new(literal.clone(), REDACTED, false));
+        }
+        replacements.sort_by_key(|r| Reverse(r.needle.len()));
+
+        Self {
+            secrets: config.secrets,
+            command_args: config.command_args,
+            replacements,
+        }
+    }
+
+    /// A copy of `info` that is safe to describe to a backend.
+    pub fn process(&self, info: &ProcessInfo) -> ProcessInfo {
+        let mut info = info.clone();
+        if !self.command_args {
+            info.cmdline.truncate(1);
+        } else if self.secrets {
+            info.cmdline = scrub_args(&info.cmdline);
+        }
+        info.cmdline = info.cmdline.iter().map(|arg| self.text(arg)).collect();
+        info.exe = info.exe.map(|p| self.text(&p.to_string_lossy()).into());
+        info.cwd = info.cwd.map(|p| self.text(&p.to_string_lossy()).into());
+        info
+    }
+
+    /// Applies the literal replacements to `text`.
+    pub fn text(&self, text: &str) -> String {
+        let mut out = text.to_string();
+        for r in &self.replacements {
+            if !out.contains(r.needle.as_str()) {
+                continue;
+            }
+            out = if r.whole_word { replace_words(&out, &r.needle, &r.with) } else { out.replace(r.needle.as_str(), &r.with) };
+        }
+        out
+    }
+}
+
+/// `text` with `needle` replaced where no letter, digit or `-` touches it
+/// on either side, as hostnames are spelled.
+fn replace_words(text: &str, needle: &str, with: &str) -> String {
+    let in_word = |c: Option<char>| c.is_some_and(|c| c.is_ascii_alphanumeric() || c == '-');
+    let mut out = String::with_capacity(text.len());
+    let mut last = 0;
+    for (start, _) in text.match_indices(needle) {
+        let end = start + needle.len();
+        if in_word(text[..start].chars().next_back()) || in_word(text[end..].chars().next()) {
+            continue;
+        }
+        out.push_str(&text[last..start]);
+        out.push_str(with);
+        last = end;
+    }
+    out.push_str(&text[last..]);
+    out
+}
+
+/// Whether an argument or variable called `name` probably holds a secret,
+/// e.g. `DB_PASSWORD`, `--api-key` or `AWS_ACCESS_KEY_ID`.
+pub fn is_secret_name(name: &str) -> bool {
+    let name = name.to_ascii_lowercase();
+    SECRET_WORDS.iter().any(|word| name.contains(word))
+        || name.ends_with("_key")
+        || name.contains("_key_")
+        || name == "key"
+}
+
+/// Replaces the values of `--token=x` and `--token x` style secret flags and
+/// `PASSWORD=x` assignments.
+pub fn scrub_args(args: &[String]) -> Vec<String> {
+    let mut out = Vec::with_capacity(args.len());
+    let mut redact_next = false;
+    for arg in args {
+        if redact_next {
+            out.push(REDACTED.to_string());
+            redact_next = false;
+            continue;
+        }
+        if let Some((name, _)) = arg.split_once('=') {
+            if is_secret_name(name.trim_start_matches('-')) {
+                out.push(format!("{}={}", name, REDACTED));
+                continue;
+            }
+        } else if arg.starts_with('-') && is_secret_name(arg.trim_start_matches('-')) {
+            redact_next = true;
+        }
+        out.push(arg.clone());
+    }
+    out
+}
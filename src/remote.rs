@@ -0,0 +1,144 @@
+//! Client side of `serve` mode: a `MetricsSource` that reads from another
+//! machine's `port-inspector serve` API, plus the payloads both sides share.
+
+use std::collections::HashMap;
+
+use anyhow::{anyhow, Context, Result};
+use reqwest::header::AUTHORIZATION;
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+
+use crate::cgroup::CgroupStats;
+use crate::error::Error;
+use crate::port::Listener;
+use crate::process::{ProcessInfo, SystemSnapshot};
+use crate::source::MetricsSource;
+
+/// `GET /api/ports/{port}`: every PID listening on the port.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PortPids {
+    pub port: u16,
+    pub pids: Vec<u32>,
+}
+
+/// `GET /api/process/{pid}/metrics`: one sample of the process and its host.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Metrics {
+    pub process: ProcessInfo,
+    pub system: SystemSnapshot,
+    pub cgroup: Option<CgroupStats>,
+}
+
+/// Body of every non-2xx response.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ApiError {
+    pub error: String,
+}
+
+/// A `MetricsSource` backed by a remote `serve` instance.
+///
+/// The port lookup and listener lists are fetched once on `connect`; each
+/// `sample` is one `/metrics` request, whose system and cgroup figures are
+/// kept for the synchronous `system`/`cgroup` calls that follow it.
+#[derive(Debug, Clone)]
+pub struct RemoteSource {
+    client: reqwest::Client,
+    base: String,
+    token: String,
+    port: u16,
+    pids: Vec<u32>,
+    listeners: HashMap<u32, Vec<Listener>>,
+    system: Option<SystemSnapshot>,
+    cgroups: HashMap<u32, CgroupStats>,
+}
+
+impl RemoteSource {
+    /// Resolves `port` on the server at `addr` (`host:port`, or a full
+    /// `http(s)://` URL) and looks up the listeners of each PID found.
+    pub async fn connect(addr: &str, token: String, port: u16) -> Result<Self> {
+        let base = if addr.contains("://") {
+            addr.trim_end_matches('/').to_string()
+        } else {
+            format!("http://{}", addr)
+        };
+        let mut source = Self {
+            client: reqwest::Client::new(),
+            base,
+            token,
+            port,
+            pids: Vec::new(),
+            listeners: HashMap::new(),
+            system: None,
+            cgroups: HashMap::new(),
+        };
+
+        let found: PortPids = source.get(&format!("/api/ports/{}", port)).await?;
+        for &pid in &found.pids {
+            let listeners = source.get(&format!("/api/process/{}/listeners", pid)).await?;
+            source.listeners.insert(pid, listeners);
+        }
+        source.pids = found.pids;
+        Ok(source)
+    }
+
+    async fn get<T: DeserializeOwned>(&self, path: &str) -> Result<T> {
+        let url = format!("{}{}", self.base, path);
+        let resp = self
+            .client
+            .get(&url)
+            .header(AUTHORIZATION, format!("Bearer {}", self.token))
+            .send()
+            .await
+            .with_context(|| format!("Failed to reach {}", self.base))?;
+
+        let status = resp.status();
+        if !status.is_success() {
+            let message = resp
+                .json::<ApiError>()
+                .await
+                .map(|e| e.error)
+                .unwrap_or_else(|_| status.to_string());
+            return Err(Error::ApiError {
+                service: url,
+                status: status.as_u16(),
+                message,
+            }
+            .into());
+        }
+        resp.json().await.with_context(|| format!("Unexpected response from {}", url))
+    }
+}
+
+impl MetricsSource for RemoteSource {
+    fn resolve_pids(&self, port: u16) -> Result<Vec<u32>> {
+        if port != self.port || self.pids.is_empty() {
+            return Err(anyhow::Error::from(Error::PortNotFound { port })
+                .context(format!("No process found listening on port {} at {}", port, self.base)));
+        }
+        Ok(self.pids.clone())
+    }
+
+    async fn sample(&mut self, pid: u32) -> Result<ProcessInfo> {
+        let metrics: Metrics = self.get(&format!("/api/process/{}/metrics", pid)).await?;
+        self.system = Some(metrics.system);
+        match metrics.cgroup {
+            Some(cgroup) => self.cgroups.insert(pid, cgroup),
+            None => self.cgroups.remove(&pid),
+        };
+        Ok(metrics.process)
+    }
+
+    fn system(&mut self) -> Result<SystemSnapshot> {
+        self.system
+            .clone()
+            .ok_or_else(|| anyhow!("No sample fetched from {} yet", self.base))
+    }
+
+    fn listeners(&mut self, pid: u32) -> Result<Vec<Listener>> {
+        Ok(self.listeners.get(&pid).cloned().unwrap_or_default())
+    }
+
+    fn cgroup(&mut self, pid: u32) -> Option<CgroupStats> {
+        self.cgroups.get(&pid).cloned()
+    }
+}
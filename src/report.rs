@@ -0,0 +1,303 @@
+//! `report`: a `--record` recording turned into one standalone HTML or
+//! Markdown file, with a summary table and a chart per metric, for pasting
+//! into an incident retro.
+
+use std::fmt::Write as _;
+use std::fs;
+use std::path::Path;
+use std::time::Duration;
+
+use anyhow::Result;
+use chrono::{DateTime, Local};
+
+use crate::baseline::Summary;
+use crate::chart::{self, Chart, Series};
+use crate::diff::{sparkline, SPARK_WIDTH};
+use crate::export::ExportSample;
+use crate::recording::{self, Metric};
+use crate::rollup::{self, Bucket, Resolution};
+use crate::units::format_duration;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ReportFormat {
+    Html,
+    Markdown,
+}
+
+impl ReportFormat {
+    /// Markdown for `.md` and `.markdown`, HTML otherwise.
+    pub fn from_path(path: &Path) -> Self {
+        match path.extension().and_then(|e| e.to_str()) {
+            Some(ext) if ext.eq_ignore_ascii_case("md") || ext.eq_ignore_ascii_case("markdown") => ReportFormat::Markdown,
+            _ => ReportFormat::Html,
+        }
+    }
+}
+
+/// Recordings larger than this are reported from their rollups, when they have them.
+pub const RAW_LIMIT: u64 = 16_000_000;
+/// Buckets a report takes from the minute rollup before it uses the hour one.
+pub const MAX_BUCKETS: usize = 20_000;
+
+/// The samples of one watched process: a host and port.
+#[derive(Debug, Clone)]
+pub struct Section {
+    pub host: Option<String>,
+    pub port: u16,
+    /// The recorded samples, or one per rollup bucket at its average.
+    pub samples: Vec<ExportSample>,
+    /// Set when `samples` come from a rollup.
+    pub rollup: Option<Rolled>,
+}
+
+/// What a section drawn from rollups needs besides the averages.
+#[derive(Debug, Clone)]
+pub struct Rolled {
+    pub resolution: Resolution,
+    /// Each bucket's maxima, in the same order as the section's samples.
+    pub peaks: Vec<ExportSample>,
+    /// Raw samples the buckets cover.
+    pub covers: u64,
+}
+
+impl Section {
+    pub fn heading(&self) -> String {
+        let last = self.samples.last();
+        let name = last.map_or("?", |s| s.process.name.as_str());
+        let pids: Vec<String> = last.map(|s| s.pids.iter().map(|p| p.to_string()).collect()).unwrap_or_default();
+        let label = if pids.len() == 1 { "PID" } else { "PIDs" };
+        match &self.host {
+            Some(host) => format!("{} ({} {}) on {} port {}", name, label, pids.join(", "), host, self.port),
+            None => format!("{} ({} {}) on port {}", name, label, pids.join(", "), self.port),
+        }
+    }
+
+    /// e.g. `2026-10-14 14:00:00 – 16:00:00 (2h 0m), 7200 samples`.
+    pub fn period(&self) -> String {
+        let (Some(first), Some(last)) = (self.samples.first(), self.samples.last()) else {
+            return "no samples".to_string();
+        };
+        let end = if first.timestamp.date_naive() == last.timestamp.date_naive() {
+            last.timestamp.format("%H:%M:%S")
+        } else {
+            last.timestamp.format("%Y-%m-%d %H:%M:%S")
+        };
+        let count = match &self.rollup {
+            Some(rolled) => format!("{} {} rollups of {} samples", self.samples.len(), rolled.resolution.label(), rolled.covers),
+            None => format!("{} samples", self.samples.len()),
+        };
+        format!(
+            "{} – {} ({}), {}",
+            first.timestamp.format("%Y-%m-%d %H:%M:%S"),
+            end,
+            format_duration(recording::span_secs(&self.samples) as u64),
+            count
+        )
+    }
+
+    /// Average, p95 and peak of each metric the samples have, with when it
+    /// peaked. From rollups, the average and p95 are of the bucket averages
+    /// and the peak is the highest bucket maximum.
+    pub fn summaries(&self) -> Vec<(Metric, Summary, DateTime<Local>)> {
+        Metric::ALL
+            .iter()
+            .filter_map(|&metric| {
+                let values: Vec<(f64, DateTime<Local>)> =
+                    self.samples.iter().filter_map(|s| Some((metric.value(s)?, s.timestamp))).collect();
+                let mut summary = Summary::of(&values.iter().map(|v| v.0).collect::<Vec<_>>())?;
+                let mut peak_at = values.iter().find(|v| v.0 == summary.max)?.1;
+                if let Some(rolled) = &self.rollup {
+                    let peak = rolled.peaks.iter().filter_map(|s| Some((metric.value(s)?, s.timestamp))).max_by(|a, b| a.0.total_cmp(&b.0));
+                    if let Some((max, at)) = peak.filter(|p| p.0 > summary.max) {
+                        (summary.max, peak_at) = (max, at);
+                    }
+                }
+                Some((metric, summary, peak_at))
+            })
+            .collect()
+    }
+}
+
+/// Splits `samples` by host and port, keeping `port` only when given and the
+/// `last` stretch before the newest sample when given.
+pub fn sections(samples: &[ExportSample], port: Option<u16>, last: Option<Duration>) -> Vec<Section> {
+    let newest = samples.iter().map(|s| s.timestamp).max();
+    let cutoff = newest.zip(last).and_then(|(newest, last)| Some(newest - chrono::Duration::from_std(last).ok()?));
+    let mut sections: Vec<Section> = Vec::new();
+    for sample in samples {
+        if port.is_some_and(|p| p != sample.port) || cutoff.is_some_and(|c| sample.timestamp < c) {
+            continue;
+        }
+        match sections.iter_mut().find(|s| s.port == sample.port && s.host == sample.host) {
+            Some(section) => section.samples.push(sample.clone()),
+            None => sections.push(Section {
+                host: sample.host.clone(),
+                port: sample.port,
+                samples: vec![sample.clone()],
+                rollup: None,
+            }),
+        }
+    }
+    sections
+}
+
+/// `sections` for rollup buckets, as read by `rollup::load`.
+pub fn rollup_sections(buckets: &[Bucket], resolution: Resolution, port: Option<u16>, last: Option<Duration>) -> Vec<Section> {
+    let averages: Vec<ExportSample> = buckets.iter().map(|b| b.as_sample(|s| s.avg)).collect();
+    let mut sections = sections(&averages, port, last);
+    for section in &mut sections {
+        let first = section.samples.first().map(|s| s.timestamp);
+        let picked: Vec<&Bucket> = buckets
+            .iter()
+            .filter(|b| b.port == section.port && b.host == section.host && first.is_some_and(|first| b.start >= first))
+            .collect();
+        section.rollup = Some(Rolled {
+            resolution,
+            peaks: picked.iter().map(|b| b.as_sample(|s| s.max)).collect(),
+            covers: picked.iter().map(|b| b.samples).sum(),
+        });
+    }
+    sections
+}
+
+/// The sections of the recording at `path`, from its samples or a rollup.
+/// Without a `resolution`, small recordings are read whole; larger ones,
+/// or ones rotated away, from the minute rollup, or the hour one when the
+/// stretch asked for holds more than `MAX_BUCKETS` minutes.
+pub fn load_sections(path: &Path, resolution: Option<Resolution>, port: Option<u16>, last: Option<Duration>) -> Result<Vec<Section>> {
+    let resolution = match resolution {
+        Some(resolution) => resolution,
+        None => {
+            let small = fs::metadata(path).is_ok_and(|m| m.len() <= RAW_LIMIT);
+            if small || !Resolution::Minute.path(path).exists() {
+                Resolution::Raw
+            } else {
+                let buckets = rollup::load(&Resolution::Minute.path(path))?;
+                let sections = rollup_sections(&buckets, Resolution::Minute, port, last);
+                let too_many = sections.iter().any(|s| s.samples.len() > MAX_BUCKETS);
+                if !too_many || !Resolution::Hour.path(path).exists() {
+                    return Ok(sections);
+                }
+                Resolution::Hour
+            }
+        }
+    };
+    Ok(match resolution {
+        Resolution::Raw => sections(&recording::load(path)?, port, last),
+        rolled => rollup_sections(&rollup::load(&rolled.path(path))?, rolled, port, last),
+    })
+}
+
+pub fn render(sections: &[Section], title: &str, format: ReportFormat) -> String {
+    match format {
+        ReportFormat::Html => render_html(sections, title),
+        ReportFormat::Markdown => render_markdown(sections, title),
+    }
+}
+
+fn render_html(sections: &[Section], title: &str) -> String {
+    let mut html = format!(
+        "<!DOCTYPE html>\n<html><head><meta charset=\"utf-8\"><title>{t}</title>\n\
+         <style>body{{font-family:sans-serif;margin:2em}}table{{border-collapse:collapse}}\
+         td,th{{border:1px solid #ccc;padding:4px 10px;text-align:right}}th:first-child,td:first-child{{text-align:left}}</style>\n\
+         </head><body>\n<h1>{t}</h1>\n<p>Generated {}</p>\n",
+        Local::now().format("%Y-%m-%d %H:%M:%S"),
+        t = chart::escape(title)
+    );
+    for section in sections {
+        let _ = write!(
+            html,
+            "<h2>{}</h2>\n<p>{}</p>\n<table><tr><th>Metric</th><th>Avg</th><th>p95</th><th>Max</th><th>Peak at</th></tr>\n",
+            chart::escape(&section.heading()),
+            section.period()
+        );
+        let summaries = section.summaries();
+        for (metric, s, peak_at) in &summaries {
+            let _ = writeln!(
+                html,
+                "<tr><td>{}</td><td>{}</td><td>{}</td><td>{}</td><td>{}</td></tr>",
+                metric.label(),
+                metric.format(s.avg),
+                metric.format(s.p95),
+                metric.format(s.max),
+                peak_at.format("%H:%M:%S")
+            );
+        }
+        html.push_str("</table>\n");
+        for (metric, _, _) in &summaries {
+            html.push_str(&history_chart(std::slice::from_ref(section), *metric, 900.0, 260.0));
+        }
+    }
+    html.push_str("</body></html>\n");
+    html
+}
+
+/// `metric` over time as an SVG line chart, one line per section.
+pub fn history_chart(sections: &[Section], metric: Metric, width: f64, height: f64) -> String {
+    with_history_chart(sections, metric, |chart| chart.render(width, height))
+}
+
+/// The same chart written to `path`, as PNG or SVG by its extension.
+pub fn write_history_chart(path: &Path, sections: &[Section], metric: Metric, width: u32, height: u32) -> Result<()> {
+    with_history_chart(sections, metric, |chart| chart::write_chart(path, chart, width, height))
+}
+
+fn with_history_chart<T>(sections: &[Section], metric: Metric, draw: impl FnOnce(&Chart<'_>) -> T) -> T {
+    let series: Vec<Series> = sections
+        .iter()
+        .enumerate()
+        .map(|(n, section)| Series {
+            label: section.heading(),
+            color: chart::PALETTE[n % chart::PALETTE.len()],
+            points: section
+                .samples
+                .iter()
+                .filter_map(|s| Some((s.timestamp.timestamp() as f64, metric.value(s)?)))
+                .collect(),
+        })
+        .collect();
+    let title = match sections {
+        [section] => format!("{}: {}", metric.label(), section.heading()),
+        _ => metric.label().to_string(),
+    };
+    draw(&Chart {
+        title: &title,
+        series: &series,
+        x_label: chart::clock_label,
+        y_label: metric.axis_label(),
+    })
+}
+
+// Markdown renderers don't agree on inline SVG, so the charts become sparklines.
+fn render_markdown(sections: &[Section], title: &str) -> String {
+    let mut md = format!("# {}\n\nGenerated {}\n", title, Local::now().format("%Y-%m-%d %H:%M:%S"));
+    for section in sections {
+        let _ = write!(
+            md,
+            "\n## {}\n\n{}\n\n| Metric | Avg | p95 | Max | Peak at |\n|---|--:|--:|--:|---|\n",
+            section.heading(),
+            section.period()
+        );
+        let summaries = section.summaries();
+        for (metric, s, peak_at) in &summaries {
+            let _ = writeln!(
+                md,
+                "| {} | {} | {} | {} | {} |",
+                metric.label(),
+                metric.format(s.avg),
+                metric.format(s.p95),
+                metric.format(s.max),
+                peak_at.format("%H:%M:%S")
+            );
+        }
+        let buckets = section.samples.len().clamp(1, SPARK_WIDTH);
+        let bucket = (recording::span_secs(&section.samples) / buckets as f64).max(0.001);
+        md.push_str("\n```text\n");
+        for (metric, s, _) in &summaries {
+            let values = recording::bucketed(&section.samples, *metric, bucket, buckets);
+            let _ = writeln!(md, "{:<12} {}  max {}", metric.label(), sparkline(&values, s.max), metric.format(s.max));
+        }
+        md.push_str("```\n");
+    }
+    md
+}
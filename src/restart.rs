@@ -0,0 +1,119 @@
+//! `restart`: working out how the process behind a port is meant to be
+//! restarted, and doing it. Containers, then systemd services, then
+//! supervisord programs; a process none of them own just gets a SIGHUP.
+
+use std::fmt;
+
+use anyhow::{anyhow, Context, Result};
+
+use crate::docker::{container_id_from_cgroup, publishes_port};
+use crate::exec::Runner;
+use crate::systemd::unit_from_cgroup;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum RestartPlan {
+    Container { id: String },
+    Systemd { unit: String },
+    Supervisor { program: String },
+    /// Nothing manages the process; most daemons reload their config on HUP.
+    Sighup { pid: u32 },
+}
+
+impl RestartPlan {
+    /// The command that carries the plan out.
+    pub fn command(&self) -> Vec<String> {
+        let (program, action, target) = match self {
+            RestartPlan::Container { id } => ("docker", "restart", id.clone()),
+            RestartPlan::Systemd { unit } => ("systemctl", "restart", unit.clone()),
+            RestartPlan::Supervisor { program } => ("supervisorctl", "restart", program.clone()),
+            RestartPlan::Sighup { pid } => ("kill", "-HUP", pid.to_string()),
+        };
+        vec![program.to_string(), action.to_string(), target]
+    }
+}
+
+impl fmt::Display for RestartPlan {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            RestartPlan::Container { id } => write!(f, "docker container {}", &id[..id.len().min(12)]),
+            RestartPlan::Systemd { unit } => write!(f, "systemd unit {}", unit),
+            RestartPlan::Supervisor { program } => write!(f, "supervisord program {}", program),
+            RestartPlan::Sighup { .. } => write!(f, "not managed by docker, systemd or supervisord"),
+        }
+    }
+}
+
+/// Decides how to restart `pid`, which listens on `port`.
+pub fn plan(runner: &Runner, port: u16, pid: u32) -> RestartPlan {
+    let read = |path: String| {
+        runner
+            .output("cat", &[&path])
+            .ok()
+            .filter(|out| out.status.success())
+            .map(|out| String::from_utf8_lossy(&out.stdout).into_owned())
+    };
+    let cgroup = read(format!("/proc/{}/cgroup", pid)).unwrap_or_default();
+    let comm = read(format!("/proc/{}/comm", pid)).unwrap_or_default();
+
+    if comm.trim() == "docker-proxy" {
+        if let Some(id) = container_publishing(runner, port) {
+            return RestartPlan::Container { id };
+        }
+    }
+    if let Some(id) = container_id_from_cgroup(&cgroup) {
+        return RestartPlan::Container { id };
+    }
+    if let Some(unit) = unit_from_cgroup(&cgroup) {
+        // supervisord itself is usually a service; its programs are what we want.
+        if let Some(program) = supervisor_program(runner, pid) {
+            return RestartPlan::Supervisor { program };
+        }
+        return RestartPlan::Systemd { unit };
+    }
+    match supervisor_program(runner, pid) {
+        Some(program) => RestartPlan::Supervisor { program },
+        None => RestartPlan::Sighup { pid },
+    }
+}
+
+fn container_publishing(runner: &Runner, port: u16) -> Option<String> {
+    let out = runner.output("docker", &["ps", "--no-trunc", "--format", "{{.ID}}\t{{.Ports}}"]).ok()?;
+    String::from_utf8_lossy(&out.stdout).lines().find_map(|line| {
+        let (id, ports) = line.split_once('\t')?;
+        publishes_port(ports, port).then(|| id.to_string())
+    })
+}
+
+fn supervisor_program(runner: &Runner, pid: u32) -> Option<String> {
+    // `supervisorctl status` exits non-zero when any program is down, so the status is ignored.
+    let out = runner.output("supervisorctl", &["status"]).ok()?;
+    parse_supervisor_status(&String::from_utf8_lossy(&out.stdout), pid)
+}
+
+/// The program running as `pid` in `supervisorctl status` output, e.g.
+/// `api:api_00   RUNNING   pid 4242, uptime 1:02:03`.
+pub fn parse_supervisor_status(text: &str, pid: u32) -> Option<String> {
+    text.lines().find_map(|line| {
+        let (name, rest) = line.split_once(char::is_whitespace)?;
+        let running = rest.split_once("pid ")?.1.split(',').next()?.trim().parse::<u32>().ok()?;
+        (running == pid).then(|| name.to_string())
+    })
+}
+
+/// Runs the plan's command.
+pub fn execute(runner: &Runner, plan: &RestartPlan) -> Result<()> {
+    let command = plan.command();
+    let args: Vec<&str> = command[1..].iter().map(String::as_str).collect();
+    let out = runner
+        .output(&command[0], &args)
+        .with_context(|| format!("Failed to run {}{}", command[0], runner.describe()))?;
+    if !out.status.success() {
+        return Err(anyhow!(
+            "`{}` failed{}: {}",
+            command.join(" "),
+            runner.describe(),
+            String::from_utf8_lossy(&out.stderr).trim()
+        ));
+    }
+    Ok(())
+}
@@ -0,0 +1,377 @@
+//! Minute and hour rollups of a `--record` recording, so weeks of daemon
+//! data stay quick to report on.
+//!
+//! Next to `samples.jsonl` the recorder keeps `samples.1m.jsonl` and
+//! `samples.1h.jsonl`: one line per host, port and minute (or hour) with the
+//! minimum, average and maximum of CPU, memory and connections. The minute
+//! buckets are built from the samples and the hour buckets from the minute
+//! ones. Rollup files are appended to, never replaced or rotated, so they
+//! outlive the raw recording; a day is 1440 minute lines and 24 hour lines.
+
+use std::fs;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+
+use anyhow::{Context, Result};
+use chrono::{DateTime, Local, TimeZone};
+use serde::{Deserialize, Serialize};
+
+use crate::export::ExportSample;
+use crate::process::ProcessInfo;
+use crate::recording::Metric;
+use crate::rotate::{RotatingFile, Rotation};
+
+/// What a report is drawn from.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, clap::ValueEnum)]
+pub enum Resolution {
+    /// Every sample in the recording.
+    Raw,
+    #[value(name = "1m")]
+    Minute,
+    #[value(name = "1h")]
+    Hour,
+}
+
+impl Resolution {
+    /// The rollups the recorder writes, finest first.
+    pub const ROLLUPS: [Resolution; 2] = [Resolution::Minute, Resolution::Hour];
+
+    pub fn label(self) -> &'static str {
+        match self {
+            Resolution::Raw => "raw",
+            Resolution::Minute => "1m",
+            Resolution::Hour => "1h",
+        }
+    }
+
+    /// Seconds per bucket; `None` for raw samples.
+    pub fn bucket_secs(self) -> Option<i64> {
+        match self {
+            Resolution::Raw => None,
+            Resolution::Minute => Some(60),
+            Resolution::Hour => Some(3600),
+        }
+    }
+
+    /// `samples.jsonl` → `samples.1m.jsonl`; the recording itself for raw.
+    pub fn path(self, recording: &Path) -> PathBuf {
+        if self == Resolution::Raw {
+            return recording.to_path_buf();
+        }
+        let stem = recording.file_stem().map(|s| s.to_string_lossy().into_owned()).unwrap_or_default();
+        let name = match recording.extension() {
+            Some(ext) => format!("{}.{}.{}", stem, self.label(), ext.to_string_lossy()),
+            None => format!("{}.{}", stem, self.label()),
+        };
+        recording.with_file_name(name)
+    }
+}
+
+/// The smallest, mean and largest value over a bucket.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct Stat {
+    pub min: f64,
+    pub avg: f64,
+    pub max: f64,
+}
+
+/// One host and port over a minute or an hour.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Bucket {
+    pub start: DateTime<Local>,
+    pub host: Option<String>,
+    pub port: u16,
+    /// The process's name and PIDs as of the bucket's last sample.
+    pub name: String,
+    pub pids: Vec<u32>,
+    /// Raw samples the bucket covers.
+    pub samples: u64,
+    pub cpu: Stat,
+    pub memory: Stat,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub connections: Option<Stat>,
+}
+
+impl Bucket {
+    pub fn stat(&self, metric: Metric) -> Option<&Stat> {
+        match metric {
+            Metric::Cpu => Some(&self.cpu),
+            Metric::Memory => Some(&self.memory),
+            Metric::Connections => self.connections.as_ref(),
+        }
+    }
+
+    /// The bucket as a sample at its start, with each figure `pick`ed from
+    /// its stat, so reports and charts can draw it like a recording.
+    pub fn as_sample(&self, pick: fn(&Stat) -> f64) -> ExportSample {
+        ExportSample {
+            timestamp: self.start,
+            host: self.host.clone(),
+            port: self.port,
+            pids: self.pids.clone(),
+            process: ProcessInfo {
+                name: self.name.clone(),
+                cpu_percent: pick(&self.cpu) as f32,
+                memory_bytes: pick(&self.memory) as u64,
+                ..Default::default()
+            },
+            connections: self.connections.as_ref().map(|c| pick(c).round() as usize),
+            annotations: Vec::new(),
+        }
+    }
+
+    /// Folds in another bucket for the same slot, as left by a restart.
+    fn merge(&mut self, other: &Bucket) {
+        let weights = (self.samples as f64, other.samples as f64);
+        self.cpu = combine(self.cpu, other.cpu, weights);
+        self.memory = combine(self.memory, other.memory, weights);
+        self.connections = match (self.connections, other.connections) {
+            (Some(a), Some(b)) => Some(combine(a, b, weights)),
+            (a, b) => a.or(b),
+        };
+        self.samples += other.samples;
+        self.name.clone_from(&other.name);
+        self.pids.clone_from(&other.pids);
+    }
+}
+
+fn combine(a: Stat, b: Stat, (wa, wb): (f64, f64)) -> Stat {
+    let total = (wa + wb).max(1.0);
+    Stat {
+        min: a.min.min(b.min),
+        avg: (a.avg * wa + b.avg * wb) / total,
+        max: a.max.max(b.max),
+    }
+}
+
+/// Running min, sum and max of one figure.
+#[derive(Debug, Clone, Copy, Default)]
+struct Acc {
+    min: f64,
+    max: f64,
+    sum: f64,
+    weight: f64,
+}
+
+impl Acc {
+    fn add(&mut self, stat: Stat, weight: f64) {
+        if self.weight == 0.0 {
+            (self.min, self.max) = (stat.min, stat.max);
+        } else {
+            (self.min, self.max) = (self.min.min(stat.min), self.max.max(stat.max));
+        }
+        self.sum += stat.avg * weight;
+        self.weight += weight;
+    }
+
+    fn stat(&self) -> Option<Stat> {
+        (self.weight > 0.0).then(|| Stat {
+            min: self.min,
+            avg: self.sum / self.weight,
+            max: self.max,
+        })
+    }
+}
+
+fn point(value: f64) -> Stat {
+    Stat {
+        min: value,
+        avg: value,
+        max: value,
+    }
+}
+
+/// A bucket still taking samples.
+#[derive(Debug, Clone)]
+struct Open {
+    resolution: Resolution,
+    start: i64,
+    host: Option<String>,
+    port: u16,
+    name: String,
+    pids: Vec<u32>,
+    samples: u64,
+    cpu: Acc,
+    memory: Acc,
+    connections: Acc,
+}
+
+impl Open {
+    fn close(self) -> Bucket {
+        Bucket {
+            start: Local.timestamp_opt(self.start, 0).single().unwrap_or_else(Local::now),
+            host: self.host,
+            port: self.port,
+            name: self.name,
+            pids: self.pids,
+            samples: self.samples,
+            cpu: self.cpu.stat().unwrap_or(point(0.0)),
+            memory: self.memory.stat().unwrap_or(point(0.0)),
+            connections: self.connections.stat(),
+        }
+    }
+}
+
+/// Turns samples into minute buckets, and minute buckets into hour ones.
+#[derive(Debug, Clone, Default)]
+pub struct Rollups {
+    open: Vec<Open>,
+}
+
+impl Rollups {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Takes a sample in; returns the buckets it closed.
+    pub fn add(&mut self, sample: &ExportSample) -> Vec<(Resolution, Bucket)> {
+        let bucket = Bucket {
+            start: sample.timestamp,
+            host: sample.host.clone(),
+            port: sample.port,
+            name: sample.process.name.clone(),
+            pids: sample.pids.clone(),
+            samples: 1,
+            cpu: point(sample.process.cpu_percent as f64),
+            memory: point(sample.process.memory_bytes as f64),
+            connections: sample.connections.map(|c| point(c as f64)),
+        };
+        let mut closed = Vec::new();
+        self.feed(Resolution::Minute, &bucket, &mut closed);
+        closed
+    }
+
+    /// Closes every open bucket, as at shutdown.
+    pub fn finish(&mut self) -> Vec<(Resolution, Bucket)> {
+        let mut closed = Vec::new();
+        for resolution in Resolution::ROLLUPS {
+            let (done, open): (Vec<Open>, Vec<Open>) = self.open.drain(..).partition(|o| o.resolution == resolution);
+            self.open = open;
+            for open in done {
+                self.closed(resolution, open.close(), &mut closed);
+            }
+        }
+        closed
+    }
+
+    fn feed(&mut self, resolution: Resolution, bucket: &Bucket, closed: &mut Vec<(Resolution, Bucket)>) {
+        let secs = resolution.bucket_secs().unwrap_or(1);
+        let start = bucket.start.timestamp().div_euclid(secs) * secs;
+        let same = |o: &Open| o.resolution == resolution && o.host == bucket.host && o.port == bucket.port;
+        if let Some(at) = self.open.iter().position(|o| same(o) && o.start != start) {
+            let done = self.open.remove(at);
+            self.closed(resolution, done.close(), closed);
+        }
+        let index = match self.open.iter().position(same) {
+            Some(index) => index,
+            None => {
+                self.open.push(Open {
+                    resolution,
+                    start,
+                    host: bucket.host.clone(),
+                    port: bucket.port,
+                    name: String::new(),
+                    pids: Vec::new(),
+                    samples: 0,
+                    cpu: Acc::default(),
+                    memory: Acc::default(),
+                    connections: Acc::default(),
+                });
+                self.open.len() - 1
+            }
+        };
+        let open = &mut self.open[index];
+        let weight = bucket.samples as f64;
+        open.cpu.add(bucket.cpu, weight);
+        open.memory.add(bucket.memory, weight);
+        if let Some(connections) = bucket.connections {
+            open.connections.add(connections, weight);
+        }
+        open.samples += bucket.samples;
+        open.name.clone_from(&bucket.name);
+        open.pids.clone_from(&bucket.pids);
+    }
+
+    /// Hands a finished bucket out, and feeds a minute into its hour.
+    fn closed(&mut self, resolution: Resolution, bucket: Bucket, closed: &mut Vec<(Resolution, Bucket)>) {
+        if resolution == Resolution::Minute {
+            self.feed(Resolution::Hour, &bucket, closed);
+        }
+        closed.push((resolution, bucket));
+    }
+}
+
+/// Appends closed buckets to the rollup files next to a recording.
+pub struct RollupWriter {
+    rollups: Rollups,
+    files: Vec<(Resolution, RotatingFile)>,
+}
+
+impl RollupWriter {
+    pub fn open(recording: &Path) -> Result<Self> {
+        let files = Resolution::ROLLUPS
+            .iter()
+            .map(|&resolution| {
+                let path = resolution.path(recording);
+                let file = RotatingFile::open(&path, Rotation::default(), false)
+                    .with_context(|| format!("Failed to open rollup {}", path.display()))?;
+                Ok((resolution, file))
+            })
+            .collect::<Result<Vec<_>>>()?;
+        Ok(Self {
+            rollups: Rollups::new(),
+            files,
+        })
+    }
+
+    pub fn add(&mut self, sample: &ExportSample) -> Result<()> {
+        let closed = self.rollups.add(sample);
+        self.write(closed)
+    }
+
+    pub fn finish(&mut self) -> Result<()> {
+        let closed = self.rollups.finish();
+        self.write(closed)
+    }
+
+    fn write(&mut self, closed: Vec<(Resolution, Bucket)>) -> Result<()> {
+        if closed.is_empty() {
+            return Ok(());
+        }
+        for (resolution, bucket) in closed {
+            if let Some((_, file)) = self.files.iter_mut().find(|(r, _)| *r == resolution) {
+                serde_json::to_writer(&mut *file, &bucket)?;
+                file.write_all(b"\n")?;
+            }
+        }
+        for (_, file) in &mut self.files {
+            file.flush()?;
+        }
+        Ok(())
+    }
+}
+
+/// Reads a rollup file back, oldest bucket first. Buckets for the same
+/// slot, written by runs either side of a restart, are merged; a torn last
+/// line is dropped.
+pub fn load(path: &Path) -> Result<Vec<Bucket>> {
+    let text = fs::read_to_string(path).with_context(|| format!("Failed to read rollup {}", path.display()))?;
+    let lines: Vec<&str> = text.lines().filter(|l| !l.trim().is_empty()).collect();
+    let mut buckets: Vec<Bucket> = Vec::with_capacity(lines.len());
+    for (n, line) in lines.iter().enumerate() {
+        match serde_json::from_str::<Bucket>(line) {
+            Ok(bucket) => buckets.push(bucket),
+            Err(_) if n + 1 == lines.len() && n > 0 => break,
+            Err(e) => return Err(e).with_context(|| format!("{} line {} is not a crabtop rollup", path.display(), n + 1)),
+        }
+    }
+    buckets.sort_by(|a, b| (a.start, &a.host, a.port).cmp(&(b.start, &b.host, b.port)));
+    let mut merged: Vec<Bucket> = Vec::with_capacity(buckets.len());
+    for bucket in buckets {
+        match merged.last_mut() {
+            Some(last) if (last.start, &last.host, last.port) == (bucket.start, &bucket.host, bucket.port) => last.merge(&bucket),
+            _ => merged.push(bucket),
+        }
+    }
+    Ok(merged)
+}
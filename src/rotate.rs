@@ -0,0 +1,211 @@
+//! Rotation and retention for the files an unattended crabtop appends to
+//! (`--record`, the CSV exporter), so a daemon left running for weeks
+//! doesn't fill the disk.
+//!
+//! A file that grows past `max_size`, or has been written to for longer
+//! than `max_age`, is renamed with the time it was rotated
+//! (`samples.csv` → `samples-20240301-140200.csv`) and a fresh one started.
+//! Rotated files older than `retention` are deleted.
+
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, BufWriter, Write};
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime};
+
+use anyhow::{Context, Result};
+use chrono::{DateTime, Local};
+
+/// When to rotate and how long to keep rotated files; all `None` writes
+/// one file forever.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct Rotation {
+    pub max_size: Option<u64>,
+    pub max_age: Option<Duration>,
+    pub retention: Option<Duration>,
+}
+
+impl Rotation {
+    pub fn is_enabled(&self) -> bool {
+        self.max_size.is_some() || self.max_age.is_some() || self.retention.is_some()
+    }
+
+    /// `max_age`, or a day when only a retention is given: rotated files
+    /// are the only ones retention can delete.
+    fn age_limit(&self) -> Option<Duration> {
+        self.max_age
+            .or_else(|| (self.retention.is_some() && self.max_size.is_none()).then(|| Duration::from_secs(86_400)))
+    }
+}
+
+/// Parses `100MB`, `1.5G`, `512KB` or a bare number of bytes (powers of 1000,
+/// like the sizes crabtop prints by default); `KiB`, `MiB`… are powers of 1024.
+pub fn parse_size(text: &str) -> Result<u64, String> {
+    let text = text.trim();
+    let at = text.find(|c: char| !c.is_ascii_digit() && c != '.').unwrap_or(text.len());
+    let (number, unit) = text.split_at(at);
+    let value: f64 = number.parse().map_err(|_| format!("invalid size: {}", text))?;
+    let factor = match unit.trim().to_ascii_uppercase().trim_end_matches('B') {
+        "" => 1.0,
+        "K" => 1e3,
+        "M" => 1e6,
+        "G" => 1e9,
+        "T" => 1e12,
+        "KI" => 1024.0,
+        "MI" => 1024.0 * 1024.0,
+        "GI" => 1024.0 * 1024.0 * 1024.0,
+        "TI" => 1024.0 * 1024.0 * 1024.0 * 1024.0,
+        _ => return Err(format!("unknown unit in {} (use B, KB, MB, GB, TB or KiB, MiB…)", text)),
+    };
+    Ok((value * factor) as u64)
+}
+
+/// `samples.csv` rotated at `at`: `samples-20240301-140200.csv`.
+pub fn rotated_name(path: &Path, at: DateTime<Local>) -> PathBuf {
+    let stem = path.file_stem().map(|s| s.to_string_lossy().into_owned()).unwrap_or_default();
+    let name = match path.extension() {
+        Some(ext) => format!("{}-{}.{}", stem, at.format("%Y%m%d-%H%M%S"), ext.to_string_lossy()),
+        None => format!("{}-{}", stem, at.format("%Y%m%d-%H%M%S")),
+    };
+    path.with_file_name(name)
+}
+
+/// Whether `candidate` is a rotated copy of `path`.
+fn is_rotated_from(path: &Path, candidate: &Path) -> bool {
+    let (Some(name), Some(stem)) = (candidate.file_name().and_then(|n| n.to_str()), path.file_stem().and_then(|s| s.to_str())) else {
+        return false;
+    };
+    let rest = match path.extension().and_then(|e| e.to_str()) {
+        Some(ext) => name.strip_prefix(stem).and_then(|r| r.strip_suffix(&format!(".{}", ext)[..])),
+        None => name.strip_prefix(stem),
+    };
+    // "-YYYYmmdd-HHMMSS", then "-N" when several rotations shared a second.
+    matches!(rest, Some(stamp) if stamp.len() >= 16 && stamp.starts_with('-') && stamp[1..].chars().all(|c| c.is_ascii_digit() || c == '-'))
+}
+
+/// Renames `path` to its rotated name, numbered if that's already taken.
+fn rotate_away(path: &Path) -> Result<()> {
+    let base = rotated_name(path, Local::now());
+    let mut target = base.clone();
+    for n in 1.. {
+        if !target.exists() {
+            break;
+        }
+        let stem = base.file_stem().map(|s| s.to_string_lossy().into_owned()).unwrap_or_default();
+        target = match base.extension() {
+            Some(ext) => base.with_file_name(format!("{}-{}.{}", stem, n, ext.to_string_lossy())),
+            None => base.with_file_name(format!("{}-{}", stem, n)),
+        };
+    }
+    fs::rename(path, &target).with_context(|| format!("Failed to rotate {}", path.display()))
+}
+
+/// Deletes the rotated copies of `path` last written more than `retention`
+/// ago, returning how many went.
+pub fn prune(path: &Path, retention: Duration) -> Result<usize> {
+    let dir = match path.parent() {
+        Some(dir) if !dir.as_os_str().is_empty() => dir,
+        _ => Path::new("."),
+    };
+    let now = SystemTime::now();
+    let mut removed = 0;
+    for entry in fs::read_dir(dir).with_context(|| format!("Failed to list {}", dir.display()))?.flatten() {
+        let candidate = entry.path();
+        if !is_rotated_from(path, &candidate) {
+            continue;
+        }
+        let modified = entry.metadata().and_then(|m| m.modified()).unwrap_or(now);
+        if now.duration_since(modified).unwrap_or_default() > retention {
+            fs::remove_file(&candidate).with_context(|| format!("Failed to remove {}", candidate.display()))?;
+            removed += 1;
+        }
+    }
+    Ok(removed)
+}
+
+/// A file written a record at a time, rotated between records.
+pub struct RotatingFile {
+    path: PathBuf,
+    rotation: Rotation,
+    writer: BufWriter<File>,
+    /// Bytes in the current file, including what was there when it was opened.
+    size: u64,
+    started: SystemTime,
+}
+
+impl RotatingFile {
+    /// Appends to `path`, or with `replace` starts it afresh. With rotation
+    /// on, the old contents are rotated away rather than overwritten.
+    pub fn open(path: &Path, rotation: Rotation, replace: bool) -> Result<Self> {
+        let existing = fs::metadata(path).ok().filter(|m| m.len() > 0);
+        if replace && rotation.is_enabled() && existing.is_some() {
+            rotate_away(path)?;
+        }
+        let mut options = OpenOptions::new();
+        options.create(true);
+        if replace {
+            options.write(true).truncate(true);
+        } else {
+            options.append(true);
+        }
+        let file = options.open(path).with_context(|| format!("Failed to open {}", path.display()))?;
+        let metadata = file.metadata()?;
+        let started = metadata.created().or_else(|_| metadata.modified()).unwrap_or_else(|_| SystemTime::now());
+        let file = Self {
+            path: path.to_path_buf(),
+            rotation,
+            writer: BufWriter::new(file),
+            size: metadata.len(),
+            started: if metadata.len() == 0 { SystemTime::now() } else { started },
+        };
+        file.prune()?;
+        Ok(file)
+    }
+
+    /// Nothing written to the current file yet, e.g. a CSV still needing its header.
+    pub fn is_empty(&self) -> bool {
+        self.size == 0
+    }
+
+    /// Starts a new file when the current one is too big or too old; call
+    /// it before each record. Returns whether it did.
+    pub fn rotate_if_due(&mut self) -> Result<bool> {
+        if self.size == 0 {
+            return Ok(false);
+        }
+        let too_big = self.rotation.max_size.is_some_and(|max| self.size >= max);
+        let too_old = self
+            .rotation
+            .age_limit()
+            .is_some_and(|max| SystemTime::now().duration_since(self.started).unwrap_or_default() >= max);
+        if !too_big && !too_old {
+            return Ok(false);
+        }
+        self.writer.flush()?;
+        rotate_away(&self.path)?;
+        let file = File::create(&self.path).with_context(|| format!("Failed to create {}", self.path.display()))?;
+        self.writer = BufWriter::new(file);
+        self.size = 0;
+        self.started = SystemTime::now();
+        self.prune()?;
+        Ok(true)
+    }
+
+    fn prune(&self) -> Result<()> {
+        if let Some(retention) = self.rotation.retention {
+            prune(&self.path, retention)?;
+        }
+        Ok(())
+    }
+}
+
+impl Write for RotatingFile {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let written = self.writer.write(buf)?;
+        self.size += written as u64;
+        Ok(written)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.writer.flush()
+    }
+}
@@ -0,0 +1,653 @@
+//! `[[rule]]`s from the config file: alerts on expressions over the
+//! samples, evaluated as each one is taken.
+//!
+//! ```toml
+//! [[rule]]
+//! name = "busy"
+//! when = "avg(cpu, 5m) > 80 && connections > 100"
+//! severity = "error"
+//! cooldown = "15m"
+//! sinks = ["email", "pagerduty"]
+//! ```
+//!
+//! Expressions combine the current value of a metric (`cpu`, `memory`,
+//! `mem_mb`, `swap_mb`, `connections`, `retransmits`, `uptime`), or its
+//! `avg`, `min` or `max` over a window, with arithmetic, comparisons,
+//! `&&`, `||` and `!`. A rule fires when its expression turns true and
+//! clears when it turns false again; one that reads a metric the sample
+//! doesn't have, or a window not yet covered, is left as it was.
+
+use std::collections::VecDeque;
+use std::fmt;
+use std::time::Duration;
+
+use anyhow::{anyhow, bail, Result};
+use chrono::{DateTime, Local};
+
+use crate::config::RuleConfig;
+use crate::process::ProcessInfo;
+use crate::profile::parse_duration;
+use crate::syslog::Severity;
+use crate::units::to_mb;
+
+/// What a rule can read from a sample.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Metric {
+    /// Percent of one core.
+    Cpu,
+    /// Resident memory in bytes.
+    Memory,
+    MemMb,
+    SwapMb,
+    Connections,
+    /// Retransmitted share of segments sent, in percent.
+    Retransmits,
+    /// Seconds the process has been running.
+    Uptime,
+}
+
+const METRICS: [(Metric, &str); 7] = [
+    (Metric::Cpu, "cpu"),
+    (Metric::Memory, "memory"),
+    (Metric::MemMb, "mem_mb"),
+    (Metric::SwapMb, "swap_mb"),
+    (Metric::Connections, "connections"),
+    (Metric::Retransmits, "retransmits"),
+    (Metric::Uptime, "uptime"),
+];
+
+impl Metric {
+    fn parse(name: &str) -> Option<Self> {
+        METRICS.iter().find(|(_, known)| *known == name).map(|(metric, _)| *metric)
+    }
+
+    fn name(self) -> &'static str {
+        METRICS[self as usize].1
+    }
+}
+
+/// One sample's metrics, `None` where it didn't have them.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct Readings([Option<f64>; METRICS.len()]);
+
+impl Readings {
+    pub fn new(info: &ProcessInfo, connections: Option<usize>, retransmit_percent: Option<f64>) -> Self {
+        let mut readings = Self::default();
+        readings.set(Metric::Cpu, Some(info.cpu_percent as f64));
+        readings.set(Metric::Memory, Some(info.memory_bytes as f64));
+        readings.set(Metric::MemMb, Some(to_mb(info.memory_bytes)));
+        readings.set(Metric::SwapMb, info.swap_bytes.map(to_mb));
+        readings.set(Metric::Connections, connections.map(|c| c as f64));
+        readings.set(Metric::Retransmits, retransmit_percent);
+        readings.set(Metric::Uptime, Some(info.uptime_secs as f64));
+        readings
+    }
+
+    pub fn set(&mut self, metric: Metric, value: Option<f64>) {
+        self.0[metric as usize] = value;
+    }
+
+    fn get(&self, metric: Metric) -> Option<f64> {
+        self.0[metric as usize]
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Aggregate {
+    Avg,
+    Min,
+    Max,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Op {
+    Or,
+    And,
+    Lt,
+    Le,
+    Gt,
+    Ge,
+    Eq,
+    Ne,
+    Add,
+    Sub,
+    Mul,
+    Div,
+}
+
+impl Op {
+    fn symbol(self) -> &'static str {
+        match self {
+            Op::Or => "||",
+            Op::And => "&&",
+            Op::Lt => "<",
+            Op::Le => "<=",
+            Op::Gt => ">",
+            Op::Ge => ">=",
+            Op::Eq => "==",
+            Op::Ne => "!=",
+            Op::Add => "+",
+            Op::Sub => "-",
+            Op::Mul => "*",
+            Op::Div => "/",
+        }
+    }
+
+    fn is_comparison(self) -> bool {
+        matches!(self, Op::Lt | Op::Le | Op::Gt | Op::Ge | Op::Eq | Op::Ne)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Node {
+    Number(f64),
+    Metric(Metric),
+    /// The aggregate of a metric over a window, with the window as written.
+    Window(Aggregate, Metric, Duration, String),
+    Not(Box<Node>),
+    Neg(Box<Node>),
+    Binary(Op, Box<Node>, Box<Node>),
+}
+
+impl fmt::Display for Node {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Node::Number(n) => write!(f, "{}", n),
+            Node::Metric(metric) => f.write_str(metric.name()),
+            Node::Window(aggregate, metric, _, window) => {
+                let name = match aggregate {
+                    Aggregate::Avg => "avg",
+                    Aggregate::Min => "min",
+                    Aggregate::Max => "max",
+                };
+                write!(f, "{}({}, {})", name, metric.name(), window)
+            }
+            Node::Not(inner) => write!(f, "!{}", Grouped(inner)),
+            Node::Neg(inner) => write!(f, "-{}", Grouped(inner)),
+            Node::Binary(op, left, right) => write!(f, "{} {} {}", Grouped(left), op.symbol(), Grouped(right)),
+        }
+    }
+}
+
+/// An operand, in brackets if it's an operation itself.
+struct Grouped<'a>(&'a Node);
+
+impl fmt::Display for Grouped<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.0 {
+            Node::Binary(..) => write!(f, "({})", self.0),
+            node => node.fmt(f),
+        }
+    }
+}
+
+/// The samples windows are taken over, oldest first.
+#[derive(Debug, Clone, Default)]
+struct Samples {
+    samples: VecDeque<(DateTime<Local>, Readings)>,
+}
+
+impl Samples {
+    /// Adds a sample and forgets those no window reaches back to.
+    fn push(&mut self, at: DateTime<Local>, readings: Readings, keep: Duration) {
+        self.samples.push_back((at, readings));
+        let Ok(keep) = chrono::Duration::from_std(keep) else { return };
+        while self.samples.len() > 1 && self.samples[1].0 <= at - keep {
+            self.samples.pop_front();
+        }
+    }
+
+    fn latest(&self, metric: Metric) -> Option<f64> {
+        self.samples.back().and_then(|(_, readings)| readings.get(metric))
+    }
+
+    /// `None` until the samples go back at least `window`.
+    fn window(&self, aggregate: Aggregate, metric: Metric, window: Duration) -> Option<f64> {
+        let (now, _) = self.samples.back()?;
+        let start = *now - chrono::Duration::from_std(window).ok()?;
+        if self.samples.front()?.0 > start {
+            return None;
+        }
+        let values: Vec<f64> = self
+            .samples
+            .iter()
+            .filter(|(at, _)| *at >= start)
+            .filter_map(|(_, readings)| readings.get(metric))
+            .collect();
+        if values.is_empty() {
+            return None;
+        }
+        Some(match aggregate {
+            Aggregate::Avg => values.iter().sum::<f64>() / values.len() as f64,
+            Aggregate::Min => values.iter().copied().fold(f64::INFINITY, f64::min),
+            Aggregate::Max => values.iter().copied().fold(f64::NEG_INFINITY, f64::max),
+        })
+    }
+}
+
+/// A parsed `when` expression.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Expr {
+    node: Node,
+    text: String,
+}
+
+impl Expr {
+    pub fn parse(text: &str) -> Result<Self> {
+        let tokens = tokenize(text)?;
+        let mut parser = Parser { tokens, at: 0 };
+        let node = parser.or()?;
+        if let Some((token, column)) = parser.tokens.get(parser.at) {
+            bail!("unexpected {} at column {}", token, column);
+        }
+        Ok(Self {
+            node,
+            text: text.trim().to_string(),
+        })
+    }
+
+    /// True or false, or `None` when a metric or window isn't there yet.
+    fn test(&self, samples: &Samples) -> Option<bool> {
+        eval(&self.node, samples).map(|value| value != 0.0)
+    }
+
+    /// The value on the left of each comparison, e.g. `avg(cpu, 5m) = 86.2`,
+    /// for saying why a rule fired.
+    fn explain(&self, samples: &Samples) -> Vec<(String, f64)> {
+        let mut seen = Vec::new();
+        explain(&self.node, samples, &mut seen);
+        seen
+    }
+
+    /// The longest window the expression reads.
+    fn reach(&self) -> Duration {
+        fn reach(node: &Node) -> Duration {
+            match node {
+                Node::Window(_, _, window, _) => *window,
+                Node::Not(inner) | Node::Neg(inner) => reach(inner),
+                Node::Binary(_, left, right) => reach(left).max(reach(right)),
+                Node::Number(_) | Node::Metric(_) => Duration::ZERO,
+            }
+        }
+        reach(&self.node)
+    }
+}
+
+/// As written in the config file.
+impl fmt::Display for Expr {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.text)
+    }
+}
+
+fn eval(node: &Node, samples: &Samples) -> Option<f64> {
+    let truth = |b: bool| if b { 1.0 } else { 0.0 };
+    Some(match node {
+        Node::Number(n) => *n,
+        Node::Metric(metric) => samples.latest(*metric)?,
+        Node::Window(aggregate, metric, window, _) => samples.window(*aggregate, *metric, *window)?,
+        Node::Not(inner) => truth(eval(inner, samples)? == 0.0),
+        Node::Neg(inner) => -eval(inner, samples)?,
+        // Short-circuits, so `connections > 0 && ...` holds off a missing reading.
+        Node::Binary(Op::And, left, right) => {
+            if eval(left, samples)? == 0.0 {
+                0.0
+            } else {
+                truth(eval(right, samples)? != 0.0)
+            }
+        }
+        Node::Binary(Op::Or, left, right) => {
+            if eval(left, samples)? != 0.0 {
+                1.0
+            } else {
+                truth(eval(right, samples)? != 0.0)
+            }
+        }
+        Node::Binary(op, left, right) => {
+            let (a, b) = (eval(left, samples)?, eval(right, samples)?);
+            match op {
+                Op::Lt => truth(a < b),
+                Op::Le => truth(a <= b),
+                Op::Gt => truth(a > b),
+                Op::Ge => truth(a >= b),
+                Op::Eq => truth(a == b),
+                Op::Ne => truth(a != b),
+                Op::Add => a + b,
+                Op::Sub => a - b,
+                Op::Mul => a * b,
+                Op::Div if b == 0.0 => return None,
+                Op::Div => a / b,
+                Op::And | Op::Or => unreachable!(),
+            }
+        }
+    })
+}
+
+fn explain(node: &Node, samples: &Samples, seen: &mut Vec<(String, f64)>) {
+    match node {
+        Node::Binary(op, left, _) if op.is_comparison() => {
+            if let Some(value) = eval(left, samples) {
+                seen.push((left.to_string(), value));
+            }
+        }
+        Node::Binary(_, left, right) => {
+            explain(left, samples, seen);
+            explain(right, samples, seen);
+        }
+        Node::Not(inner) | Node::Neg(inner) => explain(inner, samples, seen),
+        _ => {}
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    Number(f64),
+    /// A number with a unit, which only a window takes.
+    Duration(Duration, String),
+    Ident(String),
+    Op(&'static str),
+    Open,
+    Close,
+    Comma,
+}
+
+impl fmt::Display for Token {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Token::Number(n) => write!(f, "`{}`", n),
+            Token::Duration(_, text) | Token::Ident(text) => write!(f, "`{}`", text),
+            Token::Op(op) => write!(f, "`{}`", op),
+            Token::Open => f.write_str("`(`"),
+            Token::Close => f.write_str("`)`"),
+            Token::Comma => f.write_str("`,`"),
+        }
+    }
+}
+
+/// Tokens with the (1-based) column each starts at.
+fn tokenize(text: &str) -> Result<Vec<(Token, usize)>> {
+    const OPS: [&str; 13] = ["&&", "||", "<=", ">=", "==", "!=", "<", ">", "!", "+", "-", "*", "/"];
+    let mut tokens = Vec::new();
+    let mut rest = text;
+    while let Some(c) = rest.chars().next() {
+        let column = text.len() - rest.len() + 1;
+        if c.is_whitespace() {
+            rest = &rest[c.len_utf8()..];
+            continue;
+        }
+        let (token, len) = if c.is_ascii_digit() || c == '.' {
+            let len = rest.find(|c: char| !c.is_ascii_alphanumeric() && c != '.').unwrap_or(rest.len());
+            let word = &rest[..len];
+            match word.parse::<f64>() {
+                Ok(n) => (Token::Number(n), len),
+                Err(_) => {
+                    let window = parse_duration(word).map_err(|e| anyhow!("{} at column {}", e, column))?;
+                    (Token::Duration(window, word.to_string()), len)
+                }
+            }
+        } else if c.is_ascii_alphabetic() || c == '_' {
+            let len = rest.find(|c: char| !c.is_ascii_alphanumeric() && c != '_').unwrap_or(rest.len());
+            (Token::Ident(rest[..len].to_string()), len)
+        } else if let Some(op) = OPS.iter().find(|op| rest.starts_with(**op)) {
+            (Token::Op(op), op.len())
+        } else {
+            match c {
+                '(' => (Token::Open, 1),
+                ')' => (Token::Close, 1),
+                ',' => (Token::Comma, 1),
+                _ => bail!("unexpected `{}` at column {}", c, column),
+            }
+        };
+        tokens.push((token, column));
+        rest = &rest[len..];
+    }
+    Ok(tokens)
+}
+
+struct Parser {
+    tokens: Vec<(Token, usize)>,
+    at: usize,
+}
+
+impl Parser {
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.at).map(|(token, _)| token)
+    }
+
+    fn next(&mut self) -> Result<(Token, usize)> {
+        let token = self.tokens.get(self.at).cloned().ok_or_else(|| anyhow!("the expression ends too soon"))?;
+        self.at += 1;
+        Ok(token)
+    }
+
+    fn eat_op(&mut self, ops: &[(&str, Op)]) -> Option<Op> {
+        let Some(Token::Op(symbol)) = self.peek() else { return None };
+        let op = ops.iter().find(|(known, _)| known == symbol).map(|(_, op)| *op)?;
+        self.at += 1;
+        Some(op)
+    }
+
+    fn expect(&mut self, expected: Token) -> Result<()> {
+        let (token, column) = self.next()?;
+        if token != expected {
+            bail!("expected {} at column {}, found {}", expected, column, token);
+        }
+        Ok(())
+    }
+
+    fn binary(&mut self, ops: &[(&str, Op)], operand: fn(&mut Self) -> Result<Node>) -> Result<Node> {
+        let mut node = operand(self)?;
+        while let Some(op) = self.eat_op(ops) {
+            node = Node::Binary(op, Box::new(node), Box::new(operand(self)?));
+        }
+        Ok(node)
+    }
+
+    fn or(&mut self) -> Result<Node> {
+        self.binary(&[("||", Op::Or)], Self::and)
+    }
+
+    fn and(&mut self) -> Result<Node> {
+        self.binary(&[("&&", Op::And)], Self::not)
+    }
+
+    fn not(&mut self) -> Result<Node> {
+        if self.peek() == Some(&Token::Op("!")) {
+            self.at += 1;
+            return Ok(Node::Not(Box::new(self.not()?)));
+        }
+        self.comparison()
+    }
+
+    fn comparison(&mut self) -> Result<Node> {
+        const COMPARISONS: [(&str, Op); 6] = [("<", Op::Lt), ("<=", Op::Le), (">", Op::Gt), (">=", Op::Ge), ("==", Op::Eq), ("!=", Op::Ne)];
+        let left = self.sum()?;
+        match self.eat_op(&COMPARISONS) {
+            Some(op) => Ok(Node::Binary(op, Box::new(left), Box::new(self.sum()?))),
+            None => Ok(left),
+        }
+    }
+
+    fn sum(&mut self) -> Result<Node> {
+        self.binary(&[("+", Op::Add), ("-", Op::Sub)], Self::product)
+    }
+
+    fn product(&mut self) -> Result<Node> {
+        self.binary(&[("*", Op::Mul), ("/", Op::Div)], Self::unary)
+    }
+
+    fn unary(&mut self) -> Result<Node> {
+        if self.peek() == Some(&Token::Op("-")) {
+            self.at += 1;
+            return Ok(Node::Neg(Box::new(self.unary()?)));
+        }
+        self.atom()
+    }
+
+    fn atom(&mut self) -> Result<Node> {
+        let (token, column) = self.next()?;
+        match token {
+            Token::Number(n) => Ok(Node::Number(n)),
+            Token::Open => {
+                let node = self.or()?;
+                self.expect(Token::Close)?;
+                Ok(node)
+            }
+            Token::Ident(name) if self.peek() == Some(&Token::Open) => {
+                let aggregate = match name.as_str() {
+                    "avg" => Aggregate::Avg,
+                    "min" => Aggregate::Min,
+                    "max" => Aggregate::Max,
+                    _ => bail!("unknown function `{}` at column {} (use avg, min or max)", name, column),
+                };
+                self.expect(Token::Open)?;
+                let (token, column) = self.next()?;
+                let metric = match &token {
+                    Token::Ident(name) => metric(name, column)?,
+                    _ => bail!("expected a metric at column {}, found {}", column, token),
+                };
+                self.expect(Token::Comma)?;
+                let (token, column) = self.next()?;
+                let Token::Duration(window, text) = token else {
+                    bail!("expected a window like `5m` at column {}, found {}", column, token);
+                };
+                self.expect(Token::Close)?;
+                Ok(Node::Window(aggregate, metric, window, text))
+            }
+            Token::Ident(name) => Ok(Node::Metric(metric(&name, column)?)),
+            _ => bail!("unexpected {} at column {}", token, column),
+        }
+    }
+}
+
+fn metric(name: &str, column: usize) -> Result<Metric> {
+    Metric::parse(name).ok_or_else(|| {
+        let known: Vec<_> = METRICS.iter().map(|(_, name)| *name).collect();
+        anyhow!("unknown metric `{}` at column {} (known: {})", name, column, known.join(", "))
+    })
+}
+
+/// A rule ready to be evaluated.
+#[derive(Debug, Clone)]
+pub struct Rule {
+    pub name: String,
+    pub when: Expr,
+    pub severity: Severity,
+    pub cooldown: Duration,
+    /// Replaces the generated alert message.
+    pub message: Option<String>,
+}
+
+impl Rule {
+    pub fn from_config(config: &RuleConfig) -> Result<Self> {
+        let when = Expr::parse(&config.when).map_err(|e| anyhow!("Rule `{}`: {}", config.name, e))?;
+        Ok(Self {
+            name: config.name.clone(),
+            when,
+            severity: config.severity,
+            cooldown: config.cooldown,
+            message: config.message.clone(),
+        })
+    }
+}
+
+/// A rule turning true (`cleared == false`) or false again.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Transition {
+    pub rule: String,
+    pub severity: Severity,
+    pub message: String,
+    pub value: f64,
+    pub cleared: bool,
+}
+
+#[derive(Debug, Clone, Default)]
+struct RuleState {
+    /// The expression held on the last sample it could be evaluated for.
+    holds: bool,
+    /// It fired (rather than being held back by the cooldown) and hasn't cleared.
+    alerted: bool,
+    fired_at: Option<DateTime<Local>>,
+}
+
+/// Every configured rule, with the samples their windows need.
+#[derive(Debug, Clone, Default)]
+pub struct RuleEngine {
+    rules: Vec<(Rule, RuleState)>,
+    samples: Samples,
+    keep: Duration,
+}
+
+impl RuleEngine {
+    pub fn new(rules: Vec<Rule>) -> Self {
+        let keep = rules.iter().map(|rule| rule.when.reach()).max().unwrap_or_default();
+        Self {
+            rules: rules.into_iter().map(|rule| (rule, RuleState::default())).collect(),
+            samples: Samples::default(),
+            keep,
+        }
+    }
+
+    pub fn from_config(configs: &[RuleConfig]) -> Result<Self> {
+        Ok(Self::new(configs.iter().map(Rule::from_config).collect::<Result<_>>()?))
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.rules.is_empty()
+    }
+
+    /// Adds a sample and returns the rules that fired or cleared on it. A
+    /// rule that turns true again within its cooldown is held back, and so
+    /// is the clearing that follows.
+    pub fn evaluate(&mut self, at: DateTime<Local>, readings: Readings) -> Vec<Transition> {
+        self.samples.push(at, readings, self.keep);
+        let mut transitions = Vec::new();
+        for (rule, state) in &mut self.rules {
+            let Some(holds) = rule.when.test(&self.samples) else { continue };
+            if holds == state.holds {
+                continue;
+            }
+            state.holds = holds;
+            let why = rule.when.explain(&self.samples);
+            let value = why.first().map_or(1.0, |(_, value)| *value);
+            let figures: Vec<String> = why.iter().map(|(what, value)| format!("{} = {}", what, figure(*value))).collect();
+            if holds {
+                let cooling = state.fired_at.is_some_and(|fired| chrono::Duration::from_std(rule.cooldown).is_ok_and(|cooldown| at - fired < cooldown));
+                if cooling {
+                    continue;
+                }
+                state.alerted = true;
+                state.fired_at = Some(at);
+                let message = match &rule.message {
+                    Some(message) => message.clone(),
+                    None => format!("{} ({})", rule.when, figures.join(", ")),
+                };
+                transitions.push(Transition {
+                    rule: rule.name.clone(),
+                    severity: rule.severity,
+                    message,
+                    value,
+                    cleared: false,
+                });
+            } else if std::mem::take(&mut state.alerted) {
+                transitions.push(Transition {
+                    rule: rule.name.clone(),
+                    severity: rule.severity,
+                    message: format!("No longer {} ({})", rule.when, figures.join(", ")),
+                    value,
+                    cleared: true,
+                });
+            }
+        }
+        transitions
+    }
+}
+
+/// `140` or `86.2`.
+fn figure(value: f64) -> String {
+    if value.fract() == 0.0 && value.abs() < 1e15 {
+        format!("{}", value as i64)
+    } else {
+        format!("{:.1}", value)
+    }
+}
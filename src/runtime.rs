@@ -0,0 +1,268 @@
+//! Stack dumps of managed runtimes: which language a process runs on, and
+//! what each of its threads is doing right now.
+//!
+//! Python goes through `py-spy dump`, the JVM through `jcmd Thread.print`,
+//! both run on the process's host. Node has no such tool, so we talk to its
+//! inspector over WebSocket and pause it just long enough to read the stack.
+
+use std::collections::HashMap;
+use std::io::{Read, Write};
+use std::net::{SocketAddr, TcpStream};
+use std::time::{Duration, Instant};
+
+use anyhow::{anyhow, Context, Result};
+use serde_json::{json, Value};
+use tungstenite::Message;
+
+use crate::exec::Runner;
+
+/// Lines shown per page of the stack-dump pane.
+pub const DUMP_LINES: usize = 20;
+
+/// The inspector's port unless `--inspect=` says otherwise.
+const DEFAULT_INSPECTOR_PORT: u16 = 9229;
+/// How long to wait for the inspector to come up after SIGUSR1, and for it to pause.
+const INSPECTOR_WAIT: Duration = Duration::from_secs(3);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Runtime {
+    Python,
+    Jvm,
+    Node { inspector_port: u16 },
+}
+
+impl Runtime {
+    pub fn label(&self) -> &'static str {
+        match self {
+            Runtime::Python => "Python",
+            Runtime::Jvm => "JVM",
+            Runtime::Node { .. } => "Node.js",
+        }
+    }
+}
+
+/// Recognises the runtime from the executable path and the command line.
+/// The path can be empty when `/proc/<pid>/exe` isn't readable.
+pub fn detect(exe: &str, cmdline: &[&str]) -> Option<Runtime> {
+    let exe = exe.trim().trim_end_matches(" (deleted)");
+    let names = [exe, cmdline.first().copied().unwrap_or("")];
+    let names = names.iter().map(|path| path.rsplit('/').next().unwrap_or(path));
+    for name in names {
+        if name.starts_with("python") || name.starts_with("pypy") {
+            return Some(Runtime::Python);
+        }
+        if name == "java" {
+            return Some(Runtime::Jvm);
+        }
+        if name == "node" || name == "nodejs" {
+            return Some(Runtime::Node {
+                inspector_port: inspector_port(cmdline),
+            });
+        }
+    }
+    None
+}
+
+// "--inspect", "--inspect=9230", "--inspect-brk=0.0.0.0:9230", "--inspect-port=9230".
+fn inspector_port(cmdline: &[&str]) -> u16 {
+    cmdline
+        .iter()
+        .filter(|arg| arg.starts_with("--inspect"))
+        .find_map(|arg| arg.split_once('=')?.1.rsplit(':').next()?.parse().ok())
+        .unwrap_or(DEFAULT_INSPECTOR_PORT)
+}
+
+/// The runtime of `pid`, read through `runner`.
+pub fn detect_on(runner: &Runner, pid: u32) -> Option<Runtime> {
+    let exe = runner.output("readlink", &[&format!("/proc/{}/exe", pid)]).ok()?;
+    let cmdline = runner.output("cat", &[&format!("/proc/{}/cmdline", pid)]).ok()?;
+    let cmdline = String::from_utf8_lossy(&cmdline.stdout);
+    let args: Vec<&str> = cmdline.split('\0').filter(|a| !a.is_empty()).collect();
+    detect(&String::from_utf8_lossy(&exe.stdout), &args)
+}
+
+/// One stack dump, and how far the pane is scrolled into it.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct StackDump {
+    pub runtime: Runtime,
+    /// What produced it, e.g. `py-spy dump --pid 4242`.
+    pub command: String,
+    pub lines: Result<Vec<String>, String>,
+    /// Index of the first line on screen.
+    pub offset: usize,
+}
+
+impl StackDump {
+    pub fn new(runtime: Runtime, command: String, lines: Result<Vec<String>, String>) -> Self {
+        Self {
+            runtime,
+            command,
+            lines,
+            offset: 0,
+        }
+    }
+
+    fn len(&self) -> usize {
+        self.lines.as_ref().map_or(0, Vec::len)
+    }
+
+    /// The lines on screen.
+    pub fn visible(&self) -> &[String] {
+        match &self.lines {
+            Ok(lines) => &lines[self.offset.min(lines.len())..(self.offset + DUMP_LINES).min(lines.len())],
+            Err(_) => &[],
+        }
+    }
+
+    /// Moves by `lines`, negative for up, stopping with the last page full.
+    pub fn scroll(&mut self, lines: isize) {
+        let last = self.len().saturating_sub(DUMP_LINES);
+        self.offset = self.offset.saturating_add_signed(lines).min(last);
+    }
+
+    pub fn next_page(&mut self) {
+        self.scroll(DUMP_LINES as isize);
+    }
+
+    pub fn previous_page(&mut self) {
+        self.scroll(-(DUMP_LINES as isize));
+    }
+}
+
+/// Dumps the stacks of `pid` with the tool for its runtime.
+pub fn stack_dump_on(runner: &Runner, pid: u32, runtime: Runtime) -> StackDump {
+    let pid_arg = pid.to_string();
+    let (command, lines) = match runtime {
+        Runtime::Python => (
+            format!("py-spy dump --pid {}", pid),
+            run_tool(runner, "py-spy", &["dump", "--pid", &pid_arg]),
+        ),
+        Runtime::Jvm => (
+            format!("jcmd {} Thread.print", pid),
+            run_tool(runner, "jcmd", &[&pid_arg, "Thread.print"]),
+        ),
+        Runtime::Node { inspector_port } => {
+            let lines = match runner {
+                Runner::Local | Runner::Elevated(_) => node_stack(pid, inspector_port),
+                Runner::Ssh(_) => Err(anyhow!("Node stack dumps need the inspector on this machine; run crabtop on the host")),
+            };
+            (format!("Node inspector on 127.0.0.1:{}", inspector_port), lines)
+        }
+    };
+    StackDump::new(runtime, command, lines.map_err(|e| format!("{:#}", e)))
+}
+
+fn run_tool(runner: &Runner, program: &str, args: &[&str]) -> Result<Vec<String>> {
+    let out = runner
+        .output(program, args)
+        .with_context(|| format!("Failed to run {}{} (is it installed?)", program, runner.describe()))?;
+    if !out.status.success() {
+        return Err(anyhow!("{} failed: {}", program, String::from_utf8_lossy(&out.stderr).trim()));
+    }
+    Ok(String::from_utf8_lossy(&out.stdout).lines().map(|l| l.trim_end().to_string()).collect())
+}
+
+/// Pauses the main JavaScript thread through the inspector, formats its call
+/// frames and resumes it. SIGUSR1 opens the inspector if it isn't already;
+/// it then stays open until the process exits, as with `node --inspect`.
+fn node_stack(pid: u32, port: u16) -> Result<Vec<String>> {
+    let addr = SocketAddr::from(([127, 0, 0, 1], port));
+    if TcpStream::connect_timeout(&addr, Duration::from_millis(300)).is_err() {
+        let out = Runner::Local.output("kill", &["-USR1", &pid.to_string()]).context("Failed to run kill")?;
+        if !out.status.success() {
+            return Err(anyhow!("Cannot signal PID {}: {}", pid, String::from_utf8_lossy(&out.stderr).trim()));
+        }
+        let started = Instant::now();
+        while TcpStream::connect_timeout(&addr, Duration::from_millis(300)).is_err() {
+            if started.elapsed() > INSPECTOR_WAIT {
+                return Err(anyhow!("The Node inspector did not open on port {}", port));
+            }
+            std::thread::sleep(Duration::from_millis(100));
+        }
+    }
+
+    let url = debugger_url(addr)?;
+    let stream = TcpStream::connect_timeout(&addr, INSPECTOR_WAIT)?;
+    stream.set_read_timeout(Some(INSPECTOR_WAIT))?;
+    let (mut socket, _) = tungstenite::client(url.as_str(), stream).context("Inspector handshake failed")?;
+    for (id, method) in [(1, "Debugger.enable"), (2, "Debugger.pause")] {
+        socket.send(Message::text(json!({ "id": id, "method": method }).to_string()))?;
+    }
+    let mut scripts = HashMap::new();
+    let mut frames = loop {
+        let message = socket
+            .read()
+            .map_err(|_| anyhow!("No JavaScript ran within {}s; the event loop is idle", INSPECTOR_WAIT.as_secs()))?;
+        let Ok(value) = serde_json::from_str::<Value>(message.to_text().unwrap_or_default()) else {
+            continue;
+        };
+        match value["method"].as_str() {
+            // Newer Nodes leave a frame's `url` empty; the script it names was announced on enable.
+            Some("Debugger.scriptParsed") => {
+                if let (Some(id), Some(url)) = (value["params"]["scriptId"].as_str(), value["params"]["url"].as_str()) {
+                    scripts.insert(id.to_string(), url.to_string());
+                }
+            }
+            Some("Debugger.paused") => break value["params"]["callFrames"].as_array().cloned().unwrap_or_default(),
+            _ => {}
+        }
+    };
+    for frame in &mut frames {
+        if frame["url"].as_str().unwrap_or_default().is_empty() {
+            if let Some(url) = frame["location"]["scriptId"].as_str().and_then(|id| scripts.get(id)) {
+                frame["url"] = Value::from(url.as_str());
+            }
+        }
+    }
+    for (id, method) in [(3, "Debugger.resume"), (4, "Debugger.disable")] {
+        socket.send(Message::text(json!({ "id": id, "method": method }).to_string()))?;
+    }
+    let _ = socket.close(None);
+    Ok(format_call_frames(&frames))
+}
+
+// The first target's WebSocket URL from the inspector's `/json/list`.
+fn debugger_url(addr: SocketAddr) -> Result<String> {
+    let mut stream = TcpStream::connect_timeout(&addr, INSPECTOR_WAIT)?;
+    stream.set_read_timeout(Some(INSPECTOR_WAIT))?;
+    write!(stream, "GET /json/list HTTP/1.1\r\nHost: {}\r\nConnection: close\r\n\r\n", addr)?;
+    // The inspector doesn't always close the connection, so read to Content-Length.
+    let mut response = Vec::new();
+    let mut chunk = [0u8; 4096];
+    let body = loop {
+        let n = stream.read(&mut chunk)?;
+        response.extend_from_slice(&chunk[..n]);
+        let text = String::from_utf8_lossy(&response);
+        if let Some((head, body)) = text.split_once("\r\n\r\n") {
+            let length = head
+                .lines()
+                .find_map(|l| l.to_ascii_lowercase().strip_prefix("content-length:").map(|v| v.trim().parse::<usize>()))
+                .and_then(Result::ok);
+            if n == 0 || length.is_some_and(|length| body.len() >= length) {
+                break body.to_string();
+            }
+        }
+        if n == 0 {
+            return Err(anyhow!("The Node inspector closed the connection"));
+        }
+    };
+    let targets: Value = serde_json::from_str(&body).context("Unexpected reply from the Node inspector")?;
+    targets[0]["webSocketDebuggerUrl"]
+        .as_str()
+        .map(str::to_string)
+        .context("The Node inspector lists no debuggable target")
+}
+
+/// `Debugger.paused` call frames as `at fn (url:line:column)`, innermost first.
+pub fn format_call_frames(frames: &[Value]) -> Vec<String> {
+    let mut lines = vec!["Main thread (paused):".to_string()];
+    for frame in frames {
+        let name = frame["functionName"].as_str().filter(|n| !n.is_empty()).unwrap_or("(anonymous)");
+        let url = frame["url"].as_str().filter(|u| !u.is_empty()).unwrap_or("<eval>");
+        // The protocol's line and column numbers are zero-based.
+        let line = frame["location"]["lineNumber"].as_u64().unwrap_or(0) + 1;
+        let column = frame["location"]["columnNumber"].as_u64().unwrap_or(0) + 1;
+        lines.push(format!("    at {} ({}:{}:{})", name, url, line, column));
+    }
+    lines
+}
@@ -0,0 +1,153 @@
+//! Which kind of memory is growing: the brk heap, anonymous mmaps, file-backed
+//! mappings or the stack, from the resident size of each mapping in
+//! `/proc/<pid>/smaps`. A leak in small `malloc`s grows the heap; large
+//! allocations, allocator arenas, thread stacks and the heaps of the JVM, Go
+//! and V8 are anonymous mmaps; mapped files are mostly page cache the kernel
+//! can take back. Knowing which points the investigation at the right tool.
+//!
+//! smaps lists every mapping, which takes a while to read for a big process,
+//! so it is only read on the slow refreshes.
+
+use std::fmt;
+
+use chrono::{DateTime, Local};
+
+#[cfg(target_os = "linux")]
+use crate::procfs::parse_smaps;
+
+/// Readings of the segments kept to measure growth over.
+pub const SEGMENT_READINGS: usize = 12;
+
+/// Growth below this is noise: allocator caching and the like.
+pub const MIN_GROWTH: u64 = 4 * 1024 * 1024;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Segment {
+    /// `[heap]`, grown with `brk`.
+    Heap,
+    /// Mappings with no file behind them.
+    Anonymous,
+    /// Mapped files: executables, libraries, data files.
+    File,
+    /// `[stack]`, the main thread's.
+    Stack,
+    /// `[vdso]`, `[vvar]` and other kernel-provided mappings.
+    Other,
+}
+
+pub const SEGMENTS: [Segment; 4] = [Segment::Heap, Segment::Anonymous, Segment::File, Segment::Stack];
+
+impl Segment {
+    /// The kind of mapping named `path` in smaps (empty when anonymous).
+    pub fn of_path(path: &str) -> Segment {
+        match path {
+            "" => Segment::Anonymous,
+            "[heap]" => Segment::Heap,
+            "[stack]" => Segment::Stack,
+            p if p.starts_with("[anon") || p.starts_with("/memfd:") || p == "/dev/zero" => Segment::Anonymous,
+            p if p.starts_with('/') => Segment::File,
+            _ => Segment::Other,
+        }
+    }
+
+    pub fn label(self) -> &'static str {
+        match self {
+            Segment::Heap => "heap",
+            Segment::Anonymous => "anon mmap",
+            Segment::File => "file-backed",
+            Segment::Stack => "stack",
+            Segment::Other => "other",
+        }
+    }
+
+    /// Where growth of this segment usually comes from.
+    pub fn hint(self) -> &'static str {
+        match self {
+            Segment::Heap => "small malloc/new allocations; try a heap profiler (heaptrack)",
+            Segment::Anonymous => "big allocations, malloc arenas, thread stacks or a GC's heap",
+            Segment::File => "mapped files; mostly page cache the kernel can reclaim",
+            Segment::Stack => "the main thread's stack: deep recursion",
+            Segment::Other => "kernel-provided mappings",
+        }
+    }
+}
+
+impl fmt::Display for Segment {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.label())
+    }
+}
+
+/// Resident bytes per kind of mapping.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct MemorySegments {
+    pub heap: u64,
+    pub anonymous: u64,
+    pub file: u64,
+    pub stack: u64,
+    pub other: u64,
+}
+
+impl MemorySegments {
+    pub fn get(&self, segment: Segment) -> u64 {
+        match segment {
+            Segment::Heap => self.heap,
+            Segment::Anonymous => self.anonymous,
+            Segment::File => self.file,
+            Segment::Stack => self.stack,
+            Segment::Other => self.other,
+        }
+    }
+
+    pub fn get_mut(&mut self, segment: Segment) -> &mut u64 {
+        match segment {
+            Segment::Heap => &mut self.heap,
+            Segment::Anonymous => &mut self.anonymous,
+            Segment::File => &mut self.file,
+            Segment::Stack => &mut self.stack,
+            Segment::Other => &mut self.other,
+        }
+    }
+}
+
+/// `pid`'s segments; needs the same user as the process, or root.
+#[cfg(target_os = "linux")]
+pub fn read_segments(pid: u32) -> Option<MemorySegments> {
+    std::fs::read_to_string(format!("/proc/{}/smaps", pid)).ok().map(|text| parse_smaps(&text))
+}
+
+#[cfg(not(target_os = "linux"))]
+pub fn read_segments(_pid: u32) -> Option<MemorySegments> {
+    None
+}
+
+/// The segments at the latest slow refreshes, to tell which one grows.
+#[derive(Debug, Clone, Default)]
+pub struct SegmentHistory {
+    /// Oldest first, at most `SEGMENT_READINGS`.
+    pub readings: Vec<(DateTime<Local>, MemorySegments)>,
+}
+
+impl SegmentHistory {
+    pub fn add(&mut self, at: DateTime<Local>, segments: MemorySegments) {
+        self.readings.push((at, segments));
+        if self.readings.len() > SEGMENT_READINGS {
+            self.readings.remove(0);
+        }
+    }
+
+    pub fn latest(&self) -> Option<&MemorySegments> {
+        self.readings.last().map(|(_, s)| s)
+    }
+
+    /// The segment that grew the most since the oldest reading, when it grew
+    /// by at least `MIN_GROWTH` and by more than the others together: the
+    /// segment, by how much, and since when.
+    pub fn growing(&self) -> Option<(Segment, u64, DateTime<Local>)> {
+        let ((since, first), (_, last)) = (self.readings.first()?, self.readings.last()?);
+        let growth: Vec<(Segment, u64)> = SEGMENTS.iter().map(|&s| (s, last.get(s).saturating_sub(first.get(s)))).collect();
+        let total: u64 = growth.iter().map(|(_, g)| g).sum();
+        let (segment, most) = growth.into_iter().max_by_key(|&(_, g)| g)?;
+        (most >= MIN_GROWTH && most * 2 > total).then_some((segment, most, *since))
+    }
+}
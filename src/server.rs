@@ -0,0 +1,166 @@
+//! `serve` mode: this machine's ports and processes behind a small HTTP API,
+//! for `--remote` clients and anything else that wants the numbers.
+//!
+//! Every route needs the server's token, either as `Authorization: Bearer
+//! <token>` or, for WebSocket clients that can't set headers, `?token=`.
+//!
+//! - `GET /api/ports` – every listening socket and its PID
+//! - `GET /api/ports/{port}` – the PIDs listening on one port
+//! - `GET /api/process/{pid}/metrics` – a sample of the process, host and cgroup
+//! - `GET /api/process/{pid}/listeners` – every socket the process listens on
+//! - `GET /api/process/{pid}/stream?interval=<secs>` (0.2 to 3600, default 1) – WebSocket of `Sample`s as JSON
+
+use std::collections::HashMap;
+use std::net::SocketAddr;
+use std::sync::Arc;
+use std::time::Duration;
+
+use anyhow::{Context, Result};
+use axum::extract::ws::{Message, WebSocket, WebSocketUpgrade};
+use axum::extract::{Path, Query, Request, State};
+use axum::http::{header, StatusCode};
+use axum::middleware::{self, Next};
+use axum::response::{IntoResponse, Response};
+use axum::routing::get;
+use axum::{Json, Router};
+use futures_util::StreamExt;
+
+use crate::cgroup::read_cgroup_stats;
+use crate::error::Error;
+use crate::port::{find_pids_by_port, listening_ports, listening_sockets};
+use crate::process::{collect_process_info, collect_system_snapshot, MIN_MEASURE_WINDOW};
+use crate::remote::{ApiError, Metrics, PortPids};
+use crate::stream::sample_stream;
+
+/// Builds the API router. `token` is required on every request.
+pub fn router(token: String) -> Router {
+    let token = Arc::new(token);
+    Router::new()
+        .route("/api/ports", get(ports))
+        .route("/api/ports/{port}", get(port_pids))
+        .route("/api/process/{pid}/metrics", get(metrics))
+        .route("/api/process/{pid}/listeners", get(listeners))
+        .route("/api/process/{pid}/stream", get(stream))
+        .layer(middleware::from_fn_with_state(token, require_token))
+}
+
+/// Serves the API on `addr` until the process is stopped.
+pub async fn serve(addr: SocketAddr, token: String) -> Result<()> {
+    let listener = tokio::net::TcpListener::bind(addr)
+        .await
+        .with_context(|| format!("Failed to listen on {}", addr))?;
+    axum::serve(listener, router(token)).await.context("API server failed")
+}
+
+async fn require_token(
+    State(token): State<Arc<String>>,
+    Query(query): Query<HashMap<String, String>>,
+    request: Request,
+    next: Next,
+) -> Response {
+    let presented = request
+        .headers()
+        .get(header::AUTHORIZATION)
+        .and_then(|v| v.to_str().ok())
+        .and_then(|v| v.strip_prefix("Bearer "))
+        .or_else(|| query.get("token").map(String::as_str));
+
+    match presented {
+        Some(presented) if constant_time_eq(presented.as_bytes(), token.as_bytes()) => next.run(request).await,
+        _ => error(StatusCode::UNAUTHORIZED, "missing or invalid token"),
+    }
+}
+
+// Compares without short-circuiting so response timing doesn't leak the token.
+fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    a.len() == b.len() && a.iter().zip(b).fold(0, |acc, (x, y)| acc | (x ^ y)) == 0
+}
+
+fn error(status: StatusCode, message: impl Into<String>) -> Response {
+    (status, Json(ApiError { error: message.into() })).into_response()
+}
+
+async fn ports() -> Response {
+    match tokio::task::spawn_blocking(listening_sockets).await {
+        Ok(Ok(sockets)) => Json(sockets).into_response(),
+        Ok(Err(e)) => error(StatusCode::INTERNAL_SERVER_ERROR, e.to_string()),
+        Err(e) => error(StatusCode::INTERNAL_SERVER_ERROR, e.to_string()),
+    }
+}
+
+async fn port_pids(Path(port): Path<u16>) -> Response {
+    match tokio::task::spawn_blocking(move || find_pids_by_port(port)).await {
+        Ok(Ok(pids)) => Json(PortPids { port, pids }).into_response(),
+        Ok(Err(e)) => match crate::error::find(&e) {
+            Some(Error::PermissionDenied { .. }) => error(StatusCode::FORBIDDEN, e.to_string()),
+            Some(Error::ToolMissing { .. }) => error(StatusCode::INTERNAL_SERVER_ERROR, e.to_string()),
+            _ => error(StatusCode::NOT_FOUND, format!("No process found listening on port {}", port)),
+        },
+        Err(e) => error(StatusCode::INTERNAL_SERVER_ERROR, e.to_string()),
+    }
+}
+
+async fn metrics(Path(pid): Path<u32>) -> Response {
+    let process = match collect_process_info(pid).await {
+        Ok(process) => process,
+        Err(e) => return error(StatusCode::NOT_FOUND, e.to_string()),
+    };
+    Json(Metrics {
+        process,
+        system: collect_system_snapshot(),
+        cgroup: read_cgroup_stats(pid),
+    })
+    .into_response()
+}
+
+async fn listeners(Path(pid): Path<u32>) -> Response {
+    match tokio::task::spawn_blocking(move || listening_ports(pid)).await {
+        Ok(Ok(listeners)) => Json(listeners).into_response(),
+        Ok(Err(e)) => error(StatusCode::INTERNAL_SERVER_ERROR, e.to_string()),
+        Err(e) => error(StatusCode::INTERNAL_SERVER_ERROR, e.to_string()),
+    }
+}
+
+/// The longest `?interval=` a stream may ask for.
+const MAX_STREAM_INTERVAL: Duration = Duration::from_secs(3600);
+
+async fn stream(
+    Path(pid): Path<u32>,
+    Query(query): Query<HashMap<String, String>>,
+    ws: WebSocketUpgrade,
+) -> Response {
+    let interval = match query.get("interval") {
+        None => Duration::from_secs(1),
+        Some(text) => match text
+            .parse::<f64>()
+            .ok()
+            .and_then(|secs| Duration::try_from_secs_f64(secs).ok())
+            .filter(|interval| (MIN_MEASURE_WINDOW..=MAX_STREAM_INTERVAL).contains(interval))
+        {
+            Some(interval) => interval,
+            None => {
+                let message = format!(
+                    "interval must be between {} and {} seconds",
+                    MIN_MEASURE_WINDOW.as_secs_f64(),
+                    MAX_STREAM_INTERVAL.as_secs()
+                );
+                return error(StatusCode::BAD_REQUEST, message);
+            }
+        },
+    };
+    ws.on_upgrade(move |socket| stream_samples(socket, pid, interval))
+}
+
+// Pushes one JSON `Sample` per tick until the process goes away or the client hangs up.
+async fn stream_samples(mut socket: WebSocket, pid: u32, interval: Duration) {
+    let mut samples = Box::pin(sample_stream(pid, interval));
+    while let Some(sample) = samples.next().await {
+        let Ok(json) = serde_json::to_string(&sample) else {
+            break;
+        };
+        if socket.send(Message::Text(json.into())).await.is_err() {
+            return;
+        }
+    }
+    let _ = socket.send(Message::Close(None)).await;
+}
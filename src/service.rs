@@ -0,0 +1,144 @@
+//! `--service`: a `[[service]]` from the config file watched as one, e.g.
+//! the web tier on 8080 and 8443 plus its queue workers.
+//!
+//! Members are looked up again on every tick, so workers that come and go
+//! and a port that is briefly down show up as they are. The service total
+//! counts each process once, even when a pattern also matches a listener.
+
+use std::collections::HashMap;
+use std::fmt;
+
+use anyhow::{anyhow, bail, Result};
+
+use crate::config::ServiceConfig;
+use crate::history::ProcessHistory;
+use crate::pattern::Pattern;
+use crate::process::{aggregate, ProcessInfo};
+use crate::source::MetricsSource;
+use crate::watch::HISTORY_LEN;
+
+/// How a member's processes are found.
+#[derive(Debug, Clone)]
+pub enum MemberKind {
+    /// Whatever listens on the port.
+    Port(u16),
+    /// Processes whose command line matches.
+    Pattern(Pattern),
+}
+
+impl fmt::Display for MemberKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            MemberKind::Port(port) => write!(f, "port {}", port),
+            MemberKind::Pattern(pattern) => write!(f, "match {}", pattern),
+        }
+    }
+}
+
+/// One port or pattern of the service, summed over its processes.
+pub struct Member {
+    pub kind: MemberKind,
+    /// The processes found on the latest tick; none while it is down.
+    pub pids: Vec<u32>,
+    pub info: Option<ProcessInfo>,
+    pub history: ProcessHistory,
+}
+
+/// The `[[service]]` called `name`.
+pub fn find<'a>(services: &'a [ServiceConfig], name: &str) -> Result<&'a ServiceConfig> {
+    services.iter().find(|s| s.name == name).ok_or_else(|| {
+        let known: Vec<&str> = services.iter().map(|s| s.name.as_str()).collect();
+        match known.is_empty() {
+            true => anyhow!("No service `{}`: the config file has no [[service]] tables", name),
+            false => anyhow!("No service `{}` in the config file (services: {})", name, known.join(", ")),
+        }
+    })
+}
+
+pub struct ServiceSession<S: MetricsSource> {
+    source: S,
+    pub name: String,
+    pub members: Vec<Member>,
+    /// Every member's processes together, each counted once.
+    pub total: Option<ProcessInfo>,
+    pub history: ProcessHistory,
+    pub iteration: u64,
+}
+
+impl<S: MetricsSource> ServiceSession<S> {
+    pub fn new(source: S, config: &ServiceConfig) -> Result<Self> {
+        if config.ports.is_empty() && config.patterns.is_empty() {
+            bail!("Service `{}` has neither ports nor match patterns", config.name);
+        }
+        let mut kinds: Vec<MemberKind> = config.ports.iter().map(|&port| MemberKind::Port(port)).collect();
+        for pattern in &config.patterns {
+            let pattern = Pattern::new(pattern).map_err(|e| anyhow!("Service `{}`: {}", config.name, e))?;
+            kinds.push(MemberKind::Pattern(pattern));
+        }
+        let members = kinds
+            .into_iter()
+            .map(|kind| Member {
+                kind,
+                pids: Vec::new(),
+                info: None,
+                history: ProcessHistory::new(HISTORY_LEN),
+            })
+            .collect();
+        Ok(Self {
+            source,
+            name: config.name.clone(),
+            members,
+            total: None,
+            history: ProcessHistory::new(HISTORY_LEN),
+            iteration: 0,
+        })
+    }
+
+    /// Finds every member's processes and samples them together. Only fails
+    /// when none of the members has a process.
+    pub async fn tick(&mut self) -> Result<()> {
+        for member in &mut self.members {
+            member.pids = match &member.kind {
+                MemberKind::Port(port) => self.source.resolve_pids(*port).unwrap_or_default(),
+                MemberKind::Pattern(pattern) => self.source.find_processes(pattern),
+            };
+        }
+        let mut pids: Vec<u32> = self.members.iter().flat_map(|m| m.pids.iter().copied()).collect();
+        pids.sort_unstable();
+        pids.dedup();
+        if pids.is_empty() {
+            for member in &mut self.members {
+                member.info = None;
+            }
+            self.total = None;
+            bail!("Nothing of service `{}` is running", self.name);
+        }
+
+        let infos = match self.source.sample_group(pids.clone()).await {
+            Some(infos) => infos,
+            None => {
+                let mut infos = Vec::with_capacity(pids.len());
+                for &pid in &pids {
+                    infos.push(self.source.sample(pid).await.ok());
+                }
+                infos
+            }
+        };
+        let sampled: HashMap<u32, ProcessInfo> = pids.iter().copied().zip(infos).filter_map(|(pid, info)| Some((pid, info?))).collect();
+
+        for member in &mut self.members {
+            let infos: Vec<ProcessInfo> = member.pids.iter().filter_map(|pid| sampled.get(pid).cloned()).collect();
+            member.info = aggregate(&infos);
+            if let Some(info) = &member.info {
+                member.history.add(info);
+            }
+        }
+        let all: Vec<ProcessInfo> = pids.iter().filter_map(|pid| sampled.get(pid).cloned()).collect();
+        self.total = aggregate(&all);
+        if let Some(total) = &self.total {
+            self.history.add(total);
+        }
+        self.iteration += 1;
+        Ok(())
+    }
+}
@@ -0,0 +1,144 @@
+//! Options of the sockets listening on the port, as far as they show from
+//! outside the process: the backlog `listen` was given (capped at
+//! `net.core.somaxconn`), the receive and send buffer sizes, and whether
+//! several sockets share an address, which takes `SO_REUSEPORT`. Connections
+//! inherit the listener's buffers, so two environments whose buffers or
+//! backlogs differ here behave differently under load.
+//!
+//! `SO_REUSEADDR` and `TCP_NODELAY` are only known to the process itself;
+//! `ss` doesn't report them for a listener.
+
+use serde::{Deserialize, Serialize};
+
+use crate::exec::Runner;
+use crate::port::{parse_listener, Listener};
+
+/// One or more sockets listening on the same address alike.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SocketOptions {
+    pub listener: Listener,
+    /// Identical sockets found on that address.
+    pub count: usize,
+    /// Sockets listening on that address in all; more than one takes
+    /// `SO_REUSEPORT` on every one of them.
+    pub sharing: usize,
+    /// The backlog in effect, after the cap at `somaxconn`.
+    pub backlog: u32,
+    /// `SO_RCVBUF`, in bytes (twice what was asked for, as the kernel keeps it).
+    pub recv_buffer: u64,
+    /// `SO_SNDBUF`, in bytes.
+    pub send_buffer: u64,
+}
+
+impl SocketOptions {
+    pub fn reuses_port(&self) -> bool {
+        self.sharing > 1
+    }
+}
+
+/// The port's listening sockets, with the host defaults to compare them to.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ListenerSockets {
+    pub sockets: Vec<SocketOptions>,
+    /// `net.core.somaxconn`: a backlog at this value may have been cut down.
+    pub somaxconn: Option<u32>,
+    /// The default of `net.ipv4.tcp_rmem`; a buffer of another size was set
+    /// with `SO_RCVBUF`.
+    pub default_recv_buffer: Option<u64>,
+    /// The default of `net.ipv4.tcp_wmem`.
+    pub default_send_buffer: Option<u64>,
+}
+
+impl ListenerSockets {
+    /// Whether `socket`'s backlog is at the `somaxconn` cap.
+    pub fn backlog_capped(&self, socket: &SocketOptions) -> bool {
+        self.somaxconn == Some(socket.backlog)
+    }
+}
+
+/// Reads the listening sockets on `port` with `ss -ltnmH`, and the sysctls
+/// they're compared to; Linux (or a host over ssh) only.
+pub fn listener_sockets_on(runner: &Runner, port: u16) -> Option<ListenerSockets> {
+    if !runner.is_remote() && !cfg!(target_os = "linux") {
+        return None;
+    }
+    let out = runner.output("ss", &["-ltnmH"]).ok().filter(|out| out.status.success())?;
+    let sockets = parse_socket_options(&String::from_utf8_lossy(&out.stdout), port);
+    if sockets.is_empty() {
+        return None;
+    }
+    let read = |path: &str| match runner {
+        Runner::Local | Runner::Elevated(_) => std::fs::read_to_string(path).ok(),
+        Runner::Ssh(_) => runner
+            .output("cat", &[path])
+            .ok()
+            .map(|out| String::from_utf8_lossy(&out.stdout).into_owned()),
+    };
+    Some(ListenerSockets {
+        sockets,
+        somaxconn: read("/proc/sys/net/core/somaxconn").and_then(|s| s.trim().parse().ok()),
+        default_recv_buffer: read("/proc/sys/net/ipv4/tcp_rmem").as_deref().and_then(parse_default_buffer),
+        default_send_buffer: read("/proc/sys/net/ipv4/tcp_wmem").as_deref().and_then(parse_default_buffer),
+    })
+}
+
+/// The `ss -ltnmH` sockets on `port`: a `LISTEN 0 511 0.0.0.0:8080 0.0.0.0:*`
+/// row, then its `skmem:(r0,rb131072,t0,tb16384,...)` on the next line.
+/// Alike sockets on one address are counted together, in the order found.
+pub fn parse_socket_options(ss: &str, port: u16) -> Vec<SocketOptions> {
+    let mut found: Vec<SocketOptions> = Vec::new();
+    let mut lines = ss.lines().peekable();
+    while let Some(line) = lines.next() {
+        let cols: Vec<&str> = line.split_whitespace().collect();
+        if cols.len() < 4 || cols[0] != "LISTEN" {
+            continue;
+        }
+        let Some(listener) = parse_listener(cols[3]).filter(|l| l.port == port) else {
+            continue;
+        };
+        let Ok(backlog) = cols[2].parse::<u32>() else {
+            continue;
+        };
+        // The memory line may be on the row itself, depending on ss's version.
+        let skmem = match lines.peek() {
+            Some(next) if next.trim_start().starts_with("skmem:") => lines.next(),
+            _ => Some(line),
+        };
+        let (recv_buffer, send_buffer) = skmem.and_then(parse_skmem).unwrap_or_default();
+        let alike = |s: &&mut SocketOptions| {
+            s.listener == listener && s.backlog == backlog && s.recv_buffer == recv_buffer && s.send_buffer == send_buffer
+        };
+        match found.iter_mut().find(alike) {
+            Some(same) => same.count += 1,
+            None => found.push(SocketOptions {
+                listener,
+                count: 1,
+                sharing: 1,
+                backlog,
+                recv_buffer,
+                send_buffer,
+            }),
+        }
+    }
+    let per_address: Vec<usize> = found
+        .iter()
+        .map(|s| found.iter().filter(|o| o.listener == s.listener).map(|o| o.count).sum())
+        .collect();
+    for (socket, sharing) in found.iter_mut().zip(per_address) {
+        socket.sharing = sharing;
+    }
+    found
+}
+
+/// `rb` and `tb` out of `skmem:(r0,rb131072,t0,tb16384,f0,w0,o0,bl0,d0)`.
+fn parse_skmem(line: &str) -> Option<(u64, u64)> {
+    let start = line.find("skmem:(")? + "skmem:(".len();
+    let fields = &line[start..start + line[start..].find(')')?];
+    let field = |name: &str| fields.split(',').find_map(|f| f.strip_prefix(name)?.parse().ok());
+    Some((field("rb")?, field("tb")?))
+}
+
+/// The middle of `tcp_rmem`/`tcp_wmem`'s `min default max`.
+fn parse_default_buffer(text: &str) -> Option<u64> {
+    text.split_whitespace().nth(1)?.parse().ok()
+}
@@ -0,0 +1,703 @@
+use std::collections::{HashMap, VecDeque};
+use std::future::Future;
+use std::sync::Arc;
+use std::time::Duration;
+
+use anyhow::{anyhow, Result};
+
+use crate::error::Error;
+use crate::exec::{Elevation, Runner};
+
+use crate::children::{local_children, ChildProcess};
+use crate::cgroup::{read_cgroup_stats, CgroupStats};
+use crate::cores::{core_times, CoreTimes};
+use crate::ebpf::{EbpfCollector, EbpfStats};
+use crate::files::{open_files, OpenFile};
+use crate::logs::{tail, LogSource, LogTail};
+use crate::oom::{oom_status, OomStatus};
+use crate::pattern::Pattern;
+use crate::posture::{security_posture_on, Posture};
+use crate::segments::{read_segments, MemorySegments};
+use crate::socket::{listener_sockets_on, ListenerSockets};
+use crate::runtime::{detect_on, stack_dump_on, Runtime, StackDump};
+use crate::port::{
+    accept_queue, connection_bytes, find_pids_by_port_on, listening_ports_on, port_connections, tcp_stats, AcceptQueue, ConnectionBytes, Listener, PortConnection,
+    TcpStats,
+};
+use crate::upstream::{upstream_ports_on, ProxyKind};
+use crate::syscalls::{task_syscalls_on, SyscallCounts};
+use crate::systemd::{unit_action_on, unit_status_on, UnitAction, UnitStatus};
+use crate::process::{children_on, cmdline_on, collect_system_snapshot, find_processes, process_name, ProcessInfo, ProcessSampler, SystemSnapshot};
+
+/// Where port lookups and process samples come from.
+///
+/// The watch loop, snapshot mode and anything downstream of them only talk to
+/// this trait, so they can be driven by `ScriptedSource` in tests instead of
+/// needing a real process bound to a real port.
+pub trait MetricsSource {
+    /// Every PID listening on `port`; never empty on success.
+    fn resolve_pids(&self, port: u16) -> Result<Vec<u32>>;
+
+    fn resolve_pid(&self, port: u16) -> Result<u32> {
+        self.resolve_pids(port).map(|pids| pids[0])
+    }
+
+    /// Short name of `pid`, for listing a port's candidates; `None` if unknown.
+    fn process_name(&self, _pid: u32) -> Option<String> {
+        None
+    }
+
+    /// `pid`'s full command line, for `--match`; `None` if unknown.
+    fn cmdline(&self, _pid: u32) -> Option<String> {
+        None
+    }
+
+    /// PIDs whose parent is `pid`, e.g. a prefork server's workers.
+    fn children(&self, _pid: u32) -> Vec<u32> {
+        Vec::new()
+    }
+
+    /// Every process whose command line matches `pattern`, lowest PID
+    /// first, for `[[service]]` members that don't listen on a port.
+    fn find_processes(&self, _pattern: &Pattern) -> Vec<u32> {
+        Vec::new()
+    }
+
+    /// The children of `pids`, zombies included, for the child-process feed.
+    fn child_processes(&mut self, _pids: &[u32]) -> Vec<ChildProcess> {
+        Vec::new()
+    }
+
+    /// Ports on this host the reverse proxy `pid` forwards to, for
+    /// `--upstream`; `listening` are the proxy's own ports.
+    fn upstream_ports(&self, _pid: u32, _kind: ProxyKind, _listening: &[u16]) -> Vec<u16> {
+        Vec::new()
+    }
+
+    fn sample(&mut self, pid: u32) -> impl Future<Output = Result<ProcessInfo>> + Send;
+
+    /// Samples `pids` together, e.g. a prefork server's workers: one entry per
+    /// PID, `None` for any that couldn't be read. `None` if the source can't.
+    fn sample_group(&mut self, _pids: Vec<u32>) -> impl Future<Output = Option<Vec<Option<ProcessInfo>>>> + Send {
+        async { None }
+    }
+
+    fn system(&mut self) -> Result<SystemSnapshot>;
+
+    /// Every TCP socket `pid` is listening on, not just the one we looked up.
+    fn listeners(&mut self, pid: u32) -> Result<Vec<Listener>>;
+
+    /// Limits and usage of the cgroup `pid` runs in, where there is one.
+    fn cgroup(&mut self, pid: u32) -> Option<CgroupStats>;
+
+    /// Accept-queue depth and backlog of the sockets listening on `port`.
+    fn accept_queue(&mut self, _port: u16) -> Option<AcceptQueue> {
+        None
+    }
+
+    /// RTT, congestion window and retransmits of the port's established connections.
+    fn tcp_stats(&mut self, _port: u16) -> Option<TcpStats> {
+        None
+    }
+
+    /// Backlog, buffer sizes and port sharing of the sockets listening on `port`.
+    fn listener_sockets(&mut self, _port: u16) -> Option<ListenerSockets> {
+        None
+    }
+
+    /// Every connection on the port, open or closing, for churn rates.
+    fn port_connections(&mut self, _port: u16) -> Option<Vec<PortConnection>> {
+        None
+    }
+
+    /// Bytes sent and received so far by each established connection on the
+    /// port, for the peers' traffic.
+    fn connection_bytes(&mut self, _port: u16) -> Option<Vec<ConnectionBytes>> {
+        None
+    }
+
+    /// Accepts, retransmits and syscall time of `pid` on `port`, counted by
+    /// eBPF probes since they were attached (`ebpf` feature); `None` without.
+    fn ebpf_stats(&mut self, _pid: u32, _port: u16) -> Option<EbpfStats> {
+        None
+    }
+
+    /// The syscall each of `pid`'s threads is in right now, by category,
+    /// with those on a CPU counted as `running`.
+    fn task_syscalls(&mut self, _pid: u32) -> Option<SyscallCounts> {
+        None
+    }
+
+    /// `pid`'s OOM-killer score, its rank on the host and memory pressure.
+    fn oom_status(&mut self, _pid: u32) -> Option<OomStatus> {
+        None
+    }
+
+    /// `pid`'s user, capabilities, binary and LSM confinement.
+    fn security_posture(&mut self, _pid: u32) -> Option<Posture> {
+        None
+    }
+
+    /// `pid`'s resident memory by kind of mapping (heap, anonymous, files).
+    fn memory_segments(&mut self, _pid: u32) -> Option<MemorySegments> {
+        None
+    }
+
+    /// Per-core CPU ticks of the host and the core each of `pid`'s threads last ran on.
+    fn core_times(&mut self, _pid: u32) -> Option<CoreTimes> {
+        None
+    }
+
+    /// Everything `pid` has open, by descriptor number.
+    fn open_files(&mut self, _pid: u32) -> Option<Vec<OpenFile>> {
+        None
+    }
+
+    /// The systemd service `pid` runs under, with its state and limits.
+    fn systemd_unit(&mut self, _pid: u32) -> Option<UnitStatus> {
+        None
+    }
+
+    /// The last `lines` lines of a log on the process's host.
+    fn log_tail(&mut self, _source: &LogSource, _lines: usize) -> Option<LogTail> {
+        None
+    }
+
+    /// Restarts or stops `unit` through systemctl.
+    fn unit_action(&mut self, unit: &str, _action: UnitAction) -> Result<()> {
+        Err(anyhow!("Cannot control {} from this source", unit))
+    }
+
+    /// The language runtime `pid` runs on, if it's one we can dump stacks of.
+    fn runtime(&mut self, _pid: u32) -> Option<Runtime> {
+        None
+    }
+
+    /// What each thread of `pid` is doing right now.
+    fn stack_dump(&mut self, _pid: u32, _runtime: Runtime) -> Option<StackDump> {
+        None
+    }
+}
+
+/// The default source: `lsof`/`ss`/`netstat` for lookups, `sysinfo` for samples.
+#[derive(Debug, Default, Clone)]
+pub struct SystemSource {
+    lookup: Runner,
+    /// Keeps each process's CPU time between samples.
+    sampler: ProcessSampler,
+    /// The PID the eBPF probes were attached for, and the probes if that worked.
+    ebpf: Option<(u32, Option<Arc<EbpfCollector>>)>,
+}
+
+impl SystemSource {
+    /// Runs the port lookups (only those) through `sudo` or `pkexec`, so
+    /// other users' listeners can be found; samples still come from sysinfo.
+    pub fn elevated(elevation: Elevation) -> Self {
+        Self {
+            lookup: Runner::Elevated(elevation),
+            ..Self::default()
+        }
+    }
+
+    /// How long a process's first sample measures CPU over; later ones cover
+    /// the time since the one before.
+    pub fn measure_window(mut self, window: Duration) -> Self {
+        self.sampler = ProcessSampler::new(window);
+        self
+    }
+}
+
+impl MetricsSource for SystemSource {
+    fn resolve_pids(&self, port: u16) -> Result<Vec<u32>> {
+        find_pids_by_port_on(&self.lookup, port)
+    }
+
+    fn process_name(&self, pid: u32) -> Option<String> {
+        process_name(pid)
+    }
+
+    fn cmdline(&self, pid: u32) -> Option<String> {
+        cmdline_on(&Runner::Local, pid)
+    }
+
+    fn children(&self, pid: u32) -> Vec<u32> {
+        children_on(&Runner::Local, pid)
+    }
+
+    fn find_processes(&self, pattern: &Pattern) -> Vec<u32> {
+        find_processes(pattern)
+    }
+
+    fn child_processes(&mut self, pids: &[u32]) -> Vec<ChildProcess> {
+        local_children(pids)
+    }
+
+    fn upstream_ports(&self, pid: u32, kind: ProxyKind, listening: &[u16]) -> Vec<u16> {
+        upstream_ports_on(&self.lookup, pid, kind, listening)
+    }
+
+    fn sample(&mut self, pid: u32) -> impl Future<Output = Result<ProcessInfo>> + Send {
+        let sampler = self.sampler.clone();
+        async move { sampler.sample(pid).await }
+    }
+
+    fn sample_group(&mut self, pids: Vec<u32>) -> impl Future<Output = Option<Vec<Option<ProcessInfo>>>> + Send {
+        let sampler = self.sampler.clone();
+        async move { sampler.sample_group(pids).await }
+    }
+
+    fn system(&mut self) -> Result<SystemSnapshot> {
+        Ok(collect_system_snapshot())
+    }
+
+    fn listeners(&mut self, pid: u32) -> Result<Vec<Listener>> {
+        listening_ports_on(&self.lookup, pid)
+    }
+
+    fn cgroup(&mut self, pid: u32) -> Option<CgroupStats> {
+        read_cgroup_stats(pid)
+    }
+
+    fn accept_queue(&mut self, port: u16) -> Option<AcceptQueue> {
+        accept_queue(port)
+    }
+
+    fn tcp_stats(&mut self, port: u16) -> Option<TcpStats> {
+        tcp_stats(port)
+    }
+
+    fn listener_sockets(&mut self, port: u16) -> Option<ListenerSockets> {
+        listener_sockets_on(&Runner::Local, port)
+    }
+
+    fn port_connections(&mut self, port: u16) -> Option<Vec<PortConnection>> {
+        port_connections(port)
+    }
+
+    fn connection_bytes(&mut self, port: u16) -> Option<Vec<ConnectionBytes>> {
+        connection_bytes(port)
+    }
+
+    fn ebpf_stats(&mut self, pid: u32, port: u16) -> Option<EbpfStats> {
+        if self.ebpf.as_ref().is_none_or(|(attached, _)| *attached != pid) {
+            self.ebpf = Some((pid, EbpfCollector::attach(pid, port).map(Arc::new)));
+        }
+        self.ebpf.as_ref()?.1.as_ref()?.read()
+    }
+
+    fn task_syscalls(&mut self, pid: u32) -> Option<SyscallCounts> {
+        task_syscalls_on(&self.lookup, pid)
+    }
+
+    fn oom_status(&mut self, pid: u32) -> Option<OomStatus> {
+        oom_status(pid)
+    }
+
+    fn security_posture(&mut self, pid: u32) -> Option<Posture> {
+        security_posture_on(&self.lookup, pid)
+    }
+
+    fn memory_segments(&mut self, pid: u32) -> Option<MemorySegments> {
+        read_segments(pid)
+    }
+
+    fn core_times(&mut self, pid: u32) -> Option<CoreTimes> {
+        core_times(pid)
+    }
+
+    fn open_files(&mut self, pid: u32) -> Option<Vec<OpenFile>> {
+        open_files(pid)
+    }
+
+    fn systemd_unit(&mut self, pid: u32) -> Option<UnitStatus> {
+        unit_status_on(&Runner::Local, pid)
+    }
+
+    fn log_tail(&mut self, source: &LogSource, lines: usize) -> Option<LogTail> {
+        Some(tail(&Runner::Local, source, lines))
+    }
+
+    fn unit_action(&mut self, unit: &str, action: UnitAction) -> Result<()> {
+        unit_action_on(&Runner::Local, unit, action)
+    }
+
+    fn runtime(&mut self, pid: u32) -> Option<Runtime> {
+        detect_on(&Runner::Local, pid)
+    }
+
+    fn stack_dump(&mut self, pid: u32, runtime: Runtime) -> Option<StackDump> {
+        Some(stack_dump_on(&Runner::Local, pid, runtime))
+    }
+}
+
+/// A canned source that replays a fixed script of samples.
+///
+/// Each call to `sample` pops the next scripted entry; once the script runs out
+/// the process is reported as gone, which is how watch mode ends in tests.
+#[derive(Debug, Default)]
+pub struct ScriptedSource {
+    ports: HashMap<u16, Vec<u32>>,
+    /// `Err(None)` is the process having exited.
+    script: VecDeque<std::result::Result<ProcessInfo, Option<String>>>,
+    system: Option<SystemSnapshot>,
+    listeners: HashMap<u32, Vec<Listener>>,
+    listener_sets: HashMap<u32, VecDeque<Vec<Listener>>>,
+    cgroups: VecDeque<CgroupStats>,
+    accept_queues: VecDeque<AcceptQueue>,
+    tcp_stats: VecDeque<TcpStats>,
+    sockets: VecDeque<ListenerSockets>,
+    oom: VecDeque<OomStatus>,
+    postures: VecDeque<Posture>,
+    segments: VecDeque<MemorySegments>,
+    core_times: VecDeque<CoreTimes>,
+    cmdlines: HashMap<u32, String>,
+    children: HashMap<u32, Vec<u32>>,
+    child_lists: VecDeque<Vec<ChildProcess>>,
+    groups: VecDeque<Vec<ProcessInfo>>,
+    upstreams: HashMap<u32, Vec<u16>>,
+    connections: VecDeque<Vec<PortConnection>>,
+    connection_bytes: VecDeque<Vec<ConnectionBytes>>,
+    ebpf: VecDeque<EbpfStats>,
+    task_syscalls: VecDeque<SyscallCounts>,
+}
+
+impl ScriptedSource {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Pretend `pid` is listening on `port` (on all addresses). Calling this
+    /// again for the same port adds another listener, as with SO_REUSEPORT.
+    pub fn with_listener(mut self, port: u16, pid: u32) -> Self {
+        self.ports.entry(port).or_default().push(pid);
+        self.listeners.entry(pid).or_default().push(Listener {
+            address: "*".to_string(),
+            port,
+        });
+        self
+    }
+
+    /// Queue the sockets the next lookup of `pid`'s listeners finds; the
+    /// last one queued keeps being reported, and without any the
+    /// `with_listener` ones are.
+    pub fn push_listeners(mut self, pid: u32, listeners: Vec<Listener>) -> Self {
+        self.listener_sets.entry(pid).or_default().push_back(listeners);
+        self
+    }
+
+    /// Give `pid` a command line, for `--match`.
+    pub fn with_cmdline(mut self, pid: u32, cmdline: impl Into<String>) -> Self {
+        self.cmdlines.insert(pid, cmdline.into());
+        self
+    }
+
+    /// Pretend `child` was forked by `parent`.
+    pub fn with_child(mut self, parent: u32, child: u32) -> Self {
+        self.children.entry(parent).or_default().push(child);
+        self
+    }
+
+    /// Queue the children the next sample finds, for the child-process
+    /// feed; without any, the `with_child` ones are found every time.
+    pub fn push_children(mut self, children: Vec<ChildProcess>) -> Self {
+        self.child_lists.push_back(children);
+        self
+    }
+
+    /// Pretend the proxy `pid` forwards to `port`.
+    pub fn with_upstream(mut self, pid: u32, port: u16) -> Self {
+        self.upstreams.entry(pid).or_default().push(port);
+        self
+    }
+
+    /// Queue a successful sample.
+    pub fn push_sample(mut self, info: ProcessInfo) -> Self {
+        self.script.push_back(Ok(info));
+        self
+    }
+
+    /// Queue a failed sample with the given error message.
+    pub fn push_error(mut self, message: impl Into<String>) -> Self {
+        self.script.push_back(Err(Some(message.into())));
+        self
+    }
+
+    /// Queue a sample that finds the process gone, as running out of script does.
+    pub fn push_exit(mut self) -> Self {
+        self.script.push_back(Err(None));
+        self
+    }
+
+    /// Report `snapshot` for every `system` call.
+    pub fn with_system(mut self, snapshot: SystemSnapshot) -> Self {
+        self.system = Some(snapshot);
+        self
+    }
+
+    /// Queue a cgroup reading; the last one queued keeps being reported.
+    pub fn push_cgroup(mut self, stats: CgroupStats) -> Self {
+        self.cgroups.push_back(stats);
+        self
+    }
+
+    /// Queue an accept-queue reading; the last one queued keeps being reported.
+    pub fn push_accept_queue(mut self, queue: AcceptQueue) -> Self {
+        self.accept_queues.push_back(queue);
+        self
+    }
+
+    /// Queue a `tcp_info` summary; the last one queued keeps being reported.
+    pub fn push_tcp_stats(mut self, stats: TcpStats) -> Self {
+        self.tcp_stats.push_back(stats);
+        self
+    }
+
+    /// Queue a reading of the listening sockets' options; the last one
+    /// queued keeps being reported.
+    pub fn push_listener_sockets(mut self, sockets: ListenerSockets) -> Self {
+        self.sockets.push_back(sockets);
+        self
+    }
+
+    /// Queue a socket table of the port; the last one queued keeps being reported.
+    pub fn push_connections(mut self, connections: Vec<PortConnection>) -> Self {
+        self.connections.push_back(connections);
+        self
+    }
+
+    /// Queue the connections' byte counters; the last one queued keeps being reported.
+    pub fn push_connection_bytes(mut self, bytes: Vec<ConnectionBytes>) -> Self {
+        self.connection_bytes.push_back(bytes);
+        self
+    }
+
+    /// Queue eBPF counters; the last one queued keeps being reported.
+    pub fn push_ebpf_stats(mut self, stats: EbpfStats) -> Self {
+        self.ebpf.push_back(stats);
+        self
+    }
+
+    /// Queue a sampling of the threads' syscalls; the last one queued keeps being reported.
+    pub fn push_task_syscalls(mut self, counts: SyscallCounts) -> Self {
+        self.task_syscalls.push_back(counts);
+        self
+    }
+
+    /// Queue an OOM reading; the last one queued keeps being reported.
+    pub fn push_oom_status(mut self, status: OomStatus) -> Self {
+        self.oom.push_back(status);
+        self
+    }
+
+    /// Queue a security posture; the last one queued keeps being reported.
+    pub fn push_posture(mut self, posture: Posture) -> Self {
+        self.postures.push_back(posture);
+        self
+    }
+
+    /// Queue a reading of the memory segments; the last one queued keeps
+    /// being reported.
+    pub fn push_segments(mut self, segments: MemorySegments) -> Self {
+        self.segments.push_back(segments);
+        self
+    }
+
+    /// Queue a per-core reading; the last one queued keeps being reported.
+    pub fn push_core_times(mut self, times: CoreTimes) -> Self {
+        self.core_times.push_back(times);
+        self
+    }
+
+    /// Queue the processes a `sample_group` call finds alive, matched to the
+    /// requested PIDs by `ProcessInfo::pid`; the last one queued keeps being reported.
+    pub fn push_group(mut self, alive: Vec<ProcessInfo>) -> Self {
+        self.groups.push_back(alive);
+        self
+    }
+
+    /// Number of scripted entries not yet consumed.
+    pub fn remaining(&self) -> usize {
+        self.script.len()
+    }
+}
+
+impl MetricsSource for ScriptedSource {
+    fn resolve_pids(&self, port: u16) -> Result<Vec<u32>> {
+        self.ports
+            .get(&port)
+            .filter(|pids| !pids.is_empty())
+            .cloned()
+            .ok_or_else(|| Error::PortNotFound { port }.into())
+    }
+
+    fn cmdline(&self, pid: u32) -> Option<String> {
+        self.cmdlines.get(&pid).cloned()
+    }
+
+    fn children(&self, pid: u32) -> Vec<u32> {
+        self.children.get(&pid).cloned().unwrap_or_default()
+    }
+
+    fn find_processes(&self, pattern: &Pattern) -> Vec<u32> {
+        let mut pids: Vec<u32> = self.cmdlines.iter().filter(|(_, cmdline)| pattern.is_match(cmdline)).map(|(&pid, _)| pid).collect();
+        pids.sort_unstable();
+        pids
+    }
+
+    fn child_processes(&mut self, pids: &[u32]) -> Vec<ChildProcess> {
+        if let Some(children) = self.child_lists.pop_front() {
+            return children;
+        }
+        let mut children = Vec::new();
+        for &ppid in pids {
+            for pid in self.children(ppid) {
+                let command = self.cmdlines.get(&pid).cloned().unwrap_or_default();
+                children.push(ChildProcess {
+                    pid,
+                    ppid,
+                    command,
+                    zombie: false,
+                    exit: None,
+                });
+            }
+        }
+        children
+    }
+
+    fn upstream_ports(&self, pid: u32, _kind: ProxyKind, _listening: &[u16]) -> Vec<u16> {
+        self.upstreams.get(&pid).cloned().unwrap_or_default()
+    }
+
+    fn sample(&mut self, pid: u32) -> impl Future<Output = Result<ProcessInfo>> + Send {
+        let next = match self.script.pop_front() {
+            Some(Ok(info)) => Ok(info),
+            Some(Err(Some(message))) => Err(anyhow!(message)),
+            Some(Err(None)) | None => Err(Error::ProcessGone { pid }.into()),
+        };
+        async move { next }
+    }
+
+    fn sample_group(&mut self, pids: Vec<u32>) -> impl Future<Output = Option<Vec<Option<ProcessInfo>>>> + Send {
+        let alive = if self.groups.len() > 1 {
+            self.groups.pop_front()
+        } else {
+            self.groups.front().cloned()
+        };
+        let group = alive.map(|alive| pids.iter().map(|&pid| alive.iter().find(|i| i.pid == pid).cloned()).collect());
+        async move { group }
+    }
+
+    fn system(&mut self) -> Result<SystemSnapshot> {
+        self.system
+            .clone()
+            .ok_or_else(|| anyhow!("No system snapshot scripted"))
+    }
+
+    fn listeners(&mut self, pid: u32) -> Result<Vec<Listener>> {
+        if let Some(sets) = self.listener_sets.get_mut(&pid) {
+            if sets.len() > 1 {
+                return Ok(sets.pop_front().unwrap_or_default());
+            }
+            if let Some(set) = sets.front() {
+                return Ok(set.clone());
+            }
+        }
+        Ok(self.listeners.get(&pid).cloned().unwrap_or_default())
+    }
+
+    fn cgroup(&mut self, _pid: u32) -> Option<CgroupStats> {
+        if self.cgroups.len() > 1 {
+            self.cgroups.pop_front()
+        } else {
+            self.cgroups.front().cloned()
+        }
+    }
+
+    fn accept_queue(&mut self, _port: u16) -> Option<AcceptQueue> {
+        if self.accept_queues.len() > 1 {
+            self.accept_queues.pop_front()
+        } else {
+            self.accept_queues.front().copied()
+        }
+    }
+
+    fn tcp_stats(&mut self, _port: u16) -> Option<TcpStats> {
+        if self.tcp_stats.len() > 1 {
+            self.tcp_stats.pop_front()
+        } else {
+            self.tcp_stats.front().copied()
+        }
+    }
+
+    fn listener_sockets(&mut self, _port: u16) -> Option<ListenerSockets> {
+        if self.sockets.len() > 1 {
+            self.sockets.pop_front()
+        } else {
+            self.sockets.front().cloned()
+        }
+    }
+
+    fn port_connections(&mut self, _port: u16) -> Option<Vec<PortConnection>> {
+        if self.connections.len() > 1 {
+            self.connections.pop_front()
+        } else {
+            self.connections.front().cloned()
+        }
+    }
+
+    fn connection_bytes(&mut self, _port: u16) -> Option<Vec<ConnectionBytes>> {
+        if self.connection_bytes.len() > 1 {
+            self.connection_bytes.pop_front()
+        } else {
+            self.connection_bytes.front().cloned()
+        }
+    }
+
+    fn ebpf_stats(&mut self, _pid: u32, _port: u16) -> Option<EbpfStats> {
+        if self.ebpf.len() > 1 {
+            self.ebpf.pop_front()
+        } else {
+            self.ebpf.front().copied()
+        }
+    }
+
+    fn task_syscalls(&mut self, _pid: u32) -> Option<SyscallCounts> {
+        if self.task_syscalls.len() > 1 {
+            self.task_syscalls.pop_front()
+        } else {
+            self.task_syscalls.front().copied()
+        }
+    }
+
+    fn memory_segments(&mut self, _pid: u32) -> Option<MemorySegments> {
+        if self.segments.len() > 1 {
+            self.segments.pop_front()
+        } else {
+            self.segments.front().copied()
+        }
+    }
+
+    fn oom_status(&mut self, _pid: u32) -> Option<OomStatus> {
+        if self.oom.len() > 1 {
+            self.oom.pop_front()
+        } else {
+            self.oom.front().copied()
+        }
+    }
+
+    fn security_posture(&mut self, _pid: u32) -> Option<Posture> {
+        if self.postures.len() > 1 {
+            self.postures.pop_front()
+        } else {
+            self.postures.front().cloned()
+        }
+    }
+
+    fn core_times(&mut self, _pid: u32) -> Option<CoreTimes> {
+        if self.core_times.len() > 1 {
+            self.core_times.pop_front()
+        } else {
+            self.core_times.front().cloned()
+        }
+    }
+}
@@ -0,0 +1,337 @@
+//! Inspecting a process on another machine over SSH.
+//!
+//! Nothing is installed on the remote host: port lookups run the same
+//! `lsof`/`ss`/`netstat` commands through `ssh`, and samples are a single
+//! `cat` of the relevant `/proc` files per tick, parsed here. The dashboard
+//! is rendered locally. Remote hosts are assumed to be Linux.
+
+use std::collections::HashMap;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use anyhow::{anyhow, Context, Result};
+
+use crate::cgroup::CgroupStats;
+use crate::cores::{core_times_on, CoreTimes};
+use crate::files::{open_files_on, OpenFile};
+use crate::logs::{tail, LogSource, LogTail};
+use crate::systemd::{unit_action_on, unit_status_on, UnitAction, UnitStatus};
+use crate::error::Error;
+use crate::exec::Runner;
+use crate::posture::{security_posture_on, Posture};
+use crate::port::{
+    accept_queue_on, connection_bytes_on, find_pids_by_port_on, listening_ports_on, port_connections_on, tcp_stats_on, AcceptQueue, ConnectionBytes, Listener,
+    PortConnection, TcpStats,
+};
+use crate::oom::{oom_status_on, OomStatus};
+use crate::process::{children_on, cmdline_on, ProcessCounters, ProcessInfo, SystemSnapshot, DEFAULT_MEASURE_WINDOW};
+use crate::procfs::{parse_loadavg, parse_meminfo, parse_smaps_rollup, parse_stat, parse_status, parse_uptime, SmapsRollup};
+use crate::runtime::{detect_on, stack_dump_on, Runtime, StackDump};
+use crate::socket::{listener_sockets_on, ListenerSockets};
+use crate::source::MetricsSource;
+use crate::syscalls::{task_syscalls_on, SyscallCounts};
+use crate::upstream::{upstream_ports_on, ProxyKind};
+
+/// Separates the sections of the per-sample script's output.
+const MARKER: &str = "::crabtop::";
+
+/// A `MetricsSource` that runs every probe on `target` through `ssh`.
+#[derive(Debug, Clone)]
+pub struct SshSource {
+    runner: Runner,
+    clock_ticks: f64,
+    /// Previous (uptime secs, utime + stime ticks) per PID, for CPU deltas.
+    last_cpu: HashMap<u32, (f64, u64)>,
+    /// How long the first sample of a PID measures CPU over.
+    window: Duration,
+}
+
+impl SshSource {
+    /// Checks `target` is reachable without a password prompt and reads its
+    /// clock tick rate. Authentication has to be key- or agent-based.
+    pub fn connect(target: &str) -> Result<Self> {
+        Self::with_runner(Runner::Ssh(target.to_string()))
+            .with_context(|| format!("Failed to reach {} over ssh", target))
+    }
+
+    /// Same as `connect` with an arbitrary runner; `Runner::Local` reads this
+    /// machine's `/proc` the way a remote one would be read.
+    pub fn with_runner(runner: Runner) -> Result<Self> {
+        let out = run(&runner, "getconf CLK_TCK")?;
+        let clock_ticks = out.trim().parse().unwrap_or(100.0);
+        Ok(Self {
+            runner,
+            clock_ticks,
+            last_cpu: HashMap::new(),
+            window: DEFAULT_MEASURE_WINDOW,
+        })
+    }
+
+    /// How long a process's first sample measures CPU over, as `SystemSource::measure_window`.
+    pub fn measure_window(mut self, window: Duration) -> Self {
+        self.window = window;
+        self
+    }
+
+    pub fn runner(&self) -> &Runner {
+        &self.runner
+    }
+}
+
+impl MetricsSource for SshSource {
+    fn resolve_pids(&self, port: u16) -> Result<Vec<u32>> {
+        find_pids_by_port_on(&self.runner, port)
+    }
+
+    fn process_name(&self, pid: u32) -> Option<String> {
+        run(&self.runner, &format!("cat /proc/{}/comm", pid))
+            .ok()
+            .map(|name| name.trim().to_string())
+    }
+
+    fn cmdline(&self, pid: u32) -> Option<String> {
+        cmdline_on(&self.runner, pid)
+    }
+
+    fn children(&self, pid: u32) -> Vec<u32> {
+        children_on(&self.runner, pid)
+    }
+
+    fn upstream_ports(&self, pid: u32, kind: ProxyKind, listening: &[u16]) -> Vec<u16> {
+        upstream_ports_on(&self.runner, pid, kind, listening)
+    }
+
+    async fn sample(&mut self, pid: u32) -> Result<ProcessInfo> {
+        let mut reading = read_remote(&self.runner, pid).await?;
+        let previous = match self.last_cpu.get(&pid) {
+            Some(&previous) => previous,
+            None => {
+                let first = (reading.uptime, reading.ticks);
+                tokio::time::sleep(self.window).await;
+                reading = read_remote(&self.runner, pid).await?;
+                first
+            }
+        };
+        self.last_cpu.insert(pid, (reading.uptime, reading.ticks));
+
+        let elapsed = reading.uptime - previous.0;
+        let cpu_percent = if elapsed > 0.0 {
+            (reading.ticks.saturating_sub(previous.1) as f64 / self.clock_ticks / elapsed * 100.0) as f32
+        } else {
+            0.0
+        };
+        let uptime_secs = (reading.uptime - reading.start_ticks as f64 / self.clock_ticks).max(0.0) as u64;
+        let now = SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.as_secs()).unwrap_or(0);
+
+        Ok(ProcessInfo {
+            name: reading.name,
+            pid,
+            cpu_percent,
+            memory_bytes: reading.rss_bytes,
+            virtual_bytes: reading.vm_size_bytes,
+            shared_bytes: reading.smaps.as_ref().map(SmapsRollup::shared_bytes),
+            swap_bytes: reading.smaps.map(|s| s.swap_bytes),
+            commit_bytes: None,
+            counters: reading.counters,
+            user: reading.user,
+            cmdline: reading.cmdline,
+            exe: reading.exe.map(Into::into),
+            cwd: reading.cwd.map(Into::into),
+            start_time: now.saturating_sub(uptime_secs),
+            uptime_secs,
+        })
+    }
+
+    async fn sample_group(&mut self, pids: Vec<u32>) -> Option<Vec<Option<ProcessInfo>>> {
+        let mut infos = Vec::with_capacity(pids.len());
+        for pid in pids {
+            infos.push(self.sample(pid).await.ok());
+        }
+        Some(infos)
+    }
+
+    fn system(&mut self) -> Result<SystemSnapshot> {
+        let script = format!("cat /proc/loadavg /proc/meminfo && echo {MARKER} && nproc");
+        let out = run(&self.runner, &script)?;
+        let (procfs, nproc) = out
+            .split_once(MARKER)
+            .ok_or_else(|| anyhow!("Unexpected /proc output{}", self.runner.describe()))?;
+        let load_average = procfs
+            .lines()
+            .next()
+            .and_then(parse_loadavg)
+            .ok_or_else(|| anyhow!("Failed to parse /proc/loadavg{}", self.runner.describe()))?;
+        let meminfo = parse_meminfo(procfs);
+        let field = |name: &str| meminfo.get(name).copied().unwrap_or(0);
+
+        Ok(SystemSnapshot {
+            load_average,
+            total_memory_bytes: field("MemTotal"),
+            available_memory_bytes: field("MemAvailable"),
+            total_swap_bytes: field("SwapTotal"),
+            used_swap_bytes: field("SwapTotal").saturating_sub(field("SwapFree")),
+            cpu_count: nproc.trim().parse().unwrap_or(0),
+        })
+    }
+
+    fn listeners(&mut self, pid: u32) -> Result<Vec<Listener>> {
+        listening_ports_on(&self.runner, pid)
+    }
+
+    fn cgroup(&mut self, _pid: u32) -> Option<CgroupStats> {
+        None
+    }
+
+    fn accept_queue(&mut self, port: u16) -> Option<AcceptQueue> {
+        accept_queue_on(&self.runner, port)
+    }
+
+    fn tcp_stats(&mut self, port: u16) -> Option<TcpStats> {
+        tcp_stats_on(&self.runner, port)
+    }
+
+    fn listener_sockets(&mut self, port: u16) -> Option<ListenerSockets> {
+        listener_sockets_on(&self.runner, port)
+    }
+
+    fn port_connections(&mut self, port: u16) -> Option<Vec<PortConnection>> {
+        port_connections_on(&self.runner, port)
+    }
+
+    fn connection_bytes(&mut self, port: u16) -> Option<Vec<ConnectionBytes>> {
+        connection_bytes_on(&self.runner, port)
+    }
+
+    fn task_syscalls(&mut self, pid: u32) -> Option<SyscallCounts> {
+        task_syscalls_on(&self.runner, pid)
+    }
+
+    fn oom_status(&mut self, pid: u32) -> Option<OomStatus> {
+        oom_status_on(&self.runner, pid)
+    }
+
+    fn security_posture(&mut self, pid: u32) -> Option<Posture> {
+        security_posture_on(&self.runner, pid)
+    }
+
+    fn core_times(&mut self, pid: u32) -> Option<CoreTimes> {
+        core_times_on(&self.runner, pid)
+    }
+
+    fn open_files(&mut self, pid: u32) -> Option<Vec<OpenFile>> {
+        open_files_on(&self.runner, pid)
+    }
+
+    fn systemd_unit(&mut self, pid: u32) -> Option<UnitStatus> {
+        unit_status_on(&self.runner, pid)
+    }
+
+    fn log_tail(&mut self, source: &LogSource, lines: usize) -> Option<LogTail> {
+        Some(tail(&self.runner, source, lines))
+    }
+
+    fn unit_action(&mut self, unit: &str, action: UnitAction) -> Result<()> {
+        unit_action_on(&self.runner, unit, action)
+    }
+
+    fn runtime(&mut self, pid: u32) -> Option<Runtime> {
+        detect_on(&self.runner, pid)
+    }
+
+    fn stack_dump(&mut self, pid: u32, runtime: Runtime) -> Option<StackDump> {
+        Some(stack_dump_on(&self.runner, pid, runtime))
+    }
+}
+
+/// One remote read of a process, before CPU is turned into a percentage.
+struct Reading {
+    uptime: f64,
+    ticks: u64,
+    start_ticks: u64,
+    name: String,
+    rss_bytes: u64,
+    vm_size_bytes: u64,
+    smaps: Option<SmapsRollup>,
+    counters: Option<ProcessCounters>,
+    user: Option<String>,
+    cmdline: Vec<String>,
+    exe: Option<String>,
+    cwd: Option<String>,
+}
+
+async fn read_remote(runner: &Runner, pid: u32) -> Result<Reading> {
+    // The first two files must exist or the process is gone; the rest are
+    // best-effort (exe/cwd need the same user or root).
+    let script = format!(
+        "p=/proc/{pid}; cat /proc/uptime $p/stat && echo ::crabtop::status && cat $p/status && \
+         echo ::crabtop::cmdline && tr '\\0' '\\n' < $p/cmdline; \
+         echo ::crabtop::user; getent passwd \"$(awk '/^Uid:/{{print $2}}' $p/status)\" | cut -d: -f1; \
+         echo ::crabtop::exe; readlink $p/exe; echo ::crabtop::cwd; readlink $p/cwd; \
+         echo ::crabtop::smaps; cat $p/smaps_rollup 2>/dev/null; true"
+    );
+    let runner = runner.clone();
+    let out = tokio::task::spawn_blocking(move || run(&runner, &script))
+        .await
+        .context("Failed to spawn ssh")?
+        .with_context(|| format!("Failed to read process info for PID {}", pid))?;
+
+    let mut sections: HashMap<&str, &str> = HashMap::new();
+    let mut rest = out.as_str();
+    let mut key = "head";
+    while let Some(pos) = rest.find(MARKER) {
+        sections.insert(key, &rest[..pos]);
+        let after = &rest[pos + MARKER.len()..];
+        let (name, body) = after.split_once('\n').unwrap_or((after, ""));
+        key = name;
+        rest = body;
+    }
+    sections.insert(key, rest);
+
+    let head = sections.get("head").copied().unwrap_or_default();
+    let mut head_lines = head.lines();
+    let uptime = head_lines.next().and_then(parse_uptime);
+    let stat = head_lines.next().and_then(parse_stat);
+    let (Some(uptime), Some(stat)) = (uptime, stat) else {
+        return Err(Error::ProcessGone { pid }.into());
+    };
+    let status = parse_status(sections.get("status").copied().unwrap_or_default());
+    let text = |name: &str| {
+        sections
+            .get(name)
+            .map(|s| s.trim())
+            .filter(|s| !s.is_empty())
+            .map(str::to_string)
+    };
+
+    Ok(Reading {
+        uptime,
+        ticks: stat.utime + stat.stime,
+        start_ticks: stat.start_ticks,
+        counters: ProcessCounters::from_proc(&stat, &status),
+        name: status.name.unwrap_or(stat.comm),
+        rss_bytes: status.rss_bytes.unwrap_or(0),
+        vm_size_bytes: status.vm_size_bytes.unwrap_or(0),
+        smaps: sections
+            .get("smaps")
+            .filter(|s| !s.trim().is_empty())
+            .map(|s| parse_smaps_rollup(s)),
+        user: text("user").or_else(|| status.uid.map(|u| u.to_string())),
+        cmdline: sections
+            .get("cmdline")
+            .map(|s| s.lines().filter(|l| !l.is_empty()).map(str::to_string).collect())
+            .unwrap_or_default(),
+        exe: text("exe"),
+        cwd: text("cwd"),
+    })
+}
+
+fn run(runner: &Runner, script: &str) -> Result<String> {
+    let out = runner.shell(script).context("Failed to run `ssh`. Is it installed and on PATH?")?;
+    if !out.status.success() {
+        return Err(anyhow!(
+            "remote command failed{}: {}",
+            runner.describe(),
+            String::from_utf8_lossy(&out.stderr).trim()
+        ));
+    }
+    Ok(String::from_utf8_lossy(&out.stdout).into_owned())
+}
@@ -0,0 +1,106 @@
+//! Emitting watch samples as StatsD / DogStatsD gauges over UDP.
+
+use std::io;
+use std::net::UdpSocket;
+
+use anyhow::{Context, Result};
+use serde::Deserialize;
+
+use crate::export::{ExportSample, Exporter};
+
+/// How the port and process name travel with each gauge.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize, clap::ValueEnum)]
+#[serde(rename_all = "lowercase")]
+pub enum StatsdFormat {
+    /// Tags as `|#port:8080,process:nginx`
+    #[default]
+    Dogstatsd,
+    /// No tags; port and process name go into the metric name
+    Plain,
+}
+
+/// Sends one packet of gauges per sample. UDP, so a missing collector never
+/// slows down or breaks the watch loop.
+#[derive(Debug)]
+pub struct StatsdEmitter {
+    socket: UdpSocket,
+    prefix: String,
+    format: StatsdFormat,
+    /// Extra `key:value` tags added to every gauge (DogStatsD only).
+    tags: Vec<String>,
+}
+
+impl StatsdEmitter {
+    pub fn connect(addr: &str, prefix: &str, format: StatsdFormat, tags: Vec<String>) -> Result<Self> {
+        let socket = UdpSocket::bind("0.0.0.0:0").context("Failed to open a UDP socket for statsd")?;
+        socket
+            .connect(addr)
+            .with_context(|| format!("Failed to resolve statsd address {}", addr))?;
+        Ok(Self {
+            socket,
+            prefix: prefix.trim_end_matches('.').to_string(),
+            format,
+            tags,
+        })
+    }
+
+    pub fn emit(&self, sample: &ExportSample) -> io::Result<()> {
+        self.socket
+            .send(self.format_sample(sample).join("\n").as_bytes())
+            .map(|_| ())
+    }
+
+    /// The gauge lines for one sample, in the configured format.
+    pub fn format_sample(&self, sample: &ExportSample) -> Vec<String> {
+        let ExportSample {
+            port, process: info, ..
+        } = sample;
+        let process = sanitize(&info.name);
+        let host = sample.host.as_deref().map(sanitize);
+        let gauges = [
+            ("cpu_percent", format!("{:.2}", info.cpu_percent)),
+            ("memory_bytes", info.memory_bytes.to_string()),
+            ("uptime_secs", info.uptime_secs.to_string()),
+        ];
+
+        match self.format {
+            StatsdFormat::Dogstatsd => {
+                let mut tags = vec![format!("port:{}", port), format!("process:{}", process)];
+                tags.extend(host.map(|h| format!("host:{}", h)));
+                tags.extend(self.tags.iter().cloned());
+                let tags = tags.join(",");
+                gauges
+                    .iter()
+                    .map(|(name, value)| format!("{}.{}:{}|g|#{}", self.prefix, name, value, tags))
+                    .collect()
+            }
+            StatsdFormat::Plain => {
+                let scope = match host {
+                    Some(host) => format!("{}.{}.{}.{}", self.prefix, host, process, port),
+                    None => format!("{}.{}.{}", self.prefix, process, port),
+                };
+                gauges
+                    .iter()
+                    .map(|(name, value)| format!("{}.{}:{}|g", scope, name, value))
+                    .collect()
+            }
+        }
+    }
+}
+
+// Characters with a meaning in the line protocol (or in Graphite paths) become `_`.
+fn sanitize(name: &str) -> String {
+    name.chars()
+        .map(|c| if c.is_ascii_alphanumeric() || c == '-' || c == '_' { c } else { '_' })
+        .collect()
+}
+
+impl Exporter for StatsdEmitter {
+    fn name(&self) -> &str {
+        "statsd"
+    }
+
+    fn on_sample(&mut self, sample: &ExportSample) -> Result<()> {
+        Ok(self.emit(sample)?)
+    }
+}
@@ -0,0 +1,77 @@
+use std::time::Duration;
+
+use chrono::{DateTime, Local};
+use futures_util::stream::{self, Stream};
+use serde::{Deserialize, Serialize};
+use sysinfo::{Pid, System, Users, MINIMUM_CPU_UPDATE_INTERVAL};
+use tokio::time::{self, Interval, MissedTickBehavior};
+
+use crate::process::{full_refresh_kind, read_process_info, usage_refresh_kind, ProcessInfo, SystemSnapshot};
+
+/// One tick of the sampler: the target process plus the machine around it.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Sample {
+    pub timestamp: DateTime<Local>,
+    pub process: ProcessInfo,
+    pub system: SystemSnapshot,
+}
+
+struct SamplerState {
+    sys: System,
+    users: Users,
+    ticker: Interval,
+}
+
+/// Yields a `Sample` for `pid` every `interval`.
+///
+/// The same `System` is kept across ticks, so each sample's CPU figure is the
+/// delta since the previous one rather than a fresh 200ms measurement. The
+/// stream ends once the process can no longer be read (it exited, or we lost
+/// permission to see it).
+pub fn sample_stream(pid: u32, interval: Duration) -> impl Stream<Item = Sample> {
+    let pid = Pid::from_u32(pid);
+
+    stream::unfold(None, move |state: Option<SamplerState>| async move {
+        let SamplerState { sys, users, mut ticker } = match state {
+            Some(state) => state,
+            None => {
+                // Take a baseline so the very first sample already has a CPU delta.
+                let (sys, users) = tokio::task::spawn_blocking(move || {
+                    let mut sys = System::new();
+                    sys.refresh_cpu();
+                    sys.refresh_process_specifics(pid, full_refresh_kind());
+                    (sys, Users::new_with_refreshed_list())
+                })
+                .await
+                .ok()?;
+                time::sleep(MINIMUM_CPU_UPDATE_INTERVAL).await;
+
+                let mut ticker = time::interval(interval);
+                ticker.set_missed_tick_behavior(MissedTickBehavior::Delay);
+                SamplerState { sys, users, ticker }
+            }
+        };
+
+        ticker.tick().await;
+
+        let (sys, users, result) = tokio::task::spawn_blocking(move || {
+            let mut sys = sys;
+            sys.refresh_process_specifics(pid, usage_refresh_kind());
+            sys.refresh_memory();
+            let result =
+                read_process_info(&sys, &users, pid).map(|info| (info, SystemSnapshot::from_system(&sys)));
+            (sys, users, result)
+        })
+        .await
+        .ok()?;
+
+        let (process, system) = result.ok()?;
+        let sample = Sample {
+            timestamp: Local::now(),
+            process,
+            system,
+        };
+
+        Some((sample, Some(SamplerState { sys, users, ticker })))
+    })
+}
@@ -0,0 +1,171 @@
+//! What a process's syscalls go to, in broad strokes: reading, writing,
+//! waiting for IO (`poll`/`epoll`), locking (`futex`) or something else. With
+//! eBPF the calls are counted; otherwise the threads are sampled through
+//! `/proc/<pid>/task/*/syscall`, which also shows how often they were on a
+//! CPU rather than in the kernel.
+//!
+//! Syscall numbers are per architecture; remote hosts are assumed to share
+//! this machine's.
+
+use std::ops::Sub;
+
+use crate::exec::Runner;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SyscallCategory {
+    Read,
+    Write,
+    Poll,
+    Futex,
+    Other,
+}
+
+#[cfg(target_arch = "x86_64")]
+mod numbers {
+    pub const READ: &[i64] = &[0, 17, 19, 45, 47, 295, 299];
+    pub const WRITE: &[i64] = &[1, 18, 20, 40, 44, 46, 296, 307];
+    pub const POLL: &[i64] = &[7, 23, 232, 270, 271, 281, 441];
+    pub const FUTEX: &[i64] = &[202, 449];
+}
+
+#[cfg(target_arch = "aarch64")]
+mod numbers {
+    pub const READ: &[i64] = &[63, 65, 67, 69, 207, 212, 243];
+    pub const WRITE: &[i64] = &[64, 66, 68, 70, 71, 206, 211, 269];
+    pub const POLL: &[i64] = &[22, 72, 73, 441];
+    pub const FUTEX: &[i64] = &[98, 449];
+}
+
+#[cfg(not(any(target_arch = "x86_64", target_arch = "aarch64")))]
+mod numbers {
+    pub const READ: &[i64] = &[];
+    pub const WRITE: &[i64] = &[];
+    pub const POLL: &[i64] = &[];
+    pub const FUTEX: &[i64] = &[];
+}
+
+impl SyscallCategory {
+    /// The syscall numbers in this category on this architecture; `Other` has none.
+    pub fn numbers(&self) -> &'static [i64] {
+        match self {
+            SyscallCategory::Read => numbers::READ,
+            SyscallCategory::Write => numbers::WRITE,
+            SyscallCategory::Poll => numbers::POLL,
+            SyscallCategory::Futex => numbers::FUTEX,
+            SyscallCategory::Other => &[],
+        }
+    }
+
+    pub fn of(number: i64) -> Self {
+        [SyscallCategory::Read, SyscallCategory::Write, SyscallCategory::Poll, SyscallCategory::Futex]
+            .into_iter()
+            .find(|category| category.numbers().contains(&number))
+            .unwrap_or(SyscallCategory::Other)
+    }
+}
+
+/// Syscalls by category: calls made (eBPF) or threads seen in them (sampling).
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct SyscallCounts {
+    pub read: u64,
+    pub write: u64,
+    pub poll: u64,
+    pub futex: u64,
+    pub other: u64,
+    /// Threads seen running rather than in a syscall; sampling only.
+    pub running: u64,
+}
+
+impl SyscallCounts {
+    pub fn add(&mut self, category: SyscallCategory, count: u64) {
+        let slot = match category {
+            SyscallCategory::Read => &mut self.read,
+            SyscallCategory::Write => &mut self.write,
+            SyscallCategory::Poll => &mut self.poll,
+            SyscallCategory::Futex => &mut self.futex,
+            SyscallCategory::Other => &mut self.other,
+        };
+        *slot += count;
+    }
+
+    pub fn total(&self) -> u64 {
+        self.read + self.write + self.poll + self.futex + self.other + self.running
+    }
+
+    /// What the process seems busy with: IO (read, write, poll), locking
+    /// (futex) or computing (running); `None` when nothing was seen.
+    pub fn dominant(&self) -> Option<&'static str> {
+        let groups = [
+            ("IO", self.read + self.write + self.poll),
+            ("locking", self.futex),
+            ("computing", self.running),
+            ("other syscalls", self.other),
+        ];
+        groups.into_iter().filter(|(_, n)| *n > 0).max_by_key(|(_, n)| *n).map(|(label, _)| label)
+    }
+}
+
+impl std::ops::AddAssign for SyscallCounts {
+    fn add_assign(&mut self, other: SyscallCounts) {
+        self.read += other.read;
+        self.write += other.write;
+        self.poll += other.poll;
+        self.futex += other.futex;
+        self.other += other.other;
+        self.running += other.running;
+    }
+}
+
+impl Sub for SyscallCounts {
+    type Output = SyscallCounts;
+
+    fn sub(self, earlier: SyscallCounts) -> SyscallCounts {
+        SyscallCounts {
+            read: self.read.saturating_sub(earlier.read),
+            write: self.write.saturating_sub(earlier.write),
+            poll: self.poll.saturating_sub(earlier.poll),
+            futex: self.futex.saturating_sub(earlier.futex),
+            other: self.other.saturating_sub(earlier.other),
+            running: self.running.saturating_sub(earlier.running),
+        }
+    }
+}
+
+/// Where each of `pid`'s threads is right now, read through `runner`.
+pub fn task_syscalls_on(runner: &Runner, pid: u32) -> Option<SyscallCounts> {
+    let text = match runner {
+        Runner::Local => {
+            let mut text = String::new();
+            for task in std::fs::read_dir(format!("/proc/{}/task", pid)).ok()?.flatten() {
+                if let Ok(line) = std::fs::read_to_string(task.path().join("syscall")) {
+                    text.push_str(&line);
+                }
+            }
+            text
+        }
+        // Another user's threads, or another host's.
+        Runner::Ssh(_) | Runner::Elevated(_) => {
+            let out = runner.shell(&format!("cat /proc/{}/task/*/syscall 2>/dev/null", pid)).ok()?;
+            String::from_utf8_lossy(&out.stdout).into_owned()
+        }
+    };
+    (!text.is_empty()).then(|| parse_task_syscalls(&text))
+}
+
+/// One `/proc/<pid>/task/<tid>/syscall` line per thread: the syscall
+/// number and its arguments, `running`, or `-1 ...` when the thread is
+/// blocked outside a syscall (a page fault, say), which isn't counted.
+pub fn parse_task_syscalls(text: &str) -> SyscallCounts {
+    let mut counts = SyscallCounts::default();
+    for line in text.lines() {
+        match line.split_whitespace().next() {
+            Some("running") => counts.running += 1,
+            Some(number) => match number.parse::<i64>() {
+                Ok(number) if number >= 0 => counts.add(SyscallCategory::of(number), 1),
+                _ => {}
+            },
+            None => {}
+        }
+    }
+    counts
+}
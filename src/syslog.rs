@@ -0,0 +1,201 @@
+//! Alert sinks for syslog and the systemd journal, so log-based alerting
+//! already watching those picks crabtop's alerts up. Samples aren't sent;
+//! only alerts, at a priority that depends on the rule.
+//!
+//! Both write datagrams to the local socket (`/dev/log`, or journald's
+//! native `/run/systemd/journal/socket`), the way `logger` and
+//! `systemd-cat` do. Journal entries carry the rule, port and value as
+//! fields, so `journalctl CRABTOP_RULE=process_exited` finds them.
+
+use std::fmt::Write as _;
+
+use chrono::{DateTime, Local};
+use serde::{Deserialize, Serialize};
+
+use crate::export::Alert;
+
+/// Where syslog messages go, with the usual paths tried in turn.
+pub const SYSLOG_SOCKETS: [&str; 3] = ["/dev/log", "/var/run/syslog", "/var/run/log"];
+
+/// journald's native protocol socket.
+pub const JOURNAL_SOCKET: &str = "/run/systemd/journal/socket";
+
+/// Syslog severities, most urgent first.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Severity {
+    Critical = 2,
+    Error = 3,
+    Warning = 4,
+    Notice = 5,
+}
+
+/// How urgent a built-in alert is: the process exiting is critical, being next
+/// for the OOM killer an error, and the rest warnings.
+pub fn severity(rule: &str) -> Severity {
+    match rule {
+        "process_exited" => Severity::Critical,
+        "oom_at_risk" | "watchdog_action" => Severity::Error,
+        "process_restarted" | "retransmits_high" | "swap_started" | "watchdog_held" | "zombies_growing" => Severity::Warning,
+        _ => Severity::Notice,
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Facility {
+    User = 1,
+    #[default]
+    Daemon = 3,
+    Local0 = 16,
+    Local1 = 17,
+    Local2 = 18,
+    Local3 = 19,
+    Local4 = 20,
+    Local5 = 21,
+    Local6 = 22,
+    Local7 = 23,
+}
+
+/// What an alert says, e.g. `port 8080: PID 4242 exited [process_exited]`.
+fn text(alert: &Alert) -> String {
+    let target = match &alert.host {
+        Some(host) => format!("{} port {}", host, alert.port),
+        None => format!("port {}", alert.port),
+    };
+    format!("{}: {} [{}]", target, alert.message, alert.rule)
+}
+
+/// The message as `syslog(3)` sends it to the local socket (RFC 3164).
+pub fn syslog_line(alert: &Alert, facility: Facility, pid: u32, now: DateTime<Local>) -> String {
+    let priority = facility as u8 * 8 + alert.severity as u8;
+    format!("<{}>{} crabtop[{}]: {}", priority, now.format("%b %e %H:%M:%S"), pid, text(alert))
+}
+
+/// The datagram journald's native protocol takes: `FIELD=value` lines,
+/// with a length-prefixed value for anything with a newline in it.
+pub fn journal_entry(alert: &Alert, facility: Facility) -> Vec<u8> {
+    let mut fields = vec![
+        ("MESSAGE", text(alert)),
+        ("PRIORITY", (alert.severity as u8).to_string()),
+        ("SYSLOG_IDENTIFIER", "crabtop".to_string()),
+        ("SYSLOG_FACILITY", (facility as u8).to_string()),
+        ("CRABTOP_RULE", alert.rule.clone()),
+        ("CRABTOP_PORT", alert.port.to_string()),
+        ("CRABTOP_VALUE", alert.value.to_string()),
+    ];
+    if let Some(host) = &alert.host {
+        fields.push(("CRABTOP_HOST", host.clone()));
+    }
+    let mut entry = Vec::new();
+    for (name, value) in fields {
+        if value.contains('\n') {
+            entry.extend_from_slice(name.as_bytes());
+            entry.push(b'\n');
+            entry.extend_from_slice(&(value.len() as u64).to_le_bytes());
+            entry.extend_from_slice(value.as_bytes());
+            entry.push(b'\n');
+        } else {
+            let mut line = String::new();
+            let _ = writeln!(line, "{}={}", name, value);
+            entry.extend_from_slice(line.as_bytes());
+        }
+    }
+    entry
+}
+
+#[cfg(unix)]
+pub use sinks::{JournaldExporter, SyslogExporter};
+
+#[cfg(unix)]
+mod sinks {
+    use std::os::unix::net::UnixDatagram;
+    use std::path::{Path, PathBuf};
+
+    use anyhow::{Context, Result};
+    use chrono::Local;
+
+    use super::Facility;
+    use crate::export::{Alert, ExportSample, Exporter};
+
+    fn connect(path: &Path) -> Result<UnixDatagram> {
+        let socket = UnixDatagram::unbound()?;
+        socket.connect(path).with_context(|| format!("Failed to connect to {}", path.display()))?;
+        Ok(socket)
+    }
+
+    /// Sends each alert to the local syslog daemon.
+    pub struct SyslogExporter {
+        socket: UnixDatagram,
+        facility: Facility,
+    }
+
+    impl SyslogExporter {
+        /// Connects to the first of `SYSLOG_SOCKETS` that exists.
+        pub fn new(facility: Facility) -> Result<Self> {
+            let path = super::SYSLOG_SOCKETS
+                .iter()
+                .map(PathBuf::from)
+                .find(|path| path.exists())
+                .context("No syslog socket found (tried /dev/log, /var/run/syslog and /var/run/log)")?;
+            Self::connect(&path, facility)
+        }
+
+        pub fn connect(path: &Path, facility: Facility) -> Result<Self> {
+            Ok(Self {
+                socket: connect(path)?,
+                facility,
+            })
+        }
+    }
+
+    impl Exporter for SyslogExporter {
+        fn name(&self) -> &str {
+            "syslog"
+        }
+
+        fn on_sample(&mut self, _sample: &ExportSample) -> Result<()> {
+            Ok(())
+        }
+
+        fn on_alert(&mut self, alert: &Alert) -> Result<()> {
+            let line = super::syslog_line(alert, self.facility, std::process::id(), Local::now());
+            self.socket.send(line.as_bytes())?;
+            Ok(())
+        }
+    }
+
+    /// Sends each alert to the systemd journal, with its fields.
+    pub struct JournaldExporter {
+        socket: UnixDatagram,
+        facility: Facility,
+    }
+
+    impl JournaldExporter {
+        pub fn new(facility: Facility) -> Result<Self> {
+            Self::connect(Path::new(super::JOURNAL_SOCKET), facility).context("Is systemd-journald running?")
+        }
+
+        pub fn connect(path: &Path, facility: Facility) -> Result<Self> {
+            Ok(Self {
+                socket: connect(path)?,
+                facility,
+            })
+        }
+    }
+
+    impl Exporter for JournaldExporter {
+        fn name(&self) -> &str {
+            "journald"
+        }
+
+        fn on_sample(&mut self, _sample: &ExportSample) -> Result<()> {
+            Ok(())
+        }
+
+        fn on_alert(&mut self, alert: &Alert) -> Result<()> {
+            self.socket.send(&super::journal_entry(alert, self.facility))?;
+            Ok(())
+        }
+    }
+}
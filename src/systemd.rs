@@ -0,0 +1,126 @@
+//! The systemd unit a process belongs to: found from its cgroup path, with
+//! the unit's state and resource limits from `systemctl show`.
+
+use anyhow::{anyhow, Context, Result};
+use serde::Serialize;
+
+use crate::exec::Runner;
+
+/// Properties read with `systemctl show -p`.
+const PROPERTIES: &str = "Id,ActiveState,SubState,MainPID,NRestarts,CPUQuotaPerSecUSec,MemoryMax,MemoryCurrent,TasksCurrent";
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+pub struct UnitStatus {
+    pub name: String,
+    /// `active`, `failed`, `activating`…
+    pub active_state: String,
+    /// `running`, `exited`, `auto-restart`…
+    pub sub_state: String,
+    pub main_pid: Option<u32>,
+    pub restarts: Option<u32>,
+    /// `CPUQuota=` as a percentage of one CPU; `None` when unlimited.
+    pub cpu_quota_percent: Option<f64>,
+    /// `MemoryMax=` in bytes; `None` when unlimited.
+    pub memory_max: Option<u64>,
+    pub memory_current: Option<u64>,
+    pub tasks: Option<u64>,
+}
+
+impl UnitStatus {
+    pub fn is_active(&self) -> bool {
+        self.active_state == "active"
+    }
+
+    /// MemoryCurrent as a share of MemoryMax, when both are known.
+    pub fn memory_ratio(&self) -> Option<f64> {
+        Some(self.memory_current? as f64 / self.memory_max?.max(1) as f64)
+    }
+}
+
+/// What the dashboard can ask systemd to do to the unit.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum UnitAction {
+    Restart,
+    Stop,
+}
+
+impl UnitAction {
+    pub fn verb(&self) -> &'static str {
+        match self {
+            UnitAction::Restart => "restart",
+            UnitAction::Stop => "stop",
+        }
+    }
+}
+
+/// The service a process runs under, from the lines of `/proc/<pid>/cgroup`.
+/// The innermost `.service` wins, so a service in a user manager is found
+/// rather than `user@1000.service` itself.
+pub fn unit_from_cgroup(text: &str) -> Option<String> {
+    // v2 has one "0::/path" line; under v1 the name=systemd hierarchy has the same path.
+    let path = text
+        .lines()
+        .find_map(|l| l.strip_prefix("0::").filter(|p| *p != "/"))
+        .or_else(|| text.lines().find_map(|l| l.split_once(":name=systemd:").map(|(_, p)| p)))?;
+    path.rsplit('/').find(|part| part.ends_with(".service")).map(str::to_string)
+}
+
+/// Parses `systemctl show -p …` output (`Key=value` lines).
+pub fn parse_show(text: &str) -> Option<UnitStatus> {
+    let mut status = UnitStatus::default();
+    for line in text.lines() {
+        let Some((key, value)) = line.split_once('=') else {
+            continue;
+        };
+        match key {
+            "Id" => status.name = value.to_string(),
+            "ActiveState" => status.active_state = value.to_string(),
+            "SubState" => status.sub_state = value.to_string(),
+            "MainPID" => status.main_pid = value.parse().ok().filter(|&pid| pid != 0),
+            "NRestarts" => status.restarts = value.parse().ok(),
+            "CPUQuotaPerSecUSec" => status.cpu_quota_percent = parse_timespan_secs(value).map(|s| s * 100.0),
+            "MemoryMax" => status.memory_max = value.parse().ok(),
+            "MemoryCurrent" => status.memory_current = value.parse().ok(),
+            "TasksCurrent" => status.tasks = value.parse().ok(),
+            _ => {}
+        }
+    }
+    (!status.name.is_empty()).then_some(status)
+}
+
+// "2s", "500ms", "1.500000s" -> seconds; "infinity" -> None.
+fn parse_timespan_secs(value: &str) -> Option<f64> {
+    let (number, scale) = if let Some(ms) = value.strip_suffix("ms") {
+        (ms, 1e-3)
+    } else if let Some(us) = value.strip_suffix("us") {
+        (us, 1e-6)
+    } else {
+        (value.strip_suffix('s')?, 1.0)
+    };
+    number.parse::<f64>().ok().map(|n| n * scale)
+}
+
+/// The unit `pid` belongs to and its current state, if it runs under systemd.
+pub fn unit_status_on(runner: &Runner, pid: u32) -> Option<UnitStatus> {
+    let out = runner.output("cat", &[&format!("/proc/{}/cgroup", pid)]).ok()?;
+    let unit = unit_from_cgroup(&String::from_utf8_lossy(&out.stdout))?;
+    let out = runner.output("systemctl", &["show", &unit, "-p", PROPERTIES]).ok()?;
+    parse_show(&String::from_utf8_lossy(&out.stdout))
+}
+
+/// Runs `systemctl <action> <unit>`; needs root or a polkit rule allowing it.
+pub fn unit_action_on(runner: &Runner, unit: &str, action: UnitAction) -> Result<()> {
+    let out = runner
+        .output("systemctl", &[action.verb(), unit])
+        .with_context(|| format!("Failed to run systemctl{}", runner.describe()))?;
+    if !out.status.success() {
+        return Err(anyhow!(
+            "systemctl {} {} failed{}: {}",
+            action.verb(),
+            unit,
+            runner.describe(),
+            String::from_utf8_lossy(&out.stderr).trim()
+        ));
+    }
+    Ok(())
+}
@@ -0,0 +1,161 @@
+//! `--target-cgroup` and `--target-container`: following a container's
+//! processes instead of a port's, for a sidecar watching its neighbour.
+//!
+//! A cgroup target is read from its `cgroup.procs`, so the sidecar needs the
+//! cgroup mounted and the processes in its PID namespace. A container target
+//! needs the pod's process namespace shared (`shareProcessNamespace: true`):
+//! every visible process is grouped by its cgroup, and the group picked by
+//! container ID prefix, or by the name of its first process. Either way the
+//! processes are looked up again on every tick, as the container forks and
+//! restarts.
+
+use std::collections::HashMap;
+use std::fmt;
+use std::fs;
+use std::path::PathBuf;
+
+use anyhow::{anyhow, bail, Result};
+
+use crate::cgroup;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Target {
+    /// A cgroup directory, or a path under the cgroup root.
+    Cgroup(PathBuf),
+    /// A container ID prefix, or the name of the container's first process.
+    Container(String),
+}
+
+impl fmt::Display for Target {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Target::Cgroup(path) => write!(f, "cgroup {}", path.display()),
+            Target::Container(wanted) => write!(f, "container {}", wanted),
+        }
+    }
+}
+
+impl Target {
+    /// The target's processes now, lowest PID first.
+    pub fn pids(&self) -> Result<Vec<u32>> {
+        match self {
+            Target::Cgroup(path) => {
+                let dir = cgroup::resolve_dir(path);
+                if !dir.join("cgroup.procs").is_file() {
+                    bail!("{} is not a cgroup directory", dir.display());
+                }
+                let pids = cgroup::procs(&dir);
+                if pids.is_empty() {
+                    bail!("No process of cgroup {} is visible here; crabtop needs to share its PID namespace", dir.display());
+                }
+                Ok(pids)
+            }
+            Target::Container(wanted) => find_container(&visible_processes(), &own_cgroup().unwrap_or_default(), wanted),
+        }
+    }
+
+    /// The cgroup whose limits and usage to show, when it is known outright.
+    pub fn cgroup_dir(&self) -> Option<PathBuf> {
+        match self {
+            Target::Cgroup(path) => Some(cgroup::resolve_dir(path)),
+            Target::Container(_) => None,
+        }
+    }
+}
+
+/// A process in this PID namespace and the cgroup it is in.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct VisibleProcess {
+    pub pid: u32,
+    /// The cgroup v2 path from `/proc/<pid>/cgroup`.
+    pub cgroup: String,
+    pub name: String,
+}
+
+/// Every process in `/proc` whose cgroup can be read.
+pub fn visible_processes() -> Vec<VisibleProcess> {
+    let Ok(entries) = fs::read_dir("/proc") else {
+        return Vec::new();
+    };
+    entries
+        .flatten()
+        .filter_map(|entry| {
+            let pid: u32 = entry.file_name().to_str()?.parse().ok()?;
+            let cgroup = cgroup_path(&fs::read_to_string(entry.path().join("cgroup")).ok()?)?;
+            let name = fs::read_to_string(entry.path().join("comm")).ok()?.trim().to_string();
+            Some(VisibleProcess { pid, cgroup, name })
+        })
+        .collect()
+}
+
+/// The cgroup crabtop itself runs in.
+fn own_cgroup() -> Option<String> {
+    cgroup_path(&fs::read_to_string("/proc/self/cgroup").ok()?)
+}
+
+/// The path on the single `0::` line of cgroup v2.
+fn cgroup_path(text: &str) -> Option<String> {
+    text.lines().find_map(|l| l.strip_prefix("0::")).map(str::to_string)
+}
+
+/// The 64-hex-digit container ID at the end of a cgroup path, as Docker,
+/// containerd, CRI-O and Podman name them: `…/docker-<id>.scope`,
+/// `…/cri-containerd-<id>.scope`, `…/crio-<id>.scope`, `…/libpod-<id>.scope`,
+/// or a bare `…/<id>` under cgroupfs.
+pub fn container_id(cgroup: &str) -> Option<&str> {
+    let last = cgroup.rsplit('/').next()?;
+    let last = last.strip_suffix(".scope").unwrap_or(last);
+    let id = ["docker-", "cri-containerd-", "crio-", "libpod-"]
+        .iter()
+        .find_map(|prefix| last.strip_prefix(prefix))
+        .unwrap_or(last);
+    (id.len() == 64 && id.bytes().all(|b| b.is_ascii_hexdigit())).then_some(id)
+}
+
+/// The PIDs of the container `wanted` among `processes`, leaving out the
+/// cgroup crabtop runs in (`own`).
+pub fn find_container(processes: &[VisibleProcess], own: &str, wanted: &str) -> Result<Vec<u32>> {
+    let mut groups: HashMap<&str, Vec<&VisibleProcess>> = HashMap::new();
+    for process in processes.iter().filter(|p| p.cgroup != own) {
+        groups.entry(process.cgroup.as_str()).or_default().push(process);
+    }
+    let mut containers: Vec<(String, Option<&str>, Vec<u32>)> = groups
+        .into_iter()
+        .map(|(cgroup, mut members)| {
+            members.sort_by_key(|p| p.pid);
+            let pids = members.iter().map(|p| p.pid).collect();
+            (members[0].name.clone(), container_id(cgroup), pids)
+        })
+        .collect();
+    containers.sort_by_key(|(_, _, pids)| pids[0]);
+    if containers.is_empty() {
+        bail!("No other container's processes are visible; the pod needs `shareProcessNamespace: true`");
+    }
+
+    let wanted_id = wanted.to_ascii_lowercase();
+    let matching: Vec<usize> = (0..containers.len())
+        .filter(|&i| {
+            let (name, id, _) = &containers[i];
+            name == wanted || (!wanted.is_empty() && id.is_some_and(|id| id.starts_with(&wanted_id)))
+        })
+        .collect();
+    let describe = |containers: &[(String, Option<&str>, Vec<u32>)], picked: &[usize]| {
+        picked
+            .iter()
+            .map(|&i| match containers[i].1 {
+                Some(id) => format!("{} ({})", containers[i].0, &id[..12]),
+                None => containers[i].0.clone(),
+            })
+            .collect::<Vec<_>>()
+            .join(", ")
+    };
+    match matching.as_slice() {
+        [i] => Ok(containers.swap_remove(*i).2),
+        [] => Err(anyhow!(
+            "No container `{}` is visible (containers: {})",
+            wanted,
+            describe(&containers, &(0..containers.len()).collect::<Vec<_>>())
+        )),
+        several => Err(anyhow!("`{}` matches several containers: {}; give more of the ID", wanted, describe(&containers, several))),
+    }
+}
@@ -0,0 +1,412 @@
+//! What a TLS port is actually serving: the negotiated protocol and cipher,
+//! and the leaf certificate's names and expiry.
+//!
+//! The handshake accepts any certificate so self-signed and expired ones can
+//! still be inspected; whether the chain verifies against the Mozilla roots is
+//! reported separately. Certificates are read with a minimal DER walker that
+//! only knows the handful of fields shown here.
+
+use std::fmt;
+use std::net::IpAddr;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use anyhow::{anyhow, Context, Result};
+use chrono::{DateTime, NaiveDateTime, Utc};
+use rustls::client::danger::{HandshakeSignatureValid, ServerCertVerified, ServerCertVerifier};
+use rustls::client::WebPkiServerVerifier;
+use rustls::crypto::{ring, CryptoProvider};
+use rustls::pki_types::{CertificateDer, ServerName, UnixTime};
+use rustls::{ClientConfig, DigitallySignedStruct, ProtocolVersion, RootCertStore, SignatureScheme};
+use serde::Serialize;
+use tokio::net::TcpStream;
+use tokio_rustls::TlsConnector;
+
+/// Certificates expiring within this many days are flagged.
+pub const EXPIRY_WARNING_DAYS: i64 = 14;
+
+/// The outcome of one handshake.
+#[derive(Debug, Clone, Serialize)]
+pub struct TlsInfo {
+    /// `host:port` that was connected to.
+    pub target: String,
+    /// Name sent as SNI and checked against the certificate.
+    pub server_name: String,
+    pub protocol: String,
+    pub cipher: String,
+    pub alpn: Option<String>,
+    pub handshake_ms: f64,
+    /// `Err` holds why the chain didn't verify (self-signed, wrong name, expired).
+    #[serde(rename = "verify_error", serialize_with = "serialize_verified")]
+    pub verified: Result<(), String>,
+    pub certificate: Option<Certificate>,
+}
+
+/// The fields of an X.509 certificate worth showing.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct Certificate {
+    pub subject: String,
+    pub issuer: String,
+    /// DNS names and IP addresses from the subjectAltName extension.
+    pub san: Vec<String>,
+    pub not_before: DateTime<Utc>,
+    pub not_after: DateTime<Utc>,
+}
+
+impl Certificate {
+    /// Whole days until `not_after`; negative once expired.
+    pub fn days_until_expiry(&self, now: DateTime<Utc>) -> i64 {
+        (self.not_after - now).num_days()
+    }
+
+    pub fn expires_soon(&self, now: DateTime<Utc>) -> bool {
+        self.days_until_expiry(now) < EXPIRY_WARNING_DAYS
+    }
+}
+
+/// Watch mode's periodic re-inspection; certificates don't change every tick.
+pub struct TlsCheck {
+    pub target: String,
+    server_name: Option<String>,
+    timeout: Duration,
+    /// Outcome of the latest handshake, or why it failed.
+    pub last: Option<Result<TlsInfo, String>>,
+    checked_at: Option<Instant>,
+}
+
+impl TlsCheck {
+    /// How often the handshake is repeated.
+    pub const RECHECK: Duration = Duration::from_secs(300);
+
+    pub fn new(target: String, server_name: Option<String>, timeout: Duration) -> Self {
+        Self {
+            target,
+            server_name,
+            timeout,
+            last: None,
+            checked_at: None,
+        }
+    }
+
+    /// Handshakes again when [`Self::RECHECK`] has passed since the last one.
+    pub async fn tick(&mut self) {
+        if self.checked_at.is_some_and(|at| at.elapsed() < Self::RECHECK) {
+            return;
+        }
+        self.checked_at = Some(Instant::now());
+        let outcome = inspect(&self.target, self.server_name.as_deref(), self.timeout).await;
+        self.last = Some(outcome.map_err(|e| format!("{:#}", e)));
+    }
+}
+
+fn serialize_verified<S: serde::Serializer>(verified: &Result<(), String>, s: S) -> Result<S::Ok, S::Error> {
+    match verified {
+        Ok(()) => s.serialize_none(),
+        Err(reason) => s.serialize_some(reason),
+    }
+}
+
+/// Connects to `target` (`host:port`), handshakes with `server_name` as SNI
+/// (defaults to the host part) and reports what was negotiated.
+pub async fn inspect(target: &str, server_name: Option<&str>, timeout: Duration) -> Result<TlsInfo> {
+    let host = target
+        .rsplit_once(':')
+        .map(|(host, _)| host.trim_start_matches('[').trim_end_matches(']'))
+        .unwrap_or(target);
+    let name = server_name.unwrap_or(host).to_string();
+    let server = match name.parse::<IpAddr>() {
+        Ok(ip) => ServerName::IpAddress(ip.into()),
+        Err(_) => ServerName::try_from(name.clone()).with_context(|| format!("Invalid TLS server name {}", name))?,
+    };
+
+    let provider = Arc::new(ring::default_provider());
+    let verifier = Arc::new(RecordingVerifier::new(Arc::clone(&provider))?);
+    let mut config = ClientConfig::builder_with_provider(provider)
+        .with_safe_default_protocol_versions()
+        .context("Failed to set up TLS")?
+        .dangerous()
+        .with_custom_certificate_verifier(verifier.clone())
+        .with_no_client_auth();
+    config.alpn_protocols = vec![b"h2".to_vec(), b"http/1.1".to_vec()];
+
+    let start = Instant::now();
+    let handshake = async {
+        let tcp = TcpStream::connect(target)
+            .await
+            .with_context(|| format!("Failed to connect to {}", target))?;
+        TlsConnector::from(Arc::new(config))
+            .connect(server, tcp)
+            .await
+            .with_context(|| format!("TLS handshake with {} failed; is the port speaking TLS?", target))
+    };
+    let stream = tokio::time::timeout(timeout, handshake)
+        .await
+        .map_err(|_| anyhow!("TLS handshake with {} timed out after {:?}", target, timeout))??;
+    let handshake_ms = start.elapsed().as_secs_f64() * 1000.0;
+
+    let (_, conn) = stream.get_ref();
+    let certificate = conn
+        .peer_certificates()
+        .and_then(|chain| chain.first())
+        .map(|leaf| parse_certificate(leaf))
+        .transpose()?;
+    let verified = verifier
+        .outcome
+        .lock()
+        .expect("verifier lock")
+        .clone()
+        .unwrap_or_else(|| Err("no certificate presented".to_string()));
+
+    Ok(TlsInfo {
+        target: target.to_string(),
+        server_name: name,
+        protocol: conn.protocol_version().map(protocol_label).unwrap_or_else(|| "-".to_string()),
+        cipher: conn
+            .negotiated_cipher_suite()
+            .map(|suite| format!("{:?}", suite.suite()))
+            .unwrap_or_else(|| "-".to_string()),
+        alpn: conn.alpn_protocol().map(|p| String::from_utf8_lossy(p).into_owned()),
+        handshake_ms,
+        verified,
+        certificate,
+    })
+}
+
+fn protocol_label(version: ProtocolVersion) -> String {
+    match version {
+        ProtocolVersion::TLSv1_3 => "TLS 1.3".to_string(),
+        ProtocolVersion::TLSv1_2 => "TLS 1.2".to_string(),
+        other => format!("{:?}", other),
+    }
+}
+
+/// Lets every handshake through, remembering what the WebPKI verifier thought.
+#[derive(Debug)]
+struct RecordingVerifier {
+    inner: Arc<WebPkiServerVerifier>,
+    outcome: Mutex<Option<Result<(), String>>>,
+}
+
+impl RecordingVerifier {
+    fn new(provider: Arc<CryptoProvider>) -> Result<Self> {
+        let roots = RootCertStore {
+            roots: webpki_roots::TLS_SERVER_ROOTS.to_vec(),
+        };
+        let inner = WebPkiServerVerifier::builder_with_provider(Arc::new(roots), provider)
+            .build()
+            .context("Failed to load TLS root certificates")?;
+        Ok(Self {
+            inner,
+            outcome: Mutex::new(None),
+        })
+    }
+}
+
+impl ServerCertVerifier for RecordingVerifier {
+    fn verify_server_cert(
+        &self,
+        end_entity: &CertificateDer<'_>,
+        intermediates: &[CertificateDer<'_>],
+        server_name: &ServerName<'_>,
+        ocsp_response: &[u8],
+        now: UnixTime,
+    ) -> Result<ServerCertVerified, rustls::Error> {
+        let outcome = self
+            .inner
+            .verify_server_cert(end_entity, intermediates, server_name, ocsp_response, now)
+            .map(|_| ())
+            .map_err(|e| e.to_string());
+        *self.outcome.lock().expect("verifier lock") = Some(outcome);
+        Ok(ServerCertVerified::assertion())
+    }
+
+    fn verify_tls12_signature(
+        &self,
+        message: &[u8],
+        cert: &CertificateDer<'_>,
+        dss: &DigitallySignedStruct,
+    ) -> Result<HandshakeSignatureValid, rustls::Error> {
+        self.inner.verify_tls12_signature(message, cert, dss)
+    }
+
+    fn verify_tls13_signature(
+        &self,
+        message: &[u8],
+        cert: &CertificateDer<'_>,
+        dss: &DigitallySignedStruct,
+    ) -> Result<HandshakeSignatureValid, rustls::Error> {
+        self.inner.verify_tls13_signature(message, cert, dss)
+    }
+
+    fn supported_verify_schemes(&self) -> Vec<SignatureScheme> {
+        self.inner.supported_verify_schemes()
+    }
+}
+
+// DER tags used below.
+const SEQUENCE: u8 = 0x30;
+const SET: u8 = 0x31;
+const OID: u8 = 0x06;
+const OCTET_STRING: u8 = 0x04;
+const UTC_TIME: u8 = 0x17;
+const GENERALIZED_TIME: u8 = 0x18;
+const EXPLICIT_0: u8 = 0xa0;
+const EXPLICIT_3: u8 = 0xa3;
+const SAN_DNS: u8 = 0x82;
+const SAN_IP: u8 = 0x87;
+
+/// id-ce-subjectAltName, 2.5.29.17.
+const OID_SAN: &[u8] = &[0x55, 0x1d, 0x11];
+
+/// Reads TLVs off the front of a DER buffer.
+struct Der<'a>(&'a [u8]);
+
+impl<'a> Der<'a> {
+    fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    fn peek_tag(&self) -> Option<u8> {
+        self.0.first().copied()
+    }
+
+    fn read(&mut self) -> Result<(u8, &'a [u8])> {
+        let data = self.0;
+        let (&tag, rest) = data.split_first().ok_or_else(|| anyhow!("Truncated certificate"))?;
+        let (&first, rest) = rest.split_first().ok_or_else(|| anyhow!("Truncated certificate"))?;
+        let (len, rest) = if first < 0x80 {
+            (first as usize, rest)
+        } else {
+            let n = (first & 0x7f) as usize;
+            if n == 0 || n > 4 || rest.len() < n {
+                return Err(anyhow!("Bad DER length in certificate"));
+            }
+            let len = rest[..n].iter().fold(0usize, |acc, &b| (acc << 8) | b as usize);
+            (len, &rest[n..])
+        };
+        if rest.len() < len {
+            return Err(anyhow!("Truncated certificate"));
+        }
+        self.0 = &rest[len..];
+        Ok((tag, &rest[..len]))
+    }
+
+    fn expect(&mut self, want: u8) -> Result<&'a [u8]> {
+        let (tag, content) = self.read()?;
+        if tag != want {
+            return Err(anyhow!("Unexpected DER tag {:#04x} in certificate (wanted {:#04x})", tag, want));
+        }
+        Ok(content)
+    }
+}
+
+/// Subject, issuer, SAN and validity of a DER-encoded X.509 certificate.
+pub fn parse_certificate(der: &[u8]) -> Result<Certificate> {
+    let mut cert = Der(Der(der).expect(SEQUENCE)?);
+    let mut tbs = Der(cert.expect(SEQUENCE)?);
+    if tbs.peek_tag() == Some(EXPLICIT_0) {
+        tbs.read()?; // version
+    }
+    tbs.read()?; // serial number
+    tbs.expect(SEQUENCE)?; // signature algorithm
+    let issuer = format_name(tbs.expect(SEQUENCE)?)?;
+    let mut validity = Der(tbs.expect(SEQUENCE)?);
+    let not_before = parse_time(validity.read()?)?;
+    let not_after = parse_time(validity.read()?)?;
+    let subject = format_name(tbs.expect(SEQUENCE)?)?;
+    tbs.expect(SEQUENCE)?; // subject public key info
+
+    let mut san = Vec::new();
+    while !tbs.is_empty() {
+        let (tag, content) = tbs.read()?;
+        if tag != EXPLICIT_3 {
+            continue; // issuer/subject unique IDs
+        }
+        let mut extensions = Der(Der(content).expect(SEQUENCE)?);
+        while !extensions.is_empty() {
+            let mut ext = Der(extensions.expect(SEQUENCE)?);
+            let oid = ext.expect(OID)?;
+            let (mut tag, mut value) = ext.read()?;
+            if tag != OCTET_STRING {
+                (tag, value) = ext.read()?; // skip the `critical` flag
+            }
+            if oid == OID_SAN && tag == OCTET_STRING {
+                san = parse_san(value)?;
+            }
+        }
+    }
+
+    Ok(Certificate {
+        subject,
+        issuer,
+        san,
+        not_before,
+        not_after,
+    })
+}
+
+/// `CN=example.com, O=Example Inc` from a Name's RDN sequence.
+fn format_name(content: &[u8]) -> Result<String> {
+    let mut parts = Vec::new();
+    let mut rdns = Der(content);
+    while !rdns.is_empty() {
+        let mut set = Der(rdns.expect(SET)?);
+        while !set.is_empty() {
+            let mut pair = Der(set.expect(SEQUENCE)?);
+            let oid = pair.expect(OID)?;
+            let (_, value) = pair.read()?;
+            let key = match oid {
+                [0x55, 0x04, 0x03] => "CN".to_string(),
+                [0x55, 0x04, 0x06] => "C".to_string(),
+                [0x55, 0x04, 0x07] => "L".to_string(),
+                [0x55, 0x04, 0x08] => "ST".to_string(),
+                [0x55, 0x04, 0x0a] => "O".to_string(),
+                [0x55, 0x04, 0x0b] => "OU".to_string(),
+                _ => continue,
+            };
+            parts.push(format!("{}={}", key, String::from_utf8_lossy(value)));
+        }
+    }
+    Ok(parts.join(", "))
+}
+
+fn parse_time((tag, content): (u8, &[u8])) -> Result<DateTime<Utc>> {
+    let text = std::str::from_utf8(content).context("Bad time in certificate")?;
+    let parsed = match tag {
+        // Two-digit years: 50–99 are 19xx, per RFC 5280.
+        UTC_TIME => {
+            let century = if text.get(..2).and_then(|y| y.parse::<u32>().ok()).is_some_and(|y| y >= 50) {
+                "19"
+            } else {
+                "20"
+            };
+            NaiveDateTime::parse_from_str(&format!("{}{}", century, text), "%Y%m%d%H%M%SZ")
+        }
+        GENERALIZED_TIME => NaiveDateTime::parse_from_str(text, "%Y%m%d%H%M%SZ"),
+        _ => return Err(anyhow!("Unexpected time tag {:#04x} in certificate", tag)),
+    };
+    Ok(parsed
+        .with_context(|| format!("Bad time {:?} in certificate", text))?
+        .and_utc())
+}
+
+fn parse_san(value: &[u8]) -> Result<Vec<String>> {
+    let mut names = Vec::new();
+    let mut der = Der(Der(value).expect(SEQUENCE)?);
+    while !der.is_empty() {
+        let (tag, content) = der.read()?;
+        match (tag, content.len()) {
+            (SAN_DNS, _) => names.push(String::from_utf8_lossy(content).into_owned()),
+            (SAN_IP, 4) => names.push(IpAddr::from(<[u8; 4]>::try_from(content)?).to_string()),
+            (SAN_IP, 16) => names.push(IpAddr::from(<[u8; 16]>::try_from(content)?).to_string()),
+            _ => {}
+        }
+    }
+    Ok(names)
+}
+
+impl fmt::Display for Certificate {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} (issued by {})", self.subject, self.issuer)
+    }
+}
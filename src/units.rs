@@ -0,0 +1,95 @@
+//! Unit conventions. Memory is carried around as bytes everywhere and only
+//! converted for display, in decimal units (MB, to match `ps`/`top` on
+//! macOS) or, with `--units binary`, binary ones (MiB, as `free` and
+//! Kubernetes count). Exporters keep sending bytes either way.
+
+use std::sync::atomic::{AtomicBool, Ordering};
+
+/// `--units`: powers of 1000 or of 1024.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, clap::ValueEnum)]
+pub enum Units {
+    /// KB, MB, GB.
+    #[default]
+    Decimal,
+    /// KiB, MiB, GiB.
+    Binary,
+}
+
+impl Units {
+    /// Bytes per kilo: 1000 or 1024.
+    pub fn step(self) -> f64 {
+        match self {
+            Units::Decimal => 1_000.0,
+            Units::Binary => 1_024.0,
+        }
+    }
+
+    /// Kilo, mega, giga and terabytes, as printed.
+    pub fn names(self) -> [&'static str; 4] {
+        match self {
+            Units::Decimal => ["KB", "MB", "GB", "TB"],
+            Units::Binary => ["KiB", "MiB", "GiB", "TiB"],
+        }
+    }
+}
+
+static BINARY: AtomicBool = AtomicBool::new(false);
+
+/// Sets the units everything is displayed in from now on; once, at startup.
+pub fn set_units(units: Units) {
+    BINARY.store(units == Units::Binary, Ordering::Relaxed);
+}
+
+pub fn units() -> Units {
+    if BINARY.load(Ordering::Relaxed) {
+        Units::Binary
+    } else {
+        Units::Decimal
+    }
+}
+
+/// What `to_mb` figures are in: `MB`, or `MiB` with binary units.
+pub fn mb_unit() -> &'static str {
+    units().names()[1]
+}
+
+/// Bytes -> MB (or MiB).
+pub fn to_mb(bytes: u64) -> f64 {
+    bytes as f64 / units().step().powi(2)
+}
+
+/// Formats a duration in seconds compactly, e.g. `3d 4h`, `2h 5m`, `42s`.
+pub fn format_duration(secs: u64) -> String {
+    let (days, hours, mins, secs) = (secs / 86_400, secs / 3_600 % 24, secs / 60 % 60, secs % 60);
+    if days > 0 {
+        format!("{}d {}h", days, hours)
+    } else if hours > 0 {
+        format!("{}h {}m", hours, mins)
+    } else if mins > 0 {
+        format!("{}m {}s", mins, secs)
+    } else {
+        format!("{}s", secs)
+    }
+}
+
+/// Formats a byte count for display, e.g. `42.07 MB`.
+pub fn format_mb(bytes: u64) -> String {
+    format!("{:.2} {}", to_mb(bytes), mb_unit())
+}
+
+/// Formats a byte count with the largest fitting unit, e.g. `812 B`,
+/// `4.1 KB`, `1.3 GB` (`1.2 GiB` with binary units).
+pub fn format_bytes(bytes: u64) -> String {
+    let units = units();
+    let (step, names) = (units.step(), units.names());
+    if (bytes as f64) < step {
+        return format!("{} B", bytes);
+    }
+    let mut value = bytes as f64 / step;
+    let mut unit = 0;
+    while value >= step && unit < names.len() - 1 {
+        value /= step;
+        unit += 1;
+    }
+    format!("{:.1} {}", value, names[unit])
+}
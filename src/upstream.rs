@@ -0,0 +1,194 @@
+//! Looking past a reverse proxy on the port (nginx, HAProxy, Caddy...) to the
+//! backends on this host it forwards to, for `--upstream`.
+//!
+//! Backends are found from the proxy's open connections and, when it has
+//! none yet, from its configuration. Only loopback upstreams count: those are
+//! the ones whose port can be mapped back to a local process.
+
+use crate::exec::Runner;
+use crate::process::{aggregate, cmdline_on, ProcessInfo};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ProxyKind {
+    Nginx,
+    Haproxy,
+    Caddy,
+    Envoy,
+    Traefik,
+}
+
+impl ProxyKind {
+    /// The proxy a process name belongs to, e.g. `nginx` for an nginx worker.
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "nginx" => Some(ProxyKind::Nginx),
+            "haproxy" => Some(ProxyKind::Haproxy),
+            "caddy" => Some(ProxyKind::Caddy),
+            "envoy" => Some(ProxyKind::Envoy),
+            "traefik" => Some(ProxyKind::Traefik),
+            _ => None,
+        }
+    }
+}
+
+/// One upstream port and the processes listening on it.
+#[derive(Debug, Clone)]
+pub struct Backend {
+    pub port: u16,
+    /// Empty when nothing on this host listens on the port (any more).
+    pub pids: Vec<u32>,
+    /// The backend's processes summed, as of the latest sample.
+    pub latest: Option<ProcessInfo>,
+}
+
+/// A proxy and the backends it was seen forwarding to.
+#[derive(Debug, Clone)]
+pub struct ProxyChain {
+    pub kind: ProxyKind,
+    /// Ordered by port.
+    pub backends: Vec<Backend>,
+}
+
+impl ProxyChain {
+    pub fn new(kind: ProxyKind) -> Self {
+        Self {
+            kind,
+            backends: Vec::new(),
+        }
+    }
+
+    /// Starts following ports not seen before; known ones keep their PIDs.
+    pub fn add_ports(&mut self, ports: &[u16]) {
+        for &port in ports {
+            if !self.backends.iter().any(|b| b.port == port) {
+                self.backends.push(Backend {
+                    port,
+                    pids: Vec::new(),
+                    latest: None,
+                });
+            }
+        }
+        self.backends.sort_by_key(|b| b.port);
+    }
+
+    /// The proxy and every sampled backend summed.
+    pub fn total(&self, proxy: &ProcessInfo) -> ProcessInfo {
+        let mut all = vec![proxy.clone()];
+        all.extend(self.backends.iter().filter_map(|b| b.latest.clone()));
+        aggregate(&all).expect("the proxy is always there")
+    }
+}
+
+/// Loopback ports `pid` forwards to, read through `runner`: from its
+/// established connections, or from its configuration if it has none.
+/// Connections to `listening` (the proxy's own ports) are its clients.
+pub fn upstream_ports_on(runner: &Runner, pid: u32, kind: ProxyKind, listening: &[u16]) -> Vec<u16> {
+    let connections = runner
+        .output("ss", &["-tnpH", "state", "established"])
+        .ok()
+        .filter(|out| out.status.success())
+        .map(|out| parse_upstream_connections(&String::from_utf8_lossy(&out.stdout), pid, listening))
+        .unwrap_or_default();
+    if !connections.is_empty() {
+        return connections;
+    }
+    let config = read_config(runner, pid, kind).unwrap_or_default();
+    parse_config_upstreams(kind, &config)
+        .into_iter()
+        .filter(|port| !listening.contains(port))
+        .collect()
+}
+
+fn read_config(runner: &Runner, pid: u32, kind: ProxyKind) -> Option<String> {
+    let read = |path: &str| {
+        let out = runner.output("cat", &[path]).ok()?;
+        out.status.success().then(|| String::from_utf8_lossy(&out.stdout).into_owned())
+    };
+    // The config the process was started with, e.g. `haproxy -f /etc/haproxy/haproxy.cfg`.
+    let flag_value = |flags: &[&str]| {
+        let cmdline = cmdline_on(runner, pid)?;
+        let words: Vec<&str> = cmdline.split_whitespace().collect();
+        words
+            .windows(2)
+            .find(|pair| flags.contains(&pair[0]))
+            .map(|pair| pair[1].to_string())
+    };
+    match kind {
+        // `nginx -T` prints the whole configuration with its includes resolved.
+        ProxyKind::Nginx => {
+            let out = runner.output("nginx", &["-T"]).ok()?;
+            out.status.success().then(|| String::from_utf8_lossy(&out.stdout).into_owned())
+        }
+        ProxyKind::Haproxy => read(&flag_value(&["-f"]).unwrap_or_else(|| "/etc/haproxy/haproxy.cfg".to_string())),
+        ProxyKind::Caddy => read(&flag_value(&["--config"]).unwrap_or_else(|| "/etc/caddy/Caddyfile".to_string())),
+        ProxyKind::Envoy | ProxyKind::Traefik => None,
+    }
+}
+
+/// Peer ports of `pid`'s established connections to this host, from
+/// `ss -tnpH state established` (`0 0 127.0.0.1:41234 127.0.0.1:3000 users:(("nginx",pid=1234,fd=12))`).
+pub fn parse_upstream_connections(ss: &str, pid: u32, listening: &[u16]) -> Vec<u16> {
+    let owner = format!("pid={},", pid);
+    let mut ports: Vec<u16> = ss
+        .lines()
+        .filter(|line| line.contains(&owner))
+        .filter_map(|line| {
+            let cols: Vec<&str> = line.split_whitespace().collect();
+            // `state established` drops the State column, so local and peer are 3rd and 4th.
+            let (local, peer) = (split_address(cols.get(2)?)?, split_address(cols.get(3)?)?);
+            let to_self = is_loopback(peer.0) || peer.0 == local.0;
+            (to_self && !listening.contains(&local.1)).then_some(peer.1)
+        })
+        .collect();
+    ports.sort_unstable();
+    ports.dedup();
+    ports
+}
+
+/// Loopback upstream ports named in a proxy's configuration: nginx
+/// `proxy_pass`/`fastcgi_pass`/... and `server` lines, HAProxy `server`
+/// lines, Caddy `reverse_proxy`, `to` and `php_fastcgi`.
+pub fn parse_config_upstreams(kind: ProxyKind, config: &str) -> Vec<u16> {
+    let mut ports = Vec::new();
+    // One statement per `;`, `{` or `}` (nginx allows `location / { proxy_pass ...; }`), else per line.
+    let statements = config
+        .lines()
+        .map(|line| line.split('#').next().unwrap_or(""))
+        .flat_map(|line| line.split([';', '{', '}']));
+    for statement in statements {
+        let words: Vec<&str> = statement.split_whitespace().collect();
+        let Some((&directive, args)) = words.split_first() else {
+            continue;
+        };
+        let addresses: &[&str] = match (kind, directive) {
+            (ProxyKind::Nginx, "proxy_pass" | "fastcgi_pass" | "uwsgi_pass" | "scgi_pass" | "grpc_pass" | "server") => {
+                &args[..args.len().min(1)]
+            }
+            (ProxyKind::Haproxy, "server") => args.get(1..2).unwrap_or(&[]),
+            (ProxyKind::Caddy, "reverse_proxy" | "to" | "php_fastcgi") => args,
+            _ => &[],
+        };
+        ports.extend(addresses.iter().filter_map(|address| loopback_port(address)));
+    }
+    ports.sort_unstable();
+    ports.dedup();
+    ports
+}
+
+// `http://127.0.0.1:3000/api`, `localhost:9000`, `[::1]:8000` or Caddy's `:3000`.
+fn loopback_port(address: &str) -> Option<u16> {
+    let address = address.split_once("://").map_or(address, |(_, rest)| rest);
+    let address = address.split('/').next().unwrap_or(address);
+    let (host, port) = split_address(address)?;
+    (host.is_empty() || host == "localhost" || is_loopback(host)).then_some(port)
+}
+
+fn split_address(address: &str) -> Option<(&str, u16)> {
+    let (host, port) = address.rsplit_once(':')?;
+    Some((host.trim_start_matches('[').trim_end_matches(']'), port.parse().ok()?))
+}
+
+fn is_loopback(host: &str) -> bool {
+    let host = host.strip_prefix("::ffff:").unwrap_or(host);
+    host.starts_with("127.") || host == "::1"
+}
@@ -0,0 +1,1252 @@
+use std::collections::{HashMap, VecDeque};
+use std::path::Path;
+use std::time::{Duration, Instant};
+
+use anyhow::{anyhow, Context, Result};
+use chrono::Local;
+use tracing::{debug, info};
+
+use crate::annotation::{Annotation, AnnotationKind};
+use crate::anomaly::{AnomalyDetector, Signal};
+use crate::cgroup::{self, CgroupStats};
+use crate::children::{ChildFeed, MIN_ZOMBIES, ZOMBIE_WINDOW};
+use crate::cores::CoreTimes;
+use crate::ebpf::EbpfStats;
+use crate::error::{self, Error};
+use crate::export::{Alert, ExportSample};
+use crate::files::OpenFile;
+use crate::logs::{LogSource, LogTail, LOG_LINES};
+use crate::history::ProcessHistory;
+use crate::oom::OomStatus;
+use crate::pattern::Pattern;
+use crate::peers::{top_peers, Peer, PeerResolver, PeerTraffic, DEFAULT_PEER_ROWS};
+use crate::pool::{worker_group, WorkerPool, MIN_WORKERS};
+use crate::port::{AcceptQueue, Listener, PortConnection, TcpStats};
+use crate::posture::Posture;
+use crate::rules::{Readings, RuleEngine};
+use crate::runtime::{Runtime, StackDump};
+use crate::segments::SegmentHistory;
+use crate::socket::ListenerSockets;
+use crate::process::{aggregate, CpuMode, ProcessCounters, ProcessInfo, SystemSnapshot};
+use crate::source::MetricsSource;
+use crate::syscalls::SyscallCounts;
+use crate::syslog;
+use crate::systemd::{UnitAction, UnitStatus};
+use crate::target::Target;
+use crate::units::format_mb;
+use crate::upstream::{ProxyChain, ProxyKind};
+use crate::watchdog::{Verdict, Watchdog};
+
+/// Number of samples kept for averages, peaks and sparklines.
+pub const HISTORY_LEN: usize = 60;
+
+/// How long `--match` waits for a replacement after the followed process exits.
+const RESPAWN_WAIT: Duration = Duration::from_secs(5);
+const RESPAWN_POLL: Duration = Duration::from_millis(250);
+
+/// Ticks between looks for new backends behind a proxy, with `--upstream`.
+const UPSTREAM_REFRESH: u64 = 10;
+
+/// Ticks between readings of what changes slowly but costs a process spawn
+/// or a walk of `/proc` to read: the systemd unit and the OOM standing, and
+/// the TCP stats while the Network tab isn't shown.
+pub const SLOW_REFRESH: u64 = 5;
+
+/// Timeline events kept for the charts; older ones are dropped.
+pub const MAX_ANNOTATIONS: usize = 200;
+
+/// Ticks of thread samples summed for `--syscalls` without eBPF.
+pub const SYSCALL_WINDOW: usize = 10;
+
+/// How full the port's accept queue has been over the session.
+#[derive(Debug, Clone, Default)]
+pub struct AcceptQueueHistory {
+    pub latest: AcceptQueue,
+    /// Queue depth at each sample, oldest first.
+    pub depths: Vec<u32>,
+    /// Rise in the host's ListenOverflows counter since the previous sample.
+    pub new_overflows: Option<u64>,
+}
+
+impl AcceptQueueHistory {
+    fn add(&mut self, queue: AcceptQueue, max_history: usize) {
+        self.new_overflows = match (queue.listen_overflows, self.latest.listen_overflows) {
+            (Some(now), Some(prev)) if !self.depths.is_empty() => Some(now.saturating_sub(prev)),
+            _ => None,
+        };
+        self.latest = queue;
+        self.depths.push(queue.queued);
+        if self.depths.len() > max_history {
+            self.depths.remove(0);
+        }
+    }
+
+    pub fn peak(&self) -> u32 {
+        self.depths.iter().copied().max().unwrap_or(0)
+    }
+}
+
+/// Connections opened and closed on the port, from the difference between
+/// successive socket tables. One that opens and closes within an interval
+/// is still caught while it sits in TIME-WAIT.
+#[derive(Debug, Clone, Default)]
+pub struct ChurnHistory {
+    previous: Option<HashMap<String, bool>>,
+    /// New connections in each interval, oldest first.
+    pub opened: Vec<u32>,
+    /// Connections that closed in each interval.
+    pub closed: Vec<u32>,
+    /// Length of each interval.
+    pub seconds: Vec<f64>,
+    /// Connections open at the latest reading.
+    pub open_now: usize,
+}
+
+impl ChurnHistory {
+    /// Records a socket table read `elapsed` after the previous one.
+    pub fn add(&mut self, connections: &[PortConnection], elapsed: Duration, max_history: usize) {
+        let now: HashMap<String, bool> = connections.iter().map(|c| (c.key.clone(), c.open)).collect();
+        self.open_now = now.values().filter(|&&open| open).count();
+        if let Some(previous) = &self.previous {
+            let opened = now.keys().filter(|key| !previous.contains_key(*key)).count();
+            // Open before and not any more, or opened and already closing.
+            let closed = previous.iter().filter(|(key, &was_open)| was_open && now.get(*key) != Some(&true)).count()
+                + now.iter().filter(|(key, &open)| !open && !previous.contains_key(*key)).count();
+            for (series, value) in [(&mut self.opened, opened), (&mut self.closed, closed)] {
+                series.push(value as u32);
+                if series.len() > max_history {
+                    series.remove(0);
+                }
+            }
+            self.seconds.push(elapsed.as_secs_f64());
+            if self.seconds.len() > max_history {
+                self.seconds.remove(0);
+            }
+        }
+        self.previous = Some(now);
+    }
+
+    /// `counts` (`opened` or `closed`) as per-second rates.
+    pub fn per_second(&self, counts: &[u32]) -> Vec<f64> {
+        counts
+            .iter()
+            .zip(&self.seconds)
+            .map(|(&count, &secs)| count as f64 / secs.max(f64::EPSILON))
+            .collect()
+    }
+}
+
+/// How much of the process is out in swap, per sample. Low RSS with bad
+/// latency is usually this: the pages it needs are on disk.
+#[derive(Debug, Clone, Default)]
+pub struct SwapHistory {
+    /// Swapped-out bytes, one entry per sample, oldest first.
+    pub swapped: Vec<u64>,
+    /// Resident bytes at the latest sample.
+    resident: u64,
+}
+
+impl SwapHistory {
+    fn add(&mut self, swapped: u64, resident: u64, max_history: usize) {
+        self.swapped.push(swapped);
+        if self.swapped.len() > max_history {
+            self.swapped.remove(0);
+        }
+        self.resident = resident;
+    }
+
+    pub fn latest(&self) -> u64 {
+        self.swapped.last().copied().unwrap_or(0)
+    }
+
+    /// Bytes that went out to swap since the previous sample.
+    pub fn growth(&self) -> u64 {
+        match self.swapped[..] {
+            [.., previous, latest] => latest.saturating_sub(previous),
+            _ => 0,
+        }
+    }
+
+    /// Whether more of the process went out to swap since the last sample.
+    pub fn is_swapping(&self) -> bool {
+        self.growth() > 0
+    }
+
+    /// Percent of the process (resident plus swapped) that is in swap.
+    pub fn share_percent(&self) -> f64 {
+        let swapped = self.latest();
+        swapped as f64 * 100.0 / (swapped + self.resident).max(1) as f64
+    }
+}
+
+/// Page faults and context switches per interval, from the lifetime counters
+/// in successive samples.
+#[derive(Debug, Clone, Default)]
+pub struct CounterHistory {
+    previous: Option<ProcessCounters>,
+    /// One entry per interval, oldest first.
+    pub deltas: Vec<ProcessCounters>,
+}
+
+impl CounterHistory {
+    fn add(&mut self, counters: ProcessCounters, max_history: usize) {
+        if let Some(previous) = self.previous {
+            self.deltas.push(counters.since(&previous));
+            if self.deltas.len() > max_history {
+                self.deltas.remove(0);
+            }
+        }
+        self.previous = Some(counters);
+    }
+
+    pub fn latest(&self) -> Option<&ProcessCounters> {
+        self.deltas.last()
+    }
+
+    /// One counter's per-interval series, for a sparkline.
+    pub fn series(&self, field: fn(&ProcessCounters) -> u64) -> Vec<f32> {
+        self.deltas.iter().map(|d| field(d) as f32).collect()
+    }
+}
+
+/// The eBPF counters per interval, from their running totals.
+#[derive(Debug, Clone, Default)]
+pub struct EbpfHistory {
+    previous: Option<EbpfStats>,
+    /// One entry per interval, oldest first.
+    pub deltas: Vec<EbpfStats>,
+}
+
+impl EbpfHistory {
+    pub fn add(&mut self, totals: EbpfStats, max_history: usize) {
+        if let Some(previous) = self.previous {
+            self.deltas.push(totals - previous);
+            if self.deltas.len() > max_history {
+                self.deltas.remove(0);
+            }
+        }
+        self.previous = Some(totals);
+    }
+
+    pub fn latest(&self) -> Option<&EbpfStats> {
+        self.deltas.last()
+    }
+
+    pub fn series(&self, field: fn(&EbpfStats) -> u64) -> Vec<f32> {
+        self.deltas.iter().map(|d| field(d) as f32).collect()
+    }
+}
+
+/// Where the process's syscalls went, with `--syscalls`: counted by eBPF
+/// over the last interval, or else sampled from its threads on each tick.
+#[derive(Debug, Clone, Default)]
+pub struct SyscallActivity {
+    /// True when `readings` are eBPF counts rather than thread samples.
+    pub counted: bool,
+    readings: VecDeque<SyscallCounts>,
+}
+
+impl SyscallActivity {
+    pub fn add(&mut self, counts: SyscallCounts, counted: bool) {
+        if counted != self.counted {
+            self.readings.clear();
+            self.counted = counted;
+        }
+        self.readings.push_back(counts);
+        let keep = if counted { 1 } else { SYSCALL_WINDOW };
+        while self.readings.len() > keep {
+            self.readings.pop_front();
+        }
+    }
+
+    /// The last interval's calls, or the thread samples of the window summed.
+    pub fn summary(&self) -> SyscallCounts {
+        let mut total = SyscallCounts::default();
+        for &reading in &self.readings {
+            total += reading;
+        }
+        total
+    }
+
+    /// Ticks sampled so far, up to `SYSCALL_WINDOW`.
+    pub fn samples(&self) -> usize {
+        self.readings.len()
+    }
+}
+
+/// How much of each core the process's threads used over the last interval.
+/// A thread's time is charged to the core it was last seen on.
+#[derive(Debug, Clone, Default)]
+pub struct CoreUsage {
+    previous: Option<CoreTimes>,
+    /// Percent of each core's time, by CPU number; empty until two readings.
+    pub percent: Vec<f64>,
+    /// Threads last seen on each core, by CPU number.
+    pub threads: Vec<usize>,
+}
+
+impl CoreUsage {
+    fn add(&mut self, times: CoreTimes) {
+        let cores = times.core_ticks.len();
+        self.threads = vec![0; cores];
+        for thread in &times.threads {
+            if let Some(count) = self.threads.get_mut(thread.cpu as usize) {
+                *count += 1;
+            }
+        }
+        if let Some(previous) = &self.previous {
+            let before: HashMap<u32, u64> = previous.threads.iter().map(|t| (t.tid, t.ticks)).collect();
+            let mut used = vec![0u64; cores];
+            for thread in &times.threads {
+                // A thread we haven't seen before started during the interval.
+                let ticks = thread.ticks.saturating_sub(before.get(&thread.tid).copied().unwrap_or(0));
+                if let Some(slot) = used.get_mut(thread.cpu as usize) {
+                    *slot += ticks;
+                }
+            }
+            self.percent = used
+                .iter()
+                .enumerate()
+                .map(|(cpu, &ticks)| {
+                    let elapsed = times.core_ticks[cpu].saturating_sub(previous.core_ticks.get(cpu).copied().unwrap_or(0));
+                    if elapsed == 0 {
+                        0.0
+                    } else {
+                        (ticks as f64 / elapsed as f64 * 100.0).min(100.0)
+                    }
+                })
+                .collect();
+        }
+        self.previous = Some(times);
+    }
+
+    /// The core the process kept busiest, and how busy.
+    pub fn busiest(&self) -> Option<(usize, f64)> {
+        self.percent
+            .iter()
+            .copied()
+            .enumerate()
+            .max_by(|a, b| a.1.total_cmp(&b.1))
+    }
+}
+
+/// The watch dashboard's views, picked with 1-5 or Tab.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Tab {
+    /// CPU, memory, cgroup limits and the rest of the process's own figures.
+    Resources,
+    /// Accept queue, TCP stats, connection churn, probes and upstreams.
+    Network,
+    /// Per-core usage, workers, child processes, syscalls and stack dumps.
+    Threads,
+    Files,
+    Logs,
+}
+
+pub const TABS: [Tab; 5] = [Tab::Resources, Tab::Network, Tab::Threads, Tab::Files, Tab::Logs];
+
+impl Tab {
+    pub fn label(self) -> &'static str {
+        match self {
+            Tab::Resources => "Resources",
+            Tab::Network => "Network",
+            Tab::Threads => "Threads",
+            Tab::Files => "Files",
+            Tab::Logs => "Logs",
+        }
+    }
+
+    /// The tab a number key selects, '1' being the first.
+    pub fn from_key(key: char) -> Option<Tab> {
+        let index = key.to_digit(10)?.checked_sub(1)?;
+        TABS.get(index as usize).copied()
+    }
+
+    /// The key that selects it.
+    pub fn key(self) -> usize {
+        TABS.iter().position(|&tab| tab == self).expect("every tab is in TABS") + 1
+    }
+
+    /// The one after, wrapping around (Tab).
+    pub fn next(self) -> Tab {
+        TABS[self.key() % TABS.len()]
+    }
+
+    /// The one before, wrapping around (Shift-Tab).
+    pub fn previous(self) -> Tab {
+        TABS[(self.key() + TABS.len() - 2) % TABS.len()]
+    }
+}
+
+/// The data side of watch mode: which process we're following and what we've
+/// seen of it so far. Terminal handling lives in the binary.
+pub struct WatchSession<S: MetricsSource> {
+    source: S,
+    pub pid: u32,
+    /// Every PID being followed. More than one means samples are aggregated.
+    pub pids: Vec<u32>,
+    pub port: u16,
+    pub history: ProcessHistory,
+    pub iteration: u64,
+    pub system: Option<SystemSnapshot>,
+    /// All sockets the process listens on, looked up again on the slow refreshes.
+    pub listeners: Vec<Listener>,
+    /// Latest cgroup reading for the followed process, if it has one.
+    pub cgroup: Option<CgroupStats>,
+    /// Share of CFS periods throttled between the last two cgroup readings.
+    pub throttled_ratio: Option<f64>,
+    /// Accept-queue readings, once the source has reported one.
+    pub accept_queue: Option<AcceptQueueHistory>,
+    /// Latest `tcp_info` summary of the port's connections.
+    pub tcp_stats: Option<TcpStats>,
+    /// Backlog, buffers and port sharing of the port's listening sockets.
+    pub sockets: Option<ListenerSockets>,
+    /// Connections opened and closed per interval.
+    pub churn: Option<ChurnHistory>,
+    /// Kernel counters from eBPF probes, when they could be attached.
+    pub ebpf: Option<EbpfHistory>,
+    /// When the socket table behind `churn` was last read.
+    churn_read_at: Option<Instant>,
+    /// The top peers of the port's open connections, read with the Network tab.
+    pub peers: Option<Vec<Peer>>,
+    peer_rows: usize,
+    traffic: PeerTraffic,
+    /// `--resolve-peers`/`--geoip`: names and places the peers are looked up in.
+    resolver: Option<PeerResolver>,
+    /// Fault and context-switch rates, when the source reports the counters.
+    pub counters: Option<CounterHistory>,
+    /// Latest OOM-killer standing of the followed process.
+    pub oom: Option<OomStatus>,
+    /// User, capabilities, binary and confinement, read with the Network tab.
+    pub posture: Option<Posture>,
+    /// The process's swapped-out pages, where the platform reports them.
+    pub swap: Option<SwapHistory>,
+    /// Resident memory by kind of mapping, on Linux, to tell which one grows.
+    pub segments: Option<SegmentHistory>,
+    /// Per-core usage of the followed process's threads.
+    pub cores: Option<CoreUsage>,
+    /// The systemd service the followed process runs under.
+    pub unit: Option<UnitStatus>,
+    /// The language runtime of the followed process, when stacks can be dumped.
+    pub runtime: Option<Runtime>,
+    /// `--match`: when the followed process exits, switch to the next one
+    /// whose command line matches instead of stopping.
+    pub matcher: Option<Pattern>,
+    /// `--target-cgroup`/`--target-container`: the processes are the
+    /// container's, looked up again every tick; there is no port (it is 0).
+    pub target: Option<Target>,
+    /// The followed process's workers, once it looks like a prefork server.
+    pub pool: Option<WorkerPool>,
+    /// The watched processes' children being spawned and exiting.
+    pub children: ChildFeed,
+    /// `--upstream`: look past a reverse proxy to the backends it forwards to.
+    pub follow_upstream: bool,
+    /// The backends behind the followed proxy, once one is recognised.
+    pub upstream: Option<ProxyChain>,
+    /// `--syscalls`: break the process's syscalls down by category.
+    pub follow_syscalls: bool,
+    pub syscalls: Option<SyscallActivity>,
+    /// Restarts, threshold breaches and markers, oldest first.
+    pub annotations: Vec<Annotation>,
+    /// Annotations not yet handed to the exporters.
+    fresh_annotations: Vec<Annotation>,
+    /// Breaches, restarts and exits as exporter alerts, not yet handed over.
+    fresh_alerts: Vec<Alert>,
+    /// Alerted conditions that have since cleared, not yet handed over.
+    fresh_recoveries: Vec<Alert>,
+    /// Retransmit percent that marks the timeline when crossed.
+    pub retransmit_alert: Option<f64>,
+    // Whether each threshold was over on the last tick, so a breach is marked once.
+    retransmits_over: bool,
+    oom_at_risk: bool,
+    swapping: bool,
+    /// `zombies_growing` fired and the zombies haven't been reaped since.
+    zombies_piling: bool,
+    /// The process exited and it's been alerted on; cleared by the next sample.
+    exited: bool,
+    /// The config file's `[[rule]]`s, evaluated on every sample.
+    rules: RuleEngine,
+    /// CPU and memory bands; anomalies are marked on the timeline.
+    pub anomalies: Option<AnomalyDetector>,
+    /// Also raise `cpu_anomaly` and `memory_anomaly` alerts.
+    anomaly_alerts: bool,
+    /// `[watchdog]`: restarts the process when it stays unhealthy.
+    pub watchdog: Option<Watchdog>,
+    /// The watchdog just acted, so a failed sample waits for the process
+    /// to come back rather than ending the session.
+    restarting: bool,
+    /// Take the `SLOW_REFRESH` readings on the next tick, whatever its number.
+    refresh_slow: bool,
+    /// `--cpu-mode`, applied to every sample (the workers' and backends' too).
+    pub cpu_mode: CpuMode,
+    /// Cores of the process's host, looked up for `CpuMode::System`.
+    pub cpu_count: usize,
+    /// The tab on screen; readings only it shows are skipped on the others.
+    /// `None` (daemon mode, the fleet view) reads everything.
+    pub tab: Option<Tab>,
+}
+
+impl<S: MetricsSource> WatchSession<S> {
+    pub fn new(source: S, port: u16) -> Result<Self> {
+        let pid = source
+            .resolve_pid(port)
+            .with_context(|| format!("No process found listening on port {}", port))?;
+
+        Self::with_pids(source, port, vec![pid])
+    }
+
+    /// Follows an explicit set of PIDs, e.g. all the listeners of a shared port.
+    pub fn with_pids(mut source: S, port: u16, pids: Vec<u32>) -> Result<Self> {
+        let pid = *pids
+            .first()
+            .with_context(|| format!("No process selected for port {}", port))?;
+
+        // Not fatal: the dashboard just falls back to showing the queried port.
+        let listeners = source.listeners(pid).unwrap_or_default();
+        let runtime = source.runtime(pid);
+
+        Ok(Self {
+            source,
+            pid,
+            pids,
+            port,
+            history: ProcessHistory::new(HISTORY_LEN),
+            iteration: 0,
+            system: None,
+            listeners,
+            cgroup: None,
+            throttled_ratio: None,
+            accept_queue: None,
+            tcp_stats: None,
+            sockets: None,
+            churn: None,
+            churn_read_at: None,
+            peers: None,
+            peer_rows: DEFAULT_PEER_ROWS,
+            traffic: PeerTraffic::default(),
+            resolver: None,
+            ebpf: None,
+            counters: None,
+            oom: None,
+            posture: None,
+            swap: None,
+            segments: None,
+            cores: None,
+            unit: None,
+            runtime,
+            matcher: None,
+            target: None,
+            pool: None,
+            children: ChildFeed::new(),
+            follow_upstream: false,
+            upstream: None,
+            follow_syscalls: false,
+            syscalls: None,
+            annotations: Vec::new(),
+            fresh_annotations: Vec::new(),
+            fresh_alerts: Vec::new(),
+            fresh_recoveries: Vec::new(),
+            retransmit_alert: None,
+            retransmits_over: false,
+            oom_at_risk: false,
+            swapping: false,
+            zombies_piling: false,
+            exited: false,
+            rules: RuleEngine::default(),
+            anomalies: None,
+            anomaly_alerts: false,
+            watchdog: None,
+            restarting: false,
+            refresh_slow: false,
+            cpu_mode: CpuMode::Process,
+            cpu_count: 0,
+            tab: None,
+        })
+    }
+
+    /// Re-attaches to whatever matches `pattern` once the followed process exits.
+    pub fn follow_matching(mut self, pattern: Option<Pattern>) -> Self {
+        self.matcher = pattern;
+        self
+    }
+
+    /// Acts on the process, as `[watchdog]` says, when it stays unhealthy.
+    pub fn with_watchdog(mut self, watchdog: Option<Watchdog>) -> Self {
+        self.watchdog = watchdog;
+        self
+    }
+
+    /// Follows a container's processes rather than a port's.
+    pub fn follow_target(mut self, target: Option<Target>) -> Self {
+        self.target = target;
+        self
+    }
+
+    /// Also samples the backends behind the port when it's served by a
+    /// known reverse proxy.
+    pub fn follow_upstream(mut self, enabled: bool) -> Self {
+        self.follow_upstream = enabled;
+        self
+    }
+
+    /// How many of the port's peers to list, most connections first.
+    pub fn top_peers(mut self, rows: usize) -> Self {
+        self.peer_rows = rows;
+        self
+    }
+
+    /// Looks the listed peers up in `resolver`, for their names and places.
+    pub fn resolve_peers(mut self, resolver: Option<PeerResolver>) -> Self {
+        self.resolver = resolver;
+        self
+    }
+
+    /// Marks the timeline when the port's retransmitted share reaches `percent`.
+    pub fn alert_on_retransmits(mut self, percent: f64) -> Self {
+        self.retransmit_alert = Some(percent);
+        self
+    }
+
+    /// Raises an alert whenever one of `rules` fires, and a recovery when it clears.
+    pub fn with_rules(mut self, rules: RuleEngine) -> Self {
+        self.rules = rules;
+        self
+    }
+
+    /// Marks samples more than `sigma` deviations from the process's usual
+    /// CPU or memory (0 turns it off), raising alerts too when `alert`.
+    pub fn detect_anomalies(mut self, sigma: f64, alert: bool) -> Self {
+        self.anomalies = (sigma > 0.0).then(|| AnomalyDetector::new(sigma));
+        self.anomaly_alerts = alert;
+        self
+    }
+
+    /// `--resume`: starts the history from `samples` an earlier session
+    /// recorded, with their annotations, so the chart isn't empty after a
+    /// restart. Anomaly bands already set up learn from them too.
+    pub fn resume(mut self, samples: &[ExportSample]) -> Self {
+        for sample in samples {
+            for annotation in &sample.annotations {
+                self.annotations.push(Annotation {
+                    sample: self.history.added(),
+                    ..annotation.clone()
+                });
+            }
+            self.history.add_at(&sample.process, sample.timestamp);
+            if let Some(detector) = &mut self.anomalies {
+                detector.observe(sample.process.cpu_percent as f64, sample.process.memory_bytes as f64);
+            }
+        }
+        let excess = self.annotations.len().saturating_sub(MAX_ANNOTATIONS);
+        self.annotations.drain(..excess);
+        self
+    }
+
+    /// Keeps up to `samples` of CPU and memory for the scrollable history
+    /// chart, beyond the window averages are taken over.
+    pub fn keep_history(mut self, samples: usize) -> Self {
+        self.history.keep_session(samples);
+        self
+    }
+
+    /// Gives CPU as a share of the whole host rather than of one core.
+    pub fn cpu_mode(mut self, mode: CpuMode) -> Self {
+        if mode == CpuMode::System {
+            self.cpu_count = self.source.system().map_or(0, |system| system.cpu_count);
+        }
+        self.cpu_mode = mode;
+        self
+    }
+
+    /// Also works out what the process's syscalls go to (IO, locking...).
+    pub fn follow_syscalls(mut self, enabled: bool) -> Self {
+        self.follow_syscalls = enabled;
+        self
+    }
+
+    /// Switches the dashboard to `tab`. Rates worked out between two readings
+    /// (connection churn, peer traffic, per-core usage, syscalls) start over
+    /// when their tab is left, rather than averaging over the time it was
+    /// hidden.
+    pub fn show_tab(&mut self, tab: Tab) {
+        if self.tab.is_some_and(|shown| shown != tab) {
+            match self.tab {
+                Some(Tab::Network) => {
+                    self.churn = None;
+                    self.churn_read_at = None;
+                    self.traffic.reset();
+                }
+                Some(Tab::Threads) => {
+                    self.cores = None;
+                    self.syscalls = None;
+                }
+                _ => {}
+            }
+        }
+        self.tab = Some(tab);
+    }
+
+    /// Whether readings for `tab` are taken this tick.
+    fn collects(&self, tab: Tab) -> bool {
+        self.tab.is_none_or(|shown| shown == tab)
+    }
+
+    /// Takes the next sample and records it in the history.
+    pub async fn tick(&mut self) -> Result<ProcessInfo> {
+        // A container that has stopped keeps its last PIDs, so the exit is seen.
+        if let Some(pids) = self.target.as_ref().and_then(|t| t.pids().ok()) {
+            if pids != self.pids {
+                self.attach(pids);
+            }
+        }
+        let sampled = match self.sample().await {
+            Err(e) if self.matcher.is_some() || std::mem::take(&mut self.restarting) => match self.follow_respawn(e).await {
+                Ok(()) => self.sample().await,
+                Err(e) => Err(e),
+            },
+            result => result,
+        };
+        let info = match sampled {
+            Ok(info) => info,
+            Err(e) => {
+                if matches!(error::find(&e), Some(Error::ProcessGone { .. })) && !self.exited {
+                    self.exited = true;
+                    self.alert("process_exited", format!("PID {} exited", self.pid), self.pid as f64);
+                }
+                return Err(e);
+            }
+        };
+        if std::mem::take(&mut self.exited) {
+            self.recover("process_exited", format!("PID {} is running", self.pid), self.pid as f64);
+        }
+        self.iteration += 1;
+        self.history.add(&info);
+        if let Some(counters) = info.counters {
+            self.counters.get_or_insert_with(Default::default).add(counters, HISTORY_LEN);
+        }
+        if let Some(swapped) = info.swap_bytes {
+            self.swap.get_or_insert_with(Default::default).add(swapped, info.memory_bytes, HISTORY_LEN);
+        }
+
+        let cgroup = match self.target.as_ref().and_then(Target::cgroup_dir) {
+            Some(dir) => cgroup::read_stats(&dir),
+            None => self.source.cgroup(self.pid),
+        };
+        self.throttled_ratio = match (&cgroup, &self.cgroup) {
+            (Some(now), Some(prev)) => now.cpu_stat.throttled_ratio_since(&prev.cpu_stat),
+            _ => None,
+        };
+        self.cgroup = cgroup;
+
+        // Read on a slow tick (after a restart too), so nothing stays stale for long.
+        let slow = self.iteration % SLOW_REFRESH == 1 || std::mem::take(&mut self.refresh_slow);
+        // The retransmit alert and the exporters' connection count come from
+        // the TCP stats too, so they are still read off the Network tab, just
+        // less often.
+        if self.port != 0 && (slow || self.collects(Tab::Network)) {
+            self.tcp_stats = self.source.tcp_stats(self.port);
+        }
+        // Mostly set as the socket is opened, so they rarely change.
+        if self.port != 0 && (slow || self.sockets.is_none()) && self.collects(Tab::Network) {
+            self.sockets = self.source.listener_sockets(self.port);
+        }
+        // A server may drop root after binding, so read again now and then.
+        if (slow || self.posture.is_none()) && self.collects(Tab::Network) {
+            self.posture = self.source.security_posture(self.pid);
+        }
+        if self.port != 0 && self.collects(Tab::Network) {
+            if let Some(queue) = self.source.accept_queue(self.port) {
+                self.accept_queue.get_or_insert_with(Default::default).add(queue, HISTORY_LEN);
+            }
+            if let Some(connections) = self.source.port_connections(self.port) {
+                let now = Instant::now();
+                let elapsed = self.churn_read_at.map_or(Duration::ZERO, |at| now - at);
+                self.churn.get_or_insert_with(Default::default).add(&connections, elapsed, HISTORY_LEN);
+                self.churn_read_at = Some(now);
+                self.update_peers(&connections, now);
+            }
+        }
+        if let Some(totals) = self.source.ebpf_stats(self.pid, self.port) {
+            self.ebpf.get_or_insert_with(Default::default).add(totals, HISTORY_LEN);
+        }
+        if self.follow_syscalls && self.collects(Tab::Threads) {
+            self.update_syscalls();
+        }
+        if slow {
+            self.oom = self.source.oom_status(self.pid);
+            self.unit = self.source.systemd_unit(self.pid);
+            self.update_listeners();
+        }
+        if slow && self.collects(Tab::Resources) {
+            if let Some(segments) = self.source.memory_segments(self.pid) {
+                self.segments.get_or_insert_with(Default::default).add(Local::now(), segments);
+            }
+        }
+        let children = self.source.child_processes(&self.pids);
+        self.children.update(children, Local::now());
+        self.check_thresholds();
+        self.check_anomalies(&info);
+        self.evaluate_rules(&info);
+        self.check_watchdog(&info).await;
+        if self.collects(Tab::Threads) {
+            if let Some(times) = self.source.core_times(self.pid) {
+                self.cores.get_or_insert_with(Default::default).add(times);
+            }
+            self.update_pool(&info).await;
+        }
+        if self.follow_upstream && self.collects(Tab::Network) {
+            self.update_upstream(&info).await;
+        }
+
+        Ok(info)
+    }
+
+    /// The top peers of `connections`, with their traffic since the last
+    /// reading when the connections' counters can be read.
+    fn update_peers(&mut self, connections: &[PortConnection], now: Instant) {
+        let mut peers = top_peers(connections, self.peer_rows);
+        match self.source.connection_bytes(self.port) {
+            Some(bytes) => {
+                if let Some(rates) = self.traffic.update(&bytes, now) {
+                    for peer in &mut peers {
+                        peer.bytes_per_sec = Some(rates.get(&peer.address).copied().unwrap_or(0.0));
+                    }
+                }
+            }
+            None => self.traffic.reset(),
+        }
+        if let Some(resolver) = &mut self.resolver {
+            resolver.enrich(&mut peers);
+        }
+        self.peers = Some(peers);
+    }
+
+    /// Pins an event to the timeline at the current sample.
+    pub fn annotate(&mut self, kind: AnnotationKind, text: impl Into<String>) {
+        let annotation = Annotation {
+            timestamp: Local::now(),
+            kind,
+            text: text.into(),
+            sample: self.history.added(),
+        };
+        self.fresh_annotations.push(annotation.clone());
+        self.annotations.push(annotation);
+        if self.annotations.len() > MAX_ANNOTATIONS {
+            self.annotations.remove(0);
+        }
+    }
+
+    /// Annotations made since the last call, for the exporters.
+    pub fn take_fresh_annotations(&mut self) -> Vec<Annotation> {
+        std::mem::take(&mut self.fresh_annotations)
+    }
+
+    /// Alerts raised since the last call, for the exporters.
+    pub fn take_fresh_alerts(&mut self) -> Vec<Alert> {
+        std::mem::take(&mut self.fresh_alerts)
+    }
+
+    /// Conditions alerted on that have since cleared, for the exporters.
+    pub fn take_fresh_recoveries(&mut self) -> Vec<Alert> {
+        std::mem::take(&mut self.fresh_recoveries)
+    }
+
+    fn alert(&mut self, rule: &str, message: String, value: f64) {
+        info!(rule, "{}", message);
+        let alert = self.alert_for(rule, message, value);
+        self.fresh_alerts.push(alert);
+    }
+
+    /// The all-clear for an earlier `rule` alert; `message` says how things stand now.
+    fn recover(&mut self, rule: &str, message: String, value: f64) {
+        info!(rule, "Recovered: {}", message);
+        let alert = self.alert_for(rule, message, value);
+        self.fresh_recoveries.push(alert);
+    }
+
+    fn alert_for(&self, rule: &str, message: String, value: f64) -> Alert {
+        Alert {
+            timestamp: Local::now(),
+            host: None,
+            port: self.port,
+            rule: rule.to_string(),
+            severity: syslog::severity(rule),
+            message,
+            value,
+        }
+    }
+
+    fn check_anomalies(&mut self, info: &ProcessInfo) {
+        let Some(detector) = &mut self.anomalies else { return };
+        let signals = [Signal::Cpu, Signal::Memory];
+        let was_out = signals.map(|signal| detector.is_out(signal));
+        let fresh = detector.observe(info.cpu_percent as f64, info.memory_bytes as f64);
+        let back: Vec<Signal> = signals
+            .into_iter()
+            .zip(was_out)
+            .filter(|&(signal, was)| was && !detector.is_out(signal))
+            .map(|(signal, _)| signal)
+            .collect();
+        for anomaly in fresh {
+            let message = anomaly.describe();
+            if self.anomaly_alerts {
+                self.alert(anomaly.signal.rule(), message.clone(), anomaly.value);
+            }
+            self.annotate(AnnotationKind::Anomaly, message);
+        }
+        if self.anomaly_alerts {
+            for signal in back {
+                let (message, value) = match signal {
+                    Signal::Cpu => (format!("CPU back in its usual range at {:.1}%", info.cpu_percent), info.cpu_percent as f64),
+                    Signal::Memory => (format!("Memory back in its usual range at {}", format_mb(info.memory_bytes)), info.memory_bytes as f64),
+                };
+                self.recover(signal.rule(), message, value);
+            }
+        }
+    }
+
+    async fn check_watchdog(&mut self, info: &ProcessInfo) {
+        let Some(watchdog) = &mut self.watchdog else { return };
+        let finished = watchdog.finished().await;
+        let mut started = None;
+        let healthy = watchdog.check_health().await;
+        match watchdog.judge(Local::now(), healthy, info.memory_bytes) {
+            Verdict::Fine => {}
+            Verdict::Held(trigger, reason) => {
+                self.alert("watchdog_held", format!("Watchdog held back ({}): {}", reason, trigger), info.memory_bytes as f64);
+            }
+            Verdict::Act(trigger) => {
+                // Marked as soon as it starts, so the process going away isn't an error.
+                self.restarting = true;
+                started = watchdog.act(Local::now(), &trigger, self.pid, self.port);
+            }
+        }
+        for done in finished.into_iter().chain(started) {
+            self.annotate(AnnotationKind::Watchdog, done.clone());
+            self.alert("watchdog_action", format!("Watchdog: {}", done), info.memory_bytes as f64);
+        }
+    }
+
+    fn evaluate_rules(&mut self, info: &ProcessInfo) {
+        if self.rules.is_empty() {
+            return;
+        }
+        let readings = Readings::new(info, self.tcp_stats.map(|s| s.connections), self.tcp_stats.and_then(|s| s.retransmit_percent()));
+        for transition in self.rules.evaluate(Local::now(), readings) {
+            let mut alert = self.alert_for(&transition.rule, transition.message, transition.value);
+            alert.severity = transition.severity;
+            if transition.cleared {
+                info!(rule = alert.rule, "Recovered: {}", alert.message);
+                self.fresh_recoveries.push(alert);
+            } else {
+                info!(rule = alert.rule, "{}", alert.message);
+                self.annotate(AnnotationKind::Threshold, format!("{}: {}", alert.rule, alert.message));
+                self.fresh_alerts.push(alert);
+            }
+        }
+    }
+
+    fn check_thresholds(&mut self) {
+        let retransmits = self
+            .retransmit_alert
+            .zip(self.tcp_stats.and_then(|s| s.retransmit_percent()))
+            .filter(|(alert, percent)| percent >= alert);
+        if let Some((alert, percent)) = retransmits.filter(|_| !self.retransmits_over) {
+            let message = format!("Retransmits at {:.1}% (alert at {}%)", percent, alert);
+            self.alert("retransmits_high", message.clone(), percent);
+            self.annotate(AnnotationKind::Threshold, message);
+        }
+        if self.retransmits_over && retransmits.is_none() {
+            let percent = self.tcp_stats.and_then(|s| s.retransmit_percent()).unwrap_or(0.0);
+            self.recover("retransmits_high", format!("Retransmits back down to {:.1}%", percent), percent);
+        }
+        self.retransmits_over = retransmits.is_some();
+
+        let at_risk = self.oom.filter(|o| o.at_risk());
+        if let Some(oom) = at_risk.filter(|_| !self.oom_at_risk) {
+            let message = "Next in line for the OOM killer while memory is short";
+            self.alert("oom_at_risk", message.to_string(), oom.score as f64);
+            self.annotate(AnnotationKind::Threshold, message);
+        }
+        let at_risk = at_risk.is_some();
+        if self.oom_at_risk && !at_risk {
+            let score = self.oom.map_or(0, |o| o.score);
+            self.recover("oom_at_risk", "No longer next in line for the OOM killer".to_string(), score as f64);
+        }
+        self.oom_at_risk = at_risk;
+
+        let swapping = self.swap.as_ref().filter(|s| s.is_swapping()).map(|s| (s.latest(), s.growth()));
+        if let Some((swapped, growth)) = swapping.filter(|_| !self.swapping) {
+            let message = format!("Started swapping: {} out, {} since the last sample", format_mb(swapped), format_mb(growth));
+            self.alert("swap_started", message.clone(), swapped as f64);
+            self.annotate(AnnotationKind::Threshold, message);
+        }
+        if self.swapping && swapping.is_none() {
+            let swapped = self.swap.as_ref().map_or(0, |s| s.latest());
+            self.recover("swap_started", format!("Stopped swapping: {} out", format_mb(swapped)), swapped as f64);
+        }
+        self.swapping = swapping.is_some();
+
+        if let Some((then, now)) = self.children.zombies_growing().filter(|_| !self.zombies_piling) {
+            let message = format!(
+                "{} zombie children, up from {} over {} samples: PID {} isn't reaping them (waitpid)",
+                now, then, ZOMBIE_WINDOW, self.pid
+            );
+            self.alert("zombies_growing", message.clone(), now as f64);
+            self.annotate(AnnotationKind::Threshold, message);
+            self.zombies_piling = true;
+        }
+        if self.zombies_piling && self.children.zombies < MIN_ZOMBIES {
+            let zombies = self.children.zombies;
+            self.recover("zombies_growing", format!("Zombie children reaped, {} left", zombies), zombies as f64);
+            self.zombies_piling = false;
+        }
+    }
+
+    /// Looks the process's listening sockets up again, and marks the timeline
+    /// with each one bound or closed since the last look: a debug port opened
+    /// on demand, or listeners leaking. A failed lookup keeps the old ones.
+    fn update_listeners(&mut self) {
+        let Ok(listeners) = self.source.listeners(self.pid) else {
+            return;
+        };
+        let bound = listeners.iter().filter(|l| !self.listeners.contains(l)).map(|l| format!("Started listening on {}", l));
+        let closed = self.listeners.iter().filter(|l| !listeners.contains(l)).map(|l| format!("Stopped listening on {}", l));
+        let changes: Vec<String> = bound.chain(closed).collect();
+        for message in changes {
+            info!("{}", message);
+            self.annotate(AnnotationKind::Listener, message);
+        }
+        self.listeners = listeners;
+    }
+
+    /// Prefers the eBPF counts, which need a second reading before they
+    /// say anything; the thread samples stand in when there are no probes.
+    fn update_syscalls(&mut self) {
+        let reading = match &self.ebpf {
+            Some(ebpf) => ebpf.latest().map(|delta| (delta.calls, true)),
+            None => self.source.task_syscalls(self.pid).map(|counts| (counts, false)),
+        };
+        if let Some((counts, counted)) = reading {
+            self.syscalls.get_or_insert_with(Default::default).add(counts, counted);
+        }
+    }
+
+    async fn sample(&mut self) -> Result<ProcessInfo> {
+        let mut info = if self.pids.len() > 1 {
+            let mut infos = Vec::with_capacity(self.pids.len());
+            for &pid in &self.pids {
+                infos.push(self.source.sample(pid).await?);
+            }
+            aggregate(&infos).expect("pids is non-empty")
+        } else {
+            self.source.sample(self.pid).await?
+        };
+        self.scale_cpu(&mut info);
+        Ok(info)
+    }
+
+    /// Samples `pids` together, in the session's CPU mode.
+    async fn sample_group(&mut self, pids: Vec<u32>) -> Option<Vec<Option<ProcessInfo>>> {
+        let mut samples = self.source.sample_group(pids).await?;
+        for info in samples.iter_mut().flatten() {
+            self.scale_cpu(info);
+        }
+        Some(samples)
+    }
+
+    fn scale_cpu(&self, info: &mut ProcessInfo) {
+        info.cpu_percent = self.cpu_mode.scale(info.cpu_percent, self.cpu_count);
+    }
+
+    /// Samples the followed process's children, and starts following them as
+    /// a pool once at least `MIN_WORKERS` of them share a name.
+    async fn update_pool(&mut self, master: &ProcessInfo) {
+        let children = self.source.children(self.pid);
+        if self.pool.is_none() && children.len() < MIN_WORKERS {
+            return;
+        }
+        let Some(samples) = self.sample_group(children).await else {
+            return;
+        };
+        let alive: Vec<ProcessInfo> = samples.into_iter().flatten().collect();
+        match &mut self.pool {
+            Some(pool) => pool.update(master, alive, Local::now()),
+            None => {
+                if let Some((name, workers)) = worker_group(alive) {
+                    let mut pool = WorkerPool::new(name, HISTORY_LEN);
+                    pool.update(master, workers, Local::now());
+                    self.pool = Some(pool);
+                }
+            }
+        }
+    }
+
+    /// Looks for the proxy's backends now and then, and samples each one's
+    /// listeners (again resolving a port whose processes have all gone).
+    async fn update_upstream(&mut self, proxy: &ProcessInfo) {
+        if self.upstream.is_none() {
+            self.upstream = ProxyKind::from_name(&proxy.name).map(ProxyChain::new);
+        }
+        let Some(chain) = &mut self.upstream else {
+            return;
+        };
+        if self.iteration % UPSTREAM_REFRESH == 1 || chain.backends.is_empty() {
+            let mut listening: Vec<u16> = self.listeners.iter().map(|l| l.port).collect();
+            listening.push(self.port);
+            chain.add_ports(&self.source.upstream_ports(self.pid, chain.kind, &listening));
+        }
+        for backend in &mut chain.backends {
+            if backend.latest.is_none() {
+                backend.pids = self
+                    .source
+                    .resolve_pids(backend.port)
+                    .unwrap_or_default()
+                    .into_iter()
+                    .filter(|pid| !self.pids.contains(pid))
+                    .collect();
+            }
+            let samples = if backend.pids.is_empty() {
+                None
+            } else {
+                self.source.sample_group(backend.pids.clone()).await
+            };
+            let alive: Vec<ProcessInfo> = samples.unwrap_or_default().into_iter().flatten().collect();
+            backend.latest = aggregate(&alive).map(|mut total| {
+                total.cpu_percent = self.cpu_mode.scale(total.cpu_percent, self.cpu_count);
+                total
+            });
+        }
+    }
+
+    /// Waits for a different set of processes to show up after sampling
+    /// failed with `error` (with `--match`, or after the watchdog restarted
+    /// the process), and switches to it; `error` if none does.
+    async fn follow_respawn(&mut self, error: anyhow::Error) -> Result<()> {
+        debug!("sampling PID {} failed ({:#}); waiting for the process to come back", self.pid, error);
+        let started = Instant::now();
+        loop {
+            if let Ok(mut pids) = self.lookup_pids() {
+                if self.pids.len() == 1 {
+                    pids.truncate(1);
+                }
+                if pids != self.pids {
+                    self.attach(pids);
+                    return Ok(());
+                }
+            }
+            if started.elapsed() >= RESPAWN_WAIT {
+                return Err(error);
+            }
+            tokio::time::sleep(RESPAWN_POLL).await;
+        }
+    }
+
+    /// Restarts or stops the process's systemd unit.
+    pub fn unit_action(&mut self, action: UnitAction) -> Result<()> {
+        let unit = self.unit.as_ref().context("The process isn't part of a systemd service")?;
+        let name = unit.name.clone();
+        self.refresh_slow = true;
+        self.source.unit_action(&name, action)
+    }
+
+    /// Looks the port up again after its process was replaced (e.g. by a
+    /// restart). History is kept; per-thread readings start over.
+    pub fn reattach(&mut self) -> Result<()> {
+        let pids = self.lookup_pids()?;
+        self.attach(pids);
+        Ok(())
+    }
+
+    /// The processes being watched, looked up afresh.
+    fn lookup_pids(&self) -> Result<Vec<u32>> {
+        match (&self.target, &self.matcher) {
+            (Some(target), _) => target.pids(),
+            (None, Some(pattern)) => matching_pids(&self.source, self.port, pattern),
+            (None, None) => self.source.resolve_pids(self.port),
+        }
+    }
+
+    fn attach(&mut self, pids: Vec<u32>) {
+        if pids[0] != self.pid {
+            let message = format!("PID {} replaced by {}", self.pid, pids[0]);
+            self.alert("process_restarted", message.clone(), pids[0] as f64);
+            self.annotate(AnnotationKind::Restart, message);
+        }
+        self.pid = pids[0];
+        self.pids = pids;
+        self.counters = None;
+        self.swap = None;
+        self.segments = None;
+        self.refresh_slow = true;
+        self.ebpf = None;
+        self.syscalls = None;
+        self.cores = None;
+        self.accept_queue = None;
+        self.sockets = None;
+        self.posture = None;
+        self.traffic.reset();
+        self.pool = None;
+        // A new process's sockets aren't binds of the old one's.
+        self.listeners = self.source.listeners(self.pid).unwrap_or_default();
+        self.runtime = self.source.runtime(self.pid);
+    }
+
+    /// The latest lines of `log_path`, or else of the process's journal.
+    pub fn log_tail(&mut self, log_path: Option<&Path>) -> Option<LogTail> {
+        let source = match log_path {
+            Some(path) => LogSource::File(path.to_path_buf()),
+            None => LogSource::Journal {
+                unit: self.unit.as_ref().map(|u| u.name.clone()),
+                pid: self.pid,
+            },
+        };
+        self.source.log_tail(&source, LOG_LINES)
+    }
+
+    /// Reads the followed process's open descriptors; only done on request.
+    pub fn open_files(&mut self) -> Option<Vec<OpenFile>> {
+        self.source.open_files(self.pid)
+    }
+
+    /// Dumps the followed process's stacks; only done on request.
+    pub fn stack_dump(&mut self) -> Option<StackDump> {
+        let runtime = self.runtime?;
+        self.source.stack_dump(self.pid, runtime)
+    }
+
+    /// Re-reads the machine-wide snapshot. A failed read keeps the previous one.
+    pub fn refresh_system(&mut self) -> Option<&SystemSnapshot> {
+        if let Ok(snapshot) = self.source.system() {
+            self.system = Some(snapshot);
+        }
+        self.system.as_ref()
+    }
+
+    pub fn source(&self) -> &S {
+        &self.source
+    }
+}
+
+/// The processes on `port` whose command line matches `pattern`, lowest PID
+/// first: its listeners and their children, so one worker of a prefork server
+/// can be picked out even when only the master holds the socket.
+pub fn matching_pids<S: MetricsSource>(source: &S, port: u16, pattern: &Pattern) -> Result<Vec<u32>> {
+    let listeners = source
+        .resolve_pids(port)
+        .with_context(|| format!("No process found listening on port {}", port))?;
+    let mut candidates = listeners.clone();
+    for &pid in &listeners {
+        candidates.extend(source.children(pid));
+    }
+    candidates.sort_unstable();
+    candidates.dedup();
+    let matching: Vec<u32> = candidates
+        .into_iter()
+        .filter(|&pid| source.cmdline(pid).is_some_and(|cmdline| pattern.is_match(&cmdline)))
+        .collect();
+    if matching.is_empty() {
+        return Err(anyhow!("No process on port {} has a command line matching '{}'", port, pattern));
+    }
+    Ok(matching)
+}
@@ -0,0 +1,277 @@
+//! `[watchdog]`: restarting a process that stays unhealthy.
+//!
+//! The process is unhealthy once its health check has failed `failures`
+//! times in a row, or its memory is over `memory_max`. The watchdog then
+//! runs its action, unless it acted less than `cooldown` ago or has already
+//! acted `max_actions` times in the last `window`, and appends a line saying
+//! what it did, and why, to the audit log. A restart loop is stopped by the
+//! limits rather than made worse.
+//!
+//! The action runs on a task of its own, so sampling carries on while a
+//! `systemctl restart` takes its time; one still running after `timeout` is
+//! killed and logged as failed.
+
+use std::collections::VecDeque;
+use std::fmt;
+use std::fs::{self, OpenOptions};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use anyhow::{bail, Context, Result};
+use chrono::{DateTime, Local};
+use tokio::task::JoinHandle;
+
+use crate::config::{WatchdogAction, WatchdogConfig};
+use crate::docker::container_id_from_cgroup;
+use crate::exec::Runner;
+use crate::probe::HttpCheck;
+use crate::restart;
+use crate::systemd::unit_from_cgroup;
+use crate::units::{format_bytes, format_duration};
+
+/// A health check slower than this has failed.
+const HEALTH_TIMEOUT: Duration = Duration::from_secs(2);
+
+/// Why the process was found unhealthy.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Trigger {
+    Unhealthy { failures: u32 },
+    Memory { bytes: u64, max: u64 },
+}
+
+impl fmt::Display for Trigger {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Trigger::Unhealthy { failures } => write!(f, "health check failed {} times in a row", failures),
+            Trigger::Memory { bytes, max } => write!(f, "memory {} is over the {} cap", format_bytes(*bytes), format_bytes(*max)),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Verdict {
+    Fine,
+    /// Act on it now.
+    Act(Trigger),
+    /// It's unhealthy but the limits say wait; given once per episode.
+    Held(Trigger, String),
+}
+
+/// An action under way, logged once it's done.
+struct Running {
+    task: JoinHandle<String>,
+    at: DateTime<Local>,
+    trigger: Trigger,
+    pid: u32,
+    port: u16,
+}
+
+pub struct Watchdog {
+    config: WatchdogConfig,
+    health: Option<HttpCheck>,
+    /// Failed health checks in a row.
+    failures: u32,
+    /// When it acted within the last `window`, oldest first.
+    actions: VecDeque<DateTime<Local>>,
+    /// `Held` was given for the episode under way.
+    held: bool,
+    running: Option<Running>,
+    audit_log: PathBuf,
+}
+
+impl Watchdog {
+    /// `target` is the `host:port` a health check path is requested on;
+    /// without one (`--target-*`) the check must be a URL.
+    pub fn new(config: &WatchdogConfig, target: Option<&str>) -> Result<Self> {
+        if config.health.is_none() && config.memory_max.is_none() {
+            bail!("[watchdog] needs `health` or `memory_max` to act on");
+        }
+        if config.failures == 0 {
+            bail!("[watchdog] failures must be at least 1");
+        }
+        if config.action == WatchdogAction::Command && config.command.is_none() {
+            bail!("[watchdog] action = \"command\" needs `command`");
+        }
+        let health = match (&config.health, target) {
+            (Some(path), _) if path.starts_with("http://") || path.starts_with("https://") => Some(path.as_str()),
+            (Some(_), None) => bail!("[watchdog] health must be a full URL when there is no port"),
+            (Some(path), Some(_)) => Some(path.as_str()),
+            (None, _) => None,
+        };
+        let health = health
+            .map(|path| HttpCheck::new(target.unwrap_or_default(), path, config.expect_status, HEALTH_TIMEOUT, 1))
+            .transpose()?;
+        let audit_log = config.audit_log.clone().or_else(default_audit_path).unwrap_or_else(|| PathBuf::from("crabtop-watchdog.log"));
+        Ok(Self {
+            config: config.clone(),
+            health,
+            failures: 0,
+            actions: VecDeque::new(),
+            held: false,
+            running: None,
+            audit_log,
+        })
+    }
+
+    pub fn audit_log(&self) -> &Path {
+        &self.audit_log
+    }
+
+    /// Runs the health check, when there is one.
+    pub async fn check_health(&mut self) -> Option<bool> {
+        match self.health.as_mut() {
+            Some(check) => Some(check.tick().await),
+            None => None,
+        }
+    }
+
+    /// Takes this sample's health check (if one was made) and memory in.
+    /// Nothing more is done while an action is still running.
+    pub fn judge(&mut self, now: DateTime<Local>, healthy: Option<bool>, memory_bytes: u64) -> Verdict {
+        if self.running.is_some() {
+            return Verdict::Fine;
+        }
+        match healthy {
+            Some(true) => self.failures = 0,
+            Some(false) => self.failures += 1,
+            None => {}
+        }
+        let trigger = match self.config.memory_max {
+            Some(max) if memory_bytes > max => Trigger::Memory { bytes: memory_bytes, max },
+            _ if self.failures >= self.config.failures => Trigger::Unhealthy { failures: self.failures },
+            _ => {
+                self.held = false;
+                return Verdict::Fine;
+            }
+        };
+
+        let window = chrono::Duration::from_std(self.config.window).unwrap_or(chrono::Duration::MAX);
+        while self.actions.front().is_some_and(|&at| now - at >= window) {
+            self.actions.pop_front();
+        }
+        let cooldown = chrono::Duration::from_std(self.config.cooldown).unwrap_or(chrono::Duration::MAX);
+        let reason = match self.actions.back() {
+            Some(&last) if now - last < cooldown => Some(format!("cooling down until {}", (last + cooldown).format("%H:%M:%S"))),
+            _ if self.actions.len() >= self.config.max_actions as usize => Some(format!(
+                "already acted {} times in the last {}",
+                self.actions.len(),
+                format_duration(self.config.window.as_secs())
+            )),
+            _ => None,
+        };
+        match reason {
+            None => Verdict::Act(trigger),
+            Some(_) if self.held => Verdict::Fine,
+            Some(reason) => {
+                self.held = true;
+                Verdict::Held(trigger, reason)
+            }
+        }
+    }
+
+    /// The command the action comes to for `pid`, listening on `port`.
+    pub fn command(&self, pid: u32, port: u16) -> Result<Vec<String>> {
+        let cgroup = || fs::read_to_string(format!("/proc/{}/cgroup", pid)).unwrap_or_default();
+        Ok(match self.config.action {
+            WatchdogAction::Restart => restart::plan(&Runner::Local, port, pid).command(),
+            WatchdogAction::Systemd => {
+                let unit = match &self.config.unit {
+                    Some(unit) => unit.clone(),
+                    None => unit_from_cgroup(&cgroup()).with_context(|| format!("PID {} isn't part of a systemd service; set `unit`", pid))?,
+                };
+                vec!["systemctl".to_string(), "restart".to_string(), unit]
+            }
+            WatchdogAction::Docker => {
+                let container = match &self.config.container {
+                    Some(container) => container.clone(),
+                    None => container_id_from_cgroup(&cgroup()).with_context(|| format!("PID {} isn't in a Docker container; set `container`", pid))?,
+                };
+                vec!["docker".to_string(), "restart".to_string(), container]
+            }
+            WatchdogAction::Command => {
+                let command = self.config.command.as_deref().unwrap_or_default();
+                let command = command.replace("{pid}", &pid.to_string()).replace("{port}", &port.to_string());
+                vec!["sh".to_string(), "-c".to_string(), command]
+            }
+        })
+    }
+
+    /// Starts the action (or, with `dry_run`, doesn't). Returns what was
+    /// done when that's known at once, e.g. with a dry run; otherwise
+    /// `finished` gives it once the command has exited. Needs a tokio runtime.
+    pub fn act(&mut self, now: DateTime<Local>, trigger: &Trigger, pid: u32, port: u16) -> Option<String> {
+        self.actions.push_back(now);
+        self.failures = 0;
+        self.held = false;
+        let command = match self.command(pid, port) {
+            Err(err) => return Some(self.log(now, trigger, pid, port, format!("did nothing: {:#}", err))),
+            Ok(command) if self.config.dry_run => {
+                return Some(self.log(now, trigger, pid, port, format!("would run `{}` (dry run)", command.join(" "))));
+            }
+            Ok(command) => command,
+        };
+        self.running = Some(Running {
+            task: tokio::spawn(run(command, self.config.timeout)),
+            at: now,
+            trigger: trigger.clone(),
+            pid,
+            port,
+        });
+        None
+    }
+
+    /// What the action started by `act` did, once it's over, e.g.
+    /// ``memory 2.1 GB is over the 2.0 GB cap; ran `systemctl restart api.service` ``.
+    pub async fn finished(&mut self) -> Option<String> {
+        if !self.running.as_ref()?.task.is_finished() {
+            return None;
+        }
+        let running = self.running.take()?;
+        let outcome = running.task.await.unwrap_or_else(|err| format!("the action was lost: {}", err));
+        Some(self.log(running.at, &running.trigger, running.pid, running.port, outcome))
+    }
+
+    /// Appends the action to the audit log; returns the trigger and outcome.
+    fn log(&self, now: DateTime<Local>, trigger: &Trigger, pid: u32, port: u16, outcome: String) -> String {
+        let done = format!("{}; {}", trigger, outcome);
+        let line = format!("{} port {} PID {}: {}\n", now.format("%F %T%:z"), port, pid, done);
+        if let Err(err) = self.append_audit(&line) {
+            tracing::warn!("Failed to write the watchdog audit log {}: {:#}", self.audit_log.display(), err);
+        }
+        done
+    }
+
+    fn append_audit(&self, line: &str) -> Result<()> {
+        if let Some(dir) = self.audit_log.parent().filter(|d| !d.as_os_str().is_empty()) {
+            fs::create_dir_all(dir)?;
+        }
+        let mut file = OpenOptions::new().create(true).append(true).open(&self.audit_log)?;
+        file.write_all(line.as_bytes())?;
+        Ok(())
+    }
+}
+
+/// Runs `command`, killing it past `timeout`; says how it went.
+async fn run(command: Vec<String>, timeout: Duration) -> String {
+    let shown = command.join(" ");
+    let output = tokio::process::Command::new(&command[0])
+        .args(&command[1..])
+        .stdin(std::process::Stdio::null())
+        .kill_on_drop(true)
+        .output();
+    match tokio::time::timeout(timeout, output).await {
+        Ok(Ok(out)) if out.status.success() => format!("ran `{}`", shown),
+        Ok(Ok(out)) => format!("ran `{}`, which failed ({}): {}", shown, out.status, String::from_utf8_lossy(&out.stderr).trim()),
+        Ok(Err(err)) => format!("couldn't run `{}`: {}", shown, err),
+        Err(_) => format!("ran `{}`, which failed: still running after {}, killed", shown, format_duration(timeout.as_secs())),
+    }
+}
+
+/// `$XDG_STATE_HOME/crabtop/watchdog.log`, falling back to `~/.local/state`.
+pub fn default_audit_path() -> Option<PathBuf> {
+    let base = std::env::var_os("XDG_STATE_HOME")
+        .map(PathBuf::from)
+        .or_else(|| std::env::var_os("HOME").map(|home| PathBuf::from(home).join(".local").join("state")))?;
+    Some(base.join("crabtop").join("watchdog.log"))
+}
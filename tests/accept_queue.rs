@@ -0,0 +1,66 @@
+//! Accept-queue parsing and how watch sessions track it.
+
+use crabtop::port::{parse_accept_queue, parse_listen_overflows, AcceptQueue};
+use crabtop::watch::WatchSession;
+use crabtop::{ProcessInfo, ScriptedSource};
+
+const SS: &str = "\
+LISTEN 0      4096       127.0.0.53%lo:53         0.0.0.0:*
+LISTEN 12     511            0.0.0.0:8080       0.0.0.0:*
+LISTEN 3      511               [::]:8080          [::]:*
+LISTEN 0      128            0.0.0.0:18080      0.0.0.0:*
+";
+
+const NETSTAT: &str = "\
+TcpExt: SyncookiesSent SyncookiesRecv ListenOverflows ListenDrops
+TcpExt: 0 0 1532 1540
+IpExt: InNoRoutes InTruncatedPkts
+IpExt: 0 0
+";
+
+#[test]
+fn sums_listen_sockets_on_the_port() {
+    let queue = parse_accept_queue(SS, 8080).unwrap();
+    assert_eq!((queue.queued, queue.backlog), (15, 1022));
+    assert!((queue.fill_ratio() - 15.0 / 1022.0).abs() < 1e-9);
+
+    assert_eq!(parse_accept_queue(SS, 18080).unwrap().backlog, 128);
+    assert!(parse_accept_queue(SS, 9090).is_none());
+    assert_eq!(parse_listen_overflows(NETSTAT), Some(1532));
+    assert_eq!(parse_listen_overflows("IpExt: a\nIpExt: 1\n"), None);
+}
+
+#[tokio::test]
+async fn session_charts_depth_and_overflow_deltas() {
+    let queue = |queued, overflows| AcceptQueue {
+        queued,
+        backlog: 128,
+        listen_overflows: Some(overflows),
+    };
+    let mut source = ScriptedSource::new().with_listener(8080, 4242);
+    for _ in 0..3 {
+        source = source.push_sample(ProcessInfo {
+            pid: 4242,
+            ..Default::default()
+        });
+    }
+    let source = source
+        .push_accept_queue(queue(0, 100))
+        .push_accept_queue(queue(128, 140))
+        .push_accept_queue(queue(40, 140));
+
+    let mut session = WatchSession::new(source, 8080).unwrap();
+    session.tick().await.unwrap();
+    assert_eq!(session.accept_queue.as_ref().unwrap().new_overflows, None);
+
+    session.tick().await.unwrap();
+    let history = session.accept_queue.as_ref().unwrap();
+    assert_eq!(history.new_overflows, Some(40));
+    assert_eq!(history.latest.fill_ratio(), 1.0);
+
+    session.tick().await.unwrap();
+    let history = session.accept_queue.as_ref().unwrap();
+    assert_eq!(history.depths, vec![0, 128, 40]);
+    assert_eq!(history.peak(), 128);
+    assert_eq!(history.new_overflows, Some(0));
+}
@@ -0,0 +1,52 @@
+//! Daily AI budgets carried across runs in the state file, and the cache
+//! of replies.
+
+use std::fs;
+
+use crabtop::budget::{Budget, CACHE_ENTRIES};
+use crabtop::config::BudgetConfig;
+
+#[test]
+fn daily_limits_add_up_across_runs() {
+    let path = std::env::temp_dir().join(format!("crabtop-ai-usage-{}.json", std::process::id()));
+    let _ = fs::remove_file(&path);
+    let config = BudgetConfig {
+        max_requests_per_day: Some(2),
+        state_file: Some(path.clone()),
+        ..BudgetConfig::default()
+    };
+
+    let mut first = Budget::new(config.clone());
+    assert_eq!(first.begin("a").unwrap(), None);
+    first.record("a".to_string(), "{}", 10).unwrap();
+
+    let mut second = Budget::new(config);
+    assert_eq!(second.begin("b").unwrap(), None);
+    // Counted as it's sent, before the reply comes back.
+    assert!(second.check().is_err());
+    second.record("b".to_string(), "{}", 10).unwrap();
+    let err = second.begin("c").unwrap_err();
+    assert!(err.to_string().contains("2 of 2 requests today"), "{}", err);
+    assert_eq!(second.today().unwrap().unwrap().tokens, 20);
+
+    fs::remove_file(&path).unwrap();
+}
+
+#[test]
+fn the_cache_keeps_the_most_recently_used_replies() {
+    let mut budget = Budget::new(BudgetConfig::default());
+    for n in 0..CACHE_ENTRIES {
+        let key = format!("prompt {}", n);
+        assert_eq!(budget.begin(&key).unwrap(), None);
+        budget.record(key, &format!("reply {}", n), 1).unwrap();
+    }
+    // Used again, so the second prompt is the one that goes.
+    assert_eq!(budget.begin("prompt 0").unwrap().as_deref(), Some("reply 0"));
+    budget.record("one more".to_string(), "reply", 1).unwrap();
+
+    assert_eq!(budget.begin("prompt 0").unwrap().as_deref(), Some("reply 0"));
+    assert_eq!(budget.begin("prompt 1").unwrap(), None);
+    assert_eq!(budget.begin("one more").unwrap().as_deref(), Some("reply"));
+    assert_eq!(budget.run_usage().requests, CACHE_ENTRIES as u64 + 1);
+    assert_eq!(budget.cache_hits(), 3);
+}
@@ -0,0 +1,254 @@
+//! AI backends against a stub server standing in for the real API.
+
+mod common;
+
+use std::sync::{Arc, Mutex};
+
+use axum::http::HeaderMap;
+use axum::routing::post;
+use axum::{Json, Router};
+use crabtop::ai::{
+    generate_insight, generate_verdict, parse_verdict, DryRun, Message, PromptTemplate, Provider, ProviderKind, ProviderOptions,
+    Severity,
+};
+use crabtop::budget::Usage;
+use crabtop::chat::Chat;
+use crabtop::config::BudgetConfig;
+use crabtop::{ProcessHistory, ProcessInfo};
+use serde_json::{json, Value};
+use common::info;
+
+type Seen = Arc<Mutex<Option<(HeaderMap, Value)>>>;
+
+/// Serves `path`, replying with `reply` and remembering the last request.
+async fn stub(path: &str, reply: Value) -> (String, Seen) {
+    let seen = Seen::default();
+    let recorded = Arc::clone(&seen);
+    let app = Router::new().route(
+        path,
+        post(move |headers: HeaderMap, Json(body): Json<Value>| {
+            *recorded.lock().unwrap() = Some((headers, body));
+            let reply = reply.clone();
+            async move { Json(reply) }
+        }),
+    );
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let url = format!("http://{}", listener.local_addr().unwrap());
+    tokio::spawn(async move { axum::serve(listener, app).await });
+    (url, seen)
+}
+
+async fn ask(provider: &Provider) -> anyhow::Result<String> {
+    let mut history = ProcessHistory::new(60);
+    history.add(&nginx());
+    generate_insight(provider, &PromptTemplate::default(), &nginx(), &history).await
+}
+
+fn nginx() -> ProcessInfo {
+    info("nginx", 42, 3.4, 128_000_000)
+}
+
+#[tokio::test]
+async fn ollama_gets_a_non_streaming_chat_request() {
+    let (url, seen) = stub("/api/chat", json!({"message": {"role": "assistant", "content": "Looks idle."}})).await;
+    let provider = Provider::new(ProviderOptions {
+        url: Some(url),
+        ..ProviderOptions::new(ProviderKind::Ollama)
+    })
+    .unwrap();
+
+    assert_eq!(ask(&provider).await.unwrap(), "Looks idle.");
+
+    let (_, body) = seen.lock().unwrap().take().unwrap();
+    assert_eq!(body["model"], "llama3");
+    assert_eq!(body["stream"], false);
+    assert!(body["messages"][1]["content"].as_str().unwrap().contains("Name: nginx"));
+}
+
+#[tokio::test]
+async fn openai_needs_a_key_and_reads_the_first_choice() {
+    assert!(Provider::new(ProviderOptions::new(ProviderKind::Openai)).is_err());
+
+    let (url, seen) = stub(
+        "/chat/completions",
+        json!({"choices": [{"message": {"role": "assistant", "content": "Steady."}}]}),
+    )
+    .await;
+    let provider = Provider::new(ProviderOptions {
+        url: Some(url),
+        model: Some("gpt-4.1".into()),
+        api_key: Some("sk".into()),
+        ..ProviderOptions::new(ProviderKind::Openai)
+    })
+    .unwrap();
+
+    assert_eq!(ask(&provider).await.unwrap(), "Steady.");
+    let (headers, body) = seen.lock().unwrap().take().unwrap();
+    assert_eq!(body["model"], "gpt-4.1");
+    assert_eq!(headers["authorization"], "Bearer sk");
+}
+
+#[tokio::test]
+async fn anthropic_sends_the_system_prompt_separately() {
+    let (url, seen) = stub(
+        "/messages",
+        json!({"content": [{"type": "text", "text": "Calm."}], "stop_reason": "end_turn"}),
+    )
+    .await;
+    let provider = Provider::new(ProviderOptions {
+        url: Some(url),
+        api_key: Some("sk-ant".into()),
+        ..ProviderOptions::new(ProviderKind::Anthropic)
+    })
+    .unwrap();
+
+    assert_eq!(ask(&provider).await.unwrap(), "Calm.");
+
+    let (headers, body) = seen.lock().unwrap().take().unwrap();
+    assert_eq!(headers["x-api-key"], "sk-ant");
+    assert!(headers.contains_key("anthropic-version"));
+    assert!(body["system"].as_str().unwrap().contains("performance analyst"));
+    assert_eq!(body["messages"].as_array().unwrap().len(), 1);
+    assert_eq!(body["messages"][0]["role"], "user");
+}
+
+#[tokio::test]
+async fn azure_uses_an_api_key_header_and_no_default_host() {
+    assert!(Provider::new(ProviderOptions {
+        api_key: Some("k".into()),
+        ..ProviderOptions::new(ProviderKind::Azure)
+    })
+    .is_err());
+
+    let (url, seen) = stub("/chat/completions", json!({"choices": [{"message": {"role": "assistant", "content": "Fine."}}]})).await;
+    let provider = Provider::new(ProviderOptions {
+        url: Some(url),
+        api_key: Some("azure-key".into()),
+        ..ProviderOptions::new(ProviderKind::Azure)
+    })
+    .unwrap();
+
+    assert_eq!(ask(&provider).await.unwrap(), "Fine.");
+    let (headers, _) = seen.lock().unwrap().take().unwrap();
+    assert_eq!(headers["api-key"], "azure-key");
+    assert!(!headers.contains_key("authorization"));
+}
+
+#[test]
+fn prompt_templates_fill_placeholders_and_reject_typos() {
+    let mut history = ProcessHistory::new(60);
+    for cpu in [10.0, 30.0] {
+        history.add(&ProcessInfo {
+            cpu_percent: cpu,
+            ..nginx()
+        });
+    }
+    let template = PromptTemplate::parse("{name}/{pid}: {cpu}% now, {avg_cpu}% avg over { samples } {{raw}}").unwrap();
+    assert_eq!(template.render(&nginx(), &history), "nginx/42: 3.40% now, 20.00% avg over 2 {raw}");
+
+    let err = PromptTemplate::parse("CPU is {cpu_pct}").unwrap_err();
+    assert!(err.to_string().contains("{cpu_pct}"), "{}", err);
+    assert!(PromptTemplate::parse("oops {name").is_err());
+}
+
+#[tokio::test]
+async fn verdicts_ask_for_json_and_parse_the_reply() {
+    let reply = r#"{"severity": "warning", "summary": "Memory is climbing.", "cause": "A leak", "action": "none"}"#;
+    let (url, seen) = stub("/api/chat", json!({"message": {"role": "assistant", "content": reply}})).await;
+    let provider = Provider::new(ProviderOptions {
+        url: Some(url),
+        ..ProviderOptions::new(ProviderKind::Ollama)
+    })
+    .unwrap();
+
+    let mut history = ProcessHistory::new(60);
+    history.add(&nginx());
+    let verdict = generate_verdict(&provider, &PromptTemplate::default(), &nginx(), &history)
+        .await
+        .unwrap();
+    assert_eq!(verdict.severity, Severity::Warning);
+    assert_eq!(verdict.cause, "A leak");
+    assert_eq!(verdict.action, "", "\"none\" means no action");
+
+    let (_, body) = seen.lock().unwrap().take().unwrap();
+    assert_eq!(body["format"], "json");
+    assert!(body["messages"][0]["content"].as_str().unwrap().contains("\"severity\""));
+}
+
+#[test]
+fn verdict_parsing_tolerates_fences_and_rejects_unknown_severities() {
+    let fenced = "```json\n{\"severity\": \"High\", \"summary\": \"Pegged.\", \"probable_cause\": \"Busy loop\"}\n```";
+    let verdict = parse_verdict(fenced).unwrap();
+    assert_eq!(verdict.severity, Severity::Critical);
+    assert_eq!(verdict.cause, "Busy loop");
+
+    assert!(parse_verdict(r#"{"severity": "meh", "summary": "?"}"#).is_err());
+    assert!(parse_verdict("All good!").is_err());
+}
+
+#[tokio::test]
+async fn chat_keeps_earlier_turns_and_drops_failed_ones() {
+    let (url, seen) = stub("/api/chat", json!({"message": {"role": "assistant", "content": "It is idle."}})).await;
+    let provider = Provider::new(ProviderOptions {
+        url: Some(url),
+        ..ProviderOptions::new(ProviderKind::Ollama)
+    })
+    .unwrap();
+    let mut chat = Chat::new(provider);
+
+    assert_eq!(chat.ask("Is it busy?", "CPU: 0.1%").await.unwrap(), "It is idle.");
+    chat.ask("And now?", "CPU: 0.2%").await.unwrap();
+    assert_eq!(chat.turns(), 2);
+
+    let (_, body) = seen.lock().unwrap().take().unwrap();
+    let messages = body["messages"].as_array().unwrap();
+    let roles: Vec<&str> = messages.iter().map(|m| m["role"].as_str().unwrap()).collect();
+    assert_eq!(roles, ["system", "user", "assistant", "user"]);
+    assert!(messages[3]["content"].as_str().unwrap().starts_with("CPU: 0.2%"));
+
+    chat.reset();
+    assert_eq!(chat.turns(), 0);
+}
+
+#[tokio::test]
+async fn dry_runs_return_the_request_without_sending_it() {
+    let provider = Provider::new(ProviderOptions {
+        api_key: Some("sk-secret".to_string()),
+        dry_run: true,
+        ..ProviderOptions::new(ProviderKind::Openai)
+    })
+    .unwrap();
+
+    let err = ask(&provider).await.unwrap_err();
+    let dry = err.downcast_ref::<DryRun>().expect("a dry run, not a network error");
+    assert!(dry.request.starts_with("POST https://api.openai.com/v1/chat/completions"));
+    assert!(dry.request.contains("Name: nginx"));
+    assert!(!dry.request.contains("sk-secret"), "{}", dry.request);
+}
+
+#[tokio::test]
+async fn repeated_prompts_hit_the_cache_and_budgets_stop_new_ones() {
+    let (url, seen) = stub(
+        "/api/chat",
+        json!({"message": {"role": "assistant", "content": "Idle."}, "prompt_eval_count": 30, "eval_count": 5}),
+    )
+    .await;
+    let provider = Provider::new(ProviderOptions {
+        url: Some(url),
+        budget: BudgetConfig {
+            max_requests_per_run: Some(1),
+            ..BudgetConfig::default()
+        },
+        ..ProviderOptions::new(ProviderKind::Ollama)
+    })
+    .unwrap();
+
+    assert_eq!(ask(&provider).await.unwrap(), "Idle.");
+    assert!(seen.lock().unwrap().take().is_some());
+    assert_eq!(ask(&provider).await.unwrap(), "Idle.");
+    assert!(seen.lock().unwrap().is_none(), "the repeat was served from the cache");
+    assert_eq!(provider.usage(), (Usage { requests: 1, tokens: 35 }, 1));
+
+    let err = provider.complete(&[Message::user("Something new")]).await.unwrap_err();
+    assert!(err.to_string().contains("max_requests_per_run"), "{}", err);
+}
@@ -0,0 +1,62 @@
+//! The EWMA bands behind anomaly marks.
+
+use crabtop::anomaly::{AnomalyDetector, Band, Signal, WARMUP};
+
+/// Steady CPU around 10% (±1) and memory around 200 MB.
+fn steady(detector: &mut AnomalyDetector, samples: u32) {
+    for i in 0..samples {
+        let wobble = if i % 2 == 0 { 1.0 } else { -1.0 };
+        assert!(detector.observe(10.0 + wobble, 200_000_000.0 + wobble * 500_000.0).is_empty(), "sample {}", i);
+    }
+}
+
+#[test]
+fn band_tracks_mean_and_spread() {
+    let mut band = Band::default();
+    assert_eq!(band.observe(10.0, 0.0), 0.0);
+    for _ in 0..200 {
+        band.observe(10.0, 0.0);
+        band.observe(20.0, 0.0);
+    }
+    assert!((band.mean - 15.0).abs() < 0.5, "{:?}", band);
+    assert!((band.spread(0.0) - 5.0).abs() < 0.5, "{:?}", band);
+    assert_eq!(band.spread(8.0), 8.0);
+}
+
+#[test]
+fn flags_a_spike_once_and_only_after_warming_up() {
+    let mut early = AnomalyDetector::new(3.0);
+    steady(&mut early, WARMUP - 1);
+    assert!(early.observe(95.0, 200_000_000.0).is_empty());
+
+    let mut detector = AnomalyDetector::new(3.0);
+    steady(&mut detector, 40);
+    let fresh = detector.observe(60.0, 200_000_000.0);
+    assert_eq!(fresh.len(), 1);
+    assert_eq!(fresh[0].signal, Signal::Cpu);
+    assert!(fresh[0].z > 3.0);
+    assert!(fresh[0].describe().starts_with("CPU 60.0% is "), "{}", fresh[0].describe());
+    assert!(fresh[0].describe().contains("σ above its usual 10.0% ± 2.0"), "{}", fresh[0].describe());
+    // Still out, but already reported.
+    assert!(detector.observe(60.0, 200_000_000.0).is_empty());
+    assert!(detector.is_out(Signal::Cpu));
+
+    // A level that lasts becomes the new usual.
+    for _ in 0..100 {
+        detector.observe(60.0, 200_000_000.0);
+    }
+    assert!(!detector.is_out(Signal::Cpu));
+}
+
+#[test]
+fn small_moves_on_a_flat_line_are_not_anomalies() {
+    let mut detector = AnomalyDetector::new(3.0);
+    for _ in 0..50 {
+        assert!(detector.observe(0.3, 50_000_000.0).is_empty());
+    }
+    // Under the 2-point CPU floor and the 1 MB memory floor.
+    assert!(detector.observe(4.0, 52_500_000.0).is_empty());
+    let fresh = detector.observe(0.3, 20_000_000.0);
+    assert_eq!(fresh.iter().map(|a| a.signal).collect::<Vec<_>>(), vec![Signal::Memory]);
+    assert!(fresh[0].describe().contains("below its usual"), "{}", fresh[0].describe());
+}
@@ -0,0 +1,77 @@
+use std::time::{Duration, Instant};
+
+use crabtop::appmetrics::{parse_exposition, parse_memstats, parse_pprof_index, AppMetrics, GoHeap, MetricKind};
+
+const EXPOSITION: &str = r#"# HELP http_requests_total Requests served.
+# TYPE http_requests_total counter
+http_requests_total{code="200",path="/a{b}"} 1000
+http_requests_total{code="500",path="/"} 20 1715000000000
+# TYPE go_goroutines gauge
+go_goroutines 42
+# TYPE go_gc_duration_seconds summary
+go_gc_duration_seconds{quantile="0.5"} 3.1e-05
+go_gc_duration_seconds_sum 0.0123
+go_gc_duration_seconds_count 157
+# TYPE request_seconds histogram
+request_seconds_bucket{le="0.1"} 90
+request_seconds_count 100
+untyped_total 7
+"#;
+
+#[test]
+fn parses_the_text_format() {
+    let metrics = parse_exposition(EXPOSITION);
+    let requests = metrics["http_requests_total"];
+    assert_eq!((requests.kind, requests.value), (MetricKind::Counter, 1020.0));
+    assert_eq!(metrics["go_goroutines"].kind, MetricKind::Gauge);
+    assert_eq!(metrics["go_gc_duration_seconds_count"].kind, MetricKind::Counter);
+    assert_eq!(metrics["request_seconds_count"].value, 100.0);
+    assert_eq!(metrics["untyped_total"].kind, MetricKind::Counter);
+    assert!(!metrics.contains_key("go_gc_duration_seconds"));
+    assert!(!metrics.contains_key("request_seconds_bucket"));
+}
+
+#[test]
+fn reads_pprof_index_and_memstats() {
+    let index = "<tr><td>42</td><td><a href='goroutine?debug=1'>goroutine</a></td></tr>\n\
+                 <tr><td align=right>7<td><a href=\"threadcreate?debug=1\">threadcreate</a>";
+    let counts = parse_pprof_index(index);
+    assert_eq!(counts.get("goroutine"), Some(&42));
+    assert_eq!(counts.get("threadcreate"), Some(&7));
+
+    let heap = "heap profile: 3: 4096 [10: 8192] @ heap/1048576\n\n# runtime.MemStats\n# Alloc = 123\n\
+                # HeapAlloc = 1000\n# HeapSys = 4000\n# HeapInuse = 2000\n# NumGC = 9\n# DebugGC = false\n";
+    assert_eq!(
+        parse_memstats(heap),
+        Some(GoHeap {
+            alloc_bytes: 1000,
+            inuse_bytes: 2000,
+            sys_bytes: 4000,
+            num_gc: 9
+        })
+    );
+    assert_eq!(parse_memstats("heap profile: 0: 0 [0: 0] @ heap/1"), None);
+}
+
+#[test]
+fn counters_become_rates_between_scrapes() {
+    let mut app = AppMetrics::new(Some("http://127.0.0.1:8080/metrics".to_string()), None, Vec::new(), Duration::from_secs(1)).unwrap();
+    let start = Instant::now();
+    app.apply_exposition(start, EXPOSITION);
+    assert_eq!(app.goroutines, Some(42));
+    assert_eq!(app.values.len(), 1);
+    assert_eq!(app.values[0].value, None);
+
+    app.apply_exposition(start + Duration::from_secs(2), &EXPOSITION.replace("} 1000", "} 1100"));
+    assert_eq!(app.values[0].name, "http_requests_total");
+    assert_eq!(app.values[0].value, Some(50.0));
+
+    // A counter going backwards is a restart, not a negative rate.
+    app.apply_exposition(start + Duration::from_secs(4), EXPOSITION);
+    assert_eq!(app.values[0].value, None);
+
+    let mut picked = AppMetrics::new(None, None, vec!["go_goroutines".to_string(), "missing".to_string()], Duration::from_secs(1)).unwrap();
+    picked.apply_exposition(start, EXPOSITION);
+    assert_eq!(picked.values.len(), 1);
+    assert_eq!(picked.values[0].value, Some(42.0));
+}
@@ -0,0 +1,66 @@
+use chrono::Local;
+
+use crabtop::baseline::{check, parse_tolerance, Baseline, Summary};
+use crabtop::ProcessInfo;
+
+fn run(samples: &[(f32, u64)]) -> Baseline {
+    let infos: Vec<ProcessInfo> = samples
+        .iter()
+        .map(|&(cpu_percent, memory_bytes)| ProcessInfo {
+            name: "api".to_string(),
+            cpu_percent,
+            memory_bytes,
+            ..Default::default()
+        })
+        .collect();
+    Baseline::from_samples(8080, Local::now(), 60.0, &infos).unwrap()
+}
+
+#[test]
+fn summarises_with_nearest_rank_p95() {
+    let values: Vec<f64> = (1..=20).map(f64::from).collect();
+    let summary = Summary::of(&values).unwrap();
+    assert_eq!(summary.avg, 10.5);
+    assert_eq!(summary.p95, 19.0);
+    assert_eq!(summary.max, 20.0);
+    assert!(Summary::of(&[]).is_none());
+}
+
+#[test]
+fn parses_tolerances() {
+    assert_eq!(parse_tolerance("20%"), Ok(20.0));
+    assert_eq!(parse_tolerance("7.5"), Ok(7.5));
+    assert!(parse_tolerance("-5%").is_err());
+    assert!(parse_tolerance("lots").is_err());
+}
+
+#[test]
+fn flags_figures_above_the_tolerance() {
+    let saved = run(&[(40.0, 200_000_000), (44.0, 210_000_000), (42.0, 205_000_000)]);
+    let same = run(&[(41.0, 206_000_000), (45.0, 212_000_000), (43.0, 204_000_000)]);
+    assert!(check(&saved, &same, 20.0).iter().all(|row| row.passed()));
+
+    let hungrier = run(&[(41.0, 300_000_000), (45.0, 310_000_000), (43.0, 305_000_000)]);
+    let failed: Vec<&str> = check(&saved, &hungrier, 20.0)
+        .iter()
+        .filter(|row| !row.passed())
+        .map(|row| row.metric)
+        .collect();
+    assert_eq!(failed, vec!["Memory avg", "Memory p95"]);
+
+    // An idle process gets a point of CPU headroom rather than failing on noise.
+    let idle = run(&[(0.1, 10_000_000), (0.2, 10_000_000)]);
+    let blip = run(&[(0.6, 10_000_000), (0.9, 10_000_000)]);
+    assert!(check(&idle, &blip, 20.0).iter().all(|row| row.passed()));
+}
+
+#[test]
+fn round_trips_through_a_file() {
+    let saved = run(&[(12.0, 50_000_000), (14.0, 52_000_000)]);
+    let path = std::env::temp_dir().join(format!("crabtop-baseline-{}.json", std::process::id()));
+    saved.save(&path).unwrap();
+    assert_eq!(Baseline::load(&path).unwrap(), saved);
+    std::fs::write(&path, "{}").unwrap();
+    assert!(Baseline::load(&path).is_err());
+    std::fs::remove_file(&path).unwrap();
+}
@@ -0,0 +1,97 @@
+use std::fs;
+
+use crabtop::chart::{elapsed_label, write_chart, Chart, Series, PALETTE};
+use crabtop::raster::Bitmap;
+
+fn chart_of(points: Vec<(f64, f64)>) -> String {
+    let series = [Series {
+        label: "CPU <api>".to_string(),
+        color: PALETTE[0],
+        points,
+    }];
+    Chart {
+        title: "CPU",
+        series: &series,
+        x_label: elapsed_label,
+        y_label: |v| format!("{:.0}%", v),
+    }
+    .render(500.0, 200.0)
+}
+
+#[test]
+fn scales_lines_into_the_plot_area_from_zero() {
+    let svg = chart_of(vec![(0.0, 0.0), (60.0, 50.0), (120.0, 100.0)]);
+    // 80px left margin, 400px wide plot; 34px top margin, 120px high plot.
+    assert!(svg.contains("<polyline points=\"80.0,154.0 280.0,94.0 480.0,34.0\""));
+    assert!(svg.contains(">100%</text>"));
+    assert!(svg.contains(">2m</text>"));
+    assert!(svg.contains("CPU &lt;api&gt;"));
+}
+
+#[test]
+fn survives_empty_and_flat_series() {
+    assert!(chart_of(Vec::new()).contains("<polyline points=\"\""));
+    assert!(chart_of(vec![(5.0, 0.0)]).ends_with("</svg>\n"));
+}
+
+#[test]
+fn labels_elapsed_time() {
+    assert_eq!(elapsed_label(45.0), "45s");
+    assert_eq!(elapsed_label(300.0), "5m");
+    assert_eq!(elapsed_label(7200.0), "2h");
+    assert_eq!(elapsed_label(5400.0 + 3600.0), "2h30m");
+}
+
+#[test]
+fn writes_svg_unless_asked_for_png() {
+    let series = [Series {
+        label: "CPU".to_string(),
+        color: PALETTE[1],
+        points: vec![(0.0, 0.0), (60.0, 50.0), (120.0, 100.0)],
+    }];
+    let chart = Chart {
+        title: "CPU",
+        series: &series,
+        x_label: elapsed_label,
+        y_label: |v| format!("{:.0}%", v),
+    };
+    let path = std::env::temp_dir().join(format!("crabtop-chart-{}.svg", std::process::id()));
+    write_chart(&path, &chart, 500, 200).unwrap();
+    assert_eq!(fs::read_to_string(&path).unwrap(), chart.render(500.0, 200.0));
+    fs::remove_file(&path).unwrap();
+
+    let path = path.with_extension("png");
+    write_chart(&path, &chart, 500, 200).unwrap();
+    let png = fs::read(&path).unwrap();
+    assert_eq!(&png[..8], b"\x89PNG\r\n\x1a\n");
+    assert_eq!(&png[12..16], b"IHDR");
+    assert_eq!((&png[16..20], &png[20..24]), (&500u32.to_be_bytes()[..], &200u32.to_be_bytes()[..]));
+    assert!(png.ends_with(b"IEND\xaeB`\x82"));
+    // Mostly white, so it packs down well below the raw 300 KB.
+    assert!(png.len() < 30_000, "{}", png.len());
+    fs::remove_file(&path).unwrap();
+}
+
+#[test]
+fn draws_the_png_to_the_svg_layout() {
+    let series = [Series {
+        label: "CPU".to_string(),
+        color: "#d62728",
+        points: vec![(0.0, 0.0), (120.0, 100.0)],
+    }];
+    let chart = Chart {
+        title: "CPU",
+        series: &series,
+        x_label: elapsed_label,
+        y_label: |v| format!("{:.0}%", v),
+    };
+    let mut bitmap = Bitmap::new(500, 200, "#ffffff");
+    bitmap.line((80.0, 154.0), (480.0, 34.0), 1.5, "#d62728");
+    assert_eq!(bitmap.pixel(280, 94), Some([0xd6, 0x27, 0x28]));
+    assert_eq!(bitmap.pixel(280, 60), Some([0xff, 0xff, 0xff]));
+    assert_eq!(bitmap.pixel(500, 0), None);
+
+    let png = chart.render_png(500, 200);
+    assert_eq!(png, chart.render_png(500, 200));
+    assert_eq!(&png[16..24], &[0, 0, 1, 0xf4, 0, 0, 0, 0xc8]);
+}
@@ -0,0 +1,108 @@
+//! The child-process feed: spawns and exits of the watched processes'
+//! children, with exit codes when they're caught before being reaped.
+
+mod common;
+
+use std::process::Command;
+use std::time::{Duration, Instant};
+
+use crabtop::children::{local_children, ChildEventKind, ChildExit, ChildProcess};
+use crabtop::watch::WatchSession;
+use crabtop::ScriptedSource;
+use common::info;
+
+fn child(pid: u32, command: &str, exit: Option<ChildExit>) -> ChildProcess {
+    ChildProcess {
+        pid,
+        ppid: 100,
+        command: command.to_string(),
+        zombie: exit.is_some(),
+        exit,
+    }
+}
+
+#[tokio::test]
+async fn the_watch_follows_children_from_sample_to_sample() {
+    let source = ScriptedSource::new()
+        .push_sample(info("app", 100, 0.0, 0))
+        .push_sample(info("app", 100, 0.0, 0))
+        .push_sample(info("app", 100, 0.0, 0))
+        .push_sample(info("app", 100, 0.0, 0))
+        .push_children(vec![child(200, "worker", None)])
+        .push_children(vec![child(200, "worker", None), child(201, "sh -c curl -s http://169.254.169.254/", None)])
+        .push_children(vec![child(200, "worker", None), child(201, "sh", Some(ChildExit::Code(7)))])
+        .push_children(vec![child(202, "worker", None)]);
+    let mut session = WatchSession::with_pids(source, 8080, vec![100]).unwrap();
+    for _ in 0..4 {
+        session.tick().await.unwrap();
+    }
+
+    let events: Vec<_> = session.children.events.iter().map(|e| (e.pid, e.kind, e.command.as_str())).collect();
+    // What ran before the watch is no spawn; an exit is reported once, with
+    // the command line read while the child was alive.
+    assert_eq!(
+        events,
+        vec![
+            (201, ChildEventKind::Spawned, "sh -c curl -s http://169.254.169.254/"),
+            (201, ChildEventKind::Exited(Some(ChildExit::Code(7))), "sh -c curl -s http://169.254.169.254/"),
+            (202, ChildEventKind::Spawned, "worker"),
+            (200, ChildEventKind::Exited(None), "worker"),
+        ]
+    );
+    assert_eq!((session.children.spawned, session.children.exited, session.children.running()), (2, 2, 1));
+}
+
+#[test]
+fn an_unreaped_child_shows_its_exit_code() {
+    let me = std::process::id();
+    let mut failing = Command::new("sh").args(["-c", "exit 3"]).spawn().unwrap();
+    let mut killed = Command::new("sleep").arg("30").spawn().unwrap();
+    killed.kill().unwrap();
+
+    let started = Instant::now();
+    let children = loop {
+        let children = local_children(&[me]);
+        let exits: Vec<_> = [failing.id(), killed.id()].iter().map(|&pid| children.iter().find(|c| c.pid == pid).and_then(|c| c.exit)).collect();
+        if exits.iter().all(Option::is_some) || started.elapsed() > Duration::from_secs(5) {
+            break children;
+        }
+        std::thread::sleep(Duration::from_millis(20));
+    };
+    let found = |pid| children.iter().find(|c| c.pid == pid).unwrap();
+    assert_eq!((found(failing.id()).ppid, found(failing.id()).exit), (me, Some(ChildExit::Code(3))));
+    assert_eq!(found(killed.id()).exit, Some(ChildExit::Signal(9)));
+    assert!(found(killed.id()).zombie);
+
+    failing.wait().unwrap();
+    killed.wait().unwrap();
+    assert!(!local_children(&[me]).iter().any(|c| c.pid == failing.id()));
+}
+
+#[tokio::test]
+async fn warns_when_zombies_pile_up_and_recovers_once_reaped() {
+    // One more unreaped child every sample, until the parent catches up.
+    let zombies = [0, 1, 2, 3, 4, 5, 6, 6, 0];
+    let mut source = ScriptedSource::new();
+    for &count in &zombies {
+        let children: Vec<_> = (0..count).map(|i| child(300 + i, "sh", Some(ChildExit::Code(0)))).collect();
+        source = source.push_sample(info("app", 100, 0.0, 0)).push_children(children);
+    }
+    let mut session = WatchSession::with_pids(source, 8080, vec![100]).unwrap();
+    let mut counts = Vec::new();
+    for _ in zombies {
+        session.tick().await.unwrap();
+        counts.push(session.children.zombies_growing().map(|(_, now)| now));
+    }
+    assert_eq!(counts, vec![None, None, None, None, None, Some(5), Some(6), Some(6), None]);
+
+    let alerts: Vec<_> = session.take_fresh_alerts().into_iter().map(|a| (a.rule, a.message)).collect();
+    assert_eq!(
+        alerts,
+        vec![(
+            "zombies_growing".to_string(),
+            "5 zombie children, up from 0 over 5 samples: PID 100 isn't reaping them (waitpid)".to_string()
+        )]
+    );
+    let recoveries: Vec<_> = session.take_fresh_recoveries().into_iter().map(|a| a.message).collect();
+    assert_eq!(recoveries, vec!["Zombie children reaped, 0 left".to_string()]);
+}
@@ -0,0 +1,200 @@
+//! The Datadog and CloudWatch payloads, and CloudWatch's request signing
+//! and credentials.
+
+mod common;
+
+use chrono::{Local, TimeZone};
+use crabtop::export::ExportSample;
+use crabtop::{cloudwatch, config, datadog, ProcessInfo};
+use serde_json::json;
+use common::info;
+
+fn sample(host: Option<&str>) -> ExportSample {
+    ExportSample {
+        timestamp: Local.timestamp_opt(1_700_000_000, 0).unwrap(),
+        host: host.map(str::to_string),
+        pids: vec![42],
+        ..common::sample(ProcessInfo {
+            uptime_secs: 60,
+            ..info("nginx", 0, 12.5, 2_000)
+        })
+    }
+}
+
+#[test]
+fn both_are_configured_as_exporters_with_defaults() {
+    let config = config::parse(
+        "[[exporter]]\ntype = \"datadog\"\ntags = [\"env:staging\"]\n\n[[exporter]]\ntype = \"cloudwatch\"\nregion = \"eu-west-1\"\n",
+    )
+    .unwrap();
+    assert!(matches!(
+        &config.exporters[0],
+        config::ExporterConfig::Datadog { site, api_key_env, tags }
+            if site == "datadoghq.com" && api_key_env == "DD_API_KEY" && tags == &["env:staging"]
+    ));
+    assert!(matches!(
+        &config.exporters[1],
+        config::ExporterConfig::Cloudwatch { region: Some(region), namespace } if region == "eu-west-1" && namespace == "crabtop"
+    ));
+}
+
+#[test]
+fn datadog_series_carry_the_statsd_gauges_and_tags() {
+    let body = datadog::series(&sample(Some("web-1:7070")), &["env:staging".to_string()]);
+    let series = body["series"].as_array().unwrap();
+    assert_eq!(series.len(), 3);
+    assert_eq!(
+        series[0],
+        json!({
+            "metric": "crabtop.cpu_percent",
+            "type": 3,
+            "points": [{ "timestamp": 1_700_000_000, "value": 12.5 }],
+            "tags": ["port:8080", "process:nginx", "host:web-1:7070", "env:staging"],
+        })
+    );
+    assert_eq!(series[1]["metric"], "crabtop.memory_bytes");
+    assert_eq!(series[1]["points"][0]["value"], 2000.0);
+}
+
+#[test]
+fn cloudwatch_puts_a_datum_per_figure_with_dimensions() {
+    let params = cloudwatch::put_metric_data(&sample(None), "crabtop");
+    let get = |key: &str| params.iter().find(|(k, _)| k == key).map(|(_, v)| v.as_str());
+    assert_eq!(get("Action"), Some("PutMetricData"));
+    assert_eq!(get("MetricData.member.1.MetricName"), Some("CpuPercent"));
+    assert_eq!(get("MetricData.member.1.Unit"), Some("Percent"));
+    assert_eq!(get("MetricData.member.2.Value"), Some("2000"));
+    assert_eq!(get("MetricData.member.3.Timestamp"), Some("2023-11-14T22:13:20Z"));
+    assert_eq!(get("MetricData.member.1.Dimensions.member.2.Value"), Some("nginx"));
+    assert_eq!(get("MetricData.member.1.Dimensions.member.3.Name"), None);
+
+    let encoded = cloudwatch::form_encode(&[("Namespace".to_string(), "my app/prod".to_string())]);
+    assert_eq!(encoded, "Namespace=my%20app%2Fprod");
+}
+
+/// AWS's published `get-vanilla` SigV4 test case.
+#[cfg(feature = "cloudwatch")]
+#[test]
+fn signs_the_aws_sigv4_test_vector() {
+    use chrono::Utc;
+
+    let credentials = cloudwatch::Credentials {
+        access_key_id: "AKIDEXAMPLE".to_string(),
+        secret_access_key: "wJalrXUtnFEMI/K7MDENG+bPxRfiCYEXAMPLEKEY".to_string(),
+        session_token: None,
+        expires: None,
+    };
+    let request = cloudwatch::Request {
+        method: "GET",
+        path: "/",
+        query: "",
+        headers: &[("Host", "example.amazonaws.com")],
+        body: b"",
+    };
+    let now = Utc.with_ymd_and_hms(2015, 8, 30, 12, 36, 0).unwrap();
+    let headers = cloudwatch::sign(&credentials, "us-east-1", "service", request, now);
+    assert_eq!(headers[0], ("x-amz-date".to_string(), "20150830T123600Z".to_string()));
+    assert_eq!(
+        headers[1].1,
+        "AWS4-HMAC-SHA256 Credential=AKIDEXAMPLE/20150830/us-east-1/service/aws4_request, \
+         SignedHeaders=host;x-amz-date, \
+         Signature=5fa00fa31553b73ebf1942676e86291e8372ff2a2260956d9b8aae1d763fbf31"
+    );
+}
+
+#[cfg(feature = "cloudwatch")]
+#[test]
+fn credentials_are_looked_for_where_the_sdks_look() {
+    use cloudwatch::CredentialSource;
+
+    let detect = |vars: &[(&str, &str)]| {
+        let vars: Vec<(String, String)> = vars.iter().map(|(k, v)| (k.to_string(), v.to_string())).collect();
+        CredentialSource::from_vars(|name| vars.iter().find(|(k, _)| k == name).map(|(_, v)| v.clone()))
+    };
+    let irsa = [("AWS_ROLE_ARN", "arn:aws:iam::1:role/crabtop"), ("AWS_WEB_IDENTITY_TOKEN_FILE", "/var/run/token")];
+    let ecs = [("AWS_CONTAINER_CREDENTIALS_RELATIVE_URI", "/v2/credentials/abc")];
+    assert_eq!(detect(&[("AWS_ACCESS_KEY_ID", "AKID"), ("AWS_SECRET_ACCESS_KEY", "s"), irsa[0], irsa[1]]).unwrap(), CredentialSource::Environment);
+    assert!(detect(&[("AWS_ACCESS_KEY_ID", "AKID")]).is_err());
+    assert_eq!(
+        detect(&[irsa[0], irsa[1], ecs[0]]).unwrap(),
+        CredentialSource::WebIdentity {
+            role_arn: "arn:aws:iam::1:role/crabtop".to_string(),
+            token_file: "/var/run/token".into(),
+            session_name: "crabtop".to_string(),
+        }
+    );
+    assert_eq!(
+        detect(&ecs).unwrap(),
+        CredentialSource::Container {
+            url: "http://169.254.170.2/v2/credentials/abc".to_string(),
+            token: None,
+            token_file: None,
+        }
+    );
+    assert_eq!(detect(&[]).unwrap(), CredentialSource::Instance);
+    assert!(detect(&[("AWS_EC2_METADATA_DISABLED", "true")]).is_err());
+}
+
+#[cfg(feature = "cloudwatch")]
+#[test]
+fn temporary_credentials_from_json_and_sts() {
+    use chrono::Utc;
+
+    let issued = cloudwatch::Credentials::from_json(
+        r#"{"AccessKeyId":"ASIA1","SecretAccessKey":"secret","Token":"token","Expiration":"2024-03-01T12:00:00Z"}"#,
+    )
+    .unwrap();
+    assert_eq!((issued.access_key_id.as_str(), issued.session_token.as_deref()), ("ASIA1", Some("token")));
+    assert!(!issued.expired_by(Utc.with_ymd_and_hms(2024, 3, 1, 11, 59, 0).unwrap()));
+    assert!(issued.expired_by(Utc.with_ymd_and_hms(2024, 3, 1, 12, 0, 0).unwrap()));
+
+    let assumed = cloudwatch::Credentials::from_sts_reply(
+        "<AssumeRoleWithWebIdentityResponse><AssumeRoleWithWebIdentityResult><Credentials>\
+         <AccessKeyId>ASIA2</AccessKeyId><SecretAccessKey>s2</SecretAccessKey><SessionToken>t2</SessionToken>\
+         <Expiration>2024-03-01T13:00:00Z</Expiration></Credentials></AssumeRoleWithWebIdentityResult></AssumeRoleWithWebIdentityResponse>",
+    )
+    .unwrap();
+    assert_eq!((assumed.access_key_id.as_str(), assumed.secret_access_key.as_str()), ("ASIA2", "s2"));
+    assert_eq!(assumed.expires, Some(Utc.with_ymd_and_hms(2024, 3, 1, 13, 0, 0).unwrap()));
+
+    let refused = cloudwatch::Credentials::from_sts_reply(
+        "<ErrorResponse><Error><Code>InvalidIdentityToken</Code><Message>expired</Message></Error></ErrorResponse>",
+    );
+    assert_eq!(refused.unwrap_err().to_string(), "InvalidIdentityToken: expired");
+}
+
+#[cfg(feature = "cloudwatch")]
+#[tokio::test]
+async fn container_credentials_are_fetched_with_their_token() {
+    use axum::http::HeaderMap;
+    use axum::routing::get;
+
+    let app = axum::Router::new().route(
+        "/creds",
+        get(|headers: HeaderMap| async move {
+            match headers.get("authorization").and_then(|v| v.to_str().ok()) {
+                Some("pod-token") => (axum::http::StatusCode::OK, r#"{"AccessKeyId":"ASIA3","SecretAccessKey":"s3","Token":"t3"}"#),
+                _ => (axum::http::StatusCode::UNAUTHORIZED, ""),
+            }
+        }),
+    );
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let url = format!("http://{}/creds", listener.local_addr().unwrap());
+    tokio::spawn(async move { axum::serve(listener, app).await });
+
+    let client = reqwest::Client::new();
+    let source = cloudwatch::CredentialSource::Container {
+        url: url.clone(),
+        token: Some("pod-token".to_string()),
+        token_file: None,
+    };
+    let credentials = source.fetch(&client, "eu-west-1").await.unwrap();
+    assert_eq!((credentials.access_key_id.as_str(), credentials.expires), ("ASIA3", None));
+
+    let unauthorised = cloudwatch::CredentialSource::Container {
+        url: url.clone(),
+        token: None,
+        token_file: None,
+    };
+    assert_eq!(unauthorised.fetch(&client, "eu-west-1").await.unwrap_err().to_string(), format!("{} answered HTTP 401 Unauthorized", url));
+}
@@ -0,0 +1,41 @@
+//! Fixtures shared by the integration tests; each test file uses some of them.
+
+#![allow(dead_code)]
+
+use std::path::PathBuf;
+
+use chrono::Local;
+use crabtop::export::ExportSample;
+use crabtop::ProcessInfo;
+
+/// An empty directory under the system's temp dir, named for the test file,
+/// `name` and this process, so parallel runs don't collide.
+pub fn scratch(name: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("crabtop-{}-{}-{}", env!("CARGO_CRATE_NAME"), name, std::process::id()));
+    let _ = std::fs::remove_dir_all(&dir);
+    std::fs::create_dir_all(&dir).unwrap();
+    dir
+}
+
+pub fn info(name: &str, pid: u32, cpu_percent: f32, memory_bytes: u64) -> ProcessInfo {
+    ProcessInfo {
+        name: name.to_string(),
+        pid,
+        cpu_percent,
+        memory_bytes,
+        ..Default::default()
+    }
+}
+
+/// `process` sampled now on port 8080.
+pub fn sample(process: ProcessInfo) -> ExportSample {
+    ExportSample {
+        timestamp: Local::now(),
+        host: None,
+        port: 8080,
+        pids: vec![process.pid],
+        process,
+        connections: None,
+        annotations: Vec::new(),
+    }
+}
@@ -0,0 +1,24 @@
+//! The config given whole in `$CRABTOP_CONFIG_TOML`, for containers with no
+//! file to mount. One test, since it sets the variable for the process.
+
+use crabtop::ai::ProviderKind;
+use crabtop::config::{self, INLINE_ENV};
+
+#[test]
+fn the_config_can_come_from_the_environment() {
+    std::env::set_var(INLINE_ENV, "[ai]\nprovider = \"ollama\"\n\n[[service]]\nname = \"web\"\nports = [8080]\n");
+    let config = config::load(None).unwrap();
+    assert_eq!(config.ai.provider, Some(ProviderKind::Ollama));
+    assert_eq!(config.services[0].ports, vec![8080]);
+
+    // A file named outright still wins.
+    let path = std::env::temp_dir().join(format!("crabtop-config-env-{}.toml", std::process::id()));
+    std::fs::write(&path, "[ai]\nprovider = \"anthropic\"\n").unwrap();
+    assert_eq!(config::load(Some(&path)).unwrap().ai.provider, Some(ProviderKind::Anthropic));
+    std::fs::remove_file(&path).unwrap();
+
+    std::env::set_var(INLINE_ENV, "[ai\n");
+    let error = config::load(None).unwrap_err();
+    assert_eq!(error.to_string(), "Invalid config in CRABTOP_CONFIG_TOML");
+    std::env::remove_var(INLINE_ENV);
+}
@@ -0,0 +1,81 @@
+//! Commands sent to a watch session over its control socket.
+
+use std::path::PathBuf;
+
+use crabtop::annotation::AnnotationKind;
+use crabtop::control::{parse_command, ControlCommand};
+
+#[test]
+fn deploys_and_markers_carry_their_text() {
+    assert_eq!(
+        parse_command("deploy   v1.2 rolled out\n"),
+        Ok(ControlCommand::Annotate {
+            kind: AnnotationKind::Deploy,
+            text: "v1.2 rolled out".to_string(),
+        })
+    );
+    assert_eq!(
+        parse_command("annotate cache flushed"),
+        Ok(ControlCommand::Annotate {
+            kind: AnnotationKind::Marker,
+            text: "cache flushed".to_string(),
+        })
+    );
+}
+
+#[test]
+fn unknown_or_empty_commands_are_refused() {
+    assert_eq!(parse_command("  "), Err("empty command".to_string()));
+    assert_eq!(parse_command("deploy"), Err("`deploy` needs some text".to_string()));
+    assert!(parse_command("reboot now").unwrap_err().contains("unknown command `reboot`"));
+    assert_eq!(parse_command("interval 500ms"), Err("the interval must be at least 1s".to_string()));
+    assert_eq!(parse_command("stats now"), Err("`stats` takes no arguments".to_string()));
+}
+
+#[test]
+fn interval_snapshot_and_stats() {
+    assert_eq!(parse_command("interval 5"), Ok(ControlCommand::Interval(5)));
+    assert_eq!(parse_command("interval 2m"), Ok(ControlCommand::Interval(120)));
+    assert_eq!(parse_command("snapshot"), Ok(ControlCommand::Snapshot(None)));
+    assert_eq!(
+        parse_command("snapshot /tmp/before-deploy.txt"),
+        Ok(ControlCommand::Snapshot(Some(PathBuf::from("/tmp/before-deploy.txt"))))
+    );
+    assert_eq!(parse_command("stats"), Ok(ControlCommand::Stats));
+    assert_eq!(parse_command("stream"), Ok(ControlCommand::Stream));
+    assert_eq!(parse_command("stop"), Ok(ControlCommand::Stop));
+}
+
+#[cfg(unix)]
+#[tokio::test]
+async fn a_client_gets_the_sessions_answer_back() {
+    use crabtop::control::{send, ControlSocket};
+
+    let path = std::env::temp_dir().join(format!("crabtop-test-{}.sock", std::process::id()));
+    let mut socket = ControlSocket::bind(&path).unwrap();
+    assert!(ControlSocket::bind(&path).is_err(), "a live socket must not be taken over");
+
+    let client = {
+        let path = path.clone();
+        tokio::task::spawn_blocking(move || (send(&path, "stats"), send(&path, "deploy"), send(&path, "interval 3")))
+    };
+    for _ in 0..2 {
+        let request = loop {
+            match socket.try_recv() {
+                Some(request) => break request,
+                None => tokio::time::sleep(std::time::Duration::from_millis(10)).await,
+            }
+        };
+        match request.command {
+            ControlCommand::Stats => request.answer(r#"{"cpu_percent":12.5}"#),
+            _ => request.fail("not now"),
+        }
+    }
+    let (stats, deploy, interval) = client.await.unwrap();
+    assert_eq!(stats.unwrap(), r#"{"cpu_percent":12.5}"#);
+    assert_eq!(deploy.unwrap_err().to_string(), "`deploy` needs some text");
+    assert_eq!(interval.unwrap_err().to_string(), "not now");
+
+    drop(socket);
+    assert!(!path.exists());
+}
@@ -0,0 +1,62 @@
+use crabtop::cores::{parse_core_times, CoreTimes, ThreadTime};
+use crabtop::watch::WatchSession;
+use crabtop::{ProcessInfo, ScriptedSource};
+
+// A task stat line with `utime`/`stime` and the processor it last ran on.
+fn task(tid: u32, comm: &str, utime: u64, stime: u64, cpu: u32) -> String {
+    format!(
+        "{tid} ({comm}) S 1 {tid} {tid} 0 -1 4194560 900 0 3 0 {utime} {stime} 0 0 20 0 4 0 5120 \
+         1048576 256 18446744073709551615 1 1 0 0 0 0 0 4096 17 0 0 0 17 {cpu} 0 0 0 0 0"
+    )
+}
+
+#[test]
+fn parses_cores_and_threads_skipping_the_aggregate_line() {
+    let text = format!(
+        "cpu  500 0 300 9000 0 0 0 0 0 0\ncpu0 200 0 100 4000 0 0 0 0 0 0\ncpu1 300 0 200 5000 0 0 0 0 0 0\n{}\n{}\n",
+        task(700, "worker (main)", 40, 10, 1),
+        task(701, "io) thread", 5, 5, 0),
+    );
+    let times = parse_core_times(&text).unwrap();
+    assert_eq!(times.core_ticks, vec![4300, 5500]);
+    assert_eq!(
+        times.threads,
+        vec![
+            ThreadTime { tid: 700, cpu: 1, ticks: 50 },
+            ThreadTime { tid: 701, cpu: 0, ticks: 10 },
+        ]
+    );
+    assert!(parse_core_times("cpu0 1 2 3 4\n").is_none());
+}
+
+#[tokio::test]
+async fn charges_thread_time_to_the_core_it_ran_on() {
+    let reading = |core_ticks: Vec<u64>, threads: &[(u32, u32, u64)]| CoreTimes {
+        core_ticks,
+        threads: threads.iter().map(|&(tid, cpu, ticks)| ThreadTime { tid, cpu, ticks }).collect(),
+    };
+    let sample = ProcessInfo {
+        pid: 4242,
+        name: "api".to_string(),
+        cpu_percent: 100.0,
+        memory_bytes: 1_000_000,
+        ..Default::default()
+    };
+    let source = ScriptedSource::new()
+        .with_listener(8080, 4242)
+        .push_sample(sample.clone())
+        .push_sample(sample)
+        .push_core_times(reading(vec![1_000, 1_000, 1_000], &[(1, 2, 500), (2, 0, 10)]))
+        // 100 ticks per core later: thread 1 kept cpu2 busy, a new thread started on cpu0.
+        .push_core_times(reading(vec![1_100, 1_100, 1_100], &[(1, 2, 595), (2, 0, 12), (3, 0, 8)]));
+
+    let mut session = WatchSession::new(source, 8080).unwrap();
+    session.tick().await.unwrap();
+    assert!(session.cores.as_ref().unwrap().percent.is_empty());
+
+    session.tick().await.unwrap();
+    let cores = session.cores.as_ref().unwrap();
+    assert_eq!(cores.percent, vec![10.0, 0.0, 95.0]);
+    assert_eq!(cores.threads, vec![2, 0, 1]);
+    assert_eq!(cores.busiest(), Some((2, 95.0)));
+}
@@ -0,0 +1,88 @@
+//! Finding a daemon through its state file and following its samples.
+
+#![cfg(unix)]
+
+mod common;
+
+use std::time::Duration;
+
+use chrono::Local;
+use crabtop::control::ControlSocket;
+use crabtop::daemon::{DaemonSource, DaemonState};
+use crabtop::export::ExportSample;
+use crabtop::MetricsSource;
+use common::info;
+
+fn sample(cpu_percent: f32) -> ExportSample {
+    ExportSample {
+        connections: Some(3),
+        ..common::sample(info("node", 4242, cpu_percent, 0))
+    }
+}
+
+#[tokio::test]
+async fn state_files_are_found_by_port_and_streams_are_followed() {
+    // The only test in this binary, so nothing else reads the variable.
+    let dir = std::env::temp_dir().join(format!("crabtop-daemon-test-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    std::env::set_var("XDG_RUNTIME_DIR", &dir);
+
+    let state = DaemonState {
+        pid: std::process::id(),
+        port: 8080,
+        interval_secs: 1,
+        started: Local::now(),
+        start_ticks: crabtop::daemon::start_ticks(std::process::id()),
+        socket: dir.join("crabtop-8080.sock"),
+        log: DaemonState::log_path(8080),
+    };
+    state.save().unwrap();
+    assert_eq!(DaemonState::load(8080).unwrap(), Some(state.clone()));
+    assert_eq!(DaemonState::load(8081).unwrap(), None);
+    assert_eq!(DaemonState::all(), vec![state.clone()]);
+    // Elsewhere the daemon is known by its command line, which a test's isn't.
+    if cfg!(target_os = "linux") {
+        assert!(state.is_running());
+        // The same PID, but a process that started at another time.
+        let reused = DaemonState {
+            start_ticks: state.start_ticks.map(|ticks| ticks + 1),
+            ..state.clone()
+        };
+        assert!(!reused.is_running());
+    }
+
+    let socket = ControlSocket::bind(&state.socket).unwrap();
+    let connecting = tokio::spawn({
+        let path = state.socket.clone();
+        async move { DaemonSource::connect(&path).await }
+    });
+    // Nothing is sent until a client is streaming, so keep publishing until it is.
+    let mut source = loop {
+        socket.publish(&sample(10.0));
+        tokio::time::sleep(Duration::from_millis(10)).await;
+        if connecting.is_finished() {
+            break connecting.await.unwrap().unwrap();
+        }
+    };
+    assert_eq!(source.resolve_pids(8080).unwrap(), vec![4242]);
+    assert!(source.resolve_pids(9090).is_err());
+    assert_eq!(source.sample(4242).await.unwrap().cpu_percent, 10.0);
+
+    // Skip any 10% samples still on their way from the loop above.
+    let next = tokio::spawn(async move {
+        loop {
+            let cpu = source.sample(4242).await.map(|info| info.cpu_percent)?;
+            if cpu != 10.0 {
+                return anyhow::Ok(cpu);
+            }
+        }
+    });
+    tokio::time::sleep(Duration::from_millis(50)).await;
+    socket.publish(&sample(55.0));
+    assert_eq!(next.await.unwrap().unwrap(), 55.0);
+
+    DaemonState::remove(8080);
+    assert!(DaemonState::all().is_empty());
+    drop(socket);
+    std::fs::remove_dir_all(&dir).unwrap();
+}
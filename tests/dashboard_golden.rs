@@ -0,0 +1,1187 @@
+//! Golden-file tests for the watch dashboard.
+//!
+//! Frames are rendered into an in-memory buffer, stripped of colour escapes and
+//! compared against `tests/golden/*.txt`. Run with `UPDATE_GOLDEN=1` to rewrite
+//! the golden files after an intentional layout change.
+
+mod common;
+
+use std::fs;
+use std::path::PathBuf;
+use std::time::{Duration, Instant};
+
+use chrono::{Local, NaiveDate, TimeZone, Utc};
+use crabtop::ai::{Severity, Verdict};
+use crabtop::annotation::{Annotation, AnnotationKind};
+use crabtop::appmetrics::AppMetrics;
+use crabtop::cgroup::{CgroupStats, CpuStat};
+use crabtop::children::{ChildExit, ChildFeed, ChildProcess};
+use crabtop::cores::{CoreTimes, ThreadTime};
+use crabtop::ebpf::EbpfStats;
+use crabtop::syscalls::SyscallCounts;
+use crabtop::files::{FileListing, OpenFile};
+use crabtop::fingerprint::classify_reply;
+use crabtop::geoip::GeoInfo;
+use crabtop::logging::{Level, LogLine};
+use crabtop::logs::LogTail;
+use crabtop::oom::{MemoryPressure, OomStatus};
+use crabtop::peers::Peer;
+use crabtop::pool::WorkerPool;
+use crabtop::port::{AcceptQueue, PortConnection, TcpStats};
+use crabtop::posture::Posture;
+use crabtop::power::PowerMeter;
+use crabtop::process::{CpuMode, ProcessCounters};
+use crabtop::watch::{ChurnHistory, Tab, WatchSession};
+use crabtop::probe::{HttpCheck, Probe};
+use crabtop::runtime::{Runtime, StackDump};
+use crabtop::segments::{MemorySegments, SegmentHistory};
+use crabtop::socket::{ListenerSockets, SocketOptions};
+use crabtop::systemd::UnitStatus;
+use crabtop::tls::{Certificate, TlsCheck, TlsInfo};
+use crabtop::upstream::{ProxyChain, ProxyKind};
+use crabtop::dashboard::{
+    relative_change, render_compare, render_dashboard, render_grid, render_top, sort_top_rows, CompareSide, Frame, GridCell, TopRow, TopSort,
+};
+use crabtop::history::ChartView;
+use crabtop::{Listener, ProcessHistory, ProcessInfo, ScriptedSource, SystemSnapshot};
+
+fn info(cpu_percent: f32, memory_mb: f64) -> ProcessInfo {
+    ProcessInfo {
+        user: Some("www-data".to_string()),
+        cmdline: vec![
+            "node".to_string(),
+            "--max-old-space-size=4096".to_string(),
+            "/srv/app/dist/server.js".to_string(),
+            "--port".to_string(),
+            "8888".to_string(),
+        ],
+        exe: Some("/usr/bin/node".into()),
+        cwd: Some("/srv/app".into()),
+        start_time: 1_769_590_000,
+        uptime_secs: 11_525,
+        ..common::info("node", 12345, cpu_percent, (memory_mb * 1_000_000.0) as u64)
+    }
+}
+
+fn history(samples: &[(f32, f64)]) -> ProcessHistory {
+    let mut history = ProcessHistory::new(60);
+    for &(cpu, mem) in samples {
+        history.add(&info(cpu, mem));
+    }
+    history
+}
+
+fn listeners() -> &'static [Listener] {
+    Box::leak(Box::new([
+        Listener {
+            address: "0.0.0.0".to_string(),
+            port: 8888,
+        },
+        Listener {
+            address: "127.0.0.1".to_string(),
+            port: 9464,
+        },
+    ]))
+}
+
+/// Drops CSI escape sequences (`ESC [ ... final`) so goldens only capture layout.
+fn strip_ansi(raw: &str) -> String {
+    let mut out = String::with_capacity(raw.len());
+    let mut chars = raw.chars().peekable();
+    while let Some(c) = chars.next() {
+        if c == '\u{1b}' && chars.peek() == Some(&'[') {
+            chars.next();
+            for c in chars.by_ref() {
+                if ('@'..='~').contains(&c) {
+                    break;
+                }
+            }
+        } else {
+            out.push(c);
+        }
+    }
+    out
+}
+
+/// A frame with no optional panels; tests switch panels on with struct update syntax.
+fn frame<'a>(current: &'a ProcessInfo, history: &'a ProcessHistory, width: u16) -> Frame<'a> {
+    Frame {
+        info: current,
+        history,
+        port: 8888,
+        iteration: history.cpu_history.len() as u64,
+        timestamp: NaiveDate::from_ymd_opt(2026, 1, 28)
+            .unwrap()
+            .and_hms_opt(10, 30, 45)
+            .unwrap(),
+        width,
+        cpu_mode: CpuMode::Process,
+        cpu_count: 0,
+        tab: None,
+        cpu_histogram: false,
+        chart: ChartView::default(),
+        overlay: false,
+        annotations: &[],
+        pids: &[],
+        container: None,
+        listeners: listeners(),
+        protocol: None,
+        cgroup: None,
+        throttled_ratio: None,
+        accept_queue: None,
+        oom: None,
+        swap: None,
+        segments: None,
+        own: None,
+        counters: None,
+        ebpf: None,
+        syscalls: None,
+        cores: None,
+        pool: None,
+        children: None,
+        upstream: None,
+        tcp_stats: None,
+        sockets: None,
+        posture: None,
+        churn: None,
+        peers: None,
+        retransmit_alert: 2.0,
+        probe: None,
+        http_check: None,
+        tls: None,
+        power: None,
+        app: None,
+        unit: None,
+        unit_prompt: None,
+        logs: None,
+        crabtop_log: None,
+        open_files: None,
+        runtime: None,
+        stack_dump: None,
+        system: None,
+        verdict: None,
+        insight: None,
+        ai_enabled: false,
+    }
+}
+
+fn render_frame(frame: &Frame) -> String {
+    let mut buf = Vec::new();
+    render_dashboard(&mut buf, frame).unwrap();
+    strip_ansi(&String::from_utf8(buf).unwrap())
+}
+
+fn render(current: &ProcessInfo, history: &ProcessHistory, width: u16) -> String {
+    render_frame(&frame(current, history, width))
+}
+
+fn assert_golden(name: &str, actual: &str) {
+    let path = PathBuf::from(env!("CARGO_MANIFEST_DIR"))
+        .join("tests/golden")
+        .join(format!("{}.txt", name));
+
+    if std::env::var_os("UPDATE_GOLDEN").is_some() {
+        fs::create_dir_all(path.parent().unwrap()).unwrap();
+        fs::write(&path, actual).unwrap();
+        return;
+    }
+
+    let expected = fs::read_to_string(&path)
+        .unwrap_or_else(|_| panic!("missing golden file {}; run with UPDATE_GOLDEN=1", path.display()));
+    assert_eq!(actual, expected, "dashboard output differs from {}", path.display());
+}
+
+#[test]
+fn steady_process_at_80_columns() {
+    let samples = [(5.1, 41.0), (5.3, 41.5), (4.9, 42.0), (5.3, 42.1)];
+    let history = history(&samples);
+    assert_golden("steady_80", &render(&info(5.3, 42.1), &history, 80));
+}
+
+#[test]
+fn cpu_histogram_counts_the_whole_session_not_just_the_window() {
+    // Idle most of the time with bursts to 70-80%: 90 samples, more than the 60 kept.
+    let samples: Vec<(f32, f64)> = (0..90)
+        .map(|i| (if i % 6 == 0 { 72.0 + (i % 4) as f32 } else { 3.0 + (i % 3) as f32 * 6.0 }, 41.5))
+        .collect();
+    let history = history(&samples);
+    let current = info(4.0, 41.5);
+    let frame = Frame {
+        cpu_histogram: true,
+        ..frame(&current, &history, 80)
+    };
+    assert_golden("cpu_histogram_80", &render_frame(&frame));
+}
+
+#[test]
+fn history_chart_zoomed_out_and_scrolled_back() {
+    // Ten minutes at a second a sample: a burst 4-5 minutes in, then a slow memory climb.
+    let samples: Vec<(f32, f64)> = (0..600)
+        .map(|i| (if (240..300).contains(&i) { 85.0 } else { 6.0 }, 40.0 + i as f64 / 10.0))
+        .collect();
+    let mut history = ProcessHistory::new(60);
+    history.keep_session(3600);
+    for &(cpu, mem) in &samples {
+        history.add(&info(cpu, mem));
+    }
+    let current = info(6.0, 99.9);
+    let frame = Frame {
+        chart: ChartView { zoom: 5, offset: 120, cursor: None },
+        ..frame(&current, &history, 80)
+    };
+    assert_golden("chart_scrolled_80", &render_frame(&frame));
+}
+
+#[test]
+fn cursor_reads_out_a_zoomed_out_column() {
+    // A minute and a half of samples with a burst at 10:00:40, five to a column.
+    let start = Local.with_ymd_and_hms(2026, 1, 28, 10, 0, 0).unwrap();
+    let mut history = ProcessHistory::new(60);
+    history.keep_session(3600);
+    for i in 0..90 {
+        let cpu = if (40..43).contains(&i) { 92.5 } else { 4.0 };
+        history.add_at(&info(cpu, 40.0 + i as f64 / 10.0), start + chrono::Duration::seconds(i));
+    }
+    let current = info(4.0, 48.9);
+    let frame = Frame {
+        chart: ChartView {
+            zoom: 5,
+            offset: 0,
+            cursor: Some(8),
+        },
+        ..frame(&current, &history, 80)
+    };
+    assert_golden("chart_cursor_80", &render_frame(&frame));
+}
+
+#[test]
+fn overlay_chart_lines_up_a_cpu_spike_with_a_memory_jump() {
+    // Memory steps up 20 MB in the same samples CPU spikes, then stays there.
+    let samples: Vec<(f32, f64)> = (0..40)
+        .map(|i| match i {
+            0..=19 => (8.0, 200.0),
+            20..=24 => (90.0, 210.0 + (i - 20) as f64 * 2.5),
+            _ => (12.0, 220.0),
+        })
+        .collect();
+    let history = history(&samples);
+    let current = info(12.0, 220.0);
+    let frame = Frame {
+        overlay: true,
+        ..frame(&current, &history, 80)
+    };
+    assert_golden("overlay_80", &render_frame(&frame));
+}
+
+#[test]
+fn events_are_marked_under_the_cpu_history() {
+    // A restart at sample 10, a deploy at 25 and a CPU breach just after it.
+    let samples: Vec<(f32, f64)> = (0..40).map(|i| (if i > 25 { 70.0 } else { 10.0 }, 150.0)).collect();
+    let history = history(&samples);
+    let at = |sample: u64, kind, text: &str| Annotation {
+        timestamp: Local.with_ymd_and_hms(2024, 3, 1, 14, 2, 0).unwrap() + chrono::Duration::seconds(sample as i64),
+        kind,
+        text: text.to_string(),
+        sample,
+    };
+    let annotations = [
+        at(10, AnnotationKind::Restart, "PID 12001 replaced by 12345"),
+        at(25, AnnotationKind::Deploy, "v1.2 rolled out"),
+        at(27, AnnotationKind::Threshold, "Retransmits at 4.2% (alert at 2%)"),
+    ];
+    let current = info(70.0, 150.0);
+    let frame = Frame {
+        annotations: &annotations,
+        overlay: true,
+        ..frame(&current, &history, 80)
+    };
+    assert_golden("annotations_80", &render_frame(&frame));
+}
+
+#[test]
+fn narrow_terminal_falls_back_to_minimum_width() {
+    let samples = [(12.0, 120.0), (18.0, 130.0)];
+    let history = history(&samples);
+    assert_golden("narrow_40", &render(&info(18.0, 130.0), &history, 40));
+}
+
+#[test]
+fn hot_process_at_120_columns() {
+    let samples: Vec<(f32, f64)> = (0..60)
+        .map(|i| (40.0 + i as f32, 600.0 + i as f64 * 10.0))
+        .collect();
+    let history = history(&samples);
+    assert_golden("hot_120", &render(&info(99.0, 1190.0), &history, 120));
+}
+
+#[test]
+fn system_panel_when_toggled_on() {
+    let samples = [(5.1, 41.0), (5.3, 41.5)];
+    let history = history(&samples);
+    let system = SystemSnapshot {
+        load_average: (3.50, 2.25, 1.00),
+        total_memory_bytes: 16_000_000_000,
+        available_memory_bytes: 4_000_000_000,
+        total_swap_bytes: 2_000_000_000,
+        used_swap_bytes: 250_000_000,
+        cpu_count: 4,
+    };
+    let current = info(5.3, 41.5);
+    let frame = Frame {
+        system: Some(&system),
+        ..frame(&current, &history, 80)
+    };
+    assert_golden("system_80", &render_frame(&frame));
+}
+
+#[test]
+fn ai_verdict_panel_wraps_to_the_terminal() {
+    let samples = [(5.1, 41.0), (5.3, 41.5)];
+    let history = history(&samples);
+    let current = info(5.3, 41.5);
+    let verdict = Verdict {
+        severity: Severity::Warning,
+        summary: "node is idling at about 5% CPU with memory creeping up half a megabyte over the window.".to_string(),
+        cause: "A cache or listener list that grows with every request and is never trimmed.".to_string(),
+        action: String::new(),
+    };
+    let frame = Frame {
+        verdict: Some(&verdict),
+        insight: Some("Updated 10:30:40"),
+        ai_enabled: true,
+        ..frame(&current, &history, 80)
+    };
+    assert_golden("insight_80", &render_frame(&frame));
+}
+
+#[tokio::test]
+async fn probe_panels_highlight_failures() {
+    let samples = [(5.1, 41.0), (5.3, 41.5), (5.2, 41.5)];
+    let history = history(&samples);
+    let current = info(5.2, 41.5);
+    let mut probe = Probe::resolve("127.0.0.1:8888", Duration::from_secs(1), 60).await.unwrap();
+    for latency in [Some(0.21), Some(0.35), None, Some(12.5)] {
+        probe.history.add(latency);
+    }
+    let mut check = HttpCheck::new("127.0.0.1:8888", "/healthz", 200, Duration::from_secs(1), 60).unwrap();
+    for (status, latency) in [(200, Some(1.5)), (200, Some(2.0)), (503, None)] {
+        *check.statuses.entry(status).or_default() += 1;
+        check.history.add(latency);
+        check.last = Some(Ok(status));
+    }
+    let frame = Frame {
+        probe: Some(&probe),
+        http_check: Some(&check),
+        ..frame(&current, &history, 80)
+    };
+    assert_golden("probe_80", &render_frame(&frame));
+}
+
+#[test]
+fn tls_panel_warns_about_a_certificate_close_to_expiry() {
+    let samples = [(5.1, 41.0), (5.3, 41.5)];
+    let history = history(&samples);
+    let current = info(5.3, 41.5);
+    let mut check = TlsCheck::new("127.0.0.1:8888".to_string(), None, Duration::from_secs(1));
+    check.last = Some(Ok(TlsInfo {
+        target: "127.0.0.1:8888".to_string(),
+        server_name: "127.0.0.1".to_string(),
+        protocol: "TLS 1.3".to_string(),
+        cipher: "TLS13_AES_256_GCM_SHA384".to_string(),
+        alpn: Some("h2".to_string()),
+        handshake_ms: 1.8,
+        verified: Err("invalid peer certificate: UnknownIssuer".to_string()),
+        certificate: Some(Certificate {
+            subject: "CN=app.example.com".to_string(),
+            issuer: "CN=R11, O=Let's Encrypt, C=US".to_string(),
+            san: vec!["app.example.com".to_string(), "www.app.example.com".to_string()],
+            not_before: Utc.with_ymd_and_hms(2025, 11, 7, 0, 0, 0).unwrap(),
+            not_after: Utc.with_ymd_and_hms(2026, 2, 5, 0, 0, 0).unwrap(),
+        }),
+    }));
+    let protocol = classify_reply(&[0x15, 0x03, 0x03, 0x00, 0x02, 0x02, 0x46]);
+    let frame = Frame {
+        tls: Some(&check),
+        protocol: protocol.as_ref(),
+        ..frame(&current, &history, 80)
+    };
+    assert_golden("tls_80", &render_frame(&frame));
+}
+
+#[tokio::test]
+async fn accept_queue_panel_flags_a_full_backlog() {
+    let queue = |queued, overflows| AcceptQueue {
+        queued,
+        backlog: 128,
+        listen_overflows: Some(overflows),
+    };
+    let mut source = ScriptedSource::new().with_listener(8888, 12345);
+    for (cpu, mem) in [(5.1, 41.0), (5.3, 41.5), (5.2, 41.5), (5.2, 41.6)] {
+        source = source.push_sample(info(cpu, mem));
+    }
+    let source = [(2, 10), (40, 10), (120, 25), (128, 61)]
+        .into_iter()
+        .fold(source, |source, (queued, overflows)| source.push_accept_queue(queue(queued, overflows)));
+    let mut session = WatchSession::new(source, 8888).unwrap();
+    while session.tick().await.is_ok() {}
+
+    let current = info(5.2, 41.6);
+    let frame = Frame {
+        accept_queue: session.accept_queue.as_ref(),
+        ..frame(&current, &session.history, 80)
+    };
+    assert_golden("accept_queue_80", &render_frame(&frame));
+}
+
+#[test]
+fn tcp_panel_alerts_on_retransmits() {
+    let samples = [(5.1, 41.0), (5.3, 41.5)];
+    let history = history(&samples);
+    let current = info(5.3, 41.5);
+    let stats = TcpStats {
+        connections: 14,
+        rtt_avg_ms: Some(18.4),
+        rtt_max_ms: Some(142.7),
+        cwnd_avg: Some(9.6),
+        retransmits: 87,
+        segments_out: 2_950,
+    };
+    let frame = Frame {
+        tcp_stats: Some(&stats),
+        ..frame(&current, &history, 80)
+    };
+    assert_golden("tcp_80", &render_frame(&frame));
+}
+
+#[test]
+fn network_tab_leaves_out_cpu_and_memory() {
+    let samples = [(5.1, 41.0), (5.3, 41.5)];
+    let history = history(&samples);
+    let current = info(5.3, 41.5);
+    let stats = TcpStats {
+        connections: 3,
+        rtt_avg_ms: Some(0.4),
+        rtt_max_ms: Some(1.2),
+        cwnd_avg: Some(10.0),
+        retransmits: 0,
+        segments_out: 1_200,
+    };
+    let frame = Frame {
+        tab: Some(Tab::Network),
+        tcp_stats: Some(&stats),
+        ..frame(&current, &history, 80)
+    };
+    assert_golden("network_tab_80", &render_frame(&frame));
+}
+
+#[test]
+fn socket_panel_shows_backlog_buffers_and_sharing() {
+    let samples = [(5.1, 41.0), (5.3, 41.5)];
+    let history = history(&samples);
+    let current = info(5.3, 41.5);
+    let socket = |address: &str, count, sharing, backlog, recv_buffer| SocketOptions {
+        listener: Listener {
+            address: address.to_string(),
+            port: 8888,
+        },
+        count,
+        sharing,
+        backlog,
+        recv_buffer,
+        send_buffer: 16_384,
+    };
+    let sockets = ListenerSockets {
+        sockets: vec![socket("0.0.0.0", 3, 4, 4096, 2_097_152), socket("0.0.0.0", 1, 4, 511, 131_072), socket("[::]", 1, 1, 511, 131_072)],
+        somaxconn: Some(4096),
+        default_recv_buffer: Some(131_072),
+        default_send_buffer: Some(16_384),
+    };
+    let frame = Frame {
+        tab: Some(Tab::Network),
+        sockets: Some(&sockets),
+        ..frame(&current, &history, 80)
+    };
+    assert_golden("socket_80", &render_frame(&frame));
+}
+
+#[test]
+fn top_talkers_with_their_traffic_internal_or_from_the_internet() {
+    let samples = [(5.1, 41.0), (5.3, 41.5)];
+    let history = history(&samples);
+    let current = info(5.3, 41.5);
+    let peer = |address: &str, connections, bytes_per_sec, hostname: Option<&str>, geo: GeoInfo| Peer {
+        address: address.parse().unwrap(),
+        connections,
+        bytes_per_sec,
+        hostname: hostname.map(str::to_string),
+        geo,
+    };
+    let google = GeoInfo {
+        country: Some("US".to_string()),
+        asn: Some(15169),
+        organization: Some("GOOGLE".to_string()),
+    };
+    let peers = [
+        peer("10.0.0.7", 14, Some(2_400.0), Some("web-1.internal"), GeoInfo::default()),
+        peer("8.8.8.8", 3, Some(12_650_000.0), Some("dns.google"), google),
+        peer("2a00:1450:4009:81f::200e", 2, Some(0.0), None, GeoInfo::default()),
+        peer("81.2.69.160", 1, None, None, GeoInfo { country: Some("GB".to_string()), ..Default::default() }),
+    ];
+    let frame = Frame {
+        tab: Some(Tab::Network),
+        peers: Some(&peers),
+        ..frame(&current, &history, 80)
+    };
+    assert_golden("peers_80", &render_frame(&frame));
+}
+
+#[test]
+fn security_panel_flags_root_on_every_interface() {
+    let samples = [(5.1, 41.0), (5.3, 41.5)];
+    let history = history(&samples);
+    let current = ProcessInfo {
+        user: Some("root".to_string()),
+        ..info(5.3, 41.5)
+    };
+    let status = "Uid:\t0\t0\t0\t0\nCapEff:\t000001ffffffffff\n";
+    let posture = Posture::from_parts(status, Some("unconfined"), None, Some("/usr/bin/node".to_string()), Some(0o755)).unwrap();
+    let frame = Frame {
+        tab: Some(Tab::Network),
+        posture: Some(&posture),
+        ..frame(&current, &history, 80)
+    };
+    assert_golden("security_80", &render_frame(&frame));
+}
+
+#[test]
+fn memory_breakdown_of_a_forking_server() {
+    let samples = [(12.0, 1380.0), (12.5, 1400.0)];
+    let history = history(&samples);
+    let current = ProcessInfo {
+        virtual_bytes: 11_240_000_000,
+        shared_bytes: Some(980_000_000),
+        swap_bytes: Some(60_000_000),
+        ..info(12.5, 1400.0)
+    };
+    assert_golden("memory_breakdown_80", &render_frame(&frame(&current, &history, 80)));
+}
+
+#[tokio::test]
+async fn counters_panel_for_a_major_fault_storm() {
+    let mut source = ScriptedSource::new().with_listener(8888, 12345);
+    let script = [(1_000, 0, 400, 10), (1_300, 2, 460, 14), (1_900, 40, 500, 90), (2_100, 900, 520, 1_500)];
+    for (minor, major, voluntary, involuntary) in script {
+        source = source.push_sample(ProcessInfo {
+            counters: Some(ProcessCounters {
+                minor_faults: minor,
+                major_faults: major,
+                voluntary_switches: voluntary,
+                involuntary_switches: involuntary,
+            }),
+            ..info(5.2, 41.5)
+        });
+    }
+    let mut session = WatchSession::new(source, 8888).unwrap();
+    while session.tick().await.is_ok() {}
+
+    let current = info(5.2, 41.5);
+    let frame = Frame {
+        counters: session.counters.as_ref(),
+        ..frame(&current, &session.history, 80)
+    };
+    assert_golden("counters_80", &render_frame(&frame));
+}
+
+#[tokio::test]
+async fn core_strip_flags_a_single_saturated_core() {
+    // Eight cores; the event loop pins cpu5 while a few helpers nibble elsewhere.
+    let reading = |ticks: u64, threads: [(u32, u32, u64); 4]| CoreTimes {
+        core_ticks: vec![ticks; 8],
+        threads: threads.iter().map(|&(tid, cpu, ticks)| ThreadTime { tid, cpu, ticks }).collect(),
+    };
+    let source = ScriptedSource::new()
+        .with_listener(8888, 12345)
+        .push_sample(info(112.0, 41.5))
+        .push_sample(info(112.0, 41.5))
+        .push_core_times(reading(1_000, [(1, 5, 0), (2, 1, 0), (3, 2, 0), (4, 2, 0)]))
+        .push_core_times(reading(1_100, [(1, 5, 98), (2, 1, 4), (3, 2, 6), (4, 2, 55)]));
+    let mut session = WatchSession::new(source, 8888).unwrap();
+    while session.tick().await.is_ok() {}
+
+    let current = info(112.0, 41.5);
+    let frame = Frame {
+        cores: session.cores.as_ref(),
+        ..frame(&current, &session.history, 80)
+    };
+    assert_golden("cores_80", &render_frame(&frame));
+}
+
+#[test]
+fn crabtops_own_usage_above_the_footer() {
+    let samples = [(4.0, 41.0), (4.5, 41.5)];
+    let history = history(&samples);
+    let current = info(4.5, 41.5);
+    let own = ProcessInfo {
+        name: "port-inspector".to_string(),
+        cpu_percent: 0.31,
+        memory_bytes: 9_830_000,
+        ..Default::default()
+    };
+    let frame = Frame {
+        own: Some(&own),
+        ..frame(&current, &history, 80)
+    };
+    assert_golden("own_usage_80", &render_frame(&frame));
+}
+
+#[test]
+fn oom_panel_warns_when_the_process_is_next() {
+    let samples = [(12.0, 1380.0), (12.5, 1400.0)];
+    let history = history(&samples);
+    let current = info(12.5, 1400.0);
+    let oom = OomStatus {
+        score: 812,
+        score_adj: 0,
+        rank: 1,
+        processes: 213,
+        pressure: Some(MemoryPressure {
+            some_avg10: 18.2,
+            some_avg60: 9.4,
+            full_avg10: 2.1,
+            full_avg60: 0.8,
+        }),
+    };
+    let frame = Frame {
+        oom: Some(&oom),
+        ..frame(&current, &history, 80)
+    };
+    assert_golden("oom_80", &render_frame(&frame));
+}
+
+#[test]
+fn memory_segments_point_at_the_one_growing() {
+    let samples = [(3.0, 240.0), (3.1, 320.0)];
+    let history = history(&samples);
+    let current = info(3.1, 320.0);
+    let mb = 1_000_000;
+    let at = Local.with_ymd_and_hms(2026, 1, 28, 10, 29, 45).unwrap();
+    let mut segments = SegmentHistory::default();
+    let reading = |anonymous| MemorySegments {
+        heap: 48 * mb,
+        anonymous,
+        file: 22 * mb,
+        stack: 132_000,
+        other: 8_000,
+    };
+    segments.add(at, reading(170 * mb));
+    segments.add(at + chrono::Duration::seconds(60), reading(250 * mb));
+    let frame = Frame {
+        segments: Some(&segments),
+        ..frame(&current, &history, 80)
+    };
+    assert_golden("segments_80", &render_frame(&frame));
+}
+
+#[tokio::test]
+async fn swap_warning_while_pages_go_out() {
+    let swapped = |mb: f64| ProcessInfo {
+        shared_bytes: Some(12_000_000),
+        swap_bytes: Some((mb * 1_000_000.0) as u64),
+        ..info(3.0, 88.0)
+    };
+    let source = ScriptedSource::new()
+        .with_listener(8888, 12345)
+        .push_sample(swapped(20.0))
+        .push_sample(swapped(26.5));
+    let mut session = WatchSession::new(source, 8888).unwrap();
+    while session.tick().await.is_ok() {}
+
+    let current = swapped(26.5);
+    let oom = OomStatus {
+        score: 240,
+        score_adj: 0,
+        rank: 4,
+        processes: 213,
+        pressure: Some(MemoryPressure {
+            some_avg10: 14.6,
+            some_avg60: 6.0,
+            full_avg10: 3.2,
+            full_avg60: 1.1,
+        }),
+    };
+    let frame = Frame {
+        swap: session.swap.as_ref(),
+        oom: Some(&oom),
+        ..frame(&current, &session.history, 80)
+    };
+    assert_golden("swap_80", &render_frame(&frame));
+}
+
+#[test]
+fn power_panel_after_one_interval() {
+    // One 65 W package, a host with half its eight cores busy and a process using three.
+    let dir = std::env::temp_dir().join(format!("crabtop-golden-power-{}", std::process::id()));
+    let zone = dir.join("intel-rapl:0");
+    fs::create_dir_all(&zone).unwrap();
+    fs::write(zone.join("name"), "package-0\n").unwrap();
+    fs::write(zone.join("energy_uj"), "0\n").unwrap();
+    let cores: String = (0..8).map(|n| format!("cpu{} 0 0 0 0\n", n)).collect();
+    fs::write(dir.join("stat"), format!("cpu  0 0 0 0\n{}", cores)).unwrap();
+    let mut power = PowerMeter::from_paths(&dir, &dir.join("stat")).unwrap();
+    let start = Instant::now();
+    power.update_at(start, 300.0);
+    fs::write(zone.join("energy_uj"), "130000000\n").unwrap();
+    fs::write(dir.join("stat"), format!("cpu  800 0 0 800\n{}", cores)).unwrap();
+    power.update_at(start + Duration::from_secs(2), 300.0);
+    fs::remove_dir_all(&dir).unwrap();
+
+    let samples = [(300.0, 512.0), (300.0, 512.0)];
+    let history = history(&samples);
+    let current = info(300.0, 512.0);
+    let frame = Frame {
+        power: Some(&power),
+        ..frame(&current, &history, 80)
+    };
+    assert_golden("power_80", &render_frame(&frame));
+}
+
+#[test]
+fn open_files_listing_second_page() {
+    let mut files = vec![
+        OpenFile::new(0, "/dev/null".to_string(), Some(0)),
+        OpenFile::new(1, "pipe:[30211]".to_string(), None),
+        OpenFile::new(2, "/var/log/api/error.log".to_string(), Some(4_815_162_342)),
+        OpenFile::new(3, "anon_inode:[eventpoll]".to_string(), None),
+    ];
+    for fd in 4..18 {
+        files.push(OpenFile::new(fd, format!("socket:[{}]", 90_000 + fd), None));
+    }
+    files.push(OpenFile::new(18, "/mnt/nfs/shared/uploads/2024/incoming/batch-000123.csv".to_string(), Some(812)));
+    files.push(OpenFile::new(19, "/srv/api/data/cache.db".to_string(), Some(73_400_320)));
+    let mut listing = FileListing::new(files);
+    listing.next_page();
+
+    let samples = [(3.0, 120.0), (3.1, 121.0)];
+    let history = history(&samples);
+    let current = info(3.1, 121.0);
+    let frame = Frame {
+        open_files: Some(&listing),
+        ..frame(&current, &history, 80)
+    };
+    assert_golden("open_files_80", &render_frame(&frame));
+}
+
+#[test]
+fn app_metrics_from_a_go_service() {
+    let exposition = "# TYPE go_goroutines gauge\ngo_goroutines 1312\n# TYPE go_threads gauge\ngo_threads 14\n\
+                      # TYPE go_memstats_heap_alloc_bytes gauge\ngo_memstats_heap_alloc_bytes 8.4e+07\n\
+                      # TYPE go_memstats_heap_inuse_bytes gauge\ngo_memstats_heap_inuse_bytes 9.1e+07\n\
+                      # TYPE go_memstats_heap_sys_bytes gauge\ngo_memstats_heap_sys_bytes 1.5e+08\n\
+                      # TYPE go_gc_duration_seconds summary\ngo_gc_duration_seconds_count 4821\n\
+                      # TYPE http_requests_total counter\nhttp_requests_total{code=\"200\"} 90000\n\
+                      # TYPE process_open_fds gauge\nprocess_open_fds 212\n";
+    let mut app = AppMetrics::new(
+        Some("http://127.0.0.1:8080/metrics".to_string()),
+        Some("http://127.0.0.1:8080/debug/pprof/".to_string()),
+        Vec::new(),
+        Duration::from_secs(1),
+    )
+    .unwrap();
+    let start = Instant::now();
+    app.apply_exposition(start, exposition);
+    app.apply_exposition(start + Duration::from_secs(1), &exposition.replace("90000", "90250"));
+
+    let samples = [(35.0, 180.0), (37.0, 182.0)];
+    let history = history(&samples);
+    let current = info(37.0, 182.0);
+    let frame = Frame {
+        app: Some(&app),
+        ..frame(&current, &history, 80)
+    };
+    assert_golden("app_metrics_80", &render_frame(&frame));
+}
+
+#[test]
+fn python_stack_dump_scrolled() {
+    let mut lines = vec![
+        "Process 4242: /usr/bin/python3 -m gunicorn api:app".to_string(),
+        "Python v3.11.4 (/usr/bin/python3.11)".to_string(),
+        String::new(),
+        "Thread 4242 (idle): \"MainThread\"".to_string(),
+        "    sleep (gunicorn/arbiter.py:357)".to_string(),
+        "    run (gunicorn/arbiter.py:209)".to_string(),
+    ];
+    for n in 0..20 {
+        lines.push(format!("Thread {} (active): \"worker-{}\"", 4300 + n, n));
+        lines.push("    execute (psycopg2/extras.py:146)".to_string());
+    }
+    let mut dump = StackDump::new(Runtime::Python, "py-spy dump --pid 4242".to_string(), Ok(lines));
+    dump.scroll(3);
+
+    let samples = [(41.0, 300.0), (43.0, 302.0)];
+    let history = history(&samples);
+    let current = info(43.0, 302.0);
+    let frame = Frame {
+        runtime: Some(Runtime::Python),
+        stack_dump: Some(&dump),
+        ..frame(&current, &history, 80)
+    };
+    assert_golden("stack_dump_80", &render_frame(&frame));
+}
+
+#[test]
+fn systemd_panel_asks_before_restarting() {
+    let samples = [(22.0, 400.0), (24.0, 410.0)];
+    let history = history(&samples);
+    let current = info(24.0, 410.0);
+    let unit = UnitStatus {
+        name: "api.service".to_string(),
+        active_state: "active".to_string(),
+        sub_state: "running".to_string(),
+        main_pid: Some(12345),
+        restarts: Some(2),
+        cpu_quota_percent: Some(150.0),
+        memory_max: Some(512_000_000),
+        memory_current: Some(410_000_000),
+        tasks: Some(17),
+    };
+    let frame = Frame {
+        unit: Some(&unit),
+        unit_prompt: Some("Restart api.service? Press 'y' to confirm, any other key to cancel"),
+        ..frame(&current, &history, 80)
+    };
+    assert_golden("systemd_80", &render_frame(&frame));
+}
+
+#[test]
+fn log_pane_next_to_a_memory_spike() {
+    let samples = [(8.0, 300.0), (9.0, 310.0), (35.0, 980.0)];
+    let history = history(&samples);
+    let current = info(35.0, 980.0);
+    let logs = LogTail {
+        source: "journalctl -u api.service".to_string(),
+        lines: Ok(vec![
+            "2024-05-01T12:00:01+0000 web-1 api[12345]: GET /health 200 1ms".to_string(),
+            "2024-05-01T12:00:02+0000 web-1 api[12345]: WARN cache miss storm, rebuilding index".to_string(),
+            "2024-05-01T12:00:03+0000 web-1 api[12345]: ERROR export job loaded 2.1M rows into memory at once".to_string(),
+        ]),
+    };
+    let frame = Frame {
+        logs: Some(&logs),
+        ..frame(&current, &history, 80)
+    };
+    assert_golden("logs_80", &render_frame(&frame));
+}
+
+#[test]
+fn crabtop_log_pane_after_a_failed_lookup() {
+    let samples = [(8.0, 300.0), (9.0, 310.0)];
+    let history = history(&samples);
+    let current = info(9.0, 310.0);
+    let line = |second, level, message: &str| LogLine {
+        timestamp: Local.with_ymd_and_hms(2024, 5, 1, 12, 0, second).unwrap(),
+        level,
+        target: "crabtop::port".to_string(),
+        message: message.to_string(),
+    };
+    let lines = [
+        line(1, Level::DEBUG, "lsof: nothing for port 8080 (exit status: 1)"),
+        line(1, Level::DEBUG, "falling back to ss and netstat for port 8080"),
+        line(2, Level::INFO, "PID 4242 replaced by 4300 rule=process_restarted"),
+        line(3, Level::WARN, "exporter statsd: connection refused"),
+    ];
+    let frame = Frame {
+        crabtop_log: Some(&lines),
+        ..frame(&current, &history, 80)
+    };
+    assert_golden("crabtop_log_80", &render_frame(&frame));
+}
+
+#[test]
+fn cgroup_limits_panel_for_a_throttled_container() {
+    let samples = [(45.0, 400.0), (48.0, 420.0)];
+    let history = history(&samples);
+    let cgroup = CgroupStats {
+        path: "/sys/fs/cgroup/system.slice/docker-abc.scope".into(),
+        memory_current: Some(420_000_000),
+        memory_max: Some(512_000_000),
+        cpu_quota_cores: Some(0.5),
+        cpu_stat: CpuStat {
+            usage_usec: 90_000_000,
+            nr_periods: 3_000,
+            nr_throttled: 450,
+            throttled_usec: 12_500_000,
+        },
+    };
+    let current = info(48.0, 420.0);
+    let frame = Frame {
+        cgroup: Some(&cgroup),
+        throttled_ratio: Some(0.3),
+        ..frame(&current, &history, 80)
+    };
+    assert_golden("cgroup_80", &render_frame(&frame));
+}
+
+#[test]
+fn fleet_grid_with_an_unreachable_host() {
+    let samples = [(5.1, 41.0), (25.0, 41.5), (62.0, 42.0)];
+    let history = history(&samples);
+    let current = info(62.0, 42.0);
+    let cells = [
+        GridCell {
+            label: "web-1:7070 · 8888",
+            info: Some(&current),
+            pids: &[12345],
+            history: Some(&history),
+            error: None,
+        },
+        GridCell {
+            label: "web-2:7070 · 8888",
+            info: Some(&current),
+            pids: &[2001, 2002],
+            history: Some(&history),
+            error: None,
+        },
+        GridCell {
+            label: "web-3:7070 · 8888",
+            info: None,
+            pids: &[],
+            history: None,
+            error: Some("Failed to reach http://web-3:7070: connection refused"),
+        },
+    ];
+    let timestamp = NaiveDate::from_ymd_opt(2026, 1, 28)
+        .unwrap()
+        .and_hms_opt(10, 30, 45)
+        .unwrap();
+    let mut buf = Vec::new();
+    render_grid(&mut buf, &cells, 1, 80, timestamp).unwrap();
+    assert_golden("grid_80", &strip_ansi(&String::from_utf8(buf).unwrap()));
+}
+
+#[test]
+fn compare_two_versions() {
+    let old_samples = [(20.0, 300.0), (22.0, 310.0), (21.0, 305.0)];
+    let new_samples = [(28.0, 280.0), (30.0, 282.0), (29.0, 281.0)];
+    let (old_history, new_history) = (history(&old_samples), history(&new_samples));
+    let (old, new) = (info(21.0, 305.0), info(29.0, 281.0));
+    let left = CompareSide {
+        port: 8080,
+        info: &old,
+        pids: &[12345],
+        history: &old_history,
+    };
+    let right = CompareSide {
+        port: 8081,
+        info: &new,
+        pids: &[12346, 12347],
+        history: &new_history,
+    };
+    let timestamp = NaiveDate::from_ymd_opt(2026, 1, 28)
+        .unwrap()
+        .and_hms_opt(10, 30, 45)
+        .unwrap();
+    let mut buf = Vec::new();
+    render_compare(&mut buf, &left, &right, 80, timestamp).unwrap();
+    assert_golden("compare_80", &strip_ansi(&String::from_utf8(buf).unwrap()));
+
+    assert_eq!(relative_change(20.0, 25.0), Some(25.0));
+    assert_eq!(relative_change(0.0, 5.0), None);
+}
+
+#[test]
+fn top_by_cpu_then_connections() {
+    let busy = ProcessInfo {
+        pid: 2001,
+        name: "gunicorn: worker [api]".to_string(),
+        ..info(91.5, 420.0)
+    };
+    let quiet = ProcessInfo {
+        pid: 2002,
+        name: "nginx".to_string(),
+        ..info(3.2, 48.0)
+    };
+    let node = info(40.0, 180.0);
+    let (busy_history, quiet_history, node_history) =
+        (history(&[(60.0, 400.0), (91.5, 420.0)]), history(&[(3.2, 48.0)]), history(&[(35.0, 170.0), (40.0, 180.0)]));
+    let mut rows = vec![
+        TopRow {
+            info: &quiet,
+            ports: &[80, 443],
+            connections: Some(120),
+            history: &quiet_history,
+        },
+        TopRow {
+            info: &busy,
+            ports: &[8000],
+            connections: Some(12),
+            history: &busy_history,
+        },
+        TopRow {
+            info: &node,
+            ports: &[8888, 9464],
+            connections: None,
+            history: &node_history,
+        },
+    ];
+    let timestamp = NaiveDate::from_ymd_opt(2026, 1, 28)
+        .unwrap()
+        .and_hms_opt(10, 30, 45)
+        .unwrap();
+    sort_top_rows(&mut rows, TopSort::Cpu);
+    let mut buf = Vec::new();
+    render_top(&mut buf, &rows, 7, TopSort::Cpu, 1, 80, timestamp).unwrap();
+    assert_golden("top_80", &strip_ansi(&String::from_utf8(buf).unwrap()));
+
+    sort_top_rows(&mut rows, TopSort::Connections);
+    let order: Vec<u32> = rows.iter().map(|r| r.info.pid).collect();
+    assert_eq!(order, vec![2002, 2001, 12345]);
+    assert_eq!(TopSort::Connections.next(), TopSort::Cpu);
+}
+
+#[test]
+fn worker_pool_sums_workers_and_notes_respawns() {
+    let samples = [(1.0, 60.0), (1.2, 60.0)];
+    let history = history(&samples);
+    let master = info(1.2, 60.0);
+    let worker = |pid, cpu, mb| ProcessInfo {
+        pid,
+        ..info(cpu, mb)
+    };
+    let at = chrono::Local.with_ymd_and_hms(2026, 1, 28, 10, 30, 44).unwrap();
+    let mut pool = WorkerPool::new("node", 60);
+    pool.update(&master, vec![worker(12346, 40.0, 120.0), worker(12347, 85.0, 150.0)], at);
+    pool.update(&master, vec![worker(12347, 90.0, 155.0), worker(12350, 12.0, 95.0)], at + chrono::Duration::seconds(1));
+    let frame = Frame {
+        pool: Some(&pool),
+        ..frame(&master, &history, 80)
+    };
+    assert_golden("pool_80", &render_frame(&frame));
+}
+
+#[test]
+fn child_feed_lists_spawns_and_exits() {
+    let samples = [(1.0, 60.0), (1.2, 60.0)];
+    let history = history(&samples);
+    let current = info(1.2, 60.0);
+    let child = |pid, command: &str, exit: Option<ChildExit>| ChildProcess {
+        pid,
+        ppid: 12345,
+        command: command.to_string(),
+        zombie: exit.is_some(),
+        exit,
+    };
+    let at = chrono::Local.with_ymd_and_hms(2026, 1, 28, 10, 30, 43).unwrap();
+    let mut feed = ChildFeed::new();
+    feed.update(vec![child(12350, "node worker.js", None)], at);
+    feed.update(vec![child(12350, "node worker.js", None), child(12360, "sh -c convert in.png out.jpg", None)], at + chrono::Duration::seconds(1));
+    feed.update(
+        vec![child(12350, "node worker.js", None), child(12360, "sh", Some(ChildExit::Code(1))), child(12361, "git fetch --quiet", None)],
+        at + chrono::Duration::seconds(2),
+    );
+    feed.update(vec![child(12350, "node worker.js", Some(ChildExit::Signal(9)))], at + chrono::Duration::seconds(3));
+    let frame = Frame {
+        children: Some(&feed),
+        tab: Some(Tab::Threads),
+        ..frame(&current, &history, 80)
+    };
+    assert_golden("children_80", &render_frame(&frame));
+}
+
+#[test]
+fn upstream_panel_shows_the_proxy_chain() {
+    let samples = [(1.0, 12.0), (1.5, 12.0)];
+    let history = history(&samples);
+    let proxy = ProcessInfo {
+        name: "nginx".to_string(),
+        ..info(1.5, 12.0)
+    };
+    let mut chain = ProxyChain::new(ProxyKind::Nginx);
+    chain.add_ports(&[9000, 3000]);
+    chain.backends[0].pids = vec![4242, 4243];
+    chain.backends[0].latest = Some(ProcessInfo {
+        pid: 4242,
+        ..info(22.5, 180.0)
+    });
+    let frame = Frame {
+        upstream: Some(&chain),
+        ..frame(&proxy, &history, 80)
+    };
+    assert_golden("upstream_80", &render_frame(&frame));
+}
+
+#[test]
+fn churn_panel_flags_a_burst_of_new_connections() {
+    let samples = [(1.0, 60.0), (1.2, 60.0)];
+    let history = history(&samples);
+    let current = info(1.2, 60.0);
+    let connection = |n: u32, open| PortConnection {
+        key: format!("127.0.0.1:8888->127.0.0.1:{}", 40000 + n),
+        open,
+    };
+    // Two steady connections, then a burst of 30 short ones that are already in TIME-WAIT.
+    let tables = [
+        vec![connection(0, true), connection(1, true)],
+        vec![connection(0, true), connection(1, true), connection(2, true)],
+        vec![connection(0, true), connection(1, true), connection(3, true)],
+        (0..2).map(|n| connection(n, true)).chain((10..40).map(|n| connection(n, false))).collect(),
+    ];
+    let mut churn = ChurnHistory::default();
+    for table in &tables {
+        churn.add(table, std::time::Duration::from_secs(1), 60);
+    }
+    let frame = Frame {
+        churn: Some(&churn),
+        ..frame(&current, &history, 80)
+    };
+    assert_golden("churn_80", &render_frame(&frame));
+}
+
+#[tokio::test]
+async fn ebpf_panel_shows_accepts_retransmits_and_syscall_latency() {
+    let mut source = ScriptedSource::new().with_listener(8888, 12345);
+    let totals = [(0, 0, 0, 0, 0), (120, 0, 9_000, 27_000_000, 400_000), (310, 4, 21_000, 75_000_000, 1_800_000)];
+    for (accepts, retransmits, syscalls, syscall_ns, syscall_max_ns) in totals {
+        source = source.push_sample(info(5.2, 41.5)).push_ebpf_stats(EbpfStats {
+            accepts,
+            retransmits,
+            syscalls,
+            syscall_ns,
+            syscall_max_ns,
+            ..Default::default()
+        });
+    }
+    let mut session = WatchSession::new(source, 8888).unwrap();
+    while session.tick().await.is_ok() {}
+
+    let current = info(5.2, 41.5);
+    let frame = Frame {
+        ebpf: session.ebpf.as_ref(),
+        ..frame(&current, &session.history, 80)
+    };
+    assert_golden("ebpf_80", &render_frame(&frame));
+}
+
+#[tokio::test]
+async fn syscall_panel_breaks_sampled_threads_down_by_category() {
+    let samples = [
+        SyscallCounts { poll: 1, futex: 6, running: 1, ..Default::default() },
+        SyscallCounts { read: 1, futex: 5, running: 2, ..Default::default() },
+        SyscallCounts { write: 1, futex: 6, other: 1, ..Default::default() },
+    ];
+    let mut source = ScriptedSource::new().with_listener(8888, 12345);
+    for counts in samples {
+        source = source.push_sample(info(5.2, 41.5)).push_task_syscalls(counts);
+    }
+    let mut session = WatchSession::new(source, 8888).unwrap().follow_syscalls(true);
+    for _ in 0..samples.len() {
+        session.tick().await.unwrap();
+    }
+
+    let current = info(5.2, 41.5);
+    let frame = Frame {
+        syscalls: session.syscalls.as_ref(),
+        ..frame(&current, &session.history, 80)
+    };
+    assert_golden("syscalls_80", &render_frame(&frame));
+}
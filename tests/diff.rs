@@ -0,0 +1,86 @@
+use std::fs;
+
+use chrono::{Duration, Local, TimeZone};
+
+use crabtop::diff::{diff, render_html, sparkline};
+use crabtop::export::{ExportSample, Exporter};
+use crabtop::recording::{self, Metric, Recorder};
+use crabtop::ProcessInfo;
+
+/// One sample a second from `start` (seconds past the hour) with these CPU figures.
+fn run(start: i64, cpu: &[f32], connections: Option<usize>) -> Vec<ExportSample> {
+    let base = Local.with_ymd_and_hms(2026, 10, 14, 10, 0, 0).unwrap() + Duration::seconds(start);
+    cpu.iter()
+        .enumerate()
+        .map(|(n, &cpu_percent)| ExportSample {
+            timestamp: base + Duration::seconds(n as i64),
+            host: None,
+            port: 8080,
+            pids: vec![4242],
+            process: ProcessInfo {
+                name: "api".to_string(),
+                cpu_percent,
+                memory_bytes: 1_000_000,
+                ..Default::default()
+            },
+            connections,
+            annotations: Vec::new(),
+        })
+        .collect()
+}
+
+#[test]
+fn recordings_round_trip_and_survive_a_torn_last_line() {
+    let path = std::env::temp_dir().join(format!("crabtop-test-{}.rec", std::process::id()));
+    let mut recorder = Recorder::create(&path).unwrap();
+    for sample in run(0, &[1.0, 2.0, 3.0], Some(7)) {
+        recorder.on_sample(&sample).unwrap();
+    }
+    recorder.on_shutdown().unwrap();
+    let mut text = fs::read_to_string(&path).unwrap();
+    text.push_str("{\"timestamp\":\"2026-10");
+    fs::write(&path, text).unwrap();
+
+    let loaded = recording::load(&path).unwrap();
+    fs::remove_file(&path).unwrap();
+    assert_eq!(loaded.len(), 3);
+    assert_eq!(loaded[2].process.cpu_percent, 3.0);
+    assert_eq!(loaded[0].connections, Some(7));
+}
+
+#[test]
+fn aligns_recordings_by_time_since_each_started() {
+    // B started an hour later and ran a second longer; only elapsed time matters.
+    let a = run(0, &[10.0, 10.0, 20.0, 20.0], Some(5));
+    let b = run(3600, &[10.0, 30.0, 40.0, 40.0, 50.0], None);
+    let result = diff(&a, &b, 2.0);
+    assert_eq!(result.metrics.len(), 3);
+
+    let cpu = &result.metrics[0];
+    assert_eq!(cpu.metric, Metric::Cpu);
+    assert_eq!(cpu.a, vec![Some(10.0), Some(20.0), None]);
+    assert_eq!(cpu.b, vec![Some(20.0), Some(40.0), Some(50.0)]);
+    assert_eq!(cpu.widest_gap(), Some((1, 20.0, 40.0)));
+    // Averages over every sample: 15% against 34%.
+    assert_eq!(cpu.change().map(f64::round), Some(127.0));
+
+    let connections = &result.metrics[2];
+    assert_eq!(connections.summary_a.unwrap().avg, 5.0);
+    assert!(connections.summary_b.is_none());
+    assert_eq!(connections.change(), None);
+}
+
+#[test]
+fn draws_sparklines_on_a_shared_scale_with_gaps() {
+    assert_eq!(sparkline(&[Some(0.0), Some(50.0), None, Some(100.0)], 100.0), "▁▅ █");
+}
+
+#[test]
+fn html_report_has_a_table_and_a_chart_per_metric() {
+    let result = diff(&run(0, &[1.0, 2.0], None), &run(0, &[3.0, 4.0], None), 1.0);
+    let html = render_html(&result, "before.rec", "after <fix>.rec");
+    assert_eq!(html.matches("<svg").count(), 2);
+    assert!(html.contains("<td>CPU</td><td>1.5%</td><td>3.5%</td><td>+133.3%</td>"));
+    assert!(html.contains("after &lt;fix&gt;.rec"));
+    assert!(!html.contains("<h2>Connections</h2>"));
+}
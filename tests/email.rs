@@ -0,0 +1,137 @@
+//! Alert emails: which get sent, what they say, and the SMTP dialogue.
+
+use std::time::{Duration, Instant};
+
+use chrono::{Local, TimeZone};
+use crabtop::config::{self, ExporterConfig};
+use crabtop::email::{message, AlertFilter, EmailExporter, Mailer, Security};
+use crabtop::export::{Alert, Exporter};
+use crabtop::syslog::{severity, Severity};
+use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
+use tokio::net::TcpListener;
+
+fn alert(rule: &str, message: &str) -> Alert {
+    Alert {
+        timestamp: Local::now(),
+        host: None,
+        port: 8080,
+        rule: rule.to_string(),
+        severity: severity(rule),
+        message: message.to_string(),
+        value: 4242.0,
+    }
+}
+
+#[test]
+fn configured_with_a_severity_and_a_cooldown() {
+    let config = config::parse(
+        "[[exporter]]\ntype = \"email\"\nserver = \"smtp.example.com:587\"\nfrom = \"crabtop@example.com\"\nto = [\"ops@example.com\"]\ncooldown = \"1h\"\n",
+    )
+    .unwrap();
+    assert!(matches!(
+        &config.exporters[0],
+        ExporterConfig::Email { security: Security::Starttls, username: None, min_severity: Severity::Warning, cooldown, .. }
+            if *cooldown == Duration::from_secs(3600)
+    ));
+    let bad = "[[exporter]]\ntype = \"email\"\nserver = \"s:25\"\nfrom = \"a@b\"\nto = []\ncooldown = \"soon\"\n";
+    assert!(config::parse(bad).is_err());
+}
+
+#[test]
+fn filter_drops_minor_alerts_and_counts_repeats_within_the_cooldown() {
+    let mut filter = AlertFilter::new(Severity::Error, Duration::from_secs(600));
+    let start = Instant::now();
+    assert_eq!(filter.admit(&alert("swap_started", "Started swapping"), start), None);
+    assert_eq!(filter.admit(&alert("process_exited", "PID 1 exited"), start), Some(0));
+    assert_eq!(filter.admit(&alert("process_exited", "PID 1 exited"), start + Duration::from_secs(60)), None);
+    assert_eq!(filter.admit(&alert("process_exited", "PID 1 exited"), start + Duration::from_secs(120)), None);
+    // Another target, or another rule, has its own cooldown.
+    let mut other = alert("process_exited", "PID 2 exited");
+    other.port = 9090;
+    assert_eq!(filter.admit(&other, start + Duration::from_secs(60)), Some(0));
+    assert_eq!(filter.admit(&alert("oom_at_risk", "Next for the OOM killer"), start), Some(0));
+
+    assert_eq!(filter.admit(&alert("process_exited", "PID 1 exited"), start + Duration::from_secs(601)), Some(2));
+    assert_eq!(filter.admit(&alert("process_exited", "PID 1 exited"), start + Duration::from_secs(602)), None);
+}
+
+#[test]
+fn message_has_headers_a_body_and_the_held_back_count() {
+    let now = Local.with_ymd_and_hms(2024, 3, 5, 9, 7, 1).unwrap();
+    let mut remote = alert("process_exited", "PID 4242 exited");
+    remote.host = Some("web-1:7070".to_string());
+    let to = vec!["ops@example.com".to_string(), "Pager <pager@example.com>".to_string()];
+    let text = message(&remote, 3, "crabtop <alerts@example.com>", &to, now);
+    assert!(text.starts_with("From: crabtop <alerts@example.com>\r\nTo: ops@example.com, Pager <pager@example.com>\r\n"), "{}", text);
+    assert!(text.contains("\r\nSubject: [crabtop] web-1:7070 port 8080: PID 4242 exited\r\n"), "{}", text);
+    assert!(text.contains(&format!("\r\nDate: {}\r\n", now.to_rfc2822())), "{}", text);
+    assert!(text.contains("@example.com>\r\n"), "{}", text);
+    assert!(text.contains("\r\n\r\nPID 4242 exited\r\n"), "{}", text);
+    assert!(text.contains("Rule:     process_exited (critical)\r\n"), "{}", text);
+    assert!(text.contains("3 more process_exited alerts for this target were held back"), "{}", text);
+    assert!(!message(&remote, 0, "alerts@example.com", &to, now).contains("held back"));
+}
+
+/// Plays an SMTP server for one session, recording what the client sends.
+async fn smtp_server(listener: TcpListener) -> Vec<String> {
+    let (socket, _) = listener.accept().await.unwrap();
+    let (read, mut write) = socket.into_split();
+    let mut lines = BufReader::new(read).lines();
+    let mut seen = Vec::new();
+    write.write_all(b"220 mail.test ESMTP\r\n").await.unwrap();
+    let mut in_data = false;
+    while let Some(line) = lines.next_line().await.unwrap() {
+        seen.push(line.clone());
+        let reply: &[u8] = if in_data {
+            if line != "." {
+                continue;
+            }
+            in_data = false;
+            b"250 queued\r\n"
+        } else if line.starts_with("EHLO") {
+            b"250-mail.test\r\n250-AUTH PLAIN\r\n250 8BITMIME\r\n"
+        } else if line.starts_with("AUTH PLAIN") {
+            b"235 ok\r\n"
+        } else if line == "DATA" {
+            in_data = true;
+            b"354 go ahead\r\n"
+        } else if line == "QUIT" {
+            write.write_all(b"221 bye\r\n").await.unwrap();
+            break;
+        } else {
+            b"250 ok\r\n"
+        };
+        write.write_all(reply).await.unwrap();
+    }
+    seen
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn exporter_mails_alerts_through_an_smtp_server() {
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let server = listener.local_addr().unwrap().to_string();
+    let session = tokio::spawn(smtp_server(listener));
+
+    let mailer = Mailer {
+        server,
+        security: Security::None,
+        login: Some(("crabtop".to_string(), "hunter2".to_string())),
+        from: "crabtop <alerts@example.com>".to_string(),
+        to: vec!["ops@example.com".to_string()],
+    };
+    let mut exporter = EmailExporter::new(mailer, AlertFilter::new(Severity::Warning, Duration::from_secs(60))).unwrap();
+    exporter.on_alert(&alert("something_minor", "Not worth a message")).unwrap();
+    exporter.on_alert(&alert("process_exited", "PID 4242 exited\r\n.hidden line")).unwrap();
+    exporter.on_shutdown().unwrap();
+
+    let seen = session.await.unwrap();
+    // "\0crabtop\0hunter2"
+    assert!(seen.contains(&"AUTH PLAIN AGNyYWJ0b3AAaHVudGVyMg==".to_string()), "{:?}", seen);
+    assert!(seen.contains(&"MAIL FROM:<alerts@example.com>".to_string()), "{:?}", seen);
+    assert!(seen.contains(&"RCPT TO:<ops@example.com>".to_string()), "{:?}", seen);
+    assert!(seen.contains(&"Subject: [crabtop] port 8080: PID 4242 exited".to_string()), "{:?}", seen);
+    // A line starting with '.' is stuffed so it doesn't end the message.
+    assert!(seen.contains(&"..hidden line".to_string()), "{:?}", seen);
+    assert!(!seen.iter().any(|line| line.contains("Not worth a message")), "{:?}", seen);
+    assert_eq!(seen.last().map(String::as_str), Some("QUIT"));
+}
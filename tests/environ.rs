@@ -0,0 +1,30 @@
+use crabtop::environ::parse_environ;
+
+const RAW: &[u8] = b"PATH=/usr/bin\0DATABASE_URL=postgres://app@db/app\0AWS_ACCESS_KEY_ID=AKIAEXAMPLE\0\
+GITHUB_TOKEN=ghp_example\0EMPTY_PASSWORD=\0LANG=C.UTF-8\0NO_EQUALS\0";
+
+#[test]
+fn masks_secret_looking_values_and_sorts_by_name() {
+    let vars = parse_environ(RAW, false);
+    let shown: Vec<(&str, &str, bool)> = vars.iter().map(|v| (v.name.as_str(), v.value.as_str(), v.masked)).collect();
+    assert_eq!(
+        shown,
+        vec![
+            ("AWS_ACCESS_KEY_ID", "[REDACTED]", true),
+            ("DATABASE_URL", "postgres://app@db/app", false),
+            // Nothing to hide in an empty value, and seeing it's empty is useful.
+            ("EMPTY_PASSWORD", "", false),
+            ("GITHUB_TOKEN", "[REDACTED]", true),
+            ("LANG", "C.UTF-8", false),
+            ("NO_EQUALS", "", false),
+            ("PATH", "/usr/bin", false),
+        ]
+    );
+}
+
+#[test]
+fn show_secrets_keeps_every_value() {
+    let vars = parse_environ(RAW, true);
+    assert!(vars.iter().all(|v| !v.masked));
+    assert!(vars.iter().any(|v| v.name == "GITHUB_TOKEN" && v.value == "ghp_example"));
+}
@@ -0,0 +1,64 @@
+//! Typed errors: found through added context, with their own exit codes and JSON.
+
+use anyhow::Context;
+use crabtop::error::{exit_code, find, to_json};
+use crabtop::{Error, MetricsSource, ScriptedSource};
+use serde_json::json;
+
+#[tokio::test]
+async fn a_scripted_source_says_which_failure_it_was() {
+    let mut source = ScriptedSource::new();
+    let err = source.resolve_pids(8080).unwrap_err();
+    assert_eq!(find(&err), Some(&Error::PortNotFound { port: 8080 }));
+
+    let err = source.sample(4242).await.unwrap_err();
+    assert_eq!(find(&err), Some(&Error::ProcessGone { pid: 4242 }));
+    assert_eq!(exit_code(&err), 5);
+}
+
+#[test]
+fn context_keeps_the_kind_and_untyped_errors_exit_with_one() {
+    let err = Err::<(), _>(Error::ToolMissing { tool: "lsof".to_string() })
+        .context("Failed to inspect port 8080 on web-1")
+        .unwrap_err();
+    assert_eq!(exit_code(&err), 6);
+    assert_eq!(err.to_string(), "Failed to inspect port 8080 on web-1");
+
+    let plain = anyhow::anyhow!("something else");
+    assert_eq!(exit_code(&plain), 1);
+    assert_eq!(to_json(&plain), json!({ "error": { "kind": "other", "message": "something else", "exit_code": 1 } }));
+}
+
+#[test]
+fn each_kind_has_its_own_exit_code_and_json_fields() {
+    let errors = [
+        Error::ThresholdBreached { breached: 1, checked: 4 },
+        Error::PortNotFound { port: 8080 },
+        Error::PermissionDenied { port: Some(80), pid: None },
+        Error::ToolMissing { tool: "lsof".to_string() },
+        Error::ProcessGone { pid: 7 },
+        Error::ApiError {
+            service: "OpenAI API".to_string(),
+            status: 429,
+            message: "rate limited".to_string(),
+        },
+    ];
+    let codes: Vec<i32> = errors.iter().map(Error::exit_code).collect();
+    // The exit-code contract; these numbers must never change.
+    assert_eq!(codes, [2, 3, 4, 6, 5, 7]);
+
+    let json = to_json(&errors[2].clone().into());
+    assert_eq!(
+        json,
+        json!({ "error": {
+            "kind": "permission_denied",
+            "port": 80,
+            "message": "Port 80 is in use, but by a process this user isn't allowed to see (another user's, or root's)",
+            "exit_code": 4,
+        } })
+    );
+    let json = to_json(&errors[5].clone().into());
+    assert_eq!(json["error"]["kind"], "api_error");
+    assert_eq!(json["error"]["status"], 429);
+    assert_eq!(json["error"]["message"], "OpenAI API returned 429: rate limited");
+}
@@ -0,0 +1,244 @@
+mod common;
+
+use std::collections::BTreeMap;
+use std::fs;
+use std::sync::{Arc, Mutex};
+
+use anyhow::{anyhow, Result};
+use crabtop::ai::ProviderKind;
+use crabtop::config::{self, ExporterConfig};
+use crabtop::export::{Alert, CsvExporter, ExportSample, Exporter, ExporterRegistry, QueuedWorker, WORKER_DRAIN};
+use crabtop::prometheus::{self, PushExporter, PushProtocol};
+use crabtop::statsd::StatsdFormat;
+use common::info;
+
+fn sample(name: &str, cpu_percent: f32) -> ExportSample {
+    ExportSample {
+        pids: vec![1, 2],
+        ..common::sample(info(name, 0, cpu_percent, 1_000))
+    }
+}
+
+/// Counts calls and fails every sample after the first.
+struct Flaky {
+    samples: usize,
+    alerts: usize,
+}
+
+impl Exporter for Flaky {
+    fn name(&self) -> &str {
+        "flaky"
+    }
+
+    fn on_sample(&mut self, _sample: &ExportSample) -> Result<()> {
+        self.samples += 1;
+        if self.samples > 1 {
+            return Err(anyhow!("collector down"));
+        }
+        Ok(())
+    }
+
+    fn on_alert(&mut self, _alert: &Alert) -> Result<()> {
+        self.alerts += 1;
+        Ok(())
+    }
+}
+
+#[test]
+fn config_file_lists_exporters_by_type() {
+    let config = config::parse(
+        r#"
+        [[exporter]]
+        type = "csv"
+        path = "/tmp/samples.csv"
+
+        [[exporter]]
+        type = "statsd"
+        address = "127.0.0.1:8125"
+        format = "plain"
+
+        [[exporter]]
+        type = "webhook"
+        url = "https://hooks.example.com/crabtop"
+        "#,
+    )
+    .unwrap();
+
+    assert_eq!(config.exporters.len(), 3);
+    assert!(matches!(
+        &config.exporters[1],
+        ExporterConfig::Statsd { prefix, format: StatsdFormat::Plain, .. } if prefix == "crabtop"
+    ));
+    assert!(matches!(&config.exporters[2], ExporterConfig::Webhook { samples: false, .. }));
+
+    assert!(config::parse("[[exporter]]\ntype = \"carrier-pigeon\"\n").is_err());
+
+    let ai = config::parse("[ai]\nprovider = \"openai-compatible\"\nurl = \"http://vllm:8000/v1\"\n")
+        .unwrap()
+        .ai;
+    assert_eq!(ai.provider, Some(ProviderKind::OpenaiCompatible));
+    assert_eq!(ai.url.as_deref(), Some("http://vllm:8000/v1"));
+}
+
+#[test]
+fn registry_keeps_going_when_an_exporter_fails() {
+    let dir = std::env::temp_dir().join(format!("crabtop-export-{}", std::process::id()));
+    fs::create_dir_all(&dir).unwrap();
+    let csv_path = dir.join("samples.csv");
+    let _ = fs::remove_file(&csv_path);
+
+    let mut registry = ExporterRegistry::new();
+    registry.push(Box::new(Flaky { samples: 0, alerts: 0 }));
+    registry.push(Box::new(CsvExporter::create(&csv_path).unwrap()));
+
+    registry.on_sample(&sample("nginx", 1.5));
+    registry.on_sample(&sample("my, \"app\"", 2.0));
+    registry.on_sample(&sample("nginx", 3.0));
+
+    assert_eq!(
+        registry.on_shutdown(),
+        vec!["flaky failed 2 times; last error: collector down".to_string()]
+    );
+
+    let csv = fs::read_to_string(&csv_path).unwrap();
+    let lines: Vec<&str> = csv.lines().collect();
+    assert_eq!(lines.len(), 4, "{}", csv);
+    assert_eq!(lines[0], "timestamp,host,port,pids,process,cpu_percent,memory_bytes,uptime_secs");
+    assert!(lines[2].ends_with(",,8080,1 2,\"my, \"\"app\"\"\",2.00,1000,0"), "{}", lines[2]);
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn prometheus_exposition_labels_each_target() {
+    let mut samples = BTreeMap::new();
+    samples.insert((String::new(), 8080), sample("nginx", 1.5));
+    let mut remote = sample("api", 50.0);
+    remote.host = Some("web-1:7070".to_string());
+    remote.port = 9000;
+    samples.insert(("web-1:7070".to_string(), 9000), remote);
+
+    let text = prometheus::render(&samples);
+    assert!(text.contains("# TYPE crabtop_cpu_percent gauge\n"));
+    assert!(text.contains("crabtop_cpu_percent{port=\"8080\",process=\"nginx\"} 1.5\n"));
+    assert!(text.contains("crabtop_memory_bytes{port=\"9000\",process=\"api\",host=\"web-1:7070\"} 1000\n"));
+}
+
+#[test]
+fn textfile_exporter_replaces_one_prom_file_per_target() {
+    let dir = std::env::temp_dir().join(format!("crabtop-textfile-{}", std::process::id()));
+    fs::create_dir_all(&dir).unwrap();
+    let mut exporter = prometheus::TextfileExporter::new(&dir).unwrap();
+
+    exporter.on_sample(&sample("nginx", 1.5)).unwrap();
+    exporter.on_sample(&sample("nginx", 3.0)).unwrap();
+    let mut remote = sample("api", 50.0);
+    remote.host = Some("web-1:7070".to_string());
+    exporter.on_sample(&remote).unwrap();
+
+    let mut names: Vec<String> = fs::read_dir(&dir)
+        .unwrap()
+        .map(|entry| entry.unwrap().file_name().to_string_lossy().into_owned())
+        .collect();
+    names.sort();
+    assert_eq!(names, ["crabtop_8080.prom", "crabtop_web_1_7070_8080.prom"]);
+
+    let local = fs::read_to_string(dir.join("crabtop_8080.prom")).unwrap();
+    assert!(local.contains("crabtop_cpu_percent{port=\"8080\",process=\"nginx\"} 3\n"), "{}", local);
+    assert!(!local.contains("api"), "{}", local);
+    fs::remove_dir_all(&dir).unwrap();
+
+    assert!(prometheus::TextfileExporter::new(&dir).is_err());
+}
+
+/// Every request an endpoint got: method, path, content type and body.
+type Received = Arc<Mutex<Vec<(String, String, String, Vec<u8>)>>>;
+
+async fn recording_endpoint() -> (String, Received) {
+    let received = Received::default();
+    let state = Arc::clone(&received);
+    let app = axum::Router::new().fallback(move |request: axum::extract::Request| {
+        let state = Arc::clone(&state);
+        async move {
+            let (parts, body) = request.into_parts();
+            let body = axum::body::to_bytes(body, usize::MAX).await.unwrap();
+            let content_type = parts.headers.get("content-type").map(|v| v.to_str().unwrap().to_string()).unwrap_or_default();
+            state.lock().unwrap().push((parts.method.to_string(), parts.uri.path().to_string(), content_type, body.to_vec()));
+        }
+    });
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let url = format!("http://{}", listener.local_addr().unwrap());
+    tokio::spawn(async move { axum::serve(listener, app).await });
+    (url, received)
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn pushes_reach_the_gateway_or_remote_write_before_shutdown_returns() {
+    let (url, received) = recording_endpoint().await;
+
+    let mut gateway = PushExporter::new(&format!("{}/", url), PushProtocol::for_url(&url)).unwrap();
+    let mut remote = sample("api", 50.0);
+    remote.host = Some("web-1:7070".to_string());
+    gateway.on_sample(&sample("nginx", 1.5)).unwrap();
+    gateway.on_sample(&remote).unwrap();
+    gateway.on_shutdown().unwrap();
+
+    let write_url = format!("{}/api/v1/write", url);
+    assert_eq!(PushProtocol::for_url(&write_url), PushProtocol::RemoteWrite);
+    let mut writer = PushExporter::new(&write_url, PushProtocol::RemoteWrite).unwrap();
+    let pushed = sample("nginx", 1.5);
+    writer.on_sample(&pushed).unwrap();
+    writer.on_shutdown().unwrap();
+
+    let received = received.lock().unwrap();
+    assert_eq!(received.len(), 3);
+    let (method, path, content_type, body) = &received[0];
+    assert_eq!((method.as_str(), path.as_str()), ("PUT", "/metrics/job/crabtop/port/8080"));
+    assert_eq!(content_type, "text/plain; version=0.0.4");
+    assert!(String::from_utf8_lossy(body).contains("crabtop_cpu_percent{port=\"8080\",process=\"nginx\"} 1.5\n"));
+    assert_eq!(received[1].1, "/metrics/job/crabtop/port/8080/host/web-1:7070");
+
+    let (method, path, content_type, body) = &received[2];
+    assert_eq!((method.as_str(), path.as_str(), content_type.as_str()), ("POST", "/api/v1/write", "application/x-protobuf"));
+    assert_eq!(body, &prometheus::snappy_block(&prometheus::write_request(&pushed)));
+}
+
+#[test]
+fn a_queued_worker_drops_samples_once_full_and_gives_up_on_a_stuck_sink() {
+    let (release, stuck) = std::sync::mpsc::channel::<()>();
+    let (took, taken) = std::sync::mpsc::channel::<()>();
+    let mut worker = QueuedWorker::spawn("test", "the sink", move |samples, _| {
+        // Takes one sample, then hangs like a server that stopped answering.
+        let _ = samples.recv();
+        let _ = took.send(());
+        let _ = stuck.recv();
+    })
+    .unwrap();
+    worker.send(&sample("nginx", 1.0)).unwrap();
+    taken.recv().unwrap();
+    let queued = (0..20).take_while(|_| worker.send(&sample("nginx", 1.0)).is_ok()).count();
+    assert_eq!(queued, 16);
+    assert_eq!(worker.send(&sample("nginx", 1.0)).unwrap_err().to_string(), "the sink is falling behind; a sample was dropped");
+
+    let started = std::time::Instant::now();
+    let drained = worker.drain().unwrap_err();
+    assert!(started.elapsed() >= WORKER_DRAIN && started.elapsed() < WORKER_DRAIN * 2);
+    assert_eq!(drained.to_string(), "the last samples weren't sent within 5s");
+    drop(release);
+}
+
+#[test]
+fn remote_write_bodies_are_protobuf_in_a_snappy_literal() {
+    assert_eq!(prometheus::snappy_block(b"abc"), [3, 2 << 2, b'a', b'b', b'c']);
+    let long = vec![7u8; 300];
+    let block = prometheus::snappy_block(&long);
+    // Length 300 as a varint, then a literal whose length-1 (299) takes two bytes.
+    assert_eq!(&block[..5], &[0xac, 0x02, 61 << 2, 0x2b, 0x01]);
+    assert_eq!(block.len(), 305);
+
+    let request = prometheus::write_request(&sample("nginx", 1.5));
+    // The first series starts with its `__name__` label, before any other.
+    let name_label = b"\x0a\x08__name__\x12\x13crabtop_cpu_percent";
+    assert_eq!(&request[2..4], &[0x0a, name_label.len() as u8]);
+    assert_eq!(&request[4..4 + name_label.len()], name_label);
+    assert!(request.windows(b"\x0a\x03job\x12\x07crabtop".len()).any(|w| w == b"\x0a\x03job\x12\x07crabtop"));
+}
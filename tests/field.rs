@@ -0,0 +1,53 @@
+//! `--field` values: bare, one per field, empty when missing.
+
+use std::path::PathBuf;
+
+use crabtop::field::{parse_field, Field};
+use crabtop::{Listener, ProcessInfo};
+
+fn api() -> ProcessInfo {
+    ProcessInfo {
+        name: "api".to_string(),
+        pid: 4242,
+        cpu_percent: 12.345,
+        memory_bytes: 512_400_000,
+        shared_bytes: Some(100_000_000),
+        user: Some("www".to_string()),
+        cmdline: vec!["/usr/bin/api".to_string(), "--port".to_string(), "8080".to_string()],
+        exe: Some(PathBuf::from("/usr/bin/api")),
+        uptime_secs: 3_600,
+        ..Default::default()
+    }
+}
+
+#[test]
+fn field_names_parse_with_either_separator() {
+    assert_eq!(parse_field("mem_mb"), Ok(Field::MemMb));
+    assert_eq!(parse_field("MEM-MB"), Ok(Field::MemMb));
+    assert_eq!(parse_field("cpu"), Ok(Field::Cpu));
+    let err = parse_field("memory").unwrap_err();
+    assert!(err.starts_with("unknown field `memory` (one of port, pid,"), "{}", err);
+    assert_eq!(Field::StartTime.to_string(), "start_time");
+}
+
+#[test]
+fn values_are_bare_and_missing_ones_empty() {
+    let info = api();
+    let listeners = [Listener {
+        address: "0.0.0.0".to_string(),
+        port: 8080,
+    }];
+    let value = |field| Field::value(&field, 8080, &[4242, 4243], &info, &listeners);
+    assert_eq!(value(Field::Cpu), "12.35");
+    assert_eq!(value(Field::Mem), "512400000");
+    assert_eq!(value(Field::MemMb), "512");
+    assert_eq!(value(Field::SharedMb), "100");
+    assert_eq!(value(Field::Pids), "4242 4243");
+    assert_eq!(value(Field::Cmdline), "/usr/bin/api --port 8080");
+    assert_eq!(value(Field::Listeners), "0.0.0.0:8080");
+    assert_eq!(value(Field::Uptime), "3600");
+    // Not reported on this platform (or by this source).
+    assert_eq!(value(Field::SwapMb), "");
+    assert_eq!(value(Field::VirtualMb), "");
+    assert_eq!(value(Field::Cwd), "");
+}
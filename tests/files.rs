@@ -0,0 +1,38 @@
+use crabtop::files::{parse_fd_listing, FileKind, FileListing, OpenFile, PAGE_SIZE};
+
+#[test]
+fn classifies_descriptors_and_keeps_sizes_of_regular_files_only() {
+    let text = "10\t0\tsocket:[4521]\n2\t1048576\t/var/log/app/error.log\n\
+                0\t\t/dev/null\n7\t0\tanon_inode:[eventpoll]\n3\t0\tpipe:[881]\nbogus line\n";
+    let files = parse_fd_listing(text);
+    let kinds: Vec<(u32, FileKind)> = files.iter().map(|f| (f.fd, f.kind)).collect();
+    assert_eq!(
+        kinds,
+        vec![
+            (0, FileKind::Device),
+            (2, FileKind::File),
+            (3, FileKind::Pipe),
+            (7, FileKind::Anon),
+            (10, FileKind::Socket),
+        ]
+    );
+    assert_eq!(files[1].size, Some(1_048_576));
+    assert!(files.iter().filter(|f| f.kind != FileKind::File).all(|f| f.size.is_none()));
+}
+
+#[test]
+fn pages_stay_in_range() {
+    let files: Vec<OpenFile> = (0..PAGE_SIZE as u32 * 2 + 1)
+        .map(|fd| OpenFile::new(fd, format!("/tmp/{}", fd), Some(0)))
+        .collect();
+    let mut listing = FileListing::new(files);
+    assert_eq!(listing.pages(), 3);
+    listing.previous_page();
+    assert_eq!(listing.page, 0);
+    for _ in 0..5 {
+        listing.next_page();
+    }
+    assert_eq!(listing.page, 2);
+    assert_eq!(listing.current().len(), 1);
+    assert_eq!(FileListing::new(Vec::new()).pages(), 1);
+}
@@ -0,0 +1,69 @@
+//! Protocol detection from banners and probe replies.
+
+use crabtop::fingerprint::{banner, classify_banner, classify_reply, detect, Protocol};
+use std::time::Duration;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::TcpListener;
+
+#[test]
+fn recognises_server_greetings() {
+    let ssh = classify_banner(b"SSH-2.0-OpenSSH_9.6p1 Ubuntu-3ubuntu13\r\n").unwrap();
+    assert_eq!(ssh.to_string(), "SSH (OpenSSH_9.6p1)");
+
+    let mut mysql = vec![0x4a, 0x00, 0x00, 0x00, 0x0a];
+    mysql.extend_from_slice(b"8.0.36\0");
+    mysql.resize(0x4a + 4, 0);
+    assert_eq!(classify_banner(&mysql).unwrap().to_string(), "MySQL (8.0.36)");
+
+    assert_eq!(classify_banner(b"220 mail.example.com ESMTP Postfix\r\n").unwrap().protocol, Protocol::Smtp);
+    assert_eq!(classify_banner(b"220 (vsFTPd 3.0.5)\r\n").unwrap().protocol, Protocol::Ftp);
+    assert_eq!(classify_banner(b"* OK [CAPABILITY IMAP4rev1] Dovecot ready.\r\n").unwrap().protocol, Protocol::Imap);
+    assert!(classify_banner(b"").is_none());
+    assert!(classify_banner(b"hello\n").is_none());
+}
+
+#[test]
+fn recognises_replies_to_the_http_probe() {
+    let http = classify_reply(b"HTTP/1.1 405 Not Allowed\r\nServer: nginx/1.24.0\r\nContent-Length: 0\r\n\r\n").unwrap();
+    assert_eq!(http.to_string(), "HTTP (HTTP/1.1, nginx/1.24.0)");
+
+    let redis = classify_reply(b"-ERR unknown command 'OPTIONS', with args beginning with: '/' 'HTTP/1.0' \r\n").unwrap();
+    assert_eq!(redis.protocol, Protocol::Redis);
+    assert_eq!(classify_reply(b"-NOAUTH Authentication required.\r\n").unwrap().protocol, Protocol::Redis);
+    assert_eq!(classify_reply(b"ERROR\r\nERROR\r\n").unwrap().protocol, Protocol::Memcached);
+
+    // SETTINGS then GOAWAY, as an h2c (e.g. gRPC) server answers a bad preface.
+    let h2 = classify_reply(&[0, 0, 0, 4, 0, 0, 0, 0, 0, 0, 0, 8, 7, 0, 0, 0, 0, 0]).unwrap();
+    assert_eq!(h2.to_string(), "HTTP/2 (cleartext)");
+    assert_eq!(classify_reply(&[0x15, 0x03, 0x03, 0x00, 0x02, 0x02, 0x46]).unwrap().protocol, Protocol::Tls);
+    assert!(classify_reply(b"").is_none());
+}
+
+#[tokio::test]
+async fn detects_a_banner_and_a_probe_reply() {
+    let ssh = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let ssh_addr = ssh.local_addr().unwrap();
+    tokio::spawn(async move {
+        while let Ok((mut tcp, _)) = ssh.accept().await {
+            let _ = tcp.write_all(b"SSH-2.0-dropbear_2022.83\r\n").await;
+        }
+    });
+    let found = detect(&ssh_addr.to_string(), Duration::from_secs(1)).await.unwrap();
+    assert_eq!(found.to_string(), "SSH (dropbear_2022.83)");
+    assert_eq!(banner(&ssh_addr.to_string(), Duration::from_secs(1)).await, Some(found));
+
+    let redis = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let redis_addr = redis.local_addr().unwrap();
+    tokio::spawn(async move {
+        while let Ok((mut tcp, _)) = redis.accept().await {
+            let mut buf = [0u8; 64];
+            if tcp.read(&mut buf).await.unwrap_or(0) > 0 {
+                let _ = tcp.write_all(b"-NOAUTH Authentication required.\r\n").await;
+            }
+        }
+    });
+    let found = detect(&redis_addr.to_string(), Duration::from_secs(1)).await.unwrap();
+    assert_eq!(found.protocol, Protocol::Redis);
+    // Redis waits to be spoken to, which only `detect` does.
+    assert_eq!(banner(&redis_addr.to_string(), Duration::from_secs(1)).await, None);
+}
@@ -0,0 +1,25 @@
+use crabtop::fleet::{parse_hosts_file, FleetTarget};
+
+#[test]
+fn hosts_file_lines_default_to_the_cli_port() {
+    let text = "# edge nodes\nweb-1:7070\n\n  web-2:7070 9090   # admin port\nhttps://db-1:7443 5432\n";
+    let targets = parse_hosts_file(text, 8080).unwrap();
+    let target = |addr: &str, port| FleetTarget {
+        addr: addr.to_string(),
+        port,
+    };
+    assert_eq!(
+        targets,
+        vec![
+            target("web-1:7070", 8080),
+            target("web-2:7070", 9090),
+            target("https://db-1:7443", 5432),
+        ]
+    );
+}
+
+#[test]
+fn hosts_file_errors_name_the_line() {
+    let err = parse_hosts_file("web-1:7070\nweb-2:7070 http\n", 8080).unwrap_err();
+    assert!(err.to_string().contains("line 2"), "{}", err);
+}
@@ -0,0 +1,35 @@
+//! Telling "another user's process holds the port" apart from "nothing does".
+
+use crabtop::port::{macos_netstat_lists, ss_hides_owner};
+
+#[test]
+fn ss_without_users_means_the_owner_is_hidden() {
+    let ss = "\
+State  Recv-Q Send-Q Local Address:Port Peer Address:Port Process
+LISTEN 0      128          0.0.0.0:22        0.0.0.0:*    users:((\"sshd\",pid=812,fd=3))
+LISTEN 0      511          0.0.0.0:80        0.0.0.0:*
+LISTEN 0      511             [::]:8080         [::]:*    users:((\"node\",pid=4242,fd=21))
+";
+    assert!(ss_hides_owner(ss, 80));
+    assert!(!ss_hides_owner(ss, 22));
+    assert!(!ss_hides_owner(ss, 8080));
+    // Nothing there at all isn't "hidden".
+    assert!(!ss_hides_owner(ss, 9090));
+    // :80 mustn't match :8080.
+    assert!(!ss_hides_owner("LISTEN 0 511 [::]:8080 [::]:*\n", 80));
+}
+
+#[test]
+fn macos_netstat_lists_listeners_lsof_cannot_see() {
+    let netstat = "\
+Active Internet connections (including servers)
+Proto Recv-Q Send-Q  Local Address          Foreign Address        (state)
+tcp4       0      0  *.5432                 *.*                    LISTEN
+tcp4       0      0  127.0.0.1.8080         127.0.0.1.52311        ESTABLISHED
+tcp6       0      0  ::1.6379               *.*                    LISTEN
+";
+    assert!(macos_netstat_lists(netstat, 5432));
+    assert!(macos_netstat_lists(netstat, 6379));
+    assert!(!macos_netstat_lists(netstat, 8080));
+    assert!(!macos_netstat_lists(netstat, 432));
+}
@@ -0,0 +1,147 @@
+//! PagerDuty and Opsgenie incidents: opened for exits and sustained
+//! breaches, and resolved when the condition clears.
+
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use chrono::Local;
+use crabtop::config::{self, ExporterConfig};
+use crabtop::export::{Alert, Exporter};
+use crabtop::incident::{dedup_key, opsgenie_request, pagerduty_event, Event, IncidentExporter, Incidents, Service};
+use crabtop::syslog::severity;
+
+fn alert(rule: &str, message: &str) -> Alert {
+    Alert {
+        timestamp: Local::now(),
+        host: None,
+        port: 5432,
+        rule: rule.to_string(),
+        severity: severity(rule),
+        message: message.to_string(),
+        value: 7.5,
+    }
+}
+
+fn rules(events: &[Event]) -> Vec<(&'static str, &str)> {
+    events
+        .iter()
+        .map(|event| match event {
+            Event::Trigger(alert) => ("trigger", alert.rule.as_str()),
+            Event::Resolve(alert) => ("resolve", alert.rule.as_str()),
+        })
+        .collect()
+}
+
+#[test]
+fn exits_page_at_once_and_breaches_only_once_sustained() {
+    let mut incidents = Incidents::new(Duration::from_secs(60));
+    let start = Instant::now();
+    let mut events = Vec::new();
+    events.extend(incidents.breach(&alert("process_restarted", "PID 1 replaced by 2"), start));
+    events.extend(incidents.breach(&alert("process_exited", "PID 2 exited"), start));
+    events.extend(incidents.breach(&alert("process_exited", "PID 2 exited"), start));
+    events.extend(incidents.breach(&alert("retransmits_high", "Retransmits at 7.5%"), start));
+    events.extend(incidents.breach(&alert("swap_started", "Started swapping"), start));
+    events.extend(incidents.due(start + Duration::from_secs(30)));
+    // Swapping stops before it has lasted a minute, so it never pages.
+    events.extend(incidents.recovery(&alert("swap_started", "Stopped swapping")));
+    events.extend(incidents.due(start + Duration::from_secs(60)));
+    events.extend(incidents.due(start + Duration::from_secs(90)));
+    events.extend(incidents.recovery(&alert("retransmits_high", "Retransmits back down to 0.5%")));
+    events.extend(incidents.recovery(&alert("process_exited", "PID 3 is running")));
+    events.extend(incidents.recovery(&alert("oom_at_risk", "No longer next in line")));
+
+    assert_eq!(
+        rules(&events),
+        vec![
+            ("trigger", "process_exited"),
+            ("trigger", "retransmits_high"),
+            ("resolve", "retransmits_high"),
+            ("resolve", "process_exited"),
+        ]
+    );
+}
+
+#[test]
+fn events_carry_one_key_per_host_port_and_rule() {
+    let mut remote = alert("oom_at_risk", "Next in line for the OOM killer");
+    remote.host = Some("db-1:7070".to_string());
+    assert_eq!(dedup_key(&remote, "box"), "crabtop/db-1:7070/5432/oom_at_risk");
+    assert_eq!(dedup_key(&alert("oom_at_risk", ""), "box"), "crabtop/box/5432/oom_at_risk");
+
+    let trigger = pagerduty_event("R0UT1NG", &Event::Trigger(remote.clone()), "box");
+    assert_eq!(trigger["event_action"], "trigger");
+    assert_eq!(trigger["routing_key"], "R0UT1NG");
+    assert_eq!(trigger["dedup_key"], "crabtop/db-1:7070/5432/oom_at_risk");
+    assert_eq!(trigger["payload"]["summary"], "db-1:7070 port 5432: Next in line for the OOM killer");
+    assert_eq!(trigger["payload"]["severity"], "error");
+    assert_eq!(trigger["payload"]["source"], "db-1:7070");
+    let resolve = pagerduty_event("R0UT1NG", &Event::Resolve(remote.clone()), "box");
+    assert_eq!(resolve["event_action"], "resolve");
+    assert_eq!(resolve["dedup_key"], trigger["dedup_key"]);
+    assert!(resolve.get("payload").is_none());
+
+    let (path, body) = opsgenie_request(&Event::Trigger(alert("process_exited", "PID 9 exited")), "box");
+    assert_eq!(path, "/v2/alerts");
+    assert_eq!(body["alias"], "crabtop/box/5432/process_exited");
+    assert_eq!(body["priority"], "P1");
+    assert_eq!(body["message"], "box port 5432: PID 9 exited");
+    let (path, body) = opsgenie_request(&Event::Resolve(remote), "box");
+    assert_eq!(path, "/v2/alerts/crabtop%2Fdb-1%3A7070%2F5432%2Foom_at_risk/close?identifierType=alias");
+    assert_eq!(body["note"], "Next in line for the OOM killer");
+}
+
+#[test]
+fn configured_with_keys_from_the_environment() {
+    let config =
+        config::parse("[[exporter]]\ntype = \"pagerduty\"\n\n[[exporter]]\ntype = \"opsgenie\"\nurl = \"https://api.eu.opsgenie.com\"\nsustain = \"5m\"\n")
+            .unwrap();
+    assert!(matches!(
+        &config.exporters[0],
+        ExporterConfig::Pagerduty { routing_key_env, url: None, sustain }
+            if routing_key_env == "PAGERDUTY_ROUTING_KEY" && *sustain == Duration::from_secs(60)
+    ));
+    assert!(matches!(
+        &config.exporters[1],
+        ExporterConfig::Opsgenie { api_key_env, url: Some(_), sustain }
+            if api_key_env == "OPSGENIE_API_KEY" && *sustain == Duration::from_secs(300)
+    ));
+}
+
+/// Every request the endpoint got: path with query, Authorization header and JSON body.
+type Received = Arc<Mutex<Vec<(String, String, serde_json::Value)>>>;
+
+#[tokio::test(flavor = "multi_thread")]
+async fn opsgenie_alerts_are_created_then_closed() {
+    let received = Received::default();
+    let state = Arc::clone(&received);
+    let app = axum::Router::new().fallback(move |request: axum::extract::Request| {
+        let state = Arc::clone(&state);
+        async move {
+            let (parts, body) = request.into_parts();
+            let body = axum::body::to_bytes(body, usize::MAX).await.unwrap();
+            let auth = parts.headers.get("authorization").map(|v| v.to_str().unwrap().to_string()).unwrap_or_default();
+            state.lock().unwrap().push((parts.uri.to_string(), auth, serde_json::from_slice(&body).unwrap()));
+        }
+    });
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let url = format!("http://{}", listener.local_addr().unwrap());
+    tokio::spawn(async move { axum::serve(listener, app).await });
+
+    let service = Service::Opsgenie {
+        api_key: "k3y".to_string(),
+    };
+    let mut exporter = IncidentExporter::new(service, Some(&url), Duration::ZERO);
+    exporter.on_alert(&alert("retransmits_high", "Retransmits at 7.5%")).unwrap();
+    exporter.on_recovery(&alert("retransmits_high", "Retransmits back down to 0.5%")).unwrap();
+    exporter.on_shutdown().unwrap();
+
+    let received = received.lock().unwrap();
+    assert_eq!(received.len(), 2);
+    let (path, auth, body) = &received[0];
+    assert_eq!((path.as_str(), auth.as_str()), ("/v2/alerts", "GenieKey k3y"));
+    assert_eq!(body["priority"], "P3");
+    let (path, _, body) = &received[1];
+    assert!(path.starts_with("/v2/alerts/crabtop%2F") && path.ends_with("%2F5432%2Fretransmits_high/close?identifierType=alias"), "{}", path);
+    assert_eq!(body["note"], "Retransmits back down to 0.5%");
+}
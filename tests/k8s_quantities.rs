@@ -0,0 +1,18 @@
+use crabtop::k8s::{parse_cpu_quantity, parse_memory_quantity};
+
+#[test]
+fn cpu_quantities_are_cores() {
+    assert_eq!(parse_cpu_quantity("250m"), Some(0.25));
+    assert_eq!(parse_cpu_quantity("2"), Some(2.0));
+    assert_eq!(parse_cpu_quantity("1.5"), Some(1.5));
+    assert_eq!(parse_cpu_quantity("lots"), None);
+}
+
+#[test]
+fn memory_quantities_are_bytes() {
+    assert_eq!(parse_memory_quantity("128Mi"), Some(128 * 1024 * 1024));
+    assert_eq!(parse_memory_quantity("1G"), Some(1_000_000_000));
+    assert_eq!(parse_memory_quantity("64Ki"), Some(64 * 1024));
+    assert_eq!(parse_memory_quantity("512000"), Some(512_000));
+    assert_eq!(parse_memory_quantity("Mi"), None);
+}
@@ -0,0 +1,68 @@
+//! The internal logger: levels, what it keeps, and the file it writes.
+
+use std::fs;
+use std::sync::Arc;
+
+use chrono::Local;
+use crabtop::logging::{level_for, Level, LogBuffer, LogLine, Logger, LOG_BUFFER_LEN};
+
+#[test]
+fn each_v_adds_a_level() {
+    assert_eq!(level_for(0), Level::WARN);
+    assert_eq!(level_for(1), Level::INFO);
+    assert_eq!(level_for(2), Level::DEBUG);
+    assert_eq!(level_for(3), Level::TRACE);
+    assert_eq!(level_for(7), Level::TRACE);
+}
+
+#[test]
+fn keeps_crabtop_lines_and_other_crates_warnings_in_the_buffer_and_file() {
+    let path = std::env::temp_dir().join(format!("crabtop-logging-{}.log", std::process::id()));
+    let _ = fs::remove_file(&path);
+    let logger = Arc::new(Logger::new(Level::DEBUG, Some(&path)).unwrap());
+    logger.set_stderr(false);
+
+    tracing::subscriber::with_default(logger.clone(), || {
+        tracing::debug!(target: "crabtop::port", "lsof: nothing for port {}", 8080);
+        tracing::trace!(target: "crabtop::exec", "ran `lsof`");
+        tracing::debug!(target: "hyper::proto", "flushed 12 bytes");
+        tracing::warn!(target: "h2::codec", stream = 7, "reset by peer");
+    });
+
+    let lines = logger.buffer().recent(10);
+    let logged: Vec<(Level, &str, &str)> = lines
+        .iter()
+        .map(|line| (line.level, line.target.as_str(), line.message.as_str()))
+        .collect();
+    assert_eq!(
+        logged,
+        [
+            (Level::DEBUG, "crabtop::port", "lsof: nothing for port 8080"),
+            (Level::WARN, "h2::codec", "reset by peer stream=7"),
+        ]
+    );
+
+    let written = fs::read_to_string(&path).unwrap();
+    let _ = fs::remove_file(&path);
+    assert_eq!(written.lines().count(), 2);
+    assert!(written.contains("DEBUG crabtop::port: lsof: nothing for port 8080"), "{}", written);
+    assert!(written.contains(" WARN h2::codec: reset by peer stream=7"), "{}", written);
+}
+
+#[test]
+fn the_buffer_drops_the_oldest_line_when_full() {
+    let buffer = LogBuffer::default();
+    for n in 0..=LOG_BUFFER_LEN {
+        buffer.push(LogLine {
+            timestamp: Local::now(),
+            level: Level::INFO,
+            target: "crabtop".to_string(),
+            message: format!("line {}", n),
+        });
+    }
+    assert_eq!(buffer.len(), LOG_BUFFER_LEN);
+    let recent = buffer.recent(2);
+    assert_eq!(recent[0].message, format!("line {}", LOG_BUFFER_LEN - 1));
+    assert_eq!(recent[1].message, format!("line {}", LOG_BUFFER_LEN));
+    assert_eq!(buffer.recent(LOG_BUFFER_LEN * 2)[0].message, "line 1");
+}
@@ -0,0 +1,33 @@
+use std::fs;
+
+use crabtop::logs::{last_lines, tail_file, LogSource};
+
+#[test]
+fn tails_the_end_of_a_large_file() {
+    let path = std::env::temp_dir().join(format!("crabtop-log-{}.log", std::process::id()));
+    let text: String = (0..5_000).map(|n| format!("2024-05-01T12:00:00 request {} took 3ms\n", n)).collect();
+    fs::write(&path, text).unwrap();
+
+    let lines = tail_file(&path, 3).unwrap();
+    assert_eq!(
+        lines,
+        vec![
+            "2024-05-01T12:00:00 request 4997 took 3ms",
+            "2024-05-01T12:00:00 request 4998 took 3ms",
+            "2024-05-01T12:00:00 request 4999 took 3ms",
+        ]
+    );
+    // Asking for more than the look-back window holds never yields a torn first line.
+    let many = tail_file(&path, 100_000).unwrap();
+    assert!(many[0].starts_with("2024-05-01T12:00:00 request "));
+    fs::remove_file(&path).unwrap();
+    assert!(tail_file(&path, 3).is_err());
+}
+
+#[test]
+fn keeps_the_last_non_empty_lines() {
+    assert_eq!(last_lines("a\n\nb\nc\n\n", 2), vec!["b", "c"]);
+    assert_eq!(last_lines("only\n", 10), vec!["only"]);
+    let journal = LogSource::Journal { unit: None, pid: 4242 };
+    assert_eq!(journal.describe(), "journalctl _PID=4242");
+}
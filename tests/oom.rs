@@ -0,0 +1,50 @@
+//! OOM-score ranking and memory PSI parsing.
+
+use crabtop::oom::{parse_pressure, MemoryPressure, OomStatus};
+
+#[test]
+fn parses_memory_pressure() {
+    let pressure = parse_pressure(
+        "some avg10=12.50 avg60=4.01 avg300=1.00 total=123456\nfull avg10=0.75 avg60=0.20 avg300=0.05 total=2345\n",
+    )
+    .unwrap();
+    assert_eq!(
+        pressure,
+        MemoryPressure {
+            some_avg10: 12.5,
+            some_avg60: 4.01,
+            full_avg10: 0.75,
+            full_avg60: 0.2,
+        }
+    );
+    assert!(pressure.is_high());
+    assert!(parse_pressure("").is_none());
+}
+
+#[test]
+fn the_highest_score_is_the_next_victim() {
+    let calm = MemoryPressure::default();
+    let scores = [2, 0, 667, 812, 300];
+
+    let top = OomStatus::from_parts(812, 0, &scores, Some(calm));
+    assert_eq!((top.rank, top.processes), (1, 5));
+    assert!(top.is_next_victim());
+    assert!(!top.at_risk(), "no pressure, so not an immediate risk");
+
+    let squeezed = OomStatus::from_parts(812, 0, &scores, Some(MemoryPressure { full_avg10: 3.0, ..calm }));
+    assert!(squeezed.at_risk());
+
+    let second = OomStatus::from_parts(667, 0, &scores, None);
+    assert_eq!(second.rank, 2);
+    assert!(!second.is_next_victim());
+
+    let exempt = OomStatus::from_parts(0, -1000, &[0, 0], None);
+    assert!(!exempt.is_next_victim());
+}
+
+#[cfg(target_os = "linux")]
+#[test]
+fn reads_our_own_score() {
+    let status = crabtop::oom::oom_status(std::process::id()).unwrap();
+    assert!(status.rank >= 1 && status.rank <= status.processes);
+}
@@ -0,0 +1,47 @@
+use crabtop::pattern::Pattern;
+
+fn matches(pattern: &str, text: &str) -> bool {
+    Pattern::new(pattern).unwrap().is_match(text)
+}
+
+#[test]
+fn finds_literals_and_anchors() {
+    assert!(matches("worker", "gunicorn: worker [app]"));
+    assert!(!matches("^worker", "gunicorn: worker [app]"));
+    assert!(matches(r"\[app\]$", "gunicorn: worker [app]"));
+    assert!(matches("", "anything"));
+    assert!(matches("celery|gunicorn", "python -m gunicorn app:wsgi"));
+}
+
+#[test]
+fn handles_classes_escapes_and_repeats() {
+    assert!(matches(r"--bind[= ]\S+:80\d{2}$", "gunicorn --bind 0.0.0.0:8000"));
+    assert!(matches("app-[0-9]+$", "node server.js app-42"));
+    assert!(!matches("app-[0-9]+$", "node server.js app-42x"));
+    assert!(matches("^(uvicorn|gunicorn) .*main:app", "uvicorn --port 9000 main:app"));
+    assert!(matches("a{2,3}b", "caaab"));
+    assert!(!matches("^a{2,3}b", "aaaab"));
+    assert!(matches("[^ ]+\\.py", "python3 manage.py runserver"));
+    assert!(matches("colou?r", "color"));
+    assert!(matches("(a*)*b", "aaab"));
+}
+
+#[test]
+fn rejects_malformed_patterns() {
+    for bad in ["(worker", "worker)", "[abc", "*x", "a{x}", "a{3,2}", "a{1001}", "(a{1000}){1000}", "trailing\\", r"\bword"] {
+        assert!(Pattern::new(bad).is_err(), "{} should not parse", bad);
+    }
+}
+
+#[test]
+fn nested_repeats_take_linear_time() {
+    let run = "a".repeat(10_000);
+    assert!(!matches("(a*)*b", &run));
+    assert!(matches("(a*)*b", &format!("{}b", run)));
+
+    let jvm = format!("java {}-jar app.jar", "-Dprop=value ".repeat(500));
+    assert!(!matches(r"(\S+ )*worker", &jvm));
+    assert!(matches(r"^(\S+ )*-jar app\.jar$", &jvm));
+    assert!(matches("^(a|ab)(c|bcd)(d*)$", "abcd"));
+    assert!(matches("^x{2}y{0,2}$", "xxyy") && !matches("^x{2}y{0,2}$", "xxyyy"));
+}
@@ -0,0 +1,152 @@
+//! Peers of the port's connections, their traffic, and what
+//! `--resolve-peers` and `--geoip` add to them.
+
+use std::net::IpAddr;
+use std::time::{Duration, Instant};
+
+use crabtop::geoip::{GeoInfo, GeoIp};
+use crabtop::peers::{is_internal, open_peers, parse_hostname, peer_address, top_peers, PeerResolver, PeerTraffic, DEFAULT_PEER_ROWS};
+use crabtop::port::{parse_connection_bytes, ConnectionBytes, PortConnection};
+
+fn ip(text: &str) -> IpAddr {
+    text.parse().unwrap()
+}
+
+fn connection(key: &str, open: bool) -> PortConnection {
+    PortConnection {
+        key: key.to_string(),
+        open,
+    }
+}
+
+#[test]
+fn peer_addresses_come_canonical_out_of_ss_keys() {
+    assert_eq!(peer_address("10.0.0.2:8080->8.8.8.8:51234"), Some(ip("8.8.8.8")));
+    assert_eq!(peer_address("[::ffff:10.0.0.2]:8080->[::ffff:10.0.0.7]:51234"), Some(ip("10.0.0.7")));
+    assert_eq!(peer_address("[fe80::2%eth0]:22->[fe80::1%eth0]:40000"), Some(ip("fe80::1")));
+    assert_eq!(peer_address("[::]:8080->[2a00:1450::1]:443"), Some(ip("2a00:1450::1")));
+    assert_eq!(peer_address("0.0.0.0:8080"), None);
+    assert_eq!(peer_address("10.0.0.2:8080->*:*"), None);
+}
+
+#[test]
+fn open_connections_are_counted_per_peer() {
+    let connections = [
+        connection("10.0.0.2:8080->10.0.0.7:1", true),
+        connection("10.0.0.2:8080->8.8.8.8:2", true),
+        connection("10.0.0.2:8080->10.0.0.7:3", true),
+        connection("10.0.0.2:8080->81.2.69.160:4", false),
+        connection("[::ffff:10.0.0.2]:8080->[::ffff:8.8.8.8]:5", true),
+        connection("10.0.0.2:8080->1.1.1.1:6", true),
+    ];
+    assert_eq!(open_peers(&connections), vec![(ip("8.8.8.8"), 2), (ip("10.0.0.7"), 2), (ip("1.1.1.1"), 1)]);
+}
+
+#[test]
+fn private_loopback_and_link_local_addresses_are_internal() {
+    for internal in ["127.0.0.1", "10.1.2.3", "172.16.0.1", "192.168.1.1", "169.254.0.1", "100.64.0.1", "::1", "fd00::1", "fe80::1", "::ffff:10.0.0.1"] {
+        assert!(is_internal(ip(internal)), "{}", internal);
+    }
+    for external in ["8.8.8.8", "100.128.0.1", "172.32.0.1", "2a00:1450::1"] {
+        assert!(!is_internal(ip(external)), "{}", external);
+    }
+}
+
+#[test]
+fn hostnames_from_getent_and_host() {
+    assert_eq!(parse_hostname("8.8.8.8         dns.google\n"), Some("dns.google".to_string()));
+    assert_eq!(
+        parse_hostname("8.8.8.8.in-addr.arpa domain name pointer dns.google.\n"),
+        Some("dns.google".to_string())
+    );
+    assert_eq!(parse_hostname(""), None);
+    assert_eq!(parse_hostname("10.0.0.7\n"), None);
+}
+
+#[test]
+fn countries_and_networks_from_a_maxmind_database() {
+    let geoip = GeoIp::open(&["tests/fixtures/peers.mmdb"]).unwrap();
+    assert_eq!(
+        geoip.lookup(ip("8.8.8.8")),
+        GeoInfo {
+            country: Some("US".to_string()),
+            asn: Some(15169),
+            organization: Some("GOOGLE".to_string()),
+        }
+    );
+    assert_eq!(geoip.lookup(ip("81.2.69.160")).country.as_deref(), Some("GB"));
+    // The registered country stands in when there is no other.
+    let ie = geoip.lookup(ip("2a00:1450::1"));
+    assert_eq!((ie.country.as_deref(), ie.asn), (Some("IE"), Some(15169)));
+    assert!(geoip.lookup(ip("10.0.0.7")).is_empty());
+    assert!(geoip.lookup(ip("::ffff:8.8.4.4")).is_empty());
+    assert!(GeoIp::open(&["Cargo.toml"]).is_err());
+}
+
+#[test]
+fn only_the_top_peers_are_listed_and_looked_up() {
+    let connections: Vec<PortConnection> = (1..=12)
+        .flat_map(|n| (0..n).map(move |c| connection(&format!("10.0.0.2:8080->10.0.0.{}:{}", n, 1000 + c), true)))
+        .chain((0..30).map(|c| connection(&format!("10.0.0.2:8080->8.8.8.8:{}", 2000 + c), true)))
+        .collect();
+    let mut peers = top_peers(&connections, DEFAULT_PEER_ROWS);
+    assert_eq!(peers.len(), DEFAULT_PEER_ROWS);
+    assert_eq!((peers[0].address, peers[0].connections), (ip("8.8.8.8"), 30));
+    assert_eq!((peers[1].address, peers[1].connections), (ip("10.0.0.12"), 12));
+
+    let geoip = GeoIp::open(&["tests/fixtures/peers.mmdb"]).unwrap();
+    let mut resolver = PeerResolver::new(false, Some(geoip)).unwrap();
+    resolver.enrich(&mut peers);
+    assert_eq!(peers[0].geo.country.as_deref(), Some("US"));
+    assert!(peers[0].hostname.is_none() && !peers[0].is_internal());
+    assert!(peers[1].is_internal() && peers[1].geo.is_empty());
+}
+
+#[test]
+fn connection_bytes_from_tcp_info() {
+    let ss = "\
+0      0      10.0.0.2:8080      8.8.8.8:51234
+\t cubic wscale:7,7 rto:204 rtt:0.5/0.25 bytes_sent:1200 bytes_acked:1201 bytes_received:640 segs_out:12
+0      0      10.0.0.2:8080      10.0.0.7:40000
+\t cubic rto:204 rtt:0.1/0.05 bytes_acked:1 bytes_received:77
+0      0      10.0.0.2:9090      10.0.0.7:40001
+\t cubic bytes_sent:5 bytes_received:5
+0      0      10.0.0.2:8080      10.0.0.9:40002
+\t cubic rto:204
+";
+    assert_eq!(
+        parse_connection_bytes(ss, 8080),
+        vec![
+            ConnectionBytes {
+                key: "10.0.0.2:8080->8.8.8.8:51234".to_string(),
+                sent: 1200,
+                received: 640,
+            },
+            ConnectionBytes {
+                key: "10.0.0.2:8080->10.0.0.7:40000".to_string(),
+                sent: 1,
+                received: 77,
+            },
+        ]
+    );
+}
+
+#[test]
+fn traffic_is_summed_per_peer_over_the_interval() {
+    let bytes = |peer: &str, sent, received| ConnectionBytes {
+        key: format!("10.0.0.2:8080->{}", peer),
+        sent,
+        received,
+    };
+    let start = Instant::now();
+    let mut traffic = PeerTraffic::default();
+    assert!(traffic.update(&[bytes("8.8.8.8:1", 500, 500)], start).is_none());
+    let rates = traffic
+        .update(&[bytes("8.8.8.8:1", 1_500, 2_500), bytes("8.8.8.8:2", 1_000, 0), bytes("10.0.0.7:3", 0, 0)], start + Duration::from_secs(2))
+        .unwrap();
+    assert_eq!(rates.get(&ip("8.8.8.8")), Some(&2_000.0));
+    assert_eq!(rates.get(&ip("10.0.0.7")), Some(&0.0));
+
+    traffic.reset();
+    assert!(traffic.update(&[bytes("8.8.8.8:1", 9_000, 9_000)], start + Duration::from_secs(3)).is_none());
+}
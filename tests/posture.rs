@@ -0,0 +1,102 @@
+//! The security posture of the port's process, and when watch sessions read it.
+
+use crabtop::posture::{Confinement, Exposure, Posture};
+use crabtop::watch::{Tab, WatchSession};
+use crabtop::{ProcessInfo, ScriptedSource};
+
+const ROOT_STATUS: &str = "\
+Name:\tnginx
+Umask:\t0022
+State:\tS (sleeping)
+Uid:\t0\t0\t0\t0
+Gid:\t0\t0\t0\t0
+CapInh:\t0000000000000000
+CapPrm:\t000001ffffffffff
+CapEff:\t000001ffffffffff
+CapBnd:\t000001ffffffffff
+NoNewPrivs:\t0
+";
+
+const WORKER_STATUS: &str = "\
+Name:\tnode
+Uid:\t1000\t33\t33\t33
+Gid:\t33\t33\t33\t33
+CapEff:\t0000000000003400
+";
+
+#[test]
+fn exposure_of_listening_addresses() {
+    assert_eq!(Exposure::of("0.0.0.0"), Exposure::Everywhere);
+    assert_eq!(Exposure::of("[::]"), Exposure::Everywhere);
+    assert_eq!(Exposure::of("*"), Exposure::Everywhere);
+    assert_eq!(Exposure::of("127.0.0.1"), Exposure::Loopback);
+    assert_eq!(Exposure::of("127.0.0.53%lo"), Exposure::Loopback);
+    assert_eq!(Exposure::of("[::1]"), Exposure::Loopback);
+    assert_eq!(Exposure::of("10.0.0.2"), Exposure::Interface);
+    assert!(Exposure::Everywhere > Exposure::Interface && Exposure::Interface > Exposure::Loopback);
+}
+
+#[test]
+fn user_and_capabilities_from_the_status_file() {
+    let root = Posture::from_parts(ROOT_STATUS, None, None, Some("/usr/sbin/nginx".to_string()), Some(0o755)).unwrap();
+    assert!(root.runs_as_root() && root.has_all_capabilities());
+    assert_eq!(root.capability_names().len(), 41);
+    assert_eq!(root.exe_world_writable(), Some(false));
+
+    // The effective UID counts, not the real one.
+    let worker = Posture::from_parts(WORKER_STATUS, None, None, None, Some(0o777)).unwrap();
+    assert_eq!(worker.uid, 33);
+    assert!(!worker.runs_as_root() && !worker.has_all_capabilities());
+    assert_eq!(worker.capability_names(), ["net_bind_service", "net_admin", "net_raw"]);
+    assert_eq!(worker.exe_world_writable(), Some(true));
+    assert_eq!(Posture::from_parts(WORKER_STATUS, None, None, None, None).unwrap().exe_world_writable(), None);
+
+    assert!(Posture::from_parts("Name:\tnode\n", None, None, None, None).is_none());
+}
+
+#[test]
+fn apparmor_and_selinux_labels() {
+    let parse = Confinement::parse;
+    assert_eq!(parse(Some("nginx (enforce)\n"), Some("nginx (enforce)\n")), Confinement::AppArmor("nginx (enforce)".to_string()));
+    assert_eq!(parse(None, Some("docker-default (enforce)")), Confinement::AppArmor("docker-default (enforce)".to_string()));
+    assert_eq!(
+        parse(None, Some("system_u:system_r:httpd_t:s0\0")),
+        Confinement::SeLinux("system_u:system_r:httpd_t:s0".to_string())
+    );
+    assert_eq!(parse(Some(""), None), Confinement::Unknown);
+    assert_eq!(parse(None, Some("kernel")), Confinement::Unknown);
+
+    assert!(parse(Some("nginx (enforce)"), None).is_confined());
+    assert!(parse(None, Some("system_u:system_r:httpd_t:s0")).is_confined());
+    assert!(!parse(Some("unconfined"), None).is_confined());
+    assert!(!parse(Some("nginx (complain)"), None).is_confined());
+    assert!(!parse(None, Some("unconfined_u:unconfined_r:unconfined_t:s0-s0:c0.c1023")).is_confined());
+    assert!(!Confinement::Unknown.is_confined());
+}
+
+#[cfg(target_os = "linux")]
+#[test]
+fn reads_our_own_posture() {
+    let posture = crabtop::posture::security_posture(std::process::id()).unwrap();
+    assert_eq!(posture.exe.as_deref(), std::env::current_exe().ok().and_then(|p| p.to_str().map(str::to_string)).as_deref());
+    assert!(posture.exe_mode.is_some());
+}
+
+#[tokio::test]
+async fn read_for_a_process_watched_without_a_port() {
+    let root = Posture::from_parts("Uid:\t0\t0\t0\t0\n", None, None, None, None).unwrap();
+    let source = ScriptedSource::new()
+        .push_sample(ProcessInfo {
+            pid: 4242,
+            ..Default::default()
+        })
+        .push_posture(root);
+
+    // `--pid`: no port whose sockets could be read, but the process's
+    // privileges are still its own.
+    let mut session = WatchSession::with_pids(source, 0, vec![4242]).unwrap();
+    session.show_tab(Tab::Network);
+    session.tick().await.unwrap();
+    assert!(session.sockets.is_none());
+    assert!(session.posture.as_ref().unwrap().runs_as_root());
+}
@@ -0,0 +1,82 @@
+use crabtop::power::PowerMeter;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
+
+/// A fake powercap tree with one package and a subzone that must be ignored,
+/// plus a four-core /proc/stat.
+fn fixture(name: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("crabtop-power-{}-{}", name, std::process::id()));
+    let _ = fs::remove_dir_all(&dir);
+    for (zone, label) in [("intel-rapl:0", "package-0"), ("intel-rapl:0:0", "core")] {
+        let zone = dir.join("powercap").join(zone);
+        fs::create_dir_all(&zone).unwrap();
+        fs::write(zone.join("name"), format!("{}\n", label)).unwrap();
+        fs::write(zone.join("max_energy_range_uj"), "262143328850\n").unwrap();
+        fs::write(zone.join("energy_uj"), "1000000\n").unwrap();
+    }
+    write_stat(&dir, 0, 1000);
+    dir
+}
+
+fn write_energy(dir: &Path, zone: &str, uj: u64) {
+    fs::write(dir.join("powercap").join(zone).join("energy_uj"), format!("{}\n", uj)).unwrap();
+}
+
+// `busy` and `idle` jiffies, spread over four cores.
+fn write_stat(dir: &Path, busy: u64, idle: u64) {
+    let mut text = format!("cpu  {} 0 0 {} 0 0 0 0 0 0\n", busy, idle);
+    for core in 0..4 {
+        text.push_str(&format!("cpu{} {} 0 0 {} 0 0 0 0 0 0\n", core, busy / 4, idle / 4));
+    }
+    fs::write(dir.join("stat"), text).unwrap();
+}
+
+#[test]
+fn process_gets_its_share_of_package_power() {
+    let dir = fixture("share");
+    let mut meter = PowerMeter::from_paths(&dir.join("powercap"), &dir.join("stat")).unwrap();
+    let start = Instant::now();
+    meter.update_at(start, 0.0);
+    assert_eq!(meter.process_watts, None);
+
+    // 40 W for a second while the host kept two of its four cores busy,
+    // and the process used one of them.
+    write_energy(&dir, "intel-rapl:0", 41_000_000);
+    // The subzone is part of the package already; counting it would double up.
+    write_energy(&dir, "intel-rapl:0:0", 999_000_000);
+    write_stat(&dir, 200, 1200);
+    meter.update_at(start + Duration::from_secs(1), 100.0);
+
+    assert!((meter.package_watts.unwrap() - 40.0).abs() < 1e-9);
+    assert!((meter.process_watts.unwrap() - 20.0).abs() < 1e-9);
+    assert!((meter.process_joules - 20.0).abs() < 1e-9);
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn counter_wraparound_does_not_spike() {
+    let dir = fixture("wrap");
+    let max = 262_143_328_850;
+    write_energy(&dir, "intel-rapl:0", max - 5_000_000);
+    let mut meter = PowerMeter::from_paths(&dir.join("powercap"), &dir.join("stat")).unwrap();
+    let start = Instant::now();
+    meter.update_at(start, 400.0);
+    write_energy(&dir, "intel-rapl:0", 5_000_000);
+    write_stat(&dir, 400, 1000);
+    meter.update_at(start + Duration::from_secs(2), 400.0);
+
+    assert!((meter.package_watts.unwrap() - 5.0).abs() < 1e-9);
+    // The process was all of the host's CPU time, so all of the power.
+    assert!((meter.process_joules - 10.0).abs() < 1e-9);
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn no_meter_without_readable_packages() {
+    let dir = fixture("missing");
+    assert!(PowerMeter::from_paths(&dir.join("nowhere"), &dir.join("stat")).is_none());
+    fs::remove_file(dir.join("powercap/intel-rapl:0/energy_uj")).unwrap();
+    assert!(PowerMeter::from_paths(&dir.join("powercap"), &dir.join("stat")).is_none());
+    fs::remove_dir_all(&dir).unwrap();
+}
@@ -0,0 +1,57 @@
+//! Connect-latency probe against real loopback sockets.
+
+use std::time::Duration;
+
+use crabtop::probe::{HttpCheck, LatencyHistory, Probe};
+use tokio::net::TcpListener;
+
+#[tokio::test]
+async fn probes_record_successes_and_failures() {
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    let mut probe = Probe::resolve(&addr.to_string(), Duration::from_secs(1), 60).await.unwrap();
+    probe.tick().await.unwrap();
+
+    // Nothing listens once the socket is dropped, so the next connect is refused.
+    drop(listener);
+    assert!(probe.tick().await.is_err());
+
+    assert_eq!(probe.history.samples.len(), 2);
+    assert_eq!(probe.history.failures(), 1);
+    assert_eq!(probe.history.last(), Some(None));
+}
+
+#[test]
+fn percentiles_use_nearest_rank_over_successes() {
+    let mut history = LatencyHistory::new(101);
+    for ms in 1..=100 {
+        history.add(Some(ms as f64));
+    }
+    history.add(None);
+
+    assert_eq!(history.percentile(50.0), Some(50.0));
+    assert_eq!(history.percentile(99.0), Some(99.0));
+    history.add(Some(1000.0));
+    assert_eq!(history.samples.len(), 101, "window is capped");
+    assert_eq!(history.successes()[0], 2.0);
+    assert_eq!(LatencyHistory::new(10).percentile(50.0), None);
+}
+
+#[tokio::test]
+async fn http_checks_track_status_codes_against_the_expected_one() {
+    let app = axum::Router::new().route("/healthz", axum::routing::get(|| async { "ok" }));
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let target = listener.local_addr().unwrap().to_string();
+    tokio::spawn(async move { axum::serve(listener, app).await.unwrap() });
+
+    let mut healthy = HttpCheck::new(&target, "/healthz", 200, Duration::from_secs(1), 60).unwrap();
+    assert_eq!(healthy.url, format!("http://{}/healthz", target));
+    assert!(healthy.tick().await);
+
+    let mut missing = HttpCheck::new(&target, "nope", 200, Duration::from_secs(1), 60).unwrap();
+    assert!(!missing.tick().await);
+    assert!(!missing.tick().await);
+    assert_eq!(missing.statuses.get(&404), Some(&2));
+    assert_eq!(missing.last, Some(Ok(404)));
+    assert_eq!(missing.history.failures(), 2);
+}
@@ -0,0 +1,52 @@
+use std::time::Duration;
+
+use crabtop::profile::{fold_perf_script, parse_duration, render_flamegraph, Folded};
+
+#[test]
+fn parses_durations() {
+    assert_eq!(parse_duration("30s"), Ok(Duration::from_secs(30)));
+    assert_eq!(parse_duration("2m"), Ok(Duration::from_secs(120)));
+    assert_eq!(parse_duration("500ms"), Ok(Duration::from_millis(500)));
+    assert_eq!(parse_duration("5"), Ok(Duration::from_secs(5)));
+    assert_eq!(parse_duration("7d"), Ok(Duration::from_secs(7 * 86_400)));
+    assert!(parse_duration("5 parsecs").is_err());
+    assert!(parse_duration("s").is_err());
+}
+
+const PERF_SCRIPT: &str = "\
+api 4242/4250 [003] 12345.678901:   10101010 cpu-clock:
+\t    7f3a2b1c0d9e parse_json+0x1f (/srv/api/bin/api)
+\t    7f3a2b1c0aaa handle_request+0x88 (/srv/api/bin/api)
+\t    7f3a2b1c0bbb main+0x10 (/srv/api/bin/api)
+
+api 4242/4250 [001] 12345.688901:   10101010 cpu-clock:
+\t    7f3a2b1c0d9e parse_json+0x2a (/srv/api/bin/api)
+\t    7f3a2b1c0aaa handle_request+0x88 (/srv/api/bin/api)
+\t    7f3a2b1c0bbb main+0x10 (/srv/api/bin/api)
+
+tokio runtime 4242/4251 [000] 12345.698901:   10101010 cpu-clock:
+\t    ffffffff8100 [unknown] ([kernel.kallsyms])
+\t    7f3a2b1c0ccc epoll_wait+0x4 (/usr/lib/libc.so.6)
+";
+
+#[test]
+fn folds_perf_script_root_first() {
+    let folded = fold_perf_script(PERF_SCRIPT);
+    assert_eq!(folded.len(), 2);
+    assert_eq!(folded["api;main;handle_request;parse_json"], 2);
+    assert_eq!(folded["tokio runtime;epoll_wait;[unknown]"], 1);
+}
+
+#[test]
+fn draws_a_flamegraph() {
+    let mut folded = Folded::new();
+    folded.insert("api;main;handle_request".to_string(), 3);
+    folded.insert("api;main;<idle>".to_string(), 1);
+    let svg = render_flamegraph(&folded, "PID 42 & friends");
+    assert!(svg.starts_with("<svg"));
+    assert!(svg.trim_end().ends_with("</svg>"));
+    assert!(svg.contains("PID 42 &amp; friends"));
+    assert!(svg.contains("<title>handle_request (3 samples, 75.00%)</title>"));
+    assert!(svg.contains("<title>&lt;idle&gt; (1 samples, 25.00%)</title>"));
+    assert_eq!(svg.matches("<rect").count(), 1 + 5);
+}
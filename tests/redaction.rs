@@ -0,0 +1,55 @@
+//! What is scrubbed from process details before an AI call.
+
+use crabtop::config::RedactConfig;
+use crabtop::redact::{scrub_args, Redactor};
+use crabtop::ProcessInfo;
+
+fn args(list: &[&str]) -> Vec<String> {
+    list.iter().map(|a| a.to_string()).collect()
+}
+
+#[test]
+fn secret_flags_and_assignments_lose_their_values() {
+    let scrubbed = scrub_args(&args(&[
+        "server",
+        "--token=abc123",
+        "--api-key",
+        "sk-live",
+        "DB_PASSWORD=hunter2",
+        "--port",
+        "8080",
+    ]));
+    assert_eq!(
+        scrubbed,
+        args(&["server", "--token=[REDACTED]", "--api-key", "[REDACTED]", "DB_PASSWORD=[REDACTED]", "--port", "8080"])
+    );
+}
+
+#[test]
+fn config_rules_drop_arguments_and_replace_literals() {
+    let redactor = Redactor::from_config(&RedactConfig {
+        command_args: false,
+        literals: vec!["acme-internal".to_string()],
+        ..RedactConfig::default()
+    });
+    let info = ProcessInfo {
+        name: "api".to_string(),
+        cmdline: args(&["/srv/acme-internal/bin/api", "--secret", "x"]),
+        cwd: Some("/srv/acme-internal".into()),
+        ..Default::default()
+    };
+
+    let redacted = redactor.process(&info);
+    assert_eq!(redacted.cmdline, args(&["/srv/[REDACTED]/bin/api"]));
+    assert_eq!(redacted.cwd, Some("/srv/[REDACTED]".into()));
+    assert_eq!(redactor.text("deployed by acme-internal CI"), "deployed by [REDACTED] CI");
+}
+
+#[test]
+fn hostnames_are_only_replaced_as_whole_names() {
+    let redactor = Redactor::for_host(&RedactConfig::default(), Some("web.prod.example.com".to_string()));
+    assert_eq!(redactor.text("curl http://web.prod.example.com:8080/"), "curl http://[host]:8080/");
+    assert_eq!(redactor.text("web: node /srv/web/webpack.config.js"), "[host]: node /srv/[host]/webpack.config.js");
+    assert_eq!(redactor.text("a websocket from web-2, not web"), "a websocket from web-2, not [host]");
+    assert_eq!(redactor.text("webpack"), "webpack");
+}
@@ -0,0 +1,71 @@
+//! The `/proc` parsers behind `--ssh`, and `SshSource` driven through a local
+//! shell so no SSH server is needed.
+
+use crabtop::exec::Runner;
+use crabtop::procfs::{parse_meminfo, parse_smaps_rollup, parse_stat, parse_status};
+use crabtop::ssh::SshSource;
+use crabtop::MetricsSource;
+
+#[test]
+fn stat_with_spaces_and_parens_in_the_name() {
+    let line = "4242 (my (odd) app) S 1 4242 4242 0 -1 4194560 1200 0 3 0 \
+                150 75 0 0 20 0 8 0 987654 123456789 2048 18446744073709551615";
+    let stat = parse_stat(line).unwrap();
+    assert_eq!(stat.comm, "my (odd) app");
+    assert_eq!((stat.utime, stat.stime, stat.start_ticks), (150, 75, 987654));
+    assert_eq!((stat.minor_faults, stat.major_faults), (1200, 3));
+    assert_eq!((stat.state, stat.ppid, stat.exit_code), ('S', 1, None));
+}
+
+#[test]
+fn status_and_meminfo_are_reported_in_bytes() {
+    let status = parse_status("Name:\tnginx\nUid:\t33\t33\t33\t33\nVmSize:\t  65536 kB\nVmRSS:\t   2048 kB\n");
+    assert_eq!(status.name.as_deref(), Some("nginx"));
+    assert_eq!(status.uid, Some(33));
+    assert_eq!(status.rss_bytes, Some(2048 * 1024));
+    assert_eq!(status.vm_size_bytes, Some(65536 * 1024));
+
+    let meminfo = parse_meminfo("MemTotal:       16384 kB\nHugePages_Total:       0\n");
+    assert_eq!(meminfo["MemTotal"], 16384 * 1024);
+    assert_eq!(meminfo["HugePages_Total"], 0);
+}
+
+#[test]
+fn smaps_rollup_splits_private_and_shared_pages() {
+    let rollup = parse_smaps_rollup(
+        "55d0c8a4e000-7ffd2b9f5000 ---p 00000000 00:00 0                          [rollup]\n\
+         Rss:               14336 kB\n\
+         Pss:                9216 kB\n\
+         Shared_Clean:       4096 kB\n\
+         Shared_Dirty:       2048 kB\n\
+         Private_Clean:      1024 kB\n\
+         Private_Dirty:      7168 kB\n\
+         Swap:                512 kB\n",
+    );
+    assert_eq!(rollup.rss_bytes, 14336 * 1024);
+    assert_eq!(rollup.pss_bytes, 9216 * 1024);
+    assert_eq!(rollup.shared_bytes(), 6144 * 1024);
+    assert_eq!(rollup.private_bytes(), 8192 * 1024);
+    assert_eq!(rollup.swap_bytes, 512 * 1024);
+}
+
+#[cfg(target_os = "linux")]
+#[tokio::test]
+async fn samples_a_process_through_the_shell() {
+    let mut source = SshSource::with_runner(Runner::Local).unwrap();
+    let pid = std::process::id();
+
+    let info = source.sample(pid).await.unwrap();
+    assert_eq!(info.pid, pid);
+    assert!(info.memory_bytes > 0);
+    assert!(info.virtual_bytes >= info.memory_bytes);
+    assert!(info.shared_bytes.is_some(), "smaps_rollup of our own process is readable");
+    assert!(info.counters.is_some_and(|c| c.minor_faults > 0));
+    assert!(!info.cmdline.is_empty());
+
+    let system = source.system().unwrap();
+    assert!(system.cpu_count > 0);
+    assert!(system.total_memory_bytes >= system.available_memory_bytes);
+
+    assert!(source.sample(u32::MAX).await.is_err());
+}
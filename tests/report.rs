@@ -0,0 +1,71 @@
+use std::path::Path;
+use std::time::Duration;
+
+use chrono::{Local, TimeZone};
+
+use crabtop::export::ExportSample;
+use crabtop::recording::Metric;
+use crabtop::report::{render, sections, ReportFormat};
+use crabtop::ProcessInfo;
+
+/// Samples a minute apart from 14:00, alternating between two ports.
+fn recording() -> Vec<ExportSample> {
+    let start = Local.with_ymd_and_hms(2026, 10, 14, 14, 0, 0).unwrap();
+    (0..6)
+        .map(|n| ExportSample {
+            timestamp: start + chrono::Duration::minutes(n),
+            host: None,
+            port: if n % 2 == 0 { 8080 } else { 9090 },
+            pids: vec![4242],
+            process: ProcessInfo {
+                name: "api".to_string(),
+                cpu_percent: [10.0, 5.0, 90.0, 5.0, 30.0, 5.0][n as usize],
+                memory_bytes: 2_000_000,
+                ..Default::default()
+            },
+            connections: Some(n as usize),
+            annotations: Vec::new(),
+        })
+        .collect()
+}
+
+#[test]
+fn picks_the_port_and_the_last_stretch() {
+    let samples = recording();
+    let all = sections(&samples, None, None);
+    assert_eq!(all.iter().map(|s| s.port).collect::<Vec<_>>(), vec![8080, 9090]);
+
+    // The newest sample is at 14:05, so the last 3 minutes start at 14:02.
+    let picked = sections(&samples, Some(8080), Some(Duration::from_secs(180)));
+    assert_eq!(picked.len(), 1);
+    assert_eq!(picked[0].samples.len(), 2);
+    assert_eq!(picked[0].heading(), "api (PID 4242) on port 8080");
+    assert_eq!(picked[0].period(), "2026-10-14 14:02:00 – 14:04:00 (2m 0s), 2 samples");
+}
+
+#[test]
+fn summaries_note_when_each_metric_peaked() {
+    let samples = recording();
+    let section = &sections(&samples, Some(8080), None)[0];
+    let (metric, summary, peak_at) = section.summaries()[0];
+    assert_eq!(metric, Metric::Cpu);
+    assert_eq!((summary.avg.round(), summary.max), (43.0, 90.0));
+    assert_eq!(peak_at.format("%H:%M").to_string(), "14:02");
+}
+
+#[test]
+fn renders_markdown_or_html_by_extension() {
+    assert_eq!(ReportFormat::from_path(Path::new("retro.md")), ReportFormat::Markdown);
+    assert_eq!(ReportFormat::from_path(Path::new("retro.html")), ReportFormat::Html);
+
+    let samples = recording();
+    let picked = sections(&samples, Some(9090), None);
+    let md = render(&picked, "Incident 42", ReportFormat::Markdown);
+    assert!(md.starts_with("# Incident 42\n"));
+    assert!(md.contains("| CPU | 5.0% | 5.0% | 5.0% | 14:01:00 |"));
+    assert!(md.contains("Connections  ▂▅█  max 5.0"));
+
+    let html = render(&picked, "Incident <42>", ReportFormat::Html);
+    assert!(html.contains("<h1>Incident &lt;42&gt;</h1>"));
+    assert_eq!(html.matches("<svg").count(), 3);
+}
@@ -0,0 +1,46 @@
+use crabtop::docker::{container_id_from_cgroup, publishes_port};
+use crabtop::restart::{parse_supervisor_status, RestartPlan};
+
+const ID: &str = "3f4e8a6b1c2d9e0f7a5b4c3d2e1f0a9b8c7d6e5f4a3b2c1d0e9f8a7b6c5d4e3f";
+
+#[test]
+fn finds_the_container_under_either_cgroup_driver() {
+    assert_eq!(container_id_from_cgroup(&format!("0::/system.slice/docker-{}.scope\n", ID)).as_deref(), Some(ID));
+    assert_eq!(container_id_from_cgroup(&format!("12:memory:/docker/{}\n0::/docker/{}\n", ID, ID)).as_deref(), Some(ID));
+    assert_eq!(container_id_from_cgroup("0::/system.slice/nginx.service\n"), None);
+}
+
+#[test]
+fn matches_a_supervisor_program_by_pid() {
+    let status = "api:api_00                       RUNNING   pid 4242, uptime 1:02:03\n\
+                  api:api_01                       RUNNING   pid 4243, uptime 1:02:03\n\
+                  worker                           FATAL     Exited too quickly (process log may have details)\n";
+    assert_eq!(parse_supervisor_status(status, 4243).as_deref(), Some("api:api_01"));
+    assert_eq!(parse_supervisor_status(status, 99), None);
+}
+
+#[test]
+fn plans_print_the_command_they_run() {
+    let container = RestartPlan::Container { id: ID.to_string() };
+    assert_eq!(container.command(), vec!["docker", "restart", ID]);
+    assert_eq!(container.to_string(), "docker container 3f4e8a6b1c2d");
+    assert_eq!(RestartPlan::Sighup { pid: 4242 }.command(), vec!["kill", "-HUP", "4242"]);
+}
+
+#[test]
+fn published_ports_from_docker_ps() {
+    assert!(publishes_port("0.0.0.0:8080->80/tcp, :::8080->80/tcp", 8080));
+    assert!(!publishes_port("0.0.0.0:8080->80/tcp, :::8080->80/tcp", 80));
+
+    let range = "0.0.0.0:8000-8010->8000-8010/tcp, :::8000-8010->8000-8010/tcp";
+    assert!(publishes_port(range, 8000) && publishes_port(range, 8005) && publishes_port(range, 8010));
+    assert!(!publishes_port(range, 7999) && !publishes_port(range, 8011));
+
+    // Bound to one address, or only exposed and not published at all.
+    assert!(publishes_port("127.0.0.1:5432->5432/tcp", 5432));
+    assert!(publishes_port("[::1]:5432->5432/tcp", 5432));
+    assert!(!publishes_port("5432/tcp", 5432));
+
+    assert!(!publishes_port("0.0.0.0:53->53/udp", 53));
+    assert!(publishes_port("0.0.0.0:53->53/udp, 0.0.0.0:53->53/tcp", 53));
+}
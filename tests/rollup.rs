@@ -0,0 +1,95 @@
+//! Minute and hour rollups of a recording, and reports drawn from them.
+
+mod common;
+
+use chrono::{DateTime, Local, TimeZone};
+
+use crabtop::export::{ExportSample, Exporter};
+use crabtop::recording::{Metric, Recorder};
+use crabtop::report::{load_sections, rollup_sections};
+use crabtop::rollup::{self, Resolution, Rollups};
+use common::{info, scratch};
+
+fn at(secs: i64) -> DateTime<Local> {
+    Local.with_ymd_and_hms(2026, 10, 14, 14, 0, 0).unwrap() + chrono::Duration::seconds(secs)
+}
+
+fn sample(secs: i64, cpu: f32) -> ExportSample {
+    ExportSample {
+        timestamp: at(secs),
+        connections: Some(3),
+        ..common::sample(info("api", 4242, cpu, 1_000_000 + secs as u64))
+    }
+}
+
+#[test]
+fn minutes_close_as_time_moves_on_and_feed_the_hours() {
+    let mut rollups = Rollups::new();
+    let mut closed = Vec::new();
+    // Every 10 seconds for an hour and a half; CPU at 90% once a minute.
+    for secs in (0..5400).step_by(10) {
+        let cpu = if secs % 60 == 30 { 90.0 } else { 10.0 };
+        closed.extend(rollups.add(&sample(secs, cpu)));
+    }
+    let minutes: Vec<_> = closed.iter().filter(|(r, _)| *r == Resolution::Minute).map(|(_, b)| b).collect();
+    let hours: Vec<_> = closed.iter().filter(|(r, _)| *r == Resolution::Hour).map(|(_, b)| b).collect();
+    assert_eq!((minutes.len(), hours.len()), (89, 1));
+    assert_eq!((minutes[0].start, minutes[0].samples), (at(0), 6));
+    assert_eq!(minutes[0].cpu.min, 10.0);
+    assert!((minutes[0].cpu.avg - 70.0 / 3.0).abs() < 1e-9);
+    assert_eq!(minutes[0].cpu.max, 90.0);
+    assert_eq!(minutes[0].stat(Metric::Memory).unwrap().max, 1_000_050.0);
+    assert_eq!((hours[0].start, hours[0].samples, hours[0].cpu.max), (at(0), 360, 90.0));
+
+    // Shutdown flushes the minute in progress and the hour it belongs to.
+    let rest: Vec<_> = rollups.finish().into_iter().map(|(r, b)| (r, b.start, b.samples)).collect();
+    assert_eq!(rest, vec![(Resolution::Minute, at(5340), 6), (Resolution::Hour, at(3600), 180)]);
+}
+
+#[test]
+fn the_recorder_appends_rollups_that_survive_a_restart() {
+    let dir = scratch("restart");
+    let path = dir.join("samples.jsonl");
+    assert_eq!(Resolution::Minute.path(&path), dir.join("samples.1m.jsonl"));
+    // Two runs, the second starting half way through the first's last minute.
+    for secs in [0..90, 90..150] {
+        let mut recorder = Recorder::create(&path).unwrap().with_rollups(&path).unwrap();
+        for secs in secs.step_by(10) {
+            recorder.on_sample(&sample(secs, 20.0)).unwrap();
+        }
+        recorder.on_shutdown().unwrap();
+    }
+    let minutes = rollup::load(&Resolution::Minute.path(&path)).unwrap();
+    let counts: Vec<_> = minutes.iter().map(|b| (b.start, b.samples)).collect();
+    assert_eq!(counts, vec![(at(0), 6), (at(60), 6), (at(120), 3)]);
+    assert_eq!(rollup::load(&Resolution::Hour.path(&path)).unwrap()[0].samples, 15);
+}
+
+#[test]
+fn reports_from_rollups_keep_the_true_peak() {
+    let mut rollups = Rollups::new();
+    let mut buckets = Vec::new();
+    for secs in (0..600).step_by(10) {
+        let cpu = if secs == 250 { 95.0 } else { 5.0 };
+        buckets.extend(rollups.add(&sample(secs, cpu)).into_iter().filter(|(r, _)| *r == Resolution::Minute).map(|(_, b)| b));
+    }
+    let sections = rollup_sections(&buckets, Resolution::Minute, None, None);
+    assert_eq!(sections[0].period(), "2026-10-14 14:00:00 – 14:08:00 (8m 0s), 9 1m rollups of 54 samples");
+    let (metric, summary, peak_at) = sections[0].summaries()[0];
+    assert_eq!(metric, Metric::Cpu);
+    // Eight minutes at 5% and one averaging 20%.
+    assert!((summary.avg - 60.0 / 9.0).abs() < 1e-6);
+    assert_eq!((summary.max, peak_at), (95.0, at(240)));
+
+    // A recording that was rotated away is still reported from its rollups.
+    let path = scratch("rotated").join("samples.jsonl");
+    let mut recorder = Recorder::create(&path).unwrap().with_rollups(&path).unwrap();
+    for secs in (0..180).step_by(10) {
+        recorder.on_sample(&sample(secs, 5.0)).unwrap();
+    }
+    recorder.on_shutdown().unwrap();
+    std::fs::remove_file(&path).unwrap();
+    let sections = load_sections(&path, None, None, None).unwrap();
+    assert_eq!(sections[0].rollup.as_ref().map(|r| r.resolution), Some(Resolution::Minute));
+    assert!(load_sections(&path, Some(Resolution::Raw), None, None).is_err());
+}
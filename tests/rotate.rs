@@ -0,0 +1,97 @@
+//! Rotating long-running recordings and CSV files, and pruning old ones.
+
+mod common;
+
+use std::fs::{self, File};
+use std::time::{Duration, SystemTime};
+
+use chrono::{Local, TimeZone};
+use crabtop::export::{CsvExporter, Exporter};
+use crabtop::recording::{self, Recorder};
+use crabtop::rotate::{parse_size, prune, rotated_name, Rotation};
+use common::{info, sample, scratch};
+
+fn names(dir: &std::path::Path) -> Vec<String> {
+    let mut names: Vec<String> = fs::read_dir(dir).unwrap().flatten().map(|e| e.file_name().into_string().unwrap()).collect();
+    names.sort();
+    names
+}
+
+#[test]
+fn sizes_and_rotated_names() {
+    assert_eq!(parse_size("100MB"), Ok(100_000_000));
+    assert_eq!(parse_size("1.5G"), Ok(1_500_000_000));
+    assert_eq!(parse_size("512kb"), Ok(512_000));
+    assert_eq!(parse_size("4096"), Ok(4096));
+    assert_eq!(parse_size("2MiB"), Ok(2 * 1024 * 1024));
+    assert_eq!(parse_size("1ki"), Ok(1024));
+    assert!(parse_size("10 furlongs").is_err());
+
+    let at = Local.with_ymd_and_hms(2024, 3, 1, 14, 2, 0).unwrap();
+    assert_eq!(rotated_name("/var/log/samples.csv".as_ref(), at).to_str(), Some("/var/log/samples-20240301-140200.csv"));
+    assert_eq!(rotated_name("api".as_ref(), at).to_str(), Some("api-20240301-140200"));
+}
+
+#[test]
+fn a_full_csv_moves_aside_and_the_new_one_gets_a_header() {
+    let dir = scratch("csv");
+    let path = dir.join("samples.csv");
+    let rotation = Rotation {
+        max_size: Some(100),
+        ..Default::default()
+    };
+    let mut csv = CsvExporter::rotating(&path, rotation).unwrap();
+    for cpu in [1.0, 2.0, 3.0] {
+        csv.on_sample(&sample(info("api", 7, cpu, 0))).unwrap();
+    }
+    csv.on_shutdown().unwrap();
+
+    // The header alone is 72 bytes, so every file past the first sample fills up.
+    let files = names(&dir);
+    assert_eq!(files.len(), 3, "{:?}", files);
+    assert!(files.iter().all(|name| name.starts_with("samples")));
+    for name in &files {
+        let text = fs::read_to_string(dir.join(name)).unwrap();
+        assert!(text.starts_with("timestamp,host,port,"), "{}: {}", name, text);
+        assert_eq!(text.lines().count(), 2, "{}: {}", name, text);
+    }
+    assert!(fs::read_to_string(&path).unwrap().contains(",3.00,"));
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn a_new_recording_rotates_the_old_one_away_and_retention_prunes() {
+    let dir = scratch("record");
+    let path = dir.join("api.jsonl");
+    let rotation = Rotation {
+        retention: Some(Duration::from_secs(7 * 86_400)),
+        ..Default::default()
+    };
+
+    let mut first = Recorder::rotating(&path, rotation).unwrap();
+    first.on_sample(&sample(info("api", 7, 10.0, 0))).unwrap();
+    drop(first);
+    // A rotated copy from a fortnight ago, and something that just looks like one.
+    let stale = dir.join("api-20240101-000000.jsonl");
+    File::create(&stale)
+        .unwrap()
+        .set_modified(SystemTime::now() - Duration::from_secs(14 * 86_400))
+        .unwrap();
+    fs::write(dir.join("api-notes.jsonl"), "keep me").unwrap();
+
+    let mut second = Recorder::rotating(&path, rotation).unwrap();
+    second.on_sample(&sample(info("api", 7, 20.0, 0))).unwrap();
+    drop(second);
+
+    let files = names(&dir);
+    assert_eq!(files.len(), 3, "{:?}", files);
+    assert!(!stale.exists());
+    assert!(files.contains(&"api-notes.jsonl".to_string()));
+    let rotated = files.iter().find(|n| n.starts_with("api-2")).unwrap();
+    assert_eq!(recording::load(&dir.join(rotated)).unwrap()[0].process.cpu_percent, 10.0);
+    assert_eq!(recording::load(&path).unwrap()[0].process.cpu_percent, 20.0);
+
+    // Nothing else is old enough yet.
+    assert_eq!(prune(&path, Duration::from_secs(86_400)).unwrap(), 0);
+    fs::remove_dir_all(&dir).unwrap();
+}
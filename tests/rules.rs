@@ -0,0 +1,149 @@
+//! `[[rule]]` expressions: parsing, windows, cooldowns and routing.
+
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use chrono::{DateTime, Local, TimeZone};
+use crabtop::config;
+use crabtop::export::{Alert, ExportSample, Exporter, ExporterRegistry};
+use crabtop::rules::{Expr, Metric, Readings, Rule, RuleEngine, Transition};
+use crabtop::syslog::Severity;
+
+fn rule(name: &str, when: &str, cooldown: Duration) -> Rule {
+    Rule {
+        name: name.to_string(),
+        when: Expr::parse(when).unwrap(),
+        severity: Severity::Error,
+        cooldown,
+        message: None,
+    }
+}
+
+fn readings(cpu: f64, connections: Option<f64>) -> Readings {
+    let mut readings = Readings::default();
+    readings.set(Metric::Cpu, Some(cpu));
+    readings.set(Metric::Connections, connections);
+    readings
+}
+
+fn at(secs: i64) -> DateTime<Local> {
+    Local.with_ymd_and_hms(2024, 3, 5, 9, 0, 0).unwrap() + chrono::Duration::seconds(secs)
+}
+
+fn fired(transitions: Vec<Transition>) -> Vec<(bool, String)> {
+    transitions.into_iter().map(|t| (t.cleared, t.message)).collect()
+}
+
+#[test]
+fn parse_errors_say_where() {
+    let error = |text: &str| Expr::parse(text).unwrap_err().to_string();
+    assert_eq!(error("cpu > "), "the expression ends too soon");
+    assert_eq!(error("cpus > 80"), "unknown metric `cpus` at column 1 (known: cpu, memory, mem_mb, swap_mb, connections, retransmits, uptime)");
+    assert_eq!(error("avg(cpu, 80) > 1"), "expected a window like `5m` at column 10, found `80`");
+    assert_eq!(error("median(cpu, 5m) > 1"), "unknown function `median` at column 1 (use avg, min or max)");
+    assert_eq!(error("cpu > 80 )"), "unexpected `)` at column 10");
+    assert_eq!(error("cpu > 5x"), "unknown unit in 5x (use ms, s, m, h or d) at column 7");
+    assert!(Expr::parse("!(cpu >= 80 || -mem_mb < -512) && (connections + 1) * 2 != 0").is_ok());
+}
+
+#[test]
+fn windows_wait_until_covered_then_fire_and_clear() {
+    let mut engine = RuleEngine::new(vec![rule("busy", "avg(cpu, 1m) > 80 && connections > 100", Duration::ZERO)]);
+    assert!(engine.evaluate(at(0), readings(95.0, Some(150.0))).is_empty());
+    // The minute isn't covered yet, however busy it is.
+    assert!(engine.evaluate(at(30), readings(95.0, Some(150.0))).is_empty());
+    assert_eq!(
+        fired(engine.evaluate(at(60), readings(95.0, Some(150.0)))),
+        vec![(false, "avg(cpu, 1m) > 80 && connections > 100 (avg(cpu, 1m) = 95, connections = 150)".to_string())]
+    );
+    assert!(engine.evaluate(at(90), readings(90.0, Some(150.0))).is_empty());
+    // Without a connection count the rule can't be told either way, so it stays as it was.
+    assert!(engine.evaluate(at(100), readings(95.0, None)).is_empty());
+    assert_eq!(
+        fired(engine.evaluate(at(120), readings(10.0, Some(150.0)))),
+        vec![(true, "No longer avg(cpu, 1m) > 80 && connections > 100 (avg(cpu, 1m) = 72.5, connections = 150)".to_string())]
+    );
+}
+
+#[test]
+fn a_cooldown_holds_back_refiring_and_its_clearing() {
+    let mut engine = RuleEngine::new(vec![rule("hot", "cpu > 80", Duration::from_secs(600))]);
+    let mut seen = Vec::new();
+    for (secs, cpu) in [(0, 90.0), (10, 10.0), (20, 90.0), (30, 10.0), (700, 90.0)] {
+        seen.extend(fired(engine.evaluate(at(secs), readings(cpu, None))).into_iter().map(|(cleared, _)| (secs, cleared)));
+    }
+    assert_eq!(seen, vec![(0, false), (10, true), (700, false)]);
+}
+
+#[test]
+fn rules_are_read_from_the_config_file() {
+    let config = config::parse(
+        "[[rule]]\nname = \"busy\"\nwhen = \"max(cpu, 5m) > 80\"\nseverity = \"critical\"\ncooldown = \"15m\"\nsinks = [\"webhook\"]\n\n[[rule]]\nname = \"big\"\nwhen = \"mem_mb > 512\"\n",
+    )
+    .unwrap();
+    assert_eq!(config.rules.len(), 2);
+    assert_eq!(config.rules[0].severity, Severity::Critical);
+    assert_eq!(config.rules[0].cooldown, Duration::from_secs(900));
+    assert_eq!(config.rules[0].sinks, vec!["webhook".to_string()]);
+    assert_eq!((config.rules[1].severity, config.rules[1].cooldown), (Severity::Warning, Duration::ZERO));
+    assert!(RuleEngine::from_config(&config.rules).is_ok());
+
+    let bad = config::parse("[[rule]]\nname = \"bad\"\nwhen = \"cpu >\"\n").unwrap();
+    assert_eq!(RuleEngine::from_config(&bad.rules).unwrap_err().to_string(), "Rule `bad`: the expression ends too soon");
+}
+
+/// Remembers the rules of the alerts and recoveries it was handed.
+struct Sink {
+    name: &'static str,
+    seen: Arc<Mutex<Vec<String>>>,
+}
+
+impl Exporter for Sink {
+    fn name(&self) -> &str {
+        self.name
+    }
+
+    fn on_sample(&mut self, _sample: &ExportSample) -> anyhow::Result<()> {
+        Ok(())
+    }
+
+    fn on_alert(&mut self, alert: &Alert) -> anyhow::Result<()> {
+        self.seen.lock().unwrap().push(format!("{}: {}", self.name, alert.rule));
+        Ok(())
+    }
+
+    fn on_recovery(&mut self, recovery: &Alert) -> anyhow::Result<()> {
+        self.seen.lock().unwrap().push(format!("{}: {} cleared", self.name, recovery.rule));
+        Ok(())
+    }
+}
+
+#[test]
+fn routed_rules_reach_only_their_sinks() {
+    let seen = Arc::new(Mutex::new(Vec::new()));
+    let mut registry = ExporterRegistry::new();
+    for name in ["email", "webhook"] {
+        let seen = Arc::clone(&seen);
+        registry.push(Box::new(Sink { name, seen }));
+    }
+    registry.route("busy", &["email".to_string()]).unwrap();
+    let error = registry.route("busy", &["pagerduty".to_string()]).unwrap_err();
+    assert_eq!(error.to_string(), "Rule `busy` sends to `pagerduty`, which isn't configured (exporters: email, webhook)");
+
+    let alert = |rule: &str| Alert {
+        timestamp: Local::now(),
+        host: None,
+        port: 8080,
+        rule: rule.to_string(),
+        severity: Severity::Warning,
+        message: String::new(),
+        value: 0.0,
+    };
+    registry.on_alert(&alert("busy"));
+    registry.on_alert(&alert("process_exited"));
+    registry.on_recovery(&alert("busy"));
+    assert_eq!(
+        *seen.lock().unwrap(),
+        vec!["email: busy", "email: process_exited", "webhook: process_exited", "email: busy cleared"]
+    );
+}
@@ -0,0 +1,57 @@
+use serde_json::json;
+
+use crabtop::runtime::{detect, format_call_frames, Runtime, StackDump, DUMP_LINES};
+
+#[test]
+fn detects_runtimes_from_exe_or_argv0() {
+    assert_eq!(detect("/usr/bin/python3.11", &["/srv/venv/bin/gunicorn", "api:app"]), Some(Runtime::Python));
+    assert_eq!(detect("", &["python3", "-m", "http.server"]), Some(Runtime::Python));
+    assert_eq!(
+        detect("/usr/lib/jvm/java-17-openjdk/bin/java (deleted)", &["java", "-jar", "app.jar"]),
+        Some(Runtime::Jvm)
+    );
+    assert_eq!(
+        detect("/usr/bin/node", &["node", "server.js"]),
+        Some(Runtime::Node { inspector_port: 9229 })
+    );
+    assert_eq!(
+        detect("/usr/bin/node", &["node", "--inspect=0.0.0.0:9301", "server.js"]),
+        Some(Runtime::Node { inspector_port: 9301 })
+    );
+    assert_eq!(detect("/usr/sbin/nginx", &["nginx: master process"]), None);
+}
+
+#[test]
+fn scrolling_stops_at_the_last_page() {
+    let lines: Vec<String> = (0..DUMP_LINES + 5).map(|n| format!("line {}", n)).collect();
+    let mut dump = StackDump::new(Runtime::Jvm, "jcmd 42 Thread.print".to_string(), Ok(lines));
+    assert_eq!(dump.visible().len(), DUMP_LINES);
+    dump.next_page();
+    assert_eq!(dump.offset, 5);
+    assert_eq!(dump.visible().last().map(String::as_str), Some("line 24"));
+    dump.scroll(-2);
+    assert_eq!(dump.offset, 3);
+    dump.previous_page();
+    assert_eq!(dump.offset, 0);
+
+    let mut failed = StackDump::new(Runtime::Python, "py-spy dump --pid 42".to_string(), Err("denied".to_string()));
+    failed.next_page();
+    assert_eq!(failed.offset, 0);
+    assert!(failed.visible().is_empty());
+}
+
+#[test]
+fn formats_inspector_call_frames() {
+    let frames = [
+        json!({"functionName": "hash", "url": "file:///srv/app/auth.js", "location": {"lineNumber": 41, "columnNumber": 9}}),
+        json!({"functionName": "", "url": "node:internal/timers", "location": {"lineNumber": 0, "columnNumber": 0}}),
+    ];
+    assert_eq!(
+        format_call_frames(&frames),
+        vec![
+            "Main thread (paused):",
+            "    at hash (file:///srv/app/auth.js:42:10)",
+            "    at (anonymous) (node:internal/timers:1:1)",
+        ]
+    );
+}
@@ -0,0 +1,35 @@
+//! Sampling a real process: only the first read of a PID waits.
+
+use std::time::{Duration, Instant};
+
+use crabtop::process::ProcessSampler;
+
+/// Keeps this process busy for `ms`.
+fn spin(ms: u64) {
+    let started = Instant::now();
+    while started.elapsed() < Duration::from_millis(ms) {
+        std::hint::black_box(started.elapsed());
+    }
+}
+
+#[tokio::test]
+async fn measures_over_the_window_once_then_since_the_last_sample() {
+    let sampler = ProcessSampler::new(Duration::from_millis(400));
+    let pid = std::process::id();
+    // sysinfo has nothing to take a difference from until the process has used some CPU.
+    spin(50);
+
+    let started = Instant::now();
+    let first = sampler.sample(pid).await.unwrap();
+    assert!(started.elapsed() >= Duration::from_millis(400));
+    assert_eq!(first.pid, pid);
+
+    spin(300);
+    let started = Instant::now();
+    let second = sampler.sample(pid).await.unwrap();
+    assert!(started.elapsed() < Duration::from_millis(200), "took {:?}", started.elapsed());
+    assert!(second.cpu_percent > 40.0, "{}", second.cpu_percent);
+
+    assert!(sampler.sample(u32::MAX - 1).await.is_err());
+    assert_eq!(ProcessSampler::new(Duration::from_millis(10)).window(), Duration::from_millis(200));
+}
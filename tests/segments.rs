@@ -0,0 +1,100 @@
+//! Memory by kind of mapping from smaps, and which kind is growing.
+
+use chrono::{Duration, Local, TimeZone};
+use crabtop::procfs::parse_smaps;
+use crabtop::segments::{read_segments, MemorySegments, Segment, SegmentHistory};
+use crabtop::watch::{WatchSession, SLOW_REFRESH};
+use crabtop::{ProcessInfo, ScriptedSource};
+
+const MB: u64 = 1024 * 1024;
+
+const SMAPS: &str = "\
+55d0c4a00000-55d0c4a21000 r-xp 00000000 fd:01 1311 /usr/bin/app
+Size:                132 kB
+Rss:                 120 kB
+Pss:                 120 kB
+55d0c6200000-55d0c8b00000 rw-p 00000000 00:00 0 [heap]
+Size:              41984 kB
+Rss:               40960 kB
+7f2b10000000-7f2b14000000 rw-p 00000000 00:00 0 
+Size:              65536 kB
+Rss:               65000 kB
+Private_Dirty:     65000 kB
+7f2b20000000-7f2b20400000 rw-s 00000000 00:01 2048 /memfd:jit (deleted)
+Rss:                2048 kB
+7f2b30000000-7f2b30100000 r--p 00000000 fd:01 4242 /var/lib/app/data file.db
+Rss:                1024 kB
+7ffd1c000000-7ffd1c021000 rw-p 00000000 00:00 0 [stack]
+Rss:                  64 kB
+7ffd1c1f0000-7ffd1c1f2000 r-xp 00000000 00:00 0 [vdso]
+Rss:                   8 kB
+VmFlags: rd ex mr mw me de sd
+";
+
+#[test]
+fn smaps_mappings_are_sorted_by_kind() {
+    let segments = parse_smaps(SMAPS);
+    assert_eq!(
+        segments,
+        MemorySegments {
+            heap: 40960 * 1024,
+            anonymous: (65000 + 2048) * 1024,
+            file: (120 + 1024) * 1024,
+            stack: 64 * 1024,
+            other: 8 * 1024,
+        }
+    );
+    assert_eq!(Segment::of_path("[anon:scudo:primary]"), Segment::Anonymous);
+    assert_eq!(Segment::of_path("[vvar]"), Segment::Other);
+
+    // crabtop's own process, for a reading off a real smaps.
+    let own = read_segments(std::process::id()).unwrap();
+    assert!(own.file > 0 && own.anonymous + own.heap > 0, "{:?}", own);
+}
+
+#[test]
+fn the_segment_that_grows_is_named() {
+    let at = Local.with_ymd_and_hms(2026, 10, 14, 9, 0, 0).unwrap();
+    let reading = |anonymous, file| MemorySegments {
+        heap: 40 * MB,
+        anonymous,
+        file,
+        stack: MB / 8,
+        other: 0,
+    };
+    let mut history = SegmentHistory::default();
+    history.add(at, reading(100 * MB, 30 * MB));
+    assert_eq!(history.growing(), None);
+    history.add(at + Duration::seconds(5), reading(102 * MB, 30 * MB));
+    // Too little to be more than allocator slack.
+    assert_eq!(history.growing(), None);
+    history.add(at + Duration::seconds(10), reading(180 * MB, 34 * MB));
+    assert_eq!(history.growing(), Some((Segment::Anonymous, 80 * MB, at)));
+    // Two segments growing alike: neither stands out.
+    history.add(at + Duration::seconds(15), reading(180 * MB, 110 * MB));
+    assert_eq!(history.growing(), None);
+}
+
+#[tokio::test]
+async fn segments_are_read_on_the_slow_refreshes() {
+    let mut source = ScriptedSource::new();
+    for _ in 0..=SLOW_REFRESH {
+        source = source.push_sample(ProcessInfo {
+            name: "api".to_string(),
+            pid: 4242,
+            ..Default::default()
+        });
+    }
+    let grown = MemorySegments {
+        heap: 200 * MB,
+        ..Default::default()
+    };
+    source = source.push_segments(MemorySegments::default()).push_segments(grown);
+    let mut session = WatchSession::with_pids(source, 8080, vec![4242]).unwrap();
+    for _ in 0..=SLOW_REFRESH {
+        session.tick().await.unwrap();
+    }
+    let segments = session.segments.as_ref().unwrap();
+    assert_eq!(segments.readings.len(), 2);
+    assert_eq!(segments.growing().map(|(s, growth, _)| (s, growth)), Some((Segment::Heap, 200 * MB)));
+}
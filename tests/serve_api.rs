@@ -0,0 +1,76 @@
+//! `serve` mode end to end: the API on an ephemeral port, queried both raw and
+//! through `RemoteSource`, with this test process as the thing being inspected.
+
+use std::net::TcpListener as StdListener;
+
+use crabtop::remote::RemoteSource;
+use crabtop::server::router;
+use crabtop::MetricsSource;
+
+const TOKEN: &str = "s3cret";
+
+async fn spawn_server() -> String {
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    tokio::spawn(async move { axum::serve(listener, router(TOKEN.to_string())).await });
+    addr.to_string()
+}
+
+#[tokio::test]
+async fn rejects_requests_without_the_token() {
+    let addr = spawn_server().await;
+    let url = format!("http://{}/api/ports", addr);
+
+    let missing = reqwest::get(&url).await.unwrap();
+    assert_eq!(missing.status(), 401);
+
+    let wrong = reqwest::Client::new()
+        .get(&url)
+        .bearer_auth("nope")
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(wrong.status(), 401);
+
+    let query = reqwest::get(format!("{}?token={}", url, TOKEN)).await.unwrap();
+    assert_eq!(query.status(), 200);
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn remote_source_samples_a_served_process() {
+    let addr = spawn_server().await;
+    let target = StdListener::bind("127.0.0.1:0").unwrap();
+    let port = target.local_addr().unwrap().port();
+    let pid = std::process::id();
+
+    let mut source = RemoteSource::connect(&addr, TOKEN.to_string(), port).await.unwrap();
+    assert_eq!(source.resolve_pids(port).unwrap(), vec![pid]);
+    assert!(source.listeners(pid).unwrap().iter().any(|l| l.port == port));
+    assert!(source.system().is_err(), "no system figures before the first sample");
+
+    let info = source.sample(pid).await.unwrap();
+    assert_eq!(info.pid, pid);
+    assert!(info.memory_bytes > 0);
+    assert!(source.system().unwrap().total_memory_bytes > 0);
+
+    let unknown = RemoteSource::connect(&addr, TOKEN.to_string(), 1).await;
+    assert!(unknown.unwrap_err().to_string().contains("404"));
+}
+
+#[tokio::test]
+async fn stream_intervals_out_of_range_are_refused() {
+    let addr = spawn_server().await;
+    let upgrade = |interval: &str| {
+        reqwest::Client::new()
+            .get(format!("http://{}/api/process/{}/stream?interval={}&token={}", addr, std::process::id(), interval, TOKEN))
+            .header("connection", "upgrade")
+            .header("upgrade", "websocket")
+            .header("sec-websocket-version", "13")
+            .header("sec-websocket-key", "dGhlIHNhbXBsZSBub25jZQ==")
+            .send()
+    };
+    for bad in ["1e300", "1e-12", "0", "-1", "NaN", "inf", "soon", "3601"] {
+        assert_eq!(upgrade(bad).await.unwrap().status(), 400, "interval={}", bad);
+    }
+    assert_eq!(upgrade("0.5").await.unwrap().status(), 101);
+}
@@ -0,0 +1,75 @@
+//! `[[service]]` groups: finding their members, and summing them without
+//! counting a process twice.
+
+mod common;
+
+use chrono::NaiveDate;
+use crabtop::config;
+use crabtop::dashboard::render_service;
+use crabtop::service::{self, ServiceSession};
+use crabtop::ScriptedSource;
+use common::info;
+
+const CONFIG: &str = "[[service]]\nname = \"web\"\nports = [8080, 8443]\nmatch = [\"celery .*worker\"]\n\n[[service]]\nname = \"db\"\nports = [5432]\n";
+
+#[test]
+fn services_are_read_from_the_config_file() {
+    let config = config::parse(CONFIG).unwrap();
+    let web = service::find(&config.services, "web").unwrap();
+    assert_eq!(web.ports, vec![8080, 8443]);
+    assert_eq!(web.patterns, vec!["celery .*worker".to_string()]);
+    let error = service::find(&config.services, "api").unwrap_err();
+    assert_eq!(error.to_string(), "No service `api` in the config file (services: web, db)");
+
+    let empty = config::parse("[[service]]\nname = \"idle\"\n").unwrap();
+    assert!(ServiceSession::new(ScriptedSource::new(), &empty.services[0]).is_err());
+    let bad = config::parse("[[service]]\nname = \"bad\"\nmatch = [\"(worker\"]\n").unwrap();
+    let error = ServiceSession::new(ScriptedSource::new(), &bad.services[0]).err().unwrap();
+    assert!(error.to_string().starts_with("Service `bad`: "), "{}", error);
+}
+
+#[tokio::test]
+async fn sums_members_and_counts_shared_processes_once() {
+    let config = config::parse(CONFIG).unwrap();
+    // 8080 and 8443 are one process; two celery workers run beside it, and a
+    // third process has nothing to do with the service.
+    let source = ScriptedSource::new()
+        .with_listener(8080, 100)
+        .with_listener(8443, 100)
+        .with_cmdline(100, "gunicorn app:web")
+        .with_cmdline(201, "celery -A app worker")
+        .with_cmdline(202, "celery -A app worker --queue slow")
+        .with_cmdline(300, "redis-server")
+        .push_group(vec![info("scripted", 100, 40.0, 300_000_000), info("scripted", 201, 10.0, 100_000_000), info("scripted", 202, 5.0, 50_000_000)]);
+    let mut session = ServiceSession::new(source, service::find(&config.services, "web").unwrap()).unwrap();
+    session.tick().await.unwrap();
+
+    let members: Vec<_> = session.members.iter().map(|m| (m.kind.to_string(), m.pids.clone(), m.info.as_ref().map(|i| i.cpu_percent))).collect();
+    assert_eq!(
+        members,
+        vec![
+            ("port 8080".to_string(), vec![100], Some(40.0)),
+            ("port 8443".to_string(), vec![100], Some(40.0)),
+            ("match celery .*worker".to_string(), vec![201, 202], Some(15.0)),
+        ]
+    );
+    let total = session.total.as_ref().unwrap();
+    assert_eq!((total.cpu_percent, total.memory_bytes), (55.0, 450_000_000));
+    assert_eq!(session.history.cpu_history, vec![55.0]);
+
+    let mut out = Vec::new();
+    let at = NaiveDate::from_ymd_opt(2026, 10, 14).unwrap().and_hms_opt(9, 0, 0).unwrap();
+    render_service(&mut out, &session.name, session.total.as_ref(), &session.history, &session.members, 2, 100, at).unwrap();
+    let text = String::from_utf8(out).unwrap();
+    assert!(text.contains("Service web (3 of 3 members running)  2026-10-14 09:00:00"), "{}", text);
+    assert!(text.contains("201 +1"), "{}", text);
+}
+
+#[tokio::test]
+async fn fails_only_when_nothing_of_the_service_runs() {
+    let config = config::parse(CONFIG).unwrap();
+    let mut session = ServiceSession::new(ScriptedSource::new(), service::find(&config.services, "db").unwrap()).unwrap();
+    let error = session.tick().await.unwrap_err();
+    assert_eq!(error.to_string(), "Nothing of service `db` is running");
+    assert!(session.members[0].info.is_none());
+}
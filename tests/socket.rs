@@ -0,0 +1,80 @@
+//! Listening-socket options from `ss -ltnm`, and when watch sessions read them.
+
+use crabtop::Listener;
+use crabtop::socket::{parse_socket_options, ListenerSockets, SocketOptions};
+use crabtop::watch::{Tab, WatchSession, SLOW_REFRESH};
+use crabtop::{ProcessInfo, ScriptedSource};
+
+const SS: &str = "\
+LISTEN 0      4096       127.0.0.53%lo:53         0.0.0.0:*
+\t skmem:(r0,rb131072,t0,tb16384,f0,w0,o0,bl0,d0)
+LISTEN 0      4096           0.0.0.0:8080       0.0.0.0:*
+\t skmem:(r0,rb2097152,t0,tb16384,f0,w0,o0,bl0,d0)
+LISTEN 2      4096           0.0.0.0:8080       0.0.0.0:*
+\t skmem:(r0,rb2097152,t0,tb16384,f0,w0,o0,bl0,d0)
+LISTEN 0      511               [::]:8080          [::]:* skmem:(r0,rb131072,t0,tb46080,f0,w0,o0,bl0,d0)
+LISTEN 0      128            0.0.0.0:18080      0.0.0.0:*
+\t skmem:(r0,rb131072,t0,tb16384,f0,w0,o0,bl0,d0)
+";
+
+fn socket(address: &str, count: usize, sharing: usize, backlog: u32, recv_buffer: u64, send_buffer: u64) -> SocketOptions {
+    SocketOptions {
+        listener: Listener {
+            address: address.to_string(),
+            port: 8080,
+        },
+        count,
+        sharing,
+        backlog,
+        recv_buffer,
+        send_buffer,
+    }
+}
+
+#[test]
+fn alike_sockets_on_an_address_are_counted_together() {
+    assert_eq!(
+        parse_socket_options(SS, 8080),
+        vec![socket("0.0.0.0", 2, 2, 4096, 2_097_152, 16_384), socket("[::]", 1, 1, 511, 131_072, 46_080)]
+    );
+    let sockets = parse_socket_options(SS, 8080);
+    assert!(sockets[0].reuses_port() && !sockets[1].reuses_port());
+    let listener = ListenerSockets {
+        sockets,
+        somaxconn: Some(4096),
+        ..Default::default()
+    };
+    assert!(listener.backlog_capped(&listener.sockets[0]));
+    assert!(!listener.backlog_capped(&listener.sockets[1]));
+    assert!(parse_socket_options(SS, 9090).is_empty());
+}
+
+#[tokio::test]
+async fn read_on_the_network_tab_and_then_on_the_slow_refreshes() {
+    let reading = |backlog| ListenerSockets {
+        sockets: vec![socket("0.0.0.0", 1, 1, backlog, 131_072, 16_384)],
+        ..Default::default()
+    };
+    let mut source = ScriptedSource::new().with_listener(8080, 4242);
+    for _ in 0..=SLOW_REFRESH + 1 {
+        source = source.push_sample(ProcessInfo {
+            pid: 4242,
+            ..Default::default()
+        });
+    }
+    let source = source.push_listener_sockets(reading(128)).push_listener_sockets(reading(511)).push_listener_sockets(reading(1024));
+
+    let mut session = WatchSession::new(source, 8080).unwrap();
+    session.show_tab(Tab::Resources);
+    session.tick().await.unwrap();
+    assert!(session.sockets.is_none());
+
+    session.show_tab(Tab::Network);
+    let mut backlogs = Vec::new();
+    for _ in 0..SLOW_REFRESH {
+        session.tick().await.unwrap();
+        backlogs.push(session.sockets.as_ref().unwrap().sockets[0].backlog);
+    }
+    // Read as soon as the tab is shown, then again at the next slow tick.
+    assert_eq!(backlogs, [128, 128, 128, 128, 511]);
+}
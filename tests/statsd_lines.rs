@@ -0,0 +1,51 @@
+mod common;
+
+use std::net::UdpSocket;
+use std::time::Duration;
+
+use crabtop::export::ExportSample;
+use crabtop::statsd::{StatsdEmitter, StatsdFormat};
+use crabtop::ProcessInfo;
+use common::info;
+
+fn sample(host: Option<&str>) -> ExportSample {
+    ExportSample {
+        host: host.map(str::to_string),
+        ..common::sample(ProcessInfo {
+            uptime_secs: 90,
+            ..info("node server", 4242, 12.5, 42_000_000)
+        })
+    }
+}
+
+#[test]
+fn dogstatsd_tags_carry_port_process_and_extras() {
+    let collector = UdpSocket::bind("127.0.0.1:0").unwrap();
+    collector.set_read_timeout(Some(Duration::from_secs(2))).unwrap();
+    let addr = collector.local_addr().unwrap().to_string();
+
+    let emitter = StatsdEmitter::connect(&addr, "app.", StatsdFormat::Dogstatsd, vec!["env:prod".to_string()]).unwrap();
+    emitter.emit(&sample(None)).unwrap();
+
+    let mut buf = [0u8; 1024];
+    let n = collector.recv(&mut buf).unwrap();
+    assert_eq!(
+        std::str::from_utf8(&buf[..n]).unwrap(),
+        "app.cpu_percent:12.50|g|#port:8080,process:node_server,env:prod\n\
+         app.memory_bytes:42000000|g|#port:8080,process:node_server,env:prod\n\
+         app.uptime_secs:90|g|#port:8080,process:node_server,env:prod"
+    );
+}
+
+#[test]
+fn plain_statsd_folds_tags_into_the_name() {
+    let emitter = StatsdEmitter::connect("127.0.0.1:8125", "crabtop", StatsdFormat::Plain, Vec::new()).unwrap();
+    assert_eq!(
+        emitter.format_sample(&sample(None))[0],
+        "crabtop.node_server.8080.cpu_percent:12.50|g"
+    );
+    assert_eq!(
+        emitter.format_sample(&sample(Some("web-1:7070")))[0],
+        "crabtop.web-1_7070.node_server.8080.cpu_percent:12.50|g"
+    );
+}
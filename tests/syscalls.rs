@@ -0,0 +1,32 @@
+//! Sorting a process's syscalls into what it's busy with.
+
+use crabtop::syscalls::{parse_task_syscalls, SyscallCounts};
+
+#[cfg(target_arch = "x86_64")]
+#[test]
+fn threads_are_counted_by_the_syscall_they_are_in() {
+    // epoll_wait, futex three times, read, on a CPU, and blocked outside a syscall.
+    let text = "\
+232 0x3 0x7ffd 0x400 0xffffffff 0x0 0x0 0x7ffd10 0x7f2a31
+202 0x7f2a 0x80 0x0 0x0 0x0 0x0 0x7ffd20 0x7f2a32
+202 0x7f2b 0x80 0x0 0x0 0x0 0x0 0x7ffd30 0x7f2a32
+202 0x7f2d 0x80 0x0 0x0 0x0 0x0 0x7ffd38 0x7f2a32
+0 0x5 0x7f2c 0x1000 0x0 0x0 0x0 0x7ffd40 0x7f2a33
+running
+-1 0x7ffd50 0x7f2a34
+";
+    let counts = parse_task_syscalls(text);
+    assert_eq!(
+        counts,
+        SyscallCounts {
+            read: 1,
+            poll: 1,
+            futex: 3,
+            running: 1,
+            ..Default::default()
+        }
+    );
+    assert_eq!(counts.dominant(), Some("locking"));
+    let spinning = SyscallCounts { running: 4, ..counts };
+    assert_eq!(spinning.dominant(), Some("computing"));
+}
@@ -0,0 +1,89 @@
+//! Alerts as syslog lines and journal entries, at a priority per rule.
+
+use chrono::{Local, TimeZone};
+use crabtop::export::Alert;
+use crabtop::syslog::{journal_entry, severity, syslog_line, Facility, Severity};
+
+fn alert(rule: &str, message: &str) -> Alert {
+    Alert {
+        timestamp: Local::now(),
+        host: None,
+        port: 8080,
+        rule: rule.to_string(),
+        severity: severity(rule),
+        message: message.to_string(),
+        value: 4242.0,
+    }
+}
+
+#[test]
+fn each_rule_has_a_priority_and_a_syslog_line() {
+    assert_eq!(severity("process_exited"), Severity::Critical);
+    assert_eq!(severity("oom_at_risk"), Severity::Error);
+    assert_eq!(severity("retransmits_high"), Severity::Warning);
+    assert_eq!(severity("something_new"), Severity::Notice);
+
+    let now = Local.with_ymd_and_hms(2024, 3, 5, 9, 7, 1).unwrap();
+    let line = syslog_line(&alert("process_exited", "PID 4242 exited"), Facility::Daemon, 77, now);
+    // daemon (3) * 8 + crit (2)
+    assert_eq!(line, "<26>Mar  5 09:07:01 crabtop[77]: port 8080: PID 4242 exited [process_exited]");
+    let line = syslog_line(&alert("swap_started", "Started swapping"), Facility::Local3, 77, now);
+    assert!(line.starts_with("<156>"), "{}", line);
+}
+
+#[test]
+fn journal_entries_carry_fields_and_frame_multiline_values() {
+    let mut remote = alert("oom_at_risk", "Next in line for the OOM killer");
+    remote.host = Some("web-1:7070".to_string());
+    let entry = String::from_utf8(journal_entry(&remote, Facility::Daemon)).unwrap();
+    assert!(entry.starts_with("MESSAGE=web-1:7070 port 8080: Next in line for the OOM killer [oom_at_risk]\nPRIORITY=3\n"), "{}", entry);
+    assert!(entry.contains("\nCRABTOP_RULE=oom_at_risk\nCRABTOP_PORT=8080\nCRABTOP_VALUE=4242\nCRABTOP_HOST=web-1:7070\n"), "{}", entry);
+
+    let entry = journal_entry(&alert("process_exited", "gone\nfor good"), Facility::Daemon);
+    let message = "port 8080: gone\nfor good [process_exited]";
+    let mut framed = b"MESSAGE\n".to_vec();
+    framed.extend_from_slice(&(message.len() as u64).to_le_bytes());
+    framed.extend_from_slice(message.as_bytes());
+    framed.push(b'\n');
+    assert!(entry.starts_with(&framed));
+}
+
+#[cfg(unix)]
+#[test]
+fn alerts_reach_the_socket_and_samples_do_not() {
+    use std::os::unix::net::UnixDatagram;
+
+    use crabtop::export::{ExportSample, Exporter};
+    use crabtop::syslog::{JournaldExporter, SyslogExporter};
+
+    let dir = std::env::temp_dir().join(format!("crabtop-syslog-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let path = dir.join("log.sock");
+    let _ = std::fs::remove_file(&path);
+    let server = UnixDatagram::bind(&path).unwrap();
+    server.set_nonblocking(true).unwrap();
+
+    let mut syslog = SyslogExporter::connect(&path, Facility::Daemon).unwrap();
+    let mut journal = JournaldExporter::connect(&path, Facility::Daemon).unwrap();
+    let sample = ExportSample {
+        timestamp: Local::now(),
+        host: None,
+        port: 8080,
+        pids: vec![4242],
+        process: Default::default(),
+        connections: None,
+        annotations: Vec::new(),
+    };
+    syslog.on_sample(&sample).unwrap();
+    syslog.on_alert(&alert("process_restarted", "PID 1 replaced by 2")).unwrap();
+    journal.on_alert(&alert("process_restarted", "PID 1 replaced by 2")).unwrap();
+
+    let mut buf = [0u8; 1024];
+    let n = server.recv(&mut buf).unwrap();
+    let line = String::from_utf8_lossy(&buf[..n]);
+    assert!(line.starts_with("<28>") && line.ends_with("port 8080: PID 1 replaced by 2 [process_restarted]"), "{}", line);
+    let n = server.recv(&mut buf).unwrap();
+    assert!(buf[..n].starts_with(b"MESSAGE=port 8080: PID 1 replaced by 2"));
+    assert!(server.recv(&mut buf).is_err(), "only the two alerts");
+    std::fs::remove_dir_all(&dir).unwrap();
+}
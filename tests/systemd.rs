@@ -0,0 +1,32 @@
+use crabtop::systemd::{parse_show, unit_from_cgroup};
+
+#[test]
+fn finds_the_innermost_service_in_the_cgroup_path() {
+    assert_eq!(unit_from_cgroup("0::/system.slice/nginx.service\n").as_deref(), Some("nginx.service"));
+    assert_eq!(
+        unit_from_cgroup("0::/user.slice/user-1000.slice/user@1000.service/app.slice/api.service\n").as_deref(),
+        Some("api.service")
+    );
+    // cgroup v1: the unit is on the name=systemd line.
+    let v1 = "12:memory:/system.slice/redis.service\n1:name=systemd:/system.slice/redis.service\n0::/\n";
+    assert_eq!(unit_from_cgroup(v1).as_deref(), Some("redis.service"));
+    // A login session is a scope, not something to restart.
+    assert_eq!(unit_from_cgroup("0::/user.slice/user-1000.slice/session-4.scope\n"), None);
+}
+
+#[test]
+fn parses_state_and_limits_from_systemctl_show() {
+    let text = "Id=api.service\nActiveState=active\nSubState=running\nMainPID=4242\nNRestarts=3\n\
+                CPUQuotaPerSecUSec=1.500000s\nMemoryMax=536870912\nMemoryCurrent=134217728\nTasksCurrent=17\n";
+    let unit = parse_show(text).unwrap();
+    assert_eq!(unit.name, "api.service");
+    assert!(unit.is_active());
+    assert_eq!(unit.main_pid, Some(4242));
+    assert_eq!(unit.restarts, Some(3));
+    assert_eq!(unit.cpu_quota_percent, Some(150.0));
+    assert_eq!(unit.memory_ratio(), Some(0.25));
+
+    let unlimited = parse_show("Id=worker.service\nCPUQuotaPerSecUSec=infinity\nMemoryMax=infinity\nMainPID=0\n").unwrap();
+    assert_eq!((unlimited.cpu_quota_percent, unlimited.memory_max, unlimited.main_pid), (None, None, None));
+    assert!(parse_show("").is_none());
+}
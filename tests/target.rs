@@ -0,0 +1,81 @@
+//! Sidecar targets: a container's processes found by cgroup, not by port.
+
+mod common;
+
+use crabtop::annotation::AnnotationKind;
+use crabtop::target::{container_id, find_container, Target, VisibleProcess};
+use crabtop::watch::WatchSession;
+use crabtop::ScriptedSource;
+use common::{info, scratch};
+
+const ID: &str = "3f2a9c1b7d40e8a95c6b2f1e0d3a7c4b9e8f1a2b3c4d5e6f708192a3b4c5d6e7";
+
+fn visible(pid: u32, cgroup: &str, name: &str) -> VisibleProcess {
+    VisibleProcess {
+        pid,
+        cgroup: cgroup.to_string(),
+        name: name.to_string(),
+    }
+}
+
+#[test]
+fn container_ids_are_read_off_the_runtimes_cgroup_names() {
+    for cgroup in [
+        format!("/kubepods.slice/kubepods-burstable.slice/cri-containerd-{}.scope", ID),
+        format!("/../crio-{}.scope", ID),
+        format!("/system.slice/docker-{}.scope", ID),
+        format!("/kubepods/burstable/pod61a2/{}", ID),
+    ] {
+        assert_eq!(container_id(&cgroup), Some(ID), "{}", cgroup);
+    }
+    assert_eq!(container_id("/system.slice/nginx.service"), None);
+}
+
+#[test]
+fn containers_are_told_apart_by_cgroup() {
+    let app = format!("/../cri-containerd-{}.scope", ID);
+    let processes = vec![
+        visible(1, "/../cri-containerd-9a9a.scope", "pause"),
+        visible(7, &app, "gunicorn"),
+        visible(12, &app, "gunicorn"),
+        visible(30, "/", "crabtop"),
+        visible(9, &app, "python3"),
+    ];
+    assert_eq!(find_container(&processes, "/", "gunicorn").unwrap(), vec![7, 9, 12]);
+    assert_eq!(find_container(&processes, "/", "3F2A9C").unwrap(), vec![7, 9, 12]);
+    // crabtop's own cgroup is never a candidate.
+    let error = find_container(&processes, "/", "crabtop").unwrap_err();
+    assert_eq!(error.to_string(), "No container `crabtop` is visible (containers: pause, gunicorn (3f2a9c1b7d40))");
+
+    let alone = vec![visible(30, "/", "crabtop")];
+    assert!(find_container(&alone, "/", "app").unwrap_err().to_string().contains("shareProcessNamespace"));
+}
+
+#[tokio::test]
+async fn the_watch_follows_the_cgroup_as_its_processes_change() {
+    let dir = scratch("follow");
+    // PID 0 is a process outside crabtop's PID namespace.
+    std::fs::write(dir.join("cgroup.procs"), "300\n0\n100\n").unwrap();
+    std::fs::write(dir.join("memory.current"), "52428800\n").unwrap();
+    std::fs::write(dir.join("memory.max"), "104857600\n").unwrap();
+    let target = Target::Cgroup(dir.clone());
+    assert_eq!(target.pids().unwrap(), vec![100, 300]);
+
+    let source = ScriptedSource::new().push_sample(info("app", 100, 10.0, 10_000_000)).push_sample(info("app", 300, 5.0, 10_000_000)).push_sample(info("app", 200, 30.0, 10_000_000));
+    let mut session = WatchSession::with_pids(source, 0, target.pids().unwrap()).unwrap().follow_target(Some(target));
+    let first = session.tick().await.unwrap();
+    assert_eq!(first.cpu_percent, 15.0);
+    let cgroup = session.cgroup.as_ref().unwrap();
+    assert_eq!((cgroup.memory_current, cgroup.memory_max), (Some(52_428_800), Some(104_857_600)));
+
+    // The container restarted its process.
+    std::fs::write(dir.join("cgroup.procs"), "200\n").unwrap();
+    assert_eq!(session.tick().await.unwrap().cpu_percent, 30.0);
+    assert_eq!(session.pids, vec![200]);
+    assert_eq!(session.annotations.last().map(|a| (a.kind, a.text.as_str())), Some((AnnotationKind::Restart, "PID 100 replaced by 200")));
+
+    std::fs::write(dir.join("cgroup.procs"), "").unwrap();
+    assert!(session.tick().await.is_err());
+    let error = Target::Cgroup(dir.clone()).pids().unwrap_err();
+    assert!(error.to_string().starts_with("No process of cgroup "), "{}", error);
+}
@@ -0,0 +1,62 @@
+//! Parsing `ss -ti` and `ss -ta` output into per-port `tcp_info` totals and connection tables.
+
+use crabtop::port::{parse_connection_counts, parse_port_connections, parse_tcp_info};
+
+#[test]
+fn sums_tcp_info_of_connections_on_the_port() {
+    let ss = "\
+0      0      127.0.0.1:8080 127.0.0.1:36688
+\t cubic wscale:7,7 rto:204 rtt:0.5/0.25 mss:32768 cwnd:10 bytes_sent:9000 retrans:0/3 segs_out:100 segs_in:80
+0      0      10.0.0.5:8080 10.0.0.9:51000
+\t cubic wscale:7,7 rto:240 rtt:40.25/10.5 mss:1448 cwnd:4 ssthresh:7 segs_out:300 segs_in:290
+0      0      10.0.0.5:44120 10.0.0.7:5432
+\t cubic rtt:900/10 cwnd:2 retrans:1/50 segs_out:50
+";
+    let stats = parse_tcp_info(ss, 8080);
+    assert_eq!(stats.connections, 2);
+    assert_eq!(stats.retransmits, 3);
+    assert_eq!(stats.segments_out, 400);
+    assert_eq!(stats.retransmit_percent(), Some(0.75));
+    assert_eq!(stats.rtt_max_ms, Some(40.25));
+    assert_eq!(stats.rtt_avg_ms, Some(20.375));
+    assert_eq!(stats.cwnd_avg, Some(7.0));
+
+    let idle = parse_tcp_info("", 8080);
+    assert_eq!(idle.connections, 0);
+    assert_eq!(idle.retransmit_percent(), None);
+}
+
+#[test]
+fn counts_established_connections_by_local_port() {
+    let ss = "\
+ESTAB      0 0 127.0.0.1:8080 127.0.0.1:36688
+ESTAB      0 0 10.0.0.5:8080  10.0.0.9:51000
+TIME-WAIT  0 0 10.0.0.5:8080  10.0.0.9:50990
+ESTAB      0 0 [::1]:443      [::1]:40112
+";
+    let counts = parse_connection_counts(ss);
+    assert_eq!(counts.get(&8080), Some(&2));
+    assert_eq!(counts.get(&443), Some(&1));
+    assert_eq!(counts.len(), 2);
+}
+
+#[test]
+fn socket_table_lists_the_ports_connections_in_every_state() {
+    let ss = "\
+LISTEN    0 4096       0.0.0.0:8080        0.0.0.0:*
+ESTAB     0 0        127.0.0.1:8080      127.0.0.1:51234
+SYN-RECV  0 0        127.0.0.1:8080      127.0.0.1:51240
+TIME-WAIT 0 0        127.0.0.1:8080      127.0.0.1:51236
+ESTAB     0 0        127.0.0.1:51234     127.0.0.1:8080
+ESTAB     0 0        127.0.0.1:18080     127.0.0.1:51300
+";
+    let connections: Vec<(String, bool)> = parse_port_connections(ss, 8080).into_iter().map(|c| (c.key, c.open)).collect();
+    assert_eq!(
+        connections,
+        vec![
+            ("127.0.0.1:8080->127.0.0.1:51234".to_string(), true),
+            ("127.0.0.1:8080->127.0.0.1:51240".to_string(), true),
+            ("127.0.0.1:8080->127.0.0.1:51236".to_string(), false),
+        ]
+    );
+}
@@ -0,0 +1,77 @@
+//! Certificate parsing and a real handshake against a local rustls server.
+//!
+//! The fixture is a self-signed P-256 certificate for `crabtop.test` valid
+//! from 2026-01-01 to 2036-01-01.
+
+use std::sync::Arc;
+use std::time::Duration;
+
+use chrono::{TimeZone, Utc};
+use crabtop::tls::{inspect, parse_certificate};
+use rustls::pki_types::{CertificateDer, PrivateKeyDer, PrivatePkcs8KeyDer};
+use tokio::io::AsyncWriteExt;
+use tokio::net::TcpListener;
+use tokio_rustls::TlsAcceptor;
+
+const CERT: &[u8] = include_bytes!("fixtures/cert.der");
+const KEY: &[u8] = include_bytes!("fixtures/key.der");
+
+#[test]
+fn parses_names_and_validity() {
+    let cert = parse_certificate(CERT).unwrap();
+    assert_eq!(cert.subject, "CN=crabtop.test, O=Crabtop Tests");
+    assert_eq!(cert.issuer, cert.subject);
+    assert_eq!(cert.san, ["crabtop.test", "localhost", "127.0.0.1"]);
+    assert_eq!(cert.not_before, Utc.with_ymd_and_hms(2026, 1, 1, 0, 0, 0).unwrap());
+    assert_eq!(cert.not_after, Utc.with_ymd_and_hms(2036, 1, 1, 0, 0, 0).unwrap());
+
+    let near_the_end = Utc.with_ymd_and_hms(2035, 12, 25, 0, 0, 0).unwrap();
+    assert_eq!(cert.days_until_expiry(near_the_end), 7);
+    assert!(cert.expires_soon(near_the_end));
+    assert!(!cert.expires_soon(cert.not_before));
+
+    assert!(parse_certificate(&CERT[..CERT.len() / 2]).is_err());
+}
+
+#[tokio::test]
+async fn inspects_a_self_signed_server() {
+    let _ = rustls::crypto::ring::default_provider().install_default();
+    let config = rustls::ServerConfig::builder()
+        .with_no_client_auth()
+        .with_single_cert(
+            vec![CertificateDer::from(CERT.to_vec())],
+            PrivateKeyDer::Pkcs8(PrivatePkcs8KeyDer::from(KEY.to_vec())),
+        )
+        .unwrap();
+    let acceptor = TlsAcceptor::from(Arc::new(config));
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    tokio::spawn(async move {
+        let (tcp, _) = listener.accept().await.unwrap();
+        if let Ok(mut tls) = acceptor.accept(tcp).await {
+            let _ = tls.shutdown().await;
+        }
+    });
+
+    let info = inspect(&addr.to_string(), Some("crabtop.test"), Duration::from_secs(5))
+        .await
+        .unwrap();
+    assert_eq!(info.protocol, "TLS 1.3");
+    assert!(info.cipher.starts_with("TLS13_"), "{}", info.cipher);
+    assert_eq!(info.server_name, "crabtop.test");
+    assert!(info.verified.is_err(), "a self-signed certificate shouldn't verify");
+    assert_eq!(info.certificate.unwrap().subject, "CN=crabtop.test, O=Crabtop Tests");
+}
+
+#[tokio::test]
+async fn plain_tcp_is_reported_as_a_failed_handshake() {
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    tokio::spawn(async move {
+        let (mut tcp, _) = listener.accept().await.unwrap();
+        let _ = tcp.write_all(b"HTTP/1.1 400 Bad Request\r\n\r\n").await;
+    });
+
+    let err = inspect(&addr.to_string(), None, Duration::from_secs(5)).await.unwrap_err();
+    assert!(err.to_string().contains("TLS handshake"), "{}", err);
+}
@@ -0,0 +1,20 @@
+//! Display units. One test, since `--units` is process-wide.
+
+use crabtop::units::{format_bytes, format_mb, mb_unit, set_units, to_mb, units, Units};
+
+#[test]
+fn decimal_by_default_and_binary_when_asked() {
+    assert_eq!(units(), Units::Decimal);
+    assert_eq!(format_mb(42_070_000), "42.07 MB");
+    assert_eq!(format_bytes(812), "812 B");
+    assert_eq!(format_bytes(1_500_000), "1.5 MB");
+    assert_eq!(format_bytes(1_300_000_000), "1.3 GB");
+
+    set_units(Units::Binary);
+    assert_eq!(mb_unit(), "MiB");
+    assert_eq!(to_mb(3 * 1024 * 1024), 3.0);
+    assert_eq!(format_mb(1024 * 1024), "1.00 MiB");
+    assert_eq!(format_bytes(1_000), "1000 B");
+    assert_eq!(format_bytes(1_300_000_000), "1.2 GiB");
+    assert_eq!(format_bytes(4 * 1024 * 1024 * 1024 * 1024 * 1024), "4096.0 TiB");
+}
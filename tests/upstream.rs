@@ -0,0 +1,55 @@
+//! Finding the backends a reverse proxy forwards to.
+
+use crabtop::upstream::{parse_config_upstreams, parse_upstream_connections, ProxyKind};
+
+#[test]
+fn connections_to_this_host_are_upstreams_and_clients_are_not() {
+    let ss = "\
+0      0      127.0.0.1:18097 127.0.0.1:50360 users:((\"node\",pid=4242,fd=4))
+0      0      127.0.0.1:50360 127.0.0.1:3000 users:((\"nginx\",pid=1234,fd=4),(\"nginx\",pid=1235,fd=4))
+0      0      [::1]:50400 [::1]:9000 users:((\"nginx\",pid=1234,fd=9))
+0      0      10.0.0.5:44100 10.0.0.5:5000 users:((\"nginx\",pid=1234,fd=10))
+0      0      10.0.0.5:44102 10.0.0.9:5000 users:((\"nginx\",pid=1234,fd=11))
+0      0      10.0.0.5:8080 203.0.113.7:61000 users:((\"nginx\",pid=1234,fd=12))
+0      0      127.0.0.1:50362 127.0.0.1:3000 users:((\"nginx\",pid=12345,fd=4))
+";
+    assert_eq!(parse_upstream_connections(ss, 1234, &[8080]), vec![3000, 5000, 9000]);
+}
+
+#[test]
+fn loopback_upstreams_are_read_from_each_proxys_config() {
+    let nginx = "
+upstream api {
+    server 127.0.0.1:3000 weight=5;
+    server 10.0.0.9:3000;
+}
+server {
+    listen 8080;
+    location / { proxy_pass http://api; }
+    location /legacy/ { proxy_pass http://localhost:5000/; }
+    location ~ \\.php$ { fastcgi_pass 127.0.0.1:9000; }
+    # proxy_pass http://127.0.0.1:7000;
+}
+";
+    assert_eq!(parse_config_upstreams(ProxyKind::Nginx, nginx), vec![3000, 5000, 9000]);
+
+    let haproxy = "
+backend app
+    balance roundrobin
+    server app1 127.0.0.1:8001 check
+    server app2 [::1]:8002 check
+    server far 192.0.2.10:8003 check
+";
+    assert_eq!(parse_config_upstreams(ProxyKind::Haproxy, haproxy), vec![8001, 8002]);
+
+    let caddy = "
+example.com {
+    reverse_proxy /api/* localhost:3000 127.0.0.1:3001
+    reverse_proxy {
+        to :4000
+    }
+    php_fastcgi unix//run/php/php-fpm.sock
+}
+";
+    assert_eq!(parse_config_upstreams(ProxyKind::Caddy, caddy), vec![3000, 3001, 4000]);
+}
@@ -0,0 +1,663 @@
+mod common;
+
+use chrono::Local;
+use crabtop::annotation::{Annotation, AnnotationKind};
+use crabtop::cgroup::{CgroupStats, CpuStat};
+use crabtop::export::{ExportSample, Exporter};
+use crabtop::process::{CpuMode, ProcessCounters};
+use crabtop::pattern::Pattern;
+use crabtop::pool::WorkerEvent;
+use crabtop::port::{AcceptQueue, ConnectionBytes, Listener, PortConnection, TcpStats};
+use crabtop::recording::{self, Recorder};
+use crabtop::rotate::Rotation;
+use crabtop::syscalls::SyscallCounts;
+use crabtop::watch::{matching_pids, Tab, WatchSession, SLOW_REFRESH, SYSCALL_WINDOW};
+use crabtop::{MetricsSource, ProcessInfo, ScriptedSource, SystemSnapshot};
+
+fn info(cpu_percent: f32, memory_bytes: u64) -> ProcessInfo {
+    common::info("scripted", 4242, cpu_percent, memory_bytes)
+}
+
+#[tokio::test]
+async fn records_history_until_the_process_goes_away() {
+    let source = ScriptedSource::new()
+        .with_listener(8080, 4242)
+        .push_sample(info(10.0, 100_000_000))
+        .push_sample(info(30.0, 300_000_000))
+        .push_sample(info(20.0, 200_000_000));
+
+    let mut session = WatchSession::new(source, 8080).unwrap();
+    assert_eq!(session.pid, 4242);
+    assert_eq!(session.listeners.len(), 1);
+    assert_eq!(session.listeners[0].port, 8080);
+
+    while session.tick().await.is_ok() {}
+
+    assert_eq!(session.iteration, 3);
+    assert_eq!(session.history.cpu_history, vec![10.0, 30.0, 20.0]);
+    assert_eq!(session.history.avg_cpu(), 20.0);
+    assert_eq!(session.history.max_cpu(), 30.0);
+    assert_eq!(session.history.avg_mem(), 200_000_000.0);
+    assert_eq!(session.history.max_mem(), 300_000_000);
+    assert_eq!(session.source().remaining(), 0);
+}
+
+#[tokio::test]
+async fn surfaces_scripted_errors() {
+    let source = ScriptedSource::new()
+        .with_listener(8080, 4242)
+        .push_error("permission denied");
+
+    let mut session = WatchSession::new(source, 8080).unwrap();
+    let err = session.tick().await.unwrap_err();
+
+    assert_eq!(err.to_string(), "permission denied");
+    assert_eq!(session.iteration, 0);
+    assert!(session.history.cpu_history.is_empty());
+}
+
+#[test]
+fn unknown_port_is_not_resolved() {
+    let err = WatchSession::new(ScriptedSource::new(), 9999).err().unwrap();
+    assert!(err.to_string().contains("9999"));
+}
+
+#[tokio::test]
+async fn aggregates_processes_sharing_a_port() {
+    let source = ScriptedSource::new()
+        .with_listener(8080, 100)
+        .with_listener(8080, 101)
+        .push_sample(info(10.0, 100_000_000))
+        .push_sample(info(15.0, 50_000_000));
+
+    let pids = source.resolve_pids(8080).unwrap();
+    assert_eq!(pids, vec![100, 101]);
+
+    let mut session = WatchSession::with_pids(source, 8080, pids).unwrap();
+    let combined = session.tick().await.unwrap();
+
+    assert_eq!(combined.cpu_percent, 25.0);
+    assert_eq!(combined.memory_bytes, 150_000_000);
+    assert_eq!(session.history.cpu_history, vec![25.0]);
+}
+
+#[tokio::test]
+async fn tracks_cgroup_throttling_between_samples() {
+    let cgroup = |nr_periods, nr_throttled| CgroupStats {
+        cpu_quota_cores: Some(1.0),
+        cpu_stat: CpuStat {
+            nr_periods,
+            nr_throttled,
+            ..Default::default()
+        },
+        ..Default::default()
+    };
+    let source = ScriptedSource::new()
+        .with_listener(8080, 4242)
+        .push_sample(info(90.0, 100_000_000))
+        .push_sample(info(95.0, 100_000_000))
+        .push_cgroup(cgroup(100, 10))
+        .push_cgroup(cgroup(200, 60));
+
+    let mut session = WatchSession::new(source, 8080).unwrap();
+
+    session.tick().await.unwrap();
+    assert_eq!(session.throttled_ratio, None);
+
+    session.tick().await.unwrap();
+    assert_eq!(session.throttled_ratio, Some(0.5));
+    assert_eq!(session.cgroup.unwrap().cpu_stat.nr_throttled, 60);
+}
+
+#[tokio::test]
+async fn turns_lifetime_counters_into_per_interval_deltas() {
+    let counted = |minor, major, voluntary, involuntary| ProcessInfo {
+        counters: Some(ProcessCounters {
+            minor_faults: minor,
+            major_faults: major,
+            voluntary_switches: voluntary,
+            involuntary_switches: involuntary,
+        }),
+        ..info(1.0, 1_000_000)
+    };
+    let source = ScriptedSource::new()
+        .with_listener(8080, 4242)
+        .push_sample(counted(1_000, 10, 500, 20))
+        .push_sample(counted(1_600, 10, 560, 25))
+        .push_sample(counted(1_650, 250, 600, 900));
+
+    let mut session = WatchSession::new(source, 8080).unwrap();
+    session.tick().await.unwrap();
+    assert!(session.counters.as_ref().unwrap().latest().is_none());
+
+    while session.tick().await.is_ok() {}
+    let counters = session.counters.as_ref().unwrap();
+    assert_eq!(counters.series(|c| c.minor_faults), vec![600.0, 50.0]);
+    assert_eq!(counters.series(|c| c.major_faults), vec![0.0, 240.0]);
+    assert_eq!(counters.latest().unwrap().involuntary_switches, 875);
+}
+
+#[tokio::test]
+async fn warns_once_each_time_the_process_starts_swapping() {
+    let swapped = |bytes| ProcessInfo { swap_bytes: Some(bytes), ..info(1.0, 36_000_000) };
+    let source = ScriptedSource::new()
+        .with_listener(8080, 4242)
+        .push_sample(swapped(0))
+        .push_sample(swapped(2_000_000))
+        .push_sample(swapped(4_000_000))
+        .push_sample(swapped(4_000_000))
+        .push_sample(swapped(5_000_000));
+
+    let mut session = WatchSession::new(source, 8080).unwrap();
+    for _ in 0..5 {
+        session.tick().await.unwrap();
+    }
+
+    let swap = session.swap.as_ref().unwrap();
+    assert_eq!(swap.swapped, vec![0, 2_000_000, 4_000_000, 4_000_000, 5_000_000]);
+    assert_eq!(swap.growth(), 1_000_000);
+    assert!((swap.share_percent() - 5_000_000.0 * 100.0 / 41_000_000.0).abs() < 1e-9);
+    let alerts: Vec<_> = session.take_fresh_alerts().into_iter().map(|a| (a.rule, a.message)).collect();
+    assert_eq!(
+        alerts,
+        vec![
+            ("swap_started".to_string(), "Started swapping: 2.00 MB out, 2.00 MB since the last sample".to_string()),
+            ("swap_started".to_string(), "Started swapping: 5.00 MB out, 1.00 MB since the last sample".to_string()),
+        ]
+    );
+    assert_eq!(session.annotations.len(), 2);
+}
+
+#[tokio::test]
+async fn alerts_once_when_the_process_exits() {
+    let source = ScriptedSource::new().with_listener(8080, 4242).push_sample(info(10.0, 100_000_000));
+
+    let mut session = WatchSession::new(source, 8080).unwrap();
+    session.tick().await.unwrap();
+    assert!(session.tick().await.is_err());
+    assert!(session.tick().await.is_err());
+
+    let alerts: Vec<_> = session.take_fresh_alerts().into_iter().map(|a| (a.rule, a.message)).collect();
+    assert_eq!(alerts, vec![("process_exited".to_string(), "PID 4242 exited".to_string())]);
+}
+
+#[tokio::test]
+async fn follows_a_matching_process_when_it_respawns() {
+    // Worker 101 has exited and the master (1) forked 102 in its place.
+    let source = ScriptedSource::new()
+        .with_listener(8000, 1)
+        .with_cmdline(1, "gunicorn: master [app]")
+        .with_child(1, 102)
+        .with_cmdline(102, "gunicorn: worker [app]")
+        .push_sample(info(10.0, 100_000_000))
+        .push_error("Failed to read process info for PID 101")
+        .push_sample(info(20.0, 100_000_000));
+    let pattern = Pattern::new(r"worker \[app\]").unwrap();
+
+    let mut session = WatchSession::with_pids(source, 8000, vec![101]).unwrap().follow_matching(Some(pattern));
+    session.tick().await.unwrap();
+    session.tick().await.unwrap();
+
+    assert_eq!((session.pid, session.pids.clone()), (102, vec![102]));
+    assert_eq!(session.history.cpu_history, vec![10.0, 20.0]);
+}
+
+#[tokio::test]
+async fn annotates_a_restart_and_each_retransmit_breach_once() {
+    let retransmits = |percent: u64| TcpStats {
+        connections: 4,
+        retransmits: percent,
+        segments_out: 100,
+        ..Default::default()
+    };
+    let source = ScriptedSource::new()
+        .with_listener(8000, 1)
+        .with_cmdline(1, "gunicorn: master [app]")
+        .with_child(1, 102)
+        .with_cmdline(102, "gunicorn: worker [app]")
+        .push_sample(info(10.0, 100_000_000))
+        .push_error("Failed to read process info for PID 101")
+        .push_sample(info(10.0, 100_000_000))
+        .push_sample(info(10.0, 100_000_000))
+        .push_sample(info(10.0, 100_000_000))
+        .push_sample(info(10.0, 100_000_000))
+        .push_tcp_stats(retransmits(1))
+        .push_tcp_stats(retransmits(5))
+        .push_tcp_stats(retransmits(6))
+        .push_tcp_stats(retransmits(1))
+        .push_tcp_stats(retransmits(3));
+    let pattern = Pattern::new(r"worker \[app\]").unwrap();
+
+    let mut session = WatchSession::with_pids(source, 8000, vec![101])
+        .unwrap()
+        .follow_matching(Some(pattern))
+        .alert_on_retransmits(2.0);
+    for _ in 0..5 {
+        session.tick().await.unwrap();
+    }
+    session.annotate(AnnotationKind::Deploy, "v1.2");
+
+    let seen: Vec<_> = session.annotations.iter().map(|a| (a.kind, a.text.as_str(), a.sample)).collect();
+    assert_eq!(
+        seen,
+        vec![
+            (AnnotationKind::Restart, "PID 101 replaced by 102", 1),
+            (AnnotationKind::Threshold, "Retransmits at 5.0% (alert at 2%)", 2),
+            (AnnotationKind::Threshold, "Retransmits at 3.0% (alert at 2%)", 5),
+            (AnnotationKind::Deploy, "v1.2", 5),
+        ]
+    );
+    assert_eq!(session.take_fresh_annotations().len(), 4);
+    assert!(session.take_fresh_annotations().is_empty());
+    let alerts: Vec<_> = session.take_fresh_alerts().into_iter().map(|a| (a.rule, a.value)).collect();
+    assert_eq!(
+        alerts,
+        vec![
+            ("process_restarted".to_string(), 102.0),
+            ("retransmits_high".to_string(), 5.0),
+            ("retransmits_high".to_string(), 3.0),
+        ]
+    );
+}
+
+#[test]
+fn matches_listeners_and_their_children_by_cmdline() {
+    let source = ScriptedSource::new()
+        .with_listener(8000, 1)
+        .with_cmdline(1, "gunicorn: master [app]")
+        .with_child(1, 12)
+        .with_child(1, 11)
+        .with_cmdline(11, "gunicorn: worker [app]")
+        .with_cmdline(12, "gunicorn: worker [app]");
+
+    let workers = matching_pids(&source, 8000, &Pattern::new("worker").unwrap()).unwrap();
+    assert_eq!(workers, vec![11, 12]);
+    assert_eq!(matching_pids(&source, 8000, &Pattern::new("^gunicorn").unwrap()).unwrap(), vec![1, 11, 12]);
+
+    let err = matching_pids(&source, 8000, &Pattern::new("celery").unwrap()).unwrap_err();
+    assert_eq!(err.to_string(), "No process on port 8000 has a command line matching 'celery'");
+}
+
+#[tokio::test]
+async fn follows_the_workers_of_a_prefork_master() {
+    let worker = |pid, cpu_percent| ProcessInfo {
+        name: "gunicorn".to_string(),
+        pid,
+        cpu_percent,
+        memory_bytes: 50_000_000,
+        ..Default::default()
+    };
+    let helper = ProcessInfo {
+        name: "sh".to_string(),
+        pid: 14,
+        ..Default::default()
+    };
+    // Worker 11 dies between the two samples and 13 replaces it.
+    let source = ScriptedSource::new()
+        .with_listener(8000, 1)
+        .with_child(1, 11)
+        .with_child(1, 12)
+        .with_child(1, 13)
+        .with_child(1, 14)
+        .push_sample(info(1.0, 20_000_000))
+        .push_sample(info(1.0, 20_000_000))
+        .push_group(vec![worker(11, 30.0), worker(12, 50.0), helper])
+        .push_group(vec![worker(12, 40.0), worker(13, 5.0)]);
+
+    let mut session = WatchSession::new(source, 8000).unwrap();
+    session.tick().await.unwrap();
+    let pool = session.pool.as_ref().expect("two gunicorn children make a pool");
+    assert_eq!(pool.name, "gunicorn");
+    assert_eq!(pool.total.as_ref().map(|t| (t.cpu_percent, t.memory_bytes)), Some((81.0, 120_000_000)));
+    assert!(pool.events.is_empty());
+
+    session.tick().await.unwrap();
+    let pool = session.pool.as_ref().unwrap();
+    assert_eq!(pool.workers.iter().map(|w| w.pid).collect::<Vec<_>>(), vec![12, 13]);
+    assert_eq!((pool.exited, pool.spawned), (1, 1));
+    assert_eq!(pool.events.iter().map(|e| (e.pid, e.kind)).collect::<Vec<_>>(), vec![(11, WorkerEvent::Exited), (13, WorkerEvent::Spawned)]);
+    assert_eq!(pool.totals.cpu_history, vec![81.0, 46.0]);
+}
+
+#[tokio::test]
+async fn samples_the_backends_behind_a_proxy() {
+    let nginx = ProcessInfo {
+        name: "nginx".to_string(),
+        ..info(1.0, 10_000_000)
+    };
+    let backend = ProcessInfo {
+        name: "node".to_string(),
+        pid: 42,
+        cpu_percent: 30.0,
+        memory_bytes: 90_000_000,
+        ..Default::default()
+    };
+    // 9000 is named in the config but nothing listens there.
+    let source = ScriptedSource::new()
+        .with_listener(8080, 1)
+        .with_listener(3000, 42)
+        .with_upstream(1, 3000)
+        .with_upstream(1, 9000)
+        .push_sample(nginx)
+        .push_group(vec![backend]);
+
+    let mut session = WatchSession::new(source, 8080).unwrap().follow_upstream(true);
+    let proxy = session.tick().await.unwrap();
+    let chain = session.upstream.as_ref().expect("nginx is a known proxy");
+    let backends: Vec<_> = chain.backends.iter().map(|b| (b.port, b.pids.clone(), b.latest.as_ref().map(|i| i.cpu_percent))).collect();
+    assert_eq!(backends, vec![(3000, vec![42], Some(30.0)), (9000, vec![], None)]);
+    let total = chain.total(&proxy);
+    assert_eq!((total.cpu_percent, total.memory_bytes), (31.0, 100_000_000));
+}
+
+#[tokio::test]
+async fn counts_connections_opened_and_closed_between_ticks() {
+    let connection = |peer: u16, open| PortConnection {
+        key: format!("127.0.0.1:8080->127.0.0.1:{}", peer),
+        open,
+    };
+    let source = ScriptedSource::new()
+        .with_listener(8080, 1)
+        .push_sample(info(1.0, 10_000_000))
+        .push_sample(info(1.0, 10_000_000))
+        .push_connections(vec![connection(50001, true), connection(50002, true)])
+        // 50001 closed (TIME-WAIT), 50002 went away, 50003 opened.
+        .push_connections(vec![connection(50001, false), connection(50003, true)]);
+
+    let mut session = WatchSession::new(source, 8080).unwrap();
+    session.tick().await.unwrap();
+    assert!(session.churn.as_ref().unwrap().opened.is_empty(), "needs two tables");
+    session.tick().await.unwrap();
+    let churn = session.churn.as_ref().unwrap();
+    assert_eq!((churn.opened.clone(), churn.closed.clone(), churn.open_now), (vec![1], vec![2], 1));
+}
+
+#[tokio::test]
+async fn ranks_the_top_talkers_with_their_traffic() {
+    let key = |peer: &str| format!("10.0.0.2:8080->{}", peer);
+    let connection = |peer: &str| PortConnection { key: key(peer), open: true };
+    let bytes = |peer: &str, sent, received| ConnectionBytes {
+        key: key(peer),
+        sent,
+        received,
+    };
+    let source = ScriptedSource::new()
+        .with_listener(8080, 1)
+        .push_sample(info(1.0, 10_000_000))
+        .push_sample(info(1.0, 10_000_000))
+        .push_connections(vec![connection("10.0.0.7:1"), connection("9.9.9.9:2"), connection("10.0.0.7:3"), connection("8.8.8.8:4")])
+        .push_connection_bytes(vec![bytes("10.0.0.7:1", 100, 100), bytes("8.8.8.8:4", 0, 0)])
+        // 8.8.8.8 pulled a megabyte; 10.0.0.7:3 is new, all its bytes count.
+        .push_connection_bytes(vec![bytes("10.0.0.7:1", 100, 100), bytes("8.8.8.8:4", 1_000, 1_000_000), bytes("10.0.0.7:3", 200, 300)]);
+
+    let mut session = WatchSession::new(source, 8080).unwrap().top_peers(2);
+    session.tick().await.unwrap();
+    let peers = session.peers.as_ref().unwrap();
+    let listed: Vec<(String, usize)> = peers.iter().map(|p| (p.address.to_string(), p.connections)).collect();
+    assert_eq!(listed, [("10.0.0.7".to_string(), 2), ("8.8.8.8".to_string(), 1)]);
+    assert!(peers[0].bytes_per_sec.is_none(), "needs two readings");
+    assert!(peers[0].hostname.is_none(), "no lookups without a resolver");
+
+    session.tick().await.unwrap();
+    let peers = session.peers.as_ref().unwrap();
+    let (internal, google) = (peers[0].bytes_per_sec.unwrap(), peers[1].bytes_per_sec.unwrap());
+    assert!(internal > 0.0);
+    assert!((google / internal - 2002.0).abs() < 0.01, "{} vs {}", google, internal);
+}
+
+#[tokio::test]
+async fn system_cpu_mode_divides_by_the_hosts_cores() {
+    let source = ScriptedSource::new()
+        .with_listener(8080, 4242)
+        .with_system(SystemSnapshot {
+            load_average: (1.0, 1.0, 1.0),
+            total_memory_bytes: 0,
+            available_memory_bytes: 0,
+            total_swap_bytes: 0,
+            used_swap_bytes: 0,
+            cpu_count: 8,
+        })
+        .push_sample(info(200.0, 10_000_000));
+
+    let mut session = WatchSession::new(source, 8080).unwrap().cpu_mode(CpuMode::System);
+    assert_eq!(session.tick().await.unwrap().cpu_percent, 25.0);
+    assert_eq!(session.history.max_cpu(), 25.0);
+    assert_eq!(session.cpu_mode.label(session.cpu_count), "of 8 cores");
+    assert_eq!(CpuMode::Process.scale(200.0, 8), 200.0);
+    assert_eq!(CpuMode::System.scale(200.0, 0), 200.0, "unknown core count");
+}
+
+#[tokio::test]
+async fn reads_a_tabs_figures_only_while_it_is_shown() {
+    let connection = |peer: u16| PortConnection {
+        key: format!("127.0.0.1:8080->127.0.0.1:{}", peer),
+        open: true,
+    };
+    let mut source = ScriptedSource::new().with_listener(8080, 1);
+    for _ in 0..4 {
+        source = source.push_sample(info(1.0, 10_000_000));
+    }
+    let source = source
+        .push_accept_queue(AcceptQueue::default())
+        .push_tcp_stats(TcpStats::default())
+        .push_connections(vec![connection(50001)]);
+
+    let mut session = WatchSession::new(source, 8080).unwrap();
+    session.show_tab(Tab::Resources);
+    session.tick().await.unwrap();
+    assert!(session.accept_queue.is_none() && session.churn.is_none());
+    assert!(session.tcp_stats.is_some(), "read on the first tick, whatever the tab");
+
+    session.show_tab(Tab::Network);
+    session.tick().await.unwrap();
+    session.tick().await.unwrap();
+    assert!(session.accept_queue.is_some());
+    assert_eq!(session.churn.as_ref().unwrap().seconds.len(), 1);
+
+    // Back on the tab, churn starts over rather than spanning the time away.
+    session.show_tab(Tab::Threads);
+    assert!(session.churn.is_none());
+    session.show_tab(Tab::Network);
+    session.tick().await.unwrap();
+    assert!(session.churn.as_ref().unwrap().seconds.is_empty());
+}
+
+#[tokio::test]
+async fn reads_tcp_stats_less_often_when_nothing_needs_them_every_tick() {
+    let connected = |connections: usize| TcpStats { connections, ..Default::default() };
+    let mut source = ScriptedSource::new().with_listener(8080, 1);
+    for n in 1..=7 {
+        source = source.push_sample(info(1.0, 10_000_000)).push_tcp_stats(connected(n));
+    }
+
+    let mut session = WatchSession::new(source, 8080).unwrap();
+    session.show_tab(Tab::Resources);
+    let mut seen = Vec::new();
+    for _ in 0..SLOW_REFRESH + 1 {
+        session.tick().await.unwrap();
+        seen.push(session.tcp_stats.unwrap().connections);
+    }
+    // Read on the first tick and again SLOW_REFRESH ticks later.
+    assert_eq!(seen, [1, 1, 1, 1, 1, 2]);
+
+    session.show_tab(Tab::Network);
+    session.tick().await.unwrap();
+    assert_eq!(session.tcp_stats.unwrap().connections, 3);
+}
+
+#[tokio::test]
+async fn marks_ports_bound_and_closed_during_the_session() {
+    let listener = |address: &str, port| Listener { address: address.to_string(), port };
+    let mut source = ScriptedSource::new()
+        .with_listener(8080, 4242)
+        .push_listeners(4242, vec![listener("*", 8080)])
+        .push_listeners(4242, vec![listener("*", 8080), listener("127.0.0.1", 6060)])
+        .push_listeners(4242, vec![listener("127.0.0.1", 6060)]);
+    for _ in 0..=SLOW_REFRESH {
+        source = source.push_sample(info(1.0, 10_000_000));
+    }
+
+    let mut session = WatchSession::new(source, 8080).unwrap();
+    for _ in 0..=SLOW_REFRESH {
+        session.tick().await.unwrap();
+    }
+    let events: Vec<_> = session.annotations.iter().map(|a| (a.kind, a.text.as_str(), a.sample)).collect();
+    assert_eq!(
+        events,
+        vec![
+            (AnnotationKind::Listener, "Started listening on 127.0.0.1:6060", 1),
+            (AnnotationKind::Listener, "Stopped listening on *:8080", SLOW_REFRESH + 1),
+        ]
+    );
+    assert_eq!(session.listeners, vec![listener("127.0.0.1", 6060)]);
+}
+
+#[test]
+fn number_keys_and_tab_pick_the_view() {
+    assert_eq!(Tab::from_key('1'), Some(Tab::Resources));
+    assert_eq!(Tab::from_key('5'), Some(Tab::Logs));
+    assert_eq!(Tab::from_key('0'), None);
+    assert_eq!(Tab::from_key('6'), None);
+    assert_eq!(Tab::Logs.next(), Tab::Resources);
+    assert_eq!(Tab::Resources.previous(), Tab::Logs);
+    assert_eq!(Tab::Network.next().key(), 3);
+}
+
+#[tokio::test]
+async fn sums_thread_samples_over_a_window_for_the_syscall_panel() {
+    let waiting = SyscallCounts {
+        poll: 1,
+        futex: 3,
+        ..Default::default()
+    };
+    let mut source = ScriptedSource::new().with_listener(8080, 1).push_task_syscalls(waiting);
+    for _ in 0..=SYSCALL_WINDOW + 5 {
+        source = source.push_sample(info(1.0, 10_000_000));
+    }
+
+    let mut session = WatchSession::new(source, 8080).unwrap();
+    session.tick().await.unwrap();
+    assert!(session.syscalls.is_none(), "off without --syscalls");
+
+    let mut session = session.follow_syscalls(true);
+    for _ in 0..SYSCALL_WINDOW + 5 {
+        session.tick().await.unwrap();
+    }
+    let activity = session.syscalls.as_ref().unwrap();
+    assert!(!activity.counted);
+    assert_eq!(activity.samples(), SYSCALL_WINDOW);
+    assert_eq!((activity.summary().futex, activity.summary().dominant()), (3 * SYSCALL_WINDOW as u64, Some("locking")));
+}
+
+#[tokio::test]
+async fn recovers_when_a_breach_clears_or_the_process_is_back() {
+    let retransmits = |percent: u64| TcpStats {
+        connections: 4,
+        retransmits: percent,
+        segments_out: 100,
+        ..Default::default()
+    };
+    let source = ScriptedSource::new()
+        .with_listener(8080, 4242)
+        .push_sample(info(10.0, 100_000_000))
+        .push_sample(info(10.0, 100_000_000))
+        .push_exit()
+        .push_sample(info(10.0, 100_000_000))
+        .push_tcp_stats(retransmits(6))
+        .push_tcp_stats(retransmits(1))
+        .push_tcp_stats(retransmits(1));
+
+    let mut session = WatchSession::new(source, 8080).unwrap().alert_on_retransmits(2.0);
+    session.tick().await.unwrap();
+    session.tick().await.unwrap();
+    assert!(session.tick().await.is_err());
+    session.tick().await.unwrap();
+
+    let rules = |alerts: Vec<crabtop::export::Alert>| alerts.into_iter().map(|a| (a.rule, a.message)).collect::<Vec<_>>();
+    assert_eq!(
+        rules(session.take_fresh_alerts()),
+        vec![
+            ("retransmits_high".to_string(), "Retransmits at 6.0% (alert at 2%)".to_string()),
+            ("process_exited".to_string(), "PID 4242 exited".to_string()),
+        ]
+    );
+    assert_eq!(
+        rules(session.take_fresh_recoveries()),
+        vec![
+            ("retransmits_high".to_string(), "Retransmits back down to 1.0%".to_string()),
+            ("process_exited".to_string(), "PID 4242 is running".to_string()),
+        ]
+    );
+}
+
+#[tokio::test]
+async fn marks_and_alerts_on_samples_out_of_the_usual_range() {
+    let mut source = ScriptedSource::new().with_listener(8080, 4242);
+    for i in 0..30 {
+        source = source.push_sample(info(if i % 2 == 0 { 11.0 } else { 9.0 }, 100_000_000));
+    }
+    source = source.push_sample(info(80.0, 100_000_000)).push_sample(info(10.0, 100_000_000));
+
+    let mut session = WatchSession::new(source, 8080).unwrap().detect_anomalies(3.0, true);
+    for _ in 0..32 {
+        session.tick().await.unwrap();
+    }
+
+    let marks: Vec<_> = session.annotations.iter().map(|a| (a.kind, a.sample)).collect();
+    assert_eq!(marks, vec![(AnnotationKind::Anomaly, 31)]);
+    let alerts: Vec<_> = session.take_fresh_alerts().into_iter().map(|a| (a.rule, a.value)).collect();
+    assert_eq!(alerts, vec![("cpu_anomaly".to_string(), 80.0)]);
+    let recoveries: Vec<_> = session.take_fresh_recoveries().into_iter().map(|a| a.message).collect();
+    assert_eq!(recoveries, vec!["CPU back in its usual range at 10.0%".to_string()]);
+}
+
+#[tokio::test]
+async fn resumes_the_chart_from_an_earlier_recording() {
+    let dir = std::env::temp_dir().join(format!("crabtop-resume-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let path = dir.join("samples.jsonl");
+    let start = Local::now() - chrono::Duration::seconds(60);
+    let recorded = |n: i64, port: u16| ExportSample {
+        timestamp: start + chrono::Duration::seconds(n),
+        host: None,
+        port,
+        pids: vec![4141],
+        process: info(n as f32, 50_000_000),
+        connections: None,
+        annotations: match n {
+            3 => vec![Annotation {
+                timestamp: start + chrono::Duration::seconds(n),
+                kind: AnnotationKind::Deploy,
+                text: "v1.2".to_string(),
+                sample: 3,
+            }],
+            _ => Vec::new(),
+        },
+    };
+    let mut recorder = Recorder::create(&path).unwrap();
+    for n in 0..5 {
+        recorder.on_sample(&recorded(n, 8080)).unwrap();
+        recorder.on_sample(&recorded(n, 9090)).unwrap();
+    }
+    recorder.on_shutdown().unwrap();
+
+    // The newest 4 of port 8080, with the deploy among them.
+    let resumed = recording::recent(&path, 8080, 4).unwrap();
+    assert_eq!(resumed.iter().map(|s| s.process.cpu_percent).collect::<Vec<_>>(), vec![1.0, 2.0, 3.0, 4.0]);
+    let source = ScriptedSource::new().with_listener(8080, 4242).push_sample(info(50.0, 60_000_000));
+    let mut session = WatchSession::new(source, 8080).unwrap().resume(&resumed);
+    session.tick().await.unwrap();
+    assert_eq!(session.history.cpu_history, vec![1.0, 2.0, 3.0, 4.0, 50.0]);
+    let deploys: Vec<_> = session.annotations.iter().map(|a| (a.kind, a.sample)).collect();
+    assert_eq!(deploys, vec![(AnnotationKind::Deploy, 2)]);
+    // Restored annotations were exported the first time round.
+    assert!(session.take_fresh_annotations().is_empty());
+
+    // Resuming adds to the recording rather than starting it over.
+    let mut recorder = Recorder::appending(&path, Rotation::default()).unwrap();
+    recorder.on_sample(&recorded(5, 8080)).unwrap();
+    recorder.on_shutdown().unwrap();
+    assert_eq!(recording::load(&path).unwrap().len(), 11);
+    assert!(recording::recent(&dir.join("missing.jsonl"), 8080, 4).unwrap().is_empty());
+    let _ = std::fs::remove_dir_all(&dir);
+}
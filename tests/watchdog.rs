@@ -0,0 +1,159 @@
+//! `[watchdog]`: when an unhealthy process is acted on, and what is logged.
+
+mod common;
+
+use chrono::{Duration, Local, TimeZone};
+use crabtop::annotation::AnnotationKind;
+use crabtop::config;
+use crabtop::watch::WatchSession;
+use crabtop::watchdog::{Trigger, Verdict, Watchdog};
+use crabtop::{ProcessInfo, ScriptedSource};
+use common::scratch;
+
+/// `sample` for as many ticks as `until_acted` may take.
+fn memory_hog(sample: ProcessInfo) -> ScriptedSource {
+    (0..=TICKS).fold(ScriptedSource::new(), |source, _| source.push_sample(sample.clone()))
+}
+
+const TICKS: usize = 500;
+
+/// Ticks until the action started on the first tick reports back, and
+/// returns the annotations made by then.
+async fn until_acted(session: &mut WatchSession<ScriptedSource>) -> Vec<(AnnotationKind, String)> {
+    for _ in 0..TICKS {
+        session.tick().await.unwrap();
+        let annotations: Vec<_> = session.take_fresh_annotations().into_iter().map(|a| (a.kind, a.text)).collect();
+        if !annotations.is_empty() {
+            return annotations;
+        }
+        tokio::time::sleep(std::time::Duration::from_millis(10)).await;
+    }
+    panic!("the watchdog's action never finished");
+}
+
+fn watchdog(table: &str) -> Watchdog {
+    let config = config::parse(&format!("[watchdog]\n{}", table)).unwrap();
+    Watchdog::new(config.watchdog.as_ref().unwrap(), Some("127.0.0.1:8080")).unwrap()
+}
+
+#[test]
+fn the_config_is_checked_up_front() {
+    let config = config::parse("[watchdog]\naction = \"docker\"\n").unwrap();
+    let error = Watchdog::new(config.watchdog.as_ref().unwrap(), Some("127.0.0.1:8080")).err().unwrap();
+    assert_eq!(error.to_string(), "[watchdog] needs `health` or `memory_max` to act on");
+    let config = config::parse("[watchdog]\nmemory_max = \"1GB\"\naction = \"command\"\n").unwrap();
+    assert!(Watchdog::new(config.watchdog.as_ref().unwrap(), None).is_err());
+    let config = config::parse("[watchdog]\nhealth = \"/healthz\"\n").unwrap();
+    let error = Watchdog::new(config.watchdog.as_ref().unwrap(), None).err().unwrap();
+    assert_eq!(error.to_string(), "[watchdog] health must be a full URL when there is no port");
+    assert!(config::parse("[watchdog]\nmemory_max = \"lots\"\n").is_err());
+}
+
+#[test]
+fn acts_after_enough_failures_and_within_its_limits() {
+    let dir = scratch("limits");
+    let mut watchdog = watchdog(&format!(
+        "health = \"/healthz\"\nfailures = 2\nmax_actions = 2\nwindow = \"1h\"\ncooldown = \"10m\"\naction = \"command\"\ncommand = \"systemctl restart api\"\ndry_run = true\naudit_log = \"{}\"\n",
+        dir.join("audit.log").display()
+    ));
+    let start = Local.with_ymd_and_hms(2026, 10, 14, 9, 0, 0).unwrap();
+    assert_eq!(watchdog.judge(start, Some(false), 0), Verdict::Fine);
+    // A good check starts the count over.
+    assert_eq!(watchdog.judge(start, Some(true), 0), Verdict::Fine);
+    assert_eq!(watchdog.judge(start, Some(false), 0), Verdict::Fine);
+    let failed = Trigger::Unhealthy { failures: 2 };
+    assert_eq!(watchdog.judge(start, Some(false), 0), Verdict::Act(failed.clone()));
+    watchdog.act(start, &failed, 4242, 8080);
+
+    let later = start + Duration::minutes(5);
+    watchdog.judge(later, Some(false), 0);
+    let held = watchdog.judge(later, Some(false), 0);
+    assert_eq!(held, Verdict::Held(failed.clone(), "cooling down until 09:10:00".to_string()));
+    // Held is said once, not on every sample.
+    assert_eq!(watchdog.judge(later, Some(false), 0), Verdict::Fine);
+
+    let second = start + Duration::minutes(11);
+    assert!(matches!(watchdog.judge(second, Some(false), 0), Verdict::Act(_)));
+    watchdog.act(second, &failed, 4243, 8080);
+    let third = start + Duration::minutes(30);
+    watchdog.judge(third, Some(false), 0);
+    assert_eq!(
+        watchdog.judge(third, Some(false), 0),
+        Verdict::Held(failed.clone(), "already acted 2 times in the last 1h 0m".to_string())
+    );
+    // An hour after the first action, one more is allowed.
+    let next_hour = start + Duration::minutes(61);
+    watchdog.judge(next_hour, Some(false), 0);
+    assert!(matches!(watchdog.judge(next_hour, Some(false), 0), Verdict::Act(_)));
+
+    let audit = std::fs::read_to_string(dir.join("audit.log")).unwrap();
+    let lines: Vec<_> = audit.lines().collect();
+    assert_eq!(lines.len(), 2);
+    assert!(lines[0].starts_with("2026-10-14 09:00:00"), "{}", lines[0]);
+    assert!(
+        lines[0].ends_with("port 8080 PID 4242: health check failed 2 times in a row; would run `sh -c systemctl restart api` (dry run)"),
+        "{}",
+        lines[0]
+    );
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[tokio::test]
+async fn a_process_over_its_memory_cap_is_restarted_by_command() {
+    let dir = scratch("memory");
+    let marker = dir.join("restarted");
+    let mut session = WatchSession::with_pids(
+        memory_hog(ProcessInfo {
+            name: "api".to_string(),
+            pid: 4242,
+            memory_bytes: 3_000_000_000,
+            ..Default::default()
+        }),
+        8080,
+        vec![4242],
+    )
+    .unwrap()
+    .with_watchdog(Some(watchdog(&format!(
+        "memory_max = \"2GB\"\naction = \"command\"\ncommand = \"echo {{pid}} {{port}} > {}\"\naudit_log = \"{}\"\n",
+        marker.display(),
+        dir.join("audit.log").display()
+    ))));
+    let annotations = until_acted(&mut session).await;
+
+    assert_eq!(std::fs::read_to_string(&marker).unwrap(), "4242 8080\n");
+    let done = format!("memory 3.0 GB is over the 2.0 GB cap; ran `sh -c echo 4242 8080 > {}`", marker.display());
+    assert_eq!(annotations, vec![(AnnotationKind::Watchdog, done.clone())]);
+    // Still over the cap once it's done, so held back until the cooldown is over.
+    let alerts: Vec<_> = session.take_fresh_alerts().into_iter().map(|a| (a.rule, a.message)).collect();
+    assert_eq!(alerts.last(), Some(&("watchdog_action".to_string(), format!("Watchdog: {}", done))));
+    assert!(alerts[..alerts.len() - 1].iter().all(|(rule, _)| rule == "watchdog_held"), "{:?}", alerts);
+    let audit = std::fs::read_to_string(dir.join("audit.log")).unwrap();
+    assert!(audit.ends_with(&format!("port 8080 PID 4242: {}\n", done)), "{}", audit);
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[tokio::test]
+async fn an_action_that_hangs_is_killed_and_logged_as_failed() {
+    let dir = scratch("timeout");
+    let source = memory_hog(ProcessInfo {
+        pid: 4242,
+        memory_bytes: 3_000_000_000,
+        ..Default::default()
+    });
+    let mut session = WatchSession::with_pids(source, 8080, vec![4242])
+        .unwrap()
+        .with_watchdog(Some(watchdog(&format!(
+            "memory_max = \"2GB\"\naction = \"command\"\ncommand = \"sleep 30\"\ntimeout = \"1s\"\naudit_log = \"{}\"\n",
+            dir.join("audit.log").display()
+        ))));
+    let started = std::time::Instant::now();
+    session.tick().await.unwrap();
+    assert!(started.elapsed() < std::time::Duration::from_millis(500), "the tick waited for the action");
+
+    let annotations = until_acted(&mut session).await;
+    let done = "memory 3.0 GB is over the 2.0 GB cap; ran `sh -c sleep 30`, which failed: still running after 1s, killed";
+    assert_eq!(annotations, vec![(AnnotationKind::Watchdog, done.to_string())]);
+    let audit = std::fs::read_to_string(dir.join("audit.log")).unwrap();
+    assert!(audit.ends_with(&format!("port 8080 PID 4242: {}\n", done)), "{}", audit);
+    std::fs::remove_dir_all(&dir).unwrap();
+}
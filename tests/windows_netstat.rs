@@ -0,0 +1,29 @@
+//! Parsing Windows `netstat -ano` output into listening sockets.
+
+use crabtop::port::parse_windows_netstat;
+
+#[test]
+fn keeps_tcp_listeners_whatever_the_locale() {
+    let output = "
+Aktive Verbindungen
+
+  Proto  Lokale Adresse         Remoteadresse          Status           PID
+  TCP    0.0.0.0:135            0.0.0.0:0              ABHÖREN         1024
+  TCP    127.0.0.1:8080         0.0.0.0:0              ABHÖREN         4242
+  TCP    127.0.0.1:8080         127.0.0.1:51544        HERGESTELLT     4242
+  TCP    [::]:8080              [::]:0                 ABHÖREN         4242
+  UDP    0.0.0.0:5353           *:*                                    2200
+";
+    let sockets: Vec<(String, u32)> = parse_windows_netstat(output)
+        .into_iter()
+        .map(|s| (s.listener.to_string(), s.pid))
+        .collect();
+    assert_eq!(
+        sockets,
+        vec![
+            ("0.0.0.0:135".to_string(), 1024),
+            ("127.0.0.1:8080".to_string(), 4242),
+            ("[::]:8080".to_string(), 4242),
+        ]
+    );
+}